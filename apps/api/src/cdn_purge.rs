@@ -9,6 +9,26 @@ use scuffle_context::{ContextFutExt, ContextStreamExt};
 
 use crate::global::Global;
 
+/// Asks the cdn to purge the files, does nothing if there is nothing to purge.
+pub async fn request_purge(global: &Arc<Global>, request: &shared::cdn::PurgeRequest) -> anyhow::Result<()> {
+	if request.files.is_empty() && request.prefixes.is_empty() {
+		return Ok(());
+	}
+
+	global
+		.jetstream
+		.publish(
+			format!("{}.request", global.config.cdn.purge_stream_subject),
+			serde_json::to_vec(request)
+				.context("failed to serialize purge request")?
+				.into(),
+		)
+		.await
+		.context("failed to send purge request")?;
+
+	Ok(())
+}
+
 pub async fn run(global: Arc<Global>, ctx: scuffle_context::Context) -> anyhow::Result<()> {
 	let stream = global
 		.jetstream
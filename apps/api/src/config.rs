@@ -46,8 +46,91 @@ pub struct Api {
 	#[default("api.events".into())]
 	pub nats_event_subject: String,
 
+	/// Maximum nesting depth of v3 GraphQL queries, introspection queries
+	/// of common clients need up to 14
+	#[default(16)]
+	pub v3_gql_max_depth: usize,
+
+	/// Number of emote sets an emote can be used in before it can only be
+	/// deleted by admins
+	#[default(1000)]
+	pub emote_delete_usage_threshold: u64,
+
+	/// Number of emote sets an emote can be used in before it can only be
+	/// merged by admins
+	#[default(1000)]
+	pub emote_merge_usage_threshold: u64,
+
+	/// Seconds an image can stay pending before it is submitted to the image
+	/// processor again, 0 disables the check
+	#[default(60 * 60)]
+	pub image_pending_timeout: u64,
+
+	/// Days after which pending images are assumed to have failed and are no
+	/// longer submitted again
+	#[default(7)]
+	pub image_pending_max_days: u32,
+
+	/// Number of times a pending image is submitted again before giving up
+	#[default(3)]
+	pub image_max_resubmits: u32,
+
+	/// Number of profile pictures kept per user, older ones are deleted and
+	/// purged from the CDN while their stored files are kept, 0 disables the
+	/// limit
+	#[default(10)]
+	pub max_profile_pictures: usize,
+
+	/// Seconds between refreshes of the profile data of a connection, which
+	/// happen in the background when the user logs in with another connection,
+	/// 0 disables the refresh
+	#[default(60 * 60 * 24)]
+	pub connection_refresh_interval: u64,
+
 	/// IP Header config
 	pub incoming_request: IncomingRequestConfig,
+
+	/// Persisted GraphQL queries, disabled if not set
+	pub persisted_queries: Option<PersistedQueriesConfig>,
+
+	/// Cache of the computed permissions and entitlements of users in redis,
+	/// disabled if not set
+	pub user_computed_cache: Option<UserComputedCacheConfig>,
+}
+
+#[derive(Debug, Clone, smart_default::SmartDefault, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct PersistedQueriesConfig {
+	/// Path to a JSON file mapping sha256 hashes to queries of the v3 schema,
+	/// generated when building the clients
+	pub v3_allow_list: Option<PathBuf>,
+
+	/// Path to a JSON file mapping sha256 hashes to queries of the v4 schema
+	pub v4_allow_list: Option<PathBuf>,
+
+	/// Only execute queries from the allow list, queries sent in full are
+	/// rejected
+	#[default(false)]
+	pub allow_list_only: bool,
+
+	/// Total size in bytes of the queries sent in full which are kept for later
+	/// requests by hash, for each schema, 0 disables the cache
+	#[default(4 * 1024 * 1024)]
+	pub cache_capacity: usize,
+
+	/// Maximum size in bytes of a query sent in full, larger queries are
+	/// rejected
+	#[default(64 * 1024)]
+	pub max_query_size: usize,
+}
+
+#[derive(Debug, Clone, smart_default::SmartDefault, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct UserComputedCacheConfig {
+	/// Seconds the computed permissions of a user are cached for, 0 disables
+	/// the cache
+	#[default(30)]
+	pub ttl: u64,
 }
 
 #[derive(Debug, Clone, smart_default::SmartDefault, serde::Deserialize, serde::Serialize)]
@@ -62,6 +145,35 @@ pub struct JwtConfig {
 	pub issuer: String,
 }
 
+#[derive(Debug, Clone, smart_default::SmartDefault, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct AuthConfig {
+	/// Session duration in seconds
+	#[default(60 * 60 * 24 * 30)]
+	pub session_duration: u64,
+
+	/// Session duration in seconds for users with admin permissions
+	#[default(60 * 60 * 24)]
+	pub elevated_session_duration: u64,
+
+	/// Key of the hash of the client ip stored on sessions, kept separate
+	/// from the JWT secret
+	#[default("seventv-api-ip".into())]
+	pub ip_hash_secret: String,
+}
+
+impl AuthConfig {
+	pub fn session_duration(&self, elevated: bool) -> chrono::Duration {
+		let seconds = if elevated {
+			self.elevated_session_duration
+		} else {
+			self.session_duration
+		};
+
+		chrono::Duration::seconds(seconds as i64)
+	}
+}
+
 #[derive(Debug, Clone, smart_default::SmartDefault, serde::Deserialize, serde::Serialize)]
 #[serde(default)]
 pub struct ConnectionsConfig {
@@ -87,6 +199,14 @@ pub struct ConnectionConfig {
 	/// Client Secret
 	#[default("client_secret".into())]
 	pub client_secret: String,
+	/// OAuth scope to request on login, uses the platform default if not set
+	#[default(None)]
+	pub scope: Option<String>,
+	/// Bot token (Discord) or API key (Google) used to look up users without
+	/// their access token, connections of this platform are not refreshed if
+	/// not set
+	#[default(None)]
+	pub api_key: Option<String>,
 }
 
 #[derive(Debug, Clone, smart_default::SmartDefault, serde::Deserialize, serde::Serialize)]
@@ -153,6 +273,9 @@ pub struct Config {
 	/// jwt config
 	pub jwt: JwtConfig,
 
+	/// auth config
+	pub auth: AuthConfig,
+
 	/// image processor config
 	pub image_processor: ImageProcessorConfig,
 
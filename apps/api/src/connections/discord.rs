@@ -30,16 +30,35 @@ impl From<DiscordUserData> for PlatformUserData {
 }
 
 pub async fn get_user_data(global: &Arc<Global>, access_token: &str) -> Result<DiscordUserData, ConnectionError> {
-	let res = global
-		.http_client
-		.get("https://discord.com/api/v10/users/@me")
-		.bearer_auth(access_token)
-		.send()
-		.await
-		.map_err(|err| {
-			tracing::error!(error = %err, "request failed");
-			ConnectionError::RequestError
-		})?;
+	fetch_user_data(
+		global
+			.http_client
+			.get("https://discord.com/api/v10/users/@me")
+			.bearer_auth(access_token),
+	)
+	.await
+}
+
+/// Looks up a user by id with a bot token.
+pub async fn get_user_data_by_id(
+	global: &Arc<Global>,
+	bot_token: &str,
+	id: &str,
+) -> Result<DiscordUserData, ConnectionError> {
+	fetch_user_data(
+		global
+			.http_client
+			.get(format!("https://discord.com/api/v10/users/{id}"))
+			.header("Authorization", format!("Bot {bot_token}")),
+	)
+	.await
+}
+
+async fn fetch_user_data(req: reqwest::RequestBuilder) -> Result<DiscordUserData, ConnectionError> {
+	let res = req.send().await.map_err(|err| {
+		tracing::error!(error = %err, "request failed");
+		ConnectionError::RequestError
+	})?;
 
 	let status = res.status();
 	let text = res.text().await.map_err(|err| {
@@ -50,16 +50,31 @@ impl From<YoutubeUserData> for PlatformUserData {
 }
 
 pub async fn get_user_data(global: &Arc<Global>, access_token: &str) -> Result<YoutubeUserData, ConnectionError> {
-	let res = global
-		.http_client
-		.get("https://youtube.googleapis.com/youtube/v3/channels?part=snippet&mine=true")
-		.bearer_auth(access_token)
-		.send()
-		.await
-		.map_err(|err| {
-			tracing::error!(error = %err, "request failed");
-			ConnectionError::RequestError
-		})?;
+	fetch_user_data(
+		global
+			.http_client
+			.get("https://youtube.googleapis.com/youtube/v3/channels?part=snippet&mine=true")
+			.bearer_auth(access_token),
+	)
+	.await
+}
+
+/// Looks up a channel by id with an API key.
+pub async fn get_user_data_by_id(global: &Arc<Global>, api_key: &str, id: &str) -> Result<YoutubeUserData, ConnectionError> {
+	fetch_user_data(
+		global
+			.http_client
+			.get("https://youtube.googleapis.com/youtube/v3/channels?part=snippet")
+			.query(&[("id", id), ("key", api_key)]),
+	)
+	.await
+}
+
+async fn fetch_user_data(req: reqwest::RequestBuilder) -> Result<YoutubeUserData, ConnectionError> {
+	let res = req.send().await.map_err(|err| {
+		tracing::error!(error = %err, "request failed");
+		ConnectionError::RequestError
+	})?;
 
 	let status = res.status();
 	let text = res.text().await.map_err(|err| {
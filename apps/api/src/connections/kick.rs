@@ -15,7 +15,7 @@ pub struct KickUserData {
 	// pub email: String,
 	pub name: String,
 	pub profile_picture: Option<String>,
-	pub user_id: i32,
+	pub user_id: u64,
 }
 
 impl From<KickUserData> for PlatformUserData {
@@ -30,16 +30,36 @@ impl From<KickUserData> for PlatformUserData {
 }
 
 pub async fn get_user_data(global: &Arc<Global>, access_token: &str) -> Result<KickUserData, ConnectionError> {
-	let res = global
-		.http_client
-		.get("https://api.kick.com/public/v1/users")
-		.bearer_auth(access_token)
-		.send()
-		.await
-		.map_err(|err| {
-			tracing::error!(error = %err, "request failed");
-			ConnectionError::RequestError
-		})?;
+	fetch_user_data(
+		global
+			.http_client
+			.get("https://api.kick.com/public/v1/users")
+			.bearer_auth(access_token),
+	)
+	.await
+}
+
+/// Looks up a user by id with an app access token.
+pub async fn get_user_data_by_id(
+	global: &Arc<Global>,
+	app_access_token: &str,
+	id: &str,
+) -> Result<KickUserData, ConnectionError> {
+	fetch_user_data(
+		global
+			.http_client
+			.get("https://api.kick.com/public/v1/users")
+			.query(&[("id", id)])
+			.bearer_auth(app_access_token),
+	)
+	.await
+}
+
+async fn fetch_user_data(req: reqwest::RequestBuilder) -> Result<KickUserData, ConnectionError> {
+	let res = req.send().await.map_err(|err| {
+		tracing::error!(error = %err, "request failed");
+		ConnectionError::RequestError
+	})?;
 
 	let status = res.status();
 	let text = res.text().await.map_err(|err| {
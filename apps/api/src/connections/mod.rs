@@ -1,5 +1,6 @@
 use std::sync::Arc;
 
+use fred::prelude::KeysInterface;
 use serde::{Deserialize, Serialize};
 use shared::database::user::connection::Platform;
 
@@ -9,6 +10,7 @@ use crate::http::error::{ApiError, ApiErrorCode};
 mod discord;
 mod google;
 mod kick;
+pub mod refresh;
 mod twitch;
 
 #[derive(Debug, thiserror::Error)]
@@ -121,3 +123,138 @@ pub async fn get_user_data(
 		Platform::Kick => kick::get_user_data(global, access_token).await.map(Into::into),
 	}
 }
+
+/// Looks up the current profile data of a user by their platform id without
+/// their access token, using the app credentials of the platform. Returns
+/// [`ConnectionError::UnsupportedPlatform`] if the platform has no credentials
+/// for this configured.
+#[tracing::instrument(skip(global))]
+pub async fn get_user_data_by_id(
+	global: &Arc<Global>,
+	platform: Platform,
+	platform_id: &str,
+) -> Result<PlatformUserData, ConnectionError> {
+	match platform {
+		Platform::Discord => {
+			let bot_token = global
+				.config
+				.connections
+				.discord
+				.api_key
+				.as_deref()
+				.ok_or(ConnectionError::UnsupportedPlatform)?;
+			discord::get_user_data_by_id(global, bot_token, platform_id)
+				.await
+				.map(Into::into)
+		}
+		Platform::Google => {
+			let api_key = global
+				.config
+				.connections
+				.google
+				.api_key
+				.as_deref()
+				.ok_or(ConnectionError::UnsupportedPlatform)?;
+			google::get_user_data_by_id(global, api_key, platform_id)
+				.await
+				.map(Into::into)
+		}
+		Platform::Twitch => {
+			let token = app_access_token(global, platform).await?;
+			twitch::get_user_data_by_id(global, &token, platform_id).await.map(Into::into)
+		}
+		Platform::Kick => {
+			let token = app_access_token(global, platform).await?;
+			kick::get_user_data_by_id(global, &token, platform_id).await.map(Into::into)
+		}
+	}
+}
+
+#[derive(Debug, Serialize)]
+struct AppTokenRequest<'a> {
+	grant_type: &'a str,
+	client_id: &'a str,
+	client_secret: &'a str,
+}
+
+#[derive(Debug, Deserialize)]
+struct AppTokenResponse {
+	access_token: String,
+	expires_in: i64,
+}
+
+/// Returns an app access token of the platform from the client credentials
+/// grant. Tokens are cached in redis until shortly before they expire.
+///
+/// Twitch docs: https://dev.twitch.tv/docs/authentication/getting-tokens-oauth/#client-credentials-grant-flow
+/// Kick docs: https://docs.kick.com/getting-started/generating-tokens-oauth2-flow#app-access-token
+async fn app_access_token(global: &Arc<Global>, platform: Platform) -> Result<String, ConnectionError> {
+	let (endpoint, config) = match platform {
+		Platform::Twitch => ("https://id.twitch.tv/oauth2/token", &global.config.connections.twitch),
+		Platform::Kick => ("https://id.kick.com/oauth/token", &global.config.connections.kick),
+		_ => return Err(ConnectionError::UnsupportedPlatform),
+	};
+
+	if !config.enabled {
+		return Err(ConnectionError::UnsupportedPlatform);
+	}
+
+	let key = format!("connections:app_token:{platform}");
+
+	let cached: Option<String> = global.redis.get(&key).await.map_err(|err| {
+		tracing::error!(error = %err, "failed to get app token from redis");
+		ConnectionError::RequestError
+	})?;
+
+	if let Some(token) = cached {
+		return Ok(token);
+	}
+
+	let req = AppTokenRequest {
+		grant_type: "client_credentials",
+		client_id: &config.client_id,
+		client_secret: &config.client_secret,
+	};
+
+	let res = global.http_client.post(endpoint).form(&req).send().await.map_err(|err| {
+		tracing::error!(error = %err, "request failed");
+		ConnectionError::RequestError
+	})?;
+
+	let status = res.status();
+	let text = res.text().await.map_err(|err| {
+		tracing::error!(error = %err, "failed to read response");
+		ConnectionError::RequestError
+	})?;
+
+	if !status.is_success() {
+		tracing::error!(%status, text, "invalid response");
+		return Err(ConnectionError::RequestError);
+	}
+
+	let res: AppTokenResponse = serde_json::from_str(&text).map_err(|err| {
+		tracing::error!(error = %err, text, "failed to parse response");
+		ConnectionError::RequestError
+	})?;
+
+	// expire the cached token a minute early so it is never used after it expired
+	let ttl = res.expires_in - 60;
+
+	if ttl > 0 {
+		if let Err(err) = global
+			.redis
+			.set::<(), _, _>(
+				&key,
+				res.access_token.as_str(),
+				Some(fred::types::Expiration::EX(ttl)),
+				None,
+				false,
+			)
+			.await
+		{
+			tracing::error!(error = %err, "failed to set app token in redis");
+		}
+	}
+
+	Ok(res.access_token)
+}
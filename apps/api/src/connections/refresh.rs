@@ -0,0 +1,224 @@
+use std::convert::Infallible;
+use std::sync::Arc;
+
+use anyhow::Context as _;
+use fred::prelude::KeysInterface;
+use mongodb::options::{FindOneAndUpdateOptions, ReturnDocument};
+use shared::database::queries::{filter, update};
+use shared::database::user::connection::{Platform, UserConnection};
+use shared::database::user::{User, UserId};
+use shared::event::{InternalEvent, InternalEventData, InternalEventUserData};
+
+use super::{ConnectionError, PlatformUserData};
+use crate::global::Global;
+use crate::transactions::{transaction_with_mutex, GeneralMutexKey, TransactionError};
+
+/// Refreshes the profile data of the user's connections in the background,
+/// except for the connection they just logged in with, which is already
+/// updated by the login itself. Failures are only logged.
+pub fn spawn_refresh(global: &Arc<Global>, user_id: UserId, platform: Platform, platform_id: &str) {
+	if global.config.api.connection_refresh_interval == 0 {
+		return;
+	}
+
+	let global = Arc::clone(global);
+	let platform_id = platform_id.to_string();
+
+	tokio::spawn(async move {
+		if let Err(err) = refresh_connections(&global, user_id, platform, &platform_id).await {
+			tracing::warn!(error = %err, user_id = %user_id, "failed to refresh connections");
+		}
+	});
+}
+
+async fn refresh_connections(
+	global: &Arc<Global>,
+	user_id: UserId,
+	platform: Platform,
+	platform_id: &str,
+) -> anyhow::Result<()> {
+	let interval = global.config.api.connection_refresh_interval;
+
+	let user = global
+		.user_by_id_loader
+		.load(user_id)
+		.await
+		.map_err(|()| anyhow::anyhow!("failed to load user"))?
+		.context("user not found")?;
+
+	let mut updates = Vec::new();
+
+	for connection in &user.connections {
+		if connection.platform == platform && connection.platform_id == platform_id {
+			continue;
+		}
+
+		if !claim_refresh(global, connection, interval).await? {
+			continue;
+		}
+
+		let user_data = match super::get_user_data_by_id(global, connection.platform, &connection.platform_id).await {
+			Ok(user_data) => user_data,
+			Err(ConnectionError::UnsupportedPlatform) => continue,
+			Err(err) => {
+				tracing::warn!(
+					error = %err,
+					platform = %connection.platform,
+					platform_id = %connection.platform_id,
+					"failed to refresh connection"
+				);
+				continue;
+			}
+		};
+
+		if let Some(new) = refreshed_connection(connection, &user_data, chrono::Utc::now()) {
+			updates.push((connection.clone(), new));
+		}
+	}
+
+	if updates.is_empty() {
+		return Ok(());
+	}
+
+	transaction_with_mutex(
+		global,
+		"connections.refresh",
+		Some(GeneralMutexKey::User(user_id).into()),
+		move |mut tx| async move {
+			for (old, new) in updates {
+				let after = tx
+					.find_one_and_update(
+						filter::filter! {
+							User {
+								#[query(rename = "_id")]
+								id: user_id,
+								#[query(elem_match)]
+								connections: UserConnection {
+									platform: old.platform,
+									platform_id: &old.platform_id,
+								}
+							}
+						},
+						update::update! {
+							#[query(set)]
+							User {
+								#[query(flatten, index = "$")]
+								connections: UserConnection {
+									platform_username: &new.platform_username,
+									platform_display_name: &new.platform_display_name,
+									platform_avatar_url: &new.platform_avatar_url,
+									updated_at: new.updated_at,
+								},
+								updated_at: chrono::Utc::now(),
+								search_updated_at: &None,
+							}
+						},
+						FindOneAndUpdateOptions::builder()
+							.return_document(ReturnDocument::After)
+							.build(),
+					)
+					.await?;
+
+				// the connection was removed in the meantime
+				let Some(after) = after else {
+					continue;
+				};
+
+				tx.register_event(InternalEvent {
+					actor: None,
+					session_id: None,
+					data: InternalEventData::User {
+						after,
+						data: InternalEventUserData::UpdateConnection { old, new },
+					},
+					timestamp: chrono::Utc::now(),
+				})?;
+			}
+
+			Ok::<_, TransactionError<Infallible>>(())
+		},
+	)
+	.await?;
+
+	Ok(())
+}
+
+/// Marks the connection as refreshed for the length of the interval, returns
+/// false if it was already refreshed within the interval. This also rate limits
+/// the requests to the platform.
+async fn claim_refresh(global: &Arc<Global>, connection: &UserConnection, interval: u64) -> anyhow::Result<bool> {
+	let set: Option<String> = global
+		.redis
+		.set(
+			format!("connection_refresh:{}:{}", connection.platform, connection.platform_id),
+			1,
+			Some(fred::types::Expiration::EX(interval as i64)),
+			Some(fred::types::SetOptions::NX),
+			false,
+		)
+		.await?;
+
+	Ok(set.is_some())
+}
+
+/// Returns the connection updated with the profile data from the platform, or
+/// `None` if nothing changed.
+fn refreshed_connection(
+	connection: &UserConnection,
+	user_data: &PlatformUserData,
+	now: chrono::DateTime<chrono::Utc>,
+) -> Option<UserConnection> {
+	if user_data.id != connection.platform_id
+		|| (user_data.username == connection.platform_username
+			&& user_data.display_name == connection.platform_display_name
+			&& user_data.avatar == connection.platform_avatar_url)
+	{
+		return None;
+	}
+
+	Some(UserConnection {
+		platform_username: user_data.username.clone(),
+		platform_display_name: user_data.display_name.clone(),
+		platform_avatar_url: user_data.avatar.clone(),
+		updated_at: now,
+		..connection.clone()
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_refreshed_connection() {
+		let now = chrono::Utc::now();
+
+		let connection = UserConnection {
+			platform: Platform::Discord,
+			platform_id: "1".to_string(),
+			platform_username: "user".to_string(),
+			platform_display_name: "User".to_string(),
+			platform_avatar_url: None,
+			updated_at: now - chrono::Duration::days(1),
+			linked_at: now - chrono::Duration::days(1),
+			allow_login: true,
+		};
+
+		let mut user_data = PlatformUserData {
+			id: "1".to_string(),
+			username: "user".to_string(),
+			display_name: "User".to_string(),
+			avatar: None,
+		};
+		assert!(refreshed_connection(&connection, &user_data, now).is_none());
+
+		user_data.avatar = Some("https://cdn.discordapp.com/avatars/1/a.png".to_string());
+		let refreshed = refreshed_connection(&connection, &user_data, now).unwrap();
+		assert_eq!(refreshed.platform_avatar_url, user_data.avatar);
+		assert_eq!(refreshed.updated_at, now);
+		assert_eq!(refreshed.linked_at, connection.linked_at);
+
+		user_data.id = "2".to_string();
+		assert!(refreshed_connection(&connection, &user_data, now).is_none());
+	}
+}
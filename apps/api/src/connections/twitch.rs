@@ -30,17 +30,38 @@ impl From<TwitchUserData> for PlatformUserData {
 }
 
 pub async fn get_user_data(global: &Arc<Global>, access_token: &str) -> Result<TwitchUserData, ConnectionError> {
-	let res = global
-		.http_client
-		.get("https://api.twitch.tv/helix/users")
-		.header("Client-Id", global.config.connections.twitch.client_id.clone())
-		.bearer_auth(access_token)
-		.send()
-		.await
-		.map_err(|err| {
-			tracing::error!(error = %err, "request failed");
-			ConnectionError::RequestError
-		})?;
+	fetch_user_data(
+		global
+			.http_client
+			.get("https://api.twitch.tv/helix/users")
+			.header("Client-Id", global.config.connections.twitch.client_id.clone())
+			.bearer_auth(access_token),
+	)
+	.await
+}
+
+/// Looks up a user by id with an app access token.
+pub async fn get_user_data_by_id(
+	global: &Arc<Global>,
+	app_access_token: &str,
+	id: &str,
+) -> Result<TwitchUserData, ConnectionError> {
+	fetch_user_data(
+		global
+			.http_client
+			.get("https://api.twitch.tv/helix/users")
+			.query(&[("id", id)])
+			.header("Client-Id", global.config.connections.twitch.client_id.clone())
+			.bearer_auth(app_access_token),
+	)
+	.await
+}
+
+async fn fetch_user_data(req: reqwest::RequestBuilder) -> Result<TwitchUserData, ConnectionError> {
+	let res = req.send().await.map_err(|err| {
+		tracing::error!(error = %err, "request failed");
+		ConnectionError::RequestError
+	})?;
 
 	let status = res.status();
 	let text = res.text().await.map_err(|err| {
@@ -0,0 +1,97 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use futures::TryStreamExt;
+use shared::database::cron_job::CronJob;
+use shared::database::entitlement::{EntitlementEdge, EntitlementEdgeId, EntitlementEdgeKind};
+use shared::database::queries::{filter, update};
+use shared::database::user::{User, UserCached};
+use shared::database::MongoCollection;
+
+use crate::dataloader::user_computed_cache;
+use crate::global::Global;
+
+pub async fn run(global: &Arc<Global>, _job: CronJob) -> anyhow::Result<()> {
+	tracing::info!("started entitlement expiry job");
+
+	let now = chrono::Utc::now();
+
+	let expired: Vec<EntitlementEdge> = EntitlementEdge::collection(&global.db)
+		.find(filter::filter! {
+			EntitlementEdge {
+				#[query(selector = "lt")]
+				expires_at: now,
+			}
+		})
+		.await?
+		.try_collect()
+		.await?;
+
+	if expired.is_empty() {
+		return Ok(());
+	}
+
+	tracing::info!("found {} expired entitlement edges", expired.len());
+
+	let ids: Vec<EntitlementEdgeId> = expired.iter().map(|edge| edge.id.clone()).collect();
+
+	let res = EntitlementEdge::collection(&global.db)
+		.delete_many(filter::filter! {
+			EntitlementEdge {
+				#[query(rename = "_id", selector = "in", serde)]
+				id: ids,
+			}
+		})
+		.await?;
+
+	tracing::info!("deleted {} expired entitlement edges", res.deleted_count);
+
+	// The computed entitlements of every user that was granted something through
+	// one of these edges are now stale, so we mark them for recalculation.
+	let from: HashSet<EntitlementEdgeKind> = expired.into_iter().map(|edge| edge.id.from).collect();
+
+	let user_ids: Vec<_> = from
+		.iter()
+		.filter_map(|kind| match kind {
+			EntitlementEdgeKind::User { user_id } => Some(*user_id),
+			_ => None,
+		})
+		.collect();
+
+	let res = User::collection(&global.db)
+		.update_many(
+			filter::Filter::or([
+				filter::filter! {
+					User {
+						#[query(rename = "_id", selector = "in")]
+						id: user_ids,
+					}
+				},
+				filter::filter! {
+					User {
+						#[query(flatten)]
+						cached: UserCached {
+							#[query(selector = "in", serde)]
+							entitlements: from.iter().collect::<Vec<_>>(),
+						}
+					}
+				},
+			]),
+			update::update! {
+				#[query(set)]
+				User {
+					updated_at: now,
+					search_updated_at: &None,
+				}
+			},
+		)
+		.await?;
+
+	tracing::info!("invalidated entitlements of {} users", res.modified_count);
+
+	for kind in &from {
+		user_computed_cache::invalidate_edge(global, kind).await?;
+	}
+
+	Ok(())
+}
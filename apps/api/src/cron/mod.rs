@@ -2,7 +2,7 @@ use std::sync::Arc;
 
 use anyhow::Context;
 use scuffle_context::ContextFutExt;
-use shared::database::cron_job::{CronJob, CronJobId, CronJobInterval};
+use shared::database::cron_job::{default_cron_jobs, CronJob, CronJobId, CronJobInterval};
 use shared::database::queries::{filter, update};
 use shared::database::{Id, MongoCollection};
 use tracing::Instrument;
@@ -10,11 +10,15 @@ use tracing::Instrument;
 use crate::global::Global;
 
 mod emote_stats;
+mod entitlement_expiry;
+mod stale_images;
 mod sub_refresh;
 
 pub async fn run(global: Arc<Global>, ctx: scuffle_context::Context) -> anyhow::Result<()> {
 	tracing::info!("started cron job runner");
 
+	ensure_default_jobs(&global).await.context("ensure default jobs")?;
+
 	loop {
 		if tokio::time::sleep(std::time::Duration::from_secs(5))
 			.with_context(&ctx)
@@ -76,6 +80,43 @@ pub async fn run(global: Arc<Global>, ctx: scuffle_context::Context) -> anyhow::
 	Ok(())
 }
 
+/// Creates the default jobs which do not exist yet, jobs which already exist
+/// are left untouched so their schedule and state are kept.
+async fn ensure_default_jobs(global: &Arc<Global>) -> Result<(), mongodb::error::Error> {
+	for job in default_cron_jobs() {
+		CronJob::collection(&global.db)
+			.update_one(
+				filter::filter! {
+					CronJob {
+						#[query(rename = "_id")]
+						id: job.id,
+					}
+				},
+				update::update! {
+					#[query(set_on_insert)]
+					CronJob {
+						name: job.name,
+						description: job.description,
+						tags: job.tags,
+						last_run: job.last_run,
+						next_run: job.next_run,
+						#[query(serde)]
+						interval: job.interval,
+						enabled: job.enabled,
+						currently_running_by: job.currently_running_by,
+						held_until: job.held_until,
+						updated_at: job.updated_at,
+						search_updated_at: job.search_updated_at,
+					}
+				},
+			)
+			.upsert(true)
+			.await?;
+	}
+
+	Ok(())
+}
+
 async fn fetch_job(global: &Arc<Global>, id: Id) -> Result<Option<CronJob>, mongodb::error::Error> {
 	let now = chrono::Utc::now();
 
@@ -146,6 +187,8 @@ async fn run_job(global: &Arc<Global>, job: CronJob, id: Id) -> anyhow::Result<(
 	match job_id {
 		CronJobId::SubscriptionRefresh => sub_refresh::run(global, job).await.context("sub refresh")?,
 		CronJobId::EmoteScoresUpdate => emote_stats::run(global, job).await.context("emote stats")?,
+		CronJobId::EntitlementExpiry => entitlement_expiry::run(global, job).await.context("entitlement expiry")?,
+		CronJobId::StaleImageReprocess => stale_images::run(global, job).await.context("stale images")?,
 	}
 
 	complete_job(global, job_id, interval, id).await.context("complete job")?;
@@ -0,0 +1,441 @@
+use std::sync::Arc;
+
+use bson::doc;
+use fred::prelude::KeysInterface;
+use futures::TryStreamExt;
+use mongodb::options::{FindOneAndUpdateOptions, ReturnDocument};
+use shared::database::badge::{Badge, BadgeId};
+use shared::database::cron_job::CronJob;
+use shared::database::emote::{Emote, EmoteId};
+use shared::database::image_set::{ImageSet, ImageSetInput};
+use shared::database::paint::{Paint, PaintId, PaintLayerId, PaintLayerType};
+use shared::database::queries::{filter, update};
+use shared::database::stored_event::{
+	ImageProcessorEvent, StoredEventBadgeData, StoredEventEmoteData, StoredEventPaintData, StoredEventUserProfilePictureData,
+};
+use shared::database::user::profile_picture::{UserProfilePicture, UserProfilePictureId};
+use shared::database::user::UserId;
+use shared::database::MongoCollection;
+use shared::event::{InternalEvent, InternalEventData};
+use shared::image_processor::{ProcessPriority, Subject};
+
+use crate::global::Global;
+use crate::transactions::{transaction_with_mutex, GeneralMutexKey, TransactionError};
+
+/// The maximum number of documents of each kind submitted per run.
+const MAX_RESUBMITS_PER_KIND: i64 = 1000;
+
+#[derive(Debug, Clone, Copy)]
+enum StaleImage {
+	Emote(EmoteId),
+	ProfilePicture(UserProfilePictureId, UserId),
+	Badge(BadgeId),
+	PaintLayer(PaintId, PaintLayerId),
+}
+
+impl StaleImage {
+	fn subject(&self) -> Subject {
+		match *self {
+			Self::Emote(id) => Subject::Emote(id),
+			Self::ProfilePicture(id, _) => Subject::ProfilePicture(id),
+			Self::Badge(id) => Subject::Badge(id),
+			Self::PaintLayer(id, layer_id) => Subject::PaintLayer(id, layer_id),
+		}
+	}
+
+	fn mutex_key(&self) -> Option<GeneralMutexKey> {
+		match *self {
+			Self::Emote(id) => Some(GeneralMutexKey::Emote(id)),
+			Self::ProfilePicture(..) => None,
+			Self::Badge(id) => Some(GeneralMutexKey::Badge(id)),
+			Self::PaintLayer(id, _) => Some(GeneralMutexKey::Paint(id)),
+		}
+	}
+
+	/// Submits the image to the image processor again, returns the id of the
+	/// new task.
+	async fn resubmit(&self, global: &Arc<Global>, path: String) -> anyhow::Result<String> {
+		let image_processor = &global.image_processor;
+
+		let response = match *self {
+			Self::Emote(id) => image_processor.reprocess_emote(path, id, ProcessPriority::Low).await?,
+			Self::ProfilePicture(id, user_id) => {
+				image_processor
+					.reprocess_profile_picture(path, id, user_id, ProcessPriority::Low)
+					.await?
+			}
+			Self::Badge(id) => image_processor.reprocess_badge(path, id, ProcessPriority::Low).await?,
+			Self::PaintLayer(id, layer_id) => {
+				image_processor
+					.reprocess_paint_layer(path, id, layer_id, ProcessPriority::Low)
+					.await?
+			}
+		};
+
+		if let Some(err) = response.error {
+			anyhow::bail!("image processor error: {err:?}");
+		}
+
+		Ok(response.id)
+	}
+
+	/// The filter matching the document while the image is still pending with
+	/// the task `task_id` and the path of the pending input to update.
+	fn pending_input(&self, task_id: &str) -> (bson::Document, &'static str) {
+		match *self {
+			Self::Emote(id) => (
+				doc! { "_id": id, "image_set.input.type": "pending", "image_set.input.task_id": task_id },
+				"image_set.input",
+			),
+			Self::ProfilePicture(id, _) => (
+				doc! { "_id": id, "image_set.input.type": "pending", "image_set.input.task_id": task_id },
+				"image_set.input",
+			),
+			Self::Badge(id) => (
+				doc! { "_id": id, "image_set.input.type": "pending", "image_set.input.task_id": task_id },
+				"image_set.input",
+			),
+			Self::PaintLayer(id, layer_id) => (
+				doc! {
+					"_id": id,
+					"data.layers": {
+						"$elemMatch": {
+							"id": layer_id,
+							"ty.data.input.type": "pending",
+							"ty.data.input.task_id": task_id,
+						},
+					},
+				},
+				"data.layers.$.ty.data.input",
+			),
+		}
+	}
+}
+
+#[derive(Debug)]
+struct PendingImage {
+	image: StaleImage,
+	/// The source file of the image
+	path: String,
+	/// The task the image was last submitted as
+	task_id: String,
+	resubmits: u32,
+}
+
+impl PendingImage {
+	/// Returns the image if its input is still pending.
+	fn new(image: StaleImage, image_set: ImageSet) -> Option<Self> {
+		let ImageSetInput::Pending {
+			path,
+			task_id,
+			resubmits,
+			..
+		} = image_set.input
+		else {
+			return None;
+		};
+
+		Some(Self {
+			image,
+			path,
+			task_id,
+			resubmits,
+		})
+	}
+}
+
+/// A kind of document with images which can get stuck pending.
+trait StaleImageDocument: MongoCollection + serde::de::DeserializeOwned + Send + Sync {
+	fn pending_images(self) -> Vec<PendingImage>;
+
+	fn process_event(after: Self, event: ImageProcessorEvent) -> InternalEventData;
+}
+
+impl StaleImageDocument for Emote {
+	fn pending_images(self) -> Vec<PendingImage> {
+		PendingImage::new(StaleImage::Emote(self.id), self.image_set)
+			.into_iter()
+			.collect()
+	}
+
+	fn process_event(after: Self, event: ImageProcessorEvent) -> InternalEventData {
+		InternalEventData::Emote {
+			after,
+			data: StoredEventEmoteData::Process { event },
+		}
+	}
+}
+
+impl StaleImageDocument for UserProfilePicture {
+	fn pending_images(self) -> Vec<PendingImage> {
+		PendingImage::new(StaleImage::ProfilePicture(self.id, self.user_id), self.image_set)
+			.into_iter()
+			.collect()
+	}
+
+	fn process_event(after: Self, event: ImageProcessorEvent) -> InternalEventData {
+		InternalEventData::UserProfilePicture {
+			after,
+			data: StoredEventUserProfilePictureData::Process { event },
+		}
+	}
+}
+
+impl StaleImageDocument for Badge {
+	fn pending_images(self) -> Vec<PendingImage> {
+		PendingImage::new(StaleImage::Badge(self.id), self.image_set)
+			.into_iter()
+			.collect()
+	}
+
+	fn process_event(after: Self, event: ImageProcessorEvent) -> InternalEventData {
+		InternalEventData::Badge {
+			after,
+			data: StoredEventBadgeData::Process { event },
+		}
+	}
+}
+
+impl StaleImageDocument for Paint {
+	fn pending_images(self) -> Vec<PendingImage> {
+		self.data
+			.layers
+			.into_iter()
+			.filter_map(|layer| match layer.ty {
+				PaintLayerType::Image(image_set) => PendingImage::new(StaleImage::PaintLayer(self.id, layer.id), image_set),
+				_ => None,
+			})
+			.collect()
+	}
+
+	fn process_event(after: Self, event: ImageProcessorEvent) -> InternalEventData {
+		InternalEventData::Paint {
+			after,
+			data: StoredEventPaintData::Process { event },
+		}
+	}
+}
+
+/// Matches pending inputs at `path` which were not submitted again too often.
+/// Inputs from before the count was added have no count.
+fn pending_input_filter(path: &str, max_resubmits: u32) -> bson::Document {
+	doc! {
+		format!("{path}.type"): "pending",
+		format!("{path}.resubmits"): { "$not": { "$gte": max_resubmits } },
+	}
+}
+
+struct StaleImages<'a> {
+	global: &'a Arc<Global>,
+	/// Matches documents which were updated within the window in which their
+	/// pending images are submitted again
+	updated_at: bson::Document,
+	timeout: u64,
+	max_resubmits: u32,
+}
+
+impl StaleImages<'_> {
+	/// Submits the pending images of the documents of `T` matching `filter`
+	/// again and counts the attempt on the document, returns the number of
+	/// resubmitted images.
+	async fn resubmit<T: StaleImageDocument>(&self, mut filter: bson::Document) -> anyhow::Result<u64> {
+		filter.extend(self.updated_at.clone());
+
+		let documents: Vec<T> = T::collection(&self.global.db)
+			.find(filter::Value::new(filter))
+			.limit(MAX_RESUBMITS_PER_KIND)
+			.await?
+			.try_collect()
+			.await?;
+
+		let mut total = 0;
+
+		for pending in documents.into_iter().flat_map(T::pending_images) {
+			// other layers of the same paint can still be pending
+			if pending.resubmits >= self.max_resubmits {
+				continue;
+			}
+
+			let image = pending.image;
+
+			match self.resubmit_one::<T>(pending).await {
+				Ok(true) => total += 1,
+				Ok(false) => {}
+				Err(err) => tracing::error!(error = %err, image = ?image, "failed to resubmit image"),
+			}
+		}
+
+		Ok(total)
+	}
+
+	/// Returns false if the image is no longer pending with the task it was
+	/// loaded with or if it was resubmitted recently.
+	async fn resubmit_one<T: StaleImageDocument>(&self, pending: PendingImage) -> anyhow::Result<bool> {
+		let image = pending.image;
+		let (filter, input) = image.pending_input(&pending.task_id);
+
+		// the callback might have arrived since the documents were loaded
+		let current = self
+			.global
+			.db
+			.collection::<bson::Document>(T::COLLECTION_NAME)
+			.count_documents(filter.clone())
+			.await?;
+
+		if current == 0 || !claim_resubmit(self.global, image.subject(), self.timeout).await? {
+			return Ok(false);
+		}
+
+		let task_id = image.resubmit(self.global, pending.path).await?;
+
+		let res = transaction_with_mutex(
+			self.global,
+			"cron.stale_images.resubmit",
+			image.mutex_key().map(Into::into),
+			|mut tx| async move {
+				let update = update::Update::from(update::Set::new(doc! { format!("{input}.task_id"): &task_id }))
+					.extend_one(update::Inc::new(doc! { format!("{input}.resubmits"): 1 }));
+
+				let Some(after) = tx
+					.find_one_and_update::<T>(
+						filter::Value::new(filter),
+						update,
+						FindOneAndUpdateOptions::builder()
+							.return_document(ReturnDocument::After)
+							.build(),
+					)
+					.await?
+				else {
+					// the callback of the previous task arrived in the meantime
+					return Ok(false);
+				};
+
+				tx.register_event(InternalEvent {
+					actor: None,
+					session_id: None,
+					data: T::process_event(after, ImageProcessorEvent::Resubmit { task_id }),
+					timestamp: chrono::Utc::now(),
+				})?;
+
+				Ok(true)
+			},
+		)
+		.await;
+
+		match res {
+			Ok(updated) => Ok(updated),
+			Err(TransactionError::Custom(e)) => Err(e),
+			Err(e) => anyhow::bail!("transaction failed: {e}"),
+		}
+	}
+}
+
+/// Finds images whose input is still pending long after they were uploaded,
+/// which happens when the image processor callback was lost, and submits them
+/// to the image processor again. Images which are still pending after
+/// `image_max_resubmits` attempts or `image_pending_max_days` are assumed to
+/// have failed.
+pub async fn run(global: &Arc<Global>, _job: CronJob) -> anyhow::Result<()> {
+	tracing::info!("started stale image job");
+
+	let timeout = global.config.api.image_pending_timeout;
+
+	if timeout == 0 {
+		tracing::info!("stale image check is disabled");
+		return Ok(());
+	}
+
+	let now = chrono::Utc::now();
+	let stale_before = now - chrono::Duration::seconds(timeout as i64);
+	let pending_after = now - chrono::Duration::days(global.config.api.image_pending_max_days as i64);
+	let max_resubmits = global.config.api.image_max_resubmits;
+
+	let stale = StaleImages {
+		global,
+		updated_at: doc! {
+			"updated_at": {
+				"$lt": bson::DateTime::from_chrono(stale_before),
+				"$gt": bson::DateTime::from_chrono(pending_after),
+			},
+		},
+		timeout,
+		max_resubmits,
+	};
+
+	let image_set = pending_input_filter("image_set.input", max_resubmits);
+
+	let mut emote_filter = doc! { "deleted": false };
+	emote_filter.extend(image_set.clone());
+
+	let mut total = 0;
+
+	total += stale.resubmit::<Emote>(emote_filter).await?;
+	total += stale.resubmit::<UserProfilePicture>(image_set.clone()).await?;
+	total += stale.resubmit::<Badge>(image_set).await?;
+	total += stale
+		.resubmit::<Paint>(doc! { "data.layers": { "$elemMatch": pending_input_filter("ty.data.input", max_resubmits) } })
+		.await?;
+
+	tracing::info!("resubmitted {} stale images", total);
+
+	Ok(())
+}
+
+/// Marks the image as resubmitted for the length of the timeout, returns false
+/// if it was already resubmitted and the job might still be in flight.
+async fn claim_resubmit(global: &Arc<Global>, subject: Subject, timeout: u64) -> anyhow::Result<bool> {
+	let set: Option<String> = global
+		.redis
+		.set(
+			subject.to_string("image_resubmit"),
+			1,
+			Some(fred::types::Expiration::EX(timeout as i64)),
+			Some(fred::types::SetOptions::NX),
+			false,
+		)
+		.await?;
+
+	Ok(set.is_some())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::http::fixtures;
+
+	#[test]
+	fn test_pending_input_filter() {
+		assert_eq!(
+			pending_input_filter("image_set.input", 3),
+			doc! {
+				"image_set.input.type": "pending",
+				"image_set.input.resubmits": { "$not": { "$gte": 3u32 } },
+			}
+		);
+	}
+
+	#[test]
+	fn test_pending_image() {
+		let id = EmoteId::new();
+
+		let mut image_set = fixtures::pending_image_set();
+		image_set.input = ImageSetInput::Pending {
+			task_id: "task".to_string(),
+			path: "input.png".to_string(),
+			mime: "image/png".to_string(),
+			size: 1,
+			resubmits: 2,
+		};
+
+		let pending = PendingImage::new(StaleImage::Emote(id), image_set).unwrap();
+		assert_eq!(pending.path, "input.png");
+		assert_eq!(pending.task_id, "task");
+		assert_eq!(pending.resubmits, 2);
+
+		let done = ImageSet {
+			input: ImageSetInput::Image(Default::default()),
+			outputs: vec![],
+		};
+		assert!(PendingImage::new(StaleImage::Emote(id), done).is_none());
+	}
+}
@@ -8,7 +8,7 @@ use shared::database::queries::filter;
 use shared::database::MongoCollection;
 
 use crate::global::Global;
-use crate::sub_refresh_job::refresh;
+use crate::sub_refresh_job::refresh_many;
 
 pub async fn run(global: &Arc<Global>, _job: CronJob) -> anyhow::Result<()> {
 	tracing::info!("started subscription refresh job");
@@ -29,26 +29,22 @@ pub async fn run(global: &Arc<Global>, _job: CronJob) -> anyhow::Result<()> {
 
 	// Do updates in batches of 1000
 	let total = subs.len();
-
-	let semaphore = &tokio::sync::Semaphore::new(1000);
-	let mut futures = futures::stream::FuturesUnordered::from_iter(subs.into_iter().map(|sub| async move {
-		let _ticket = semaphore.acquire().await.unwrap();
-		refresh(global, sub).await.map_err(|err| (sub, err)).map(|_| sub)
-	}));
+	let subs = subs.into_iter().collect::<Vec<_>>();
 
 	let mut error_count = 0;
 
-	while let Some(sub) = futures.next().await {
-		match sub {
-			Ok(sub) => {
-				tracing::debug!(subscription = %sub, "refreshed subscription");
-			}
-			Err((sub, err)) => {
-				tracing::error!(subscription = %sub, error = ?err, "failed to refresh subscription");
-				error_count += 1;
-				if error_count > total / 10 {
-					anyhow::bail!("too many errors");
-				}
+	for batch in subs.chunks(1000) {
+		let count = batch.len();
+
+		let failed = refresh_many(global, batch.iter().copied()).await;
+
+		if failed.is_empty() {
+			tracing::debug!(count, "refreshed subscriptions");
+		} else {
+			tracing::error!(count, failed = failed.len(), "failed to refresh some subscriptions");
+			error_count += failed.len();
+			if error_count > total / 10 {
+				anyhow::bail!("too many errors");
 			}
 		}
 	}
@@ -14,28 +14,81 @@ use shared::database::graph::{Direction, GraphTraverse};
 use shared::database::loader::dataloader::BatchLoad;
 use shared::database::paint::PaintId;
 use shared::database::queries::filter;
-use shared::database::role::permissions::{Permissions, PermissionsExt, UserPermission};
+use shared::database::role::permissions::{
+	PermissionDecision, PermissionExplanation, Permissions, PermissionsExt, UserPermission,
+};
 use shared::database::role::{Role, RoleId};
 use shared::database::user::ban::ActiveBans;
 use shared::database::user::{FullUser, User, UserComputed, UserId};
 use shared::database::{Id, MongoCollection};
 use tracing::Instrument;
 
+use super::user_computed_cache::UserComputedCache;
+use crate::config::UserComputedCacheConfig;
 use crate::global::Global;
 
 pub struct FullUserLoader {
 	pub computed_loader: DataLoader<UserComputedLoader>,
+	computed_cache: Option<UserComputedCache>,
 	all_cosmetics_loader: DataLoader<AllCosmeticsLoader>,
 }
 
 impl FullUserLoader {
-	pub fn new(global: Weak<Global>) -> Self {
+	pub fn new(global: Weak<Global>, cache_config: Option<&UserComputedCacheConfig>) -> Self {
 		Self {
 			computed_loader: UserComputedLoader::new(global.clone()),
+			computed_cache: cache_config.and_then(UserComputedCache::new),
 			all_cosmetics_loader: AllCosmeticsLoader::new(global.clone()),
 		}
 	}
 
+	/// Loads the computed permissions and entitlements of a user, from the
+	/// cache if it is enabled
+	pub async fn load_computed(&self, global: &Arc<Global>, user_id: UserId) -> Result<Option<UserComputed>, ()> {
+		self.load_computed_many(global, std::iter::once(user_id))
+			.await
+			.map(|mut computed| computed.remove(&user_id))
+	}
+
+	/// Loads the computed permissions and entitlements of the users, from the
+	/// cache if it is enabled. Redis errors fall back to computing the values.
+	pub async fn load_computed_many(
+		&self,
+		global: &Arc<Global>,
+		user_ids: impl IntoIterator<Item = UserId> + Send,
+	) -> Result<HashMap<UserId, UserComputed>, ()> {
+		let Some(cache) = &self.computed_cache else {
+			return self.computed_loader.load_many(user_ids).await;
+		};
+
+		let user_ids = user_ids.into_iter().collect::<Vec<_>>();
+
+		let (version, mut computed) = match cache.get_many(global, &user_ids).await {
+			Ok(cached) => cached,
+			Err(err) => {
+				tracing::warn!(error = %err, "failed to read user computed cache");
+				return self.computed_loader.load_many(user_ids).await;
+			}
+		};
+
+		let missing = user_ids
+			.into_iter()
+			.filter(|id| !computed.contains_key(id))
+			.collect::<Vec<_>>();
+
+		if !missing.is_empty() {
+			let loaded = self.computed_loader.load_many(missing).await?;
+
+			if let Err(err) = cache.insert_many(global, &version, &loaded).await {
+				tracing::warn!(error = %err, "failed to write user computed cache");
+			}
+
+			computed.extend(loaded);
+		}
+
+		Ok(computed)
+	}
+
 	/// Performs a full user load fetching all necessary data using the graph
 	pub async fn load(&self, global: &Arc<Global>, user_id: UserId) -> Result<Option<FullUser>, ()> {
 		self.load_many(global, std::iter::once(user_id))
@@ -70,7 +123,7 @@ impl FullUserLoader {
 	) -> Result<HashMap<UserId, FullUser>, ()> {
 		let users = user.into_iter().collect::<Vec<_>>();
 
-		let computed = self.computed_loader.load_many(users.iter().map(|user| user.id)).await?;
+		let computed = self.load_computed_many(global, users.iter().map(|user| user.id)).await?;
 
 		let all_cosmetics = if users.iter().any(|user| user.all_cosmetics) {
 			self.all_cosmetics_loader.load(()).await?.unwrap_or_default()
@@ -139,6 +192,7 @@ impl FullUserLoader {
 								to,
 								managed_by: Some(EntitlementEdgeManagedBy::AllCosmetics),
 							},
+							expires_at: None,
 						});
 					}
 				}
@@ -180,6 +234,25 @@ impl FullUserLoader {
 			.collect())
 	}
 
+	/// Explains every permission bit set for the user, along with the decision
+	/// each of their roles made for it.
+	pub async fn explain_permissions(&self, global: &Arc<Global>, user: &FullUser) -> Result<Vec<PermissionProvenance>, ()> {
+		let mut roles: Vec<_> = global
+			.role_by_id_loader
+			.load_many(user.computed.entitlements.roles.iter().copied())
+			.await?
+			.into_values()
+			.collect();
+
+		roles.sort_by_key(|r| r.rank);
+
+		Ok(compute_permission_provenance(
+			&roles,
+			&user.computed.entitlements.roles,
+			&user.computed.permissions,
+		))
+	}
+
 	/// Performs a fast user load fetching using the cache'ed data
 	pub async fn load_fast(&self, global: &Arc<Global>, user_id: UserId) -> Result<Option<FullUser>, ()> {
 		self.load_fast_many(global, std::iter::once(user_id))
@@ -319,6 +392,7 @@ impl FullUserLoader {
 							to,
 							managed_by: Some(EntitlementEdgeManagedBy::AllCosmetics),
 						},
+						expires_at: None,
 					});
 				}
 			}
@@ -595,6 +669,38 @@ fn compute_permissions(sorted_roles: &[Role], user_roles: &HashSet<RoleId>) -> P
 		})
 }
 
+#[derive(Debug, Clone)]
+pub struct PermissionProvenance {
+	pub explanation: PermissionExplanation,
+	/// The decision of every role which allows or denies the permission, in the
+	/// order they are merged
+	pub roles: Vec<(RoleId, PermissionDecision)>,
+}
+
+fn compute_permission_provenance(
+	sorted_roles: &[Role],
+	user_roles: &HashSet<RoleId>,
+	permissions: &Permissions,
+) -> Vec<PermissionProvenance> {
+	let roles: Vec<_> = sorted_roles.iter().filter(|role| user_roles.contains(&role.id)).collect();
+
+	let mut bits = permissions.bits();
+	bits.extend(roles.iter().flat_map(|role| role.permissions.bits()));
+	bits.sort_by_key(|p| (p.category(), p.bits()));
+	bits.dedup_by_key(|p| (p.category(), p.bits()));
+
+	bits.into_iter()
+		.map(|permission| PermissionProvenance {
+			explanation: permissions.explain(permission),
+			roles: roles
+				.iter()
+				.map(|role| (role.id, role.permissions.explain(permission).decision))
+				.filter(|(_, decision)| *decision != PermissionDecision::Unset)
+				.collect(),
+		})
+		.collect()
+}
+
 fn compute_highest_role_rank(sorted_roles: &[Role], user_roles: &HashSet<RoleId>) -> i32 {
 	sorted_roles
 		.iter()
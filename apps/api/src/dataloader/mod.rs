@@ -6,5 +6,6 @@ pub mod subscription_products;
 pub mod ticket_message;
 pub mod user;
 pub mod user_ban;
+pub mod user_computed_cache;
 pub mod user_editor;
 pub mod user_session;
@@ -0,0 +1,223 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use fred::prelude::KeysInterface;
+use shared::database::entitlement::EntitlementEdgeKind;
+use shared::database::user::{UserComputed, UserId};
+
+use crate::config::UserComputedCacheConfig;
+use crate::global::Global;
+
+/// Bumped to invalidate the cached values of all users at once.
+const GENERATION_KEY: &str = "user_computed:generation";
+
+/// Seconds the version of a user is kept after it was last bumped. This has to
+/// be much longer than the ttl of the cached values, otherwise a reset version
+/// could point at a value cached before the invalidation.
+const VERSION_TTL: i64 = 60 * 60 * 24;
+
+fn version_key(user_id: UserId) -> String {
+	format!("user_computed:version:{user_id}")
+}
+
+fn value_key(generation: i64, version: i64, user_id: UserId) -> String {
+	format!("user_computed:{generation}:{version}:{user_id}")
+}
+
+/// The generation and user versions read before the values were computed.
+/// Values are stored under these, so a value computed while an invalidation
+/// happened is stored under an outdated key and never read.
+#[derive(Debug, Default)]
+pub struct CacheVersion {
+	generation: i64,
+	versions: HashMap<UserId, i64>,
+}
+
+/// Seconds the value can be cached for, capped at the earliest expiry of its
+/// entitlement edges.
+fn entry_ttl(ttl: u64, value: &UserComputed, now: chrono::DateTime<chrono::Utc>) -> i64 {
+	value
+		.raw_entitlements
+		.iter()
+		.flatten()
+		.filter_map(|edge| edge.expires_at)
+		.map(|expires_at| (expires_at - now).num_seconds())
+		.fold(ttl as i64, i64::min)
+}
+
+/// A short lived cache of the computed permissions and entitlements of users,
+/// shared by all api instances through redis.
+///
+/// Anything changing entitlement edges or roles has to invalidate the cache,
+/// see [`invalidate`] and [`invalidate_all`]. Transactions writing to either
+/// collection invalidate all users on commit.
+pub struct UserComputedCache {
+	ttl: u64,
+}
+
+impl UserComputedCache {
+	pub fn new(config: &UserComputedCacheConfig) -> Option<Self> {
+		(config.ttl != 0).then_some(Self { ttl: config.ttl })
+	}
+
+	/// Returns the cached values of the users along with the version to store
+	/// the missing values under.
+	pub async fn get_many(
+		&self,
+		global: &Arc<Global>,
+		user_ids: &[UserId],
+	) -> Result<(CacheVersion, HashMap<UserId, UserComputed>), fred::error::Error> {
+		let generation: Option<i64> = global.redis.get(GENERATION_KEY).await?;
+		let generation = generation.unwrap_or_default();
+
+		let entries = futures::future::try_join_all(user_ids.iter().map(|&user_id| async move {
+			let version: Option<i64> = global.redis.get(version_key(user_id)).await?;
+			let version = version.unwrap_or_default();
+
+			let value: Option<Vec<u8>> = global.redis.get(value_key(generation, version, user_id)).await?;
+
+			Ok::<_, fred::error::Error>((user_id, version, value))
+		}))
+		.await?;
+
+		let mut version = CacheVersion {
+			generation,
+			versions: HashMap::new(),
+		};
+		let mut values = HashMap::new();
+
+		for (user_id, user_version, value) in entries {
+			version.versions.insert(user_id, user_version);
+
+			match value.map(|value| rmp_serde::from_slice(&value)) {
+				Some(Ok(value)) => {
+					values.insert(user_id, value);
+				}
+				Some(Err(err)) => tracing::warn!(error = %err, user_id = %user_id, "invalid cached user computed"),
+				None => {}
+			}
+		}
+
+		Ok((version, values))
+	}
+
+	/// Caches the values under the version read before computing them. Values
+	/// are never cached past the expiry of one of their entitlement edges.
+	pub async fn insert_many(
+		&self,
+		global: &Arc<Global>,
+		version: &CacheVersion,
+		values: &HashMap<UserId, UserComputed>,
+	) -> Result<(), fred::error::Error> {
+		let now = chrono::Utc::now();
+
+		futures::future::try_join_all(values.iter().filter_map(|(&user_id, value)| {
+			let user_version = *version.versions.get(&user_id)?;
+
+			let ttl = entry_ttl(self.ttl, value, now);
+
+			if ttl <= 0 {
+				return None;
+			}
+
+			let value = match rmp_serde::to_vec_named(value) {
+				Ok(value) => value,
+				Err(err) => {
+					tracing::warn!(error = %err, user_id = %user_id, "failed to serialize user computed");
+					return None;
+				}
+			};
+
+			Some(async move {
+				global
+					.redis
+					.set::<(), _, _>(
+						value_key(version.generation, user_version, user_id),
+						value,
+						Some(fred::types::Expiration::EX(ttl)),
+						None,
+						false,
+					)
+					.await
+			})
+		}))
+		.await?;
+
+		Ok(())
+	}
+}
+
+/// Invalidates the cached computed permissions of the user.
+pub async fn invalidate(global: &Arc<Global>, user_id: UserId) -> Result<(), fred::error::Error> {
+	if global.config.api.user_computed_cache.is_none() {
+		return Ok(());
+	}
+
+	let key = version_key(user_id);
+	global.redis.incr::<(), _>(&key).await?;
+	global.redis.expire::<(), _>(&key, VERSION_TTL, None).await?;
+
+	Ok(())
+}
+
+/// Invalidates the cached computed permissions of all users, used when roles
+/// or edges affecting more than a single user change.
+pub async fn invalidate_all(global: &Arc<Global>) -> Result<(), fred::error::Error> {
+	if global.config.api.user_computed_cache.is_none() {
+		return Ok(());
+	}
+
+	global.redis.incr::<(), _>(GENERATION_KEY).await
+}
+
+/// Invalidates the users affected by a change of an entitlement edge starting
+/// at `from`. Only edges from a user or one of their subscriptions affect a
+/// single user, anything else can be inherited by any number of users.
+pub async fn invalidate_edge(global: &Arc<Global>, from: &EntitlementEdgeKind) -> Result<(), fred::error::Error> {
+	match from {
+		EntitlementEdgeKind::User { user_id } => invalidate(global, *user_id).await,
+		EntitlementEdgeKind::Subscription { subscription_id } => invalidate(global, subscription_id.user_id).await,
+		_ => invalidate_all(global).await,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use shared::database::entitlement::EntitlementEdge;
+	use shared::database::role::RoleId;
+
+	use super::*;
+
+	#[test]
+	fn test_entry_ttl() {
+		let now = chrono::Utc::now();
+		let user_id = UserId::new();
+
+		let edge = |expires_at| EntitlementEdge {
+			expires_at,
+			..EntitlementEdge::new(
+				EntitlementEdgeKind::User { user_id },
+				EntitlementEdgeKind::Role { role_id: RoleId::new() },
+				None,
+			)
+		};
+
+		let mut value = UserComputed {
+			raw_entitlements: Some(vec![edge(None)]),
+			..Default::default()
+		};
+		assert_eq!(entry_ttl(30, &value, now), 30);
+
+		value
+			.raw_entitlements
+			.get_or_insert_default()
+			.push(edge(Some(now + chrono::Duration::seconds(10))));
+		assert_eq!(entry_ttl(30, &value, now), 10);
+
+		value
+			.raw_entitlements
+			.get_or_insert_default()
+			.push(edge(Some(now - chrono::Duration::seconds(10))));
+		assert!(entry_ttl(30, &value, now) <= 0);
+	}
+}
@@ -50,6 +50,7 @@ use crate::dataloader::user::{UserByPlatformIdLoader, UserByPlatformUsernameLoad
 use crate::dataloader::user_ban::UserBanByUserIdLoader;
 use crate::dataloader::user_editor::{UserEditorByEditorIdLoader, UserEditorByUserIdLoader};
 use crate::dataloader::user_session::UserSessionUpdaterBatcher;
+use crate::http::persisted_queries::PersistedQueries;
 use crate::http::v4;
 use crate::mutex::DistributedMutex;
 use crate::ratelimit::RateLimiter;
@@ -105,6 +106,8 @@ pub struct Global {
 	pub typesense: typesense_rs::apis::ApiClient,
 	pub updater: MongoUpdater,
 	pub mutex: DistributedMutex,
+	pub v3_persisted_queries: Option<PersistedQueries>,
+	pub v4_persisted_queries: Option<PersistedQueries>,
 	metrics_registry: scuffle_bootstrap_telemetry::prometheus_client::registry::Registry,
 }
 
@@ -203,12 +206,30 @@ impl scuffle_bootstrap::global::Global for Global {
 
 		tracing::info!("connected to redis mutex");
 
+		let v3_persisted_queries = config
+			.api
+			.persisted_queries
+			.as_ref()
+			.map(|config| PersistedQueries::new(config, config.v3_allow_list.as_deref()))
+			.transpose()
+			.context("v3 persisted queries")?;
+
+		let v4_persisted_queries = config
+			.api
+			.persisted_queries
+			.as_ref()
+			.map(|config| PersistedQueries::new(config, config.v4_allow_list.as_deref()))
+			.transpose()
+			.context("v4 persisted queries")?;
+
 		Ok(Arc::new_cyclic(|weak| Self {
 			nats,
 			geoip,
 			redis,
 			rate_limiter,
 			mutex,
+			v3_persisted_queries,
+			v4_persisted_queries,
 			jetstream,
 			image_processor,
 			event_by_id_loader: LoaderById::new(db.clone()),
@@ -251,9 +272,9 @@ impl scuffle_bootstrap::global::Global for Global {
 			updater: MongoUpdater::new(db.clone(), 1000, 500, std::time::Duration::from_millis(5)),
 			db,
 			clickhouse,
+			user_loader: FullUserLoader::new(weak.clone(), config.api.user_computed_cache.as_ref()),
 			config,
 			metrics_registry,
-			user_loader: FullUserLoader::new(weak.clone()),
 		}))
 	}
 
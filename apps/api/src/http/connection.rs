@@ -0,0 +1,54 @@
+use shared::database::role::permissions::{PermissionsExt, UserPermission};
+use shared::database::user::connection::UserConnection;
+use shared::database::user::FullUser;
+
+use crate::http::error::{ApiError, ApiErrorCode};
+
+/// Checks that a user keeps at least one connection which can be used to log
+/// in after some of their connections were removed.
+///
+/// Removing every connection which can be used to log in would lock the user
+/// out of their account, so this is only allowed for users with
+/// [`UserPermission::ManageAny`].
+pub fn check_login_connection(connections: &[UserConnection], authed_user: &FullUser) -> Result<(), ApiError> {
+	if !connections.iter().any(|c| c.allow_login) && !authed_user.has(UserPermission::ManageAny) {
+		return Err(ApiError::bad_request(
+			ApiErrorCode::BadRequest,
+			"cannot remove last login connection",
+		));
+	}
+
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn connection(allow_login: bool) -> UserConnection {
+		UserConnection {
+			platform: Default::default(),
+			platform_id: "1".to_string(),
+			platform_username: "user".to_string(),
+			platform_display_name: "User".to_string(),
+			platform_avatar_url: None,
+			updated_at: chrono::Utc::now(),
+			linked_at: chrono::Utc::now(),
+			allow_login,
+		}
+	}
+
+	#[test]
+	fn test_check_login_connection() {
+		let user = FullUser::default();
+
+		assert!(check_login_connection(&[connection(true), connection(false)], &user).is_ok());
+		assert!(check_login_connection(&[connection(false)], &user).is_err());
+		assert!(check_login_connection(&[], &user).is_err());
+
+		let mut admin = FullUser::default();
+		admin.computed.permissions.allow(UserPermission::ManageAny);
+
+		assert!(check_login_connection(&[], &admin).is_ok());
+	}
+}
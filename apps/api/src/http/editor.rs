@@ -0,0 +1,158 @@
+use std::sync::Arc;
+
+use shared::database::role::permissions::{PermissionsExt, UserPermission};
+use shared::database::user::editor::{EditorUserPermission, UserEditor, UserEditorId, UserEditorState};
+use shared::database::user::{FullUser, UserId};
+
+use crate::global::Global;
+use crate::http::error::{ApiError, ApiErrorCode};
+
+/// The maximum number of editors returned by the non paginated editor lists.
+/// The v3 lists have no paginated alternative in v3 and stay capped.
+pub const MAX_EDITORS: usize = 500;
+
+/// The key editor lists are ordered by. Editors added at the same time are
+/// ordered by their id, so the order is stable for cursors.
+pub fn editor_order_key(editor: &UserEditor) -> (chrono::DateTime<chrono::Utc>, UserId, UserId) {
+	(editor.added_at, editor.id.user_id, editor.id.editor_id)
+}
+
+/// Orders the editors by when they were added and keeps at most
+/// [`MAX_EDITORS`] of them.
+pub fn capped_editors(mut editors: Vec<UserEditor>) -> Vec<UserEditor> {
+	editors.sort_by_key(editor_order_key);
+	editors.truncate(MAX_EDITORS);
+	editors
+}
+
+/// Checks that the actor can act on behalf of the target user with the given
+/// editor permission. Users can always act on themselves and users with
+/// `UserPermission::ManageAny` can act on anyone, otherwise the actor has to be
+/// an accepted editor of the target user.
+///
+/// Returns the editor the permission was granted through, if any.
+async fn ensure_editor_permission(
+	global: &Arc<Global>,
+	actor: &FullUser,
+	target_user_id: UserId,
+	permission: EditorUserPermission,
+	permission_name: &str,
+	action: &str,
+) -> Result<Option<UserEditor>, ApiError> {
+	if actor.id == target_user_id || actor.has(UserPermission::ManageAny) {
+		return Ok(None);
+	}
+
+	let editor = global
+		.user_editor_by_id_loader
+		.load(UserEditorId {
+			editor_id: actor.id,
+			user_id: target_user_id,
+		})
+		.await
+		.map_err(|_| ApiError::internal_server_error(ApiErrorCode::LoadError, "failed to load editor"))?
+		.ok_or_else(|| {
+			ApiError::forbidden(
+				ApiErrorCode::LackingPrivileges,
+				format!("you do not have permission to {action}"),
+			)
+		})?;
+
+	if editor.state != UserEditorState::Accepted || !editor.permissions.has(permission) {
+		return Err(ApiError::forbidden(
+			ApiErrorCode::LackingPrivileges,
+			format!("you do not have permission to {action}, you need the {permission_name} permission"),
+		));
+	}
+
+	Ok(Some(editor))
+}
+
+/// Checks that the actor can manage the profile of the target user, `action`
+/// describes what is being done for the error message.
+pub async fn ensure_can_manage_profile(
+	global: &Arc<Global>,
+	actor: &FullUser,
+	target_user_id: UserId,
+	action: &str,
+) -> Result<(), ApiError> {
+	ensure_editor_permission(
+		global,
+		actor,
+		target_user_id,
+		EditorUserPermission::ManageProfile,
+		"ManageProfile",
+		action,
+	)
+	.await?;
+
+	Ok(())
+}
+
+/// Checks that the actor can manage the editors of the target user. Returns
+/// the editor the permission was granted through, so callers can make sure
+/// editors do not grant permissions they do not have themselves.
+pub async fn ensure_can_manage_editors(
+	global: &Arc<Global>,
+	actor: &FullUser,
+	target_user_id: UserId,
+) -> Result<Option<UserEditor>, ApiError> {
+	ensure_editor_permission(
+		global,
+		actor,
+		target_user_id,
+		EditorUserPermission::ManageEditors,
+		"ManageEditors",
+		"modify editors",
+	)
+	.await
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn editor(user_id: UserId, editor_id: UserId, added_at: chrono::DateTime<chrono::Utc>) -> UserEditor {
+		UserEditor {
+			id: UserEditorId { user_id, editor_id },
+			state: UserEditorState::Accepted,
+			notes: None,
+			permissions: Default::default(),
+			added_by_id: user_id,
+			added_at,
+			updated_at: added_at,
+			search_updated_at: None,
+		}
+	}
+
+	#[test]
+	fn test_capped_editors_order() {
+		let user_id = UserId::new();
+		let now = chrono::Utc::now();
+
+		let mut editor_ids = [UserId::new(), UserId::new(), UserId::new()];
+		editor_ids.sort();
+
+		let editors = capped_editors(vec![
+			editor(user_id, editor_ids[2], now),
+			editor(user_id, editor_ids[0], now + chrono::Duration::seconds(1)),
+			editor(user_id, editor_ids[1], now),
+		]);
+
+		// ties on added_at are ordered by id
+		assert_eq!(
+			editors.iter().map(|e| e.id.editor_id).collect::<Vec<_>>(),
+			[editor_ids[1], editor_ids[2], editor_ids[0]]
+		);
+	}
+
+	#[test]
+	fn test_capped_editors_limit() {
+		let user_id = UserId::new();
+		let now = chrono::Utc::now();
+
+		let editors = capped_editors((0..MAX_EDITORS + 10).map(|_| editor(user_id, UserId::new(), now)).collect());
+
+		assert_eq!(editors.len(), MAX_EDITORS);
+	}
+}
@@ -60,137 +60,142 @@ pub async fn cancel_subscription(
 	req.http(&global, async {
 		let stripe_client = global.stripe_client.safe(Id::<()>::new()).await;
 
-		let res = transaction_with_mutex(&global, Some(EgVaultMutexKey::User(target_id).into()), |mut tx| {
-			let global = Arc::clone(&global);
+		let res = transaction_with_mutex(
+			&global,
+			"egvault.cancel_subscription",
+			Some(EgVaultMutexKey::User(target_id).into()),
+			|mut tx| {
+				let global = Arc::clone(&global);
 
-			async move {
-				let period = tx
-					.find_one(
-						filter::filter! {
-							SubscriptionPeriod {
-								#[query(flatten)]
-								subscription_id: SubscriptionId {
-									user_id: target_id,
-								},
-								#[query(selector = "lt")]
-								start: chrono::Utc::now(),
-								#[query(selector = "gt")]
-								end: chrono::Utc::now(),
-							}
-						},
-						None,
-					)
-					.await?
-					.ok_or(TransactionError::Custom(ApiError::not_found(
-						ApiErrorCode::BadRequest,
-						"subscription not found",
-					)))?;
-
-				match period.provider_id {
-					Some(ProviderSubscriptionId::Stripe(id)) => {
-						stripe::Subscription::update(
-							stripe_client.client("update").await.deref(),
-							&id,
-							stripe::UpdateSubscription {
-								cancel_at_period_end: Some(true),
-								..Default::default()
+				async move {
+					let period = tx
+						.find_one(
+							filter::filter! {
+								SubscriptionPeriod {
+									#[query(flatten)]
+									subscription_id: SubscriptionId {
+										user_id: target_id,
+									},
+									#[query(selector = "lt")]
+									start: chrono::Utc::now(),
+									#[query(selector = "gt")]
+									end: chrono::Utc::now(),
+								}
 							},
+							None,
 						)
-						.await
-						.map_err(|e| {
-							tracing::error!(error = %e, "failed to update stripe subscription");
-							TransactionError::Custom(ApiError::internal_server_error(
-								ApiErrorCode::StripeError,
-								"failed to update stripe subscription",
-							))
-						})?;
-					}
-					Some(ProviderSubscriptionId::Paypal(id)) => {
-						let api_key = paypal_api::api_key(&global).await.map_err(TransactionError::Custom)?;
+						.await?
+						.ok_or(TransactionError::Custom(ApiError::not_found(
+							ApiErrorCode::BadRequest,
+							"subscription not found",
+						)))?;
 
-						// https://developer.paypal.com/docs/api/subscriptions/v1/#subscriptions_cancel
-						let response = global
-							.http_client
-							.post(format!("https://api.paypal.com/v1/billing/subscriptions/{id}/cancel"))
-							.bearer_auth(&api_key)
-							.json(&serde_json::json!({
-								"reason": "Subscription canceled by user"
-							}))
-							.send()
+					match period.provider_id {
+						Some(ProviderSubscriptionId::Stripe(id)) => {
+							stripe::Subscription::update(
+								stripe_client.client("update").await.deref(),
+								&id,
+								stripe::UpdateSubscription {
+									cancel_at_period_end: Some(true),
+									..Default::default()
+								},
+							)
 							.await
 							.map_err(|e| {
-								tracing::error!(error = %e, "failed to cancel paypal subscription");
+								tracing::error!(error = %e, "failed to update stripe subscription");
 								TransactionError::Custom(ApiError::internal_server_error(
-									ApiErrorCode::PaypalError,
-									"failed to cancel paypal subscription",
+									ApiErrorCode::StripeError,
+									"failed to update stripe subscription",
 								))
 							})?;
+						}
+						Some(ProviderSubscriptionId::Paypal(id)) => {
+							let api_key = paypal_api::api_key(&global).await.map_err(TransactionError::Custom)?;
+
+							// https://developer.paypal.com/docs/api/subscriptions/v1/#subscriptions_cancel
+							let response = global
+								.http_client
+								.post(format!("https://api.paypal.com/v1/billing/subscriptions/{id}/cancel"))
+								.bearer_auth(&api_key)
+								.json(&serde_json::json!({
+									"reason": "Subscription canceled by user"
+								}))
+								.send()
+								.await
+								.map_err(|e| {
+									tracing::error!(error = %e, "failed to cancel paypal subscription");
+									TransactionError::Custom(ApiError::internal_server_error(
+										ApiErrorCode::PaypalError,
+										"failed to cancel paypal subscription",
+									))
+								})?;
 
-						if !response.status().is_success() {
-							tracing::error!(status = %response.status(), "failed to cancel paypal subscription");
-							return Err(TransactionError::Custom(ApiError::internal_server_error(
-								ApiErrorCode::PaypalError,
-								"failed to cancel paypal subscription",
-							)));
+							if !response.status().is_success() {
+								tracing::error!(status = %response.status(), "failed to cancel paypal subscription");
+								return Err(TransactionError::Custom(ApiError::internal_server_error(
+									ApiErrorCode::PaypalError,
+									"failed to cancel paypal subscription",
+								)));
+							}
 						}
-					}
-					None => {
-						// This is a gifted or system subscription
-						// End the current period right away
+						None => {
+							// This is a gifted or system subscription
+							// End the current period right away
 
-						tx.update_one(
-							filter::filter! {
-								SubscriptionPeriod {
-									#[query(rename = "_id")]
-									id: period.id,
-								}
-							},
-							update::update! {
-								#[query(set)]
-								SubscriptionPeriod {
-									end: chrono::Utc::now(),
-									updated_at: chrono::Utc::now(),
-									search_updated_at: &None,
+							tx.update_one(
+								filter::filter! {
+									SubscriptionPeriod {
+										#[query(rename = "_id")]
+										id: period.id,
+									}
 								},
-							},
-							None,
-						)
-						.await?;
+								update::update! {
+									#[query(set)]
+									SubscriptionPeriod {
+										end: chrono::Utc::now(),
+										updated_at: chrono::Utc::now(),
+										search_updated_at: &None,
+									},
+								},
+								None,
+							)
+							.await?;
+						}
 					}
-				}
 
-				// This would get updated by the sub refresh job eventually but we want it to
-				// reflect instantly
-				tx.update_one(
-					filter::filter! {
-						Subscription {
-							#[query(rename = "_id", serde)]
-							id: period.subscription_id,
-						}
-					},
-					update::update! {
-						#[query(set)]
-						Subscription {
-							#[query(serde)]
-							state: SubscriptionState::CancelAtEnd,
-							updated_at: chrono::Utc::now(),
-							search_updated_at: &None,
-						}
-					},
-					None,
-				)
-				.await
-				.map_err(|e| {
-					tracing::error!(error = %e, "failed to update subscription");
-					TransactionError::Custom(ApiError::internal_server_error(
-						ApiErrorCode::MutationError,
-						"failed to update subscription",
-					))
-				})?;
+					// This would get updated by the sub refresh job eventually but we want it to
+					// reflect instantly
+					tx.update_one(
+						filter::filter! {
+							Subscription {
+								#[query(rename = "_id", serde)]
+								id: period.subscription_id,
+							}
+						},
+						update::update! {
+							#[query(set)]
+							Subscription {
+								#[query(serde)]
+								state: SubscriptionState::CancelAtEnd,
+								updated_at: chrono::Utc::now(),
+								search_updated_at: &None,
+							}
+						},
+						None,
+					)
+					.await
+					.map_err(|e| {
+						tracing::error!(error = %e, "failed to update subscription");
+						TransactionError::Custom(ApiError::internal_server_error(
+							ApiErrorCode::MutationError,
+							"failed to update subscription",
+						))
+					})?;
 
-				Ok(())
-			}
-		})
+					Ok(())
+				}
+			},
+		)
 		.await;
 
 		match res {
@@ -246,84 +251,89 @@ pub async fn reactivate_subscription(
 	req.http(&global, async {
 		let stripe_client = global.stripe_client.safe(Id::<()>::new()).await;
 
-		let res = transaction_with_mutex(&global, Some(EgVaultMutexKey::User(target_id).into()), |mut tx| async move {
-			let period = tx
-				.find_one(
-					filter::filter! {
-						SubscriptionPeriod {
-							#[query(flatten)]
-							subscription_id: SubscriptionId {
-								user_id: target_id,
-							},
-							#[query(selector = "lt")]
-							start: chrono::Utc::now(),
-							#[query(selector = "gt")]
-							end: chrono::Utc::now(),
-						}
-					},
-					None,
-				)
-				.await?
-				.ok_or_else(|| {
-					TransactionError::Custom(ApiError::not_found(ApiErrorCode::BadRequest, "subscription not found"))
-				})?;
-
-			match period.provider_id {
-				Some(ProviderSubscriptionId::Stripe(id)) => {
-					stripe::Subscription::update(
-						stripe_client.client("update").await.deref(),
-						&id,
-						stripe::UpdateSubscription {
-							cancel_at_period_end: Some(false),
-							..Default::default()
-						},
-					)
-					.await
-					.map_err(|e| {
-						tracing::error!(error = %e, "failed to update stripe subscription");
-						TransactionError::Custom(ApiError::internal_server_error(
-							ApiErrorCode::MutationError,
-							"failed to update stripe subscription",
-						))
-					})?;
-
-					// This would get updated by the sub refresh job eventually but we want it to
-					// reflect instantly
-					tx.update_one(
+		let res = transaction_with_mutex(
+			&global,
+			"egvault.reactivate_subscription",
+			Some(EgVaultMutexKey::User(target_id).into()),
+			|mut tx| async move {
+				let period = tx
+					.find_one(
 						filter::filter! {
-							Subscription {
-								#[query(rename = "_id", serde)]
-								id: period.subscription_id,
-							}
-						},
-						update::update! {
-							#[query(set)]
-							Subscription {
-								#[query(serde)]
-								state: SubscriptionState::Active,
-								updated_at: chrono::Utc::now(),
-								search_updated_at: &None,
+							SubscriptionPeriod {
+								#[query(flatten)]
+								subscription_id: SubscriptionId {
+									user_id: target_id,
+								},
+								#[query(selector = "lt")]
+								start: chrono::Utc::now(),
+								#[query(selector = "gt")]
+								end: chrono::Utc::now(),
 							}
 						},
 						None,
 					)
-					.await
-					.map_err(|e| {
-						tracing::error!(error = %e, "failed to update subscription");
-						TransactionError::Custom(ApiError::internal_server_error(
-							ApiErrorCode::MutationError,
-							"failed to update subscription",
-						))
+					.await?
+					.ok_or_else(|| {
+						TransactionError::Custom(ApiError::not_found(ApiErrorCode::BadRequest, "subscription not found"))
 					})?;
 
-					Ok(())
+				match period.provider_id {
+					Some(ProviderSubscriptionId::Stripe(id)) => {
+						stripe::Subscription::update(
+							stripe_client.client("update").await.deref(),
+							&id,
+							stripe::UpdateSubscription {
+								cancel_at_period_end: Some(false),
+								..Default::default()
+							},
+						)
+						.await
+						.map_err(|e| {
+							tracing::error!(error = %e, "failed to update stripe subscription");
+							TransactionError::Custom(ApiError::internal_server_error(
+								ApiErrorCode::MutationError,
+								"failed to update stripe subscription",
+							))
+						})?;
+
+						// This would get updated by the sub refresh job eventually but we want it to
+						// reflect instantly
+						tx.update_one(
+							filter::filter! {
+								Subscription {
+									#[query(rename = "_id", serde)]
+									id: period.subscription_id,
+								}
+							},
+							update::update! {
+								#[query(set)]
+								Subscription {
+									#[query(serde)]
+									state: SubscriptionState::Active,
+									updated_at: chrono::Utc::now(),
+									search_updated_at: &None,
+								}
+							},
+							None,
+						)
+						.await
+						.map_err(|e| {
+							tracing::error!(error = %e, "failed to update subscription");
+							TransactionError::Custom(ApiError::internal_server_error(
+								ApiErrorCode::MutationError,
+								"failed to update subscription",
+							))
+						})?;
+
+						Ok(())
+					}
+					_ => Err(TransactionError::Custom(ApiError::not_implemented(
+						ApiErrorCode::BadRequest,
+						"this subscription cannot be reactivated",
+					))),
 				}
-				_ => Err(TransactionError::Custom(ApiError::not_implemented(
-					ApiErrorCode::BadRequest,
-					"this subscription cannot be reactivated",
-				))),
-			}
-		})
+			},
+		)
 		.await;
 
 		match res {
@@ -14,6 +14,7 @@ use shared::database::user::UserId;
 use shared::database::{Id, MongoCollection};
 
 use super::metadata::{CheckoutSessionMetadata, StripeMetadata, SubscriptionMetadata};
+use crate::dataloader::user_computed_cache;
 use crate::global::Global;
 use crate::http::error::{ApiError, ApiErrorCode};
 use crate::http::middleware::session::Session;
@@ -258,6 +259,7 @@ pub async fn grant_entitlements(
 								redeem_code_id: redeem_code.id,
 							}),
 						},
+						expires_at: None,
 					}))
 					.await
 					.map_err(|err| {
@@ -278,6 +280,7 @@ pub async fn grant_entitlements(
 							redeem_code_id: redeem_code.id,
 						}),
 					},
+					expires_at: None,
 				})
 				.await
 				.map_err(|err| {
@@ -287,6 +290,10 @@ pub async fn grant_entitlements(
 		}
 	}
 
+	if let Err(err) = user_computed_cache::invalidate(global, user_id).await {
+		tracing::error!(error = %err, "failed to invalidate user computed cache");
+	}
+
 	Ok(())
 }
 
@@ -0,0 +1,436 @@
+use std::sync::Arc;
+
+use mongodb::options::{FindOneAndUpdateOptions, ReturnDocument};
+use shared::database::emote::{Emote, EmoteId, EmoteMerged};
+use shared::database::emote_moderation_request::{EmoteModerationRequest, EmoteModerationRequestStatus};
+use shared::database::emote_set::{EmoteSet, EmoteSetEmote};
+use shared::database::queries::{filter, update};
+use shared::database::role::permissions::{EmotePermission, PermissionsExt};
+use shared::database::stored_event::StoredEventEmoteData;
+use shared::database::user::FullUser;
+use shared::event::{InternalEvent, InternalEventData, InternalEventEmoteSetData};
+
+use crate::global::Global;
+use crate::http::error::{ApiError, ApiErrorCode};
+use crate::http::middleware::session::Session;
+use crate::transactions::{
+	transaction_with_mutex, GeneralMutexKey, TransactionError, TransactionResult, TransactionSession,
+};
+
+/// Checks that the source emote can be merged into the target. A merged
+/// emote can never be the target of a merge, so merges can not form cycles.
+pub fn check_merge(source: &Emote, target: &Emote) -> Result<(), ApiError> {
+	if source.id == target.id {
+		return Err(ApiError::bad_request(
+			ApiErrorCode::BadRequest,
+			"an emote can not be merged into itself",
+		));
+	}
+
+	if source.merged.is_some() {
+		return Err(ApiError::bad_request(ApiErrorCode::BadRequest, "emote is already merged"));
+	}
+
+	if let Some(merged) = &target.merged {
+		return Err(ApiError::bad_request(
+			ApiErrorCode::BadRequest,
+			format!("target emote is merged into {}", merged.target_id),
+		));
+	}
+
+	if target.deleted {
+		return Err(ApiError::bad_request(ApiErrorCode::BadRequest, "target emote is deleted"));
+	}
+
+	Ok(())
+}
+
+/// Checks that an emote used in the given number of emote sets can be
+/// deleted or merged. Emotes used in more sets than the threshold can only be
+/// changed by admins.
+fn check_usage(action: &str, usage: u64, threshold: u64, admin: bool) -> Result<(), ApiError> {
+	if usage > threshold && !admin {
+		return Err(ApiError::forbidden(
+			ApiErrorCode::LackingPrivileges,
+			format!("emote is used in {usage} emote sets, only admins can {action} emotes used in more than {threshold}"),
+		));
+	}
+
+	Ok(())
+}
+
+/// Counts the emote sets using the emote.
+async fn count_usage(tx: &mut TransactionSession<'_, ApiError>, emote_id: EmoteId) -> TransactionResult<u64, ApiError> {
+	tx.count(
+		filter::filter! {
+			EmoteSet {
+				#[query(flatten)]
+				emotes: EmoteSetEmote {
+					id: emote_id,
+				}
+			}
+		},
+		None,
+	)
+	.await
+}
+
+/// Counts the emote sets using the emote and checks that the user is allowed
+/// to delete it. This must be called inside the transaction which deletes the
+/// emote.
+pub async fn check_delete(
+	global: &Arc<Global>,
+	tx: &mut TransactionSession<'_, ApiError>,
+	user: &FullUser,
+	emote_id: EmoteId,
+) -> TransactionResult<(), ApiError> {
+	let usage = count_usage(tx, emote_id).await?;
+
+	check_usage(
+		"delete",
+		usage,
+		global.config.api.emote_delete_usage_threshold,
+		user.has(EmotePermission::Admin),
+	)
+	.map_err(TransactionError::Custom)
+}
+
+/// Replaces the source emote with the target emote, keeping the alias and
+/// flags. If the set already contains the target the source is removed.
+/// Returns the new emotes with the removed and added entries.
+#[allow(clippy::type_complexity)]
+fn merge_set_emotes(
+	emotes: &[EmoteSetEmote],
+	source_id: EmoteId,
+	target_id: EmoteId,
+) -> (Vec<EmoteSetEmote>, Vec<(usize, EmoteSetEmote)>, Vec<EmoteSetEmote>) {
+	let mut has_target = emotes.iter().any(|e| e.id == target_id);
+
+	let mut new_emotes = Vec::with_capacity(emotes.len());
+	let mut removed = Vec::new();
+	let mut added = Vec::new();
+
+	for (index, emote) in emotes.iter().enumerate() {
+		if emote.id != source_id {
+			new_emotes.push(emote.clone());
+			continue;
+		}
+
+		removed.push((index, emote.clone()));
+
+		if !has_target {
+			let merged = EmoteSetEmote {
+				id: target_id,
+				..emote.clone()
+			};
+			new_emotes.push(merged.clone());
+			added.push(merged);
+			has_target = true;
+		}
+	}
+
+	(new_emotes, removed, added)
+}
+
+/// Merges the source emote into the target emote in a transaction holding the
+/// locks of both emotes, see [`merge_emote`]. Otherwise merging A into B and B
+/// into A at the same time could both pass [`check_merge`] and form a cycle.
+/// The locks are taken in a fixed order, so such merges can not deadlock.
+pub async fn merge_emote_locked(
+	global: &Arc<Global>,
+	operation: &'static str,
+	session: &Session,
+	source_id: EmoteId,
+	target_id: EmoteId,
+) -> TransactionResult<Emote, ApiError> {
+	let (first, second) = if source_id < target_id {
+		(source_id, target_id)
+	} else {
+		(target_id, source_id)
+	};
+
+	// the same lock can not be taken twice, check_merge rejects this anyways
+	let first = (first != second).then_some(first);
+
+	let merge = || {
+		transaction_with_mutex(
+			global,
+			operation,
+			Some(GeneralMutexKey::Emote(second).into()),
+			|mut tx| async move { merge_emote(global, &mut tx, session, source_id, target_id).await },
+		)
+	};
+
+	match first {
+		Some(first) => global.mutex.acquire(GeneralMutexKey::Emote(first), merge).await?,
+		None => merge().await,
+	}
+}
+
+/// Merges the source emote into the target emote, every emote set using the
+/// source is rewritten to use the target instead.
+pub async fn merge_emote(
+	global: &Arc<Global>,
+	tx: &mut TransactionSession<'_, ApiError>,
+	session: &Session,
+	source_id: EmoteId,
+	target_id: EmoteId,
+) -> TransactionResult<Emote, ApiError> {
+	let authed_user = session.user().map_err(TransactionError::Custom)?;
+
+	let source = tx
+		.find_one(filter::filter! { Emote { #[query(rename = "_id")] id: source_id } }, None)
+		.await?
+		.ok_or_else(|| TransactionError::Custom(ApiError::not_found(ApiErrorCode::LoadError, "emote not found")))?;
+
+	let target = tx
+		.find_one(filter::filter! { Emote { #[query(rename = "_id")] id: target_id } }, None)
+		.await?
+		.ok_or_else(|| TransactionError::Custom(ApiError::not_found(ApiErrorCode::LoadError, "target emote not found")))?;
+
+	check_merge(&source, &target).map_err(TransactionError::Custom)?;
+
+	let usage = count_usage(tx, source_id).await?;
+
+	check_usage(
+		"merge",
+		usage,
+		global.config.api.emote_merge_usage_threshold,
+		authed_user.has(EmotePermission::Admin),
+	)
+	.map_err(TransactionError::Custom)?;
+
+	let emote_sets = tx
+		.find(
+			filter::filter! {
+				EmoteSet {
+					#[query(flatten)]
+					emotes: EmoteSetEmote {
+						id: source_id,
+					}
+				}
+			},
+			None,
+		)
+		.await?;
+
+	let emote = tx
+		.find_one_and_update(
+			filter::filter! {
+				Emote {
+					#[query(rename = "_id")]
+					id: source_id,
+				}
+			},
+			update::update! {
+				#[query(set)]
+				Emote {
+					#[query(serde)]
+					merged: EmoteMerged {
+						target_id,
+						at: chrono::Utc::now(),
+					},
+					updated_at: chrono::Utc::now(),
+					search_updated_at: &None,
+				}
+			},
+			FindOneAndUpdateOptions::builder()
+				.return_document(ReturnDocument::After)
+				.build(),
+		)
+		.await?
+		.ok_or_else(|| TransactionError::Custom(ApiError::not_found(ApiErrorCode::LoadError, "emote not found")))?;
+
+	tx.update(
+		filter::filter! {
+			EmoteModerationRequest {
+				emote_id: source_id,
+				#[query(serde)]
+				status: EmoteModerationRequestStatus::Pending,
+			}
+		},
+		update::update! {
+			#[query(set)]
+			EmoteModerationRequest {
+				#[query(serde)]
+				status: EmoteModerationRequestStatus::EmoteDeleted,
+			}
+		},
+		None,
+	)
+	.await?;
+
+	tx.register_event(InternalEvent {
+		actor: Some(authed_user.clone()),
+		session_id: session.user_session_id(),
+		data: InternalEventData::Emote {
+			after: emote.clone(),
+			data: StoredEventEmoteData::Merge { new_emote_id: target_id },
+		},
+		timestamp: chrono::Utc::now(),
+	})?;
+
+	let owners = global
+		.user_loader
+		.load_fast_many(global, [source.owner_id, target.owner_id])
+		.await
+		.map_err(|()| {
+			TransactionError::Custom(ApiError::internal_server_error(
+				ApiErrorCode::LoadError,
+				"failed to load emote owners",
+			))
+		})?;
+
+	for emote_set in emote_sets {
+		let (new_emotes, removed, added) = merge_set_emotes(&emote_set.emotes, source_id, target_id);
+
+		let emote_set = tx
+			.find_one_and_update(
+				filter::filter! {
+					EmoteSet {
+						#[query(rename = "_id")]
+						id: emote_set.id,
+					}
+				},
+				update::update! {
+					#[query(set)]
+					EmoteSet {
+						#[query(serde)]
+						emotes: &new_emotes,
+						emotes_changed_since_reindex: true,
+						updated_at: chrono::Utc::now(),
+						search_updated_at: &None,
+					}
+				},
+				FindOneAndUpdateOptions::builder()
+					.return_document(ReturnDocument::After)
+					.build(),
+			)
+			.await?
+			.ok_or_else(|| TransactionError::Custom(ApiError::not_found(ApiErrorCode::LoadError, "emote set not found")))?;
+
+		for (index, emote_set_emote) in removed {
+			tx.register_event(InternalEvent {
+				actor: Some(authed_user.clone()),
+				session_id: session.user_session_id(),
+				data: InternalEventData::EmoteSet {
+					after: emote_set.clone(),
+					data: InternalEventEmoteSetData::RemoveEmote {
+						emote: Some(Box::new(source.clone())),
+						emote_owner: owners.get(&source.owner_id).cloned().map(Box::new),
+						emote_set_emote,
+						index,
+					},
+				},
+				timestamp: chrono::Utc::now(),
+			})?;
+		}
+
+		for emote_set_emote in added {
+			tx.register_event(InternalEvent {
+				actor: Some(authed_user.clone()),
+				session_id: session.user_session_id(),
+				data: InternalEventData::EmoteSet {
+					after: emote_set.clone(),
+					data: InternalEventEmoteSetData::AddEmote {
+						emote: Box::new(target.clone()),
+						emote_owner: owners.get(&target.owner_id).cloned().map(Box::new),
+						emote_set_emote,
+					},
+				},
+				timestamp: chrono::Utc::now(),
+			})?;
+		}
+	}
+
+	Ok(emote)
+}
+
+#[cfg(test)]
+mod tests {
+	use shared::database::emote::EmoteFlags;
+	use shared::database::emote_set::EmoteSetEmoteFlag;
+	use shared::database::user::UserId;
+
+	use super::*;
+	use crate::http::fixtures;
+
+	fn emote() -> Emote {
+		fixtures::emote(EmoteId::new(), UserId::new(), EmoteFlags::default())
+	}
+
+	fn set_emote(id: EmoteId, alias: &str) -> EmoteSetEmote {
+		EmoteSetEmote {
+			id,
+			alias: alias.to_string(),
+			added_at: chrono::Utc::now(),
+			flags: EmoteSetEmoteFlag::default(),
+			added_by_id: None,
+			origin_set_id: None,
+		}
+	}
+
+	#[test]
+	fn test_check_merge() {
+		let source = emote();
+		let target = emote();
+
+		assert!(check_merge(&source, &target).is_ok());
+		assert!(check_merge(&source, &source).is_err());
+
+		// the target is merged into the source, merging would form a cycle
+		let merged_target = Emote {
+			merged: Some(EmoteMerged {
+				target_id: source.id,
+				at: chrono::Utc::now(),
+			}),
+			..target.clone()
+		};
+		assert!(check_merge(&source, &merged_target).is_err());
+
+		let deleted_target = Emote {
+			deleted: true,
+			..target.clone()
+		};
+		assert!(check_merge(&source, &deleted_target).is_err());
+	}
+
+	#[test]
+	fn test_check_usage() {
+		assert!(check_usage("delete", 10, 100, false).is_ok());
+		assert!(check_usage("delete", 100, 100, false).is_ok());
+		assert!(check_usage("delete", 101, 100, false).is_err());
+		assert!(check_usage("merge", 101, 100, true).is_ok());
+	}
+
+	#[test]
+	fn test_merge_set_emotes_replaces_source() {
+		let source = EmoteId::new();
+		let target = EmoteId::new();
+		let other = EmoteId::new();
+
+		let emotes = vec![set_emote(other, "Other"), set_emote(source, "Pog")];
+		let (new_emotes, removed, added) = merge_set_emotes(&emotes, source, target);
+
+		assert_eq!(new_emotes.len(), 2);
+		assert_eq!(new_emotes[1].id, target);
+		assert_eq!(new_emotes[1].alias, "Pog");
+		assert_eq!(removed.len(), 1);
+		assert_eq!(removed[0].0, 1);
+		assert_eq!(added.len(), 1);
+	}
+
+	#[test]
+	fn test_merge_set_emotes_target_present() {
+		let source = EmoteId::new();
+		let target = EmoteId::new();
+
+		let emotes = vec![set_emote(target, "PogU"), set_emote(source, "Pog")];
+		let (new_emotes, removed, added) = merge_set_emotes(&emotes, source, target);
+
+		assert_eq!(new_emotes.len(), 1);
+		assert_eq!(new_emotes[0].alias, "PogU");
+		assert_eq!(removed.len(), 1);
+		assert!(added.is_empty());
+	}
+}
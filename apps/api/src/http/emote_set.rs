@@ -0,0 +1,295 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use shared::database::emote::{Emote, EmoteFlags, EmoteId};
+use shared::database::emote_set::{EmoteSet, EmoteSetEmote};
+use shared::database::role::permissions::effective_emote_set_capacity;
+use shared::database::user::UserId;
+
+use crate::dataloader::emote::EmoteByIdLoaderExt;
+use crate::global::Global;
+use crate::http::error::{ApiError, ApiErrorCode};
+use crate::http::validators::{self, MAX_NAME_LENGTH, MAX_TAGS, TAG_LENGTH};
+
+/// Checks the name of an emote set, the error names the rule which was
+/// violated.
+pub fn check_name(name: &str) -> Result<(), ApiError> {
+	if name.trim().is_empty() {
+		return Err(ApiError::bad_request(
+			ApiErrorCode::BadRequest,
+			"emote set name must not be empty",
+		));
+	}
+
+	if name.chars().count() > MAX_NAME_LENGTH {
+		return Err(ApiError::bad_request(
+			ApiErrorCode::BadRequest,
+			format!("emote set name must be at most {MAX_NAME_LENGTH} characters"),
+		));
+	}
+
+	if !validators::check_name(name) {
+		return Err(ApiError::bad_request(
+			ApiErrorCode::BadRequest,
+			"emote set name contains invalid characters",
+		));
+	}
+
+	Ok(())
+}
+
+/// Checks the tags of an emote set, the error names the rule which was
+/// violated.
+pub fn check_tags(tags: &[String]) -> Result<(), ApiError> {
+	if tags.len() > MAX_TAGS {
+		return Err(ApiError::bad_request(
+			ApiErrorCode::BadRequest,
+			format!("emote sets can have at most {MAX_TAGS} tags"),
+		));
+	}
+
+	for tag in tags {
+		if !TAG_LENGTH.contains(&tag.chars().count()) {
+			return Err(ApiError::bad_request(
+				ApiErrorCode::BadRequest,
+				format!(
+					"emote set tags must be between {} and {} characters",
+					TAG_LENGTH.start(),
+					TAG_LENGTH.end()
+				),
+			));
+		}
+
+		if !validators::check_tag(tag) {
+			return Err(ApiError::bad_request(
+				ApiErrorCode::BadRequest,
+				format!("emote set tag {tag} contains invalid characters"),
+			));
+		}
+	}
+
+	Ok(())
+}
+
+/// Finds the emote which already uses the given alias in the set. Aliases are
+/// compared case-insensitively, the emote at `skip` is ignored so an emote can
+/// be renamed to a different casing of its own alias.
+pub fn find_alias_conflict(emotes: &[EmoteSetEmote], alias: &str, skip: Option<usize>) -> Option<usize> {
+	emotes
+		.iter()
+		.enumerate()
+		.position(|(idx, e)| Some(idx) != skip && e.alias.to_lowercase() == alias.to_lowercase())
+}
+
+/// Checks that the emote is not already in the set.
+pub fn check_duplicate_emote(emotes: &[EmoteSetEmote], emote_id: EmoteId) -> Result<(), ApiError> {
+	match emotes.iter().find(|e| e.id == emote_id) {
+		Some(existing) => Err(ApiError::conflict(
+			ApiErrorCode::BadRequest,
+			format!("emote {emote_id} is already in this emote set as {}", existing.alias),
+		)),
+		None => Ok(()),
+	}
+}
+
+/// Picks the emotes of a set which are copied into a new set of the user.
+/// Emotes which no longer exist, are private to someone else or are unlisted
+/// when the user can not see unlisted emotes are skipped, as are the emotes
+/// beyond the capacity of the new set.
+///
+/// Returns the copied emotes and the number of skipped emotes.
+pub fn copy_emotes(
+	source: &[EmoteSetEmote],
+	emotes: &HashMap<EmoteId, Emote>,
+	user_id: UserId,
+	view_unlisted: bool,
+	capacity: usize,
+) -> (Vec<EmoteSetEmote>, usize) {
+	let now = chrono::Utc::now();
+
+	let copied: Vec<_> = source
+		.iter()
+		.filter(|e| {
+			emotes.get(&e.id).is_some_and(|emote| {
+				!emote.deleted
+					&& emote.merged.is_none()
+					&& (!emote.flags.contains(EmoteFlags::Private) || emote.owner_id == user_id)
+					&& (view_unlisted || emote.flags.contains(EmoteFlags::PublicListed) || emote.owner_id == user_id)
+			})
+		})
+		.take(capacity)
+		.map(|e| EmoteSetEmote {
+			id: e.id,
+			alias: e.alias.clone(),
+			added_at: now,
+			flags: e.flags,
+			added_by_id: Some(user_id),
+			origin_set_id: None,
+		})
+		.collect();
+
+	let skipped = source.len() - copied.len();
+
+	(copied, skipped)
+}
+
+/// The maximum number of emotes the emote set can hold, the lower of the
+/// capacity of the set and the capacity the owner is allowed to have.
+async fn max_capacity(global: &Arc<Global>, emote_set: &EmoteSet) -> Result<Option<i32>, ApiError> {
+	let Some(owner_id) = emote_set.owner_id else {
+		return Ok(emote_set.capacity);
+	};
+
+	let owner = global
+		.user_loader
+		.load_fast(global, owner_id)
+		.await
+		.map_err(|()| ApiError::internal_server_error(ApiErrorCode::LoadError, "failed to load emote set owner"))?;
+
+	let owner_capacity = owner.map(|owner| effective_emote_set_capacity(&owner.computed.permissions, &emote_set.kind));
+
+	Ok(match (emote_set.capacity, owner_capacity) {
+		(Some(capacity), Some(owner_capacity)) => Some(capacity.min(owner_capacity)),
+		(capacity, owner_capacity) => capacity.or(owner_capacity),
+	})
+}
+
+/// Checks that an emote set is within its capacity after an emote was added.
+/// This must be called inside the transaction which added the emote with the
+/// updated emote set, so that concurrent adds can not both pass the check.
+pub async fn check_capacity(global: &Arc<Global>, emote_set: &EmoteSet) -> Result<(), ApiError> {
+	let Some(capacity) = max_capacity(global, emote_set).await? else {
+		return Ok(());
+	};
+
+	// Unfortunately we actually need to load all these emotes to check the deleted
+	// status to determine if they contribute towards the capacity limit
+	// Perhaps we could cache this in redis or something (the merge/deleted status
+	// of an emote at any given time to avoid doing a DB lookup)
+	let emotes = global
+		.emote_by_id_loader
+		.load_many_merged(emote_set.emotes.iter().map(|e| e.id))
+		.await
+		.map_err(|()| ApiError::internal_server_error(ApiErrorCode::LoadError, "failed to load emotes"))?;
+
+	let active_emotes = emote_set.emotes.iter().filter(|e| emotes.get(e.id).is_some()).count();
+
+	if active_emotes as i32 > capacity {
+		return Err(ApiError::bad_request(
+			ApiErrorCode::BadRequest,
+			format!("emote set is at capacity ({}/{capacity})", active_emotes.saturating_sub(1)),
+		));
+	}
+
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use shared::database::emote_set::EmoteSetEmoteFlag;
+
+	use super::*;
+	use crate::http::fixtures::emote as db_emote;
+
+	fn emote(alias: &str) -> EmoteSetEmote {
+		EmoteSetEmote {
+			id: EmoteId::new(),
+			alias: alias.to_string(),
+			added_at: chrono::Utc::now(),
+			flags: EmoteSetEmoteFlag::default(),
+			added_by_id: None,
+			origin_set_id: None,
+		}
+	}
+
+	#[test]
+	fn test_alias_conflict_case_insensitive() {
+		let emotes = vec![emote("Pog"), emote("KEKW")];
+
+		assert_eq!(find_alias_conflict(&emotes, "pog", None), Some(0));
+		assert_eq!(find_alias_conflict(&emotes, "kekw", None), Some(1));
+		assert_eq!(find_alias_conflict(&emotes, "OMEGALUL", None), None);
+	}
+
+	#[test]
+	fn test_rename_into_collision() {
+		let emotes = vec![emote("Pog"), emote("KEKW")];
+
+		// renaming KEKW to POG collides with Pog
+		assert_eq!(find_alias_conflict(&emotes, "POG", Some(1)), Some(0));
+	}
+
+	#[test]
+	fn test_rename_own_alias_casing() {
+		let emotes = vec![emote("Pog"), emote("KEKW")];
+
+		assert_eq!(find_alias_conflict(&emotes, "kekw", Some(1)), None);
+	}
+
+	#[test]
+	fn test_check_name() {
+		assert!(check_name("My Emotes").is_ok());
+		assert!(check_name("").is_err());
+		assert!(check_name("   ").is_err());
+		assert!(check_name(&"a".repeat(MAX_NAME_LENGTH + 1)).is_err());
+		assert!(check_name("My\u{7}Emotes").is_err());
+	}
+
+	#[test]
+	fn test_check_tags() {
+		assert!(check_tags(&["pog".to_string(), "funny".to_string()]).is_ok());
+		assert!(check_tags(&vec!["pog".to_string(); MAX_TAGS + 1]).is_err());
+		assert!(check_tags(&["ab".to_string()]).is_err());
+		assert!(check_tags(&["a".repeat(31)]).is_err());
+		assert!(check_tags(&["po\ng".to_string()]).is_err());
+	}
+
+	#[test]
+	fn test_duplicate_emote() {
+		let emotes = vec![emote("Pog")];
+
+		assert!(check_duplicate_emote(&emotes, emotes[0].id).is_err());
+		assert!(check_duplicate_emote(&emotes, EmoteId::new()).is_ok());
+	}
+
+	#[test]
+	fn test_copy_emotes() {
+		let user_id = UserId::new();
+		let other_id = UserId::new();
+
+		let source = vec![
+			emote("Listed"),
+			emote("Unlisted"),
+			emote("OwnUnlisted"),
+			emote("Private"),
+			emote("Missing"),
+		];
+
+		let emotes = HashMap::from_iter([
+			(source[0].id, db_emote(source[0].id, other_id, EmoteFlags::PublicListed)),
+			(source[1].id, db_emote(source[1].id, other_id, EmoteFlags::none())),
+			(source[2].id, db_emote(source[2].id, user_id, EmoteFlags::none())),
+			(
+				source[3].id,
+				db_emote(source[3].id, other_id, EmoteFlags::PublicListed | EmoteFlags::Private),
+			),
+		]);
+
+		let (copied, skipped) = copy_emotes(&source, &emotes, user_id, false, 10);
+		assert_eq!(
+			copied.iter().map(|e| e.alias.as_str()).collect::<Vec<_>>(),
+			["Listed", "OwnUnlisted"]
+		);
+		assert!(copied.iter().all(|e| e.added_by_id == Some(user_id)));
+		assert_eq!(skipped, 3);
+
+		let (copied, skipped) = copy_emotes(&source, &emotes, user_id, true, 10);
+		assert_eq!(copied.len(), 3);
+		assert_eq!(skipped, 2);
+
+		// emotes beyond the capacity are skipped
+		let (copied, skipped) = copy_emotes(&source, &emotes, user_id, true, 1);
+		assert_eq!(copied.len(), 1);
+		assert_eq!(skipped, 4);
+	}
+}
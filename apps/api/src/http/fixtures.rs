@@ -0,0 +1,36 @@
+use shared::database::emote::{Emote, EmoteFlags, EmoteId, EmoteScores};
+use shared::database::image_set::{ImageSet, ImageSetInput};
+use shared::database::user::UserId;
+
+/// An image set whose input has not been processed yet.
+pub fn pending_image_set() -> ImageSet {
+	ImageSet {
+		input: ImageSetInput::Pending {
+			task_id: String::new(),
+			path: String::new(),
+			mime: String::new(),
+			size: 0,
+			resubmits: 0,
+		},
+		outputs: vec![],
+	}
+}
+
+pub fn emote(id: EmoteId, owner_id: UserId, flags: EmoteFlags) -> Emote {
+	Emote {
+		id,
+		owner_id,
+		default_name: "Pog".to_string(),
+		tags: vec![],
+		image_set: pending_image_set(),
+		flags,
+		aspect_ratio: 1.0,
+		attribution: vec![],
+		merged: None,
+		scores: EmoteScores::default(),
+		deleted: false,
+		processing_error: None,
+		updated_at: chrono::Utc::now(),
+		search_updated_at: None,
+	}
+}
@@ -6,6 +6,7 @@ use shared::database::role::permissions::{Permission, PermissionsExt, RateLimitR
 use shared::database::user::UserId;
 
 use crate::global::Global;
+use crate::http::editor::ensure_can_manage_profile;
 use crate::http::error::{ApiError, ApiErrorCode};
 use crate::http::middleware::session::Session;
 use crate::ratelimit::{RateLimitRequest, RateLimitResponse};
@@ -75,6 +76,36 @@ impl Guard for UserGuard {
 	}
 }
 
+/// Checks that the authenticated user can act on behalf of the target user,
+/// either because it is the same user, they have `UserPermission::ManageAny` or
+/// they are an accepted editor with the required permission.
+pub struct EditorGuard {
+	target_user_id: UserId,
+}
+
+impl EditorGuard {
+	/// Requires the `ManageProfile` editor permission.
+	pub fn profile(target_user_id: UserId) -> Self {
+		Self { target_user_id }
+	}
+}
+
+impl Guard for EditorGuard {
+	async fn check(&self, ctx: &Context<'_>) -> async_graphql::Result<()> {
+		let global: &Arc<Global> = ctx
+			.data()
+			.map_err(|_| ApiError::internal_server_error(ApiErrorCode::MissingContext, "missing global data"))?;
+		let session = ctx
+			.data::<Session>()
+			.map_err(|_| ApiError::internal_server_error(ApiErrorCode::MissingContext, "missing session data"))?;
+		let authed_user = session.user()?;
+
+		ensure_can_manage_profile(global, authed_user, self.target_user_id, "modify this user's profile").await?;
+
+		Ok(())
+	}
+}
+
 pub struct RateLimitGuard {
 	resource: RateLimitResource,
 	ticket_count: i64,
@@ -0,0 +1,155 @@
+use std::sync::Arc;
+
+use hyper::HeaderMap;
+use shared::database::idempotency_key::{IdempotencyKey, IdempotencyKeyId, IdempotentResult};
+use shared::database::queries::{filter, update};
+use shared::database::user::UserId;
+use shared::database::MongoCollection;
+
+use crate::global::Global;
+use crate::http::error::{ApiError, ApiErrorCode};
+use crate::mutex::{MutexAquireRequest, MutexError};
+use crate::transactions::{TransactionResult, TransactionSession};
+
+pub const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+
+const MAX_IDEMPOTENCY_KEY_LENGTH: usize = 255;
+
+/// Reads the idempotency key of the request, scoped to the given user.
+pub fn idempotency_key(headers: &HeaderMap, user_id: UserId) -> Result<Option<IdempotencyKeyId>, ApiError> {
+	let Some(key) = headers.get(IDEMPOTENCY_KEY_HEADER) else {
+		return Ok(None);
+	};
+
+	let key = key
+		.to_str()
+		.ok()
+		.map(str::trim)
+		.filter(|k| !k.is_empty() && k.len() <= MAX_IDEMPOTENCY_KEY_LENGTH)
+		.ok_or_else(|| ApiError::bad_request(ApiErrorCode::BadRequest, "invalid idempotency key"))?;
+
+	Ok(Some(IdempotencyKeyId {
+		user_id,
+		key: key.to_owned(),
+	}))
+}
+
+/// The error returned when a key is sent again with a different kind of
+/// request than the one it was first used for.
+pub fn key_reused() -> ApiError {
+	ApiError::conflict(
+		ApiErrorCode::BadRequest,
+		"idempotency key was already used for a different request",
+	)
+}
+
+/// Looks up the result of a previous request with the same key. This lets
+/// retries return early before doing any work, the authoritative check is
+/// [`claim`] inside the transaction.
+pub async fn find_result(global: &Arc<Global>, key: &IdempotencyKeyId) -> Result<Option<IdempotentResult>, ApiError> {
+	let key = IdempotencyKey::collection(&global.db)
+		.find_one(filter::filter! {
+			IdempotencyKey {
+				#[query(rename = "_id", serde)]
+				id: key,
+				#[query(selector = "gt")]
+				expires_at: chrono::Utc::now(),
+			}
+		})
+		.await
+		.map_err(|e| {
+			tracing::error!(error = %e, "failed to load idempotency key");
+			ApiError::internal_server_error(ApiErrorCode::LoadError, "failed to load idempotency key")
+		})?;
+
+	Ok(key.map(|k| k.result))
+}
+
+/// Holds a lock on the key while `f` runs, so the work of a request, such as
+/// starting an image processor job, is never done twice for the same key. A
+/// request sent while another one with the same key is in flight is rejected,
+/// `f` is given the result of a request which completed before the lock was
+/// acquired.
+pub async fn lock<T, F, Fut>(global: &Arc<Global>, key: Option<&IdempotencyKeyId>, f: F) -> Result<T, ApiError>
+where
+	F: FnOnce(Option<IdempotentResult>) -> Fut,
+	Fut: std::future::Future<Output = Result<T, ApiError>>,
+{
+	let Some(key) = key else {
+		return f(None).await;
+	};
+
+	let req = MutexAquireRequest {
+		key: format!("mutex:idempotency:{}:{}", key.user_id, key.key),
+		attempts: 1,
+		delay: std::time::Duration::ZERO,
+	};
+
+	match global
+		.mutex
+		.acquire(req, || async { f(find_result(global, key).await?).await })
+		.await
+	{
+		Ok(res) => res,
+		Err(MutexError::Acquire(_)) => Err(ApiError::conflict(
+			ApiErrorCode::BadRequest,
+			"a request with this idempotency key is already in progress",
+		)),
+		Err(e) => {
+			tracing::error!(error = %e, "failed to lock idempotency key");
+			Err(ApiError::internal_server_error(
+				ApiErrorCode::TransactionError,
+				"failed to lock idempotency key",
+			))
+		}
+	}
+}
+
+/// Stores the result for the key unless a request with the same key already
+/// completed, in which case its result is returned and the transaction should
+/// not make any changes.
+pub async fn claim(
+	tx: &mut TransactionSession<'_, ApiError>,
+	key: &IdempotencyKeyId,
+	result: IdempotentResult,
+) -> TransactionResult<Option<IdempotentResult>, ApiError> {
+	let now = chrono::Utc::now();
+
+	let existing = tx
+		.find_one(
+			filter::filter! {
+				IdempotencyKey {
+					#[query(rename = "_id", serde)]
+					id: key,
+				}
+			},
+			None,
+		)
+		.await?;
+
+	if let Some(existing) = existing.filter(|k| k.expires_at > now) {
+		return Ok(Some(existing.result));
+	}
+
+	// An expired key might not have been removed yet, so it is overwritten.
+	tx.update_one(
+		filter::filter! {
+			IdempotencyKey {
+				#[query(rename = "_id", serde)]
+				id: key,
+			}
+		},
+		update::update! {
+			#[query(set)]
+			IdempotencyKey {
+				#[query(serde)]
+				result,
+				expires_at: now + chrono::Duration::hours(1),
+			}
+		},
+		mongodb::options::UpdateOptions::builder().upsert(true).build(),
+	)
+	.await?;
+
+	Ok(None)
+}
@@ -175,7 +175,7 @@ pub async fn handle(
 
 	let mutex_key = PaypalMutexKey::from_paypal(&event.resource);
 
-	let res = transaction_with_mutex(global, Some(mutex_key.into()), |mut tx| {
+	let res = transaction_with_mutex(global, "webhooks.paypal", Some(mutex_key.into()), |mut tx| {
 		async move {
 			let res = tx
 				.update_one(
@@ -143,7 +143,7 @@ pub async fn handle(
 
 	let mutex_key = StripeMutexKey::from_stripe(&event.data.object);
 
-	let res = transaction_with_mutex(&global, mutex_key.map(Into::into), |mut tx| {
+	let res = transaction_with_mutex(&global, "webhooks.stripe", mutex_key.map(Into::into), |mut tx| {
 		let global = Arc::clone(&global);
 
 		async move {
@@ -3,7 +3,9 @@ use std::sync::Arc;
 use axum::extract::Request;
 use axum::response::{IntoResponse, Response};
 use futures::future::BoxFuture;
-use hyper::header;
+use hmac::{Hmac, Mac};
+use hyper::{header, HeaderMap};
+use sha2::Sha256;
 use shared::database::queries::filter;
 use shared::database::role::permissions::{
 	FlagPermission, Permission, Permissions, PermissionsExt, RateLimitResource, UserPermission,
@@ -22,6 +24,15 @@ use crate::transactions::{transaction, TransactionResult, TransactionSession};
 
 pub const AUTH_COOKIE: &str = "seventv-auth";
 
+/// Reads the user agent of a request to store it on a new session, truncated
+/// so clients can not store arbitrarily large values.
+pub fn user_agent(headers: &HeaderMap) -> Option<String> {
+	headers
+		.get(header::USER_AGENT)
+		.and_then(|v| v.to_str().ok())
+		.map(|v| v.chars().take(256).collect())
+}
+
 #[derive(Debug, Clone)]
 pub struct Session(Arc<AuthState>, std::net::IpAddr);
 
@@ -58,6 +69,15 @@ impl Session {
 		self.1
 	}
 
+	/// A keyed hash of the client ip, so sessions can be told apart without
+	/// storing the ip itself.
+	pub fn ip_hash(&self, global: &Global) -> String {
+		let mut mac = Hmac::<Sha256>::new_from_slice(global.config.auth.ip_hash_secret.as_bytes())
+			.expect("hmac accepts keys of any size");
+		mac.update(self.ip().to_string().as_bytes());
+		hex::encode(mac.finalize().into_bytes())
+	}
+
 	pub fn user(&self) -> Result<&FullUser, ApiError> {
 		match &*self.0 {
 			AuthState::Authenticated { user, .. } => Ok(user),
@@ -75,7 +95,10 @@ impl Session {
 	}
 
 	pub async fn logout(&self, global: &Arc<Global>) -> TransactionResult<(), ApiError> {
-		transaction(global, |mut tx| async move { self.logout_with_tx(&mut tx).await }).await
+		transaction(global, "session.logout", |mut tx| async move {
+			self.logout_with_tx(&mut tx).await
+		})
+		.await
 	}
 
 	pub async fn logout_with_tx(&self, tx: &mut TransactionSession<'_, ApiError>) -> TransactionResult<(), ApiError> {
@@ -239,8 +262,7 @@ impl<S> SessionMiddlewareService<S> {
 			let default = self
 				.global
 				.user_loader
-				.computed_loader
-				.load(UserId::nil())
+				.load_computed(&self.global, UserId::nil())
 				.await
 				.map_err(|()| {
 					ApiError::internal_server_error(ApiErrorCode::LoadError, "failed to load default permissions")
@@ -3,7 +3,7 @@ use std::time::Duration;
 
 use anyhow::Context as _;
 use axum::extract::{MatchedPath, Request, State};
-use axum::http::HeaderName;
+use axum::http::{HeaderName, HeaderValue};
 use axum::response::Response;
 use axum::routing::get;
 use axum::{Extension, Router};
@@ -24,12 +24,22 @@ use self::error::ApiError;
 use self::middleware::cookies::CookieMiddleware;
 use crate::global::Global;
 
+pub mod connection;
+pub mod editor;
 pub mod egvault;
+pub mod emote;
+pub mod emote_set;
 pub mod error;
 pub mod extract;
+#[cfg(test)]
+pub mod fixtures;
 pub mod guards;
+pub mod idempotency;
 pub mod internal;
 pub mod middleware;
+pub mod persisted_queries;
+pub mod presence;
+pub mod profile_picture;
 pub mod v3;
 pub mod v4;
 pub mod validators;
@@ -44,22 +54,37 @@ const ALLOWED_CORS_HEADERS: &[&str] = &[
 	"x-seventv-platform",
 	"x-seventv-version",
 	"x-ignore-auth-failure",
+	idempotency::IDEMPOTENCY_KEY_HEADER,
 ];
 
-fn cors_layer(global: &Arc<Global>) -> CorsLayer {
+/// All origins which are allowed to make requests with credentials.
+fn allowed_credential_origins(global: &Arc<Global>) -> Vec<url::Url> {
 	let mut allowed_origins = global.config.api.cors_allowed_credential_origins.clone();
 	allowed_origins.push(global.config.api.old_website_origin.clone());
 	allowed_origins.push(global.config.api.website_origin.clone());
 	allowed_origins.push(global.config.api.api_origin.clone());
+	allowed_origins
+}
+
+/// Returns true if the origin header is one of the origins allowed to make
+/// requests with credentials.
+pub fn is_allowed_credential_origin(global: &Arc<Global>, origin: &HeaderValue) -> bool {
+	check_origin(&allowed_credential_origins(global), origin)
+}
+
+fn check_origin(allowed_origins: &[url::Url], origin: &HeaderValue) -> bool {
+	origin
+		.to_str()
+		.ok()
+		.and_then(|o| url::Url::parse(o).ok())
+		.map(|o| allowed_origins.iter().any(|allowed| allowed.origin() == o.origin()))
+		.unwrap_or_default()
+}
+
+fn cors_layer(global: &Arc<Global>) -> CorsLayer {
+	let allowed_origins = allowed_credential_origins(global);
 
-	let allow_credentials = AllowCredentials::predicate(move |origin, _| {
-		origin
-			.to_str()
-			.ok()
-			.and_then(|o| url::Url::parse(o).ok())
-			.map(|o| allowed_origins.iter().any(|allowed| allowed.origin() == o.origin()))
-			.unwrap_or_default()
-	});
+	let allow_credentials = AllowCredentials::predicate(move |origin, _| check_origin(&allowed_origins, origin));
 
 	CorsLayer::new()
 		.allow_origin(AllowOrigin::mirror_request())
@@ -0,0 +1,319 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use anyhow::Context as _;
+use async_graphql::extensions::{Extension, ExtensionContext, ExtensionFactory, NextPrepareRequest};
+use async_graphql::{ErrorExtensionValues, Request, ServerError, ServerResult};
+use sha2::{Digest, Sha256};
+
+use crate::config::PersistedQueriesConfig;
+
+/// Apollo clients retry with the full query when they get this error.
+const PERSISTED_QUERY_NOT_FOUND: &str = "PersistedQueryNotFound";
+const PERSISTED_QUERY_NOT_ALLOWED: &str = "PersistedQueryNotAllowed";
+
+/// The `persistedQuery` request extension sent by clients.
+#[derive(serde::Deserialize)]
+struct PersistedQuery {
+	version: i32,
+	#[serde(rename = "sha256Hash")]
+	sha256_hash: String,
+}
+
+/// Queries by hash, the least recently used queries are dropped once their
+/// total size exceeds the capacity.
+struct Cache {
+	queries: lru::LruCache<String, String>,
+	/// The total size of the cached queries in bytes
+	size: usize,
+	capacity: usize,
+}
+
+impl Cache {
+	fn new(capacity: usize) -> Self {
+		Self {
+			queries: lru::LruCache::unbounded(),
+			size: 0,
+			capacity,
+		}
+	}
+
+	fn get(&mut self, hash: &str) -> Option<String> {
+		self.queries.get(hash).cloned()
+	}
+
+	fn insert(&mut self, hash: String, query: String) {
+		if query.len() > self.capacity {
+			return;
+		}
+
+		self.size += query.len();
+		if let Some(old) = self.queries.put(hash, query) {
+			self.size -= old.len();
+		}
+
+		while self.size > self.capacity {
+			let Some((_, query)) = self.queries.pop_lru() else {
+				break;
+			};
+
+			self.size -= query.len();
+		}
+	}
+}
+
+struct Store {
+	allow_list: HashMap<String, String>,
+	allow_list_only: bool,
+	max_query_size: usize,
+	cache: Option<Mutex<Cache>>,
+}
+
+/// Persisted queries (APQ), lets clients send the sha256 hash of a query
+/// instead of the query itself. Queries are looked up in the allow list
+/// generated when building the clients and in a cache of queries previously
+/// sent in full.
+///
+/// Each schema has its own store, so a query persisted for one schema is never
+/// executed against another.
+#[derive(Clone)]
+pub struct PersistedQueries(Arc<Store>);
+
+fn query_hash(query: &str) -> String {
+	hex::encode(Sha256::digest(query.as_bytes()))
+}
+
+fn error(message: &str, code: &str) -> ServerError {
+	let mut extensions = ErrorExtensionValues::default();
+	extensions.set("code", code);
+
+	let mut error = ServerError::new(message, None);
+	error.extensions = Some(extensions);
+	error
+}
+
+impl PersistedQueries {
+	pub fn new(config: &PersistedQueriesConfig, allow_list: Option<&Path>) -> anyhow::Result<Self> {
+		let allow_list = if let Some(path) = allow_list {
+			let file = std::fs::read(path).with_context(|| format!("reading {}", path.display()))?;
+			serde_json::from_slice(&file).context("parsing persisted query allow list")?
+		} else {
+			HashMap::new()
+		};
+
+		Self::from_allow_list(allow_list, config)
+	}
+
+	fn from_allow_list(allow_list: HashMap<String, String>, config: &PersistedQueriesConfig) -> anyhow::Result<Self> {
+		if let Some(hash) = allow_list
+			.iter()
+			.find_map(|(hash, query)| (*hash != query_hash(query)).then_some(hash))
+		{
+			anyhow::bail!("persisted query {hash} does not match its hash");
+		}
+
+		// Only queries from the allow list can be executed, so there is nothing to
+		// cache.
+		let cache =
+			(config.cache_capacity > 0 && !config.allow_list_only).then(|| Mutex::new(Cache::new(config.cache_capacity)));
+
+		Ok(Self(Arc::new(Store {
+			allow_list,
+			allow_list_only: config.allow_list_only,
+			max_query_size: config.max_query_size,
+			cache,
+		})))
+	}
+
+	fn get(&self, hash: &str) -> Option<String> {
+		if let Some(query) = self.0.allow_list.get(hash) {
+			return Some(query.clone());
+		}
+
+		self.0.cache.as_ref()?.lock().unwrap().get(hash)
+	}
+
+	/// Replaces the query of a request which only sent the hash with the stored
+	/// query and caches queries sent together with their hash.
+	fn resolve(&self, mut request: Request) -> ServerResult<Request> {
+		let persisted_query = request
+			.extensions
+			.remove("persistedQuery")
+			.map(async_graphql::from_value::<PersistedQuery>)
+			.transpose()
+			.map_err(|_| ServerError::new("invalid persistedQuery extension", None))?;
+
+		if let Some(persisted_query) = &persisted_query {
+			if persisted_query.version != 1 {
+				return Err(ServerError::new("unsupported persistedQuery version", None));
+			}
+
+			if request.query.is_empty() {
+				request.query = self
+					.get(&persisted_query.sha256_hash)
+					.ok_or_else(|| error(PERSISTED_QUERY_NOT_FOUND, "PERSISTED_QUERY_NOT_FOUND"))?;
+				return Ok(request);
+			}
+		}
+
+		let hash = query_hash(&request.query);
+
+		if self.0.allow_list.contains_key(&hash) {
+			return Ok(request);
+		}
+
+		if self.0.allow_list_only {
+			return Err(error(PERSISTED_QUERY_NOT_ALLOWED, "PERSISTED_QUERY_NOT_ALLOWED"));
+		}
+
+		if request.query.len() > self.0.max_query_size {
+			return Err(ServerError::new("query is too large", None));
+		}
+
+		if let Some(persisted_query) = persisted_query {
+			if persisted_query.sha256_hash != hash {
+				return Err(ServerError::new("provided sha256Hash does not match the query", None));
+			}
+
+			if let Some(cache) = &self.0.cache {
+				cache.lock().unwrap().insert(hash, request.query.clone());
+			}
+		}
+
+		Ok(request)
+	}
+}
+
+impl ExtensionFactory for PersistedQueries {
+	fn create(&self) -> Arc<dyn Extension> {
+		Arc::new(self.clone())
+	}
+}
+
+#[async_trait::async_trait]
+impl Extension for PersistedQueries {
+	async fn prepare_request(
+		&self,
+		ctx: &ExtensionContext<'_>,
+		request: Request,
+		next: NextPrepareRequest<'_>,
+	) -> ServerResult<Request> {
+		let request = self.resolve(request)?;
+		next.run(ctx, request).await
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const QUERY: &str = "{ __typename }";
+
+	fn config(allow_list_only: bool, cache_capacity: usize) -> PersistedQueriesConfig {
+		PersistedQueriesConfig {
+			allow_list_only,
+			cache_capacity,
+			..Default::default()
+		}
+	}
+
+	fn request(query: &str, hash: &str) -> Request {
+		let mut request = Request::new(query);
+		request.extensions.insert(
+			"persistedQuery".to_string(),
+			async_graphql::value!({ "version": 1, "sha256Hash": hash }),
+		);
+		request
+	}
+
+	#[test]
+	fn test_cache() {
+		let persisted = PersistedQueries::from_allow_list(HashMap::new(), &config(false, 1024)).unwrap();
+		let hash = query_hash(QUERY);
+
+		let err = persisted.resolve(request("", &hash)).unwrap_err();
+		assert_eq!(err.message, PERSISTED_QUERY_NOT_FOUND);
+
+		assert!(persisted.resolve(request(QUERY, "invalid")).is_err());
+		assert_eq!(persisted.resolve(request(QUERY, &hash)).unwrap().query, QUERY);
+		assert_eq!(persisted.resolve(request("", &hash)).unwrap().query, QUERY);
+	}
+
+	#[test]
+	fn test_allow_list_only() {
+		let hash = query_hash(QUERY);
+		let persisted =
+			PersistedQueries::from_allow_list([(hash.clone(), QUERY.to_string())].into(), &config(true, 1024)).unwrap();
+
+		assert_eq!(persisted.resolve(request("", &hash)).unwrap().query, QUERY);
+		assert_eq!(persisted.resolve(Request::new(QUERY)).unwrap().query, QUERY);
+
+		let err = persisted.resolve(Request::new("{ other }")).unwrap_err();
+		assert_eq!(err.message, PERSISTED_QUERY_NOT_ALLOWED);
+	}
+
+	#[test]
+	fn test_stores_are_separate() {
+		let v3 = PersistedQueries::from_allow_list(HashMap::new(), &config(false, 1024)).unwrap();
+		let v4 = PersistedQueries::from_allow_list(HashMap::new(), &config(false, 1024)).unwrap();
+		let hash = query_hash(QUERY);
+
+		assert!(v3.resolve(request(QUERY, &hash)).is_ok());
+
+		let err = v4.resolve(request("", &hash)).unwrap_err();
+		assert_eq!(err.message, PERSISTED_QUERY_NOT_FOUND);
+	}
+
+	#[test]
+	fn test_cache_capacity() {
+		let mut cache = Cache::new(10);
+
+		cache.insert("a".to_string(), "a".repeat(4));
+		cache.insert("b".to_string(), "b".repeat(4));
+		assert_eq!(cache.size, 8);
+
+		// replacing a query only counts the new one
+		cache.insert("b".to_string(), "b".repeat(5));
+		assert_eq!(cache.size, 9);
+
+		cache.get("a");
+		cache.insert("c".to_string(), "c".repeat(4));
+		assert_eq!(cache.size, 8);
+		assert!(cache.get("a").is_some());
+		assert!(cache.get("b").is_none());
+		assert!(cache.get("c").is_some());
+
+		// a query larger than the whole cache is never cached
+		cache.insert("d".to_string(), "d".repeat(11));
+		assert!(cache.get("d").is_none());
+		assert_eq!(cache.size, 8);
+	}
+
+	#[test]
+	fn test_max_query_size() {
+		let persisted = PersistedQueries::from_allow_list(
+			HashMap::new(),
+			&PersistedQueriesConfig {
+				max_query_size: QUERY.len(),
+				..Default::default()
+			},
+		)
+		.unwrap();
+
+		assert!(persisted.resolve(Request::new(QUERY)).is_ok());
+
+		let query = "{ __typename  }";
+		let err = persisted.resolve(request(query, &query_hash(query))).unwrap_err();
+		assert_eq!(err.message, "query is too large");
+	}
+
+	#[test]
+	fn test_invalid_allow_list() {
+		assert!(PersistedQueries::from_allow_list(
+			[("invalid".to_string(), QUERY.to_string())].into(),
+			&config(false, 1024)
+		)
+		.is_err());
+	}
+}
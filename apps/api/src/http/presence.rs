@@ -0,0 +1,258 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use fred::prelude::KeysInterface;
+use shared::database::emote::EmoteFlags;
+use shared::database::role::permissions::{PermissionsExt, UserPermission};
+use shared::database::user::{FullUser, UserId};
+use shared::event::{
+	EventUserPresencePlatform, InternalEvent, InternalEventData, InternalEventPayload, InternalEventUserPresenceData,
+	InternalEventUserPresenceDataEmoteSet,
+};
+
+use crate::dataloader::emote::EmoteByIdLoaderExt;
+use crate::global::Global;
+use crate::http::error::{ApiError, ApiErrorCode};
+
+/// Identical presences of a user within this many seconds are only published
+/// once.
+const PRESENCE_DEDUP_SECONDS: i64 = 30;
+
+/// Checks the id of the channel a presence is written for.
+pub fn check_platform_id(id: &str) -> Result<(), ApiError> {
+	if id.is_empty() {
+		Err(ApiError::bad_request(ApiErrorCode::BadRequest, "missing platform id"))
+	} else if id.len() > 100 || !id.is_ascii() {
+		Err(ApiError::bad_request(ApiErrorCode::BadRequest, "platform id is invalid"))
+	} else {
+		Ok(())
+	}
+}
+
+fn dedup_key(user_id: UserId, platform: &EventUserPresencePlatform) -> String {
+	match platform {
+		EventUserPresencePlatform::Twitch(id) => format!("user_presence:{user_id}:twitch:{id}"),
+		EventUserPresencePlatform::Kick(id) => format!("user_presence:{user_id}:kick:{id}"),
+		EventUserPresencePlatform::Youtube(id) => format!("user_presence:{user_id}:youtube:{id}"),
+	}
+}
+
+/// Marks the presence as seen, returns false if the same presence was already
+/// written within the dedup window.
+async fn claim_presence(global: &Arc<Global>, key: &str) -> Result<bool, ApiError> {
+	let set: Option<String> = global
+		.redis
+		.set(
+			key,
+			1,
+			Some(fred::types::Expiration::EX(PRESENCE_DEDUP_SECONDS)),
+			Some(fred::types::SetOptions::NX),
+			false,
+		)
+		.await
+		.map_err(|err| {
+			tracing::error!(error = %err, "failed to dedup user presence");
+			ApiError::internal_server_error(ApiErrorCode::Unknown, "failed to dedup user presence")
+		})?;
+
+	Ok(set.is_some())
+}
+
+/// Removes the dedup key of a presence which was not published, so the next
+/// presence is not skipped.
+async fn release_presence(global: &Arc<Global>, key: &str) {
+	if let Err(err) = global.redis.del::<(), _>(key).await {
+		tracing::error!(error = %err, "failed to release user presence");
+	}
+}
+
+/// Publishes a presence event for the user, which the event api uses to send
+/// the user's cosmetics and personal emotes to the channel.
+pub async fn write_presence(
+	global: &Arc<Global>,
+	user_id: UserId,
+	platform: EventUserPresencePlatform,
+) -> Result<(), ApiError> {
+	let user = load_user(global, user_id).await?;
+	publish_presence(global, user, platform).await
+}
+
+/// Like [`write_presence`], but returns false without publishing if the same
+/// presence was already written recently.
+pub async fn write_presence_dedup(
+	global: &Arc<Global>,
+	user_id: UserId,
+	platform: EventUserPresencePlatform,
+) -> Result<bool, ApiError> {
+	let user = load_user(global, user_id).await?;
+
+	let key = dedup_key(user_id, &platform);
+
+	if !claim_presence(global, &key).await? {
+		return Ok(false);
+	}
+
+	if let Err(err) = publish_presence(global, user, platform).await {
+		release_presence(global, &key).await;
+		return Err(err);
+	}
+
+	Ok(true)
+}
+
+async fn load_user(global: &Arc<Global>, user_id: UserId) -> Result<FullUser, ApiError> {
+	global
+		.user_loader
+		.load(global, user_id)
+		.await
+		.map_err(|()| ApiError::internal_server_error(ApiErrorCode::LoadError, "failed to load user"))?
+		.ok_or_else(|| ApiError::not_found(ApiErrorCode::LoadError, "user not found"))
+}
+
+async fn publish_presence(
+	global: &Arc<Global>,
+	user: FullUser,
+	platform: EventUserPresencePlatform,
+) -> Result<(), ApiError> {
+	let active_badge = if let Some(id) = user
+		.style
+		.active_badge_id
+		.and_then(|id| user.computed.entitlements.badges.contains(&id).then_some(id))
+	{
+		global
+			.badge_by_id_loader
+			.load(id)
+			.await
+			.map_err(|()| ApiError::internal_server_error(ApiErrorCode::LoadError, "failed to load badge"))?
+	} else {
+		None
+	};
+
+	let active_paint = if let Some(id) = user
+		.style
+		.active_paint_id
+		.and_then(|id| user.computed.entitlements.paints.contains(&id).then_some(id))
+	{
+		global
+			.paint_by_id_loader
+			.load(id)
+			.await
+			.map_err(|()| ApiError::internal_server_error(ApiErrorCode::LoadError, "failed to load paint"))?
+	} else {
+		None
+	};
+
+	let personal_emote_sets = global
+		.emote_set_by_id_loader
+		.load_many(
+			user.computed.entitlements.emote_sets.iter().copied().chain(
+				user.style
+					.personal_emote_set_id
+					.and_then(|id| user.has(UserPermission::UsePersonalEmoteSet).then_some(id)),
+			),
+		)
+		.await
+		.map_err(|()| ApiError::internal_server_error(ApiErrorCode::LoadError, "failed to load emote sets"))?
+		.into_values()
+		.collect::<Vec<_>>();
+
+	let emotes = global
+		.emote_by_id_loader
+		.load_many_merged(
+			personal_emote_sets
+				.iter()
+				.flat_map(|s| s.emotes.iter().map(|e| e.id))
+				.collect::<Vec<_>>(),
+		)
+		.await
+		.map_err(|()| ApiError::internal_server_error(ApiErrorCode::LoadError, "failed to load emotes"))?;
+
+	let owners = global
+		.user_loader
+		.load_fast_many(
+			global,
+			emotes
+				.emotes
+				.values()
+				.filter(|e| e.flags.contains(EmoteFlags::ApprovedPersonal))
+				.map(|e| e.owner_id),
+		)
+		.await
+		.map_err(|()| ApiError::internal_server_error(ApiErrorCode::LoadError, "failed to load emotes"))?;
+
+	let mut sets = vec![];
+
+	for mut set in personal_emote_sets {
+		let mut set_emotes = Vec::new();
+		let mut emote_owners = HashMap::new();
+
+		set.emotes.retain(|e| emotes.get(e.id).is_some());
+
+		for emote in set.emotes.iter().filter_map(|e| emotes.get(e.id)) {
+			if emote.flags.contains(EmoteFlags::ApprovedPersonal) {
+				set_emotes.push(emote.clone());
+
+				if let Some(owner) = owners.get(&emote.owner_id) {
+					emote_owners.insert(owner.id, owner.clone());
+				}
+			}
+		}
+
+		sets.push(InternalEventUserPresenceDataEmoteSet {
+			emote_set: set,
+			emotes: set_emotes,
+			emote_owners,
+		});
+	}
+
+	let payload = rmp_serde::to_vec_named(&InternalEventPayload::new(Some(InternalEvent {
+		actor: None,
+		session_id: None,
+		data: InternalEventData::UserPresence(Box::new(InternalEventUserPresenceData {
+			user,
+			platform,
+			active_badge,
+			active_paint,
+			personal_emote_sets: sets,
+		})),
+		timestamp: chrono::Utc::now(),
+	})))
+	.map_err(|err| {
+		tracing::error!(error = %err, "failed to serialize event");
+		ApiError::internal_server_error(ApiErrorCode::Unknown, "failed to serialize event")
+	})?;
+
+	global.nats.publish("api.v4.events", payload.into()).await.map_err(|err| {
+		tracing::error!(error = %err, "failed to publish event");
+		ApiError::internal_server_error(ApiErrorCode::Unknown, "failed to publish event")
+	})?;
+
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_check_platform_id() {
+		assert!(check_platform_id("12345").is_ok());
+		assert!(check_platform_id("").is_err());
+		assert!(check_platform_id(&"1".repeat(101)).is_err());
+		assert!(check_platform_id("ünicode").is_err());
+	}
+
+	#[test]
+	fn test_dedup_key_per_platform() {
+		let user_id = UserId::new();
+
+		assert_ne!(
+			dedup_key(user_id, &EventUserPresencePlatform::Twitch(1)),
+			dedup_key(user_id, &EventUserPresencePlatform::Kick(1)),
+		);
+		assert_eq!(
+			dedup_key(user_id, &EventUserPresencePlatform::Youtube("a".to_string())),
+			dedup_key(user_id, &EventUserPresencePlatform::Youtube("a".to_string())),
+		);
+	}
+}
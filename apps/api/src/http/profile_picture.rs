@@ -0,0 +1,152 @@
+use std::fmt::Debug;
+
+use shared::cdn::PurgeRequest;
+use shared::database::queries::filter;
+use shared::database::stored_event::StoredEventUserProfilePictureData;
+use shared::database::user::profile_picture::{UserProfilePicture, UserProfilePictureId};
+use shared::database::user::session::UserSessionId;
+use shared::database::user::{FullUser, UserId};
+use shared::event::{InternalEvent, InternalEventData};
+
+use crate::transactions::{TransactionResult, TransactionSession};
+
+/// Returns the profile pictures exceeding the limit of `max` pictures per
+/// user, newer pictures are kept first. Pictures in `keep`, such as the active
+/// and pending picture, are never returned but count towards the limit. A
+/// limit of 0 disables the cleanup.
+pub fn evicted_profile_pictures(
+	mut pictures: Vec<UserProfilePicture>,
+	keep: &[UserProfilePictureId],
+	max: usize,
+) -> Vec<UserProfilePicture> {
+	if max == 0 {
+		return Vec::new();
+	}
+
+	// ids are time based, so this orders the pictures from newest to oldest
+	pictures.sort_by_key(|p| std::cmp::Reverse(p.id));
+
+	let mut kept = pictures.iter().filter(|p| keep.contains(&p.id)).count();
+
+	pictures
+		.into_iter()
+		.filter(|p| {
+			if keep.contains(&p.id) {
+				false
+			} else if kept < max {
+				kept += 1;
+				false
+			} else {
+				true
+			}
+		})
+		.collect()
+}
+
+/// Deletes the profile pictures and returns the purge request for their
+/// files, which has to be sent once the transaction is committed. Only the CDN
+/// cache is purged, the files stay in storage.
+pub async fn delete_profile_pictures<E: Debug>(
+	tx: &mut TransactionSession<'_, E>,
+	pictures: Vec<UserProfilePicture>,
+	actor: Option<&FullUser>,
+	session_id: Option<UserSessionId>,
+) -> TransactionResult<PurgeRequest, E> {
+	let mut purge = PurgeRequest {
+		files: Vec::new(),
+		prefixes: Vec::new(),
+	};
+
+	if pictures.is_empty() {
+		return Ok(purge);
+	}
+
+	let ids = pictures.iter().map(|p| p.id).collect::<Vec<_>>();
+
+	tx.delete(
+		filter::filter! {
+			UserProfilePicture {
+				#[query(rename = "_id", selector = "in")]
+				id: &ids,
+			}
+		},
+		None,
+	)
+	.await?;
+
+	for picture in pictures {
+		purge
+			.files
+			.extend(picture.image_set.outputs.iter().filter_map(|i| i.path.parse().ok()));
+
+		tx.register_event(InternalEvent {
+			actor: actor.cloned(),
+			session_id,
+			data: InternalEventData::UserProfilePicture {
+				after: picture,
+				data: StoredEventUserProfilePictureData::Delete,
+			},
+			timestamp: chrono::Utc::now(),
+		})?;
+	}
+
+	Ok(purge)
+}
+
+/// Deletes the oldest profile pictures of the user exceeding the limit of
+/// `max` pictures, see [`evicted_profile_pictures`].
+pub async fn evict_profile_pictures<E: Debug>(
+	tx: &mut TransactionSession<'_, E>,
+	user_id: UserId,
+	keep: &[UserProfilePictureId],
+	max: usize,
+) -> TransactionResult<PurgeRequest, E> {
+	let pictures = if max == 0 {
+		Vec::new()
+	} else {
+		tx.find(
+			filter::filter! {
+				UserProfilePicture {
+					user_id: user_id,
+				}
+			},
+			None,
+		)
+		.await?
+	};
+
+	delete_profile_pictures(tx, evicted_profile_pictures(pictures, keep, max), None, None).await
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::http::fixtures;
+
+	fn picture(user_id: UserId) -> UserProfilePicture {
+		UserProfilePicture {
+			id: UserProfilePictureId::new(),
+			user_id,
+			image_set: fixtures::pending_image_set(),
+			updated_at: chrono::Utc::now(),
+		}
+	}
+
+	#[test]
+	fn test_evicted_profile_pictures() {
+		let user_id = UserId::new();
+
+		let mut pictures = (0..5).map(|_| picture(user_id)).collect::<Vec<_>>();
+		pictures.sort_by_key(|p| p.id);
+		let ids = pictures.iter().map(|p| p.id).collect::<Vec<_>>();
+
+		// the oldest picture is active, so only the newest other picture is kept
+		let evicted = evicted_profile_pictures(pictures.clone(), &[ids[0]], 2);
+		let mut evicted = evicted.iter().map(|p| p.id).collect::<Vec<_>>();
+		evicted.sort();
+		assert_eq!(evicted, [ids[1], ids[2], ids[3]]);
+
+		assert!(evicted_profile_pictures(pictures.clone(), &[], 5).is_empty());
+		assert!(evicted_profile_pictures(pictures, &[], 0).is_empty());
+	}
+}
@@ -0,0 +1,56 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use async_graphql::dataloader::{DataLoader, Loader};
+use shared::database::badge::{Badge, BadgeId};
+use shared::database::paint::{Paint, PaintId};
+use shared::database::user::{FullUser, UserId};
+
+use crate::global::Global;
+
+/// Coalesces the paint and badge loads of every object resolved in a query
+/// into single batched loads.
+pub struct CosmeticLoader(Arc<Global>);
+
+impl CosmeticLoader {
+	pub fn new(global: Arc<Global>) -> DataLoader<Self> {
+		DataLoader::new(Self(global), tokio::spawn)
+	}
+}
+
+impl Loader<PaintId> for CosmeticLoader {
+	type Error = ();
+	type Value = Paint;
+
+	async fn load(&self, keys: &[PaintId]) -> Result<HashMap<PaintId, Self::Value>, Self::Error> {
+		self.0.paint_by_id_loader.load_many(keys.iter().copied()).await
+	}
+}
+
+impl Loader<BadgeId> for CosmeticLoader {
+	type Error = ();
+	type Value = Badge;
+
+	async fn load(&self, keys: &[BadgeId]) -> Result<HashMap<BadgeId, Self::Value>, Self::Error> {
+		self.0.badge_by_id_loader.load_many(keys.iter().copied()).await
+	}
+}
+
+/// Coalesces the user loads of every object resolved in a query, such as the
+/// owners of emote sets, into single batched loads.
+pub struct UserLoader(Arc<Global>);
+
+impl UserLoader {
+	pub fn new(global: Arc<Global>) -> DataLoader<Self> {
+		DataLoader::new(Self(global), tokio::spawn)
+	}
+}
+
+impl Loader<UserId> for UserLoader {
+	type Error = ();
+	type Value = FullUser;
+
+	async fn load(&self, keys: &[UserId]) -> Result<HashMap<UserId, Self::Value>, Self::Error> {
+		self.0.user_loader.load_fast_many(&self.0, keys.iter().copied()).await
+	}
+}
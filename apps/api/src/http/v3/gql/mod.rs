@@ -1,42 +1,69 @@
 use std::sync::Arc;
 
-use async_graphql::{extensions, BatchRequest, BatchResponse, EmptySubscription, Schema};
+use async_graphql::http::ALL_WEBSOCKET_PROTOCOLS;
+use async_graphql::{extensions, BatchRequest, BatchResponse, Schema};
 use async_graphql_axum::rejection::GraphQLRejection;
-use axum::extract::FromRequest;
+use async_graphql_axum::{GraphQLProtocol, GraphQLWebSocket};
+use axum::extract::ws::WebSocketUpgrade;
+use axum::extract::{FromRequest, State};
 use axum::response::{self, IntoResponse};
 use axum::routing::{any, get};
 use axum::{Extension, Router};
+use hyper::HeaderMap;
+use itertools::Itertools;
 
+use crate::config::Api;
 use crate::global::Global;
 use crate::http::guards::RateLimitResponseStore;
 use crate::http::middleware::session::Session;
-use crate::http::{ApiError, ApiErrorCode};
+use crate::http::{is_allowed_credential_origin, ApiError, ApiErrorCode};
 
+mod loader;
 mod metrics;
 mod mutations;
 mod queries;
+mod subscriptions;
 mod types;
+mod websocket;
 
 pub fn routes(global: &Arc<Global>) -> Router<Arc<Global>> {
 	Router::new()
 		.route("/", any(graphql_handler))
+		.route("/ws", get(graphql_ws_handler))
 		.route("/playground", get(playground))
 		.layer(Extension(schema(Some(Arc::clone(global)))))
 }
 
-pub type V3Schema = Schema<queries::Query, mutations::Mutation, EmptySubscription>;
+pub type V3Schema = Schema<queries::Query, mutations::Mutation, subscriptions::Subscription>;
 
 pub fn schema(global: Option<Arc<Global>>) -> V3Schema {
-	let mut schema = Schema::build(queries::Query::default(), mutations::Mutation::default(), EmptySubscription)
-		.enable_federation()
-		.enable_subscription_in_federation()
-		.extension(extensions::Analyzer)
-		.extension(extensions::ApolloTracing)
-		.extension(metrics::ErrorMetrics)
-		.limit_complexity(400); // We don't want to allow too complex queries to be executed
+	let max_depth = global
+		.as_ref()
+		.map_or_else(|| Api::default().v3_gql_max_depth, |g| g.config.api.v3_gql_max_depth);
+
+	let mut schema = Schema::build(
+		queries::Query::default(),
+		mutations::Mutation::default(),
+		subscriptions::Subscription::default(),
+	)
+	.enable_federation()
+	.enable_subscription_in_federation()
+	.extension(extensions::Analyzer)
+	.extension(extensions::ApolloTracing)
+	.extension(metrics::ErrorMetrics)
+	.extension(websocket::WebSocketReadOnly)
+	.limit_complexity(400) // We don't want to allow too complex queries to be executed
+	.limit_depth(max_depth);
+
+	if let Some(persisted_queries) = global.as_ref().and_then(|g| g.v3_persisted_queries.clone()) {
+		schema = schema.extension(persisted_queries);
+	}
 
 	if let Some(global) = global {
-		schema = schema.data(global);
+		schema = schema
+			.data(loader::CosmeticLoader::new(global.clone()))
+			.data(loader::UserLoader::new(global.clone()))
+			.data(global);
 	}
 
 	schema.finish()
@@ -48,7 +75,7 @@ pub struct Docs;
 
 #[axum::debug_handler]
 #[utoipa::path(post, path = "/v3/gql", tag = "gql")]
-#[tracing::instrument(skip_all, name = "v3_gql", fields(batch_size))]
+#[tracing::instrument(skip_all, name = "v3_gql", fields(batch_size, user_id, ip, operation_name))]
 pub async fn graphql_handler(
 	Extension(schema): Extension<V3Schema>,
 	Extension(session): Extension<Session>,
@@ -78,7 +105,22 @@ pub async fn graphql_handler(
 	};
 
 	let batch_size = req.0.iter().count();
-	tracing::Span::current().record("batch_size", batch_size);
+
+	// Resolver spans are nested under this span, so recording who made the request
+	// here lets all logs of a request be correlated.
+	let span = tracing::Span::current();
+	span.record("batch_size", batch_size);
+	span.record("ip", tracing::field::display(session.ip()));
+
+	if let Some(user_id) = session.user_id() {
+		span.record("user_id", tracing::field::display(user_id));
+	}
+
+	let operation_names = req.0.iter().filter_map(|r| r.operation_name.as_deref()).join(",");
+
+	if !operation_names.is_empty() {
+		span.record("operation_name", operation_names.as_str());
+	}
 
 	if let BatchRequest::Single(req) = &req.0 {
 		if req.query == "forsen" {
@@ -102,6 +144,33 @@ pub async fn graphql_handler(
 	Ok(async_graphql_axum::GraphQLResponse::from(response).into_response())
 }
 
+#[tracing::instrument(skip_all, name = "v3_gql_ws")]
+pub async fn graphql_ws_handler(
+	State(global): State<Arc<Global>>,
+	Extension(schema): Extension<V3Schema>,
+	Extension(session): Extension<Session>,
+	headers: HeaderMap,
+	protocol: GraphQLProtocol,
+	upgrade: WebSocketUpgrade,
+) -> Result<axum::response::Response, ApiError> {
+	// Browsers send cookies along with websocket upgrades from any site, so
+	// only allowed origins can open a connection.
+	if let Some(origin) = headers.get(hyper::header::ORIGIN) {
+		if !is_allowed_credential_origin(&global, origin) {
+			return Err(ApiError::forbidden(ApiErrorCode::BadRequest, "origin not allowed"));
+		}
+	}
+
+	Ok(upgrade.protocols(ALL_WEBSOCKET_PROTOCOLS).on_upgrade(move |stream| {
+		let mut data = async_graphql::Data::default();
+		data.insert(session);
+		data.insert(RateLimitResponseStore::new());
+		data.insert(websocket::WebSocketConnection);
+
+		GraphQLWebSocket::new(stream, schema, protocol).with_data(data).serve()
+	}))
+}
+
 #[utoipa::path(get, path = "/v3/gql/playground", tag = "gql")]
 pub async fn playground() -> impl IntoResponse {
 	response::Html(
@@ -111,3 +180,34 @@ pub async fn playground() -> impl IntoResponse {
 			.finish(),
 	)
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn introspection_query(of_type_depth: usize) -> String {
+		let of_type = "ofType { ".repeat(of_type_depth);
+		let close = "} ".repeat(of_type_depth);
+		format!("{{ __schema {{ types {{ fields {{ type {{ {of_type}name {close}}} }} }} }} }}")
+	}
+
+	#[tokio::test]
+	async fn rejects_deeply_nested_queries() {
+		let schema = schema(None);
+
+		let response = schema.execute(introspection_query(13)).await;
+
+		assert!(response.data == async_graphql::Value::Null);
+		assert!(response.errors.iter().any(|e| e.message.contains("nested too deep")));
+	}
+
+	#[tokio::test]
+	async fn allows_introspection_queries() {
+		let schema = schema(None);
+
+		// the type reference fragment of the graphiql introspection query
+		let response = schema.execute(introspection_query(9)).await;
+
+		assert!(response.errors.is_empty(), "{:?}", response.errors);
+	}
+}
@@ -86,56 +86,61 @@ impl BansMutation {
 			));
 		}
 
-		let res = transaction_with_mutex(global, Some(GeneralMutexKey::User(victim.id).into()), |mut tx| async move {
-			let ban = UserBan {
-				id: Default::default(),
-				user_id: victim.id,
-				expires_at: expire_at,
-				created_by_id: authed_user.id,
-				reason,
-				tags: vec![],
-				removed: None,
-				permissions: ban_effect_to_permissions(effects),
-				updated_at: chrono::Utc::now(),
-				search_updated_at: None,
-			};
-
-			let res = tx
-				.update_one(
-					filter::filter! {
-						User {
-							#[query(rename = "_id")]
-							id: victim.id,
-						}
-					},
-					update::update! {
-						#[query(set)]
-						User {
-							has_bans: true,
-							updated_at: chrono::Utc::now(),
-							search_updated_at: &None,
-						}
-					},
-					None,
-				)
-				.await?;
-
-			tx.insert_one::<UserBan>(&ban, None).await?;
-
-			if res.modified_count > 0 {
-				tx.register_event(InternalEvent {
-					actor: Some(authed_user.clone()),
-					session_id: session.user_session_id(),
-					data: InternalEventData::UserBan {
-						after: ban.clone(),
-						data: StoredEventUserBanData::Ban,
-					},
-					timestamp: chrono::Utc::now(),
-				})?;
-			}
+		let res = transaction_with_mutex(
+			global,
+			"v3.bans.create",
+			Some(GeneralMutexKey::User(victim.id).into()),
+			|mut tx| async move {
+				let ban = UserBan {
+					id: Default::default(),
+					user_id: victim.id,
+					expires_at: expire_at,
+					created_by_id: authed_user.id,
+					reason,
+					tags: vec![],
+					removed: None,
+					permissions: ban_effect_to_permissions(effects),
+					updated_at: chrono::Utc::now(),
+					search_updated_at: None,
+				};
+
+				let res = tx
+					.update_one(
+						filter::filter! {
+							User {
+								#[query(rename = "_id")]
+								id: victim.id,
+							}
+						},
+						update::update! {
+							#[query(set)]
+							User {
+								has_bans: true,
+								updated_at: chrono::Utc::now(),
+								search_updated_at: &None,
+							}
+						},
+						None,
+					)
+					.await?;
+
+				tx.insert_one::<UserBan>(&ban, None).await?;
+
+				if res.modified_count > 0 {
+					tx.register_event(InternalEvent {
+						actor: Some(authed_user.clone()),
+						session_id: session.user_session_id(),
+						data: InternalEventData::UserBan {
+							after: ban.clone(),
+							data: StoredEventUserBanData::Ban,
+						},
+						timestamp: chrono::Utc::now(),
+					})?;
+				}
 
-			Ok(ban)
-		})
+				Ok(ban)
+			},
+		)
 		.await;
 
 		match res {
@@ -170,96 +175,103 @@ impl BansMutation {
 			.map_err(|_| ApiError::internal_server_error(ApiErrorCode::MissingContext, "missing sesion data"))?;
 		let authed_user = session.user()?;
 
-		let res = transaction_with_mutex(global, Some(GeneralMutexKey::Ban(ban_id.id()).into()), |mut tx| async move {
-			let ban_before = tx
-				.find_one_and_update(
-					filter::filter! {
-						UserBan {
-							#[query(rename = "_id")]
-							id: ban_id.id(),
-						}
-					},
-					update::update! {
-						#[query(set)]
-						UserBan {
-							#[query(optional)]
-							reason: reason.clone(),
-							#[query(optional)]
-							expires_at: expire_at,
-							#[query(optional, serde)]
-							permissions: effects.map(ban_effect_to_permissions),
-							updated_at: chrono::Utc::now(),
-							search_updated_at: &None,
-						}
-					},
-					FindOneAndUpdateOptions::builder()
-						.return_document(ReturnDocument::Before)
-						.build(),
-				)
-				.await?
-				.ok_or_else(|| TransactionError::Custom(ApiError::not_found(ApiErrorCode::LoadError, "ban not found")))?;
-
-			let mut ban_after = ban_before.clone();
-
-			if let Some(reason) = reason.clone() {
-				ban_after.reason = reason;
-			}
-
-			if let Some(effects) = effects {
-				ban_after.permissions = ban_effect_to_permissions(effects);
-			}
-
-			if let Some(expire_at) = expire_at {
-				ban_after.expires_at = Some(expire_at);
-			}
-
-			if reason.is_some() {
-				tx.register_event(InternalEvent {
-					actor: Some(authed_user.clone()),
-					session_id: session.user_session_id(),
-					data: InternalEventData::UserBan {
-						after: ban_after.clone(),
-						data: StoredEventUserBanData::ChangeReason {
-							old: ban_before.reason,
-							new: ban_after.reason.clone(),
+		let res = transaction_with_mutex(
+			global,
+			"v3.bans.edit",
+			Some(GeneralMutexKey::Ban(ban_id.id()).into()),
+			|mut tx| async move {
+				let ban_before = tx
+					.find_one_and_update(
+						filter::filter! {
+							UserBan {
+								#[query(rename = "_id")]
+								id: ban_id.id(),
+							}
 						},
-					},
-					timestamp: chrono::Utc::now(),
-				})?;
-			}
-
-			if effects.is_some() {
-				tx.register_event(InternalEvent {
-					actor: Some(authed_user.clone()),
-					session_id: session.user_session_id(),
-					data: InternalEventData::UserBan {
-						after: ban_after.clone(),
-						data: StoredEventUserBanData::ChangeUserBanPermissions {
-							old: Box::new(ban_before.permissions),
-							new: Box::new(ban_after.permissions.clone()),
+						update::update! {
+							#[query(set)]
+							UserBan {
+								#[query(optional)]
+								reason: reason.clone(),
+								#[query(optional)]
+								expires_at: expire_at,
+								#[query(optional, serde)]
+								permissions: effects.map(ban_effect_to_permissions),
+								updated_at: chrono::Utc::now(),
+								search_updated_at: &None,
+							}
 						},
-					},
-					timestamp: chrono::Utc::now(),
-				})?;
-			}
-
-			if expire_at.is_some() {
-				tx.register_event(InternalEvent {
-					actor: Some(authed_user.clone()),
-					session_id: session.user_session_id(),
-					data: InternalEventData::UserBan {
-						after: ban_after.clone(),
-						data: StoredEventUserBanData::ChangeExpiresAt {
-							old: ban_before.expires_at,
-							new: ban_after.expires_at,
+						FindOneAndUpdateOptions::builder()
+							.return_document(ReturnDocument::Before)
+							.build(),
+					)
+					.await?
+					.ok_or_else(|| {
+						TransactionError::Custom(ApiError::not_found(ApiErrorCode::LoadError, "ban not found"))
+					})?;
+
+				let mut ban_after = ban_before.clone();
+
+				if let Some(reason) = reason.clone() {
+					ban_after.reason = reason;
+				}
+
+				if let Some(effects) = effects {
+					ban_after.permissions = ban_effect_to_permissions(effects);
+				}
+
+				if let Some(expire_at) = expire_at {
+					ban_after.expires_at = Some(expire_at);
+				}
+
+				if reason.is_some() {
+					tx.register_event(InternalEvent {
+						actor: Some(authed_user.clone()),
+						session_id: session.user_session_id(),
+						data: InternalEventData::UserBan {
+							after: ban_after.clone(),
+							data: StoredEventUserBanData::ChangeReason {
+								old: ban_before.reason,
+								new: ban_after.reason.clone(),
+							},
 						},
-					},
-					timestamp: chrono::Utc::now(),
-				})?;
-			}
+						timestamp: chrono::Utc::now(),
+					})?;
+				}
+
+				if effects.is_some() {
+					tx.register_event(InternalEvent {
+						actor: Some(authed_user.clone()),
+						session_id: session.user_session_id(),
+						data: InternalEventData::UserBan {
+							after: ban_after.clone(),
+							data: StoredEventUserBanData::ChangeUserBanPermissions {
+								old: Box::new(ban_before.permissions),
+								new: Box::new(ban_after.permissions.clone()),
+							},
+						},
+						timestamp: chrono::Utc::now(),
+					})?;
+				}
+
+				if expire_at.is_some() {
+					tx.register_event(InternalEvent {
+						actor: Some(authed_user.clone()),
+						session_id: session.user_session_id(),
+						data: InternalEventData::UserBan {
+							after: ban_after.clone(),
+							data: StoredEventUserBanData::ChangeExpiresAt {
+								old: ban_before.expires_at,
+								new: ban_after.expires_at,
+							},
+						},
+						timestamp: chrono::Utc::now(),
+					})?;
+				}
 
-			Ok(ban_after)
-		})
+				Ok(ban_after)
+			},
+		)
 		.await;
 
 		match res {
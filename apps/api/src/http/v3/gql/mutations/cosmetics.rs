@@ -11,6 +11,7 @@ use shared::database::queries::{filter, update};
 use shared::database::role::permissions::PaintPermission;
 use shared::database::stored_event::StoredEventPaintData;
 use shared::event::{InternalEvent, InternalEventData};
+use shared::image_processor::ProcessPriority;
 use shared::old_types::cosmetic::{CosmeticPaintFunction, CosmeticPaintModel, CosmeticPaintShape};
 use shared::old_types::object_id::GqlObjectId;
 
@@ -71,7 +72,7 @@ impl CosmeticsMutation {
 			..Default::default()
 		};
 
-		let res = transaction::<(), (), _, _>(global, |mut tx| async move {
+		let res = transaction::<(), (), _, _>(global, "v3.cosmetics.create_paint", |mut tx| async move {
 			tx.insert_one(paint.clone(), None).await?;
 
 			tx.register_event(InternalEvent {
@@ -166,7 +167,7 @@ impl CosmeticsMutation {
 
 					global
 						.image_processor
-						.reprocess_paint_layer(image, paint.id, layer.id)
+						.reprocess_paint_layer(image, paint.id, layer.id, ProcessPriority::Low)
 						.await
 						.map_err(|e| {
 							tracing::error!(error = ?e, "failed to reprocess paint layer");
@@ -208,10 +209,14 @@ impl CosmeticsMutation {
 				ImageSetInput::Image(image) => image.path.clone(),
 			};
 
-			global.image_processor.reprocess_badge(image, badge.id).await.map_err(|e| {
-				tracing::error!(error = ?e, "failed to reprocess badge");
-				ApiError::internal_server_error(ApiErrorCode::ImageProcessorError, "failed to reprocess badge")
-			})?;
+			global
+				.image_processor
+				.reprocess_badge(image, badge.id, ProcessPriority::Low)
+				.await
+				.map_err(|e| {
+					tracing::error!(error = ?e, "failed to reprocess badge");
+					ApiError::internal_server_error(ApiErrorCode::ImageProcessorError, "failed to reprocess badge")
+				})?;
 
 			badge_responses.push(CosmeticReprocessResult {
 				id: id.into(),
@@ -315,7 +320,7 @@ impl CosmeticPaintInput {
 
 				let input = match global
 					.image_processor
-					.upload_paint_layer(paint_id, layer_id, image_data)
+					.upload_paint_layer(paint_id, layer_id, image_data, ProcessPriority::Normal)
 					.await
 				{
 					Ok(image_processor_proto::ProcessImageResponse { error: Some(error), .. }) => {
@@ -339,6 +344,7 @@ impl CosmeticPaintInput {
 						path: path.path,
 						mime: content_type,
 						size: size as i64,
+						resubmits: 0,
 					},
 					Err(e) => {
 						tracing::error!(error = ?e, "failed to start send image processor request");
@@ -443,6 +449,7 @@ impl CosmeticOps {
 
 		let res = transaction_with_mutex(
 			global,
+			"v3.cosmetics.update_paint",
 			Some(GeneralMutexKey::Paint(self.id.id()).into()),
 			|mut tx| async move {
 				let before_paint = tx
@@ -10,8 +10,8 @@ use shared::database::queries::{filter, update};
 use shared::database::stored_event::StoredEventEmoteModerationRequestData;
 use shared::event::{InternalEvent, InternalEventData, InternalEventEmoteSetData};
 
-use crate::dataloader::emote::EmoteByIdLoaderExt;
 use crate::global::Global;
+use crate::http::emote_set;
 use crate::http::error::{ApiError, ApiErrorCode};
 use crate::http::middleware::session::Session;
 use crate::transactions::{TransactionError, TransactionResult, TransactionSession};
@@ -45,6 +45,8 @@ pub async fn emote_add(
 		)));
 	}
 
+	emote_set::check_duplicate_emote(&emote_set.emotes, emote_id).map_err(TransactionError::Custom)?;
+
 	let alias = name.unwrap_or_else(|| emote.default_name.clone());
 
 	// This may be a problem if the emote has been deleted.
@@ -62,14 +64,15 @@ pub async fn emote_add(
 			))
 		})?;
 
-	let conflict_emote_idx = emote_set.emotes.iter().position(|e| e.alias == alias);
+	let conflict_emote_idx = emote_set::find_alias_conflict(&emote_set.emotes, &alias, None);
 
 	if let Some(conflict_emote_idx) = conflict_emote_idx {
-		if let Some(emote) = emotes.get(&emote_set.emotes[conflict_emote_idx].id) {
+		let conflict = &emote_set.emotes[conflict_emote_idx];
+		if let Some(emote) = emotes.get(&conflict.id) {
 			if !emote.deleted {
 				return Err(TransactionError::Custom(ApiError::conflict(
 					ApiErrorCode::BadRequest,
-					"this emote has a conflicting name",
+					format!("this emote has a conflicting name with {} ({})", conflict.alias, conflict.id),
 				)));
 			}
 		}
@@ -234,31 +237,9 @@ pub async fn emote_add(
 		.await?
 		.ok_or_else(|| TransactionError::Custom(ApiError::not_found(ApiErrorCode::LoadError, "emote set not found")))?;
 
-	if let Some(capacity) = emote_set.capacity {
-		// Unfortunately we actually need to load all these emotes to check the deleted
-		// status to determine if they contribute towards the capacity limit
-		// Perhaps we could cache this in redis or something (the merge/deleted status
-		// of an emote at any given time to avoid doing a DB lookup)
-		let emotes = global
-			.emote_by_id_loader
-			.load_many_merged(emote_set.emotes.iter().map(|e| e.id))
-			.await
-			.map_err(|()| {
-				TransactionError::Custom(ApiError::internal_server_error(
-					ApiErrorCode::LoadError,
-					"failed to load emotes",
-				))
-			})?;
-
-		let active_emotes = emote_set.emotes.iter().filter(|e| emotes.get(e.id).is_some()).count();
-
-		if active_emotes as i32 > capacity {
-			return Err(TransactionError::Custom(ApiError::bad_request(
-				ApiErrorCode::LoadError,
-				"emote set is at capacity",
-			)));
-		}
-	}
+	emote_set::check_capacity(global, &emote_set)
+		.await
+		.map_err(TransactionError::Custom)?;
 
 	let emote_owner = global.user_loader.load_fast(global, emote.owner_id).await.map_err(|_| {
 		TransactionError::Custom(ApiError::internal_server_error(
@@ -7,6 +7,7 @@ use shared::database::queries::{filter, update};
 use shared::event::{InternalEvent, InternalEventData, InternalEventEmoteSetData};
 
 use crate::global::Global;
+use crate::http::emote_set;
 use crate::http::error::{ApiError, ApiErrorCode};
 use crate::http::middleware::session::Session;
 use crate::transactions::{TransactionError, TransactionResult, TransactionSession};
@@ -21,10 +22,11 @@ pub async fn emote_update(
 ) -> TransactionResult<EmoteSet, ApiError> {
 	let authed_user = session.user().map_err(TransactionError::Custom)?;
 
-	let old_emote_set_emote =
-		emote_set.emotes.iter().find(|e| e.id == emote_id).ok_or_else(|| {
+	let old_emote_set_emote_idx =
+		emote_set.emotes.iter().position(|e| e.id == emote_id).ok_or_else(|| {
 			TransactionError::Custom(ApiError::not_found(ApiErrorCode::BadRequest, "emote not found in set"))
 		})?;
+	let old_emote_set_emote = &emote_set.emotes[old_emote_set_emote_idx];
 
 	let emote = tx
 		.find_one(filter::filter! { Emote { #[query(rename = "_id")] id: emote_id } }, None)
@@ -40,10 +42,11 @@ pub async fn emote_update(
 
 	let new_name = name.unwrap_or(emote.default_name.clone());
 
-	if emote_set.emotes.iter().any(|e| e.alias == new_name) {
+	if let Some(conflict_idx) = emote_set::find_alias_conflict(&emote_set.emotes, &new_name, Some(old_emote_set_emote_idx)) {
+		let conflict = &emote_set.emotes[conflict_idx];
 		return Err(TransactionError::Custom(ApiError::conflict(
 			ApiErrorCode::BadRequest,
-			"emote name conflict",
+			format!("emote name conflict with {} ({})", conflict.alias, conflict.id),
 		)));
 	}
 
@@ -9,7 +9,9 @@ use mongodb::bson::doc;
 use mongodb::options::{FindOneAndUpdateOptions, ReturnDocument};
 use shared::database::emote_set::{EmoteSet as DbEmoteSet, EmoteSetKind};
 use shared::database::queries::{filter, update};
-use shared::database::role::permissions::{EmoteSetPermission, PermissionsExt, RateLimitResource, UserPermission};
+use shared::database::role::permissions::{
+	effective_emote_set_capacity, EmoteSetPermission, PermissionsExt, RateLimitResource, UserPermission,
+};
 use shared::database::user::editor::{
 	EditorEmoteSetPermission, EditorPermission, EditorUserPermission, UserEditorId, UserEditorState,
 };
@@ -19,12 +21,13 @@ use shared::old_types::object_id::GqlObjectId;
 
 use crate::dataloader::emote::EmoteByIdLoaderExt;
 use crate::global::Global;
+use crate::http::emote_set;
 use crate::http::error::{ApiError, ApiErrorCode};
 use crate::http::guards::{PermissionGuard, RateLimitGuard};
 use crate::http::middleware::session::Session;
 use crate::http::v3::gql::queries::emote_set::{ActiveEmote, EmoteSet};
 use crate::http::v3::gql::types::ListItemAction;
-use crate::http::validators::{EmoteNameValidator, NameValidator};
+use crate::http::validators::EmoteNameValidator;
 use crate::transactions::{transaction, transaction_with_mutex, GeneralMutexKey, TransactionError};
 
 mod emote_add;
@@ -71,6 +74,8 @@ impl EmoteSetsMutation {
 			));
 		}
 
+		emote_set::check_name(&data.name)?;
+
 		let global: &Arc<Global> = ctx
 			.data()
 			.map_err(|_| ApiError::internal_server_error(ApiErrorCode::MissingContext, "missing global data"))?;
@@ -124,7 +129,7 @@ impl EmoteSetsMutation {
 			}
 		}
 
-		let capacity = target.computed.permissions.emote_set_capacity.unwrap_or_default().max(0);
+		let capacity = effective_emote_set_capacity(&target.computed.permissions, &EmoteSetKind::Normal);
 
 		if capacity == 0 {
 			return Err(ApiError::bad_request(
@@ -133,7 +138,7 @@ impl EmoteSetsMutation {
 			));
 		}
 
-		let res = transaction(global, |mut tx| async move {
+		let res = transaction(global, "v3.emote_sets.create", |mut tx| async move {
 			let emote_set_count = tx
 				.count(
 					filter::filter! {
@@ -200,7 +205,6 @@ impl EmoteSetsMutation {
 #[derive(InputObject)]
 #[graphql(rename_fields = "snake_case")]
 pub struct CreateEmoteSetInput {
-	#[graphql(validator(custom = "NameValidator"))]
 	name: String,
 	privileged: Option<bool>,
 }
@@ -313,7 +317,6 @@ impl EmoteSetOps {
 #[derive(InputObject, Clone)]
 #[graphql(rename_fields = "snake_case")]
 pub struct UpdateEmoteSetInput {
-	#[graphql(validator(custom = "NameValidator"))]
 	name: Option<String>,
 	#[graphql(validator(minimum = 1))]
 	capacity: Option<i32>,
@@ -352,6 +355,7 @@ impl EmoteSetOps {
 
 		let res = transaction_with_mutex(
 			global,
+			"v3.emote_sets.emotes",
 			Some(GeneralMutexKey::EmoteSet(self.id.id()).into()),
 			|mut tx| async move {
 				let emote_set = tx
@@ -417,6 +421,10 @@ impl EmoteSetOps {
 		// linter to think we do not need a mutable `data` variable here.
 		let mut data = data;
 
+		if let Some(name) = &data.name {
+			emote_set::check_name(name)?;
+		}
+
 		let global: &Arc<Global> = ctx
 			.data()
 			.map_err(|_| ApiError::internal_server_error(ApiErrorCode::MissingContext, "missing global data"))?;
@@ -429,6 +437,7 @@ impl EmoteSetOps {
 
 		let res = transaction_with_mutex(
 			global,
+			"v3.emote_sets.update",
 			Some(GeneralMutexKey::EmoteSet(self.id.id()).into()),
 			|mut tx| async move {
 				let new_capacity = if let Some(capacity) = data.capacity {
@@ -439,13 +448,7 @@ impl EmoteSetOps {
 						)));
 					}
 
-					let max_capacity = if self.emote_set.kind == EmoteSetKind::Personal {
-						target.computed.permissions.personal_emote_set_capacity
-					} else {
-						target.computed.permissions.emote_set_capacity
-					};
-
-					if capacity > max_capacity.unwrap_or_default().max(0) {
+					if capacity > effective_emote_set_capacity(&target.computed.permissions, &self.emote_set.kind) {
 						return Err(TransactionError::Custom(ApiError::bad_request(
 							ApiErrorCode::LackingPrivileges,
 							"emote set capacity cannot exceed user's capacity",
@@ -573,6 +576,7 @@ impl EmoteSetOps {
 
 		let res = transaction_with_mutex(
 			global,
+			"v3.emote_sets.delete",
 			Some(GeneralMutexKey::EmoteSet(self.id.id()).into()),
 			|mut tx| async move {
 				let emote_set = tx
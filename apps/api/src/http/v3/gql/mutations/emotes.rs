@@ -4,7 +4,7 @@ use async_graphql::{ComplexObject, Context, InputObject, Object, SimpleObject};
 use chrono::Utc;
 use mongodb::bson::doc;
 use mongodb::options::{FindOneAndUpdateOptions, ReturnDocument};
-use shared::database::emote::{Emote as DbEmote, EmoteFlags, EmoteMerged};
+use shared::database::emote::{Emote as DbEmote, EmoteFlags};
 use shared::database::emote_moderation_request::{
 	EmoteModerationRequest, EmoteModerationRequestKind, EmoteModerationRequestStatus,
 };
@@ -20,6 +20,7 @@ use shared::old_types::EmoteFlagsModel;
 
 use crate::dataloader::emote::EmoteByIdLoaderExt;
 use crate::global::Global;
+use crate::http::emote::{check_delete, merge_emote_locked};
 use crate::http::error::{ApiError, ApiErrorCode};
 use crate::http::guards::{PermissionGuard, RateLimitGuard};
 use crate::http::middleware::session::Session;
@@ -110,8 +111,11 @@ impl EmoteOps {
 
 			let res = transaction_with_mutex(
 				global,
+				"v3.emotes.delete",
 				Some(GeneralMutexKey::Emote(self.id.id()).into()),
 				|mut tx| async move {
+					check_delete(global, &mut tx, authed_user, self.id.id()).await?;
+
 					let emote = tx
 						.find_one_and_update(
 							filter::filter! {
@@ -192,6 +196,7 @@ impl EmoteOps {
 
 		let res = transaction_with_mutex(
 			global,
+			"v3.emotes.update",
 			Some(GeneralMutexKey::Emote(self.id.id()).into()),
 			|mut tx| async move {
 				// only set new default name if it's different from the current one
@@ -443,75 +448,8 @@ impl EmoteOps {
 		let session = ctx
 			.data::<Session>()
 			.map_err(|_| ApiError::internal_server_error(ApiErrorCode::MissingContext, "missing sesion data"))?;
-		let authed_user = session.user()?;
-
-		let res = transaction_with_mutex(
-			global,
-			Some(GeneralMutexKey::Emote(self.id.id()).into()),
-			|mut tx| async move {
-				let emote = tx
-					.find_one_and_update(
-						filter::filter! {
-							DbEmote {
-								#[query(rename = "_id")]
-								id: self.id.id(),
-							}
-						},
-						update::update! {
-							#[query(set)]
-							DbEmote {
-								#[query(serde)]
-								merged: EmoteMerged {
-									target_id: target_id.id(),
-									at: chrono::Utc::now(),
-								},
-								updated_at: chrono::Utc::now(),
-								search_updated_at: &None,
-							}
-						},
-						None,
-					)
-					.await?
-					.ok_or_else(|| ApiError::not_found(ApiErrorCode::LoadError, "emote not found"))
-					.map_err(TransactionError::Custom)?;
 
-				tx.update(
-					filter::filter! {
-						EmoteModerationRequest {
-							emote_id: self.id.id(),
-							#[query(serde)]
-							status: EmoteModerationRequestStatus::Pending,
-						}
-					},
-					update::update! {
-						#[query(set)]
-						EmoteModerationRequest {
-							#[query(serde)]
-							status: EmoteModerationRequestStatus::EmoteDeleted,
-						}
-					},
-					None,
-				)
-				.await?;
-
-				tx.register_event(InternalEvent {
-					actor: Some(authed_user.clone()),
-					session_id: session.user_session_id(),
-					data: InternalEventData::Emote {
-						after: emote.clone(),
-						data: StoredEventEmoteData::Merge {
-							new_emote_id: target_id.id(),
-						},
-					},
-					timestamp: chrono::Utc::now(),
-				})?;
-
-				// TODO: schedule emote merge job
-
-				Ok(emote)
-			},
-		)
-		.await;
+		let res = merge_emote_locked(global, "v3.emotes.merge", session, self.id.id(), target_id.id()).await;
 
 		match res {
 			Ok(emote) => Ok(Emote::from_db(global, emote)),
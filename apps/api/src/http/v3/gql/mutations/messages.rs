@@ -51,7 +51,7 @@ impl MessagesMutation {
 			EmoteModerationRequestStatus::Pending
 		};
 
-		let res = transaction(global, |mut tx| async move {
+		let res = transaction(global, "v3.messages.read", |mut tx| async move {
 			let requests = tx
 				.find(
 					filter::filter! {
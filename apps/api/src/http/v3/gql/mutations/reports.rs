@@ -52,7 +52,7 @@ impl ReportsMutation {
 			.and_then(|c| c.iso_code)
 			.map(|c| c.to_string());
 
-		let res = transaction(global, |mut tx| async move {
+		let res = transaction(global, "v3.reports.create", |mut tx| async move {
 			let ticket_id = TicketId::new();
 
 			let message = TicketMessage {
@@ -151,6 +151,7 @@ impl ReportsMutation {
 
 		let transaction_result = transaction_with_mutex(
 			global,
+			"v3.reports.edit",
 			Some(GeneralMutexKey::Ticket(report_id.id()).into()),
 			|mut tx| async move {
 				let new_open = if let Some(status) = data.status {
@@ -6,6 +6,7 @@ use mongodb::options::FindOptions;
 use shared::database::entitlement::{EntitlementEdge, EntitlementEdgeId, EntitlementEdgeKind};
 use shared::database::queries::filter;
 use shared::database::queries::filter::Filter;
+use shared::database::queries::projection::MongoProjection;
 use shared::database::role::permissions::RolePermission;
 use shared::database::role::{Role as DbRole, RoleId};
 use shared::database::stored_event::StoredEventRoleData;
@@ -23,6 +24,13 @@ use crate::transactions::{transaction, transaction_with_mutex, GeneralMutexKey,
 #[derive(Default)]
 pub struct RolesMutation;
 
+/// Only the ranks are needed to find a free one for a new role.
+#[derive(serde::Deserialize, MongoProjection)]
+#[mongo(collection = "DbRole")]
+struct RoleRank {
+	rank: i32,
+}
+
 #[Object(rename_fields = "camelCase", rename_args = "snake_case")]
 impl RolesMutation {
 	#[graphql(guard = "PermissionGuard::one(RolePermission::Manage)")]
@@ -56,9 +64,9 @@ impl RolesMutation {
 			));
 		}
 
-		let res = transaction::<_, (), _, _>(global, |mut tx| async move {
+		let res = transaction::<_, (), _, _>(global, "v3.roles.create", |mut tx| async move {
 			let roles = tx
-				.find(
+				.find_projected::<DbRole, RoleRank>(
 					filter::filter! {
 						DbRole {}
 					},
@@ -314,6 +322,7 @@ impl RolesMutation {
 
 		let res = transaction_with_mutex(
 			global,
+			"v3.roles.delete",
 			Some(GeneralMutexKey::Role(role_id.id()).into()),
 			|mut tx| async move {
 				let role = tx
@@ -8,7 +8,11 @@ use mongodb::options::{FindOneAndUpdateOptions, ReturnDocument, UpdateOptions};
 use shared::database::badge::BadgeId;
 use shared::database::emote::Emote;
 use shared::database::emote_set::{EmoteSet, EmoteSetKind};
-use shared::database::entitlement::{EntitlementEdge, EntitlementEdgeId, EntitlementEdgeKind, EntitlementEdgeManagedBy};
+use shared::database::entitlement::{
+	CalculatedEntitlements, EntitlementEdge, EntitlementEdgeId, EntitlementEdgeKind, EntitlementEdgeManagedBy,
+};
+use shared::database::entitlement_edge::EntitlementEdgeGraphTraverse;
+use shared::database::graph::{Direction, GraphTraverse};
 use shared::database::paint::PaintId;
 use shared::database::product::invoice::{Invoice, InvoiceStatus};
 use shared::database::product::subscription::{
@@ -17,11 +21,14 @@ use shared::database::product::subscription::{
 use shared::database::product::{InvoiceId, SubscriptionProductKind};
 use shared::database::queries::{filter, update};
 use shared::database::role::permissions::{
-	FlagPermission, PermissionsExt, RateLimitResource, RolePermission, UserPermission,
+	effective_emote_set_capacity, AdminPermission, FlagPermission, PermissionsExt, RateLimitResource, RolePermission,
+	UserPermission,
 };
+use shared::database::role::RoleId;
 use shared::database::user::ban::UserBan;
 use shared::database::user::connection::UserConnection as DbUserConnection;
 use shared::database::user::editor::{EditorUserPermission, UserEditor as DbUserEditor, UserEditorId, UserEditorState};
+use shared::database::user::profile_picture::UserProfilePictureId;
 use shared::database::user::session::UserSession;
 use shared::database::user::{User, UserId, UserStyle};
 use shared::database::{Id, MongoCollection};
@@ -32,6 +39,7 @@ use shared::old_types::UserEditorModelPermission;
 use tracing::Instrument;
 
 use crate::global::Global;
+use crate::http::connection::check_login_connection;
 use crate::http::egvault::metadata::{InvoiceMetadata, StripeMetadata};
 use crate::http::error::{ApiError, ApiErrorCode};
 use crate::http::guards::{PermissionGuard, RateLimitGuard};
@@ -39,7 +47,7 @@ use crate::http::middleware::session::Session;
 use crate::http::v3::gql::queries::user::{UserConnection, UserEditor};
 use crate::http::v3::gql::types::ListItemAction;
 use crate::sub_refresh_job;
-use crate::transactions::{transaction, transaction_with_mutex, GeneralMutexKey, TransactionError};
+use crate::transactions::{transaction, transaction_with_mutex, BulkWrite, GeneralMutexKey, TransactionError};
 
 #[derive(Default)]
 pub struct UsersMutation;
@@ -49,6 +57,175 @@ impl UsersMutation {
 	async fn user(&self, id: GqlObjectId) -> UserOps {
 		UserOps { id }
 	}
+
+	/// Adds or removes a role for many users at once, returns the ids of the
+	/// users which were changed.
+	#[graphql(
+		guard = "PermissionGuard::one(RolePermission::Assign).and(RateLimitGuard::new(RateLimitResource::UserAssignRole, 1))"
+	)]
+	#[tracing::instrument(skip_all, name = "UsersMutation::assign_role")]
+	async fn assign_role<'ctx>(
+		&self,
+		ctx: &Context<'ctx>,
+		#[graphql(validator(max_items = 100))] user_ids: Vec<GqlObjectId>,
+		role_id: GqlObjectId,
+		action: ListItemAction,
+	) -> Result<Vec<GqlObjectId>, ApiError> {
+		let global: &Arc<Global> = ctx
+			.data()
+			.map_err(|_| ApiError::internal_server_error(ApiErrorCode::MissingContext, "missing global data"))?;
+		let session = ctx
+			.data::<Session>()
+			.map_err(|_| ApiError::internal_server_error(ApiErrorCode::MissingContext, "missing sesion data"))?;
+		let authed_user = session.user()?;
+
+		let role = global
+			.role_by_id_loader
+			.load(role_id.id())
+			.await
+			.map_err(|()| ApiError::internal_server_error(ApiErrorCode::LoadError, "failed to load role"))?
+			.ok_or_else(|| ApiError::not_found(ApiErrorCode::LoadError, "role not found"))?;
+
+		if !authed_user.computed.permissions.is_superset_of(&role.permissions) {
+			return Err(ApiError::forbidden(
+				ApiErrorCode::LackingPrivileges,
+				"the role has a higher permission level than you",
+			));
+		}
+
+		if matches!(action, ListItemAction::Update) {
+			return Err(ApiError::not_implemented(
+				ApiErrorCode::BadRequest,
+				"update role is not implemented",
+			));
+		}
+
+		let user_ids: HashSet<UserId> = user_ids.into_iter().map(|id| id.id()).collect();
+
+		let users = global
+			.user_loader
+			.load_many(global, user_ids.iter().copied())
+			.await
+			.map_err(|()| ApiError::internal_server_error(ApiErrorCode::LoadError, "failed to load users"))?;
+
+		if let Some(user_id) = user_ids.iter().find(|id| !users.contains_key(*id)) {
+			return Err(ApiError::not_found(
+				ApiErrorCode::LoadError,
+				format!("user {user_id} not found"),
+			));
+		}
+
+		if !authed_user.has(AdminPermission::SuperAdmin) {
+			if let Some(user) = users
+				.values()
+				.find(|u| u.computed.highest_role_rank >= authed_user.computed.highest_role_rank)
+			{
+				return Err(ApiError::forbidden(
+					ApiErrorCode::LackingPrivileges,
+					format!("can only change the roles of users with lower roles, {} is not", user.id),
+				));
+			}
+		}
+
+		let users = &users;
+
+		// Every user is locked like for a single role change, the locks are taken
+		// at once and the request fails if any of them is held.
+		let res = global
+			.mutex
+			.acquire_many(user_ids.iter().map(|id| GeneralMutexKey::User(*id)), || {
+				transaction(global, "v3.users.assign_role", |mut tx| async move {
+					let edge_id = |user_id: UserId| EntitlementEdgeId {
+						from: EntitlementEdgeKind::User { user_id },
+						to: EntitlementEdgeKind::Role { role_id: role_id.id() },
+						managed_by: None,
+					};
+
+					let edge_ids: Vec<_> = users.keys().map(|id| edge_id(*id)).collect();
+
+					let existing: HashSet<UserId> = tx
+						.find(
+							filter::filter! {
+								EntitlementEdge {
+									#[query(rename = "_id", selector = "in", serde)]
+									id: &edge_ids,
+								}
+							},
+							None,
+						)
+						.await?
+						.into_iter()
+						.filter_map(|edge| match edge.id.from {
+							EntitlementEdgeKind::User { user_id } => Some(user_id),
+							_ => None,
+						})
+						.collect();
+
+					let add = action == ListItemAction::Add;
+
+					// Only the users which do not have the edge yet when adding, or which have it
+					// when removing, are changed.
+					let changed: Vec<_> = users.values().filter(|u| existing.contains(&u.id) != add).collect();
+
+					if !changed.is_empty() {
+						let edges = if add {
+							// the edges were checked to not exist in this transaction
+							changed.iter().fold(BulkWrite::<EntitlementEdge>::new(), |write, user| {
+								write.insert_one(EntitlementEdge {
+									id: edge_id(user.id),
+									expires_at: None,
+								})
+							})
+						} else {
+							let remove_edges: Vec<_> = changed.iter().map(|u| edge_id(u.id)).collect();
+
+							BulkWrite::<EntitlementEdge>::new().delete_many(filter::filter! {
+								EntitlementEdge {
+									#[query(rename = "_id", selector = "in", serde)]
+									id: remove_edges,
+								}
+							})
+						};
+
+						tx.bulk_write(edges, None).await?;
+					}
+
+					for user in &changed {
+						let target = EntitlementEdgeKind::Role { role_id: role_id.id() };
+
+						tx.register_event(InternalEvent {
+							actor: Some(authed_user.clone()),
+							session_id: session.user_session_id(),
+							data: InternalEventData::User {
+								after: user.user.clone(),
+								data: if add {
+									InternalEventUserData::AddEntitlement { target }
+								} else {
+									InternalEventUserData::RemoveEntitlement { target }
+								},
+							},
+							timestamp: chrono::Utc::now(),
+						})?;
+					}
+
+					Ok(changed.into_iter().map(|u| u.id.into()).collect())
+				})
+			})
+			.await
+			.unwrap_or_else(|e| Err(e.into()));
+
+		match res {
+			Ok(user_ids) => Ok(user_ids),
+			Err(TransactionError::Custom(e)) => Err(e),
+			Err(e) => {
+				tracing::error!(error = %e, "transaction failed");
+				Err(ApiError::internal_server_error(
+					ApiErrorCode::TransactionError,
+					"transaction failed",
+				))
+			}
+		}
+	}
 }
 
 #[derive(SimpleObject)]
@@ -134,6 +311,7 @@ impl UserOps {
 
 		let res = transaction_with_mutex(
 			global,
+			"v3.users.connections",
 			Some(GeneralMutexKey::User(self.id.id()).into()),
 			|mut tx| async move {
 				let old_user = global
@@ -166,11 +344,20 @@ impl UserOps {
 							TransactionError::Custom(ApiError::not_found(ApiErrorCode::LoadError, "emote set not found"))
 						})?;
 
-					if emote_set.kind != EmoteSetKind::Normal {
-						return Err(TransactionError::Custom(ApiError::bad_request(
-							ApiErrorCode::BadRequest,
-							"emote set is not a normal set",
-						)));
+					match emote_set.kind {
+						EmoteSetKind::Normal => {}
+						EmoteSetKind::Global => {
+							return Err(TransactionError::Custom(ApiError::bad_request(
+								ApiErrorCode::BadRequest,
+								"global emote sets can not be used as an active emote set",
+							)));
+						}
+						EmoteSetKind::Personal | EmoteSetKind::Special => {
+							return Err(TransactionError::Custom(ApiError::bad_request(
+								ApiErrorCode::BadRequest,
+								"emote set is not a normal set",
+							)));
+						}
 					}
 
 					Some(emote_set)
@@ -219,12 +406,7 @@ impl UserOps {
 				};
 
 				if let Some(true) = data.unlink {
-					if user.connections.is_empty() {
-						return Err(TransactionError::Custom(ApiError::bad_request(
-							ApiErrorCode::BadRequest,
-							"cannot remove last connection",
-						)));
-					}
+					check_login_connection(&user.connections, authed_user).map_err(TransactionError::Custom)?;
 
 					let connection = old_user
 						.user
@@ -292,7 +474,7 @@ impl UserOps {
 						.cloned()
 						.map(|c| {
 							Some(UserConnection::from_db(
-								full_user.computed.permissions.emote_set_capacity.unwrap_or_default(),
+								effective_emote_set_capacity(&full_user.computed.permissions, &EmoteSetKind::Normal),
 								c,
 								&full_user.style,
 							))
@@ -367,6 +549,7 @@ impl UserOps {
 
 		let res = transaction_with_mutex(
 			global,
+			"v3.users.editors",
 			Some(GeneralMutexKey::User(self.id.id()).into()),
 			|mut tx| async move {
 				let editor_id = UserEditorId {
@@ -613,186 +796,262 @@ impl UserOps {
 			.map_err(|()| ApiError::internal_server_error(ApiErrorCode::LoadError, "failed to load user"))?
 			.ok_or_else(|| ApiError::not_found(ApiErrorCode::LoadError, "user not found"))?;
 
-		let res =
-			transaction_with_mutex(
-				global,
-				Some(GeneralMutexKey::User(self.id.id()).into()),
-				|mut tx| async move {
-					match update.kind {
-				CosmeticKind::Paint => {
-					let id: Option<PaintId> = if update.id.0.is_nil() { None } else { Some(update.id.id()) };
-
-					// check if user has paint
-					if id.is_some_and(|id| !user.computed.entitlements.paints.contains(&id)) {
-						return Err(TransactionError::Custom(ApiError::forbidden(
-							ApiErrorCode::LoadError,
-							"you do not have permission to use this paint",
-						)));
-					}
+		let res = transaction_with_mutex(
+			global,
+			"v3.users.cosmetics",
+			Some(GeneralMutexKey::User(self.id.id()).into()),
+			|mut tx| async move {
+				match update.kind {
+					CosmeticKind::Paint => {
+						let id: Option<PaintId> = update.id.non_nil();
 
-					if user.style.active_paint_id == id {
-						return Ok(true);
-					}
+						// check if user has paint
+						if id.is_some_and(|id| !user.computed.entitlements.paints.contains(&id)) {
+							return Err(TransactionError::Custom(ApiError::forbidden(
+								ApiErrorCode::LoadError,
+								"you do not have permission to use this paint",
+							)));
+						}
 
-					let new = if let Some(id) = id {
-						Some(
-							global
-								.paint_by_id_loader
-								.load(id)
-								.await
-								.map_err(|_| {
-									TransactionError::Custom(ApiError::internal_server_error(
-										ApiErrorCode::LoadError,
-										"failed to load paint",
-									))
-								})?
-								.ok_or_else(|| {
-									TransactionError::Custom(ApiError::not_found(ApiErrorCode::LoadError, "paint not found"))
-								})?,
-						)
-					} else {
-						None
-					};
+						if user.style.active_paint_id == id {
+							return Ok(true);
+						}
 
-					let old = if let Some(paint_id) = user.style.active_paint_id {
-						global.paint_by_id_loader.load(paint_id).await.map_err(|_| {
-							TransactionError::Custom(ApiError::internal_server_error(
-								ApiErrorCode::LoadError,
-								"failed to load badge",
-							))
-						})?
-					} else {
-						None
-					};
+						let new = if let Some(id) = id {
+							Some(
+								global
+									.paint_by_id_loader
+									.load(id)
+									.await
+									.map_err(|_| {
+										TransactionError::Custom(ApiError::internal_server_error(
+											ApiErrorCode::LoadError,
+											"failed to load paint",
+										))
+									})?
+									.ok_or_else(|| {
+										TransactionError::Custom(ApiError::not_found(
+											ApiErrorCode::LoadError,
+											"paint not found",
+										))
+									})?,
+							)
+						} else {
+							None
+						};
 
-					tx.register_event(InternalEvent {
-						actor: Some(authed_user.clone()),
-						session_id: session.user_session_id(),
-						data: InternalEventData::User {
-							after: user.user.clone(),
-							data: InternalEventUserData::ChangeActivePaint {
-								old: old.map(Box::new),
-								new: new.map(Box::new),
-							},
-						},
-						timestamp: chrono::Utc::now(),
-					})?;
+						let old = if let Some(paint_id) = user.style.active_paint_id {
+							global.paint_by_id_loader.load(paint_id).await.map_err(|_| {
+								TransactionError::Custom(ApiError::internal_server_error(
+									ApiErrorCode::LoadError,
+									"failed to load badge",
+								))
+							})?
+						} else {
+							None
+						};
 
-					let res = User::collection(&global.db)
-						.update_one(
-							filter::filter! {
-								User {
-									#[query(rename = "_id")]
-									id: self.id.id(),
-								}
-							},
-							update::update! {
-								#[query(set)]
-								User {
-									#[query(flatten)]
-									style: UserStyle {
-										active_paint_id: id,
-									},
-									updated_at: chrono::Utc::now(),
-									search_updated_at: &None,
-								}
+						tx.register_event(InternalEvent {
+							actor: Some(authed_user.clone()),
+							session_id: session.user_session_id(),
+							data: InternalEventData::User {
+								after: user.user.clone(),
+								data: InternalEventUserData::ChangeActivePaint {
+									old: old.map(Box::new),
+									new: new.map(Box::new),
+								},
 							},
-						)
-						.await?;
+							timestamp: chrono::Utc::now(),
+						})?;
 
-					Ok(res.modified_count == 1)
-				}
-				CosmeticKind::Badge => {
-					let id: Option<BadgeId> = if update.id.0.is_nil() { None } else { Some(update.id.id()) };
+						let res = User::collection(&global.db)
+							.update_one(
+								filter::filter! {
+									User {
+										#[query(rename = "_id")]
+										id: self.id.id(),
+									}
+								},
+								update::update! {
+									#[query(set)]
+									User {
+										#[query(flatten)]
+										style: UserStyle {
+											active_paint_id: id,
+										},
+										updated_at: chrono::Utc::now(),
+										search_updated_at: &None,
+									}
+								},
+							)
+							.await?;
 
-					// check if user has paint
-					if id.is_some_and(|id| !user.computed.entitlements.badges.contains(&id)) {
-						return Err(TransactionError::Custom(ApiError::forbidden(
-							ApiErrorCode::LoadError,
-							"you do not have permission to use this badge",
-						)));
+						Ok(res.modified_count == 1)
 					}
+					CosmeticKind::Badge => {
+						let id: Option<BadgeId> = update.id.non_nil();
+
+						// check if user has paint
+						if id.is_some_and(|id| !user.computed.entitlements.badges.contains(&id)) {
+							return Err(TransactionError::Custom(ApiError::forbidden(
+								ApiErrorCode::LoadError,
+								"you do not have permission to use this badge",
+							)));
+						}
 
-					if user.style.active_badge_id == id {
-						return Ok(true);
+						if user.style.active_badge_id == id {
+							return Ok(true);
+						}
+
+						let new = if let Some(id) = id {
+							Some(
+								global
+									.badge_by_id_loader
+									.load(id)
+									.await
+									.map_err(|_| {
+										TransactionError::Custom(ApiError::internal_server_error(
+											ApiErrorCode::LoadError,
+											"failed to load badge",
+										))
+									})?
+									.ok_or_else(|| {
+										TransactionError::Custom(ApiError::not_found(
+											ApiErrorCode::LoadError,
+											"badge not found",
+										))
+									})?,
+							)
+						} else {
+							None
+						};
+
+						let old = if let Some(badge_id) = user.style.active_badge_id {
+							global.badge_by_id_loader.load(badge_id).await.map_err(|_| {
+								TransactionError::Custom(ApiError::internal_server_error(
+									ApiErrorCode::LoadError,
+									"failed to load badge",
+								))
+							})?
+						} else {
+							None
+						};
+
+						tx.register_event(InternalEvent {
+							actor: Some(authed_user.clone()),
+							session_id: session.user_session_id(),
+							data: InternalEventData::User {
+								after: user.user.clone(),
+								data: InternalEventUserData::ChangeActiveBadge {
+									old: old.map(Box::new),
+									new: new.map(Box::new),
+								},
+							},
+							timestamp: chrono::Utc::now(),
+						})?;
+
+						let res = User::collection(&global.db)
+							.update_one(
+								filter::filter! {
+									User {
+										#[query(rename = "_id")]
+										id: self.id.id(),
+									}
+								},
+								update::update! {
+									#[query(set)]
+									User {
+										#[query(flatten)]
+										style: UserStyle {
+											active_badge_id: id,
+										},
+										updated_at: chrono::Utc::now(),
+										search_updated_at: &None,
+									},
+								},
+							)
+							.await?;
+
+						Ok(res.modified_count == 1)
 					}
+					CosmeticKind::Avatar => {
+						let id: Option<UserProfilePictureId> = update.id.non_nil();
 
-					let new = if let Some(id) = id {
-						Some(
+						if id.is_some() && !user.has(UserPermission::UseCustomProfilePicture) {
+							return Err(TransactionError::Custom(ApiError::forbidden(
+								ApiErrorCode::LackingPrivileges,
+								"you do not have permission to use custom profile pictures",
+							)));
+						}
+
+						if user.style.active_profile_picture == id {
+							return Ok(true);
+						}
+
+						if let Some(id) = id {
+							// only pictures previously uploaded by this user which are done processing
+							// can be selected
 							global
-								.badge_by_id_loader
+								.user_profile_picture_id_loader
 								.load(id)
 								.await
 								.map_err(|_| {
 									TransactionError::Custom(ApiError::internal_server_error(
 										ApiErrorCode::LoadError,
-										"failed to load badge",
+										"failed to load profile picture",
 									))
 								})?
+								.filter(|p| p.user_id == user.id && user.style.pending_profile_picture != Some(p.id))
 								.ok_or_else(|| {
-									TransactionError::Custom(ApiError::not_found(ApiErrorCode::LoadError, "badge not found"))
-								})?,
-						)
-					} else {
-						None
-					};
-
-					let old = if let Some(badge_id) = user.style.active_badge_id {
-						global.badge_by_id_loader.load(badge_id).await.map_err(|_| {
-							TransactionError::Custom(ApiError::internal_server_error(
-								ApiErrorCode::LoadError,
-								"failed to load badge",
-							))
-						})?
-					} else {
-						None
-					};
+									TransactionError::Custom(ApiError::not_found(
+										ApiErrorCode::LoadError,
+										"profile picture not found",
+									))
+								})?;
+						}
 
-					tx.register_event(InternalEvent {
-						actor: Some(authed_user.clone()),
-						session_id: session.user_session_id(),
-						data: InternalEventData::User {
-							after: user.user.clone(),
-							data: InternalEventUserData::ChangeActiveBadge {
-								old: old.map(Box::new),
-								new: new.map(Box::new),
+						tx.register_event(InternalEvent {
+							actor: Some(authed_user.clone()),
+							session_id: session.user_session_id(),
+							data: InternalEventData::User {
+								after: user.user.clone(),
+								data: InternalEventUserData::ChangeActiveProfilePicture {
+									old: user.style.active_profile_picture,
+									new: id,
+								},
 							},
-						},
-						timestamp: chrono::Utc::now(),
-					})?;
+							timestamp: chrono::Utc::now(),
+						})?;
 
-					let res = User::collection(&global.db)
-						.update_one(
-							filter::filter! {
-								User {
-									#[query(rename = "_id")]
-									id: self.id.id(),
-								}
-							},
-							update::update! {
-								#[query(set)]
-								User {
-									#[query(flatten)]
-									style: UserStyle {
-										active_badge_id: id,
+						let res = tx
+							.update_one(
+								filter::filter! {
+									User {
+										#[query(rename = "_id")]
+										id: self.id.id(),
+									}
+								},
+								update::update! {
+									#[query(set)]
+									User {
+										#[query(flatten)]
+										style: UserStyle {
+											active_profile_picture: id,
+										},
+										updated_at: chrono::Utc::now(),
+										search_updated_at: &None,
 									},
-									updated_at: chrono::Utc::now(),
-									search_updated_at: &None,
 								},
-							},
-						)
-						.await?;
+								None,
+							)
+							.await?;
 
-					Ok(res.modified_count == 1)
+						Ok(res.modified_count == 1)
+					}
 				}
-				CosmeticKind::Avatar => Err(TransactionError::Custom(ApiError::not_implemented(
-					ApiErrorCode::BadRequest,
-					"avatar cosmetics mutations are not supported via this endpoint, use the upload endpoint instead",
-				))),
-			}
-				},
-			)
-			.await;
+			},
+		)
+		.await;
 
 		match res {
 			Ok(b) => Ok(b),
@@ -844,11 +1103,14 @@ impl UserOps {
 			.map_err(|()| ApiError::internal_server_error(ApiErrorCode::LoadError, "failed to load user"))?
 			.ok_or_else(|| ApiError::not_found(ApiErrorCode::LoadError, "user not found"))?;
 
+		let target_user = &target_user;
+
 		let res = transaction_with_mutex(
 			global,
+			"v3.users.roles",
 			Some(GeneralMutexKey::User(self.id.id()).into()),
 			|mut tx| async move {
-				let roles = match action {
+				match action {
 					ListItemAction::Add => {
 						let edge_id = EntitlementEdgeId {
 							from: EntitlementEdgeKind::User { user_id: self.id.id() },
@@ -888,19 +1150,6 @@ impl UserOps {
 								timestamp: chrono::Utc::now(),
 							})?;
 						}
-
-						let no_role = !target_user.computed.roles.contains(&role_id.id());
-
-						target_user
-							.computed
-							.entitlements
-							.roles
-							.iter()
-							.copied()
-							// If the user didnt have the role before, we add it
-							.chain(no_role.then_some(role_id.id()))
-							.map(Into::into)
-							.collect()
 					}
 					ListItemAction::Remove => {
 						if tx
@@ -932,23 +1181,6 @@ impl UserOps {
 								timestamp: chrono::Utc::now(),
 							})?;
 						}
-
-						// They might have the role via some other entitlement.
-						let role_via_edge = target_user.computed.raw_entitlements.iter().flat_map(|e| e.iter()).any(|e| {
-							e.id.to == EntitlementEdgeKind::Role { role_id: role_id.id() }
-								&& (e.id.from != EntitlementEdgeKind::User { user_id: self.id.id() }
-									|| e.id.managed_by.is_some())
-						});
-
-						target_user
-							.computed
-							.entitlements
-							.roles
-							.iter()
-							.copied()
-							.filter(|id| role_via_edge || *id != role_id.id())
-							.map(Into::into)
-							.collect()
 					}
 					ListItemAction::Update => {
 						return Err(TransactionError::Custom(ApiError::not_implemented(
@@ -956,24 +1188,46 @@ impl UserOps {
 							"update role is not implemented",
 						)));
 					}
-				};
+				}
 
-				Ok(roles)
+				Ok(())
 			},
 		)
 		.await;
 
 		match res {
-			Ok(roles) => Ok(roles),
-			Err(TransactionError::Custom(e)) => Err(e),
+			Ok(()) => {}
+			Err(TransactionError::Custom(e)) => return Err(e),
 			Err(e) => {
 				tracing::error!(error = %e, "transaction failed");
-				Err(ApiError::internal_server_error(
+				return Err(ApiError::internal_server_error(
 					ApiErrorCode::TransactionError,
 					"transaction failed",
-				))
+				));
 			}
 		}
+
+		let traverse = &EntitlementEdgeGraphTraverse {
+			inbound_loader: &global.entitlement_edge_inbound_loader,
+			outbound_loader: &global.entitlement_edge_outbound_loader,
+		};
+
+		// They might have the role via some other entitlement, so the roles are
+		// recomputed from the entitlement graph after the change.
+		let roles = user_roles(traverse, self.id.id()).await.map_err(|()| {
+			ApiError::internal_server_error(ApiErrorCode::LoadError, "failed to traverse entitlement edges")
+		})?;
+
+		Ok(target_user
+			.computed
+			.roles
+			.iter()
+			.copied()
+			.filter(|id| roles.contains(id))
+			// Roles the user did not have before are added at the end
+			.chain(roles.iter().copied().filter(|id| !target_user.computed.roles.contains(id)))
+			.map(Into::into)
+			.collect())
 	}
 
 	#[graphql(guard = "PermissionGuard::one(UserPermission::ManageAny)")]
@@ -1014,7 +1268,7 @@ impl UserOps {
 			.unwrap_or_default();
 
 		let subscription_ids =
-			transaction_with_mutex(global, Some(GeneralMutexKey::User(src_user_id).into()), |mut tx| async move {
+			transaction_with_mutex(global, "v3.users.merge", Some(GeneralMutexKey::User(src_user_id).into()), |mut tx| async move {
 				let src_user = tx
 					.find_one(
 						filter::filter! {
@@ -1463,7 +1717,7 @@ impl UserOps {
 			.map_err(|_| ApiError::internal_server_error(ApiErrorCode::MissingContext, "missing sesion data"))?;
 		let authed_user = session.user()?;
 
-		transaction(global, |mut tx| async move {
+		transaction(global, "v3.users.delete", |mut tx| async move {
 			let user = tx
 				.find_one_and_delete(
 					filter::filter! {
@@ -1587,7 +1841,7 @@ impl UserOps {
 
 			let stripe_client = global.stripe_client.clone().safe(Id::<()>::new()).await;
 
-			let status = transaction(global, |mut tx| async move {
+			let status = transaction(global, "v3.users.create_subscription_period", |mut tx| async move {
 				let invoice = stripe::Invoice::create(
 					stripe_client.client(StripeRequest::CreateInvoice).await.deref(),
 					stripe::CreateInvoice {
@@ -1738,7 +1992,7 @@ impl UserOps {
 
 			Ok(status)
 		} else {
-			let status = transaction(global, |mut tx| async move {
+			let status = transaction(global, "v3.users.create_subscription_period", |mut tx| async move {
 				tx.insert_one(
 					SubscriptionPeriod {
 						id: SubscriptionPeriodId::new(),
@@ -1839,3 +2093,86 @@ pub struct UserCosmeticUpdate {
 	kind: CosmeticKind,
 	selected: bool,
 }
+
+/// The roles granted to a user by the entitlement graph, including the ones
+/// every user gets.
+async fn user_roles<G: GraphTraverse<Edge = EntitlementEdge>>(
+	traverse: &G,
+	user_id: UserId,
+) -> Result<HashSet<RoleId>, G::Error> {
+	let edges = traverse
+		.traversal(
+			Direction::Outbound,
+			[
+				EntitlementEdgeKind::GlobalDefaultEntitlementGroup,
+				EntitlementEdgeKind::User { user_id },
+			],
+		)
+		.await?;
+
+	Ok(CalculatedEntitlements::new(edges.into_iter().map(|e| e.id.to)).roles)
+}
+
+#[cfg(test)]
+mod tests {
+	use shared::database::product::subscription::SubscriptionId;
+
+	use super::*;
+
+	struct Graph(Vec<EntitlementEdge>);
+
+	impl GraphTraverse for Graph {
+		type Edge = EntitlementEdge;
+		type Error = ();
+
+		async fn fetch_edges(&self, direction: Direction, nodes: &[EntitlementEdgeKind]) -> Result<Vec<Self::Edge>, ()> {
+			Ok(self
+				.0
+				.iter()
+				.filter(|edge| match direction {
+					Direction::Inbound => nodes.contains(&edge.id.to),
+					Direction::Outbound => nodes.contains(&edge.id.from),
+				})
+				.cloned()
+				.collect())
+		}
+	}
+
+	#[tokio::test]
+	async fn test_user_roles_with_role_via_subscription() {
+		let user_id = UserId::new();
+		let role_id = RoleId::new();
+		let subscription_id = SubscriptionId {
+			user_id,
+			product_id: Id::new(),
+		};
+		let managed_by = Some(EntitlementEdgeManagedBy::Subscription { subscription_id });
+
+		let user = EntitlementEdgeKind::User { user_id };
+		let role = EntitlementEdgeKind::Role { role_id };
+		let subscription = EntitlementEdgeKind::Subscription { subscription_id };
+		let benefit = EntitlementEdgeKind::SubscriptionBenefit {
+			subscription_benefit_id: Id::new(),
+		};
+
+		let direct = EntitlementEdge::new(user.clone(), role.clone(), None);
+		let via_subscription = vec![
+			EntitlementEdge::new(user, subscription.clone(), None),
+			EntitlementEdge::new(subscription, benefit.clone(), managed_by),
+			EntitlementEdge::new(benefit, role, None),
+		];
+
+		let mut both = via_subscription.clone();
+		both.push(direct.clone());
+
+		let expected = HashSet::from([role_id]);
+
+		// held both directly and through the subscription
+		assert_eq!(user_roles(&Graph(both), user_id).await.unwrap(), expected);
+		// the role is kept after removing the direct edge
+		assert_eq!(user_roles(&Graph(via_subscription), user_id).await.unwrap(), expected);
+		assert_eq!(user_roles(&Graph(vec![direct]), user_id).await.unwrap(), expected);
+		// and gone once neither edge is left
+		assert!(user_roles(&Graph(vec![]), user_id).await.unwrap().is_empty());
+	}
+}
@@ -54,11 +54,14 @@ impl Emote {
 		let host = ImageHost::from_image_set(&value.image_set, &global.config.api.cdn_origin);
 		let state = EmoteVersionState::from_db(&value.flags);
 		let listed = value.flags.contains(shared::database::emote::EmoteFlags::PublicListed);
-		let lifecycle = if value.image_set.input.is_pending() {
+		let lifecycle = if value.processing_error.is_some() {
+			EmoteLifecycleModel::Failed
+		} else if value.image_set.input.is_pending() {
 			EmoteLifecycleModel::Pending
 		} else {
 			EmoteLifecycleModel::Live
 		};
+		let error = value.processing_error.as_ref().map(|e| e.reason().to_string());
 
 		Self {
 			id: value.id.into(),
@@ -74,7 +77,7 @@ impl Emote {
 				name: value.default_name,
 				description: String::new(),
 				lifecycle,
-				error: None,
+				error,
 				state: state.clone(),
 				listed,
 				host,
@@ -303,7 +306,7 @@ pub struct EmoteVersion {
 	// created_at
 	host: ImageHost,
 	lifecycle: EmoteLifecycleModel,
-	error: Option<String>, // always None
+	error: Option<String>,
 	state: Vec<EmoteVersionState>,
 	listed: bool,
 }
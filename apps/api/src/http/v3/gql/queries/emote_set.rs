@@ -1,5 +1,6 @@
 use std::sync::Arc;
 
+use async_graphql::dataloader::DataLoader;
 use async_graphql::{ComplexObject, Context, Enum, Object, SimpleObject};
 use mongodb::bson::doc;
 use shared::database::emote::Emote as DbEmote;
@@ -14,6 +15,7 @@ use crate::dataloader::emote::EmoteByIdLoaderExt;
 use crate::global::Global;
 use crate::http::error::{ApiError, ApiErrorCode};
 use crate::http::middleware::session::Session;
+use crate::http::v3::gql::loader::UserLoader;
 
 // https://github.com/SevenTV/API/blob/main/internal/api/gql/v3/schema/emoteset.gql
 
@@ -189,10 +191,12 @@ impl EmoteSet {
 		let session = ctx
 			.data::<Session>()
 			.map_err(|_| ApiError::internal_server_error(ApiErrorCode::MissingContext, "missing sesion data"))?;
+		let loader = ctx
+			.data::<DataLoader<UserLoader>>()
+			.map_err(|_| ApiError::internal_server_error(ApiErrorCode::MissingContext, "missing user loader"))?;
 
-		Ok(global
-			.user_loader
-			.load_fast(global, id.id())
+		Ok(loader
+			.load_one(id.id())
 			.await
 			.map_err(|()| ApiError::internal_server_error(ApiErrorCode::LoadError, "failed to load user"))?
 			.filter(|u| session.can_view(u))
@@ -1,7 +1,12 @@
 use std::sync::Arc;
 
+use async_graphql::dataloader::DataLoader;
 use async_graphql::{ComplexObject, Context, Object};
 use itertools::Itertools;
+use shared::database::badge::Badge;
+use shared::database::emote_set::EmoteSetKind;
+use shared::database::paint::Paint;
+use shared::database::role::permissions::effective_emote_set_capacity;
 use shared::database::user::{FullUser, UserId};
 use shared::old_types::cosmetic::{CosmeticBadgeModel, CosmeticKind, CosmeticPaintModel};
 use shared::old_types::object_id::GqlObjectId;
@@ -13,9 +18,11 @@ use super::emote::Emote;
 use super::emote_set::EmoteSet;
 use super::report::Report;
 use crate::global::Global;
+use crate::http::editor::capped_editors;
 use crate::http::error::{ApiError, ApiErrorCode};
 use crate::http::guards::RateLimitGuard;
 use crate::http::middleware::session::Session;
+use crate::http::v3::gql::loader::CosmeticLoader;
 use crate::search::{search, sorted_results, SearchOptions};
 
 // https://github.com/SevenTV/API/blob/main/internal/api/gql/v3/schema/users.gql
@@ -69,7 +76,7 @@ impl From<UserPartial> for User {
 #[ComplexObject(rename_fields = "snake_case", rename_args = "snake_case")]
 impl User {
 	async fn created_at(&self) -> chrono::DateTime<chrono::Utc> {
-		self.id.0.timestamp()
+		self.id.timestamp()
 	}
 
 	async fn style(&self) -> UserStyle {
@@ -80,6 +87,9 @@ impl User {
 		}
 	}
 
+	/// The first 500 accepted editors of the user, in the order they were
+	/// added. This list stays capped in v3, the full list is only available
+	/// through the paginated connections of the v4 API.
 	#[tracing::instrument(skip_all, name = "User::editors")]
 	async fn editors<'ctx>(&self, ctx: &Context<'ctx>) -> Result<Vec<UserEditor>, ApiError> {
 		let global: &Arc<Global> = ctx
@@ -93,9 +103,21 @@ impl User {
 			.map_err(|()| ApiError::internal_server_error(ApiErrorCode::LoadError, "failed to load user editors"))?
 			.unwrap_or_default();
 
-		Ok(editors.into_iter().filter_map(|e| UserEditor::from_db(e, false)).collect())
+		// pending editors are not returned, so they do not count towards the cap
+		let editors = editors
+			.into_iter()
+			.filter(|e| e.state == shared::database::user::editor::UserEditorState::Accepted)
+			.collect();
+
+		Ok(capped_editors(editors)
+			.into_iter()
+			.filter_map(|e| UserEditor::from_db(e, false))
+			.collect())
 	}
 
+	/// The first 500 accepted users this user is an editor of, in the order
+	/// they were added. This list stays capped in v3, the full list is only
+	/// available through the paginated connections of the v4 API.
 	#[tracing::instrument(skip_all, name = "User::editor_of")]
 	async fn editor_of<'ctx>(&self, ctx: &Context<'ctx>) -> Result<Vec<UserEditor>, ApiError> {
 		let global: &Arc<Global> = ctx
@@ -109,35 +131,35 @@ impl User {
 			.map_err(|()| ApiError::internal_server_error(ApiErrorCode::LoadError, "failed to load user editors"))?
 			.unwrap_or_default();
 
-		Ok(editors.into_iter().filter_map(|e| UserEditor::from_db(e, true)).collect())
+		// pending editors are not returned, so they do not count towards the cap
+		let editors = editors
+			.into_iter()
+			.filter(|e| e.state == shared::database::user::editor::UserEditorState::Accepted)
+			.collect();
+
+		Ok(capped_editors(editors)
+			.into_iter()
+			.filter_map(|e| UserEditor::from_db(e, true))
+			.collect())
 	}
 
 	#[tracing::instrument(skip_all, name = "User::cosmetics")]
 	async fn cosmetics(&self) -> Result<Vec<UserCosmetic>, ApiError> {
-		let paints = self
-			.full_user
-			.computed
-			.entitlements
-			.paints
-			.iter()
-			.map(|p| (CosmeticKind::Paint, p.cast::<()>()));
-		let badges = self
-			.full_user
-			.computed
-			.entitlements
-			.badges
-			.iter()
-			.map(|b| (CosmeticKind::Badge, b.cast::<()>()));
-
-		let mut cosmetics: Vec<_> = paints
-			.chain(badges)
-			.map(|(kind, id)| UserCosmetic {
-				id: id.into(),
-				selected: self.full_user.user.style.active_paint_id.is_some_and(|p| p == id.cast())
-					|| self.full_user.user.style.active_badge_id.is_some_and(|b| b == id.cast()),
-				kind,
-			})
-			.collect();
+		let style = &self.full_user.user.style;
+		let entitlements = &self.full_user.computed.entitlements;
+
+		let paints = entitlements.paints.iter().map(|&id| UserCosmetic {
+			id: id.into(),
+			selected: style.active_paint_id == Some(id),
+			kind: CosmeticKind::Paint,
+		});
+		let badges = entitlements.badges.iter().map(|&id| UserCosmetic {
+			id: id.into(),
+			selected: style.active_badge_id == Some(id),
+			kind: CosmeticKind::Badge,
+		});
+
+		let mut cosmetics: Vec<_> = paints.chain(badges).collect();
 
 		cosmetics.sort_by(|a, b| match a.kind.cmp(&b.kind) {
 			std::cmp::Ordering::Equal => a.id.cmp(&b.id),
@@ -253,13 +275,7 @@ impl User {
 	}
 
 	async fn connections<'ctx>(&self) -> Vec<UserConnection> {
-		let emote_capacity = self
-			.full_user
-			.computed
-			.permissions
-			.emote_set_capacity
-			.unwrap_or_default()
-			.max(0);
+		let emote_capacity = effective_emote_set_capacity(&self.full_user.computed.permissions, &EmoteSetKind::Normal);
 
 		self.full_user
 			.connections
@@ -387,7 +403,7 @@ impl UserPartial {
 			username: main_connection.map(|c| c.platform_username.clone()).unwrap_or_default(),
 			display_name: main_connection.map(|c| c.platform_display_name.clone()).unwrap_or_default(),
 			avatar_url: avatar_url.unwrap_or_default(),
-			biography: String::new(),
+			biography: full_user.biography.clone(),
 			full_user,
 		}
 	}
@@ -396,7 +412,7 @@ impl UserPartial {
 #[ComplexObject(rename_fields = "snake_case", rename_args = "snake_case")]
 impl UserPartial {
 	async fn created_at(&self) -> chrono::DateTime<chrono::Utc> {
-		self.id.0.timestamp()
+		self.id.timestamp()
 	}
 
 	async fn style(&self) -> UserStyle {
@@ -412,13 +428,7 @@ impl UserPartial {
 	}
 
 	async fn connections(&self) -> Vec<UserConnection> {
-		let emote_capacity = self
-			.full_user
-			.computed
-			.permissions
-			.emote_set_capacity
-			.unwrap_or_default()
-			.max(0);
+		let emote_capacity = effective_emote_set_capacity(&self.full_user.computed.permissions, &EmoteSetKind::Normal);
 
 		self.full_user
 			.connections
@@ -496,10 +506,12 @@ impl UserStyle {
 		let global = ctx
 			.data::<Arc<Global>>()
 			.map_err(|_| ApiError::internal_server_error(ApiErrorCode::MissingContext, "missing global data"))?;
+		let loader = ctx
+			.data::<DataLoader<CosmeticLoader>>()
+			.map_err(|_| ApiError::internal_server_error(ApiErrorCode::MissingContext, "missing cosmetic loader"))?;
 
-		Ok(global
-			.paint_by_id_loader
-			.load(id.id())
+		Ok(loader
+			.load_one(id.id::<Paint>())
 			.await
 			.map_err(|()| ApiError::internal_server_error(ApiErrorCode::LoadError, "failed to load paint"))?
 			.map(|p| CosmeticPaintModel::from_db(p, &global.config.api.cdn_origin)))
@@ -514,10 +526,12 @@ impl UserStyle {
 		let global = ctx
 			.data::<Arc<Global>>()
 			.map_err(|_| ApiError::internal_server_error(ApiErrorCode::MissingContext, "missing global data"))?;
+		let loader = ctx
+			.data::<DataLoader<CosmeticLoader>>()
+			.map_err(|_| ApiError::internal_server_error(ApiErrorCode::MissingContext, "missing cosmetic loader"))?;
 
-		Ok(global
-			.badge_by_id_loader
-			.load(id.id())
+		Ok(loader
+			.load_one(id.id::<Badge>())
 			.await
 			.map_err(|()| ApiError::internal_server_error(ApiErrorCode::LoadError, "failed to load badge"))?
 			.map(|b| CosmeticBadgeModel::from_db(b, &global.config.api.cdn_origin)))
@@ -0,0 +1,130 @@
+use std::sync::Arc;
+
+use async_graphql::{Context, Enum, SimpleObject, Subscription};
+use futures::{Stream, StreamExt};
+use shared::database::emote_set::EmoteSetId;
+use shared::event::{emote_set_subject, InternalEventData, InternalEventEmoteSetData, InternalEventPayload};
+use shared::old_types::object_id::GqlObjectId;
+
+use crate::global::Global;
+use crate::http::error::{ApiError, ApiErrorCode};
+use crate::http::middleware::session::Session;
+use crate::http::v3::gql::queries::emote_set::ActiveEmote;
+
+#[derive(Default)]
+pub struct EmoteSetsSubscription;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Enum)]
+pub enum EmoteSetChangeKind {
+	Added,
+	Removed,
+	Renamed,
+}
+
+#[derive(Debug, Clone, SimpleObject)]
+#[graphql(rename_fields = "snake_case")]
+pub struct EmoteSetChange {
+	id: GqlObjectId,
+	kind: EmoteSetChangeKind,
+	emote: ActiveEmote,
+	/// The previous name of the emote if it was renamed
+	old_name: Option<String>,
+}
+
+impl EmoteSetChange {
+	fn from_event(emote_set_id: EmoteSetId, data: InternalEventData) -> Option<Self> {
+		let InternalEventData::EmoteSet { after, data } = data else {
+			return None;
+		};
+
+		if after.id != emote_set_id {
+			return None;
+		}
+
+		let (kind, emote, emote_set_emote, old_name) = match data {
+			InternalEventEmoteSetData::AddEmote {
+				emote, emote_set_emote, ..
+			} => (EmoteSetChangeKind::Added, emote, emote_set_emote, None),
+			InternalEventEmoteSetData::RemoveEmote {
+				emote: Some(emote),
+				emote_set_emote,
+				..
+			} => (EmoteSetChangeKind::Removed, emote, emote_set_emote, None),
+			InternalEventEmoteSetData::RenameEmote {
+				emote,
+				emote_set_emote,
+				old_alias,
+			} => (EmoteSetChangeKind::Renamed, emote, emote_set_emote, Some(old_alias)),
+			_ => return None,
+		};
+
+		Some(Self {
+			id: after.id.into(),
+			kind,
+			emote: ActiveEmote::new(emote_set_emote, *emote),
+			old_name,
+		})
+	}
+}
+
+#[Subscription(rename_fields = "camelCase", rename_args = "snake_case")]
+impl EmoteSetsSubscription {
+	#[tracing::instrument(skip_all, name = "EmoteSetsSubscription::emote_set_changed")]
+	async fn emote_set_changed<'ctx>(
+		&self,
+		ctx: &Context<'ctx>,
+		emote_set_id: GqlObjectId,
+	) -> Result<impl Stream<Item = EmoteSetChange> + 'ctx, ApiError> {
+		let global: &Arc<Global> = ctx
+			.data()
+			.map_err(|_| ApiError::internal_server_error(ApiErrorCode::MissingContext, "missing global data"))?;
+
+		let session = ctx
+			.data::<Session>()
+			.map_err(|_| ApiError::internal_server_error(ApiErrorCode::MissingContext, "missing sesion data"))?;
+
+		let emote_set = global
+			.emote_set_by_id_loader
+			.load(emote_set_id.id())
+			.await
+			.map_err(|()| ApiError::internal_server_error(ApiErrorCode::LoadError, "failed to load emote set"))?
+			.ok_or_else(|| ApiError::not_found(ApiErrorCode::LoadError, "emote set not found"))?;
+
+		// Sets owned by users the session cannot see are treated as missing
+		if let Some(owner_id) = emote_set.owner_id {
+			let owner = global
+				.user_loader
+				.load_fast(global, owner_id)
+				.await
+				.map_err(|()| ApiError::internal_server_error(ApiErrorCode::LoadError, "failed to load user"))?;
+
+			if !owner.is_some_and(|owner| session.can_view(&owner)) {
+				return Err(ApiError::not_found(ApiErrorCode::LoadError, "emote set not found"));
+			}
+		}
+
+		let emote_set_id = emote_set.id;
+
+		// Only the events of this set are published on its subject
+		let subscriber = global.nats.subscribe(emote_set_subject(emote_set_id)).await.map_err(|err| {
+			tracing::error!(error = %err, "failed to subscribe to events");
+			ApiError::internal_server_error(ApiErrorCode::Unknown, "failed to subscribe to events")
+		})?;
+
+		Ok(subscriber.flat_map(move |message| {
+			let changes = match rmp_serde::from_slice::<InternalEventPayload>(&message.payload) {
+				Ok(payload) => payload
+					.events
+					.into_iter()
+					.filter_map(|event| EmoteSetChange::from_event(emote_set_id, event.data))
+					.collect(),
+				Err(err) => {
+					tracing::warn!(error = %err, "malformed event payload");
+					Vec::new()
+				}
+			};
+
+			futures::stream::iter(changes)
+		}))
+	}
+}
@@ -0,0 +1,6 @@
+use async_graphql::MergedSubscription;
+
+pub mod emote_set;
+
+#[derive(MergedSubscription, Default)]
+pub struct Subscription(emote_set::EmoteSetsSubscription);
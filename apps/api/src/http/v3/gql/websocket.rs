@@ -0,0 +1,72 @@
+use std::sync::Arc;
+
+use async_graphql::extensions::{Extension, ExtensionContext, ExtensionFactory, NextParseQuery};
+use async_graphql::parser::types::{ExecutableDocument, OperationType};
+use async_graphql::{ServerError, ServerResult, Variables};
+
+/// Marks requests sent over a websocket connection.
+pub struct WebSocketConnection;
+
+/// Rejects mutations sent over a websocket connection. Mutations are only
+/// accepted over http, where the CSRF protections of the http handler apply.
+pub struct WebSocketReadOnly;
+
+impl ExtensionFactory for WebSocketReadOnly {
+	fn create(&self) -> Arc<dyn Extension> {
+		Arc::new(WebSocketReadOnly)
+	}
+}
+
+#[async_trait::async_trait]
+impl Extension for WebSocketReadOnly {
+	async fn parse_query(
+		&self,
+		ctx: &ExtensionContext<'_>,
+		query: &str,
+		variables: &Variables,
+		next: NextParseQuery<'_>,
+	) -> ServerResult<ExecutableDocument> {
+		let document = next.run(ctx, query, variables).await?;
+
+		if ctx.data_opt::<WebSocketConnection>().is_some()
+			&& document
+				.operations
+				.iter()
+				.any(|(_, operation)| operation.node.ty == OperationType::Mutation)
+		{
+			return Err(ServerError::new("mutations are not allowed over websocket", None));
+		}
+
+		Ok(document)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use async_graphql::Request;
+
+	use super::*;
+	use crate::http::v3::gql::schema;
+
+	#[tokio::test]
+	async fn test_rejects_websocket_mutations() {
+		let schema = schema(None);
+
+		let response = schema
+			.execute(Request::new("mutation { __typename }").data(WebSocketConnection))
+			.await;
+		assert!(response
+			.errors
+			.iter()
+			.any(|e| e.message.contains("not allowed over websocket")));
+
+		let response = schema.execute(Request::new("{ __typename }").data(WebSocketConnection)).await;
+		assert!(response.errors.is_empty());
+
+		let response = schema.execute("mutation { __typename }").await;
+		assert!(!response
+			.errors
+			.iter()
+			.any(|e| e.message.contains("not allowed over websocket")));
+	}
+}
@@ -202,6 +202,7 @@ pub async fn handle_callback(
 	old_session: &Session,
 	query: LoginRequest,
 	cookies: &Cookies,
+	user_agent: Option<String>,
 ) -> Result<String, ApiError> {
 	let code = query
 		.code
@@ -219,6 +220,11 @@ pub async fn handle_callback(
 		.filter(|payload| payload.validate_random(&state).unwrap_or_default())
 		.ok_or_else(|| ApiError::bad_request(ApiErrorCode::BadRequest, "invalid csrf"))?;
 
+	let code_verifier = csrf_payload
+		.code_verifier
+		.clone()
+		.ok_or_else(|| ApiError::bad_request(ApiErrorCode::BadRequest, "missing code verifier"))?;
+
 	let platform = Platform::from(query.platform);
 
 	// exchange code for access token
@@ -227,7 +233,7 @@ pub async fn handle_callback(
 		platform,
 		&code,
 		redirect_uri(global, query.platform)?.to_string(),
-		None,
+		Some(code_verifier),
 	)
 	.await?;
 
@@ -253,7 +259,9 @@ pub async fn handle_callback(
 
 	let user_session = user_session.as_ref();
 
-	let response = transaction(global, |mut tx| async move {
+	let ip_hash = old_session.ip_hash(global);
+
+	let response = transaction(global, "v3.auth.login", |mut tx| async move {
 		let user = fetch_user_on_callback(&mut tx, platform, user_data, user_session).await?;
 
 		// upsert the connection
@@ -279,6 +287,7 @@ pub async fn handle_callback(
 				.api
 				.old_website_origin
 				.join(&format!("/auth/callback#platform={}&linked=true", platform))
+				.map(|url| (url, full_user.id))
 				.map_err(|e| {
 					tracing::error!(err = %e, "failed to generate redirect url");
 					TransactionError::Custom(ApiError::internal_server_error(
@@ -291,9 +300,10 @@ pub async fn handle_callback(
 		let user_session = UserSession {
 			id: Default::default(),
 			user_id: full_user.id,
-			// TODO: maybe allow for this to be configurable
-			expires_at: chrono::Utc::now() + chrono::Duration::days(30),
+			expires_at: chrono::Utc::now() + global.config.auth.session_duration(full_user.has(UserPermission::Admin)),
 			last_used_at: chrono::Utc::now(),
+			user_agent,
+			ip_hash: Some(ip_hash),
 			extensions: Default::default(),
 		};
 
@@ -338,6 +348,7 @@ pub async fn handle_callback(
 			.api
 			.old_website_origin
 			.join(&format!("/auth/callback#platform={}&token={}", query.platform, token))
+			.map(|url| (url, full_user.id))
 			.map_err(|e| {
 				tracing::error!(err = %e, "failed to generate redirect url");
 				TransactionError::Custom(ApiError::internal_server_error(
@@ -349,7 +360,10 @@ pub async fn handle_callback(
 	.await;
 
 	match response {
-		Ok(redirect_url) => Ok(redirect_url.to_string()),
+		Ok((redirect_url, user_id)) => {
+			connections::refresh::spawn_refresh(global, user_id, platform, &user_data.id);
+			Ok(redirect_url.to_string())
+		}
 		Err(TransactionError::Custom(e)) => Err(e),
 		Err(e) => {
 			tracing::error!(error = %e, "transaction failed");
@@ -369,7 +383,7 @@ pub fn handle_login(
 	cookies: &Cookies,
 ) -> Result<String, ApiError> {
 	// redirect to platform auth url
-	let (url, scope, config) = match platform {
+	let (url, default_scope, config) = match platform {
 		Platform::Twitch if global.config.connections.twitch.enabled => {
 			(TWITCH_AUTH_URL, TWITCH_AUTH_SCOPE, &global.config.connections.twitch)
 		}
@@ -387,7 +401,7 @@ pub fn handle_login(
 		}
 	};
 
-	let csrf = CsrfJwtPayload::new(if link_connection {
+	let mut csrf = CsrfJwtPayload::new(if link_connection {
 		Some(session.user_session_id().ok_or_else(|| {
 			ApiError::bad_request(ApiErrorCode::LackingPrivileges, "you need to be logged in to link an account")
 		})?)
@@ -395,6 +409,11 @@ pub fn handle_login(
 		None
 	});
 
+	// The verifier is stored in the signed csrf token so it cannot be swapped for
+	// another one on callback.
+	let (pkce_challenge, pkce_verifier) = oauth2::PkceCodeChallenge::new_random_sha256();
+	csrf.code_verifier = Some(pkce_verifier.into_secret());
+
 	cookies.add(new_cookie(
 		global,
 		(
@@ -406,15 +425,19 @@ pub fn handle_login(
 		),
 	));
 
+	let scope = config.scope.as_deref().unwrap_or(default_scope);
+
 	let redirect_uri = redirect_uri(global, platform)?;
 
 	let redirect_url = format!(
-		"{}client_id={}&redirect_uri={}&response_type=code&scope={}&state={}",
+		"{}client_id={}&redirect_uri={}&response_type=code&scope={}&state={}&code_challenge_method={}&code_challenge={}",
 		url,
 		config.client_id,
 		urlencoding::encode(redirect_uri.as_str()),
 		urlencoding::encode(scope),
-		csrf.random()
+		csrf.random(),
+		urlencoding::encode(pkce_challenge.method()),
+		urlencoding::encode(pkce_challenge.as_str()),
 	);
 
 	Ok(redirect_url)
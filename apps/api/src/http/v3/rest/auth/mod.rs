@@ -7,7 +7,7 @@ use axum::extract::State;
 use axum::response::{IntoResponse, Redirect, Response};
 use axum::routing::get;
 use axum::{Extension, Router};
-use hyper::StatusCode;
+use hyper::{HeaderMap, StatusCode};
 use mongodb::bson::doc;
 use shared::database::role::permissions::RateLimitResource;
 use shared::database::user::connection::Platform;
@@ -17,7 +17,7 @@ use crate::global::Global;
 use crate::http::error::{ApiError, ApiErrorCode};
 use crate::http::extract::Query;
 use crate::http::middleware::cookies::Cookies;
-use crate::http::middleware::session::{parse_session, Session, AUTH_COOKIE};
+use crate::http::middleware::session::{parse_session, user_agent, Session, AUTH_COOKIE};
 use crate::ratelimit::RateLimitRequest;
 use crate::transactions::TransactionError;
 
@@ -100,6 +100,7 @@ async fn login(
 	State(global): State<Arc<Global>>,
 	Extension(cookies): Extension<Cookies>,
 	Extension(session): Extension<Session>,
+	headers: HeaderMap,
 	Query(query): Query<LoginRequest>,
 ) -> Result<impl IntoResponse, ApiError> {
 	let session = if let (Some(token), None) = (&query.token, session.user_session()) {
@@ -114,7 +115,7 @@ async fn login(
 
 	req.http(&global, async {
 		let location = if query.callback {
-			handle_login_callback(&global, &session, query, &cookies).await?
+			handle_login_callback(&global, &session, query, &cookies, user_agent(&headers)).await?
 		} else {
 			handle_login(&global, &session, query.platform.into(), query.link_connection, &cookies)?
 		};
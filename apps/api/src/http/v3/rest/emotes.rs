@@ -17,6 +17,7 @@ use shared::database::role::permissions::{EmotePermission, PermissionsExt, RateL
 use shared::database::stored_event::StoredEventEmoteData;
 use shared::database::MongoCollection;
 use shared::event::{InternalEvent, InternalEventData};
+use shared::image_processor::ProcessPriority;
 use shared::old_types::{EmoteFlagsModel, EmotePartialModel, UserPartialModel};
 use tracing::Instrument;
 
@@ -30,12 +31,20 @@ use crate::ratelimit::RateLimitRequest;
 use crate::transactions::{transaction, TransactionError};
 
 #[derive(utoipa::OpenApi)]
-#[openapi(paths(create_emote, get_emote_by_id), components(schemas(XEmoteData)))]
+#[openapi(
+	paths(create_emote, get_emote_by_id, get_emotes_by_id),
+	components(schemas(XEmoteData, EmoteBatchRequest))
+)]
 pub struct Docs;
 
+/// The maximum number of emotes which can be requested at once, the same as
+/// the `emotesByID` GraphQL query.
+const MAX_EMOTE_BATCH_SIZE: usize = 100;
+
 pub fn routes() -> Router<Arc<Global>> {
 	Router::new()
 		.route("/", post(create_emote))
+		.route("/batch", post(get_emotes_by_id))
 		.route("/:id", get(get_emote_by_id))
 }
 
@@ -103,6 +112,11 @@ pub async fn create_emote(
 		return Err(ApiError::bad_request(ApiErrorCode::BadRequest, "invalid tags"));
 	}
 
+	global
+		.image_processor
+		.check_aspect_ratio(&body)
+		.map_err(|e| ApiError::bad_request(ApiErrorCode::BadRequest, e.to_string()))?;
+
 	let req = RateLimitRequest::new(RateLimitResource::ProfilePictureUpload, &session);
 
 	req.http(&global, async {
@@ -141,7 +155,7 @@ pub async fn create_emote(
 
 		let input = match global
 			.image_processor
-			.upload_emote(emote_id, body, Some(session.ip()))
+			.upload_emote(emote_id, body, Some(session.ip()), ProcessPriority::High)
 			.instrument(tracing::info_span!("image_processor_upload"))
 			.await
 		{
@@ -159,6 +173,7 @@ pub async fn create_emote(
 				path: path.path,
 				mime: content_type,
 				size: size as i64,
+				resubmits: 0,
 			},
 			Ok(ProcessImageResponse { error: Some(err), .. }) => {
 				// At this point if we get a decode error then the image is invalid
@@ -199,7 +214,7 @@ pub async fn create_emote(
 			flags |= EmoteFlags::Private;
 		}
 
-		let res = transaction(&global, |mut tx| async move {
+		let res = transaction(&global, "v3.emotes.create", |mut tx| async move {
 			let emote = Emote {
 				id: emote_id,
 				owner_id: authed_user.id,
@@ -212,6 +227,7 @@ pub async fn create_emote(
 				aspect_ratio: -1.0,
 				scores: Default::default(),
 				deleted: false,
+				processing_error: None,
 				search_updated_at: None,
 				updated_at: chrono::Utc::now(),
 			};
@@ -289,3 +305,68 @@ pub async fn get_emote_by_id(
 
 	Ok(Json(EmoteModel::from_db(emote, owner, &global.config.api.cdn_origin)))
 }
+
+#[derive(Debug, serde::Deserialize, utoipa::ToSchema)]
+pub struct EmoteBatchRequest {
+	#[schema(value_type = Vec<String>)]
+	ids: Vec<EmoteId>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/v3/emotes/batch",
+    tag = "emotes",
+    request_body = EmoteBatchRequest,
+    responses(
+        (status = 200, description = "The emotes in the order of the requested ids, null for emotes which do not exist", body = [EmoteModel]),
+        (status = 400, description = "Too many ids"),
+    ),
+)]
+#[tracing::instrument(skip_all, fields(count = body.ids.len()))]
+pub async fn get_emotes_by_id(
+	State(global): State<Arc<Global>>,
+	Extension(session): Extension<Session>,
+	Json(body): Json<EmoteBatchRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+	if body.ids.len() > MAX_EMOTE_BATCH_SIZE {
+		return Err(ApiError::bad_request(
+			ApiErrorCode::BadRequest,
+			format!("at most {MAX_EMOTE_BATCH_SIZE} emotes can be requested at once"),
+		));
+	}
+
+	let req = RateLimitRequest::new(RateLimitResource::Search, &session);
+
+	req.http(&global, async {
+		let emotes = global
+			.emote_by_id_loader
+			.load_many_exclude_deleted(body.ids.iter().copied())
+			.await
+			.map_err(|()| ApiError::internal_server_error(ApiErrorCode::LoadError, "failed to load emotes"))?;
+
+		let owners = global
+			.user_loader
+			.load_fast_many(&global, emotes.values().map(|e| e.owner_id))
+			.await
+			.map_err(|()| ApiError::internal_server_error(ApiErrorCode::LoadError, "failed to load users"))?;
+
+		let emotes = body
+			.ids
+			.iter()
+			.map(|id| {
+				// the same emote can be requested more than once
+				let emote = emotes.get(id).cloned()?;
+
+				let owner = owners
+					.get(&emote.owner_id)
+					.filter(|owner| session.can_view(owner))
+					.map(|owner| UserPartialModel::from_db(owner.clone(), None, None, &global.config.api.cdn_origin));
+
+				Some(EmoteModel::from_db(emote, owner, &global.config.api.cdn_origin))
+			})
+			.collect::<Vec<_>>();
+
+		Ok::<_, ApiError>(Json(emotes))
+	})
+	.await
+}
@@ -107,14 +107,7 @@ impl Plan {
 			SubscriptionProductKind::Yearly => (SubscriptionCycleUnit::Year, Some(0.2)),
 		};
 
-		let (currency, price) =
-			if let Some(price) = regional_currency.and_then(|currency| value.currency_prices.get(&currency)) {
-				(regional_currency.unwrap(), *price)
-			} else if let Some(price) = value.currency_prices.get(&default_currency) {
-				(default_currency, *price)
-			} else {
-				return None;
-			};
+		let (currency, price) = value.price_in(regional_currency.unwrap_or(default_currency), default_currency)?;
 
 		Some(Self {
 			interval_unit,
@@ -1,4 +1,3 @@
-use std::collections::HashMap;
 use std::str::FromStr;
 use std::sync::Arc;
 
@@ -10,30 +9,29 @@ use hyper::StatusCode;
 use image_processor_proto::{self as image_processor, ProcessImageResponse, ProcessImageResponseUploadInfo};
 use mongodb::bson::doc;
 use serde::Deserialize;
-use shared::database::emote::EmoteFlags;
 use shared::database::emote_set::EmoteSetKind;
 use shared::database::image_set::{ImageSet, ImageSetInput};
 use shared::database::queries::{filter, update};
-use shared::database::role::permissions::{FlagPermission, PermissionsExt, RateLimitResource, UserPermission};
+use shared::database::role::permissions::{
+	effective_emote_set_capacity, FlagPermission, PermissionsExt, RateLimitResource, UserPermission,
+};
 use shared::database::user::connection::Platform;
 use shared::database::user::editor::{EditorUserPermission, UserEditorId};
 use shared::database::user::profile_picture::{UserProfilePicture, UserProfilePictureId};
 use shared::database::user::{User, UserId, UserStyle};
 use shared::database::{Id, MongoCollection};
-use shared::event::{
-	EventUserPresencePlatform, InternalEvent, InternalEventData, InternalEventPayload, InternalEventUserPresenceData,
-	InternalEventUserPresenceDataEmoteSet,
-};
+use shared::event::EventUserPresencePlatform;
+use shared::image_processor::ProcessPriority;
 use shared::old_types::{
 	EmoteSetModel, EmoteSetPartialModel, UserConnectionModel, UserConnectionPartialModel, UserEditorModel, UserModel,
 };
 
 use super::types::{PresenceKind, PresenceModel, UserPresencePlatform, UserPresenceWriteRequest};
-use crate::dataloader::emote::EmoteByIdLoaderExt;
 use crate::global::Global;
 use crate::http::error::{ApiError, ApiErrorCode};
 use crate::http::extract::Path;
 use crate::http::middleware::session::Session;
+use crate::http::presence;
 use crate::http::v3::emote_set_loader::load_emote_set;
 use crate::ratelimit::RateLimitRequest;
 
@@ -235,7 +233,13 @@ pub async fn upload_user_profile_picture(
 
 		let input = match global
 			.image_processor
-			.upload_profile_picture(profile_picture_id, target_user.id, body, Some(session.ip()))
+			.upload_profile_picture(
+				profile_picture_id,
+				target_user.id,
+				body,
+				Some(session.ip()),
+				ProcessPriority::High,
+			)
 			.await
 		{
 			Ok(ProcessImageResponse {
@@ -252,6 +256,7 @@ pub async fn upload_user_profile_picture(
 				path: path.path,
 				mime: content_type,
 				size: size as i64,
+				resubmits: 0,
 			},
 			Ok(ProcessImageResponse { error: Some(err), .. }) => {
 				// At this point if we get a decode error then the image is invalid
@@ -360,145 +365,27 @@ pub async fn create_user_presence(
 			));
 		}
 
-		if presence.data.id.is_empty() {
-			return Err(ApiError::bad_request(ApiErrorCode::BadRequest, "missing data.id"));
-		} else if presence.data.id.len() > 100 || !presence.data.id.is_ascii() {
-			return Err(ApiError::bad_request(ApiErrorCode::BadRequest, "data.id is invalid"));
-		}
-
-		let Some(user) = global
-			.user_loader
-			.load(&global, id)
-			.await
-			.map_err(|()| ApiError::internal_server_error(ApiErrorCode::LoadError, "failed to load user"))?
-		else {
-			return Err(ApiError::not_found(ApiErrorCode::LoadError, "user not found"));
+		presence::check_platform_id(&presence.data.id)?;
+
+		let platform = match presence.data.platform {
+			UserPresencePlatform::Twitch => EventUserPresencePlatform::Twitch(
+				presence
+					.data
+					.id
+					.parse()
+					.map_err(|_| ApiError::bad_request(ApiErrorCode::BadRequest, "data.id is not a valid twitch id"))?,
+			),
+			UserPresencePlatform::Kick => EventUserPresencePlatform::Kick(
+				presence
+					.data
+					.id
+					.parse()
+					.map_err(|_| ApiError::bad_request(ApiErrorCode::BadRequest, "data.id is not a valid kick id"))?,
+			),
+			UserPresencePlatform::Youtube => EventUserPresencePlatform::Youtube(presence.data.id),
 		};
 
-		let active_badge = if let Some(id) = user
-			.style
-			.active_badge_id
-			.and_then(|id| user.computed.entitlements.badges.contains(&id).then_some(id))
-		{
-			global
-				.badge_by_id_loader
-				.load(id)
-				.await
-				.map_err(|()| ApiError::internal_server_error(ApiErrorCode::LoadError, "failed to load badge"))?
-		} else {
-			None
-		};
-
-		let active_paint = if let Some(id) = user
-			.style
-			.active_paint_id
-			.and_then(|id| user.computed.entitlements.paints.contains(&id).then_some(id))
-		{
-			global
-				.paint_by_id_loader
-				.load(id)
-				.await
-				.map_err(|()| ApiError::internal_server_error(ApiErrorCode::LoadError, "failed to load paint"))?
-		} else {
-			None
-		};
-
-		let personal_emote_sets = global
-			.emote_set_by_id_loader
-			.load_many(
-				user.computed.entitlements.emote_sets.iter().copied().chain(
-					user.style
-						.personal_emote_set_id
-						.and_then(|id| user.has(UserPermission::UsePersonalEmoteSet).then_some(id)),
-				),
-			)
-			.await
-			.map_err(|()| ApiError::internal_server_error(ApiErrorCode::LoadError, "failed to load emote sets"))?
-			.into_values()
-			.collect::<Vec<_>>();
-
-		let emotes = global
-			.emote_by_id_loader
-			.load_many_merged(
-				personal_emote_sets
-					.iter()
-					.flat_map(|s| s.emotes.iter().map(|e| e.id))
-					.collect::<Vec<_>>(),
-			)
-			.await
-			.map_err(|()| ApiError::internal_server_error(ApiErrorCode::LoadError, "failed to load emotes"))?;
-
-		let owners = global
-			.user_loader
-			.load_fast_many(
-				&global,
-				emotes
-					.emotes
-					.values()
-					.filter(|e| e.flags.contains(EmoteFlags::ApprovedPersonal))
-					.map(|e| e.owner_id),
-			)
-			.await
-			.map_err(|()| ApiError::internal_server_error(ApiErrorCode::LoadError, "failed to load emotes"))?;
-
-		let mut sets = vec![];
-
-		for mut set in personal_emote_sets {
-			let mut set_emotes = Vec::new();
-			let mut emote_owners = HashMap::new();
-
-			set.emotes.retain(|e| emotes.get(e.id).is_some());
-
-			for emote in set.emotes.iter().filter_map(|e| emotes.get(e.id)) {
-				if emote.flags.contains(EmoteFlags::ApprovedPersonal) {
-					set_emotes.push(emote.clone());
-
-					if let Some(owner) = owners.get(&emote.owner_id) {
-						emote_owners.insert(owner.id, owner.clone());
-					}
-				}
-			}
-
-			sets.push(InternalEventUserPresenceDataEmoteSet {
-				emote_set: set,
-				emotes: set_emotes,
-				emote_owners,
-			});
-		}
-
-		let payload = rmp_serde::to_vec_named(&InternalEventPayload::new(Some(InternalEvent {
-			actor: None,
-			session_id: None,
-			data: InternalEventData::UserPresence(Box::new(InternalEventUserPresenceData {
-				user,
-				platform: match presence.data.platform {
-					UserPresencePlatform::Twitch => {
-						EventUserPresencePlatform::Twitch(presence.data.id.parse().map_err(|_| {
-							ApiError::bad_request(ApiErrorCode::BadRequest, "data.id is not a valid twitch id")
-						})?)
-					}
-					UserPresencePlatform::Kick => {
-						EventUserPresencePlatform::Kick(presence.data.id.parse().map_err(|_| {
-							ApiError::bad_request(ApiErrorCode::BadRequest, "data.id is not a valid kick id")
-						})?)
-					}
-					UserPresencePlatform::Youtube => EventUserPresencePlatform::Youtube(presence.data.id),
-				},
-				active_badge,
-				active_paint,
-				personal_emote_sets: sets,
-			})),
-			timestamp: chrono::Utc::now(),
-		})))
-		.map_err(|err| {
-			tracing::error!(error = %err, "failed to serialize event");
-			ApiError::internal_server_error(ApiErrorCode::Unknown, "failed to serialize event")
-		})?;
-
-		global.nats.publish("api.v4.events", payload.into()).await.map_err(|err| {
-			tracing::error!(error = %err, "failed to publish event");
-			ApiError::internal_server_error(ApiErrorCode::Unknown, "failed to publish event")
-		})?;
+		presence::write_presence(&global, id, platform).await?;
 
 		let now = chrono::Utc::now();
 
@@ -566,7 +453,7 @@ pub async fn get_user_by_platform_id(
 	let mut connection_model: UserConnectionModel = UserConnectionPartialModel::from_db(
 		connection.clone(),
 		user.style.active_emote_set_id,
-		user.computed.permissions.emote_set_capacity.unwrap_or_default().max(0),
+		effective_emote_set_capacity(&user.computed.permissions, &EmoteSetKind::Normal),
 	)
 	.into();
 
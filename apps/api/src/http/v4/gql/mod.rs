@@ -6,6 +6,7 @@ use axum::extract::FromRequest;
 use axum::response::{self, IntoResponse};
 use axum::routing::{get, post};
 use axum::{Extension, Router};
+use itertools::Itertools;
 
 use crate::global::Global;
 use crate::http::error::{ApiError, ApiErrorCode};
@@ -26,6 +27,10 @@ pub fn schema(global: Option<Arc<Global>>) -> V4Schema {
 		.extension(extensions::Tracing)
 		.limit_complexity(400); // We don't want to allow too complex queries to be executed
 
+	if let Some(persisted_queries) = global.as_ref().and_then(|g| g.v4_persisted_queries.clone()) {
+		schema = schema.extension(persisted_queries);
+	}
+
 	if let Some(global) = global {
 		schema = schema.data(global);
 	}
@@ -40,6 +45,7 @@ pub fn routes(global: &Arc<Global>) -> Router<Arc<Global>> {
 		.layer(Extension(schema(Some(Arc::clone(global)))))
 }
 
+#[tracing::instrument(skip_all, name = "v4_gql", fields(batch_size, user_id, ip, operation_name))]
 pub async fn graphql_handler(
 	Extension(schema): Extension<V4Schema>,
 	Extension(session): Extension<Session>,
@@ -69,7 +75,20 @@ pub async fn graphql_handler(
 	};
 
 	let batch_size = req.0.iter().count();
-	tracing::Span::current().record("batch_size", batch_size);
+
+	let span = tracing::Span::current();
+	span.record("batch_size", batch_size);
+	span.record("ip", tracing::field::display(session.ip()));
+
+	if let Some(user_id) = session.user_id() {
+		span.record("user_id", tracing::field::display(user_id));
+	}
+
+	let operation_names = req.0.iter().filter_map(|r| r.operation_name.as_deref()).join(",");
+
+	if !operation_names.is_empty() {
+		span.record("operation_name", operation_names.as_str());
+	}
 
 	if let BatchRequest::Single(req) = &req.0 {
 		if req.query == "forsen" {
@@ -233,152 +233,157 @@ impl BillingMutation {
 
 		let stripe_client = global.stripe_client.safe(Id::<()>::new()).await;
 
-		let res = transaction_with_mutex(global, Some(EgVaultMutexKey::User(target_id).into()), |mut tx| {
-			let global = Arc::clone(global);
-
-			async move {
-				let periods = tx
-					.find(
-						filter::filter! {
-							SubscriptionPeriod {
-								#[query(flatten)]
-								subscription_id: SubscriptionId {
-									user_id: target_id,
-									product_id: product_id,
-								},
-							}
-						},
-						None,
-					)
-					.await?;
-
-				let active_period = periods
-					.iter()
-					.find(|p| p.start < chrono::Utc::now() && p.end > chrono::Utc::now())
-					.ok_or(TransactionError::Custom(ApiError::not_found(
-						ApiErrorCode::BadRequest,
-						"subscription not found",
-					)))?;
-
-				let end_date = periods
-					.iter()
-					.max_by_key(|p| p.end)
-					.map(|p| p.end)
-					.unwrap_or(active_period.end);
-
-				match &active_period.provider_id {
-					Some(ProviderSubscriptionId::Stripe(id)) => {
-						stripe::Subscription::update(
-							stripe_client.client("update").await.deref(),
-							id,
-							stripe::UpdateSubscription {
-								cancel_at_period_end: Some(true),
-								..Default::default()
+		let res = transaction_with_mutex(
+			global,
+			"v4.billing.cancel_subscription",
+			Some(EgVaultMutexKey::User(target_id).into()),
+			|mut tx| {
+				let global = Arc::clone(global);
+
+				async move {
+					let periods = tx
+						.find(
+							filter::filter! {
+								SubscriptionPeriod {
+									#[query(flatten)]
+									subscription_id: SubscriptionId {
+										user_id: target_id,
+										product_id: product_id,
+									},
+								}
 							},
+							None,
 						)
-						.await
-						.map_err(|e| {
-							tracing::error!(error = %e, "failed to update stripe subscription");
-							TransactionError::Custom(ApiError::internal_server_error(
-								ApiErrorCode::StripeError,
-								"failed to update stripe subscription",
-							))
-						})?;
-					}
-					Some(ProviderSubscriptionId::Paypal(id)) => {
-						let api_key = paypal_api::api_key(&global).await.map_err(TransactionError::Custom)?;
-
-						// https://developer.paypal.com/docs/api/subscriptions/v1/#subscriptions_cancel
-						let response = global
-							.http_client
-							.post(format!("https://api.paypal.com/v1/billing/subscriptions/{id}/cancel"))
-							.bearer_auth(&api_key)
-							.json(&serde_json::json!({
-								"reason": "Subscription canceled by user"
-							}))
-							.send()
+						.await?;
+
+					let active_period = periods
+						.iter()
+						.find(|p| p.start < chrono::Utc::now() && p.end > chrono::Utc::now())
+						.ok_or(TransactionError::Custom(ApiError::not_found(
+							ApiErrorCode::BadRequest,
+							"subscription not found",
+						)))?;
+
+					let end_date = periods
+						.iter()
+						.max_by_key(|p| p.end)
+						.map(|p| p.end)
+						.unwrap_or(active_period.end);
+
+					match &active_period.provider_id {
+						Some(ProviderSubscriptionId::Stripe(id)) => {
+							stripe::Subscription::update(
+								stripe_client.client("update").await.deref(),
+								id,
+								stripe::UpdateSubscription {
+									cancel_at_period_end: Some(true),
+									..Default::default()
+								},
+							)
 							.await
 							.map_err(|e| {
-								tracing::error!(error = %e, "failed to cancel paypal subscription");
+								tracing::error!(error = %e, "failed to update stripe subscription");
 								TransactionError::Custom(ApiError::internal_server_error(
-									ApiErrorCode::PaypalError,
-									"failed to cancel paypal subscription",
+									ApiErrorCode::StripeError,
+									"failed to update stripe subscription",
 								))
 							})?;
-
-						if !response.status().is_success() {
-							tracing::error!(status = %response.status(), "failed to cancel paypal subscription");
-							return Err(TransactionError::Custom(ApiError::internal_server_error(
-								ApiErrorCode::PaypalError,
-								"failed to cancel paypal subscription",
-							)));
 						}
-					}
-					None => {
-						// This is a gifted or system subscription
-						// End the current period right away
-
-						tx.update_one(
-							filter::filter! {
-								SubscriptionPeriod {
-									#[query(rename = "_id")]
-									id: active_period.id,
-								}
-							},
-							update::update! {
-								#[query(set)]
-								SubscriptionPeriod {
-									end: chrono::Utc::now(),
-									updated_at: chrono::Utc::now(),
-									search_updated_at: &None,
+						Some(ProviderSubscriptionId::Paypal(id)) => {
+							let api_key = paypal_api::api_key(&global).await.map_err(TransactionError::Custom)?;
+
+							// https://developer.paypal.com/docs/api/subscriptions/v1/#subscriptions_cancel
+							let response = global
+								.http_client
+								.post(format!("https://api.paypal.com/v1/billing/subscriptions/{id}/cancel"))
+								.bearer_auth(&api_key)
+								.json(&serde_json::json!({
+									"reason": "Subscription canceled by user"
+								}))
+								.send()
+								.await
+								.map_err(|e| {
+									tracing::error!(error = %e, "failed to cancel paypal subscription");
+									TransactionError::Custom(ApiError::internal_server_error(
+										ApiErrorCode::PaypalError,
+										"failed to cancel paypal subscription",
+									))
+								})?;
+
+							if !response.status().is_success() {
+								tracing::error!(status = %response.status(), "failed to cancel paypal subscription");
+								return Err(TransactionError::Custom(ApiError::internal_server_error(
+									ApiErrorCode::PaypalError,
+									"failed to cancel paypal subscription",
+								)));
+							}
+						}
+						None => {
+							// This is a gifted or system subscription
+							// End the current period right away
+
+							tx.update_one(
+								filter::filter! {
+									SubscriptionPeriod {
+										#[query(rename = "_id")]
+										id: active_period.id,
+									}
 								},
-							},
-							None,
-						)
-						.await?;
+								update::update! {
+									#[query(set)]
+									SubscriptionPeriod {
+										end: chrono::Utc::now(),
+										updated_at: chrono::Utc::now(),
+										search_updated_at: &None,
+									},
+								},
+								None,
+							)
+							.await?;
+						}
 					}
-				}
 
-				// This would get updated by the sub refresh job eventually but we want it to
-				// reflect instantly
-				tx.update_one(
-					filter::filter! {
-						Subscription {
-							#[query(rename = "_id", serde)]
-							id: active_period.subscription_id,
-						}
-					},
-					update::update! {
-						#[query(set)]
-						Subscription {
-							#[query(serde)]
-							state: SubscriptionState::CancelAtEnd,
-							updated_at: chrono::Utc::now(),
-							search_updated_at: &None,
-						}
-					},
-					None,
-				)
-				.await
-				.map_err(|e| {
-					tracing::error!(error = %e, "failed to update subscription");
-					TransactionError::Custom(ApiError::internal_server_error(
-						ApiErrorCode::MutationError,
-						"failed to update subscription",
-					))
-				})?;
-
-				let age = SubAge::new(&periods);
-
-				Ok(SubscriptionInfo {
-					active_period: Some(active_period.clone().into()),
-					end_date: Some(end_date),
-					total_days: age.days,
-					user_id: target_id,
-					periods: periods.into_iter().map(Into::into).collect(),
-				})
-			}
-		})
+					// This would get updated by the sub refresh job eventually but we want it to
+					// reflect instantly
+					tx.update_one(
+						filter::filter! {
+							Subscription {
+								#[query(rename = "_id", serde)]
+								id: active_period.subscription_id,
+							}
+						},
+						update::update! {
+							#[query(set)]
+							Subscription {
+								#[query(serde)]
+								state: SubscriptionState::CancelAtEnd,
+								updated_at: chrono::Utc::now(),
+								search_updated_at: &None,
+							}
+						},
+						None,
+					)
+					.await
+					.map_err(|e| {
+						tracing::error!(error = %e, "failed to update subscription");
+						TransactionError::Custom(ApiError::internal_server_error(
+							ApiErrorCode::MutationError,
+							"failed to update subscription",
+						))
+					})?;
+
+					let age = SubAge::new(&periods);
+
+					Ok(SubscriptionInfo {
+						active_period: Some(active_period.clone().into()),
+						end_date: Some(end_date),
+						total_days: age.days,
+						user_id: target_id,
+						periods: periods.into_iter().map(Into::into).collect(),
+					})
+				}
+			},
+		)
 		.await;
 
 		match res {
@@ -435,99 +440,104 @@ impl BillingMutation {
 
 		let stripe_client = global.stripe_client.safe(Id::<()>::new()).await;
 
-		let res = transaction_with_mutex(global, Some(EgVaultMutexKey::User(target_id).into()), |mut tx| async move {
-			let periods = tx
-				.find(
-					filter::filter! {
-						SubscriptionPeriod {
-							#[query(flatten)]
-							subscription_id: SubscriptionId {
-								user_id: target_id,
-								product_id: product_id,
-							},
-						}
-					},
-					None,
-				)
-				.await?;
-
-			let active_period = periods
-				.iter()
-				.find(|p| p.start < chrono::Utc::now() && p.end > chrono::Utc::now())
-				.ok_or_else(|| {
-					TransactionError::Custom(ApiError::not_found(ApiErrorCode::BadRequest, "subscription not found"))
-				})?;
-
-			let end_date = periods
-				.iter()
-				.max_by_key(|p| p.end)
-				.map(|p| p.end)
-				.unwrap_or(active_period.end);
-
-			match &active_period.provider_id {
-				Some(ProviderSubscriptionId::Stripe(id)) => {
-					stripe::Subscription::update(
-						stripe_client.client("update").await.deref(),
-						id,
-						stripe::UpdateSubscription {
-							cancel_at_period_end: Some(false),
-							..Default::default()
-						},
-					)
-					.await
-					.map_err(|e| {
-						tracing::error!(error = %e, "failed to update stripe subscription");
-						TransactionError::Custom(ApiError::internal_server_error(
-							ApiErrorCode::MutationError,
-							"failed to update stripe subscription",
-						))
-					})?;
-
-					// This would get updated by the sub refresh job eventually but we want it to
-					// reflect instantly
-					tx.update_one(
+		let res = transaction_with_mutex(
+			global,
+			"v4.billing.reactivate_subscription",
+			Some(EgVaultMutexKey::User(target_id).into()),
+			|mut tx| async move {
+				let periods = tx
+					.find(
 						filter::filter! {
-							Subscription {
-								#[query(rename = "_id", serde)]
-								id: active_period.subscription_id,
-							}
-						},
-						update::update! {
-							#[query(set)]
-							Subscription {
-								#[query(serde)]
-								state: SubscriptionState::Active,
-								updated_at: chrono::Utc::now(),
-								search_updated_at: &None,
+							SubscriptionPeriod {
+								#[query(flatten)]
+								subscription_id: SubscriptionId {
+									user_id: target_id,
+									product_id: product_id,
+								},
 							}
 						},
 						None,
 					)
-					.await
-					.map_err(|e| {
-						tracing::error!(error = %e, "failed to update subscription");
-						TransactionError::Custom(ApiError::internal_server_error(
-							ApiErrorCode::MutationError,
-							"failed to update subscription",
-						))
+					.await?;
+
+				let active_period = periods
+					.iter()
+					.find(|p| p.start < chrono::Utc::now() && p.end > chrono::Utc::now())
+					.ok_or_else(|| {
+						TransactionError::Custom(ApiError::not_found(ApiErrorCode::BadRequest, "subscription not found"))
 					})?;
 
-					let age = SubAge::new(&periods);
+				let end_date = periods
+					.iter()
+					.max_by_key(|p| p.end)
+					.map(|p| p.end)
+					.unwrap_or(active_period.end);
 
-					Ok(SubscriptionInfo {
-						active_period: Some(active_period.clone().into()),
-						end_date: Some(end_date),
-						total_days: age.days,
-						user_id: target_id,
-						periods: periods.into_iter().map(Into::into).collect(),
-					})
+				match &active_period.provider_id {
+					Some(ProviderSubscriptionId::Stripe(id)) => {
+						stripe::Subscription::update(
+							stripe_client.client("update").await.deref(),
+							id,
+							stripe::UpdateSubscription {
+								cancel_at_period_end: Some(false),
+								..Default::default()
+							},
+						)
+						.await
+						.map_err(|e| {
+							tracing::error!(error = %e, "failed to update stripe subscription");
+							TransactionError::Custom(ApiError::internal_server_error(
+								ApiErrorCode::MutationError,
+								"failed to update stripe subscription",
+							))
+						})?;
+
+						// This would get updated by the sub refresh job eventually but we want it to
+						// reflect instantly
+						tx.update_one(
+							filter::filter! {
+								Subscription {
+									#[query(rename = "_id", serde)]
+									id: active_period.subscription_id,
+								}
+							},
+							update::update! {
+								#[query(set)]
+								Subscription {
+									#[query(serde)]
+									state: SubscriptionState::Active,
+									updated_at: chrono::Utc::now(),
+									search_updated_at: &None,
+								}
+							},
+							None,
+						)
+						.await
+						.map_err(|e| {
+							tracing::error!(error = %e, "failed to update subscription");
+							TransactionError::Custom(ApiError::internal_server_error(
+								ApiErrorCode::MutationError,
+								"failed to update subscription",
+							))
+						})?;
+
+						let age = SubAge::new(&periods);
+
+						Ok(SubscriptionInfo {
+							active_period: Some(active_period.clone().into()),
+							end_date: Some(end_date),
+							total_days: age.days,
+							user_id: target_id,
+							periods: periods.into_iter().map(Into::into).collect(),
+						})
+					}
+					_ => Err(TransactionError::Custom(ApiError::not_implemented(
+						ApiErrorCode::BadRequest,
+						"this subscription cannot be reactivated",
+					))),
 				}
-				_ => Err(TransactionError::Custom(ApiError::not_implemented(
-					ApiErrorCode::BadRequest,
-					"this subscription cannot be reactivated",
-				))),
-			}
-		})
+			},
+		)
 		.await;
 
 		match res {
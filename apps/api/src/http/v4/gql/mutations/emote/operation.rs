@@ -2,7 +2,7 @@ use std::sync::Arc;
 
 use async_graphql::Context;
 use mongodb::options::{FindOneAndUpdateOptions, ReturnDocument};
-use shared::database::emote::{EmoteFlags, EmoteId, EmoteMerged};
+use shared::database::emote::{EmoteFlags, EmoteId};
 use shared::database::emote_moderation_request::{
 	EmoteModerationRequest, EmoteModerationRequestKind, EmoteModerationRequestStatus,
 };
@@ -17,6 +17,7 @@ use shared::event::{InternalEvent, InternalEventData};
 
 use super::EmoteFlagsInput;
 use crate::global::Global;
+use crate::http::emote::{check_delete, merge_emote_locked};
 use crate::http::error::{ApiError, ApiErrorCode};
 use crate::http::guards::{PermissionGuard, RateLimitGuard};
 use crate::http::middleware::session::Session;
@@ -115,6 +116,7 @@ impl EmoteOperation {
 
 		let res = transaction_with_mutex(
 			global,
+			"v4.emote.name",
 			Some(GeneralMutexKey::Emote(self.emote.id).into()),
 			|mut tx| async move {
 				let emote = tx
@@ -205,6 +207,7 @@ impl EmoteOperation {
 
 		let res = transaction_with_mutex(
 			global,
+			"v4.emote.flags",
 			Some(GeneralMutexKey::Emote(self.emote.id).into()),
 			|mut tx| async move {
 				// Resolve emote moderation request if user has permission to manage them
@@ -361,6 +364,7 @@ impl EmoteOperation {
 
 		let res = transaction_with_mutex(
 			global,
+			"v4.emote.owner",
 			Some(GeneralMutexKey::Emote(self.emote.id).into()),
 			|mut tx| async move {
 				let emote = tx
@@ -441,6 +445,7 @@ impl EmoteOperation {
 
 		let res = transaction_with_mutex(
 			global,
+			"v4.emote.tags",
 			Some(GeneralMutexKey::Emote(self.emote.id).into()),
 			|mut tx| async move {
 				let emote = tx
@@ -509,73 +514,8 @@ impl EmoteOperation {
 		let session = ctx
 			.data::<Session>()
 			.map_err(|_| ApiError::internal_server_error(ApiErrorCode::MissingContext, "missing sesion data"))?;
-		let authed_user = session.user()?;
 
-		let res = transaction_with_mutex(
-			global,
-			Some(GeneralMutexKey::Emote(self.emote.id).into()),
-			|mut tx| async move {
-				let emote = tx
-					.find_one_and_update(
-						filter::filter! {
-							shared::database::emote::Emote {
-								#[query(rename = "_id")]
-								id: self.emote.id,
-							}
-						},
-						update::update! {
-							#[query(set)]
-							shared::database::emote::Emote {
-								#[query(serde)]
-								merged: EmoteMerged {
-									target_id,
-									at: chrono::Utc::now(),
-								},
-								updated_at: chrono::Utc::now(),
-								search_updated_at: &None,
-							}
-						},
-						None,
-					)
-					.await?
-					.ok_or_else(|| ApiError::not_found(ApiErrorCode::LoadError, "emote not found"))
-					.map_err(TransactionError::Custom)?;
-
-				tx.update(
-					filter::filter! {
-						EmoteModerationRequest {
-							emote_id: self.emote.id,
-							#[query(serde)]
-							status: EmoteModerationRequestStatus::Pending,
-						}
-					},
-					update::update! {
-						#[query(set)]
-						EmoteModerationRequest {
-							#[query(serde)]
-							status: EmoteModerationRequestStatus::EmoteDeleted,
-						}
-					},
-					None,
-				)
-				.await?;
-
-				tx.register_event(InternalEvent {
-					actor: Some(authed_user.clone()),
-					session_id: session.user_session_id(),
-					data: InternalEventData::Emote {
-						after: emote.clone(),
-						data: StoredEventEmoteData::Merge { new_emote_id: target_id },
-					},
-					timestamp: chrono::Utc::now(),
-				})?;
-
-				// TODO: schedule emote merge job
-
-				Ok(emote)
-			},
-		)
-		.await;
+		let res = merge_emote_locked(global, "v4.emote.merge", session, self.emote.id, target_id).await;
 
 		match res {
 			Ok(emote) => Ok(Emote::from_db(emote, &global.config.api.cdn_origin)),
@@ -609,8 +549,11 @@ impl EmoteOperation {
 
 		let res = transaction_with_mutex(
 			global,
+			"v4.emote.delete",
 			Some(GeneralMutexKey::Emote(self.emote.id).into()),
 			|mut tx| async move {
+				check_delete(global, &mut tx, authed_user, self.emote.id).await?;
+
 				let emote = tx
 					.find_one_and_update(
 						filter::filter! {
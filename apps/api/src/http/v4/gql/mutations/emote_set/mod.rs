@@ -3,17 +3,19 @@ use std::sync::Arc;
 use async_graphql::Context;
 use shared::database::emote_set::{EmoteSetId, EmoteSetKind};
 use shared::database::queries::filter;
-use shared::database::role::permissions::{EmoteSetPermission, PermissionsExt, RateLimitResource};
+use shared::database::role::permissions::{
+	effective_emote_set_capacity, EmotePermission, EmoteSetPermission, PermissionsExt, RateLimitResource,
+};
 use shared::database::user::editor::{EditorEmoteSetPermission, UserEditorId, UserEditorState};
 use shared::database::user::UserId;
 use shared::event::{InternalEvent, InternalEventData, InternalEventEmoteSetData};
 
 use crate::global::Global;
+use crate::http::emote_set;
 use crate::http::error::{ApiError, ApiErrorCode};
 use crate::http::guards::{PermissionGuard, RateLimitGuard};
 use crate::http::middleware::session::Session;
-use crate::http::v4::gql::types::EmoteSet;
-use crate::http::validators::{NameValidator, TagsValidator};
+use crate::http::v4::gql::types::{self, EmoteSet};
 use crate::transactions::{transaction, TransactionError};
 
 mod operation;
@@ -21,6 +23,14 @@ mod operation;
 #[derive(Default)]
 pub struct EmoteSetMutation;
 
+#[derive(async_graphql::SimpleObject)]
+pub struct CopyEmoteSetResponse {
+	pub emote_set: EmoteSet,
+	/// The number of emotes which were not copied because they are not
+	/// available to the user or did not fit into the new set.
+	pub skipped_emote_count: u32,
+}
+
 #[async_graphql::Object]
 impl EmoteSetMutation {
 	#[tracing::instrument(skip_all, name = "EmoteSetMutation::emote_set")]
@@ -46,9 +56,10 @@ impl EmoteSetMutation {
 	async fn create(
 		&self,
 		ctx: &Context<'_>,
-		#[graphql(validator(custom = "NameValidator"))] name: String,
-		#[graphql(validator(custom = "TagsValidator"))] tags: Vec<String>,
+		name: String,
+		tags: Vec<String>,
 		owner_id: Option<UserId>,
+		kind: Option<types::EmoteSetKind>,
 	) -> Result<EmoteSet, ApiError> {
 		let global: &Arc<Global> = ctx
 			.data()
@@ -58,6 +69,33 @@ impl EmoteSetMutation {
 			.map_err(|_| ApiError::internal_server_error(ApiErrorCode::MissingContext, "missing sesion data"))?;
 		let authed_user = session.user()?;
 
+		emote_set::check_name(&name)?;
+		emote_set::check_tags(&tags)?;
+
+		let kind = kind.map(EmoteSetKind::from).unwrap_or(EmoteSetKind::Normal);
+
+		match kind {
+			EmoteSetKind::Global if !authed_user.has(EmoteSetPermission::ManageGlobal) => {
+				return Err(ApiError::forbidden(
+					ApiErrorCode::LackingPrivileges,
+					"this user does not have permission to create global emote sets",
+				));
+			}
+			EmoteSetKind::Special if !authed_user.has(EmoteSetPermission::ManageSpecial) => {
+				return Err(ApiError::forbidden(
+					ApiErrorCode::LackingPrivileges,
+					"this user does not have permission to create special emote sets",
+				));
+			}
+			EmoteSetKind::Personal => {
+				return Err(ApiError::bad_request(
+					ApiErrorCode::BadRequest,
+					"personal emote sets can not be created",
+				));
+			}
+			EmoteSetKind::Normal | EmoteSetKind::Global | EmoteSetKind::Special => {}
+		}
+
 		let owner_id = owner_id.unwrap_or(authed_user.id);
 
 		let owner = if owner_id == authed_user.id {
@@ -105,7 +143,7 @@ impl EmoteSetMutation {
 			}
 		}
 
-		let capacity = target.computed.permissions.emote_set_capacity.unwrap_or_default().max(0);
+		let capacity = effective_emote_set_capacity(&target.computed.permissions, &EmoteSetKind::Normal);
 
 		if capacity == 0 {
 			return Err(ApiError::bad_request(
@@ -114,7 +152,7 @@ impl EmoteSetMutation {
 			));
 		}
 
-		let res = transaction(global, |mut tx| async move {
+		let res = transaction(global, "v4.emote_set.create", |mut tx| async move {
 			let emote_set_count = tx
 				.count(
 					filter::filter! {
@@ -140,7 +178,7 @@ impl EmoteSetMutation {
 				capacity: Some(capacity),
 				description: None,
 				emotes: vec![],
-				kind: EmoteSetKind::Normal,
+				kind,
 				origin_config: None,
 				tags,
 				updated_at: chrono::Utc::now(),
@@ -177,4 +215,120 @@ impl EmoteSetMutation {
 			}
 		}
 	}
+
+	/// Creates a new emote set owned by the user with the emotes of another
+	/// set.
+	#[graphql(
+		guard = "PermissionGuard::one(EmoteSetPermission::Manage).and(RateLimitGuard::new(RateLimitResource::EmoteSetCreate, 1))"
+	)]
+	#[tracing::instrument(skip_all, name = "EmoteSetMutation::copy")]
+	async fn copy(&self, ctx: &Context<'_>, id: EmoteSetId, name: Option<String>) -> Result<CopyEmoteSetResponse, ApiError> {
+		let global: &Arc<Global> = ctx
+			.data()
+			.map_err(|_| ApiError::internal_server_error(ApiErrorCode::MissingContext, "missing global data"))?;
+		let session = ctx
+			.data::<Session>()
+			.map_err(|_| ApiError::internal_server_error(ApiErrorCode::MissingContext, "missing sesion data"))?;
+		let authed_user = session.user()?;
+
+		// Emote sets can be read by anyone, so any set can be copied.
+		let source = global
+			.emote_set_by_id_loader
+			.load(id)
+			.await
+			.map_err(|()| ApiError::internal_server_error(ApiErrorCode::LoadError, "failed to load emote set"))?
+			.ok_or_else(|| ApiError::not_found(ApiErrorCode::LoadError, "emote set not found"))?;
+
+		let name = name.unwrap_or_else(|| source.name.clone());
+		emote_set::check_name(&name)?;
+
+		let capacity = effective_emote_set_capacity(&authed_user.computed.permissions, &EmoteSetKind::Normal);
+
+		if capacity == 0 {
+			return Err(ApiError::bad_request(
+				ApiErrorCode::LackingPrivileges,
+				"maximum emote set capacity is 0, cannot create emote set",
+			));
+		}
+
+		let emotes = global
+			.emote_by_id_loader
+			.load_many(source.emotes.iter().map(|e| e.id))
+			.await
+			.map_err(|()| ApiError::internal_server_error(ApiErrorCode::LoadError, "failed to load emotes"))?;
+
+		let (emotes, skipped) = emote_set::copy_emotes(
+			&source.emotes,
+			&emotes,
+			authed_user.id,
+			authed_user.has(EmotePermission::ViewUnlisted),
+			capacity as usize,
+		);
+
+		let res = transaction(global, "v4.emote_set.copy", |mut tx| async move {
+			let emote_set_count = tx
+				.count(
+					filter::filter! {
+						shared::database::emote_set::EmoteSet {
+							owner_id: Some(authed_user.id),
+						}
+					},
+					None,
+				)
+				.await?;
+
+			if emote_set_count >= (authed_user.computed.permissions.emote_set_limit.unwrap_or(0).max(0) as u64) {
+				return Err(TransactionError::Custom(ApiError::bad_request(
+					ApiErrorCode::LackingPrivileges,
+					"maximum emote set limit reached",
+				)));
+			}
+
+			let emote_set = shared::database::emote_set::EmoteSet {
+				id: Default::default(),
+				owner_id: Some(authed_user.id),
+				name,
+				capacity: Some(capacity),
+				description: source.description,
+				emotes,
+				kind: EmoteSetKind::Normal,
+				origin_config: None,
+				tags: source.tags,
+				updated_at: chrono::Utc::now(),
+				search_updated_at: None,
+				emotes_changed_since_reindex: false,
+			};
+
+			tx.insert_one::<shared::database::emote_set::EmoteSet>(&emote_set, None)
+				.await?;
+
+			tx.register_event(InternalEvent {
+				actor: Some(authed_user.clone()),
+				session_id: session.user_session_id(),
+				data: InternalEventData::EmoteSet {
+					after: emote_set.clone(),
+					data: InternalEventEmoteSetData::Create,
+				},
+				timestamp: chrono::Utc::now(),
+			})?;
+
+			Ok(emote_set)
+		})
+		.await;
+
+		match res {
+			Ok(emote_set) => Ok(CopyEmoteSetResponse {
+				emote_set: emote_set.into(),
+				skipped_emote_count: skipped as u32,
+			}),
+			Err(TransactionError::Custom(e)) => Err(e),
+			Err(e) => {
+				tracing::error!(error = %e, "transaction failed");
+				Err(ApiError::internal_server_error(
+					ApiErrorCode::TransactionError,
+					"transaction failed",
+				))
+			}
+		}
+	}
 }
@@ -8,7 +8,9 @@ use shared::database::emote_moderation_request::{
 };
 use shared::database::emote_set::{EmoteSetEmoteFlag, EmoteSetKind};
 use shared::database::queries::{filter, update};
-use shared::database::role::permissions::{EmoteSetPermission, PermissionsExt, RateLimitResource, UserPermission};
+use shared::database::role::permissions::{
+	effective_emote_set_capacity, EmoteSetPermission, PermissionsExt, RateLimitResource, UserPermission,
+};
 use shared::database::stored_event::StoredEventEmoteModerationRequestData;
 use shared::database::user::editor::{
 	EditorEmoteSetPermission, EditorPermission, EditorUserPermission, UserEditorId, UserEditorState,
@@ -16,13 +18,13 @@ use shared::database::user::editor::{
 use shared::database::user::FullUserRef;
 use shared::event::{InternalEvent, InternalEventData, InternalEventEmoteSetData};
 
-use crate::dataloader::emote::EmoteByIdLoaderExt;
 use crate::global::Global;
+use crate::http::emote_set;
 use crate::http::error::{ApiError, ApiErrorCode};
 use crate::http::guards::{PermissionGuard, RateLimitGuard};
 use crate::http::middleware::session::Session;
 use crate::http::v4::gql::types::{Emote, EmoteSet, EmoteSetEmote};
-use crate::http::validators::{EmoteNameValidator, NameValidator, TagsValidator};
+use crate::http::validators::EmoteNameValidator;
 use crate::transactions::{transaction_with_mutex, GeneralMutexKey, TransactionError};
 
 pub struct EmoteSetOperation {
@@ -159,11 +161,7 @@ impl EmoteSetOperation {
 		guard = "PermissionGuard::one(EmoteSetPermission::Manage).and(RateLimitGuard::new(RateLimitResource::EmoteSetChange, 1))"
 	)]
 	#[tracing::instrument(skip_all, name = "EmoteSetOperation::name")]
-	async fn name(
-		&self,
-		ctx: &Context<'_>,
-		#[graphql(validator(custom = "NameValidator"))] name: String,
-	) -> Result<EmoteSet, ApiError> {
+	async fn name(&self, ctx: &Context<'_>, name: String) -> Result<EmoteSet, ApiError> {
 		let global: &Arc<Global> = ctx
 			.data()
 			.map_err(|_| ApiError::internal_server_error(ApiErrorCode::MissingContext, "missing global data"))?;
@@ -172,6 +170,8 @@ impl EmoteSetOperation {
 			.map_err(|_| ApiError::internal_server_error(ApiErrorCode::MissingContext, "missing sesion data"))?;
 		let authed_user = sesison.user()?;
 
+		emote_set::check_name(&name)?;
+
 		self.check_perms(global, sesison, EditorEmoteSetPermission::Manage).await?;
 
 		if self.emote_set.name == name {
@@ -180,6 +180,7 @@ impl EmoteSetOperation {
 
 		let res = transaction_with_mutex(
 			global,
+			"v4.emote_set.name",
 			Some(GeneralMutexKey::EmoteSet(self.emote_set.id).into()),
 			|mut tx| async move {
 				let emote_set = tx
@@ -245,11 +246,7 @@ impl EmoteSetOperation {
 		guard = "PermissionGuard::one(EmoteSetPermission::Manage).and(RateLimitGuard::new(RateLimitResource::EmoteSetChange, 1))"
 	)]
 	#[tracing::instrument(skip_all, name = "EmoteSetOperation::name")]
-	async fn tags(
-		&self,
-		ctx: &Context<'_>,
-		#[graphql(validator(custom = "TagsValidator"))] tags: Vec<String>,
-	) -> Result<EmoteSet, ApiError> {
+	async fn tags(&self, ctx: &Context<'_>, tags: Vec<String>) -> Result<EmoteSet, ApiError> {
 		let global: &Arc<Global> = ctx
 			.data()
 			.map_err(|_| ApiError::internal_server_error(ApiErrorCode::MissingContext, "missing global data"))?;
@@ -258,6 +255,8 @@ impl EmoteSetOperation {
 			.map_err(|_| ApiError::internal_server_error(ApiErrorCode::MissingContext, "missing sesion data"))?;
 		let authed_user = sesison.user()?;
 
+		emote_set::check_tags(&tags)?;
+
 		self.check_perms(global, sesison, EditorEmoteSetPermission::Manage).await?;
 
 		if self.emote_set.tags == tags {
@@ -266,6 +265,7 @@ impl EmoteSetOperation {
 
 		let res = transaction_with_mutex(
 			global,
+			"v4.emote_set.tags",
 			Some(GeneralMutexKey::EmoteSet(self.emote_set.id).into()),
 			|mut tx| async move {
 				let emote_set = tx
@@ -353,13 +353,7 @@ impl EmoteSetOperation {
 			));
 		}
 
-		let max_capacity = if self.emote_set.kind == EmoteSetKind::Personal {
-			target.computed.permissions.personal_emote_set_capacity
-		} else {
-			target.computed.permissions.emote_set_capacity
-		};
-
-		if capacity > max_capacity.unwrap_or_default().max(0) {
+		if capacity > effective_emote_set_capacity(&target.computed.permissions, &self.emote_set.kind) {
 			return Err(ApiError::bad_request(
 				ApiErrorCode::LackingPrivileges,
 				"emote set capacity cannot exceed user's capacity",
@@ -372,6 +366,7 @@ impl EmoteSetOperation {
 
 		let res = transaction_with_mutex(
 			global,
+			"v4.emote_set.capacity",
 			Some(GeneralMutexKey::EmoteSet(self.emote_set.id).into()),
 			|mut tx| async move {
 				let emote_set = tx
@@ -455,6 +450,7 @@ impl EmoteSetOperation {
 
 		let res = transaction_with_mutex(
 			global,
+			"v4.emote_set.add_emote",
 			Some(GeneralMutexKey::EmoteSet(self.emote_set.id).into()),
 			|mut tx| async move {
 				let emote_set = tx
@@ -495,6 +491,8 @@ impl EmoteSetOperation {
 					)));
 				}
 
+				emote_set::check_duplicate_emote(&emote_set.emotes, id.emote_id).map_err(TransactionError::Custom)?;
+
 				let alias = id.alias.unwrap_or_else(|| db_emote.default_name.clone());
 
 				// This may be a problem if the emote has been deleted.
@@ -512,14 +510,15 @@ impl EmoteSetOperation {
 						))
 					})?;
 
-				let conflict_emote_idx = emote_set.emotes.iter().position(|e| e.alias == alias);
+				let conflict_emote_idx = emote_set::find_alias_conflict(&emote_set.emotes, &alias, None);
 
 				if let Some(conflict_emote_idx) = conflict_emote_idx {
-					if let Some(emote) = emotes.get(&emote_set.emotes[conflict_emote_idx].id) {
+					let conflict = &emote_set.emotes[conflict_emote_idx];
+					if let Some(emote) = emotes.get(&conflict.id) {
 						if !emote.deleted {
 							return Err(TransactionError::Custom(ApiError::conflict(
 								ApiErrorCode::BadRequest,
-								"this emote has a conflicting name",
+								format!("this emote has a conflicting name with {} ({})", conflict.alias, conflict.id),
 							)));
 						}
 					}
@@ -690,31 +689,9 @@ impl EmoteSetOperation {
 						TransactionError::Custom(ApiError::not_found(ApiErrorCode::LoadError, "emote set not found"))
 					})?;
 
-				if let Some(capacity) = emote_set.capacity {
-					// Unfortunately we actually need to load all these emotes to check the deleted
-					// status to determine if they contribute towards the capacity limit
-					// Perhaps we could cache this in redis or something (the merge/deleted status
-					// of an emote at any given time to avoid doing a DB lookup)
-					let emotes = global
-						.emote_by_id_loader
-						.load_many_merged(emote_set.emotes.iter().map(|e| e.id))
-						.await
-						.map_err(|()| {
-							TransactionError::Custom(ApiError::internal_server_error(
-								ApiErrorCode::LoadError,
-								"failed to load emotes",
-							))
-						})?;
-
-					let active_emotes = emote_set.emotes.iter().filter(|e| emotes.get(e.id).is_some()).count();
-
-					if active_emotes as i32 > capacity {
-						return Err(TransactionError::Custom(ApiError::bad_request(
-							ApiErrorCode::LoadError,
-							"emote set is at capacity",
-						)));
-					}
-				}
+				emote_set::check_capacity(global, &emote_set)
+					.await
+					.map_err(TransactionError::Custom)?;
 
 				let emote_owner = global.user_loader.load_fast(global, db_emote.owner_id).await.map_err(|_| {
 					TransactionError::Custom(ApiError::internal_server_error(
@@ -771,6 +748,7 @@ impl EmoteSetOperation {
 
 		let res = transaction_with_mutex(
 			global,
+			"v4.emote_set.remove_emote",
 			Some(GeneralMutexKey::EmoteSet(self.emote_set.id).into()),
 			|mut tx| async move {
 				let authed_user = session.user().map_err(TransactionError::Custom)?;
@@ -935,18 +913,29 @@ impl EmoteSetOperation {
 
 		let res = transaction_with_mutex(
 			global,
+			"v4.emote_set.update_emote_alias",
 			Some(GeneralMutexKey::EmoteSet(self.emote_set.id).into()),
 			|mut tx| async move {
 				let authed_user = session.user().map_err(TransactionError::Custom)?;
 
-				let old_emote_set_emote = self
-					.emote_set
+				let current_emote_set = tx
+					.find_one(
+						filter::filter! { shared::database::emote_set::EmoteSet { #[query(rename = "_id")] id: self.emote_set.id } },
+						None,
+					)
+					.await?
+					.ok_or_else(|| {
+						TransactionError::Custom(ApiError::not_found(ApiErrorCode::BadRequest, "emote set not found"))
+					})?;
+
+				let old_emote_set_emote_idx = current_emote_set
 					.emotes
 					.iter()
-					.find(|e| e.id == id.emote_id && id.alias.as_ref().is_none_or(|a| e.alias == *a))
+					.position(|e| e.id == id.emote_id && id.alias.as_ref().is_none_or(|a| e.alias == *a))
 					.ok_or_else(|| {
 						TransactionError::Custom(ApiError::not_found(ApiErrorCode::BadRequest, "emote not found in set"))
 					})?;
+				let old_emote_set_emote = &current_emote_set.emotes[old_emote_set_emote_idx];
 
 				let emote = tx
 					.find_one(
@@ -965,10 +954,13 @@ impl EmoteSetOperation {
 					)));
 				}
 
-				if self.emote_set.emotes.iter().any(|e| e.alias == alias) {
+				if let Some(conflict_idx) =
+					emote_set::find_alias_conflict(&current_emote_set.emotes, &alias, Some(old_emote_set_emote_idx))
+				{
+					let conflict = &current_emote_set.emotes[conflict_idx];
 					return Err(TransactionError::Custom(ApiError::conflict(
 						ApiErrorCode::BadRequest,
-						"emote name conflict",
+						format!("emote name conflict with {} ({})", conflict.alias, conflict.id),
 					)));
 				}
 
@@ -1083,6 +1075,7 @@ impl EmoteSetOperation {
 
 		let res = transaction_with_mutex(
 			global,
+			"v4.emote_set.update_emote_flags",
 			Some(GeneralMutexKey::EmoteSet(self.emote_set.id).into()),
 			|mut tx| async move {
 				session.user().map_err(TransactionError::Custom)?;
@@ -1211,6 +1204,7 @@ impl EmoteSetOperation {
 
 		let res = transaction_with_mutex(
 			global,
+			"v4.emote_set.delete",
 			Some(GeneralMutexKey::EmoteSet(self.emote_set.id).into()),
 			|mut tx| async move {
 				let emote_set = tx
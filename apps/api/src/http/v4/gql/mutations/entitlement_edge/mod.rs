@@ -2,6 +2,8 @@ use std::sync::Arc;
 
 use async_graphql::Context;
 use shared::database::entitlement::EntitlementEdgeKind;
+use shared::database::entitlement_edge::EntitlementEdgeGraphTraverse;
+use shared::database::graph::{Direction, GraphTraverse};
 use shared::database::queries::filter;
 use shared::database::role::permissions::AdminPermission;
 use shared::database::MongoCollection;
@@ -11,9 +13,14 @@ use crate::http::error::{ApiError, ApiErrorCode};
 use crate::http::guards::PermissionGuard;
 use crate::http::v4::gql::types::raw_entitlement::EntitlementNodeInput;
 use crate::http::v4::gql::types::{EntitlementEdge, EntitlementNodeAny};
+use crate::transactions::{transaction_with_mutex, GeneralMutexKey, TransactionError};
 
 mod operation;
 
+/// How many edges deep the existing graph is searched for a path back to the
+/// source of a new edge.
+const MAX_CYCLE_SEARCH_DEPTH: usize = 32;
+
 #[derive(Default)]
 pub struct EntitlementEdgeMutation;
 
@@ -63,20 +70,86 @@ impl EntitlementEdgeMutation {
 		ctx: &Context<'_>,
 		from: EntitlementNodeInput,
 		to: EntitlementNodeInput,
+		expires_at: Option<chrono::DateTime<chrono::Utc>>,
 	) -> Result<EntitlementEdge<EntitlementNodeAny, EntitlementNodeAny>, ApiError> {
 		let global: &Arc<Global> = ctx
 			.data()
 			.map_err(|_| ApiError::internal_server_error(ApiErrorCode::MissingContext, "missing global data"))?;
 
-		let edge = shared::database::entitlement::EntitlementEdge::new(from.into(), to.into(), None);
+		let mut edge = shared::database::entitlement::EntitlementEdge::new(from.into(), to.into(), None);
 
-		shared::database::entitlement::EntitlementEdge::collection(&global.db)
-			.insert_one(&edge)
-			.await
-			.map_err(|e| {
-				tracing::error!(error = %e, "failed to create entitlement edge");
-				ApiError::internal_server_error(ApiErrorCode::MutationError, "failed to create entitlement edge")
-			})?;
+		if let Some(expires_at) = expires_at {
+			if expires_at <= chrono::Utc::now() {
+				return Err(ApiError::bad_request(
+					ApiErrorCode::BadRequest,
+					"expires_at must be in the future",
+				));
+			}
+
+			edge = edge.with_expiry(expires_at);
+		}
+
+		// the mutex keeps a concurrent create from closing a cycle between the
+		// check and the insert
+		let res = transaction_with_mutex(
+			global,
+			"v4.entitlement_edge.create",
+			Some(GeneralMutexKey::EntitlementGraph.into()),
+			|mut tx| async move {
+				let traverse = EntitlementEdgeGraphTraverse {
+					inbound_loader: &global.entitlement_edge_inbound_loader,
+					outbound_loader: &global.entitlement_edge_outbound_loader,
+				};
+
+				// a path from `to` back to `from` means the new edge closes a cycle
+				let cycle = traverse
+					.find_path(
+						Direction::Outbound,
+						edge.id.to.clone(),
+						edge.id.from.clone(),
+						MAX_CYCLE_SEARCH_DEPTH,
+					)
+					.await
+					.map_err(|()| {
+						TransactionError::Custom(ApiError::internal_server_error(
+							ApiErrorCode::LoadError,
+							"failed to load entitlement edges",
+						))
+					})?;
+
+				if let Some(cycle) = cycle {
+					let cycle = std::iter::once(&edge.id.from)
+						.chain(cycle.iter())
+						.map(|kind| kind.to_string())
+						.collect::<Vec<_>>()
+						.join(" -> ");
+
+					return Err(TransactionError::Custom(ApiError::bad_request(
+						ApiErrorCode::BadRequest,
+						format!("entitlement edge would create a cycle: {cycle}"),
+					)));
+				}
+
+				// the cached permissions of the affected users are invalidated on commit
+				tx.insert_one::<shared::database::entitlement::EntitlementEdge>(&edge, None)
+					.await?;
+
+				Ok(edge)
+			},
+		)
+		.await;
+
+		let edge = match res {
+			Ok(edge) => edge,
+			Err(TransactionError::Custom(e)) => return Err(e),
+			Err(e) => {
+				tracing::error!(error = %e, "transaction failed");
+				return Err(ApiError::internal_server_error(
+					ApiErrorCode::TransactionError,
+					"transaction failed",
+				));
+			}
+		};
 
 		Ok(EntitlementEdge::from_db(&edge))
 	}
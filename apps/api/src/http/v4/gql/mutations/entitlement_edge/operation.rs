@@ -5,6 +5,7 @@ use shared::database::queries::filter;
 use shared::database::role::permissions::AdminPermission;
 use shared::database::MongoCollection;
 
+use crate::dataloader::user_computed_cache;
 use crate::global::Global;
 use crate::http::error::{ApiError, ApiErrorCode};
 use crate::http::guards::PermissionGuard;
@@ -35,6 +36,10 @@ impl EntitlementEdgeOperation {
 				ApiError::internal_server_error(ApiErrorCode::MutationError, "failed to delete entitlement edge")
 			})?;
 
+		if let Err(err) = user_computed_cache::invalidate_edge(global, &self.edge.id.from).await {
+			tracing::error!(error = %err, "failed to invalidate user computed cache");
+		}
+
 		Ok(res.deleted_count == 1)
 	}
 }
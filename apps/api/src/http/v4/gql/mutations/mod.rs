@@ -5,6 +5,7 @@ mod emote;
 mod emote_set;
 mod entitlement_edge;
 mod jobs;
+mod paint;
 mod redeem_code;
 mod special_event;
 mod ticket;
@@ -19,6 +20,7 @@ pub struct Mutation {
 	emote_sets: emote_set::EmoteSetMutation,
 	entitlement_edges: entitlement_edge::EntitlementEdgeMutation,
 	jobs: jobs::JobMutation,
+	paints: paint::PaintMutation,
 	redeem_codes: redeem_code::RedeemCodeMutation,
 	special_events: special_event::SpecialEventMutation,
 	tickets: ticket::TicketMutation,
@@ -0,0 +1,176 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use async_graphql::Context;
+use mongodb::options::{FindOneAndUpdateOptions, ReturnDocument};
+use shared::database::paint::{PaintId, PaintLayer, PaintLayerId};
+use shared::database::queries::filter;
+use shared::database::role::permissions::PaintPermission;
+use shared::database::stored_event::StoredEventPaintData;
+use shared::event::{InternalEvent, InternalEventData};
+
+use crate::global::Global;
+use crate::http::error::{ApiError, ApiErrorCode};
+use crate::http::guards::PermissionGuard;
+use crate::http::middleware::session::Session;
+use crate::http::v4::gql::types::Paint;
+use crate::transactions::{transaction_with_mutex, GeneralMutexKey, TransactionError};
+
+#[derive(Default)]
+pub struct PaintMutation;
+
+/// Checks that the new order contains every existing layer exactly once.
+fn check_layer_order(layers: &[PaintLayer], layer_ids: &[PaintLayerId]) -> Result<(), ApiError> {
+	let existing: HashSet<_> = layers.iter().map(|l| l.id).collect();
+	let ordered: HashSet<_> = layer_ids.iter().copied().collect();
+
+	if ordered.len() != layer_ids.len() {
+		return Err(ApiError::bad_request(ApiErrorCode::BadRequest, "duplicate layer ids"));
+	}
+
+	if existing != ordered {
+		return Err(ApiError::bad_request(
+			ApiErrorCode::BadRequest,
+			"layer ids must be exactly the existing layers of the paint",
+		));
+	}
+
+	Ok(())
+}
+
+/// Rebuilds the layer array in the given order from the stored layers, so the
+/// layers themselves are never rewritten from a possibly outdated copy.
+fn reorder_layers_pipeline(layer_ids: &[PaintLayerId]) -> [bson::Document; 1] {
+	let layer_ids: Vec<_> = layer_ids.iter().copied().map(bson::Bson::from).collect();
+
+	[bson::doc! {
+		"$set": {
+			"data.layers": {
+				"$map": {
+					"input": layer_ids,
+					"as": "id",
+					"in": {
+						"$arrayElemAt": [
+							{ "$filter": { "input": "$data.layers", "cond": { "$eq": ["$$this.id", "$$id"] } } },
+							0,
+						],
+					},
+				},
+			},
+			"updated_at": bson::DateTime::now(),
+			"search_updated_at": bson::Bson::Null,
+		},
+	}]
+}
+
+#[async_graphql::Object]
+impl PaintMutation {
+	#[graphql(guard = "PermissionGuard::one(PaintPermission::Manage)")]
+	#[tracing::instrument(skip_all, name = "PaintMutation::reorder_layers")]
+	async fn reorder_layers(&self, ctx: &Context<'_>, id: PaintId, layer_ids: Vec<PaintLayerId>) -> Result<Paint, ApiError> {
+		let global: &Arc<Global> = ctx
+			.data()
+			.map_err(|_| ApiError::internal_server_error(ApiErrorCode::MissingContext, "missing global data"))?;
+		let session = ctx
+			.data::<Session>()
+			.map_err(|_| ApiError::internal_server_error(ApiErrorCode::MissingContext, "missing session data"))?;
+		let authed_user = session.user()?;
+
+		let layer_ids = &layer_ids;
+
+		let res = transaction_with_mutex(
+			global,
+			"v4.paint.reorder_layers",
+			Some(GeneralMutexKey::Paint(id).into()),
+			|mut tx| async move {
+				let before = tx
+					.find_one(
+						filter::filter! {
+							shared::database::paint::Paint {
+								#[query(rename = "_id")]
+								id,
+							}
+						},
+						None,
+					)
+					.await?
+					.ok_or_else(|| {
+						TransactionError::Custom(ApiError::not_found(ApiErrorCode::LoadError, "paint not found"))
+					})?;
+
+				check_layer_order(&before.data.layers, layer_ids).map_err(TransactionError::Custom)?;
+
+				let after = tx
+					.find_one_and_update_pipeline(
+						filter::filter! {
+							shared::database::paint::Paint {
+								#[query(rename = "_id")]
+								id,
+							}
+						},
+						reorder_layers_pipeline(layer_ids),
+						FindOneAndUpdateOptions::builder()
+							.return_document(ReturnDocument::After)
+							.build(),
+					)
+					.await?
+					.ok_or_else(|| {
+						TransactionError::Custom(ApiError::not_found(ApiErrorCode::LoadError, "paint not found"))
+					})?;
+
+				tx.register_event(InternalEvent {
+					actor: Some(authed_user.clone()),
+					session_id: session.user_session_id(),
+					data: InternalEventData::Paint {
+						after: after.clone(),
+						data: StoredEventPaintData::ChangeData {
+							old: before.data,
+							new: after.data.clone(),
+						},
+					},
+					timestamp: chrono::Utc::now(),
+				})?;
+
+				Ok(after)
+			},
+		)
+		.await;
+
+		match res {
+			Ok(paint) => Ok(Paint::from_db(paint, &global.config.api.cdn_origin)),
+			Err(TransactionError::Custom(e)) => Err(e),
+			Err(e) => {
+				tracing::error!(error = %e, "transaction failed");
+				Err(ApiError::internal_server_error(
+					ApiErrorCode::TransactionError,
+					"transaction failed",
+				))
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn layer() -> PaintLayer {
+		PaintLayer {
+			id: PaintLayerId::new(),
+			..Default::default()
+		}
+	}
+
+	#[test]
+	fn test_check_layer_order() {
+		let layers = vec![layer(), layer(), layer()];
+
+		assert!(check_layer_order(&layers, &[layers[2].id, layers[0].id, layers[1].id]).is_ok());
+		// missing layer
+		assert!(check_layer_order(&layers, &[layers[2].id, layers[0].id]).is_err());
+		// unknown layer
+		assert!(check_layer_order(&layers, &[layers[2].id, layers[0].id, layers[1].id, PaintLayerId::new()]).is_err());
+		// duplicate layer
+		assert!(check_layer_order(&layers, &[layers[2].id, layers[0].id, layers[0].id]).is_err());
+	}
+}
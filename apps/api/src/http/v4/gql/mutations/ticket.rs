@@ -53,7 +53,7 @@ impl TicketMutation {
 			.and_then(|c| c.iso_code)
 			.map(|c| c.to_string());
 
-		let res = transaction(global, |mut tx| async move {
+		let res = transaction(global, "v4.ticket.create_abuse_ticket", |mut tx| async move {
 			let ticket_id = TicketId::new();
 
 			let message_id = if let Some(c) = content {
@@ -6,16 +6,21 @@ use shared::database::badge::BadgeId;
 use shared::database::emote_set::{EmoteSetId, EmoteSetKind};
 use shared::database::paint::PaintId;
 use shared::database::queries::{filter, update};
-use shared::database::role::permissions::{PermissionsExt, RateLimitResource, UserPermission};
+use shared::database::role::permissions::{AdminPermission, PermissionsExt, RateLimitResource, UserPermission};
 use shared::database::stored_event::StoredEventUserSessionData;
-use shared::database::user::editor::{EditorUserPermission, UserEditorId, UserEditorState};
-use shared::event::{InternalEvent, InternalEventData, InternalEventUserData};
+use shared::database::user::profile_picture::{UserProfilePicture, UserProfilePictureId};
+use shared::database::MongoCollection;
+use shared::event::{EventUserPresencePlatform, InternalEvent, InternalEventData, InternalEventUserData};
 
+use crate::dataloader::user_computed_cache;
 use crate::global::Global;
+use crate::http::connection::check_login_connection;
 use crate::http::error::{ApiError, ApiErrorCode};
-use crate::http::guards::RateLimitGuard;
+use crate::http::guards::{EditorGuard, PermissionGuard, RateLimitGuard};
 use crate::http::middleware::session::Session;
-use crate::http::v4::gql::types::{Platform, User};
+use crate::http::presence;
+use crate::http::profile_picture::delete_profile_pictures;
+use crate::http::v4::gql::types::{Platform, User, UserConnection};
 use crate::transactions::{transaction, transaction_with_mutex, GeneralMutexKey, TransactionError};
 
 pub struct UserOperation {
@@ -30,9 +35,78 @@ pub struct KickLinkInput {
 	pub avatar_url: Option<String>,
 }
 
+#[derive(async_graphql::Enum, Copy, Clone, Eq, PartialEq)]
+pub enum PresencePlatform {
+	Twitch,
+	Kick,
+	Youtube,
+}
+
+#[derive(async_graphql::SimpleObject)]
+pub struct RemoveConnectionResponse {
+	pub user: User,
+	/// The connection which was removed
+	pub connection: UserConnection,
+}
+
+/// An update pipeline which moves the given connection to the front of the
+/// user's connections, making it the main connection.
+///
+/// This is done in a single statement so the connection can not be changed in
+/// between removing it and adding it back.
+fn main_connection_pipeline(
+	platform: shared::database::user::connection::Platform,
+	platform_id: &str,
+) -> [bson::Document; 1] {
+	let is_connection = bson::doc! {
+		"$and": [
+			{ "$eq": ["$$this.platform", platform] },
+			{ "$eq": ["$$this.platform_id", platform_id] },
+		],
+	};
+
+	[bson::doc! {
+		"$set": {
+			"connections": {
+				"$concatArrays": [
+					{ "$filter": { "input": "$connections", "cond": is_connection.clone() } },
+					{ "$filter": { "input": "$connections", "cond": { "$not": [is_connection] } } },
+				],
+			},
+			"updated_at": bson::DateTime::now(),
+			"search_updated_at": bson::Bson::Null,
+		},
+	}]
+}
+
+/// The maximum length of a user biography in characters.
+const MAX_BIOGRAPHY_LENGTH: usize = 500;
+
+/// Checks the biography of a user, line breaks are the only control
+/// characters allowed.
+fn check_biography(biography: &str) -> Result<(), ApiError> {
+	if biography.chars().count() > MAX_BIOGRAPHY_LENGTH {
+		return Err(ApiError::bad_request(
+			ApiErrorCode::BadRequest,
+			format!("biography must be at most {MAX_BIOGRAPHY_LENGTH} characters"),
+		));
+	}
+
+	if biography.chars().any(|c| c.is_control() && c != '\n') {
+		return Err(ApiError::bad_request(
+			ApiErrorCode::BadRequest,
+			"biography must not contain control characters",
+		));
+	}
+
+	Ok(())
+}
+
 #[async_graphql::Object]
 impl UserOperation {
-	#[graphql(guard = "RateLimitGuard::new(RateLimitResource::UserChangeConnections, 1)")]
+	#[graphql(
+		guard = "RateLimitGuard::new(RateLimitResource::UserChangeConnections, 1).and(EditorGuard::profile(self.user.id))"
+	)]
 	#[tracing::instrument(skip_all, name = "UserOperation::main_connection")]
 	async fn main_connection(&self, ctx: &Context<'_>, platform: Platform, platform_id: String) -> Result<User, ApiError> {
 		let global: &Arc<Global> = ctx.data().map_err(|_| {
@@ -41,37 +115,10 @@ impl UserOperation {
 				"missing global data",
 			)
 		})?;
-		let session = ctx
-			.data::<Session>()
-			.map_err(|_| ApiError::internal_server_error(ApiErrorCode::MissingContext, "missing sesion data"))?;
-		let authed_user = session.user()?;
-
-		if authed_user.id != self.user.id && !authed_user.has(UserPermission::ManageAny) {
-			let editor = global
-				.user_editor_by_id_loader
-				.load(UserEditorId {
-					editor_id: authed_user.id,
-					user_id: self.user.id,
-				})
-				.await
-				.map_err(|_| ApiError::internal_server_error(ApiErrorCode::LoadError, "failed to load editor"))?
-				.ok_or_else(|| {
-					ApiError::forbidden(
-						ApiErrorCode::LackingPrivileges,
-						"you do not have permission to modify connections",
-					)
-				})?;
-
-			if editor.state != UserEditorState::Accepted || !editor.permissions.has(EditorUserPermission::ManageProfile) {
-				return Err(ApiError::forbidden(
-					ApiErrorCode::LackingPrivileges,
-					"you do not have permission to modify connections, you need the ManageProfile permission",
-				));
-			}
-		}
 
 		let res = transaction_with_mutex(
 			global,
+			"v4.user.main_connection",
 			Some(GeneralMutexKey::User(self.user.id).into()),
 			|mut tx| async move {
 				let user = tx
@@ -91,57 +138,26 @@ impl UserOperation {
 
 				let platform = shared::database::user::connection::Platform::from(platform);
 
-				let connection = user
+				if !user
 					.connections
 					.iter()
-					.find(|c| c.platform == platform && c.platform_id == platform_id)
-					.ok_or_else(|| {
-						TransactionError::Custom(ApiError::not_found(
-							ApiErrorCode::LoadError,
-							"connection not found for platform",
-						))
-					})?;
-
-				tx.update_one(
-					filter::filter! {
-						shared::database::user::User {
-							#[query(rename = "_id")]
-							id: self.user.id,
-						}
-					},
-					update::update! {
-						#[query(pull)]
-						shared::database::user::User {
-							connections: shared::database::user::connection::UserConnection {
-								platform: connection.platform,
-								platform_id: &connection.platform_id,
-							},
-						},
-					},
-					None,
-				)
-				.await?;
+					.any(|c| c.platform == platform && c.platform_id == platform_id)
+				{
+					return Err(TransactionError::Custom(ApiError::not_found(
+						ApiErrorCode::LoadError,
+						"connection not found for platform",
+					)));
+				}
 
 				let user = tx
-					.find_one_and_update(
+					.find_one_and_update_pipeline(
 						filter::filter! {
 							shared::database::user::User {
 								#[query(rename = "_id")]
 								id: self.user.id,
 							}
 						},
-						update::update! {
-							#[query(push)]
-							shared::database::user::User {
-								#[query(serde, each, position = "0")]
-								connections: [connection],
-							},
-							#[query(set)]
-							shared::database::user::User {
-								updated_at: chrono::Utc::now(),
-								search_updated_at: &None,
-							},
-						},
+						main_connection_pipeline(platform, &platform_id),
 						FindOneAndUpdateOptions::builder()
 							.return_document(ReturnDocument::After)
 							.build(),
@@ -177,7 +193,9 @@ impl UserOperation {
 		}
 	}
 
-	#[graphql(guard = "RateLimitGuard::new(RateLimitResource::UserChangeConnections, 1)")]
+	#[graphql(
+		guard = "RateLimitGuard::new(RateLimitResource::UserChangeConnections, 1).and(EditorGuard::profile(self.user.id))"
+	)]
 	#[tracing::instrument(skip_all, name = "UserOperation::active_emote_set")]
 	async fn active_emote_set(&self, ctx: &Context<'_>, emote_set_id: Option<EmoteSetId>) -> Result<User, ApiError> {
 		let global: &Arc<Global> = ctx.data().map_err(|_| {
@@ -191,32 +209,9 @@ impl UserOperation {
 			.map_err(|_| ApiError::internal_server_error(ApiErrorCode::MissingContext, "missing sesion data"))?;
 		let authed_user = session.user()?;
 
-		if authed_user.id != self.user.id && !authed_user.has(UserPermission::ManageAny) {
-			let editor = global
-				.user_editor_by_id_loader
-				.load(UserEditorId {
-					editor_id: authed_user.id,
-					user_id: self.user.id,
-				})
-				.await
-				.map_err(|_| ApiError::internal_server_error(ApiErrorCode::LoadError, "failed to load editor"))?
-				.ok_or_else(|| {
-					ApiError::forbidden(
-						ApiErrorCode::LackingPrivileges,
-						"you do not have permission to modify the active emote set",
-					)
-				})?;
-
-			if editor.state != UserEditorState::Accepted || !editor.permissions.has(EditorUserPermission::ManageProfile) {
-				return Err(ApiError::forbidden(
-					ApiErrorCode::LackingPrivileges,
-					"you do not have permission to modify the active emote set, you need the ManageProfile permission",
-				));
-			}
-		}
-
 		let res = transaction_with_mutex(
 			global,
+			"v4.user.active_emote_set",
 			Some(GeneralMutexKey::User(self.user.id).into()),
 			|mut tx| async move {
 				// check if set exists
@@ -235,11 +230,20 @@ impl UserOperation {
 							TransactionError::Custom(ApiError::not_found(ApiErrorCode::LoadError, "emote set not found"))
 						})?;
 
-					if emote_set.kind != EmoteSetKind::Normal {
-						return Err(TransactionError::Custom(ApiError::bad_request(
-							ApiErrorCode::BadRequest,
-							"emote set is not a normal set",
-						)));
+					match emote_set.kind {
+						EmoteSetKind::Normal => {}
+						EmoteSetKind::Global => {
+							return Err(TransactionError::Custom(ApiError::bad_request(
+								ApiErrorCode::BadRequest,
+								"global emote sets can not be used as an active emote set",
+							)));
+						}
+						EmoteSetKind::Personal | EmoteSetKind::Special => {
+							return Err(TransactionError::Custom(ApiError::bad_request(
+								ApiErrorCode::BadRequest,
+								"emote set is not a normal set",
+							)));
+						}
 					}
 
 					Some(emote_set)
@@ -325,7 +329,9 @@ impl UserOperation {
 		}
 	}
 
-	#[graphql(guard = "RateLimitGuard::new(RateLimitResource::UserChangeCosmetics, 1)")]
+	#[graphql(
+		guard = "RateLimitGuard::new(RateLimitResource::UserChangeCosmetics, 1).and(EditorGuard::profile(self.user.id))"
+	)]
 	#[tracing::instrument(skip_all, name = "UserOperation::badge")]
 	async fn active_badge(&self, ctx: &Context<'_>, badge_id: Option<BadgeId>) -> Result<User, ApiError> {
 		let global: &Arc<Global> = ctx
@@ -336,30 +342,6 @@ impl UserOperation {
 			.map_err(|_| ApiError::internal_server_error(ApiErrorCode::MissingContext, "missing sesion data"))?;
 		let authed_user = session.user()?;
 
-		if authed_user.id != self.user.id && !authed_user.has(UserPermission::ManageAny) {
-			let editor = global
-				.user_editor_by_id_loader
-				.load(UserEditorId {
-					editor_id: authed_user.id,
-					user_id: self.user.id,
-				})
-				.await
-				.map_err(|_| ApiError::internal_server_error(ApiErrorCode::LoadError, "failed to load editor"))?
-				.ok_or_else(|| {
-					ApiError::forbidden(
-						ApiErrorCode::LackingPrivileges,
-						"you do not have permission to change this user's cosmetics",
-					)
-				})?;
-
-			if editor.state != UserEditorState::Accepted || !editor.permissions.has(EditorUserPermission::ManageProfile) {
-				return Err(ApiError::forbidden(
-					ApiErrorCode::LackingPrivileges,
-					"you do not have permission to modify this user's cosmetics, you need the ManageProfile permission",
-				));
-			}
-		}
-
 		let user = global
 			.user_loader
 			.load(global, self.user.id)
@@ -381,6 +363,7 @@ impl UserOperation {
 
 		let res = transaction_with_mutex(
 			global,
+			"v4.user.active_badge",
 			Some(GeneralMutexKey::User(self.user.id).into()),
 			|mut tx| async move {
 				let new = if let Some(id) = badge_id {
@@ -481,7 +464,9 @@ impl UserOperation {
 		}
 	}
 
-	#[graphql(guard = "RateLimitGuard::new(RateLimitResource::UserChangeCosmetics, 1)")]
+	#[graphql(
+		guard = "RateLimitGuard::new(RateLimitResource::UserChangeCosmetics, 1).and(EditorGuard::profile(self.user.id))"
+	)]
 	#[tracing::instrument(skip_all, name = "UserOperation::paint")]
 	async fn active_paint(&self, ctx: &Context<'_>, paint_id: Option<PaintId>) -> Result<User, ApiError> {
 		let global: &Arc<Global> = ctx
@@ -492,30 +477,6 @@ impl UserOperation {
 			.map_err(|_| ApiError::internal_server_error(ApiErrorCode::MissingContext, "missing sesion data"))?;
 		let authed_user = session.user()?;
 
-		if authed_user.id != self.user.id && !authed_user.has(UserPermission::ManageAny) {
-			let editor = global
-				.user_editor_by_id_loader
-				.load(UserEditorId {
-					editor_id: authed_user.id,
-					user_id: self.user.id,
-				})
-				.await
-				.map_err(|_| ApiError::internal_server_error(ApiErrorCode::LoadError, "failed to load editor"))?
-				.ok_or_else(|| {
-					ApiError::forbidden(
-						ApiErrorCode::LackingPrivileges,
-						"you do not have permission to change this user's cosmetics",
-					)
-				})?;
-
-			if editor.state != UserEditorState::Accepted || !editor.permissions.has(EditorUserPermission::ManageProfile) {
-				return Err(ApiError::forbidden(
-					ApiErrorCode::LackingPrivileges,
-					"you do not have permission to modify this user's cosmetics, you need the ManageProfile permission",
-				));
-			}
-		}
-
 		let user = global
 			.user_loader
 			.load(global, self.user.id)
@@ -537,6 +498,7 @@ impl UserOperation {
 
 		let res = transaction_with_mutex(
 			global,
+			"v4.user.active_paint",
 			Some(GeneralMutexKey::User(self.user.id).into()),
 			|mut tx| async move {
 				let new = if let Some(id) = paint_id {
@@ -637,9 +599,11 @@ impl UserOperation {
 		}
 	}
 
-	#[graphql(guard = "RateLimitGuard::new(RateLimitResource::UserChangeCosmetics, 1)")]
-	#[tracing::instrument(skip_all, name = "UserOperation::remove_profile_picture")]
-	async fn remove_profile_picture(&self, ctx: &Context<'_>) -> Result<User, ApiError> {
+	#[graphql(
+		guard = "RateLimitGuard::new(RateLimitResource::UserChangeCosmetics, 1).and(EditorGuard::profile(self.user.id))"
+	)]
+	#[tracing::instrument(skip_all, name = "UserOperation::biography")]
+	async fn biography(&self, ctx: &Context<'_>, biography: String) -> Result<User, ApiError> {
 		let global: &Arc<Global> = ctx
 			.data()
 			.map_err(|_| ApiError::internal_server_error(ApiErrorCode::MissingContext, "missing global data"))?;
@@ -648,29 +612,143 @@ impl UserOperation {
 			.map_err(|_| ApiError::internal_server_error(ApiErrorCode::MissingContext, "missing sesion data"))?;
 		let authed_user = session.user()?;
 
-		if authed_user.id != self.user.id && !authed_user.has(UserPermission::ManageAny) {
-			let editor = global
-				.user_editor_by_id_loader
-				.load(UserEditorId {
-					editor_id: authed_user.id,
-					user_id: self.user.id,
-				})
+		let biography = biography.trim().to_owned();
+		check_biography(&biography)?;
+
+		if self.user.biography == biography {
+			let user = global
+				.user_loader
+				.load_fast_user(global, self.user.clone())
 				.await
-				.map_err(|_| ApiError::internal_server_error(ApiErrorCode::LoadError, "failed to load editor"))?
-				.ok_or_else(|| {
-					ApiError::forbidden(
-						ApiErrorCode::LackingPrivileges,
-						"you do not have permission to change this user's cosmetics",
+				.map_err(|()| ApiError::internal_server_error(ApiErrorCode::LoadError, "failed to load user"))?;
+
+			return Ok(user.into());
+		}
+
+		let biography = &biography;
+
+		let res = transaction_with_mutex(
+			global,
+			"v4.user.biography",
+			Some(GeneralMutexKey::User(self.user.id).into()),
+			|mut tx| async move {
+				let before = tx
+					.find_one(
+						filter::filter! {
+							shared::database::user::User {
+								#[query(rename = "_id")]
+								id: self.user.id,
+							}
+						},
+						None,
+					)
+					.await?
+					.ok_or_else(|| {
+						TransactionError::Custom(ApiError::not_found(ApiErrorCode::LoadError, "user not found"))
+					})?;
+
+				let user = tx
+					.find_one_and_update(
+						filter::filter! {
+							shared::database::user::User {
+								#[query(rename = "_id")]
+								id: self.user.id,
+							}
+						},
+						update::update! {
+							#[query(set)]
+							shared::database::user::User {
+								biography,
+								updated_at: chrono::Utc::now(),
+								search_updated_at: &None,
+							},
+						},
+						FindOneAndUpdateOptions::builder()
+							.return_document(ReturnDocument::After)
+							.build(),
 					)
+					.await?
+					.ok_or_else(|| {
+						TransactionError::Custom(ApiError::not_found(ApiErrorCode::LoadError, "user not found"))
+					})?;
+
+				tx.register_event(InternalEvent {
+					actor: Some(authed_user.clone()),
+					session_id: session.user_session_id(),
+					data: InternalEventData::User {
+						after: user.clone(),
+						data: InternalEventUserData::ChangeBiography {
+							old: before.biography,
+							new: user.biography.clone(),
+						},
+					},
+					timestamp: chrono::Utc::now(),
 				})?;
 
-			if editor.state != UserEditorState::Accepted || !editor.permissions.has(EditorUserPermission::ManageProfile) {
-				return Err(ApiError::forbidden(
-					ApiErrorCode::LackingPrivileges,
-					"you do not have permission to modify this user's profile picture, you need the ManageProfile permission",
-				));
+				Ok(user)
+			},
+		)
+		.await;
+
+		match res {
+			Ok(user) => {
+				let full_user = global
+					.user_loader
+					.load_fast_user(global, user)
+					.await
+					.map_err(|()| ApiError::internal_server_error(ApiErrorCode::LoadError, "failed to load user"))?;
+
+				Ok(full_user.into())
+			}
+			Err(TransactionError::Custom(e)) => Err(e),
+			Err(e) => {
+				tracing::error!(error = %e, "transaction failed");
+				Err(ApiError::internal_server_error(
+					ApiErrorCode::TransactionError,
+					"transaction failed",
+				))
 			}
 		}
+	}
+
+	/// Records that the user is active in the given channel. Returns false if
+	/// the same presence was already recorded recently.
+	#[graphql(
+		guard = "RateLimitGuard::new(RateLimitResource::UserPresenceWrite, 1).and(EditorGuard::profile(self.user.id))"
+	)]
+	#[tracing::instrument(skip_all, name = "UserOperation::presence")]
+	async fn presence(&self, ctx: &Context<'_>, platform: PresencePlatform, platform_id: String) -> Result<bool, ApiError> {
+		let global: &Arc<Global> = ctx
+			.data()
+			.map_err(|_| ApiError::internal_server_error(ApiErrorCode::MissingContext, "missing global data"))?;
+
+		presence::check_platform_id(&platform_id)?;
+
+		let platform = match platform {
+			PresencePlatform::Twitch => EventUserPresencePlatform::Twitch(
+				platform_id
+					.parse()
+					.map_err(|_| ApiError::bad_request(ApiErrorCode::BadRequest, "platform id is not a valid twitch id"))?,
+			),
+			PresencePlatform::Kick => EventUserPresencePlatform::Kick(
+				platform_id
+					.parse()
+					.map_err(|_| ApiError::bad_request(ApiErrorCode::BadRequest, "platform id is not a valid kick id"))?,
+			),
+			PresencePlatform::Youtube => EventUserPresencePlatform::Youtube(platform_id),
+		};
+
+		presence::write_presence_dedup(global, self.user.id, platform).await
+	}
+
+	#[graphql(
+		guard = "RateLimitGuard::new(RateLimitResource::UserChangeCosmetics, 1).and(EditorGuard::profile(self.user.id))"
+	)]
+	#[tracing::instrument(skip_all, name = "UserOperation::remove_profile_picture")]
+	async fn remove_profile_picture(&self, ctx: &Context<'_>) -> Result<User, ApiError> {
+		let global: &Arc<Global> = ctx
+			.data()
+			.map_err(|_| ApiError::internal_server_error(ApiErrorCode::MissingContext, "missing global data"))?;
 
 		let user = global
 			.user_loader
@@ -683,35 +761,42 @@ impl UserOperation {
 			return Ok(user.into());
 		}
 
-		let res = transaction_with_mutex(global, Some(GeneralMutexKey::User(user.id).into()), |mut tx| async move {
-			let user = tx
-				.find_one_and_update(
-					filter::filter! {
-						shared::database::user::User {
-							#[query(rename = "_id")]
-							id: user.id,
-						}
-					},
-					update::update! {
-						#[query(set)]
-						shared::database::user::User {
-							#[query(flatten)]
-							style: shared::database::user::UserStyle {
-								active_profile_picture: &None,
+		let res = transaction_with_mutex(
+			global,
+			"v4.user.remove_profile_picture",
+			Some(GeneralMutexKey::User(user.id).into()),
+			|mut tx| async move {
+				let user = tx
+					.find_one_and_update(
+						filter::filter! {
+							shared::database::user::User {
+								#[query(rename = "_id")]
+								id: user.id,
+							}
+						},
+						update::update! {
+							#[query(set)]
+							shared::database::user::User {
+								#[query(flatten)]
+								style: shared::database::user::UserStyle {
+									active_profile_picture: &None,
+								},
+								updated_at: chrono::Utc::now(),
+								search_updated_at: &None,
 							},
-							updated_at: chrono::Utc::now(),
-							search_updated_at: &None,
 						},
-					},
-					FindOneAndUpdateOptions::builder()
-						.return_document(ReturnDocument::After)
-						.build(),
-				)
-				.await?
-				.ok_or_else(|| TransactionError::Custom(ApiError::not_found(ApiErrorCode::LoadError, "user not found")))?;
+						FindOneAndUpdateOptions::builder()
+							.return_document(ReturnDocument::After)
+							.build(),
+					)
+					.await?
+					.ok_or_else(|| {
+						TransactionError::Custom(ApiError::not_found(ApiErrorCode::LoadError, "user not found"))
+					})?;
 
-			Ok(user)
-		})
+				Ok(user)
+			},
+		)
 		.await;
 
 		match res {
@@ -735,9 +820,13 @@ impl UserOperation {
 		}
 	}
 
-	#[graphql(guard = "RateLimitGuard::new(RateLimitResource::UserChangeConnections, 1)")]
-	#[tracing::instrument(skip_all, name = "UserOperation::remove_connection")]
-	async fn remove_connection(&self, ctx: &Context<'_>, platform: Platform, platform_id: String) -> Result<User, ApiError> {
+	/// Deletes one of the user's previous profile pictures together with its
+	/// files. The active and pending profile pictures cannot be deleted.
+	#[graphql(
+		guard = "RateLimitGuard::new(RateLimitResource::UserChangeCosmetics, 1).and(EditorGuard::profile(self.user.id))"
+	)]
+	#[tracing::instrument(skip_all, name = "UserOperation::delete_profile_picture")]
+	async fn delete_profile_picture(&self, ctx: &Context<'_>, id: UserProfilePictureId) -> Result<bool, ApiError> {
 		let global: &Arc<Global> = ctx
 			.data()
 			.map_err(|_| ApiError::internal_server_error(ApiErrorCode::MissingContext, "missing global data"))?;
@@ -746,48 +835,106 @@ impl UserOperation {
 			.map_err(|_| ApiError::internal_server_error(ApiErrorCode::MissingContext, "missing sesion data"))?;
 		let authed_user = session.user()?;
 
-		if authed_user.id != self.user.id && !authed_user.has(UserPermission::ManageAny) {
-			let editor = global
-				.user_editor_by_id_loader
-				.load(UserEditorId {
-					editor_id: authed_user.id,
-					user_id: self.user.id,
-				})
-				.await
-				.map_err(|_| ApiError::internal_server_error(ApiErrorCode::LoadError, "failed to load editor"))?
-				.ok_or_else(|| {
-					ApiError::forbidden(
-						ApiErrorCode::LackingPrivileges,
-						"you do not have permission to modify connections",
+		let res = transaction_with_mutex(
+			global,
+			"v4.user.delete_profile_picture",
+			Some(GeneralMutexKey::User(self.user.id).into()),
+			|mut tx| async move {
+				let user = tx
+					.find_one(
+						filter::filter! {
+							shared::database::user::User {
+								#[query(rename = "_id")]
+								id: self.user.id,
+							}
+						},
+						None,
 					)
-				})?;
+					.await?
+					.ok_or_else(|| {
+						TransactionError::Custom(ApiError::not_found(ApiErrorCode::LoadError, "user not found"))
+					})?;
+
+				if user.style.active_profile_picture == Some(id) || user.style.pending_profile_picture == Some(id) {
+					return Err(TransactionError::Custom(ApiError::bad_request(
+						ApiErrorCode::BadRequest,
+						"cannot delete the active or pending profile picture",
+					)));
+				}
+
+				let picture = tx
+					.find_one(
+						filter::filter! {
+							UserProfilePicture {
+								#[query(rename = "_id")]
+								id: id,
+								user_id: self.user.id,
+							}
+						},
+						None,
+					)
+					.await?
+					.ok_or_else(|| {
+						TransactionError::Custom(ApiError::not_found(ApiErrorCode::LoadError, "profile picture not found"))
+					})?;
 
-			if editor.state != UserEditorState::Accepted || !editor.permissions.has(EditorUserPermission::ManageProfile) {
-				return Err(ApiError::forbidden(
-					ApiErrorCode::LackingPrivileges,
-					"you do not have permission to modify connections, you need the ManageProfile permission",
-				));
+				delete_profile_pictures(&mut tx, vec![picture], Some(authed_user), session.user_session_id()).await
+			},
+		)
+		.await;
+
+		match res {
+			Ok(purge) => {
+				if let Err(err) = crate::cdn_purge::request_purge(global, &purge).await {
+					tracing::error!(error = %err, "failed to purge profile picture files");
+				}
+
+				Ok(true)
+			}
+			Err(TransactionError::Custom(e)) => Err(e),
+			Err(e) => {
+				tracing::error!(error = %e, "transaction failed");
+				Err(ApiError::internal_server_error(
+					ApiErrorCode::TransactionError,
+					"transaction failed",
+				))
 			}
 		}
+	}
+
+	#[graphql(
+		guard = "RateLimitGuard::new(RateLimitResource::UserChangeConnections, 1).and(EditorGuard::profile(self.user.id))"
+	)]
+	#[tracing::instrument(skip_all, name = "UserOperation::remove_connection")]
+	async fn remove_connection(
+		&self,
+		ctx: &Context<'_>,
+		platform: Platform,
+		platform_id: String,
+	) -> Result<RemoveConnectionResponse, ApiError> {
+		let global: &Arc<Global> = ctx
+			.data()
+			.map_err(|_| ApiError::internal_server_error(ApiErrorCode::MissingContext, "missing global data"))?;
+		let session = ctx
+			.data::<Session>()
+			.map_err(|_| ApiError::internal_server_error(ApiErrorCode::MissingContext, "missing sesion data"))?;
+		let authed_user = session.user()?;
 
 		let platform: shared::database::user::connection::Platform = platform.into();
 
 		let res = transaction_with_mutex(
 			global,
+			"v4.user.remove_connection",
 			Some(GeneralMutexKey::User(self.user.id).into()),
 			|mut tx| async move {
-				let old_user = global
-					.user_loader
-					.load(global, self.user.id)
-					.await
-					.map_err(|_| {
-						TransactionError::Custom(ApiError::internal_server_error(
-							ApiErrorCode::LoadError,
-							"failed to load user",
-						))
-					})?
+				let connection = self
+					.user
+					.connections
+					.iter()
+					.find(|c| c.platform == platform && c.platform_id == platform_id)
+					.cloned()
 					.ok_or_else(|| {
-						TransactionError::Custom(ApiError::not_found(ApiErrorCode::LoadError, "user not found"))
+						TransactionError::Custom(ApiError::not_found(ApiErrorCode::LoadError, "connection not found"))
 					})?;
 
 				let user = tx
@@ -821,46 +968,37 @@ impl UserOperation {
 						TransactionError::Custom(ApiError::not_found(ApiErrorCode::LoadError, "user not found"))
 					})?;
 
-				if user.connections.is_empty() {
-					return Err(TransactionError::Custom(ApiError::bad_request(
-						ApiErrorCode::BadRequest,
-						"cannot remove last connection",
-					)));
-				}
-
-				let connection = old_user
-					.user
-					.connections
-					.into_iter()
-					.find(|c| c.platform == platform && c.platform_id == platform_id)
-					.ok_or_else(|| {
-						TransactionError::Custom(ApiError::not_found(ApiErrorCode::LoadError, "connection not found"))
-					})?;
+				check_login_connection(&user.connections, authed_user).map_err(TransactionError::Custom)?;
 
 				tx.register_event(InternalEvent {
 					actor: Some(authed_user.clone()),
 					session_id: session.user_session_id(),
 					data: InternalEventData::User {
 						after: user.clone(),
-						data: InternalEventUserData::RemoveConnection { connection },
+						data: InternalEventUserData::RemoveConnection {
+							connection: connection.clone(),
+						},
 					},
 					timestamp: chrono::Utc::now(),
 				})?;
 
-				Ok(user)
+				Ok((user, connection))
 			},
 		)
 		.await;
 
 		match res {
-			Ok(user) => {
+			Ok((user, connection)) => {
 				let full_user = global
 					.user_loader
 					.load_fast_user(global, user)
 					.await
 					.map_err(|()| ApiError::internal_server_error(ApiErrorCode::LoadError, "failed to load user"))?;
 
-				Ok(full_user.into())
+				Ok(RemoveConnectionResponse {
+					user: full_user.into(),
+					connection: connection.into(),
+				})
 			}
 			Err(TransactionError::Custom(e)) => Err(e),
 			Err(e) => {
@@ -893,6 +1031,7 @@ impl UserOperation {
 
 		let res = transaction_with_mutex(
 			global,
+			"v4.user.manually_link_kick",
 			Some(GeneralMutexKey::User(self.user.id).into()),
 			|mut tx| async move {
 				if tx
@@ -1014,7 +1153,7 @@ impl UserOperation {
 			.user_session_id()
 			.ok_or_else(|| ApiError::unauthorized(ApiErrorCode::LoginRequired, "you are not logged in"))?;
 
-		let res = transaction(global, |mut tx| async move {
+		let res = transaction(global, "v4.user.delete_all_sessions", |mut tx| async move {
 			let sessions: Vec<_> = tx
 				.find(
 					filter::filter! {
@@ -1073,4 +1212,99 @@ impl UserOperation {
 			}
 		}
 	}
+
+	/// Recomputes the permissions and entitlements of the user from the
+	/// entitlement graph, bypassing any cache, and makes the search indexer
+	/// refresh the entitlements cached on the user. Meant for edges which were
+	/// changed out of band.
+	#[graphql(
+		guard = "PermissionGuard::one(AdminPermission::ManageEntitlements).and(RateLimitGuard::new(RateLimitResource::UserRecomputeEntitlements, 1))"
+	)]
+	#[tracing::instrument(skip_all, name = "UserOperation::recompute_entitlements")]
+	async fn recompute_entitlements(&self, ctx: &Context<'_>) -> Result<User, ApiError> {
+		let global: &Arc<Global> = ctx
+			.data()
+			.map_err(|_| ApiError::internal_server_error(ApiErrorCode::MissingContext, "missing global data"))?;
+
+		user_computed_cache::invalidate(global, self.user.id).await.map_err(|err| {
+			tracing::error!(error = %err, "failed to invalidate user computed cache");
+			ApiError::internal_server_error(ApiErrorCode::MutationError, "failed to invalidate user computed cache")
+		})?;
+
+		// the search indexer recomputes the cached fields of the user once it is
+		// no longer marked as indexed
+		shared::database::user::User::collection(&global.db)
+			.update_one(
+				filter::filter! {
+					shared::database::user::User {
+						#[query(rename = "_id")]
+						id: self.user.id,
+					}
+				},
+				update::update! {
+					#[query(set)]
+					shared::database::user::User {
+						updated_at: chrono::Utc::now(),
+						search_updated_at: &None,
+					}
+				},
+			)
+			.await
+			.map_err(|err| {
+				tracing::error!(error = %err, "failed to update user");
+				ApiError::internal_server_error(ApiErrorCode::MutationError, "failed to update user")
+			})?;
+
+		let full_user = global
+			.user_loader
+			.load(global, self.user.id)
+			.await
+			.map_err(|()| ApiError::internal_server_error(ApiErrorCode::LoadError, "failed to load user"))?
+			.ok_or_else(|| ApiError::not_found(ApiErrorCode::LoadError, "user not found"))?;
+
+		Ok(full_user.into())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use shared::database::user::connection::Platform;
+
+	use super::*;
+
+	#[test]
+	fn test_check_biography() {
+		assert!(check_biography("").is_ok());
+		assert!(check_biography("hello\nworld").is_ok());
+		assert!(check_biography(&"a".repeat(MAX_BIOGRAPHY_LENGTH)).is_ok());
+		assert!(check_biography(&"a".repeat(MAX_BIOGRAPHY_LENGTH + 1)).is_err());
+		assert!(check_biography("hello\u{0}world").is_err());
+	}
+
+	#[test]
+	fn test_main_connection_pipeline() {
+		let [stage] = main_connection_pipeline(Platform::Discord, "2");
+		let set = stage.get_document("$set").unwrap();
+
+		let is_connection = bson::doc! {
+			"$and": [
+				{ "$eq": ["$$this.platform", Platform::Discord] },
+				{ "$eq": ["$$this.platform_id", "2"] },
+			],
+		};
+
+		// the selected connection moves to the front, the rest keep their order
+		assert_eq!(
+			set.get("connections").unwrap(),
+			&bson::bson!({
+				"$concatArrays": [
+					{ "$filter": { "input": "$connections", "cond": is_connection.clone() } },
+					{ "$filter": { "input": "$connections", "cond": { "$not": [is_connection] } } },
+				],
+			})
+		);
+
+		assert!(set.get_datetime("updated_at").is_ok());
+		assert_eq!(set.get("search_updated_at"), Some(&bson::Bson::Null));
+	}
 }
@@ -10,6 +10,7 @@ use shared::database::user::UserId;
 use shared::event::{InternalEvent, InternalEventData, InternalEventUserEditorData};
 
 use crate::global::Global;
+use crate::http::editor::ensure_can_manage_editors;
 use crate::http::error::{ApiError, ApiErrorCode};
 use crate::http::middleware::session::Session;
 use crate::http::v4::gql::types::UserEditor;
@@ -68,29 +69,7 @@ impl UserEditorMutation {
 
 		let permissions: UserEditorPermissions = permissions.into();
 
-		if authed_user.id != user_id && !authed_user.has(UserPermission::ManageAny) {
-			let editor = global
-				.user_editor_by_id_loader
-				.load(UserEditorId {
-					editor_id: authed_user.id,
-					user_id,
-				})
-				.await
-				.map_err(|_| ApiError::internal_server_error(ApiErrorCode::LoadError, "failed to load editor"))?
-				.ok_or_else(|| {
-					ApiError::forbidden(
-						ApiErrorCode::LackingPrivileges,
-						"you do not have permission to modify editors",
-					)
-				})?;
-
-			if editor.state != UserEditorState::Accepted || !editor.permissions.has(EditorUserPermission::ManageEditors) {
-				return Err(ApiError::forbidden(
-					ApiErrorCode::LackingPrivileges,
-					"you do not have permission to modify editors, you need the ManageEditors permission",
-				));
-			}
-
+		if let Some(editor) = ensure_can_manage_editors(global, authed_user, user_id).await? {
 			if permissions.is_superset_of(&editor.permissions) {
 				return Err(ApiError::bad_request(
 					ApiErrorCode::BadRequest,
@@ -117,58 +96,66 @@ impl UserEditorMutation {
 			return Err(ApiError::bad_request(ApiErrorCode::BadRequest, "editor already exists"));
 		}
 
-		let res = transaction_with_mutex(global, Some(GeneralMutexKey::User(user_id).into()), |mut tx| async move {
-			let editor_user = global
-				.user_loader
-				.load_fast(global, editor_id)
-				.await
-				.map_err(|_| {
-					TransactionError::Custom(ApiError::internal_server_error(
-						ApiErrorCode::LoadError,
-						"failed to load user",
-					))
-				})?
-				.ok_or_else(|| {
-					TransactionError::Custom(ApiError::internal_server_error(
-						ApiErrorCode::LoadError,
-						"failed to load user",
-					))
-				})?;
-
-			let state = if editor_user.has(FlagPermission::InstantInvite) {
-				UserEditorState::Accepted
-			} else {
-				UserEditorState::Pending
-			};
-
-			let editor = shared::database::user::editor::UserEditor {
-				id: UserEditorId { user_id, editor_id },
-				permissions,
-				updated_at: chrono::Utc::now(),
-				search_updated_at: None,
-				state,
-				notes: None,
-				added_at: chrono::Utc::now(),
-				added_by_id: authed_user.id,
-			};
-
-			tx.insert_one::<shared::database::user::editor::UserEditor>(&editor, None)
-				.await?;
-
-			tx.register_event(InternalEvent {
-				actor: Some(authed_user.clone()),
-				session_id: session.user_session_id(),
-				data: InternalEventData::UserEditor {
-					after: editor.clone(),
-					data: InternalEventUserEditorData::AddEditor {
-						editor: Box::new(editor_user.user),
-					},
-				},
-				timestamp: chrono::Utc::now(),
-			})?;
-
-			Ok(editor)
-		})
+		let res = transaction_with_mutex(
+			global,
+			"v4.user_editor.create",
+			Some(GeneralMutexKey::User(user_id).into()),
+			|mut tx| async move {
+				let editor_user = global
+					.user_loader
+					.load_fast(global, editor_id)
+					.await
+					.map_err(|_| {
+						TransactionError::Custom(ApiError::internal_server_error(
+							ApiErrorCode::LoadError,
+							"failed to load user",
+						))
+					})?
+					.ok_or_else(|| {
+						TransactionError::Custom(ApiError::internal_server_error(
+							ApiErrorCode::LoadError,
+							"failed to load user",
+						))
+					})?;
+
+				let state = if editor_user.has(FlagPermission::InstantInvite) {
+					UserEditorState::Accepted
+				} else {
+					UserEditorState::Pending
+				};
+
+				let editor = shared::database::user::editor::UserEditor {
+					id: UserEditorId { user_id, editor_id },
+					permissions,
+					updated_at: chrono::Utc::now(),
+					search_updated_at: None,
+					state,
+					notes: None,
+					added_at: chrono::Utc::now(),
+					added_by_id: authed_user.id,
+				};
+
+				tx.insert_one::<shared::database::user::editor::UserEditor>(&editor, None)
+					.await?;
+
+				// Pending invites only become editors once the invited user accepts them.
+				if editor.state == UserEditorState::Accepted {
+					tx.register_event(InternalEvent {
+						actor: Some(authed_user.clone()),
+						session_id: session.user_session_id(),
+						data: InternalEventData::UserEditor {
+							after: editor.clone(),
+							data: InternalEventUserEditorData::AddEditor {
+								editor: Box::new(editor_user.user),
+							},
+						},
+						timestamp: chrono::Utc::now(),
+					})?;
+				}
+
+				Ok(editor)
+			},
+		)
 		.await;
 
 		match res {
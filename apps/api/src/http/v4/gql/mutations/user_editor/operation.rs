@@ -3,12 +3,12 @@ use std::sync::Arc;
 use async_graphql::Context;
 use mongodb::options::{FindOneAndUpdateOptions, ReturnDocument};
 use shared::database::queries::{filter, update};
-use shared::database::role::permissions::{PermissionsExt, UserPermission};
-use shared::database::user::editor::{EditorUserPermission, UserEditorId, UserEditorPermissions, UserEditorState};
+use shared::database::user::editor::{UserEditorPermissions, UserEditorState};
 use shared::event::{InternalEvent, InternalEventData, InternalEventUserEditorData};
 
 use super::UserEditorPermissionsInput;
 use crate::global::Global;
+use crate::http::editor::ensure_can_manage_editors;
 use crate::http::error::{ApiError, ApiErrorCode};
 use crate::http::middleware::session::Session;
 use crate::http::v4::gql::types::UserEditor;
@@ -53,35 +53,13 @@ impl UserEditorOperation {
 		}
 
 		// They should be able to remove themselves from the editor list
-		if authed_user.id != self.user_editor.id.user_id
-			&& !authed_user.has(UserPermission::ManageAny)
-			&& authed_user.id != self.user_editor.id.editor_id
-		{
-			let editor = global
-				.user_editor_by_id_loader
-				.load(UserEditorId {
-					editor_id: authed_user.id,
-					user_id: self.user_editor.id.user_id,
-				})
-				.await
-				.map_err(|_| ApiError::internal_server_error(ApiErrorCode::LoadError, "failed to load editor"))?
-				.ok_or_else(|| {
-					ApiError::forbidden(
-						ApiErrorCode::LackingPrivileges,
-						"you do not have permission to modify editors",
-					)
-				})?;
-
-			if editor.state != UserEditorState::Accepted || !editor.permissions.has(EditorUserPermission::ManageEditors) {
-				return Err(ApiError::forbidden(
-					ApiErrorCode::LackingPrivileges,
-					"you do not have permission to modify editors, you need the ManageEditors permission",
-				));
-			}
+		if authed_user.id != self.user_editor.id.editor_id {
+			ensure_can_manage_editors(global, authed_user, self.user_editor.id.user_id).await?;
 		}
 
 		let res = transaction_with_mutex(
 			global,
+			"v4.user_editor.delete",
 			Some(GeneralMutexKey::User(self.user_editor.id.user_id).into()),
 			|mut tx| async move {
 				// Remove editor
@@ -170,9 +148,11 @@ impl UserEditorOperation {
 		}
 
 		let state = shared::database::user::editor::UserEditorState::from(state);
+		let accepted = state == UserEditorState::Accepted;
 
 		let res = transaction_with_mutex(
 			global,
+			"v4.user_editor.update_state",
 			Some(GeneralMutexKey::User(self.user_editor.id.user_id).into()),
 			|mut tx| async move {
 				let editor = tx
@@ -181,6 +161,8 @@ impl UserEditorOperation {
 							shared::database::user::editor::UserEditor {
 								#[query(serde, rename = "_id")]
 								id: self.user_editor.id,
+								#[query(serde)]
+								state: UserEditorState::Pending,
 							}
 						},
 						update::update! {
@@ -197,13 +179,25 @@ impl UserEditorOperation {
 							.build(),
 					)
 					.await?
+					// the invite was accepted, rejected or removed in the meantime
 					.ok_or_else(|| {
-						TransactionError::Custom(ApiError::internal_server_error(
-							ApiErrorCode::LoadError,
-							"failed to update editor",
-						))
+						TransactionError::Custom(ApiError::conflict(ApiErrorCode::BadRequest, "editor is not pending"))
 					})?;
 
+				if accepted {
+					tx.register_event(InternalEvent {
+						actor: Some(authed_user.clone()),
+						session_id: session.user_session_id(),
+						data: InternalEventData::UserEditor {
+							after: editor.clone(),
+							data: InternalEventUserEditorData::AddEditor {
+								editor: Box::new(authed_user.user.clone()),
+							},
+						},
+						timestamp: chrono::Utc::now(),
+					})?;
+				}
+
 				Ok(editor)
 			},
 		)
@@ -249,29 +243,7 @@ impl UserEditorOperation {
 
 		let permissions: UserEditorPermissions = permissions.into();
 
-		if authed_user.id != self.user_editor.id.user_id && !authed_user.has(UserPermission::ManageAny) {
-			let editor = global
-				.user_editor_by_id_loader
-				.load(UserEditorId {
-					editor_id: authed_user.id,
-					user_id: self.user_editor.id.user_id,
-				})
-				.await
-				.map_err(|_| ApiError::internal_server_error(ApiErrorCode::LoadError, "failed to load editor"))?
-				.ok_or_else(|| {
-					ApiError::forbidden(
-						ApiErrorCode::LackingPrivileges,
-						"you do not have permission to modify editors",
-					)
-				})?;
-
-			if editor.state != UserEditorState::Accepted || !editor.permissions.has(EditorUserPermission::ManageEditors) {
-				return Err(ApiError::forbidden(
-					ApiErrorCode::LackingPrivileges,
-					"you do not have permission to modify editors, you need the ManageEditors permission",
-				));
-			}
-
+		if let Some(editor) = ensure_can_manage_editors(global, authed_user, self.user_editor.id.user_id).await? {
 			if permissions.is_superset_of(&editor.permissions) {
 				return Err(ApiError::bad_request(
 					ApiErrorCode::BadRequest,
@@ -282,6 +254,7 @@ impl UserEditorOperation {
 
 		let res = transaction_with_mutex(
 			global,
+			"v4.user_editor.update_permissions",
 			Some(GeneralMutexKey::User(self.user_editor.id.user_id).into()),
 			|mut tx| async move {
 				let editor = tx
@@ -313,6 +286,36 @@ impl UserEditorOperation {
 						))
 					})?;
 
+				let editor_user = global
+					.user_loader
+					.load_fast(global, editor.id.editor_id)
+					.await
+					.map_err(|_| {
+						TransactionError::Custom(ApiError::internal_server_error(
+							ApiErrorCode::LoadError,
+							"failed to load user",
+						))
+					})?
+					.ok_or_else(|| {
+						TransactionError::Custom(ApiError::internal_server_error(
+							ApiErrorCode::LoadError,
+							"failed to load user",
+						))
+					})?;
+
+				tx.register_event(InternalEvent {
+					actor: Some(authed_user.clone()),
+					session_id: session.user_session_id(),
+					data: InternalEventData::UserEditor {
+						after: editor.clone(),
+						data: InternalEventUserEditorData::EditPermissions {
+							editor: Box::new(editor_user.user),
+							old: self.user_editor.permissions,
+						},
+					},
+					timestamp: chrono::Utc::now(),
+				})?;
+
 				Ok(editor)
 			},
 		)
@@ -44,12 +44,14 @@ impl UserSessionMutation {
 			.map_err(|_| ApiError::internal_server_error(ApiErrorCode::LoadError, "failed to load user"))?
 			.ok_or_else(|| ApiError::not_found(ApiErrorCode::LoadError, "user not found"))?;
 
-		let res = transaction(global, |mut tx| async move {
+		let res = transaction(global, "v4.user_session.create", |mut tx| async move {
 			let user_session = UserSession {
 				id: Default::default(),
 				user_id,
 				expires_at,
 				last_used_at: chrono::Utc::now(),
+				user_agent: None,
+				ip_hash: None,
 				extensions: Default::default(),
 			};
 
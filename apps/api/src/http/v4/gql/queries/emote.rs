@@ -10,8 +10,8 @@ use crate::global::Global;
 use crate::http::error::{ApiError, ApiErrorCode};
 use crate::http::guards::RateLimitGuard;
 use crate::http::middleware::session::Session;
-use crate::http::v4::gql::types::{Emote, SearchResult};
-use crate::search::{search, sorted_results, SearchOptions};
+use crate::http::v4::gql::types::{Emote, EmoteTagSearchResult, SearchResult, TagCount};
+use crate::search::{search, search_emotes, sorted_results, EmoteSearchParams, SearchError, SearchOptions};
 
 #[derive(Default)]
 pub struct EmoteQuery;
@@ -94,7 +94,11 @@ impl EmoteQuery {
 			return Ok(None);
 		};
 
-		if !session.has(EmotePermission::ViewUnlisted) && (emote.deleted || emote.merged.is_some()) {
+		// Uploaders can still see their emotes which failed to process, so they know
+		// why the upload failed.
+		let failed_upload = emote.processing_error.is_some() && session.user_id() == Some(emote.owner_id);
+
+		if !session.has(EmotePermission::ViewUnlisted) && !failed_upload && (emote.deleted || emote.merged.is_some()) {
 			return Ok(None);
 		}
 
@@ -245,4 +249,65 @@ impl EmoteQuery {
 
 		Ok(result)
 	}
+
+	/// Searches emotes which have all of the given tags, together with the most
+	/// common tags of the matching emotes.
+	#[allow(clippy::too_many_arguments)]
+	#[graphql(guard = "RateLimitGuard::search(1)")]
+	#[tracing::instrument(skip_all, name = "EmoteQuery::tag_search")]
+	async fn tag_search(
+		&self,
+		ctx: &Context<'_>,
+		#[graphql(validator(max_length = 100))] query: Option<String>,
+		#[graphql(validator(max_items = 10))] tags: Option<Vec<String>>,
+		animated: Option<bool>,
+		nsfw: Option<bool>,
+		#[graphql(validator(maximum = 100))] page: Option<u32>,
+		#[graphql(validator(minimum = 1, maximum = 250))] per_page: Option<u32>,
+	) -> Result<EmoteTagSearchResult, ApiError> {
+		let global: &Arc<Global> = ctx
+			.data()
+			.map_err(|_| ApiError::internal_server_error(ApiErrorCode::MissingContext, "missing global data"))?;
+		let session = ctx
+			.data::<Session>()
+			.map_err(|_| ApiError::internal_server_error(ApiErrorCode::MissingContext, "missing sesion data"))?;
+
+		let params = EmoteSearchParams::builder()
+			.query(query)
+			.tags(tags.unwrap_or_default())
+			.animated(animated)
+			.nsfw(nsfw)
+			.include_unlisted(session.has(EmotePermission::ViewUnlisted))
+			.page(Some(page.unwrap_or_default().max(1)))
+			.per_page(per_page)
+			.build();
+
+		let per_page = params.per_page();
+
+		let result = search_emotes(global, params).await.map_err(|err| match err {
+			SearchError::InvalidParams(err) => ApiError::bad_request(ApiErrorCode::BadRequest, err.to_string()),
+			err => {
+				tracing::error!(error = %err, "failed to search");
+				ApiError::internal_server_error(ApiErrorCode::LoadError, "failed to search")
+			}
+		})?;
+
+		Ok(EmoteTagSearchResult {
+			items: result
+				.emotes
+				.into_iter()
+				.map(|e| Emote::from_db(e, &global.config.api.cdn_origin))
+				.collect(),
+			total_count: result.found,
+			page_count: result.found.div_ceil(per_page as u64).min(100),
+			tags: result
+				.tags
+				.into_iter()
+				.map(|t| TagCount {
+					tag: t.value,
+					count: t.count,
+				})
+				.collect(),
+		})
+	}
 }
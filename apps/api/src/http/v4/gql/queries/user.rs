@@ -1,13 +1,14 @@
 use std::sync::Arc;
 
 use async_graphql::{Context, Object};
+use shared::database::role::permissions::AdminPermission;
 use shared::database::user::UserId;
 
 use crate::global::Global;
 use crate::http::error::{ApiError, ApiErrorCode};
-use crate::http::guards::RateLimitGuard;
+use crate::http::guards::{PermissionGuard, RateLimitGuard};
 use crate::http::middleware::session::Session;
-use crate::http::v4::gql::types::{Platform, SearchResult, User};
+use crate::http::v4::gql::types::{PermissionExplanation, Platform, SearchResult, User};
 use crate::search::{search, sorted_results, SearchOptions};
 
 #[derive(Default)]
@@ -58,6 +59,35 @@ impl UserQuery {
 		Ok(session.can_view(&user).then(|| user.into()))
 	}
 
+	#[graphql(guard = "PermissionGuard::one(AdminPermission::Admin)")]
+	#[tracing::instrument(skip_all, name = "UserQuery::explain_permissions")]
+	async fn explain_permissions(
+		&self,
+		ctx: &Context<'_>,
+		id: UserId,
+	) -> Result<Option<Vec<PermissionExplanation>>, ApiError> {
+		let global: &Arc<Global> = ctx
+			.data()
+			.map_err(|_| ApiError::internal_server_error(ApiErrorCode::MissingContext, "missing global data"))?;
+
+		let Some(user) = global
+			.user_loader
+			.load(global, id)
+			.await
+			.map_err(|()| ApiError::internal_server_error(ApiErrorCode::LoadError, "failed to load user"))?
+		else {
+			return Ok(None);
+		};
+
+		let explanations = global
+			.user_loader
+			.explain_permissions(global, &user)
+			.await
+			.map_err(|()| ApiError::internal_server_error(ApiErrorCode::LoadError, "failed to load roles"))?;
+
+		Ok(Some(explanations.into_iter().map(Into::into).collect()))
+	}
+
 	#[tracing::instrument(skip_all, name = "UserQuery::user_by_connection")]
 	async fn user_by_connection(
 		&self,
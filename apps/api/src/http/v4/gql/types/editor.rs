@@ -87,6 +87,16 @@ impl From<shared::database::user::editor::UserEditorState> for UserEditorState {
 	}
 }
 
+impl From<UserEditorState> for shared::database::user::editor::UserEditorState {
+	fn from(value: UserEditorState) -> Self {
+		match value {
+			UserEditorState::Pending => Self::Pending,
+			UserEditorState::Accepted => Self::Accepted,
+			UserEditorState::Rejected => Self::Rejected,
+		}
+	}
+}
+
 #[derive(Debug, Clone, async_graphql::SimpleObject)]
 pub struct UserEditorPermissions {
 	pub super_admin: bool,
@@ -28,6 +28,9 @@ pub struct Emote {
 	pub attribution: Vec<EmoteAttribution>,
 	pub scores: EmoteScores,
 	pub deleted: bool,
+	/// Why processing the uploaded image failed, only set when the emote was
+	/// deleted because of it
+	pub processing_error: Option<String>,
 	pub updated_at: chrono::DateTime<chrono::Utc>,
 	pub search_updated_at: Option<chrono::DateTime<chrono::Utc>>,
 }
@@ -235,6 +238,7 @@ impl Emote {
 			attribution: value.attribution.into_iter().map(Into::into).collect(),
 			scores: value.scores.into(),
 			deleted: value.deleted,
+			processing_error: value.processing_error.map(|e| e.reason().to_string()),
 			updated_at: value.updated_at,
 			search_updated_at: value.search_updated_at,
 		}
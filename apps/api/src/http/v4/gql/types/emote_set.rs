@@ -153,6 +153,17 @@ impl From<shared::database::emote_set::EmoteSetKind> for EmoteSetKind {
 	}
 }
 
+impl From<EmoteSetKind> for shared::database::emote_set::EmoteSetKind {
+	fn from(value: EmoteSetKind) -> Self {
+		match value {
+			EmoteSetKind::Normal => Self::Normal,
+			EmoteSetKind::Personal => Self::Personal,
+			EmoteSetKind::Global => Self::Global,
+			EmoteSetKind::Special => Self::Special,
+		}
+	}
+}
+
 #[derive(Debug, Clone, async_graphql::SimpleObject)]
 pub struct EmoteSetEmote {
 	pub id: EmoteId,
@@ -60,6 +60,7 @@ pub enum ImageProcessorEvent {
 	Fail,
 	Cancel,
 	Start,
+	Resubmit,
 }
 
 impl From<shared::database::stored_event::ImageProcessorEvent> for ImageProcessorEvent {
@@ -69,6 +70,7 @@ impl From<shared::database::stored_event::ImageProcessorEvent> for ImageProcesso
 			shared::database::stored_event::ImageProcessorEvent::Fail { .. } => Self::Fail,
 			shared::database::stored_event::ImageProcessorEvent::Cancel => Self::Cancel,
 			shared::database::stored_event::ImageProcessorEvent::Start => Self::Start,
+			shared::database::stored_event::ImageProcessorEvent::Resubmit { .. } => Self::Resubmit,
 		}
 	}
 }
@@ -18,6 +18,7 @@ pub enum EventUserData {
 	ChangeActiveEmoteSet(EventUserDataChangeActiveEmoteSet),
 	AddConnection(EventUserDataAddConnection),
 	RemoveConnection(EventUserDataRemoveConnection),
+	UpdateConnection(EventUserDataUpdateConnection),
 	// AddEntitlement(EventUserDataAddEntitlement),
 	// RemoveEntitlement(EventUserDataRemoveEntitlement),
 	// Merge(EventUserDataMerge),
@@ -56,6 +57,11 @@ impl TryFrom<StoredEventUserData> for EventUserData {
 					platform: platform.into(),
 				}))
 			}
+			StoredEventUserData::UpdateConnection { platform } => {
+				Ok(Self::UpdateConnection(EventUserDataUpdateConnection {
+					platform: platform.into(),
+				}))
+			}
 			StoredEventUserData::Delete => Ok(Self::Delete(EventUserDataDelete::default())),
 			_ => Err(()),
 		}
@@ -231,6 +237,12 @@ pub struct EventUserDataRemoveConnection {
 	pub platform: Platform,
 }
 
+#[derive(async_graphql::SimpleObject)]
+pub struct EventUserDataUpdateConnection {
+	#[graphql(name = "updatedPlatform")]
+	pub platform: Platform,
+}
+
 // #[derive(async_graphql::SimpleObject)]
 // pub struct EventUserDataAddEntitlement {
 // 	#[graphql(name = "addedTarget")]
@@ -1,6 +1,9 @@
 use std::collections::HashMap;
 
 use shared::database::role::permissions::{self, PermissionsExt, RateLimits};
+use shared::database::role::RoleId;
+
+use crate::dataloader::full_user::PermissionProvenance;
 
 #[derive(async_graphql::SimpleObject)]
 pub struct Permissions {
@@ -254,3 +257,60 @@ impl AdminPermission {
 		}
 	}
 }
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, async_graphql::Enum)]
+pub enum PermissionDecision {
+	GlobalAdmin,
+	CategoryAdmin,
+	Allowed,
+	Denied,
+	Unset,
+}
+
+impl From<permissions::PermissionDecision> for PermissionDecision {
+	fn from(value: permissions::PermissionDecision) -> Self {
+		match value {
+			permissions::PermissionDecision::GlobalAdmin => Self::GlobalAdmin,
+			permissions::PermissionDecision::CategoryAdmin => Self::CategoryAdmin,
+			permissions::PermissionDecision::Allowed => Self::Allowed,
+			permissions::PermissionDecision::Denied => Self::Denied,
+			permissions::PermissionDecision::Unset => Self::Unset,
+		}
+	}
+}
+
+#[derive(async_graphql::SimpleObject)]
+pub struct PermissionExplanation {
+	pub category: String,
+	pub bit: i32,
+	pub allowed: bool,
+	pub decision: PermissionDecision,
+	/// Every role which allows or denies this permission, in the order they are
+	/// merged
+	pub roles: Vec<RolePermissionDecision>,
+}
+
+#[derive(async_graphql::SimpleObject)]
+pub struct RolePermissionDecision {
+	pub role_id: RoleId,
+	pub decision: PermissionDecision,
+}
+
+impl From<PermissionProvenance> for PermissionExplanation {
+	fn from(value: PermissionProvenance) -> Self {
+		Self {
+			category: value.explanation.permission.category().to_owned(),
+			bit: value.explanation.permission.bits(),
+			allowed: value.explanation.allowed,
+			decision: value.explanation.decision.into(),
+			roles: value
+				.roles
+				.into_iter()
+				.map(|(role_id, decision)| RolePermissionDecision {
+					role_id,
+					decision: decision.into(),
+				})
+				.collect(),
+		}
+	}
+}
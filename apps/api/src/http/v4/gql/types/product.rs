@@ -1,4 +1,3 @@
-use std::collections::HashMap;
 use std::str::FromStr;
 use std::sync::Arc;
 
@@ -20,6 +19,7 @@ pub struct SubscriptionProduct {
 	pub name: String,
 	pub description: Option<String>,
 	pub benefits: Vec<SubscriptionBenefit>,
+	pub personal_emote_set_capacity: i32,
 	pub updated_at: chrono::DateTime<chrono::Utc>,
 	pub search_updated_at: Option<chrono::DateTime<chrono::Utc>>,
 
@@ -64,6 +64,9 @@ impl From<shared::database::product::SubscriptionProduct> for SubscriptionProduc
 			name: value.name,
 			description: value.description,
 			benefits: value.benefits.into_iter().map(Into::into).collect(),
+			personal_emote_set_capacity: value
+				.personal_emote_set_capacity
+				.unwrap_or(shared::database::product::SubscriptionProduct::DEFAULT_PERSONAL_EMOTE_SET_CAPACITY),
 			updated_at: value.updated_at,
 			search_updated_at: value.search_updated_at,
 			default_currency: value.default_currency,
@@ -81,7 +84,7 @@ pub struct SubscriptionProductVariant {
 	pub kind: SubscriptionProductKind,
 
 	#[graphql(skip)]
-	pub currency_prices: HashMap<stripe::Currency, i64>,
+	pub variant: shared::database::product::SubscriptionProductVariant,
 	#[graphql(skip)]
 	pub default_currency: stripe::Currency,
 }
@@ -118,22 +121,15 @@ impl SubscriptionProductVariant {
 				.map_err(|_| ApiError::bad_request(ApiErrorCode::BadRequest, "invalid currency"))?;
 		}
 
-		match self.currency_prices.get(&currency) {
-			Some(c) => Ok(Price {
-				currency: currency.to_string(),
-				amount: *c,
-			}),
-			None => {
-				let amount = self.currency_prices.get(&self.default_currency).copied().ok_or_else(|| {
-					ApiError::internal_server_error(ApiErrorCode::LoadError, "failed to load default price")
-				})?;
-
-				Ok(Price {
-					currency: self.default_currency.to_string(),
-					amount,
-				})
-			}
-		}
+		let (currency, amount) = self
+			.variant
+			.price_in(currency, self.default_currency)
+			.ok_or_else(|| ApiError::internal_server_error(ApiErrorCode::LoadError, "failed to load default price"))?;
+
+		Ok(Price {
+			currency: currency.to_string(),
+			amount,
+		})
 	}
 }
 
@@ -143,10 +139,10 @@ impl SubscriptionProductVariant {
 		default_currency: stripe::Currency,
 	) -> Self {
 		Self {
-			id: variant.id,
-			paypal_id: variant.paypal_id,
-			kind: variant.kind.into(),
-			currency_prices: variant.currency_prices,
+			id: variant.id.clone(),
+			paypal_id: variant.paypal_id.clone(),
+			kind: variant.kind.clone().into(),
+			variant,
 			default_currency,
 		}
 	}
@@ -12,3 +12,18 @@ pub struct SearchResult<T: OutputType> {
 	pub total_count: u64,
 	pub page_count: u64,
 }
+
+#[derive(SimpleObject)]
+pub struct EmoteTagSearchResult {
+	pub items: Vec<Emote>,
+	pub total_count: u64,
+	pub page_count: u64,
+	/// The most common tags of all matching emotes
+	pub tags: Vec<TagCount>,
+}
+
+#[derive(SimpleObject)]
+pub struct TagCount {
+	pub tag: String,
+	pub count: u64,
+}
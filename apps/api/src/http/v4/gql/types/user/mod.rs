@@ -1,18 +1,26 @@
+use std::future::IntoFuture;
 use std::sync::Arc;
 
+use async_graphql::connection::{Connection, CursorType, Edge, OpaqueCursor};
 use async_graphql::{ComplexObject, Context, SimpleObject};
+use futures::{TryFutureExt, TryStreamExt};
 use itertools::Itertools;
+use mongodb::bson::doc;
+use shared::database::emote::EmoteId;
 use shared::database::emote_set::{EmoteSetId, EmoteSetKind};
 use shared::database::product::{CustomerId, SubscriptionProductId};
+use shared::database::queries::filter;
 use shared::database::role::permissions::{PermissionsExt, UserPermission};
 use shared::database::role::RoleId;
-use shared::database::user::editor::EditorEmoteSetPermission;
+use shared::database::user::editor::{EditorEmoteSetPermission, UserEditorId};
 use shared::database::user::UserId;
+use shared::database::MongoCollection;
 use shared::typesense::types::event::EventId;
 
 use super::raw_entitlement::RawEntitlements;
-use super::{AnyEvent, Color, Emote, EmoteSet, Event, Permissions, Role, UserEditor, UserEvent};
+use super::{AnyEvent, Color, Emote, EmoteSet, Event, Permissions, Role, UserEditor, UserEditorState, UserEvent};
 use crate::global::Global;
+use crate::http::editor::{capped_editors, editor_order_key};
 use crate::http::error::{ApiError, ApiErrorCode};
 use crate::http::guards::{PermissionGuard, RateLimitGuard};
 use crate::http::middleware::session::Session;
@@ -21,17 +29,130 @@ use crate::search::{search, sorted_results, SearchOptions};
 pub mod billing;
 pub mod connection;
 pub mod inventory;
+pub mod session;
 pub mod style;
 
 pub use connection::*;
 pub use inventory::*;
+pub use session::*;
 pub use style::*;
 
+/// Keeps the editors in one of the given states, only accepted editors are
+/// returned unless other states are requested.
+fn with_states(
+	mut editors: Vec<shared::database::user::editor::UserEditor>,
+	states: Option<Vec<UserEditorState>>,
+) -> Vec<shared::database::user::editor::UserEditor> {
+	let states = states.unwrap_or_else(|| vec![UserEditorState::Accepted]);
+	editors.retain(|e| states.contains(&e.state.clone().into()));
+	editors
+}
+
+type EditorCursor = OpaqueCursor<(chrono::DateTime<chrono::Utc>, UserId, UserId)>;
+
+/// Returns a page of the editors matching `filter` in one of the given states,
+/// ordered by when they were added.
+async fn paginate_editors(
+	global: &Arc<Global>,
+	filter: filter::Filter<shared::database::user::editor::UserEditor>,
+	states: Option<Vec<UserEditorState>>,
+	after: Option<String>,
+	first: Option<u32>,
+) -> Result<Connection<EditorCursor, UserEditor>, ApiError> {
+	let after = after
+		.map(|cursor| EditorCursor::decode_cursor(&cursor))
+		.transpose()
+		.map_err(|_| ApiError::bad_request(ApiErrorCode::BadRequest, "invalid cursor"))?;
+	let first = first.unwrap_or(50) as usize;
+
+	let states: Vec<shared::database::user::editor::UserEditorState> = states
+		.unwrap_or_else(|| vec![UserEditorState::Accepted])
+		.into_iter()
+		.map(Into::into)
+		.collect();
+
+	let mut filters = vec![
+		filter,
+		filter::filter! {
+			shared::database::user::editor::UserEditor {
+				#[query(selector = "in", serde)]
+				state: states,
+			}
+		}
+		.into(),
+	];
+
+	// Editors are ordered by editor_order_key, so the page starts after the key of
+	// the last editor of the previous page
+	if let Some(OpaqueCursor((added_at, user_id, editor_id))) = after {
+		let cursor: [filter::Filter<shared::database::user::editor::UserEditor>; 3] = [
+			filter::filter! {
+				shared::database::user::editor::UserEditor {
+					#[query(selector = "gt")]
+					added_at: added_at,
+				}
+			}
+			.into(),
+			filter::filter! {
+				shared::database::user::editor::UserEditor {
+					added_at: added_at,
+					#[query(rename = "_id", flatten)]
+					id: UserEditorId {
+						#[query(selector = "gt")]
+						user_id: user_id,
+					},
+				}
+			}
+			.into(),
+			filter::filter! {
+				shared::database::user::editor::UserEditor {
+					added_at: added_at,
+					#[query(rename = "_id", flatten)]
+					id: UserEditorId {
+						user_id: user_id,
+						#[query(selector = "gt")]
+						editor_id: editor_id,
+					},
+				}
+			}
+			.into(),
+		];
+
+		filters.push(filter::Filter::or(cursor));
+	}
+
+	// Fetch one more editor than requested to know if there is a next page
+	let mut editors: Vec<_> = shared::database::user::editor::UserEditor::collection(&global.db)
+		.find(filter::Filter::and(filters))
+		.sort(doc! { "added_at": 1, "_id.user_id": 1, "_id.editor_id": 1 })
+		.limit(first as i64 + 1)
+		.into_future()
+		.and_then(|f| f.try_collect::<Vec<shared::database::user::editor::UserEditor>>())
+		.await
+		.map_err(|e| {
+			tracing::error!(error = %e, "failed to query editors");
+			ApiError::internal_server_error(ApiErrorCode::LoadError, "failed to query editors")
+		})?;
+
+	let has_next_page = editors.len() > first;
+	editors.truncate(first);
+
+	let mut connection = Connection::new(after.is_some(), has_next_page);
+	connection.edges.extend(
+		editors
+			.into_iter()
+			.map(|e| Edge::new(OpaqueCursor(editor_order_key(&e)), UserEditor::from(e))),
+	);
+
+	Ok(connection)
+}
+
 #[derive(Debug, Clone, SimpleObject)]
 #[graphql(complex)]
 pub struct User {
 	pub id: UserId,
 	pub connections: Vec<UserConnection>,
+	pub biography: String,
 	#[graphql(guard = "PermissionGuard::one(UserPermission::ManageBilling)")]
 	pub stripe_customer_id: Option<CustomerId>,
 	pub updated_at: chrono::DateTime<chrono::Utc>,
@@ -48,12 +169,16 @@ pub struct User {
 
 #[ComplexObject]
 impl User {
+	#[tracing::instrument(skip_all, name = "User::created_at")]
+	async fn created_at(&self) -> chrono::DateTime<chrono::Utc> {
+		self.id.timestamp()
+	}
+
 	#[tracing::instrument(skip_all, name = "User::main_connection")]
 	async fn main_connection(&self) -> Option<&UserConnection> {
 		self.connections.first()
 	}
 
-	// TODO: Does it make sense to paginate this?
 	#[tracing::instrument(skip_all, name = "User::owned_emotes")]
 	async fn owned_emotes(&self, ctx: &Context<'_>) -> Result<Vec<Emote>, ApiError> {
 		let global: &Arc<Global> = ctx
@@ -75,6 +200,73 @@ impl User {
 			.collect())
 	}
 
+	#[tracing::instrument(skip_all, name = "User::owned_emotes_connection")]
+	async fn owned_emotes_connection(
+		&self,
+		ctx: &Context<'_>,
+		after: Option<String>,
+		#[graphql(validator(minimum = 1, maximum = 100))] first: Option<u32>,
+	) -> Result<Connection<OpaqueCursor<EmoteId>, Emote>, ApiError> {
+		let global: &Arc<Global> = ctx
+			.data()
+			.map_err(|_| ApiError::internal_server_error(ApiErrorCode::MissingContext, "missing global data"))?;
+
+		let after = after
+			.map(|cursor| OpaqueCursor::<EmoteId>::decode_cursor(&cursor))
+			.transpose()
+			.map_err(|_| ApiError::bad_request(ApiErrorCode::BadRequest, "invalid cursor"))?;
+		let first = first.unwrap_or(50) as usize;
+
+		let mut filters: Vec<filter::Filter<shared::database::emote::Emote>> = vec![filter::filter! {
+			shared::database::emote::Emote {
+				owner_id: self.id,
+				deleted: false,
+				#[query(serde)]
+				merged: &None,
+			}
+		}
+		.into()];
+
+		// Emotes are ordered by id, so the page starts after the last id of the
+		// previous page
+		if let Some(after) = &after {
+			filters.push(
+				filter::filter! {
+					shared::database::emote::Emote {
+						#[query(rename = "_id", selector = "gt")]
+						id: after.0,
+					}
+				}
+				.into(),
+			);
+		}
+
+		// Fetch one more emote than requested to know if there is a next page
+		let mut emotes: Vec<_> = shared::database::emote::Emote::collection(&global.db)
+			.find(filter::Filter::and(filters))
+			.sort(doc! { "_id": 1 })
+			.limit(first as i64 + 1)
+			.into_future()
+			.and_then(|f| f.try_collect::<Vec<shared::database::emote::Emote>>())
+			.await
+			.map_err(|e| {
+				tracing::error!(error = %e, "failed to query emotes");
+				ApiError::internal_server_error(ApiErrorCode::LoadError, "failed to query emotes")
+			})?;
+
+		let has_next_page = emotes.len() > first;
+		emotes.truncate(first);
+
+		let mut connection = Connection::new(after.is_some(), has_next_page);
+		connection.edges.extend(
+			emotes
+				.into_iter()
+				.map(|e| Edge::new(OpaqueCursor(e.id), Emote::from_db(e, &global.config.api.cdn_origin))),
+		);
+
+		Ok(connection)
+	}
+
 	#[tracing::instrument(skip_all, name = "User::owned_emote_sets")]
 	async fn owned_emote_sets(&self, ctx: &Context<'_>) -> Result<Vec<EmoteSet>, ApiError> {
 		let global: &Arc<Global> = ctx
@@ -206,8 +398,53 @@ impl User {
 		Ok(Permissions::from(self.full_user.computed.permissions.clone()))
 	}
 
+	#[tracing::instrument(skip_all, name = "User::sessions")]
+	async fn sessions(&self, ctx: &Context<'_>) -> Result<Vec<UserSession>, ApiError> {
+		let global: &Arc<Global> = ctx
+			.data()
+			.map_err(|_| ApiError::internal_server_error(ApiErrorCode::MissingContext, "missing global data"))?;
+		let session = ctx
+			.data::<Session>()
+			.map_err(|_| ApiError::internal_server_error(ApiErrorCode::MissingContext, "missing session data"))?;
+		let authed_user = session.user()?;
+
+		if authed_user.id != self.id && !authed_user.has(UserPermission::ManageSessions) {
+			return Err(ApiError::forbidden(
+				ApiErrorCode::LackingPrivileges,
+				"you are not allowed to see this user's sessions",
+			));
+		}
+
+		let mut sessions: Vec<_> = shared::database::user::session::UserSession::collection(&global.db)
+			.find(filter::filter! {
+				shared::database::user::session::UserSession {
+					user_id: self.id,
+					#[query(selector = "gt")]
+					expires_at: chrono::Utc::now(),
+				}
+			})
+			.await
+			.map_err(|e| {
+				tracing::error!(error = %e, "failed to load sessions");
+				ApiError::internal_server_error(ApiErrorCode::LoadError, "failed to load sessions")
+			})?
+			.try_collect()
+			.await
+			.map_err(|e| {
+				tracing::error!(error = %e, "failed to load sessions");
+				ApiError::internal_server_error(ApiErrorCode::LoadError, "failed to load sessions")
+			})?;
+
+		sessions.sort_by_key(|s| std::cmp::Reverse(s.last_used_at));
+
+		Ok(sessions
+			.into_iter()
+			.map(|s| UserSession::from_db(s, session.user_session_id()))
+			.collect())
+	}
+
 	#[tracing::instrument(skip_all, name = "User::editors")]
-	async fn editors(&self, ctx: &Context<'_>) -> Result<Vec<UserEditor>, ApiError> {
+	async fn editors(&self, ctx: &Context<'_>, states: Option<Vec<UserEditorState>>) -> Result<Vec<UserEditor>, ApiError> {
 		let global: &Arc<Global> = ctx
 			.data()
 			.map_err(|_| ApiError::internal_server_error(ApiErrorCode::MissingContext, "missing global data"))?;
@@ -219,11 +456,18 @@ impl User {
 			.map_err(|()| ApiError::internal_server_error(ApiErrorCode::LoadError, "failed to load editors"))?
 			.unwrap_or_default();
 
-		Ok(editors.into_iter().map(Into::into).collect())
+		Ok(capped_editors(with_states(editors, states))
+			.into_iter()
+			.map(Into::into)
+			.collect())
 	}
 
 	#[tracing::instrument(skip_all, name = "User::editor_for")]
-	async fn editor_for(&self, ctx: &Context<'_>) -> Result<Vec<UserEditor>, ApiError> {
+	async fn editor_for(
+		&self,
+		ctx: &Context<'_>,
+		states: Option<Vec<UserEditorState>>,
+	) -> Result<Vec<UserEditor>, ApiError> {
 		let global: &Arc<Global> = ctx
 			.data()
 			.map_err(|_| ApiError::internal_server_error(ApiErrorCode::MissingContext, "missing global data"))?;
@@ -235,7 +479,70 @@ impl User {
 			.map_err(|()| ApiError::internal_server_error(ApiErrorCode::LoadError, "failed to load editors"))?
 			.unwrap_or_default();
 
-		Ok(editors.into_iter().map(Into::into).collect())
+		Ok(capped_editors(with_states(editors, states))
+			.into_iter()
+			.map(Into::into)
+			.collect())
+	}
+
+	#[tracing::instrument(skip_all, name = "User::editors_connection")]
+	async fn editors_connection(
+		&self,
+		ctx: &Context<'_>,
+		states: Option<Vec<UserEditorState>>,
+		after: Option<String>,
+		#[graphql(validator(minimum = 1, maximum = 100))] first: Option<u32>,
+	) -> Result<Connection<EditorCursor, UserEditor>, ApiError> {
+		let global: &Arc<Global> = ctx
+			.data()
+			.map_err(|_| ApiError::internal_server_error(ApiErrorCode::MissingContext, "missing global data"))?;
+
+		paginate_editors(
+			global,
+			filter::filter! {
+				shared::database::user::editor::UserEditor {
+					#[query(rename = "_id", flatten)]
+					id: UserEditorId {
+						user_id: self.id,
+					},
+				}
+			}
+			.into(),
+			states,
+			after,
+			first,
+		)
+		.await
+	}
+
+	#[tracing::instrument(skip_all, name = "User::editor_for_connection")]
+	async fn editor_for_connection(
+		&self,
+		ctx: &Context<'_>,
+		states: Option<Vec<UserEditorState>>,
+		after: Option<String>,
+		#[graphql(validator(minimum = 1, maximum = 100))] first: Option<u32>,
+	) -> Result<Connection<EditorCursor, UserEditor>, ApiError> {
+		let global: &Arc<Global> = ctx
+			.data()
+			.map_err(|_| ApiError::internal_server_error(ApiErrorCode::MissingContext, "missing global data"))?;
+
+		paginate_editors(
+			global,
+			filter::filter! {
+				shared::database::user::editor::UserEditor {
+					#[query(rename = "_id", flatten)]
+					id: UserEditorId {
+						editor_id: self.id,
+					},
+				}
+			}
+			.into(),
+			states,
+			after,
+			first,
+		)
+		.await
 	}
 
 	#[tracing::instrument(skip_all, name = "User::editable_emote_set_ids")]
@@ -429,6 +736,7 @@ impl From<shared::database::user::FullUser> for User {
 		Self {
 			id: value.id,
 			connections: value.connections.iter().cloned().map(Into::into).collect(),
+			biography: value.biography.clone(),
 			stripe_customer_id: value.stripe_customer_id.clone(),
 			updated_at: value.updated_at,
 			search_updated_at: value.search_updated_at,
@@ -0,0 +1,27 @@
+use shared::database::user::session::UserSessionId;
+
+#[derive(Debug, Clone, async_graphql::SimpleObject)]
+pub struct UserSession {
+	pub id: UserSessionId,
+	pub created_at: chrono::DateTime<chrono::Utc>,
+	pub expires_at: chrono::DateTime<chrono::Utc>,
+	pub last_used_at: chrono::DateTime<chrono::Utc>,
+	pub user_agent: Option<String>,
+	pub ip_hash: Option<String>,
+	/// Whether this is the session making the request
+	pub current: bool,
+}
+
+impl UserSession {
+	pub fn from_db(value: shared::database::user::session::UserSession, current: Option<UserSessionId>) -> Self {
+		Self {
+			id: value.id,
+			created_at: value.id.timestamp(),
+			expires_at: value.expires_at,
+			last_used_at: value.last_used_at,
+			user_agent: value.user_agent,
+			ip_hash: value.ip_hash,
+			current: current == Some(value.id),
+		}
+	}
+}
@@ -21,7 +21,7 @@ use crate::connections;
 use crate::global::Global;
 use crate::http::error::{ApiError, ApiErrorCode};
 use crate::http::middleware::cookies::{new_cookie, Cookies};
-use crate::http::middleware::session::{parse_session, Session, AUTH_COOKIE};
+use crate::http::middleware::session::{parse_session, user_agent, Session, AUTH_COOKIE};
 use crate::jwt::{AuthJwtPayload, JwtState};
 use crate::ratelimit::RateLimitRequest;
 use crate::transactions::{transaction, TransactionError};
@@ -150,7 +150,7 @@ async fn login_inner(
 
 	req.http(global, async {
 		// redirect to platform auth url
-		let (url, scope, config) = match platform {
+		let (url, default_scope, config) = match platform {
 			Platform::Twitch if global.config.connections.twitch.enabled => {
 				(TWITCH_AUTH_URL, TWITCH_AUTH_SCOPE, &global.config.connections.twitch)
 			}
@@ -168,6 +168,8 @@ async fn login_inner(
 			}
 		};
 
+		let scope = config.scope.as_deref().unwrap_or(default_scope);
+
 		let (pkce_challenge, pkce_verifier) = oauth2::PkceCodeChallenge::new_random_sha256();
 
 		cookies.add(
@@ -234,6 +236,7 @@ struct LoginFinishResponse {
 async fn login_finish(
 	State(global): State<Arc<Global>>,
 	Extension(cookies): Extension<Cookies>,
+	Extension(session): Extension<Session>,
 	headers: HeaderMap,
 	Json(payload): Json<LoginFinishPayload>,
 ) -> Result<impl IntoResponse, ApiError> {
@@ -273,8 +276,9 @@ async fn login_finish(
 
 	// query user data from platform
 	let user_data = connections::get_user_data(&global, platform, &token.access_token).await?;
+	let platform_id = user_data.id.clone();
 
-	let user = transaction(&global, |mut tx| async move {
+	let user = transaction(&global, "v4.auth.login_finish", |mut tx| async move {
 		let user = tx
 			.find_one(
 				filter::filter! {
@@ -446,13 +450,20 @@ async fn login_finish(
 		return Err(ApiError::forbidden(ApiErrorCode::LackingPrivileges, "not allowed to login"));
 	}
 
-	let res = transaction(&Arc::clone(&global), |mut tx| async move {
+	let user_id = full_user.id;
+	let user_agent = user_agent(&headers);
+	let ip_hash = session.ip_hash(&global);
+
+	let global = &global;
+
+	let res = transaction(global, "v4.auth.login_finish_session", |mut tx| async move {
 		let user_session = UserSession {
 			id: Default::default(),
 			user_id: full_user.id,
-			// TODO: maybe allow for this to be configurable
-			expires_at: chrono::Utc::now() + chrono::Duration::days(30),
+			expires_at: chrono::Utc::now() + global.config.auth.session_duration(full_user.has(UserPermission::Admin)),
 			last_used_at: chrono::Utc::now(),
+			user_agent,
+			ip_hash: Some(ip_hash),
 			extensions: bson::Document::new(),
 		};
 
@@ -485,7 +496,10 @@ async fn login_finish(
 	.await;
 
 	match res {
-		Ok(response) => Ok(Json(response)),
+		Ok(response) => {
+			connections::refresh::spawn_refresh(global, user_id, platform, &platform_id);
+			Ok(Json(response))
+		}
 		Err(TransactionError::Custom(e)) => Err(e),
 		Err(e) => {
 			tracing::error!(error = %e, "transaction failed");
@@ -546,7 +560,7 @@ async fn link_finish(
 	// query user data from platform
 	let user_data = connections::get_user_data(&global, platform, &token.access_token).await?;
 
-	let user = transaction(&global, |mut tx| async move {
+	let user = transaction(&global, "v4.auth.link_finish", |mut tx| async move {
 		if tx
 			.find_one(
 				filter::filter! {
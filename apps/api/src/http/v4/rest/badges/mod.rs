@@ -14,6 +14,7 @@ use shared::database::image_set::{ImageSet, ImageSetInput};
 use shared::database::role::permissions::{AdminPermission, PermissionsExt, RateLimitResource};
 use shared::database::stored_event::StoredEventBadgeData;
 use shared::event::{InternalEvent, InternalEventData};
+use shared::image_processor::ProcessPriority;
 use tracing::Instrument;
 
 use crate::global::Global;
@@ -113,7 +114,7 @@ pub async fn create_badge(
 
 		let input = match global
 			.image_processor
-			.upload_badge(badge_id, data.file)
+			.upload_badge(badge_id, data.file, ProcessPriority::Normal)
 			.instrument(tracing::info_span!("image_processor_upload"))
 			.await
 		{
@@ -131,6 +132,7 @@ pub async fn create_badge(
 				path: path.path,
 				mime: content_type,
 				size: size as i64,
+				resubmits: 0,
 			},
 			Ok(ProcessImageResponse { error: Some(err), .. }) => {
 				// At this point if we get a decode error then the image is invalid
@@ -163,7 +165,7 @@ pub async fn create_badge(
 			}
 		};
 
-		let res = transaction(&global, |mut tx| async move {
+		let res = transaction(&global, "v4.badges.create", |mut tx| async move {
 			let badge = Badge {
 				id: badge_id,
 				name: data.metadata.name,
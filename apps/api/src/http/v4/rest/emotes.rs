@@ -6,23 +6,26 @@ use axum::response::IntoResponse;
 use axum::routing::post;
 use axum::{Extension, Json, Router};
 use bytes::Bytes;
-use hyper::StatusCode;
+use hyper::{HeaderMap, StatusCode};
 use image_processor::{ProcessImageResponse, ProcessImageResponseUploadInfo};
 use image_processor_proto as image_processor;
 use shared::database::emote::{Emote, EmoteFlags, EmoteId};
 use shared::database::emote_moderation_request::{
 	EmoteModerationRequest, EmoteModerationRequestKind, EmoteModerationRequestStatus,
 };
+use shared::database::idempotency_key::IdempotentResult;
 use shared::database::image_set::{ImageSet, ImageSetInput};
 use shared::database::queries::filter;
 use shared::database::role::permissions::{EmotePermission, PermissionsExt, RateLimitResource};
 use shared::database::stored_event::StoredEventEmoteData;
 use shared::database::MongoCollection;
 use shared::event::{InternalEvent, InternalEventData};
+use shared::image_processor::ProcessPriority;
 use tracing::Instrument;
 
 use crate::global::Global;
 use crate::http::error::{ApiError, ApiErrorCode};
+use crate::http::idempotency::{self, idempotency_key};
 use crate::http::middleware::session::Session;
 use crate::http::validators;
 use crate::ratelimit::RateLimitRequest;
@@ -99,12 +102,15 @@ struct CreateEmoteResponse {
 pub async fn create_emote(
 	State(global): State<Arc<Global>>,
 	Extension(session): Extension<Session>,
+	headers: HeaderMap,
 	multipart: Multipart,
 ) -> Result<impl IntoResponse, ApiError> {
 	let data = parse_multipart(multipart).await?;
 
 	let authed_user = session.user()?;
 
+	let idempotency_key = idempotency_key(&headers, authed_user.id)?;
+
 	if !session.has(EmotePermission::Upload) {
 		return Err(ApiError::forbidden(
 			ApiErrorCode::LackingPrivileges,
@@ -120,6 +126,23 @@ pub async fn create_emote(
 		return Err(ApiError::bad_request(ApiErrorCode::BadRequest, "invalid tags"));
 	}
 
+	global
+		.image_processor
+		.check_aspect_ratio(&data.file)
+		.map_err(|e| ApiError::bad_request(ApiErrorCode::BadRequest, e.to_string()))?;
+
+	// A retried upload returns the emote of the first request without uploading
+	// the image again.
+	if let Some(key) = &idempotency_key {
+		match idempotency::find_result(&global, key).await? {
+			Some(IdempotentResult::Emote(emote_id)) => {
+				return Ok((StatusCode::CREATED, Json(CreateEmoteResponse { emote_id })).into_response());
+			}
+			Some(_) => return Err(idempotency::key_reused()),
+			None => {}
+		}
+	}
+
 	let req = RateLimitRequest::new(RateLimitResource::ProfilePictureUpload, &session);
 
 	req.http(&global, async {
@@ -154,112 +177,138 @@ pub async fn create_emote(
 
 		let session = &session;
 
-		let emote_id = EmoteId::new();
+		let global = &global;
+		let idempotency_key = idempotency_key.as_ref();
 
-		let input = match global
-			.image_processor
-			.upload_emote(emote_id, data.file, Some(session.ip()))
-			.instrument(tracing::info_span!("image_processor_upload"))
-			.await
-		{
-			Ok(ProcessImageResponse {
-				id,
-				error: None,
-				upload_info:
-					Some(ProcessImageResponseUploadInfo {
-						path: Some(path),
-						content_type,
-						size,
-					}),
-			}) => ImageSetInput::Pending {
-				task_id: id,
-				path: path.path,
-				mime: content_type,
-				size: size as i64,
-			},
-			Ok(ProcessImageResponse { error: Some(err), .. }) => {
-				// At this point if we get a decode error then the image is invalid
-				// and we should return a bad request
-				if err.code == image_processor::ErrorCode::Decode as i32
-					|| err.code == image_processor::ErrorCode::InvalidInput as i32
-				{
-					return Err(ApiError::bad_request(ApiErrorCode::BadRequest, "bad image format"));
+		// The image processor job is only started while holding the key, so a
+		// concurrent retry can not start a second one.
+		idempotency::lock(global, idempotency_key, |existing| async move {
+			match existing {
+				Some(IdempotentResult::Emote(emote_id)) => {
+					return Ok((StatusCode::CREATED, Json(CreateEmoteResponse { emote_id })));
 				}
-
-				tracing::error!(code = ?err.code(), "failed to upload emote: {}", err.message);
-				return Err(ApiError::internal_server_error(
-					ApiErrorCode::ImageProcessorError,
-					"failed to upload emote",
-				));
+				Some(_) => return Err(idempotency::key_reused()),
+				None => {}
 			}
-			Err(err) => {
-				tracing::error!("failed to upload emote: {:#}", err);
-				return Err(ApiError::internal_server_error(
-					ApiErrorCode::ImageProcessorError,
-					"failed to upload emote",
-				));
+
+			let emote_id = EmoteId::new();
+
+			let input = match global
+				.image_processor
+				.upload_emote(emote_id, data.file, Some(session.ip()), ProcessPriority::High)
+				.instrument(tracing::info_span!("image_processor_upload"))
+				.await
+			{
+				Ok(ProcessImageResponse {
+					id,
+					error: None,
+					upload_info:
+						Some(ProcessImageResponseUploadInfo {
+							path: Some(path),
+							content_type,
+							size,
+						}),
+				}) => ImageSetInput::Pending {
+					task_id: id,
+					path: path.path,
+					mime: content_type,
+					size: size as i64,
+					resubmits: 0,
+				},
+				Ok(ProcessImageResponse { error: Some(err), .. }) => {
+					// At this point if we get a decode error then the image is invalid
+					// and we should return a bad request
+					if err.code == image_processor::ErrorCode::Decode as i32
+						|| err.code == image_processor::ErrorCode::InvalidInput as i32
+					{
+						return Err(ApiError::bad_request(ApiErrorCode::BadRequest, "bad image format"));
+					}
+
+					tracing::error!(code = ?err.code(), "failed to upload emote: {}", err.message);
+					return Err(ApiError::internal_server_error(
+						ApiErrorCode::ImageProcessorError,
+						"failed to upload emote",
+					));
+				}
+				Err(err) => {
+					tracing::error!("failed to upload emote: {:#}", err);
+					return Err(ApiError::internal_server_error(
+						ApiErrorCode::ImageProcessorError,
+						"failed to upload emote",
+					));
+				}
+				_ => {
+					tracing::error!("failed to upload emote: unknown error");
+					return Err(ApiError::internal_server_error(
+						ApiErrorCode::ImageProcessorError,
+						"failed to upload emote",
+					));
+				}
+			};
+
+			let mut flags = EmoteFlags::default();
+			if data.metadata.default_zero_width == Some(true) {
+				flags |= EmoteFlags::DefaultZeroWidth;
 			}
-			_ => {
-				tracing::error!("failed to upload emote: unknown error");
-				return Err(ApiError::internal_server_error(
-					ApiErrorCode::ImageProcessorError,
-					"failed to upload emote",
-				));
+			if data.metadata.private == Some(true) {
+				flags |= EmoteFlags::Private;
 			}
-		};
 
-		let mut flags = EmoteFlags::default();
-		if data.metadata.default_zero_width == Some(true) {
-			flags |= EmoteFlags::DefaultZeroWidth;
-		}
-		if data.metadata.private == Some(true) {
-			flags |= EmoteFlags::Private;
-		}
+			let res = transaction(global, "v4.emotes.create", |mut tx| async move {
+				if let Some(key) = idempotency_key {
+					match idempotency::claim(&mut tx, key, IdempotentResult::Emote(emote_id)).await? {
+						Some(IdempotentResult::Emote(emote_id)) => return Ok(emote_id),
+						Some(_) => return Err(TransactionError::Custom(idempotency::key_reused())),
+						None => {}
+					}
+				}
 
-		let res = transaction(&global, |mut tx| async move {
-			let emote = Emote {
-				id: emote_id,
-				owner_id: authed_user.id,
-				default_name: data.metadata.name,
-				tags: data.metadata.tags,
-				image_set: ImageSet { input, outputs: vec![] },
-				flags,
-				attribution: vec![],
-				merged: None,
-				aspect_ratio: -1.0,
-				scores: Default::default(),
-				deleted: false,
-				search_updated_at: None,
-				updated_at: chrono::Utc::now(),
-			};
+				let emote = Emote {
+					id: emote_id,
+					owner_id: authed_user.id,
+					default_name: data.metadata.name,
+					tags: data.metadata.tags,
+					image_set: ImageSet { input, outputs: vec![] },
+					flags,
+					attribution: vec![],
+					merged: None,
+					aspect_ratio: -1.0,
+					scores: Default::default(),
+					deleted: false,
+					processing_error: None,
+					search_updated_at: None,
+					updated_at: chrono::Utc::now(),
+				};
 
-			tx.insert_one::<Emote>(&emote, None).await?;
+				tx.insert_one::<Emote>(&emote, None).await?;
 
-			tx.register_event(InternalEvent {
-				actor: Some(authed_user.clone()),
-				session_id: session.user_session().map(|s| s.id),
-				data: InternalEventData::Emote {
-					after: emote.clone(),
-					data: StoredEventEmoteData::Upload,
-				},
-				timestamp: chrono::Utc::now(),
-			})?;
+				tx.register_event(InternalEvent {
+					actor: Some(authed_user.clone()),
+					session_id: session.user_session().map(|s| s.id),
+					data: InternalEventData::Emote {
+						after: emote.clone(),
+						data: StoredEventEmoteData::Upload,
+					},
+					timestamp: chrono::Utc::now(),
+				})?;
 
-			Ok(emote)
-		})
-		.await;
-
-		match res {
-			Ok(emote) => Ok((StatusCode::CREATED, Json(CreateEmoteResponse { emote_id: emote.id }))),
-			Err(TransactionError::Custom(e)) => Err(e),
-			Err(e) => {
-				tracing::error!(error = %e, "transaction failed");
-				Err(ApiError::internal_server_error(
-					ApiErrorCode::TransactionError,
-					"transaction failed",
-				))
+				Ok(emote.id)
+			})
+			.await;
+
+			match res {
+				Ok(emote_id) => Ok((StatusCode::CREATED, Json(CreateEmoteResponse { emote_id }))),
+				Err(TransactionError::Custom(e)) => Err(e),
+				Err(e) => {
+					tracing::error!(error = %e, "transaction failed");
+					Err(ApiError::internal_server_error(
+						ApiErrorCode::TransactionError,
+						"transaction failed",
+					))
+				}
 			}
-		}
+		})
+		.await
 	})
 	.await
 }
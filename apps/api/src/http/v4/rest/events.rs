@@ -99,43 +99,49 @@ async fn create_event(
 		EntitlementEdgeKind::User { user_id: user.id }
 	};
 
-	transaction_with_mutex(&global, Some(GeneralMutexKey::User(user.id).into()), |mut tx| async move {
-		tx.delete(
-			filter::filter! {
+	transaction_with_mutex(
+		&global,
+		"v4.events.create",
+		Some(GeneralMutexKey::User(user.id).into()),
+		|mut tx| async move {
+			tx.delete(
+				filter::filter! {
+					EntitlementEdge {
+						#[query(rename = "_id", flatten)]
+						id: EntitlementEdgeId {
+							#[query(serde)]
+							from: &from,
+							#[query(serde)]
+							managed_by: Some(EntitlementEdgeManagedBy::SpecialEvent {
+								special_event_id: event.id,
+							}),
+						},
+					}
+				},
+				None,
+			)
+			.await?;
+
+			tx.insert_one(
 				EntitlementEdge {
-					#[query(rename = "_id", flatten)]
 					id: EntitlementEdgeId {
-						#[query(serde)]
-						from: &from,
-						#[query(serde)]
+						from,
+						to: EntitlementEdgeKind::SpecialEvent {
+							special_event_id: event.id,
+						},
 						managed_by: Some(EntitlementEdgeManagedBy::SpecialEvent {
 							special_event_id: event.id,
 						}),
 					},
-				}
-			},
-			None,
-		)
-		.await?;
-
-		tx.insert_one(
-			EntitlementEdge {
-				id: EntitlementEdgeId {
-					from,
-					to: EntitlementEdgeKind::SpecialEvent {
-						special_event_id: event.id,
-					},
-					managed_by: Some(EntitlementEdgeManagedBy::SpecialEvent {
-						special_event_id: event.id,
-					}),
+					expires_at: None,
 				},
-			},
-			None,
-		)
-		.await?;
+				None,
+			)
+			.await?;
 
-		Ok::<_, TransactionError<ApiError>>(())
-	})
+			Ok::<_, TransactionError<ApiError>>(())
+		},
+	)
 	.await
 	.map_err(|err| {
 		tracing::error!("failed to create event: {}", err);
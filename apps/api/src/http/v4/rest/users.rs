@@ -4,19 +4,23 @@ use axum::extract::{DefaultBodyLimit, Path, State};
 use axum::response::IntoResponse;
 use axum::routing::post;
 use axum::{Extension, Json, Router};
+use hyper::HeaderMap;
 use image_processor_proto::{ProcessImageResponse, ProcessImageResponseUploadInfo};
+use shared::database::idempotency_key::IdempotentResult;
 use shared::database::image_set::{ImageSet, ImageSetInput};
 use shared::database::queries::{filter, update};
 use shared::database::role::permissions::{PermissionsExt, RateLimitResource, UserPermission};
 use shared::database::user::editor::{EditorUserPermission, UserEditorId};
 use shared::database::user::profile_picture::{UserProfilePicture, UserProfilePictureId};
 use shared::database::user::{User, UserId, UserStyle};
-use shared::database::MongoCollection;
+use shared::image_processor::ProcessPriority;
 
 use crate::global::Global;
 use crate::http::error::{ApiError, ApiErrorCode};
+use crate::http::idempotency::{self, idempotency_key};
 use crate::http::middleware::session::Session;
 use crate::ratelimit::RateLimitRequest;
+use crate::transactions::{transaction, TransactionError};
 
 pub fn routes() -> Router<Arc<Global>> {
 	Router::new().route(
@@ -35,10 +39,25 @@ async fn upload_user_profile_picture(
 	State(global): State<Arc<Global>>,
 	Path(id): Path<UserId>,
 	Extension(session): Extension<Session>,
+	headers: HeaderMap,
 	body: axum::body::Bytes,
 ) -> Result<impl IntoResponse, ApiError> {
 	let authed_user = session.user()?;
 
+	let idempotency_key = idempotency_key(&headers, authed_user.id)?;
+
+	// A retried upload returns the pending profile picture of the first request,
+	// this has to happen before the pending check below would reject it.
+	if let Some(key) = &idempotency_key {
+		match idempotency::find_result(&global, key).await? {
+			Some(IdempotentResult::ProfilePicture(pending_profile_picture)) => {
+				return Ok(Json(UploadUserProfilePictureResponse { pending_profile_picture }).into_response());
+			}
+			Some(_) => return Err(idempotency::key_reused()),
+			None => {}
+		}
+	}
+
 	let target_user = global
 		.user_loader
 		.load(&global, id)
@@ -82,104 +101,146 @@ async fn upload_user_profile_picture(
 	let req = RateLimitRequest::new(RateLimitResource::ProfilePictureUpload, &session);
 
 	req.http(&global, async {
-		let profile_picture_id = UserProfilePictureId::new();
+		let global = &global;
+		let session = &session;
+		let target_user = &target_user;
+		let idempotency_key = idempotency_key.as_ref();
+
+		// The image processor job is only started while holding the key, so a
+		// concurrent retry can not start a second one.
+		idempotency::lock(global, idempotency_key, |existing| async move {
+			match existing {
+				Some(IdempotentResult::ProfilePicture(pending_profile_picture)) => {
+					return Ok(Json(UploadUserProfilePictureResponse { pending_profile_picture }));
+				}
+				Some(_) => return Err(idempotency::key_reused()),
+				None => {}
+			}
 
-		let input = match global
-			.image_processor
-			.upload_profile_picture(profile_picture_id, target_user.id, body, Some(session.ip()))
-			.await
-		{
-			Ok(ProcessImageResponse {
-				id,
-				error: None,
-				upload_info:
-					Some(ProcessImageResponseUploadInfo {
-						path: Some(path),
-						content_type,
-						size,
-					}),
-			}) => ImageSetInput::Pending {
-				task_id: id,
-				path: path.path,
-				mime: content_type,
-				size: size as i64,
-			},
-			Ok(ProcessImageResponse { error: Some(err), .. }) => {
-				// At this point if we get a decode error then the image is invalid
-				// and we should return a bad request
-				if err.code == image_processor_proto::ErrorCode::Decode as i32
-					|| err.code == image_processor_proto::ErrorCode::InvalidInput as i32
-				{
-					return Err(ApiError::bad_request(
+			let profile_picture_id = UserProfilePictureId::new();
+
+			let input = match global
+				.image_processor
+				.upload_profile_picture(
+					profile_picture_id,
+					target_user.id,
+					body,
+					Some(session.ip()),
+					ProcessPriority::High,
+				)
+				.await
+			{
+				Ok(ProcessImageResponse {
+					id,
+					error: None,
+					upload_info:
+						Some(ProcessImageResponseUploadInfo {
+							path: Some(path),
+							content_type,
+							size,
+						}),
+				}) => ImageSetInput::Pending {
+					task_id: id,
+					path: path.path,
+					mime: content_type,
+					size: size as i64,
+					resubmits: 0,
+				},
+				Ok(ProcessImageResponse { error: Some(err), .. }) => {
+					// At this point if we get a decode error then the image is invalid
+					// and we should return a bad request
+					if err.code == image_processor_proto::ErrorCode::Decode as i32
+						|| err.code == image_processor_proto::ErrorCode::InvalidInput as i32
+					{
+						return Err(ApiError::bad_request(
+							ApiErrorCode::ImageProcessorError,
+							"failed to upload profile picture",
+						));
+					}
+
+					tracing::error!(code = ?err.code(), "failed to upload profile picture: {}", err.message);
+					return Err(ApiError::internal_server_error(
+						ApiErrorCode::ImageProcessorError,
+						"failed to upload profile picture",
+					));
+				}
+				Err(err) => {
+					tracing::error!("failed to upload profile picture: {:#}", err);
+					return Err(ApiError::internal_server_error(
+						ApiErrorCode::ImageProcessorError,
+						"failed to upload profile picture",
+					));
+				}
+				_ => {
+					tracing::error!("failed to upload profile picture: unknown error");
+					return Err(ApiError::internal_server_error(
 						ApiErrorCode::ImageProcessorError,
 						"failed to upload profile picture",
 					));
 				}
+			};
 
-				tracing::error!(code = ?err.code(), "failed to upload profile picture: {}", err.message);
-				return Err(ApiError::internal_server_error(
-					ApiErrorCode::ImageProcessorError,
-					"failed to upload profile picture",
-				));
-			}
-			Err(err) => {
-				tracing::error!("failed to upload profile picture: {:#}", err);
-				return Err(ApiError::internal_server_error(
-					ApiErrorCode::ImageProcessorError,
-					"failed to upload profile picture",
-				));
-			}
-			_ => {
-				tracing::error!("failed to upload profile picture: unknown error");
-				return Err(ApiError::internal_server_error(
-					ApiErrorCode::ImageProcessorError,
-					"failed to upload profile picture",
-				));
-			}
-		};
+			let target_user_id = target_user.id;
 
-		UserProfilePicture::collection(&global.db)
-			.insert_one(UserProfilePicture {
-				id: profile_picture_id,
-				user_id: target_user.id,
-				image_set: ImageSet { input, outputs: vec![] },
-				updated_at: chrono::Utc::now(),
-			})
-			.await
-			.map_err(|e| {
-				tracing::error!(error = %e, "failed to insert profile picture");
-				ApiError::internal_server_error(ApiErrorCode::MutationError, "failed to insert profile picture")
-			})?;
-
-		User::collection(&global.db)
-			.update_one(
-				filter::filter! {
-					User {
-						#[query(rename = "_id")]
-						id: target_user.id,
+			let res = transaction(global, "v4.users.upload_profile_picture", |mut tx| async move {
+				if let Some(key) = idempotency_key {
+					match idempotency::claim(&mut tx, key, IdempotentResult::ProfilePicture(profile_picture_id)).await? {
+						Some(IdempotentResult::ProfilePicture(id)) => return Ok(id),
+						Some(_) => return Err(TransactionError::Custom(idempotency::key_reused())),
+						None => {}
 					}
-				},
-				update::update! {
-					#[query(set)]
-					User {
-						#[query(flatten)]
-						style: UserStyle {
-							pending_profile_picture: Some(profile_picture_id),
-						},
+				}
+
+				tx.insert_one::<UserProfilePicture>(
+					UserProfilePicture {
+						id: profile_picture_id,
+						user_id: target_user_id,
+						image_set: ImageSet { input, outputs: vec![] },
 						updated_at: chrono::Utc::now(),
-						search_updated_at: &None,
-					}
-				},
-			)
-			.await
-			.map_err(|err| {
-				tracing::error!(error = %err, "failed to update user");
-				ApiError::internal_server_error(ApiErrorCode::MutationError, "failed to update user")
-			})?;
-
-		Ok(Json(UploadUserProfilePictureResponse {
-			pending_profile_picture: profile_picture_id,
-		}))
+					},
+					None,
+				)
+				.await?;
+
+				tx.update_one(
+					filter::filter! {
+						User {
+							#[query(rename = "_id")]
+							id: target_user_id,
+						}
+					},
+					update::update! {
+						#[query(set)]
+						User {
+							#[query(flatten)]
+							style: UserStyle {
+								pending_profile_picture: Some(profile_picture_id),
+							},
+							updated_at: chrono::Utc::now(),
+							search_updated_at: &None,
+						}
+					},
+					None,
+				)
+				.await?;
+
+				Ok(profile_picture_id)
+			})
+			.await;
+
+			match res {
+				Ok(pending_profile_picture) => Ok(Json(UploadUserProfilePictureResponse { pending_profile_picture })),
+				Err(TransactionError::Custom(e)) => Err(e),
+				Err(e) => {
+					tracing::error!(error = %e, "transaction failed");
+					Err(ApiError::internal_server_error(
+						ApiErrorCode::TransactionError,
+						"transaction failed",
+					))
+				}
+			}
+		})
+		.await
 	})
 	.await
 }
@@ -1,5 +1,14 @@
 use async_graphql::CustomValidator;
 
+/// The maximum length of emote names and other names in characters.
+pub const MAX_NAME_LENGTH: usize = 100;
+
+/// The maximum number of tags.
+pub const MAX_TAGS: usize = 6;
+
+/// The minimum and maximum length of a tag in characters.
+pub const TAG_LENGTH: std::ops::RangeInclusive<usize> = 3..=30;
+
 #[derive(Debug, Copy, Clone)]
 pub struct EmoteNameValidator;
 
@@ -17,7 +26,12 @@ pub fn check_emote_name(value: impl AsRef<str>) -> bool {
 	static REGEX: std::sync::OnceLock<regex::Regex> = std::sync::OnceLock::new();
 
 	REGEX
-		.get_or_init(|| regex::Regex::new(r"^[a-zA-Z0-9_\-():!+|.'?><\p{Emoji_Presentation}*$#]{1,100}$").unwrap())
+		.get_or_init(|| {
+			regex::Regex::new(&format!(
+				r"^[a-zA-Z0-9_\-():!+|.'?><\p{{Emoji_Presentation}}*$#]{{1,{MAX_NAME_LENGTH}}}$"
+			))
+			.unwrap()
+		})
 		.is_match(value.as_ref())
 }
 
@@ -38,7 +52,12 @@ pub fn check_name(value: impl AsRef<str>) -> bool {
 	static REGEX: std::sync::OnceLock<regex::Regex> = std::sync::OnceLock::new();
 
 	REGEX
-		.get_or_init(|| regex::Regex::new(r"^[a-zA-Z0-9_\-():!+|.'?><\p{Emoji_Presentation}*$# ]{1,100}$").unwrap())
+		.get_or_init(|| {
+			regex::Regex::new(&format!(
+				r"^[a-zA-Z0-9_\-():!+|.'?><\p{{Emoji_Presentation}}*$# ]{{1,{MAX_NAME_LENGTH}}}$"
+			))
+			.unwrap()
+		})
 		.is_match(value.as_ref())
 }
 
@@ -59,11 +78,11 @@ pub fn check_tag(value: impl AsRef<str>) -> bool {
 	static REGEX: std::sync::OnceLock<regex::Regex> = std::sync::OnceLock::new();
 
 	REGEX
-		.get_or_init(|| regex::Regex::new(r"^\w{3,30}$").unwrap())
+		.get_or_init(|| regex::Regex::new(&format!(r"^\w{{{},{}}}$", TAG_LENGTH.start(), TAG_LENGTH.end())).unwrap())
 		.is_match(value.as_ref())
 }
 
 pub fn check_tags<S: AsRef<str>, I: ExactSizeIterator<Item = S>>(tags: impl IntoIterator<Item = S, IntoIter = I>) -> bool {
 	let mut iter = tags.into_iter();
-	iter.len() <= 6 && iter.all(check_tag)
+	iter.len() <= MAX_TAGS && iter.all(check_tag)
 }
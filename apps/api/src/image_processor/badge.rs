@@ -74,6 +74,7 @@ pub async fn handle_success(
 
 	Ok(PurgeRequest {
 		files: before.image_set.outputs.iter().filter_map(|i| i.path.parse().ok()).collect(),
+		prefixes: Vec::new(),
 	})
 }
 
@@ -4,7 +4,7 @@ use std::sync::Arc;
 use image_processor_proto::event_callback;
 use mongodb::options::{FindOneAndUpdateOptions, ReturnDocument};
 use shared::cdn::PurgeRequest;
-use shared::database::emote::{Emote, EmoteFlags, EmoteId};
+use shared::database::emote::{Emote, EmoteFlags, EmoteId, EmoteProcessingError};
 use shared::database::emote_moderation_request::{
 	EmoteModerationRequest, EmoteModerationRequestId, EmoteModerationRequestKind, EmoteModerationRequestStatus,
 };
@@ -142,6 +142,7 @@ pub async fn handle_success(
 
 	Ok(PurgeRequest {
 		files: before.image_set.outputs.iter().filter_map(|i| i.path.parse().ok()).collect(),
+		prefixes: vec![format!("emote/{id}/")],
 	})
 }
 
@@ -151,6 +152,13 @@ pub async fn handle_fail(
 	id: EmoteId,
 	event: event_callback::Fail,
 ) -> TransactionResult<(), anyhow::Error> {
+	// The reason is kept on the deleted emote so the uploader can see why their
+	// upload failed.
+	let processing_error = EmoteProcessingError {
+		code: event.error.as_ref().map(|e| e.code),
+		message: event.error.as_ref().map(|e| e.message.clone()),
+	};
+
 	let after = tx
 		.find_one_and_update(
 			filter::filter! {
@@ -163,6 +171,8 @@ pub async fn handle_fail(
 				#[query(set)]
 				Emote {
 					deleted: true,
+					#[query(serde)]
+					processing_error: Some(processing_error),
 					updated_at: chrono::Utc::now(),
 					search_updated_at: &None,
 				}
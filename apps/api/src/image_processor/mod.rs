@@ -171,29 +171,23 @@ async fn handle_success(
 		Subject::Badge(id) => Some(GeneralMutexKey::Badge(id)),
 	};
 
-	let purge_keys = transaction_with_mutex(global, mutex_key.map(Into::into), |tx| async move {
-		match subject {
-			Subject::Emote(id) => emote::handle_success(tx, global, id, event, metadata).await,
-			Subject::ProfilePicture(id) => profile_picture::handle_success(tx, global, id, event).await,
-			Subject::PaintLayer(id, layer_id) => paint_layer::handle_success(tx, global, id, layer_id, event).await,
-			Subject::Badge(id) => badge::handle_success(tx, global, id, event).await,
-		}
-	})
+	let purge_keys = transaction_with_mutex(
+		global,
+		"image_processor.success",
+		mutex_key.map(Into::into),
+		|tx| async move {
+			match subject {
+				Subject::Emote(id) => emote::handle_success(tx, global, id, event, metadata).await,
+				Subject::ProfilePicture(id) => profile_picture::handle_success(tx, global, id, event).await,
+				Subject::PaintLayer(id, layer_id) => paint_layer::handle_success(tx, global, id, layer_id, event).await,
+				Subject::Badge(id) => badge::handle_success(tx, global, id, event).await,
+			}
+		},
+	)
 	.await
 	.context("transaction")?;
 
-	if !purge_keys.files.is_empty() {
-		global
-			.jetstream
-			.publish(
-				format!("{}.request", global.config.cdn.purge_stream_subject),
-				serde_json::to_vec(&purge_keys)
-					.expect("failed to serialize purge keys")
-					.into(),
-			)
-			.await
-			.context("failed to send purge request")?;
-	}
+	crate::cdn_purge::request_purge(global, &purge_keys).await?;
 
 	Ok(())
 }
@@ -206,7 +200,7 @@ async fn handle_fail(global: &Arc<Global>, subject: Subject, event: event_callba
 		Subject::Badge(id) => Some(GeneralMutexKey::Badge(id)),
 	};
 
-	transaction_with_mutex(global, mutex_key.map(Into::into), |tx| async move {
+	transaction_with_mutex(global, "image_processor.fail", mutex_key.map(Into::into), |tx| async move {
 		match subject {
 			Subject::Emote(id) => emote::handle_fail(tx, global, id, event).await,
 			Subject::ProfilePicture(id) => profile_picture::handle_fail(tx, global, id, event).await,
@@ -226,7 +220,7 @@ async fn handle_start(global: &Arc<Global>, subject: Subject) -> anyhow::Result<
 		Subject::Badge(id) => Some(GeneralMutexKey::Badge(id)),
 	};
 
-	transaction_with_mutex(global, mutex_key.map(Into::into), |tx| async move {
+	transaction_with_mutex(global, "image_processor.start", mutex_key.map(Into::into), |tx| async move {
 		match subject {
 			Subject::Emote(id) => emote::handle_start(tx, global, id).await,
 			Subject::ProfilePicture(id) => profile_picture::handle_start(tx, global, id).await,
@@ -246,7 +240,7 @@ async fn handle_cancel(global: &Arc<Global>, subject: Subject) -> anyhow::Result
 		Subject::Badge(id) => Some(GeneralMutexKey::Badge(id)),
 	};
 
-	transaction_with_mutex(global, mutex_key.map(Into::into), |tx| async move {
+	transaction_with_mutex(global, "image_processor.cancel", mutex_key.map(Into::into), |tx| async move {
 		match subject {
 			Subject::Emote(id) => emote::handle_cancel(tx, global, id).await,
 			Subject::ProfilePicture(id) => profile_picture::handle_cancel(tx, global, id).await,
@@ -101,6 +101,7 @@ pub async fn handle_success(
 			})
 			.flatten()
 			.collect(),
+		prefixes: Vec::new(),
 	})
 }
 
@@ -11,12 +11,13 @@ use shared::event::{InternalEvent, InternalEventData};
 
 use super::event_to_image_set;
 use crate::global::Global;
+use crate::http::profile_picture::evict_profile_pictures;
 use crate::transactions::{TransactionError, TransactionResult, TransactionSession};
 
 #[tracing::instrument(skip_all, fields(id = %id))]
 pub async fn handle_success(
 	mut tx: TransactionSession<'_, anyhow::Error>,
-	_: &Arc<Global>,
+	global: &Arc<Global>,
 	id: UserProfilePictureId,
 	event: event_callback::Success,
 ) -> TransactionResult<PurgeRequest, anyhow::Error> {
@@ -89,7 +90,7 @@ pub async fn handle_success(
 		actor: None,
 		session_id: None,
 		data: InternalEventData::UserProfilePicture {
-			after: profile_picture,
+			after: profile_picture.clone(),
 			data: StoredEventUserProfilePictureData::Process {
 				event: ImageProcessorEvent::Success,
 			},
@@ -97,9 +98,38 @@ pub async fn handle_success(
 		timestamp: chrono::Utc::now(),
 	})?;
 
-	Ok(PurgeRequest {
-		files: before.image_set.outputs.iter().filter_map(|i| i.path.parse().ok()).collect(),
-	})
+	let user = tx
+		.find_one(
+			filter::filter! {
+				User {
+					#[query(rename = "_id")]
+					id: profile_picture.user_id,
+				}
+			},
+			None,
+		)
+		.await?;
+
+	let keep = user
+		.iter()
+		.flat_map(|u| [u.style.active_profile_picture, u.style.pending_profile_picture])
+		.flatten()
+		.chain(std::iter::once(profile_picture.id))
+		.collect::<Vec<_>>();
+
+	let mut purge = evict_profile_pictures(
+		&mut tx,
+		profile_picture.user_id,
+		&keep,
+		global.config.api.max_profile_pictures,
+	)
+	.await?;
+
+	purge
+		.files
+		.extend(before.image_set.outputs.iter().filter_map(|i| i.path.parse().ok()));
+
+	Ok(purge)
 }
 
 // handle failure
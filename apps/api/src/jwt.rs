@@ -149,6 +149,8 @@ pub struct CsrfJwtPayload {
 	pub random: [u8; 32],
 	pub session_id: Option<UserSessionId>,
 	pub expiration: DateTime<Utc>,
+	/// PKCE code verifier, bound to this csrf token
+	pub code_verifier: Option<String>,
 }
 
 impl CsrfJwtPayload {
@@ -157,6 +159,7 @@ impl CsrfJwtPayload {
 			random: rand::random(),
 			session_id,
 			expiration: Utc::now() + chrono::Duration::minutes(5),
+			code_verifier: None,
 		}
 	}
 
@@ -182,7 +185,11 @@ impl JwtState for CsrfJwtPayload {
 				issued_at: None,
 				json_web_token_id: Some(self.random()),
 			},
-			private: Default::default(),
+			private: self
+				.code_verifier
+				.iter()
+				.map(|verifier| ("code_verifier".to_string(), verifier.clone().into()))
+				.collect(),
 		}
 	}
 
@@ -194,6 +201,11 @@ impl JwtState for CsrfJwtPayload {
 				.ok()?
 				.try_into()
 				.ok()?,
+			code_verifier: claims
+				.private
+				.get("code_verifier")
+				.and_then(|v| v.as_str())
+				.map(ToOwned::to_owned),
 		})
 	}
 }
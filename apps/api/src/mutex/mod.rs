@@ -1,4 +1,6 @@
 use anyhow::Context;
+use fred::interfaces::ClientLike;
+use itertools::Itertools;
 use shared::database::Id;
 use tracing::Instrument;
 
@@ -6,16 +8,24 @@ pub struct DistributedMutex {
 	redis: fred::clients::Pool,
 	mutex_lock: fred::types::scripts::Function,
 	mutex_free: fred::types::scripts::Function,
+	mutex_lock_many: fred::types::scripts::Function,
+	mutex_free_many: fred::types::scripts::Function,
 }
 
 const LUA_SCRIPT: &str = include_str!("mutex.lua");
 
+/// The most keys [`DistributedMutex::acquire_many`] locks at once, all of them
+/// are refreshed in the same script call while `f` runs.
+pub const MAX_MANY_KEYS: usize = 2000;
+
 #[derive(thiserror::Error, Debug)]
 pub enum MutexError {
 	#[error("failed to acquire mutex after {0} attempts")]
 	Acquire(usize),
 	#[error("lost mutex lock while waiting for operation to complete")]
 	Lost,
+	#[error("cannot lock {0} keys at once")]
+	TooManyKeys(usize),
 	#[error("redis error: {0}")]
 	Redis(#[from] fred::error::Error),
 }
@@ -44,12 +54,22 @@ impl DistributedMutex {
 		Ok(Self {
 			mutex_lock: lib
 				.functions()
-				.get("api_mutex_lock")
+				.get("api_mutex_lock_v2")
 				.context("failed to get api_ratelimit function")?
 				.clone(),
 			mutex_free: lib
 				.functions()
-				.get("api_mutex_free")
+				.get("api_mutex_free_v2")
+				.context("failed to get api_ratelimit function")?
+				.clone(),
+			mutex_lock_many: lib
+				.functions()
+				.get("api_mutex_lock_many_v2")
+				.context("failed to get api_ratelimit function")?
+				.clone(),
+			mutex_free_many: lib
+				.functions()
+				.get("api_mutex_free_many_v2")
 				.context("failed to get api_ratelimit function")?
 				.clone(),
 			redis,
@@ -63,37 +83,10 @@ impl DistributedMutex {
 	) -> Result<R, MutexError> {
 		let req = req.into();
 		let lock = Id::<()>::new().to_string();
+		let lock = lock.as_str();
 		let key = req.key.to_string();
 
-		let mut aquired = false;
-
-		async {
-			for i in 0..req.attempts {
-				match self
-					.mutex_lock
-					.fcall::<bool, _, _, _>(&self.redis, &[&key], &[&lock, "5"]) // 5 second lock duration
-					.await?
-				{
-					true => {
-						aquired = true;
-						tracing::Span::current().record("attempts", i);
-						break;
-					}
-					false => {
-						tokio::time::sleep(req.delay).await;
-					}
-				}
-			}
-
-			if !aquired {
-				tracing::Span::current().record("attempts", req.attempts);
-				return Err(MutexError::Acquire(req.attempts));
-			}
-
-			Ok(())
-		}
-		.instrument(tracing::info_span!("DistributedMutex::acquire", key = %key, attempts = tracing::field::Empty))
-		.await?;
+		self.lock(&key, lock, req.attempts, req.delay).await?;
 
 		let f = f();
 		let mut f = std::pin::pin!(f);
@@ -103,7 +96,7 @@ impl DistributedMutex {
 				result = &mut f => {
 					if let Err(err) = self
 						.mutex_free
-						.fcall::<(), _, _, _>(&self.redis, &[&key], &[&lock])
+						.fcall::<(), _, _, _>(&self.redis, &[&key], &[lock])
 						.await
 					{
 						tracing::warn!(error = %err, "operation completed but failed to release lock: {}", req.key);
@@ -116,7 +109,7 @@ impl DistributedMutex {
 					async {
 						match self
 							.mutex_lock
-							.fcall::<bool, _, _, _>(&self.redis, &[&key], &[&lock, "5"])
+							.fcall::<bool, _, _, _>(&self.redis, &[&key], &[lock, "5"])
 							.await?
 						{
 							true => Ok(()),
@@ -130,4 +123,141 @@ impl DistributedMutex {
 			}
 		}
 	}
+
+	/// Acquires the locks of every key before running `f`. The keys are locked
+	/// in a single attempt, if any of them is held elsewhere none of them are
+	/// taken and [`MutexError::Acquire`] is returned, so a large set of keys
+	/// never waits while holding the others. At most [`MAX_MANY_KEYS`] keys can
+	/// be locked at once.
+	pub async fn acquire_many<R, T: std::fmt::Display, F: std::future::Future<Output = R>>(
+		&self,
+		keys: impl IntoIterator<Item = T>,
+		f: impl FnOnce() -> F,
+	) -> Result<R, MutexError> {
+		let mut keys: Vec<_> = keys.into_iter().map(|key| key.to_string()).collect();
+		keys.sort_unstable();
+		keys.dedup();
+
+		if keys.len() > MAX_MANY_KEYS {
+			return Err(MutexError::TooManyKeys(keys.len()));
+		}
+
+		let groups = self.slot_groups(&keys);
+
+		let lock = Id::<()>::new().to_string();
+		let lock = lock.as_str();
+
+		for (i, group) in groups.iter().enumerate() {
+			match self.lock_many(group, lock).await {
+				Ok(true) => {}
+				Ok(false) => {
+					self.free_many(&groups[..i], lock).await;
+					return Err(MutexError::Acquire(1));
+				}
+				Err(err) => {
+					// the group might have been locked before the error
+					self.free_many(&groups[..=i], lock).await;
+					return Err(err.into());
+				}
+			}
+		}
+
+		let f = f();
+		let mut f = std::pin::pin!(f);
+
+		let result = loop {
+			tokio::select! {
+				result = &mut f => break Ok(result),
+				// Refresh the locks every 2 seconds
+				_ = tokio::time::sleep(std::time::Duration::from_secs(2)) => {
+					let refreshed = futures::future::try_join_all(groups.iter().map(|group| self.lock_many(group, lock)))
+						.instrument(tracing::info_span!("DistributedMutex::refresh_many", count = keys.len()))
+						.await;
+
+					match refreshed {
+						Ok(refreshed) if refreshed.iter().all(|r| *r) => {}
+						Ok(_) => {
+							tracing::warn!("lost mutex lock while waiting for operation to complete");
+							break Err(MutexError::Lost);
+						}
+						Err(err) => break Err(err.into()),
+					}
+				}
+			}
+		};
+
+		self.free_many(&groups, lock).await;
+
+		result
+	}
+
+	/// Groups the keys by hash slot on a clustered redis, where a script can
+	/// only access the keys of a single slot. Otherwise all keys are in one
+	/// group.
+	fn slot_groups<'a>(&self, keys: &'a [String]) -> Vec<Vec<&'a str>> {
+		if keys.is_empty() {
+			return Vec::new();
+		}
+
+		if !self.redis.next().is_clustered() {
+			return vec![keys.iter().map(String::as_str).collect()];
+		}
+
+		keys.iter()
+			.map(|key| (fred::util::redis_keyslot(key.as_bytes()), key.as_str()))
+			.into_group_map()
+			.into_values()
+			.collect()
+	}
+
+	async fn lock_many(&self, keys: &[&str], lock: &str) -> Result<bool, fred::error::Error> {
+		self.mutex_lock_many
+			.fcall::<bool, _, _, _>(&self.redis, keys.to_vec(), &[lock, "5"]) // 5 second lock duration
+			.instrument(tracing::info_span!("DistributedMutex::acquire_many", count = keys.len()))
+			.await
+	}
+
+	async fn free_many(&self, groups: &[Vec<&str>], lock: &str) {
+		for keys in groups {
+			if let Err(err) = self
+				.mutex_free_many
+				.fcall::<(), _, _, _>(&self.redis, keys.clone(), &[lock])
+				.await
+			{
+				tracing::warn!(error = %err, count = keys.len(), "operation completed but failed to release locks");
+			}
+		}
+	}
+
+	async fn lock(&self, key: &str, lock: &str, attempts: usize, delay: std::time::Duration) -> Result<(), MutexError> {
+		let mut aquired = false;
+
+		async {
+			for i in 0..attempts {
+				match self
+					.mutex_lock
+					.fcall::<bool, _, _, _>(&self.redis, &[key], &[lock, "5"]) // 5 second lock duration
+					.await?
+				{
+					true => {
+						aquired = true;
+						tracing::Span::current().record("attempts", i);
+						break;
+					}
+					false => {
+						tokio::time::sleep(delay).await;
+					}
+				}
+			}
+
+			if !aquired {
+				tracing::Span::current().record("attempts", attempts);
+				return Err(MutexError::Acquire(attempts));
+			}
+
+			Ok(())
+		}
+		.instrument(tracing::info_span!("DistributedMutex::acquire", key = %key, attempts = tracing::field::Empty))
+		.await
+	}
 }
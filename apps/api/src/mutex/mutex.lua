@@ -1,4 +1,4 @@
-#!lua name=api_transaction_mutex
+#!lua name=api_transaction_mutex_v2
 
 local function lock(keys, args)
     local key = keys[1]
@@ -32,5 +32,40 @@ local function free(keys, args)
     return 0
 end
 
-redis.register_function('api_mutex_lock', lock)
-redis.register_function('api_mutex_free', free)
+-- Locks every key at once, or none of them if any is held by another owner.
+-- Keys already held by the owner are refreshed, so this also refreshes a lock
+-- taken with it.
+local function lock_many(keys, args)
+    local tx_id = args[1]
+    local duration = tonumber(args[2])
+
+    for _, key in ipairs(keys) do
+        local value = redis.call('get', key)
+        if value and value ~= tx_id then
+            return 0
+        end
+    end
+
+    for _, key in ipairs(keys) do
+        if redis.call('get', key) then
+            redis.call('expire', key, duration)
+        else
+            redis.call('set', key, tx_id, 'EX', duration)
+        end
+    end
+
+    return 1
+end
+
+local function free_many(keys, args)
+    for _, key in ipairs(keys) do
+        free({ key }, args)
+    end
+
+    return 1
+end
+
+redis.register_function('api_mutex_lock_v2', lock)
+redis.register_function('api_mutex_free_v2', free)
+redis.register_function('api_mutex_lock_many_v2', lock_many)
+redis.register_function('api_mutex_free_many_v2', free_many)
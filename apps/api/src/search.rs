@@ -4,6 +4,7 @@ use std::fmt::Display;
 use std::sync::Arc;
 
 use itertools::Itertools;
+use shared::database::emote::Emote;
 use shared::typesense::types::TypesenseCollection;
 use typed_builder::TypedBuilder;
 use typesense_rs::apis::documents_api::{
@@ -18,6 +19,8 @@ use crate::global::Global;
 pub enum SearchError {
 	Search(typesense_rs::apis::Error<SearchCollectionError>),
 	MultiSearch(typesense_rs::apis::Error<MultiSearchError>),
+	InvalidParams(EmoteSearchParamsError),
+	Load,
 }
 
 impl From<typesense_rs::apis::Error<SearchCollectionError>> for SearchError {
@@ -43,6 +46,8 @@ impl Display for SearchError {
 			}
 			Self::Search(e) => write!(f, "{e}"),
 			Self::MultiSearch(e) => write!(f, "{e}"),
+			Self::InvalidParams(e) => write!(f, "{e}"),
+			Self::Load => write!(f, "failed to load results"),
 		}
 	}
 }
@@ -213,6 +218,170 @@ pub async fn search<T: TypesenseCollection>(
 	})
 }
 
+/// The maximum number of tags an emote search can filter by.
+pub const MAX_EMOTE_SEARCH_TAGS: usize = 10;
+/// The maximum length of an emote search query.
+pub const MAX_EMOTE_SEARCH_QUERY_LENGTH: usize = 100;
+/// The maximum number of tag facet values returned by an emote search.
+const MAX_EMOTE_SEARCH_FACET_VALUES: i32 = 20;
+/// The maximum page size of an emote search, the same limit as the emote
+/// search query.
+pub const MAX_EMOTE_SEARCH_PER_PAGE: u32 = 250;
+/// The page size of an emote search when none is given.
+pub const DEFAULT_EMOTE_SEARCH_PER_PAGE: u32 = 30;
+
+#[derive(Debug, thiserror::Error)]
+pub enum EmoteSearchParamsError {
+	#[error("too many tags, at most {MAX_EMOTE_SEARCH_TAGS} are allowed")]
+	TooManyTags,
+	#[error("query is too long, at most {MAX_EMOTE_SEARCH_QUERY_LENGTH} characters are allowed")]
+	QueryTooLong,
+}
+
+/// Filters for [`search_emotes`]. All tags have to match, unset flags are not
+/// filtered on.
+#[derive(TypedBuilder, Debug, Clone)]
+#[builder(field_defaults(default, setter(into)))]
+pub struct EmoteSearchParams {
+	pub query: Option<String>,
+	pub tags: Vec<String>,
+	pub animated: Option<bool>,
+	pub nsfw: Option<bool>,
+	/// Include unlisted and private emotes
+	pub include_unlisted: bool,
+	pub page: Option<u32>,
+	/// Clamped to [`MAX_EMOTE_SEARCH_PER_PAGE`]
+	pub per_page: Option<u32>,
+}
+
+impl EmoteSearchParams {
+	pub fn validate(&self) -> Result<(), EmoteSearchParamsError> {
+		if self.tags.len() > MAX_EMOTE_SEARCH_TAGS {
+			return Err(EmoteSearchParamsError::TooManyTags);
+		}
+
+		if self
+			.query
+			.as_ref()
+			.is_some_and(|q| q.chars().count() > MAX_EMOTE_SEARCH_QUERY_LENGTH)
+		{
+			return Err(EmoteSearchParamsError::QueryTooLong);
+		}
+
+		Ok(())
+	}
+
+	pub fn per_page(&self) -> u32 {
+		self.per_page
+			.unwrap_or(DEFAULT_EMOTE_SEARCH_PER_PAGE)
+			.clamp(1, MAX_EMOTE_SEARCH_PER_PAGE)
+	}
+
+	fn filter_by(&self) -> String {
+		let mut filter_by = vec!["deleted: false".to_owned()];
+
+		if !self.include_unlisted {
+			filter_by.push("flag_public_listed: true".to_owned());
+			filter_by.push("flag_private: false".to_owned());
+		}
+
+		for tag in &self.tags {
+			let tag = tag.replace('`', "");
+			let tag = tag.trim_end_matches('\\');
+			if !tag.is_empty() {
+				filter_by.push(format!("tags:=`{tag}`"));
+			}
+		}
+
+		if let Some(animated) = self.animated {
+			filter_by.push(format!("flag_animated: {animated}"));
+		}
+
+		if let Some(nsfw) = self.nsfw {
+			filter_by.push(format!("flag_nsfw: {nsfw}"));
+		}
+
+		filter_by.join(" && ")
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct FacetCount {
+	pub value: String,
+	pub count: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct EmoteSearchResult {
+	pub emotes: Vec<Emote>,
+	pub found: u64,
+	/// The most common tags of the matching emotes
+	pub tags: Vec<FacetCount>,
+}
+
+/// Searches emotes by name and tags, returning the loaded emotes in search
+/// order together with the tag counts of all matching emotes.
+#[tracing::instrument(skip_all)]
+pub async fn search_emotes(global: &Arc<Global>, params: EmoteSearchParams) -> Result<EmoteSearchResult, SearchError> {
+	params.validate().map_err(SearchError::InvalidParams)?;
+
+	let filter_by = params.filter_by();
+	let per_page = params.per_page();
+
+	let resp = global
+		.typesense
+		.documents_api()
+		.search_collection(
+			SearchCollectionParams::builder()
+				.collection_name(shared::typesense::types::emote::Emote::COLLECTION_NAME.to_owned())
+				.q(params.query.unwrap_or_else(|| "*".to_owned()))
+				.query_by("default_name,tags".to_owned())
+				.query_by_weights("4,1".to_owned())
+				.filter_by(filter_by)
+				.facet_by("tags".to_owned())
+				.max_facet_values(MAX_EMOTE_SEARCH_FACET_VALUES)
+				.maybe_page(params.page.map(|p| i32::try_from(p).unwrap_or(i32::MAX)))
+				.per_page(per_page as i32)
+				.include_fields("id".to_string())
+				.highlight_fields("false".to_string())
+				.build(),
+		)
+		.await?;
+
+	let hits: Vec<shared::database::emote::EmoteId> = resp
+		.hits
+		.into_iter()
+		.flatten()
+		.filter_map(|h| serde_json::from_value(h.document?.remove("id")?).ok())
+		.collect();
+
+	let tags = resp
+		.facet_counts
+		.into_iter()
+		.flatten()
+		.filter(|f| f.field_name.as_deref() == Some("tags"))
+		.flat_map(|f| f.counts.into_iter().flatten())
+		.filter_map(|c| {
+			Some(FacetCount {
+				value: c.value?,
+				count: c.count.unwrap_or(0).max(0) as u64,
+			})
+		})
+		.collect();
+
+	let emotes = global
+		.emote_by_id_loader
+		.load_many(hits.iter().copied())
+		.await
+		.map_err(|()| SearchError::Load)?;
+
+	Ok(EmoteSearchResult {
+		emotes: sorted_results(hits, emotes).into_iter().collect(),
+		found: resp.found.unwrap_or(0).max(0) as u64,
+		tags,
+	})
+}
+
 pub fn sorted_results<'a, K: std::hash::Hash + Eq + 'a, V: 'a, B: Borrow<K> + 'a, H: IntoIterator<Item = B>>(
 	hits: H,
 	mut loaded: HashMap<K, V>,
@@ -222,3 +391,50 @@ where
 {
 	hits.into_iter().filter_map(move |h| loaded.remove(h.borrow()))
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_emote_search_params_validate() {
+		let params = EmoteSearchParams::builder()
+			.tags((0..=MAX_EMOTE_SEARCH_TAGS).map(|i| i.to_string()).collect::<Vec<_>>())
+			.build();
+		assert!(matches!(params.validate(), Err(EmoteSearchParamsError::TooManyTags)));
+
+		let params = EmoteSearchParams::builder()
+			.query(Some("a".repeat(MAX_EMOTE_SEARCH_QUERY_LENGTH + 1)))
+			.build();
+		assert!(matches!(params.validate(), Err(EmoteSearchParamsError::QueryTooLong)));
+
+		let params = EmoteSearchParams::builder().tags(vec!["cat".to_owned()]).build();
+		assert!(params.validate().is_ok());
+	}
+
+	#[test]
+	fn test_emote_search_per_page() {
+		let params = EmoteSearchParams::builder().build();
+		assert_eq!(params.per_page(), DEFAULT_EMOTE_SEARCH_PER_PAGE);
+
+		let params = EmoteSearchParams::builder().per_page(Some(10_000)).build();
+		assert_eq!(params.per_page(), MAX_EMOTE_SEARCH_PER_PAGE);
+
+		let params = EmoteSearchParams::builder().per_page(Some(0)).build();
+		assert_eq!(params.per_page(), 1);
+	}
+
+	#[test]
+	fn test_emote_search_filter_by() {
+		let params = EmoteSearchParams::builder()
+			.tags(vec!["cat".to_owned(), "`dog\\".to_owned()])
+			.animated(Some(true))
+			.nsfw(Some(false))
+			.build();
+
+		assert_eq!(
+			params.filter_by(),
+			"deleted: false && flag_public_listed: true && flag_private: false && tags:=`cat` && tags:=`dog` && flag_animated: true && flag_nsfw: false"
+		);
+	}
+}
@@ -1,17 +1,17 @@
-use std::collections::HashSet;
-use std::convert::Infallible;
+use std::collections::{HashMap, HashSet};
 use std::str::FromStr;
 use std::sync::Arc;
 
-use chrono::TimeZone;
-use futures::TryStreamExt;
-use mongodb::options::FindOneOptions;
+use chrono::{Datelike, TimeZone};
+use futures::{StreamExt, TryStreamExt};
+use itertools::Itertools;
+use mongodb::options::{FindOneAndUpdateOptions, FindOptions, ReturnDocument};
 use shared::database::duration::DurationUnit;
 use shared::database::emote_set::{EmoteSet, EmoteSetId, EmoteSetKind};
 use shared::database::entitlement::{EntitlementEdge, EntitlementEdgeId, EntitlementEdgeKind, EntitlementEdgeManagedBy};
 use shared::database::product::special_event::SpecialEventId;
 use shared::database::product::subscription::{Subscription, SubscriptionId, SubscriptionPeriod, SubscriptionState};
-use shared::database::product::{ProductId, SubscriptionBenefitCondition};
+use shared::database::product::{ProductId, SubscriptionBenefitCondition, SubscriptionProduct, SubscriptionProductId};
 use shared::database::queries::{filter, update};
 use shared::database::user::{User, UserId, UserStyle};
 use shared::database::MongoCollection;
@@ -19,7 +19,7 @@ use shared::event::{InternalEvent, InternalEventData, InternalEventEmoteSetData}
 
 use crate::global::Global;
 use crate::http::error::{ApiError, ApiErrorCode};
-use crate::transactions::{transaction_with_mutex, TransactionError};
+use crate::transactions::{transaction, BulkWrite, TransactionError, TransactionSession};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct SubAge {
@@ -32,10 +32,20 @@ pub struct SubAge {
 
 impl SubAge {
 	pub fn new(periods: &[SubscriptionPeriod]) -> Self {
+		Self::from_periods(
+			periods.iter().map(|p| StartEnd {
+				start: p.start,
+				end: p.end,
+			}),
+			chrono::Utc::now(),
+		)
+	}
+
+	pub fn from_periods(periods: impl IntoIterator<Item = StartEnd>, now: chrono::DateTime<chrono::Utc>) -> Self {
 		// We need to sum up all the time so that we can calculate the age of the
 		// subscription. We want to make sure there are no overlapping periods so we
 		// dont have duplicate time.
-		let now = chrono::Utc::now();
+		let periods = periods.into_iter().collect::<Vec<_>>();
 
 		let expected_end = periods.iter().map(|p| p.end).max().unwrap_or(now);
 
@@ -66,15 +76,25 @@ impl SubAge {
 			acc
 		});
 
-		let days = merged_periods
+		let total = merged_periods
 			.iter()
 			.map(|p| (p.end.min(now) - p.start))
-			.sum::<chrono::Duration>()
-			.num_days() as i32;
-
-		let months = days as f64 / (365.25 / 12.0);
-		let extra = chrono::Duration::days((months.fract() * 30.44).round() as i64);
-		let months = months as i32;
+			.sum::<chrono::Duration>();
+
+		let days = total.num_days() as i32;
+
+		// Months are counted in calendar months as if all the time was subscribed
+		// continuously from the start of the first period, so a continuous
+		// subscription reaches its nth month exactly on the nth monthly anniversary.
+		let (months, extra) = match merged_periods.first() {
+			Some(first) => {
+				let end = first.start + total;
+				let months = whole_months_between(first.start, end);
+				let anniversary = first.start + chrono::Months::new(months as u32);
+				(months, end - anniversary)
+			}
+			None => (0, chrono::Duration::zero()),
+		};
 
 		SubAge {
 			extra,
@@ -113,312 +133,411 @@ pub struct StartEnd {
 	pub end: chrono::DateTime<chrono::Utc>,
 }
 
+/// The number of whole calendar months from `start` to `end`.
+///
+/// Adding a month to a day that does not exist in the next month lands on the
+/// last day of that month, so Jan 31 reaches one month on Feb 28 (or 29).
+fn whole_months_between(start: chrono::DateTime<chrono::Utc>, end: chrono::DateTime<chrono::Utc>) -> i32 {
+	if end <= start {
+		return 0;
+	}
+
+	let mut months = (end.year() - start.year()) * 12 + end.month() as i32 - start.month() as i32;
+
+	while months > 0 && start + chrono::Months::new(months as u32) > end {
+		months -= 1;
+	}
+
+	months
+}
+
 /// Grants entitlements for a subscription.
 pub async fn refresh(global: &Arc<Global>, subscription_id: SubscriptionId) -> Result<(), ApiError> {
-	global
+	let subscription_ids = HashSet::from([subscription_id]);
+
+	let skipped = global
 		.mutex
-		.acquire(format!("mutex:subscription:refresh:{subscription_id}"), || async {
-			let product = global
-				.subscription_product_by_id_loader
-				.load(subscription_id.product_id)
-				.await
-				.map_err(|_| ApiError::internal_server_error(ApiErrorCode::LoadError, "failed to load product"))?
-				.ok_or_else(|| ApiError::internal_server_error(ApiErrorCode::LoadError, "product not found"))?;
-
-			// load existing edges
-			let outgoing: HashSet<_> = global
-				.entitlement_edge_outbound_loader
-				.load(EntitlementEdgeKind::Subscription { subscription_id })
-				.await
-				.map_err(|_| {
-					ApiError::internal_server_error(ApiErrorCode::LoadError, "failed to load subscription entitlements")
-				})?
-				.unwrap_or_default()
-				.into_iter()
-				.filter(|e| e.id.managed_by == Some(EntitlementEdgeManagedBy::Subscription { subscription_id }))
-				.map(|e| e.id.to)
-				.collect();
-
-			let incoming: HashSet<_> = global
-				.entitlement_edge_inbound_loader
-				.load(EntitlementEdgeKind::Subscription { subscription_id })
-				.await
-				.map_err(|_| {
-					ApiError::internal_server_error(ApiErrorCode::LoadError, "failed to load subscription entitlements")
-				})?
-				.unwrap_or_default()
-				.into_iter()
-				.filter(|e| e.id.managed_by == Some(EntitlementEdgeManagedBy::Subscription { subscription_id }))
-				.map(|e| e.id.from)
-				.collect();
-
-			// load all periods
-			let periods: Vec<_> = SubscriptionPeriod::collection(&global.db)
-				.find(filter::filter! {
-					SubscriptionPeriod {
-						#[query(serde)]
-						subscription_id,
-					}
+		.acquire(refresh_mutex_key(subscription_id), || {
+			global
+				.mutex
+				.acquire(personal_emote_set_mutex_key(subscription_id.user_id), || {
+					refresh_batch(global, &subscription_ids)
 				})
-				.await
-				.map_err(|e| {
-					tracing::error!(error = %e, "failed to load subscription periods");
-					ApiError::internal_server_error(ApiErrorCode::LoadError, "failed to load subscription periods")
-				})?
-				.try_collect()
-				.await
-				.map_err(|e| {
-					tracing::error!(error = %e, "failed to collect subscription periods");
-					ApiError::internal_server_error(ApiErrorCode::LoadError, "failed to collect subscription periods")
-				})?;
-
-			let mut new_edges = vec![];
-			let mut remove_edges = vec![];
-			let sub_age = SubAge::new(&periods);
-
-			for benefit in product.benefits {
-				let is_fulfilled = sub_age.meets_condition(&benefit.condition);
-
-				let benefit_edge = EntitlementEdgeId {
-					from: EntitlementEdgeKind::Subscription { subscription_id },
-					to: EntitlementEdgeKind::SubscriptionBenefit {
-						subscription_benefit_id: benefit.id,
-					},
-					managed_by: Some(EntitlementEdgeManagedBy::Subscription { subscription_id }),
-				};
+		})
+		.await
+		.and_then(|result| result)
+		.map_err(|e| {
+			tracing::error!(error = %e, "failed to acquire mutex");
+			ApiError::internal_server_error(ApiErrorCode::Unknown, "failed to acquire mutex")
+		})??;
 
-				if is_fulfilled && !outgoing.contains(&benefit_edge.to) {
-					new_edges.push(benefit_edge);
-				} else if !is_fulfilled && outgoing.contains(&benefit_edge.to) {
-					remove_edges.push(benefit_edge);
-				}
-			}
+	if skipped.is_empty() {
+		Ok(())
+	} else {
+		Err(ApiError::internal_server_error(ApiErrorCode::LoadError, "product not found"))
+	}
+}
 
-			let xmas_gift = handle_xmas_2024_gift(global, subscription_id.user_id).await?;
-			for add in xmas_gift.adds {
-				new_edges.push(EntitlementEdgeId {
-					from: EntitlementEdgeKind::Subscription { subscription_id },
-					to: EntitlementEdgeKind::SpecialEvent { special_event_id: add },
-					managed_by: Some(EntitlementEdgeManagedBy::Subscription { subscription_id }),
-				});
-			}
+/// How many subscriptions of a failed batch are refreshed at once.
+const FALLBACK_CONCURRENCY: usize = 100;
 
-			for remove in xmas_gift.removes {
-				remove_edges.push(EntitlementEdgeId {
-					from: EntitlementEdgeKind::Subscription { subscription_id },
-					to: EntitlementEdgeKind::SpecialEvent {
-						special_event_id: remove,
-					},
-					managed_by: Some(EntitlementEdgeManagedBy::Subscription { subscription_id }),
-				});
-			}
+fn refresh_mutex_key(subscription_id: SubscriptionId) -> String {
+	format!("mutex:subscription:refresh:{subscription_id}")
+}
 
-			let now = chrono::Utc::now();
-			let grace_period = now + chrono::Duration::days(2);
-			let active_periods = periods
+fn personal_emote_set_mutex_key(user_id: UserId) -> String {
+	format!("mutex:user:sub:personal:{user_id}")
+}
+
+/// Grants entitlements for many subscriptions at once and returns the ones
+/// which could not be refreshed, the reason is logged for each of them.
+///
+/// The whole batch is refreshed while holding the mutex of every subscription
+/// in it, and of the personal emote set of every user. If any of them is
+/// locked elsewhere, or the batch fails, every
+/// subscription is refreshed on its own with [`refresh`], so a single broken
+/// subscription does not hold back the others. Up to
+/// [`FALLBACK_CONCURRENCY`] of those refreshes run at once.
+pub async fn refresh_many(
+	global: &Arc<Global>,
+	subscription_ids: impl IntoIterator<Item = SubscriptionId>,
+) -> Vec<SubscriptionId> {
+	let subscription_ids: HashSet<SubscriptionId> = subscription_ids.into_iter().collect();
+
+	let result = global
+		.mutex
+		.acquire_many(
+			subscription_ids
 				.iter()
-				.filter(|p| p.start < now && (p.end > now || (p.auto_renew && p.end > grace_period)))
-				.collect::<Vec<_>>();
+				.map(|&id| refresh_mutex_key(id))
+				.chain(subscription_ids.iter().map(|id| personal_emote_set_mutex_key(id.user_id))),
+			|| refresh_batch(global, &subscription_ids),
+		)
+		.await;
+
+	let count = subscription_ids.len();
+
+	match result {
+		Ok(Ok(skipped)) => return skipped,
+		Ok(Err(err)) => tracing::warn!(error = ?err, count, "failed to refresh subscriptions, retrying one by one"),
+		Err(err) => tracing::debug!(error = %err, count, "failed to lock subscriptions, retrying one by one"),
+	}
 
-			let user_edge = EntitlementEdgeId {
-				from: EntitlementEdgeKind::User {
-					user_id: subscription_id.user_id,
-				},
-				to: EntitlementEdgeKind::Subscription { subscription_id },
-				managed_by: Some(EntitlementEdgeManagedBy::Subscription { subscription_id }),
-			};
+	futures::stream::iter(subscription_ids)
+		.map(|subscription_id| async move {
+			let err = refresh(global, subscription_id).await.err()?;
+			tracing::error!(error = ?err, %subscription_id, "failed to refresh subscription");
+			Some(subscription_id)
+		})
+		.buffer_unordered(FALLBACK_CONCURRENCY)
+		.filter_map(std::future::ready)
+		.collect()
+		.await
+}
 
-			if !active_periods.is_empty() {
-				if !incoming.contains(&user_edge.from) {
-					new_edges.push(user_edge);
-				}
+/// Everything required is loaded in batches and all resulting writes are
+/// applied in a single transaction. Subscriptions whose product does not exist
+/// are skipped and returned.
+///
+/// The caller must hold the refresh mutex of every subscription and the
+/// personal emote set mutex of every user.
+async fn refresh_batch(
+	global: &Arc<Global>,
+	subscription_ids: &HashSet<SubscriptionId>,
+) -> Result<Vec<SubscriptionId>, ApiError> {
+	if subscription_ids.is_empty() {
+		return Ok(Vec::new());
+	}
 
-				let state = if active_periods.iter().any(|period| period.auto_renew) {
-					SubscriptionState::Active
-				} else {
-					SubscriptionState::CancelAtEnd
-				};
-
-				Subscription::collection(&global.db)
-					.update_one(
-						filter::filter! {
-							Subscription {
-								#[query(rename = "_id", serde)]
-								id: subscription_id,
-							}
-						},
-						update::update! {
-							#[query(set)]
-							Subscription {
-								#[query(serde)]
-								state,
-								ended_at: &None,
-								updated_at: chrono::Utc::now(),
-								search_updated_at: &None,
-							},
-							#[query(set_on_insert)]
-							Subscription {
-								#[query(rename = "_id", serde)]
-								id: subscription_id,
-								created_at: chrono::Utc::now(),
-							}
-						},
-					)
-					.upsert(true)
-					.await
-					.map_err(|e| {
-						tracing::error!(error = %e, "failed to update subscription");
-						ApiError::internal_server_error(ApiErrorCode::MutationError, "failed to update subscription")
-					})?;
-			} else {
-				if incoming.contains(&user_edge.from) {
-					remove_edges.push(user_edge);
-				}
+	let products = global
+		.subscription_product_by_id_loader
+		.load_many(subscription_ids.iter().map(|id| id.product_id))
+		.await
+		.map_err(|_| ApiError::internal_server_error(ApiErrorCode::LoadError, "failed to load products"))?;
 
-				Subscription::collection(&global.db)
-					.update_one(
-						filter::filter! {
-							Subscription {
-								#[query(rename = "_id", serde)]
-								id: subscription_id,
-							}
-						},
-						update::update! {
-							#[query(set)]
-							Subscription {
-								#[query(serde)]
-								state: SubscriptionState::Ended,
-								ended_at: Some(sub_age.expected_end),
-								updated_at: chrono::Utc::now(),
-								search_updated_at: &None,
-							},
-							#[query(set_on_insert)]
-							Subscription {
-								#[query(rename = "_id", serde)]
-								id: subscription_id,
-								created_at: chrono::Utc::now(),
-							}
-						},
-					)
-					.upsert(true)
-					.await
-					.map_err(|e| {
-						tracing::error!(error = %e, "failed to update subscription");
-						ApiError::internal_server_error(ApiErrorCode::MutationError, "failed to update subscription")
-					})?;
+	// load existing edges
+	let edge_keys = || {
+		subscription_ids
+			.iter()
+			.map(|&subscription_id| EntitlementEdgeKind::Subscription { subscription_id })
+	};
+
+	let outgoing = global
+		.entitlement_edge_outbound_loader
+		.load_many(edge_keys())
+		.await
+		.map_err(|_| ApiError::internal_server_error(ApiErrorCode::LoadError, "failed to load subscription entitlements"))?;
+
+	let incoming = global
+		.entitlement_edge_inbound_loader
+		.load_many(edge_keys())
+		.await
+		.map_err(|_| ApiError::internal_server_error(ApiErrorCode::LoadError, "failed to load subscription entitlements"))?;
+
+	// load all periods
+	let mut all_periods = SubscriptionPeriod::collection(&global.db)
+		.find(filter::filter! {
+			SubscriptionPeriod {
+				#[query(serde, selector = "in")]
+				subscription_id: subscription_ids.iter().collect::<Vec<_>>(),
 			}
+		})
+		.await
+		.map_err(|e| {
+			tracing::error!(error = %e, "failed to load subscription periods");
+			ApiError::internal_server_error(ApiErrorCode::LoadError, "failed to load subscription periods")
+		})?
+		.try_collect::<Vec<_>>()
+		.await
+		.map_err(|e| {
+			tracing::error!(error = %e, "failed to collect subscription periods");
+			ApiError::internal_server_error(ApiErrorCode::LoadError, "failed to collect subscription periods")
+		})?
+		.into_iter()
+		.into_group_map_by(|period| period.subscription_id);
 
-			if !remove_edges.is_empty() {
-				EntitlementEdge::collection(&global.db)
-					.delete_many(filter::filter! {
-						EntitlementEdge {
-							#[query(rename = "_id", selector = "in", serde)]
-							id: remove_edges,
-						}
-					})
-					.await
-					.map_err(|e| {
-						tracing::error!(error = %e, "failed to delete entitlement edges");
-						ApiError::internal_server_error(ApiErrorCode::MutationError, "failed to delete entitlement edges")
-					})?;
+	let user_ids: HashSet<UserId> = subscription_ids.iter().map(|id| id.user_id).collect();
+
+	let gifted_subs = load_gifted_subs(global, &user_ids).await?;
+
+	let user_periods = global
+		.subscription_periods_by_user_id_loader
+		.load_many(user_ids.iter().copied())
+		.await
+		.map_err(|_| ApiError::internal_server_error(ApiErrorCode::LoadError, "failed to load subscription periods"))?;
+
+	let now = chrono::Utc::now();
+	let grace_period = now + chrono::Duration::days(2);
+	let is_active = |p: &SubscriptionPeriod| p.start < now && (p.end > now || (p.auto_renew && p.end > grace_period));
+
+	// the personal emote set capacity comes from every active subscription of the
+	// user, including the ones which are not part of this batch
+	let mut active_products: HashMap<UserId, HashSet<SubscriptionProductId>> = user_periods
+		.values()
+		.flatten()
+		.filter(|&p| !subscription_ids.contains(&p.subscription_id) && is_active(p))
+		.map(|p| (p.subscription_id.user_id, p.subscription_id.product_id))
+		.into_group_map()
+		.into_iter()
+		.map(|(user_id, product_ids)| (user_id, product_ids.into_iter().collect()))
+		.collect();
+
+	let mut new_edges = vec![];
+	let mut remove_edges = vec![];
+	let mut subscription_updates = vec![];
+	let mut skipped = Vec::new();
+
+	for &subscription_id in subscription_ids {
+		let Some(product) = products.get(&subscription_id.product_id) else {
+			tracing::error!(%subscription_id, "product not found");
+			skipped.push(subscription_id);
+			continue;
+		};
+
+		let key = EntitlementEdgeKind::Subscription { subscription_id };
+		let managed_by = Some(EntitlementEdgeManagedBy::Subscription { subscription_id });
+
+		let outgoing: HashSet<_> = outgoing
+			.get(&key)
+			.into_iter()
+			.flatten()
+			.filter(|e| e.id.managed_by == managed_by)
+			.map(|e| e.id.to.clone())
+			.collect();
+
+		let incoming: HashSet<_> = incoming
+			.get(&key)
+			.into_iter()
+			.flatten()
+			.filter(|e| e.id.managed_by == managed_by)
+			.map(|e| e.id.from.clone())
+			.collect();
+
+		let periods = all_periods.remove(&subscription_id).unwrap_or_default();
+		let sub_age = SubAge::new(&periods);
+
+		for benefit in &product.benefits {
+			let is_fulfilled = sub_age.meets_condition(&benefit.condition);
+
+			let benefit_edge = EntitlementEdgeId {
+				from: key.clone(),
+				to: EntitlementEdgeKind::SubscriptionBenefit {
+					subscription_benefit_id: benefit.id,
+				},
+				managed_by: managed_by.clone(),
+			};
+
+			if is_fulfilled && !outgoing.contains(&benefit_edge.to) {
+				new_edges.push(benefit_edge);
+			} else if !is_fulfilled && outgoing.contains(&benefit_edge.to) {
+				remove_edges.push(benefit_edge);
 			}
+		}
+
+		let xmas_gift = handle_xmas_2024_gift(
+			gifted_subs
+				.get(&subscription_id.user_id)
+				.map(Vec::as_slice)
+				.unwrap_or_default(),
+		);
+
+		for add in xmas_gift.adds {
+			new_edges.push(EntitlementEdgeId {
+				from: key.clone(),
+				to: EntitlementEdgeKind::SpecialEvent { special_event_id: add },
+				managed_by: managed_by.clone(),
+			});
+		}
 
-			if !new_edges.is_empty() {
-				EntitlementEdge::collection(&global.db)
-					.insert_many(new_edges.into_iter().map(|id| EntitlementEdge { id }))
-					.with_options(mongodb::options::InsertManyOptions::builder().ordered(false).build())
-					.await
-					.map_err(|e| {
-						tracing::error!(error = %e, "failed to delete entitlement edges");
-						ApiError::internal_server_error(ApiErrorCode::MutationError, "failed to delete entitlement edges")
-					})?;
+		for remove in xmas_gift.removes {
+			remove_edges.push(EntitlementEdgeId {
+				from: key.clone(),
+				to: EntitlementEdgeKind::SpecialEvent {
+					special_event_id: remove,
+				},
+				managed_by: managed_by.clone(),
+			});
+		}
+
+		let active_periods = periods.iter().filter(|&p| is_active(p)).collect::<Vec<_>>();
+
+		let user_edge = EntitlementEdgeId {
+			from: EntitlementEdgeKind::User {
+				user_id: subscription_id.user_id,
+			},
+			to: key.clone(),
+			managed_by: managed_by.clone(),
+		};
+
+		if !active_periods.is_empty() {
+			active_products.entry(subscription_id.user_id).or_default().insert(product.id);
+
+			if !incoming.contains(&user_edge.from) {
+				new_edges.push(user_edge);
 			}
 
-			let personal_emote_set_id = EmoteSet::collection(&global.db)
-				.find_one(filter::filter! {
-					EmoteSet {
-						owner_id: subscription_id.user_id,
-						#[query(serde)]
-						kind: EmoteSetKind::Personal,
-					}
-				})
-				.with_options(FindOneOptions::builder().sort(bson::doc! { "_id": -1 }).build())
-				.await
-				.map_err(|e| {
-					tracing::error!(error = %e, "failed to update emote set");
-					ApiError::internal_server_error(ApiErrorCode::MutationError, "failed to update emote set")
-				})?
-				.map(|set| set.id);
-
-			let personal_emote_set_id = if let Some(personal_emote_set_id) = personal_emote_set_id {
-				personal_emote_set_id
+			let state = if active_periods.iter().any(|period| period.auto_renew) {
+				SubscriptionState::Active
 			} else {
-				transaction_with_mutex(
-					global,
-					Some(format!("mutex:user:sub:personal:{}", subscription_id.user_id).into()),
-					|mut tx| async move {
-						if let Some(set) = tx
-							.find_one(
-								filter::filter! {
-									EmoteSet {
-										owner_id: subscription_id.user_id,
-										#[query(serde)]
-										kind: EmoteSetKind::Personal,
-									}
-								},
-								Some(FindOneOptions::builder().sort(bson::doc! { "_id": -1 }).build()),
-							)
-							.await?
-						{
-							return Ok(set.id);
-						}
+				SubscriptionState::CancelAtEnd
+			};
 
-						let set = EmoteSet {
-							id: EmoteSetId::new(),
-							name: "Personal Emote Set".to_string(),
-							owner_id: Some(subscription_id.user_id),
-							kind: EmoteSetKind::Personal,
-							updated_at: chrono::Utc::now(),
-							origin_config: None,
-							capacity: Some(5), /* TODO: this is hard coded however we should likely get this from the sub
-							                    * product */
-							description: None,
-							emotes: vec![],
-							emotes_changed_since_reindex: false,
-							tags: vec![],
-							search_updated_at: None,
-						};
-
-						tx.insert_one::<EmoteSet>(&set, None).await?;
-
-						let id = set.id;
-
-						tx.register_event(InternalEvent {
-							actor: None,
-							session_id: None,
-							timestamp: chrono::Utc::now(),
-							data: InternalEventData::EmoteSet {
-								after: set,
-								data: InternalEventEmoteSetData::Create,
-							},
-						})?;
+			subscription_updates.push((subscription_id, state, None));
+		} else {
+			if incoming.contains(&user_edge.from) {
+				remove_edges.push(user_edge);
+			}
+
+			subscription_updates.push((subscription_id, SubscriptionState::Ended, Some(sub_age.expected_end)));
+		}
+	}
 
-						Ok::<_, TransactionError<Infallible>>(id)
+	let active_products_by_id = global
+		.subscription_product_by_id_loader
+		.load_many(active_products.values().flatten().copied())
+		.await
+		.map_err(|_| ApiError::internal_server_error(ApiErrorCode::LoadError, "failed to load products"))?;
+
+	// a user with multiple subscriptions gets the largest capacity of them, users
+	// without an active subscription get the default capacity
+	let personal_emote_set_capacities = &user_ids
+		.iter()
+		.map(|user_id| {
+			let capacity = active_products
+				.get(user_id)
+				.into_iter()
+				.flatten()
+				.filter_map(|id| active_products_by_id.get(id))
+				.map(|product| product.personal_emote_set_capacity())
+				.max()
+				.unwrap_or(SubscriptionProduct::DEFAULT_PERSONAL_EMOTE_SET_CAPACITY);
+
+			(*user_id, capacity)
+		})
+		.collect::<HashMap<_, _>>();
+	let subscription_updates = &subscription_updates;
+	let new_edges = &new_edges;
+	let remove_edges = &remove_edges;
+
+	transaction(global, "sub_refresh.refresh", |mut tx| async move {
+		let subscriptions = subscription_updates.iter().fold(
+			BulkWrite::<Subscription>::new(),
+			|write, (subscription_id, state, ended_at)| {
+				write.upsert_one(
+					filter::filter! {
+						Subscription {
+							#[query(rename = "_id", serde)]
+							id: subscription_id,
+						}
+					},
+					update::update! {
+						#[query(set)]
+						Subscription {
+							#[query(serde)]
+							state,
+							ended_at,
+							updated_at: chrono::Utc::now(),
+							search_updated_at: &None,
+						},
+						#[query(set_on_insert)]
+						Subscription {
+							#[query(rename = "_id", serde)]
+							id: subscription_id,
+							created_at: chrono::Utc::now(),
+						}
 					},
 				)
-				.await
-				.map_err(|e| {
-					tracing::error!(error = %e, "failed to create personal emote set");
-					ApiError::internal_server_error(ApiErrorCode::MutationError, "failed to create personal emote set")
-				})?
-			};
+			},
+		);
+
+		if !subscriptions.is_empty() {
+			tx.bulk_write(subscriptions, None).await?;
+		}
+
+		let mut edges = BulkWrite::<EntitlementEdge>::new();
+
+		if !remove_edges.is_empty() {
+			edges = edges.delete_many(filter::filter! {
+				EntitlementEdge {
+					#[query(rename = "_id", selector = "in", serde)]
+					id: remove_edges,
+				}
+			});
+		}
+
+		// upserts so that edges which already exist do not fail the write
+		let edges = new_edges.iter().fold(edges, |write, id| {
+			write.upsert_one(
+				filter::filter! {
+					EntitlementEdge {
+						#[query(rename = "_id", serde)]
+						id,
+					}
+				},
+				update::update! {
+					#[query(set_on_insert)]
+					EntitlementEdge {
+						#[query(rename = "_id", serde)]
+						id,
+					}
+				},
+			)
+		});
 
-			User::collection(&global.db)
-				.update_one(
+		if !edges.is_empty() {
+			tx.bulk_write(edges, None).await?;
+		}
+
+		let personal_emote_sets = ensure_personal_emote_sets(&mut tx, personal_emote_set_capacities).await?;
+
+		let users = personal_emote_sets
+			.iter()
+			.fold(BulkWrite::<User>::new(), |write, (user_id, personal_emote_set_id)| {
+				write.update_one(
 					filter::filter! {
 						User {
 							#[query(rename = "_id")]
-							id: subscription_id.user_id,
+							id: user_id,
 							#[query(flatten)]
 							style: UserStyle {
 								personal_emote_set_id: &None,
@@ -435,19 +554,179 @@ pub async fn refresh(global: &Arc<Global>, subscription_id: SubscriptionId) -> R
 						},
 					},
 				)
-				.await
-				.map_err(|e| {
-					tracing::error!(error = %e, "failed to update user");
-					ApiError::internal_server_error(ApiErrorCode::MutationError, "failed to update user")
-				})?;
+			});
+
+		if !users.is_empty() {
+			tx.bulk_write(users, None).await?;
+		}
+
+		Ok(())
+	})
+	.await
+	.map_err(|e| {
+		tracing::error!(error = %e, "failed to refresh subscriptions");
+		match e {
+			TransactionError::Custom(e) => e,
+			_ => ApiError::internal_server_error(ApiErrorCode::MutationError, "failed to refresh subscriptions"),
+		}
+	})?;
+
+	Ok(skipped)
+}
+
+/// Finds the personal emote set of every given user, creating the ones that
+/// do not exist yet and updating the ones with the wrong capacity.
+async fn ensure_personal_emote_sets(
+	tx: &mut TransactionSession<'_, ApiError>,
+	capacities: &HashMap<UserId, i32>,
+) -> Result<HashMap<UserId, EmoteSetId>, TransactionError<ApiError>> {
+	let sets: Vec<EmoteSet> = tx
+		.find(
+			filter::filter! {
+				EmoteSet {
+					#[query(selector = "in")]
+					owner_id: capacities.keys().copied().collect::<Vec<_>>(),
+					#[query(serde)]
+					kind: EmoteSetKind::Personal,
+				}
+			},
+			FindOptions::builder().sort(bson::doc! { "_id": -1 }).build(),
+		)
+		.await?;
+
+	// sets are sorted newest first, so the first one we see for a user wins
+	let mut existing = HashMap::new();
+	for set in sets {
+		if let Some(owner_id) = set.owner_id {
+			existing.entry(owner_id).or_insert(set);
+		}
+	}
+
+	let mut personal_emote_sets = HashMap::new();
+
+	for (&user_id, &capacity) in capacities {
+		let id = match existing.remove(&user_id) {
+			Some(set) if set.capacity == Some(capacity) => set.id,
+			set => ensure_personal_emote_set(tx, user_id, set, capacity).await?,
+		};
+
+		personal_emote_sets.insert(user_id, id);
+	}
+
+	Ok(personal_emote_sets)
+}
+
+/// Creates the personal emote set of a user if it does not exist yet, or
+/// updates its capacity if it differs from the given one.
+async fn ensure_personal_emote_set(
+	tx: &mut TransactionSession<'_, ApiError>,
+	user_id: UserId,
+	existing: Option<EmoteSet>,
+	capacity: i32,
+) -> Result<EmoteSetId, TransactionError<ApiError>> {
+	if let Some(set) = existing {
+		let after = tx
+			.find_one_and_update(
+				filter::filter! {
+					EmoteSet {
+						#[query(rename = "_id")]
+						id: set.id,
+					}
+				},
+				update::update! {
+					#[query(set)]
+					EmoteSet {
+						capacity,
+						updated_at: chrono::Utc::now(),
+						search_updated_at: &None,
+					}
+				},
+				FindOneAndUpdateOptions::builder()
+					.return_document(ReturnDocument::After)
+					.build(),
+			)
+			.await?
+			.ok_or(TransactionError::Custom(ApiError::internal_server_error(
+				ApiErrorCode::LoadError,
+				"failed to load emote set",
+			)))?;
+
+		tx.register_event(InternalEvent {
+			actor: None,
+			session_id: None,
+			timestamp: chrono::Utc::now(),
+			data: InternalEventData::EmoteSet {
+				after,
+				data: InternalEventEmoteSetData::ChangeCapacity {
+					old: set.capacity,
+					new: Some(capacity),
+				},
+			},
+		})?;
+
+		return Ok(set.id);
+	}
+
+	let set = EmoteSet {
+		id: EmoteSetId::new(),
+		name: "Personal Emote Set".to_string(),
+		owner_id: Some(user_id),
+		kind: EmoteSetKind::Personal,
+		updated_at: chrono::Utc::now(),
+		origin_config: None,
+		capacity: Some(capacity),
+		description: None,
+		emotes: vec![],
+		emotes_changed_since_reindex: false,
+		tags: vec![],
+		search_updated_at: None,
+	};
+
+	tx.insert_one::<EmoteSet>(&set, None).await?;
+
+	let id = set.id;
+
+	tx.register_event(InternalEvent {
+		actor: None,
+		session_id: None,
+		timestamp: chrono::Utc::now(),
+		data: InternalEventData::EmoteSet {
+			after: set,
+			data: InternalEventEmoteSetData::Create,
+		},
+	})?;
+
+	Ok(id)
+}
 
-			Ok(())
+/// Loads the subscription periods gifted by each of the given users.
+async fn load_gifted_subs(
+	global: &Arc<Global>,
+	user_ids: &HashSet<UserId>,
+) -> Result<HashMap<UserId, Vec<SubscriptionPeriod>>, ApiError> {
+	let gifted_subs = SubscriptionPeriod::collection(&global.db)
+		.find(filter::filter! {
+			SubscriptionPeriod {
+				#[query(selector = "in")]
+				gifted_by: user_ids.iter().copied().collect::<Vec<_>>(),
+			}
 		})
 		.await
 		.map_err(|e| {
-			tracing::error!(error = %e, "failed to acquire mutex");
-			ApiError::internal_server_error(ApiErrorCode::Unknown, "failed to acquire mutex")
+			tracing::error!(error = %e, "failed to find gifted subs");
+			ApiError::internal_server_error(ApiErrorCode::LoadError, "failed to find gifted subs")
 		})?
+		.try_collect::<Vec<_>>()
+		.await
+		.map_err(|e| {
+			tracing::error!(error = %e, "failed to collect gifted subs");
+			ApiError::internal_server_error(ApiErrorCode::LoadError, "failed to collect gifted subs")
+		})?;
+
+	Ok(gifted_subs
+		.into_iter()
+		.filter_map(|sub| Some((sub.gifted_by?, sub)))
+		.into_group_map())
 }
 
 struct Xmas2024Gift {
@@ -466,7 +745,7 @@ struct Event {
 	levels: Vec<Level>,
 }
 
-async fn handle_xmas_2024_gift(global: &Arc<Global>, customer_id: UserId) -> Result<Xmas2024Gift, ApiError> {
+fn handle_xmas_2024_gift(gifted_subs: &[SubscriptionPeriod]) -> Xmas2024Gift {
 	// Count the number of subs the user has gifted.
 	const XMAS_1_SUB_ID: &str = "0193b680-0de9-25b4-dd0d-21b8ab811bc0";
 	const XMAS_10_SUBS_ID: &str = "0193F0E3-828A-13C2-1E24-17CF874748DF";
@@ -520,24 +799,6 @@ async fn handle_xmas_2024_gift(global: &Arc<Global>, customer_id: UserId) -> Res
 		},
 	];
 
-	let gifted_subs = SubscriptionPeriod::collection(&global.db)
-		.find(filter::filter! {
-			SubscriptionPeriod {
-				gifted_by: customer_id,
-			}
-		})
-		.await
-		.map_err(|e| {
-			tracing::error!(error = %e, "failed to find gifted subs");
-			ApiError::internal_server_error(ApiErrorCode::LoadError, "failed to find gifted subs")
-		})?
-		.try_collect::<Vec<_>>()
-		.await
-		.map_err(|e| {
-			tracing::error!(error = %e, "failed to collect gifted subs");
-			ApiError::internal_server_error(ApiErrorCode::LoadError, "failed to collect gifted subs")
-		})?;
-
 	// Filter out any subs that are not in the xmas event
 	const MONTH_SUB_PRODUCT_ID: &str = "price_1JWQ2QCHxsWbK3R31cZkaocV"; // = 1 gift
 	const YEAR_SUB_PRODUCT_ID: &str = "price_1JWQ2RCHxsWbK3R3a6emz76a"; // = 10 gifts
@@ -552,7 +813,7 @@ async fn handle_xmas_2024_gift(global: &Arc<Global>, customer_id: UserId) -> Res
 
 	for event in events {
 		let mut gift_count = 0;
-		for gifted_sub in &gifted_subs {
+		for gifted_sub in gifted_subs {
 			if gifted_sub.id.timestamp() >= event.start && gifted_sub.id.timestamp() <= event.end {
 				if gifted_sub.product_id == month_product_id {
 					gift_count += 1;
@@ -571,5 +832,72 @@ async fn handle_xmas_2024_gift(global: &Arc<Global>, customer_id: UserId) -> Res
 		}
 	}
 
-	Ok(xmas_gift)
+	xmas_gift
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn date(year: i32, month: u32, day: u32) -> chrono::DateTime<chrono::Utc> {
+		chrono::Utc.with_ymd_and_hms(year, month, day, 0, 0, 0).unwrap()
+	}
+
+	fn age(periods: &[(chrono::DateTime<chrono::Utc>, chrono::DateTime<chrono::Utc>)]) -> SubAge {
+		SubAge::from_periods(periods.iter().map(|&(start, end)| StartEnd { start, end }), date(2030, 1, 1))
+	}
+
+	#[test]
+	fn whole_months_month_length_boundaries() {
+		assert_eq!(whole_months_between(date(2023, 1, 31), date(2023, 2, 27)), 0);
+		assert_eq!(whole_months_between(date(2023, 1, 31), date(2023, 2, 28)), 1);
+		assert_eq!(whole_months_between(date(2023, 3, 31), date(2023, 4, 30)), 1);
+		assert_eq!(whole_months_between(date(2023, 1, 15), date(2023, 4, 14)), 2);
+		assert_eq!(whole_months_between(date(2023, 1, 15), date(2023, 4, 15)), 3);
+		assert_eq!(whole_months_between(date(2023, 12, 15), date(2024, 1, 15)), 1);
+	}
+
+	#[test]
+	fn whole_months_leap_years() {
+		assert_eq!(whole_months_between(date(2024, 1, 31), date(2024, 2, 28)), 0);
+		assert_eq!(whole_months_between(date(2024, 1, 31), date(2024, 2, 29)), 1);
+		assert_eq!(whole_months_between(date(2024, 2, 29), date(2025, 2, 28)), 12);
+		assert_eq!(whole_months_between(date(2023, 3, 1), date(2024, 3, 1)), 12);
+		assert_eq!(whole_months_between(date(2023, 3, 1), date(2024, 2, 29)), 11);
+	}
+
+	#[test]
+	fn continuous_subscription_reaches_month_on_anniversary() {
+		let start = date(2024, 1, 15);
+		let anniversary = date(2024, 4, 15);
+
+		let before = age(&[(start, anniversary - chrono::Duration::seconds(1))]);
+		assert_eq!(before.months, 2);
+		assert!(!before.meets_condition(&SubscriptionBenefitCondition::Duration(DurationUnit::Months(3))));
+
+		let on = age(&[(start, anniversary)]);
+		assert_eq!(on.months, 3);
+		assert_eq!(on.extra, chrono::Duration::zero());
+		assert!(on.meets_condition(&SubscriptionBenefitCondition::Duration(DurationUnit::Months(3))));
+	}
+
+	#[test]
+	fn gaps_are_counted_from_the_first_period() {
+		// 30 days in january and 31 days from march to april, as if subscribed
+		// continuously from january 1st this ends on march 3rd
+		let age = age(&[(date(2023, 1, 1), date(2023, 1, 31)), (date(2023, 3, 1), date(2023, 4, 1))]);
+
+		assert_eq!(age.days, 61);
+		assert_eq!(age.months, 2);
+		assert_eq!(age.extra, chrono::Duration::days(2));
+	}
+
+	#[test]
+	fn overlapping_periods_are_not_counted_twice() {
+		let age = age(&[(date(2024, 1, 1), date(2024, 2, 1)), (date(2024, 1, 15), date(2024, 3, 1))]);
+
+		assert_eq!(age.periods.len(), 1);
+		assert_eq!(age.months, 2);
+		assert_eq!(age.extra, chrono::Duration::zero());
+	}
 }
@@ -0,0 +1,118 @@
+use std::borrow::Borrow;
+use std::marker::PhantomData;
+
+use mongodb::options::{DeleteManyModel, InsertOneModel, UpdateModifications, UpdateOneModel, WriteModel};
+use mongodb::Namespace;
+use shared::database::queries::{filter, update};
+
+enum Model {
+	/// The serialization error is returned when the write is executed, so the
+	/// builder can be chained
+	InsertOne(Result<bson::Document, bson::ser::Error>),
+	UpdateOne {
+		filter: bson::Document,
+		update: bson::Document,
+		upsert: bool,
+	},
+	DeleteMany(bson::Document),
+}
+
+/// A typed list of writes on the collection of `U` which are sent to the
+/// database in a single round trip with
+/// [`TransactionSession::bulk_write`](super::TransactionSession::bulk_write).
+pub struct BulkWrite<U> {
+	models: Vec<Model>,
+	_marker: PhantomData<U>,
+}
+
+impl<U> Default for BulkWrite<U> {
+	fn default() -> Self {
+		Self {
+			models: Vec::new(),
+			_marker: PhantomData,
+		}
+	}
+}
+
+impl<U> BulkWrite<U> {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	pub fn len(&self) -> usize {
+		self.models.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.models.is_empty()
+	}
+
+	pub fn insert_one(mut self, item: impl Borrow<U>) -> Self
+	where
+		U: serde::Serialize,
+	{
+		self.models.push(Model::InsertOne(bson::to_document(item.borrow())));
+		self
+	}
+
+	pub fn update_one(mut self, filter: impl Into<filter::Filter<U>>, update: impl Into<update::Update<U>>) -> Self {
+		self.models.push(Model::UpdateOne {
+			filter: filter.into().to_document(),
+			update: update.into().to_document(),
+			upsert: false,
+		});
+		self
+	}
+
+	pub fn upsert_one(mut self, filter: impl Into<filter::Filter<U>>, update: impl Into<update::Update<U>>) -> Self {
+		self.models.push(Model::UpdateOne {
+			filter: filter.into().to_document(),
+			update: update.into().to_document(),
+			upsert: true,
+		});
+		self
+	}
+
+	pub fn delete_many(mut self, filter: impl Into<filter::Filter<U>>) -> Self {
+		self.models.push(Model::DeleteMany(filter.into().to_document()));
+		self
+	}
+
+	/// The filters of the updates and deletes and the inserted documents,
+	/// `None` for documents which failed to serialize.
+	pub(super) fn documents(&self) -> Vec<Option<bson::Document>> {
+		self.models
+			.iter()
+			.map(|model| match model {
+				Model::InsertOne(document) => document.as_ref().ok().cloned(),
+				Model::UpdateOne { filter, .. } => Some(filter.clone()),
+				Model::DeleteMany(filter) => Some(filter.clone()),
+			})
+			.collect()
+	}
+
+	pub(super) fn into_models(self, namespace: Namespace) -> Result<Vec<WriteModel>, bson::ser::Error> {
+		self.models
+			.into_iter()
+			.map(|model| match model {
+				Model::InsertOne(document) => Ok(InsertOneModel::builder()
+					.namespace(namespace.clone())
+					.document(document?)
+					.build()
+					.into()),
+				Model::UpdateOne { filter, update, upsert } => Ok(UpdateOneModel::builder()
+					.namespace(namespace.clone())
+					.filter(filter)
+					.update(UpdateModifications::Document(update))
+					.upsert(upsert)
+					.build()
+					.into()),
+				Model::DeleteMany(filter) => Ok(DeleteManyModel::builder()
+					.namespace(namespace.clone())
+					.filter(filter)
+					.build()
+					.into()),
+			})
+			.collect()
+	}
+}
@@ -1,28 +1,66 @@
 use std::borrow::Borrow;
+use std::collections::HashSet;
 use std::fmt::Debug;
 use std::marker::PhantomData;
 use std::sync::Arc;
 
 use futures::TryStreamExt;
 use mongodb::error::{TRANSIENT_TRANSACTION_ERROR, UNKNOWN_TRANSACTION_COMMIT_RESULT};
-use mongodb::results::{DeleteResult, InsertManyResult, InsertOneResult, UpdateResult};
+use mongodb::results::{DeleteResult, InsertManyResult, InsertOneResult, SummaryBulkWriteResult, UpdateResult};
+use scuffle_metrics::metrics;
 use shared::database::badge::BadgeId;
 use shared::database::emote::EmoteId;
 use shared::database::emote_set::EmoteSetId;
+use shared::database::entitlement::{EntitlementEdge, EntitlementEdgeId, EntitlementEdgeKind};
 use shared::database::paint::PaintId;
+use shared::database::queries::projection::Projection;
 use shared::database::queries::{filter, update};
-use shared::database::role::RoleId;
+use shared::database::role::{Role, RoleId};
 use shared::database::stored_event::StoredEvent;
 use shared::database::ticket::TicketId;
 use shared::database::user::ban::UserBanId;
 use shared::database::user::UserId;
 use shared::database::MongoCollection;
-use shared::event::{InternalEvent, InternalEventPayload};
+use shared::event::{emote_set_subject, InternalEvent, InternalEventPayload};
 use spin::Mutex;
 
+use crate::dataloader::user_computed_cache;
 use crate::global::Global;
 use crate::mutex::{MutexAquireRequest, MutexError};
 
+mod bulk_write;
+
+pub use bulk_write::BulkWrite;
+
+#[metrics]
+mod transaction {
+	use scuffle_metrics::{CounterU64, HistogramF64, MetricEnum};
+
+	#[derive(Debug, Clone, Copy, MetricEnum)]
+	pub enum RetryReason {
+		Operation,
+		Commit,
+	}
+
+	#[derive(Debug, Clone, Copy, MetricEnum)]
+	pub enum Outcome {
+		/// The transaction was committed.
+		Committed,
+		/// The transaction was aborted by the caller with a custom error.
+		Aborted,
+		/// The transaction was retried too many times.
+		TooManyFailures,
+		/// The transaction failed with any other error.
+		Failed,
+	}
+
+	pub fn total(operation: &'static str, outcome: Outcome) -> CounterU64;
+
+	pub fn retry(operation: &'static str, reason: RetryReason) -> CounterU64;
+
+	pub fn duration(operation: &'static str, outcome: Outcome) -> HistogramF64;
+}
+
 pub struct TransactionSession<'a, E>(Arc<Mutex<SessionInner<'a>>>, PhantomData<E>);
 
 impl<'a, E: Debug> TransactionSession<'a, E> {
@@ -33,6 +71,7 @@ impl<'a, E: Debug> TransactionSession<'a, E> {
 	async fn reset(&mut self) -> Result<(), TransactionError<E>> {
 		let mut this = self.0.try_lock().ok_or(TransactionError::SessionLocked)?;
 		this.events.clear();
+		this.invalidate_user_computed = UserComputedInvalidation::None;
 		if this.in_txn {
 			this.session.abort_transaction().await.ok();
 			this.in_txn = false;
@@ -66,6 +105,48 @@ impl<E: Debug> TransactionSession<'_, E> {
 		Ok(find.stream(&mut this.session).try_collect().await?)
 	}
 
+	#[tracing::instrument(skip_all, name = "TransactionSession::find_projected", fields(collection = %U::COLLECTION_NAME))]
+	pub async fn find_projected<U: MongoCollection, P: Projection<U> + Send + Sync>(
+		&mut self,
+		filter: impl Into<filter::Filter<U>>,
+		options: impl Into<Option<mongodb::options::FindOptions>>,
+	) -> Result<Vec<P>, TransactionError<E>> {
+		let mut this = self.0.try_lock().ok_or(TransactionError::SessionLocked)?;
+
+		let mut options = options.into().unwrap_or_default();
+		if options.projection.is_none() {
+			options.projection = Some(P::projection());
+		}
+
+		let mut find = U::collection(&this.global.db)
+			.untyped()
+			.clone_with_type::<P>()
+			.find(filter.into().to_document())
+			.with_options(options)
+			.session(&mut this.session)
+			.await?;
+
+		Ok(find.stream(&mut this.session).try_collect().await?)
+	}
+
+	#[tracing::instrument(skip_all, name = "TransactionSession::aggregate", fields(collection = %U::COLLECTION_NAME))]
+	pub async fn aggregate<U: MongoCollection, T: serde::de::DeserializeOwned + Send + Sync>(
+		&mut self,
+		pipeline: impl IntoIterator<Item = bson::Document>,
+		options: impl Into<Option<mongodb::options::AggregateOptions>>,
+	) -> Result<Vec<T>, TransactionError<E>> {
+		let mut this = self.0.try_lock().ok_or(TransactionError::SessionLocked)?;
+
+		let mut cursor = U::collection(&this.global.db)
+			.aggregate(pipeline)
+			.with_options(options)
+			.session(&mut this.session)
+			.with_type::<T>()
+			.await?;
+
+		Ok(cursor.stream(&mut this.session).try_collect().await?)
+	}
+
 	#[tracing::instrument(skip_all, name = "TransactionSession::find_one", fields(collection = %U::COLLECTION_NAME))]
 	pub async fn find_one<U: MongoCollection + serde::de::DeserializeOwned>(
 		&mut self,
@@ -92,6 +173,8 @@ impl<E: Debug> TransactionSession<'_, E> {
 		options: impl Into<Option<mongodb::options::FindOneAndUpdateOptions>>,
 	) -> Result<Option<U>, TransactionError<E>> {
 		let mut this = self.0.try_lock().ok_or(TransactionError::SessionLocked)?;
+		let filter: filter::Filter<U> = filter.into();
+		this.track_write::<U>(|| vec![Some(filter.to_document())]);
 
 		let result = U::collection(&this.global.db)
 			.find_one_and_update(filter, update)
@@ -102,6 +185,26 @@ impl<E: Debug> TransactionSession<'_, E> {
 		Ok(result)
 	}
 
+	#[tracing::instrument(skip_all, name = "TransactionSession::find_one_and_update_pipeline", fields(collection = %U::COLLECTION_NAME))]
+	pub async fn find_one_and_update_pipeline<U: MongoCollection + serde::de::DeserializeOwned>(
+		&mut self,
+		filter: impl Into<filter::Filter<U>>,
+		pipeline: impl IntoIterator<Item = bson::Document>,
+		options: impl Into<Option<mongodb::options::FindOneAndUpdateOptions>>,
+	) -> Result<Option<U>, TransactionError<E>> {
+		let mut this = self.0.try_lock().ok_or(TransactionError::SessionLocked)?;
+		let filter: filter::Filter<U> = filter.into();
+		this.track_write::<U>(|| vec![Some(filter.to_document())]);
+
+		let result = U::collection(&this.global.db)
+			.find_one_and_update_pipeline(filter, pipeline)
+			.with_options(options)
+			.session(&mut this.session)
+			.await?;
+
+		Ok(result)
+	}
+
 	#[tracing::instrument(skip_all, name = "TransactionSession::find_one_and_delete", fields(collection = %U::COLLECTION_NAME))]
 	pub async fn find_one_and_delete<U: MongoCollection + serde::de::DeserializeOwned>(
 		&mut self,
@@ -109,6 +212,8 @@ impl<E: Debug> TransactionSession<'_, E> {
 		options: impl Into<Option<mongodb::options::FindOneAndDeleteOptions>>,
 	) -> Result<Option<U>, TransactionError<E>> {
 		let mut this = self.0.try_lock().ok_or(TransactionError::SessionLocked)?;
+		let filter: filter::Filter<U> = filter.into();
+		this.track_write::<U>(|| vec![Some(filter.to_document())]);
 
 		let result = U::collection(&this.global.db)
 			.find_one_and_delete(filter)
@@ -128,6 +233,8 @@ impl<E: Debug> TransactionSession<'_, E> {
 		options: impl Into<Option<mongodb::options::UpdateOptions>>,
 	) -> Result<UpdateResult, TransactionError<E>> {
 		let mut this = self.0.try_lock().ok_or(TransactionError::SessionLocked)?;
+		let filter: filter::Filter<U> = filter.into();
+		this.track_write::<U>(|| vec![Some(filter.to_document())]);
 
 		let result = U::collection(&this.global.db)
 			.update_many(filter, update)
@@ -146,6 +253,8 @@ impl<E: Debug> TransactionSession<'_, E> {
 		options: impl Into<Option<mongodb::options::UpdateOptions>>,
 	) -> Result<UpdateResult, TransactionError<E>> {
 		let mut this = self.0.try_lock().ok_or(TransactionError::SessionLocked)?;
+		let filter: filter::Filter<U> = filter.into();
+		this.track_write::<U>(|| vec![Some(filter.to_document())]);
 
 		let result = U::collection(&this.global.db)
 			.update_one(filter, update)
@@ -163,6 +272,8 @@ impl<E: Debug> TransactionSession<'_, E> {
 		options: impl Into<Option<mongodb::options::DeleteOptions>>,
 	) -> Result<DeleteResult, TransactionError<E>> {
 		let mut this = self.0.try_lock().ok_or(TransactionError::SessionLocked)?;
+		let filter: filter::Filter<U> = filter.into();
+		this.track_write::<U>(|| vec![Some(filter.to_document())]);
 
 		let result = U::collection(&this.global.db)
 			.delete_many(filter)
@@ -180,6 +291,8 @@ impl<E: Debug> TransactionSession<'_, E> {
 		options: impl Into<Option<mongodb::options::DeleteOptions>>,
 	) -> Result<DeleteResult, TransactionError<E>> {
 		let mut this = self.0.try_lock().ok_or(TransactionError::SessionLocked)?;
+		let filter: filter::Filter<U> = filter.into();
+		this.track_write::<U>(|| vec![Some(filter.to_document())]);
 
 		let result = U::collection(&this.global.db)
 			.delete_one(filter)
@@ -215,6 +328,7 @@ impl<E: Debug> TransactionSession<'_, E> {
 		options: impl Into<Option<mongodb::options::InsertOneOptions>>,
 	) -> Result<InsertOneResult, TransactionError<E>> {
 		let mut this = self.0.try_lock().ok_or(TransactionError::SessionLocked)?;
+		this.track_write::<U>(|| vec![bson::to_document(insert.borrow()).ok()]);
 
 		let result = U::collection(&this.global.db)
 			.insert_one(insert)
@@ -232,6 +346,8 @@ impl<E: Debug> TransactionSession<'_, E> {
 		options: impl Into<Option<mongodb::options::InsertManyOptions>>,
 	) -> Result<InsertManyResult, TransactionError<E>> {
 		let mut this = self.0.try_lock().ok_or(TransactionError::SessionLocked)?;
+		let items: Vec<_> = items.into_iter().collect();
+		this.track_write::<U>(|| items.iter().map(|item| bson::to_document(item.borrow()).ok()).collect());
 
 		let result = U::collection(&this.global.db)
 			.insert_many(items)
@@ -242,6 +358,31 @@ impl<E: Debug> TransactionSession<'_, E> {
 		Ok(result)
 	}
 
+	#[tracing::instrument(skip_all, name = "TransactionSession::bulk_write", fields(collection = %U::COLLECTION_NAME, count = write.len()))]
+	pub async fn bulk_write<U: MongoCollection>(
+		&mut self,
+		write: BulkWrite<U>,
+		options: impl Into<Option<mongodb::options::BulkWriteOptions>>,
+	) -> Result<SummaryBulkWriteResult, TransactionError<E>> {
+		let mut this = self.0.try_lock().ok_or(TransactionError::SessionLocked)?;
+		this.track_write::<U>(|| write.documents());
+
+		let models = write
+			.into_models(U::collection(&this.global.db).namespace())
+			.map_err(mongodb::error::Error::from)?;
+
+		let this = &mut *this;
+		let result = this
+			.global
+			.mongo
+			.bulk_write(models)
+			.with_options(options)
+			.session(&mut this.session)
+			.await?;
+
+		Ok(result)
+	}
+
 	#[tracing::instrument(skip_all, name = "TransactionSession::register_event", fields(event = %event.kind()))]
 	pub fn register_event(&mut self, event: InternalEvent) -> Result<(), TransactionError<E>> {
 		let mut this = self.0.try_lock().ok_or(TransactionError::SessionLocked)?;
@@ -255,6 +396,103 @@ struct SessionInner<'a> {
 	session: mongodb::ClientSession,
 	in_txn: bool,
 	events: Vec<InternalEvent>,
+	invalidate_user_computed: UserComputedInvalidation,
+}
+
+impl SessionInner<'_> {
+	fn track_write<U: MongoCollection>(&mut self, documents: impl FnOnce() -> Vec<Option<bson::Document>>) {
+		self.invalidate_user_computed.track_write::<U>(documents);
+	}
+}
+
+/// The cached computed permissions which are invalidated on commit.
+#[derive(Debug, PartialEq)]
+enum UserComputedInvalidation {
+	None,
+	/// The sources of the written edges, all of which affect a single user
+	Edges(HashSet<EntitlementEdgeKind>),
+	All,
+}
+
+impl UserComputedInvalidation {
+	/// Computed permissions depend on entitlement edges and roles. Writing to
+	/// edges from a user or one of their subscriptions invalidates the cached
+	/// permissions of that user on commit, any other write to either collection
+	/// invalidates all users. `documents` returns the filters or inserted
+	/// documents of the write and is only called for entitlement edges.
+	fn track_write<U: MongoCollection>(&mut self, documents: impl FnOnce() -> Vec<Option<bson::Document>>) {
+		if U::COLLECTION_NAME == Role::COLLECTION_NAME {
+			*self = Self::All;
+		} else if U::COLLECTION_NAME == EntitlementEdge::COLLECTION_NAME {
+			let sources = documents()
+				.iter()
+				.map(|document| document.as_ref().and_then(edge_sources))
+				.collect::<Option<Vec<_>>>();
+
+			let Some(sources) = sources else {
+				*self = Self::All;
+				return;
+			};
+
+			for from in sources.into_iter().flatten() {
+				self.add(from);
+			}
+		}
+	}
+
+	fn add(&mut self, from: EntitlementEdgeKind) {
+		let single_user = matches!(
+			from,
+			EntitlementEdgeKind::User { .. } | EntitlementEdgeKind::Subscription { .. }
+		);
+
+		match self {
+			Self::All => {}
+			_ if !single_user => *self = Self::All,
+			Self::None => *self = Self::Edges(HashSet::from([from])),
+			Self::Edges(edges) => {
+				edges.insert(from);
+			}
+		}
+	}
+
+	async fn invalidate(&self, global: &Arc<Global>) -> Result<(), fred::error::Error> {
+		match self {
+			Self::None => Ok(()),
+			Self::Edges(edges) => {
+				futures::future::try_join_all(edges.iter().map(|from| user_computed_cache::invalidate_edge(global, from)))
+					.await?;
+				Ok(())
+			}
+			Self::All => user_computed_cache::invalidate_all(global).await,
+		}
+	}
+}
+
+/// The sources of the entitlement edges matched by a filter on their id, or of
+/// an inserted edge. Returns `None` if they cannot be determined from the
+/// document, e.g. for filters on other fields.
+fn edge_sources(document: &bson::Document) -> Option<Vec<EntitlementEdgeKind>> {
+	fn values(value: &bson::Bson) -> Vec<&bson::Bson> {
+		match value.as_document().and_then(|document| document.get("$in")) {
+			Some(bson::Bson::Array(items)) => items.iter().collect(),
+			_ => vec![value],
+		}
+	}
+
+	if let Some(id) = document.get("_id") {
+		values(id)
+			.into_iter()
+			.map(|id| bson::from_bson::<EntitlementEdgeId>(id.clone()).ok().map(|id| id.from))
+			.collect()
+	} else if let Some(from) = document.get("_id.from") {
+		values(from)
+			.into_iter()
+			.map(|from| bson::from_bson(from.clone()).ok())
+			.collect()
+	} else {
+		None
+	}
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -289,6 +527,8 @@ pub enum GeneralMutexKey {
 	Ban(UserBanId),
 	Ticket(TicketId),
 	Role(RoleId),
+	/// Held while an edge is checked for cycles and inserted
+	EntitlementGraph,
 }
 
 impl std::fmt::Display for GeneralMutexKey {
@@ -304,12 +544,16 @@ impl std::fmt::Display for GeneralMutexKey {
 			Self::Ban(id) => write!(f, "{PREFIX}:ban:{}", id),
 			Self::Ticket(id) => write!(f, "{PREFIX}:ticket:{}", id),
 			Self::Role(id) => write!(f, "{PREFIX}:role:{}", id),
+			Self::EntitlementGraph => write!(f, "{PREFIX}:entitlement_graph"),
 		}
 	}
 }
 
+/// Runs the transaction while holding the mutex, if any. `operation` labels
+/// the transaction metrics, see [`transaction`].
 pub async fn transaction_with_mutex<'a, K, T, E, F, Fut>(
 	global: &'a Arc<Global>,
+	operation: &'static str,
 	req: Option<MutexAquireRequest<K>>,
 	f: F,
 ) -> TransactionResult<T, E>
@@ -320,13 +564,90 @@ where
 	E: Debug,
 {
 	if let Some(req) = req {
-		global.mutex.acquire(req, || transaction(global, f)).await?
+		global.mutex.acquire(req, || transaction(global, operation, f)).await?
 	} else {
-		transaction(global, f).await
+		transaction(global, operation, f).await
+	}
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct TransactionOptions {
+	/// The maximum number of times the transaction is retried
+	pub max_retries: u32,
+	/// The delay before the first retry, doubled on every further retry
+	pub backoff_base: std::time::Duration,
+	/// The maximum delay between retries
+	pub max_backoff: std::time::Duration,
+}
+
+impl Default for TransactionOptions {
+	fn default() -> Self {
+		Self {
+			max_retries: 10,
+			backoff_base: std::time::Duration::from_millis(100),
+			max_backoff: std::time::Duration::from_secs(2),
+		}
+	}
+}
+
+impl TransactionOptions {
+	/// The delay before the given retry with up to 50% jitter.
+	fn backoff(&self, retry: u32) -> std::time::Duration {
+		let delay = self
+			.backoff_base
+			.saturating_mul(2u32.saturating_pow(retry.saturating_sub(1)))
+			.min(self.max_backoff);
+
+		delay.mul_f64(0.5 + rand::random::<f64>() * 0.5)
 	}
 }
 
-pub async fn transaction<'a, T, E, F, Fut>(global: &'a Arc<Global>, f: F) -> TransactionResult<T, E>
+/// Runs the closure in a transaction, retrying it on transient errors.
+/// `operation` names the caller, e.g. `v4.user.biography`, and labels the
+/// transaction metrics.
+pub async fn transaction<'a, T, E, F, Fut>(global: &'a Arc<Global>, operation: &'static str, f: F) -> TransactionResult<T, E>
+where
+	F: FnOnce(TransactionSession<'a, E>) -> Fut + Clone + 'a,
+	Fut: std::future::Future<Output = TransactionResult<T, E>> + 'a,
+	E: Debug,
+{
+	transaction_with_opts(global, operation, TransactionOptions::default(), f).await
+}
+
+pub async fn transaction_with_opts<'a, T, E, F, Fut>(
+	global: &'a Arc<Global>,
+	operation: &'static str,
+	opts: TransactionOptions,
+	f: F,
+) -> TransactionResult<T, E>
+where
+	F: FnOnce(TransactionSession<'a, E>) -> Fut + Clone + 'a,
+	Fut: std::future::Future<Output = TransactionResult<T, E>> + 'a,
+	E: Debug,
+{
+	let start = std::time::Instant::now();
+
+	let result = run_transaction(global, operation, opts, f).await;
+
+	let outcome = match &result {
+		Ok(_) => transaction::Outcome::Committed,
+		Err(TransactionError::Custom(_)) => transaction::Outcome::Aborted,
+		Err(TransactionError::TooManyFailures) => transaction::Outcome::TooManyFailures,
+		Err(_) => transaction::Outcome::Failed,
+	};
+
+	transaction::total(operation, outcome).incr();
+	transaction::duration(operation, outcome).observe(start.elapsed().as_secs_f64());
+
+	result
+}
+
+async fn run_transaction<'a, T, E, F, Fut>(
+	global: &'a Arc<Global>,
+	operation: &'static str,
+	opts: TransactionOptions,
+	f: F,
+) -> TransactionResult<T, E>
 where
 	F: FnOnce(TransactionSession<'a, E>) -> Fut + Clone + 'a,
 	Fut: std::future::Future<Output = TransactionResult<T, E>> + 'a,
@@ -339,12 +660,13 @@ where
 		session,
 		in_txn: false,
 		events: Vec::new(),
+		invalidate_user_computed: UserComputedInvalidation::None,
 	})));
 
 	let mut retry_count = 0;
 
 	'retry_operation: loop {
-		if retry_count > 10 {
+		if retry_count > opts.max_retries {
 			return Err(TransactionError::TooManyFailures);
 		}
 
@@ -353,7 +675,9 @@ where
 		let result = (f.clone())(session.clone()).await;
 		let mut session_inner = session.0.try_lock().ok_or(TransactionError::SessionLocked)?;
 		match result {
-			Ok(output) => 'retry_commit: loop {
+			Ok(output) => {
+				// The events are inserted once as part of the transaction, retrying the commit
+				// must not insert them again.
 				let events = session_inner
 					.events
 					.iter()
@@ -362,41 +686,68 @@ where
 					.collect::<Vec<_>>();
 
 				if !events.is_empty() {
-					StoredEvent::collection(&global.db)
+					if let Err(err) = StoredEvent::collection(&global.db)
 						.insert_many(events)
 						.session(&mut session_inner.session)
-						.await?;
+						.await
+					{
+						if err.contains_label(TRANSIENT_TRANSACTION_ERROR) {
+							tracing::debug!(error = %err, operation, "transaction error");
+
+							transaction::retry(operation, transaction::RetryReason::Operation).incr();
+							tokio::time::sleep(opts.backoff(retry_count)).await;
+							continue 'retry_operation;
+						}
+
+						return Err(TransactionError::Mongo(err));
+					}
 				}
 
-				match session_inner.session.commit_transaction().await {
-					Ok(_) => {
-						let payload = InternalEventPayload::new(session_inner.events.drain(..));
-						let payload = rmp_serde::to_vec_named(&payload)?;
+				'retry_commit: loop {
+					match session_inner.session.commit_transaction().await {
+						Ok(_) => {
+							if let Err(err) = session_inner.invalidate_user_computed.invalidate(global).await {
+								tracing::error!(error = %err, operation, "failed to invalidate user computed cache");
+							}
 
-						global.nats.publish("api.v4.events", payload.into()).await?;
+							let payload = InternalEventPayload::new(session_inner.events.drain(..));
 
-						return Ok(output);
-					}
-					Err(err) => {
-						tracing::debug!(error = %err, "transaction commit error");
+							let emote_sets = payload.by_emote_set();
 
-						if err.contains_label(UNKNOWN_TRANSACTION_COMMIT_RESULT) {
-							continue 'retry_commit;
-						} else if err.contains_label(TRANSIENT_TRANSACTION_ERROR) {
-							tokio::time::sleep(std::time::Duration::from_millis(100)).await;
-							continue 'retry_operation;
-						}
+							let payload = rmp_serde::to_vec_named(&payload)?;
+							global.nats.publish("api.v4.events", payload.into()).await?;
 
-						return Err(TransactionError::Mongo(err));
+							for (emote_set_id, payload) in emote_sets {
+								let payload = rmp_serde::to_vec_named(&payload)?;
+								global.nats.publish(emote_set_subject(emote_set_id), payload.into()).await?;
+							}
+
+							return Ok(output);
+						}
+						Err(err) => {
+							tracing::debug!(error = %err, operation, "transaction commit error");
+
+							if err.contains_label(UNKNOWN_TRANSACTION_COMMIT_RESULT) {
+								transaction::retry(operation, transaction::RetryReason::Commit).incr();
+								continue 'retry_commit;
+							} else if err.contains_label(TRANSIENT_TRANSACTION_ERROR) {
+								transaction::retry(operation, transaction::RetryReason::Operation).incr();
+								tokio::time::sleep(opts.backoff(retry_count)).await;
+								continue 'retry_operation;
+							}
+
+							return Err(TransactionError::Mongo(err));
+						}
 					}
 				}
-			},
+			}
 			Err(err) => {
 				if let TransactionError::Mongo(err) = &err {
 					if err.contains_label(TRANSIENT_TRANSACTION_ERROR) {
-						tracing::debug!(error = %err, "transaction error");
+						tracing::debug!(error = %err, operation, "transaction error");
 
-						tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+						transaction::retry(operation, transaction::RetryReason::Operation).incr();
+						tokio::time::sleep(opts.backoff(retry_count)).await;
 						continue 'retry_operation;
 					}
 				}
@@ -408,3 +759,131 @@ where
 		}
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use shared::database::product::subscription::SubscriptionId;
+	use shared::database::Id;
+
+	use super::*;
+
+	fn user(user_id: UserId) -> EntitlementEdgeKind {
+		EntitlementEdgeKind::User { user_id }
+	}
+
+	fn role() -> EntitlementEdgeKind {
+		EntitlementEdgeKind::Role { role_id: RoleId::new() }
+	}
+
+	fn edge_id(from: EntitlementEdgeKind) -> EntitlementEdgeId {
+		EntitlementEdgeId {
+			from,
+			to: role(),
+			managed_by: None,
+		}
+	}
+
+	fn track(documents: Vec<Option<bson::Document>>) -> UserComputedInvalidation {
+		let mut invalidation = UserComputedInvalidation::None;
+		invalidation.track_write::<EntitlementEdge>(|| documents);
+		invalidation
+	}
+
+	fn edges(edges: impl IntoIterator<Item = EntitlementEdgeKind>) -> UserComputedInvalidation {
+		UserComputedInvalidation::Edges(edges.into_iter().collect())
+	}
+
+	#[test]
+	fn test_edge_id_filter() {
+		let user_id = UserId::new();
+
+		assert_eq!(
+			track(vec![Some(
+				bson::doc! { "_id": bson::to_bson(&edge_id(user(user_id))).unwrap() }
+			)]),
+			edges([user(user_id)])
+		);
+	}
+
+	#[test]
+	fn test_edge_id_in_filter() {
+		let (a, b) = (UserId::new(), UserId::new());
+		let subscription = EntitlementEdgeKind::Subscription {
+			subscription_id: SubscriptionId {
+				user_id: a,
+				product_id: Id::new(),
+			},
+		};
+
+		let ids = [edge_id(user(a)), edge_id(user(b)), edge_id(subscription.clone())];
+
+		assert_eq!(
+			track(vec![Some(bson::doc! { "_id": { "$in": bson::to_bson(&ids).unwrap() } })]),
+			edges([user(a), user(b), subscription])
+		);
+	}
+
+	#[test]
+	fn test_edge_from_filter() {
+		let user_id = UserId::new();
+
+		assert_eq!(
+			track(vec![Some(bson::doc! { "_id.from": bson::to_bson(&user(user_id)).unwrap() })]),
+			edges([user(user_id)])
+		);
+
+		let (a, b) = (UserId::new(), UserId::new());
+		assert_eq!(
+			track(vec![Some(
+				bson::doc! { "_id.from": { "$in": bson::to_bson(&[user(a), user(b)]).unwrap() } }
+			)]),
+			edges([user(a), user(b)])
+		);
+	}
+
+	#[test]
+	fn test_inserted_edges() {
+		let (a, b) = (UserId::new(), UserId::new());
+		let documents = [user(a), user(b)]
+			.into_iter()
+			.map(|from| bson::to_document(&EntitlementEdge::new(from, role(), None)).ok())
+			.collect();
+
+		assert_eq!(track(documents), edges([user(a), user(b)]));
+	}
+
+	#[test]
+	fn test_falls_back_to_all() {
+		// filters on other fields
+		assert_eq!(
+			track(vec![Some(bson::doc! { "_id.to": bson::to_bson(&role()).unwrap() })]),
+			UserComputedInvalidation::All
+		);
+		assert_eq!(track(vec![Some(bson::doc! {})]), UserComputedInvalidation::All);
+		// documents which failed to serialize
+		assert_eq!(
+			track(vec![
+				Some(bson::doc! { "_id": bson::to_bson(&edge_id(user(UserId::new()))).unwrap() }),
+				None
+			]),
+			UserComputedInvalidation::All
+		);
+		// edges from anything but a user or a subscription affect many users
+		assert_eq!(
+			track(vec![Some(bson::doc! { "_id": bson::to_bson(&edge_id(role())).unwrap() })]),
+			UserComputedInvalidation::All
+		);
+	}
+
+	#[test]
+	fn test_other_collections() {
+		let mut invalidation = UserComputedInvalidation::None;
+
+		invalidation.track_write::<Role>(|| unreachable!());
+		assert_eq!(invalidation, UserComputedInvalidation::All);
+
+		let mut invalidation = UserComputedInvalidation::None;
+		invalidation.track_write::<StoredEvent>(|| unreachable!());
+		assert_eq!(invalidation, UserComputedInvalidation::None);
+	}
+}
@@ -0,0 +1,135 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use super::cache;
+use crate::config;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+	/// Requests go to the origin as normal
+	Closed,
+	/// Requests are rejected until the cool down has passed
+	Open { until: Instant },
+	/// A limited number of probe requests are let through to detect recovery
+	HalfOpen { probes: u64 },
+}
+
+struct Inner {
+	state: State,
+	/// Outcomes of recent origin requests, `true` if the request failed
+	window: VecDeque<(Instant, bool)>,
+}
+
+/// Tracks origin failures and stops sending requests to the origin while it
+/// is degraded.
+pub struct CircuitBreaker {
+	config: config::CircuitBreaker,
+	inner: Mutex<Inner>,
+}
+
+impl CircuitBreaker {
+	pub fn new(config: &config::CircuitBreaker) -> Self {
+		record_state(State::Closed);
+
+		Self {
+			config: config.clone(),
+			inner: Mutex::new(Inner {
+				state: State::Closed,
+				window: VecDeque::new(),
+			}),
+		}
+	}
+
+	/// Returns `true` if a request may be sent to the origin. Every accepted
+	/// request must be followed by a call to [`CircuitBreaker::record`].
+	pub fn try_acquire(&self) -> bool {
+		if !self.config.enabled {
+			return true;
+		}
+
+		let mut inner = self.inner.lock().unwrap();
+
+		match inner.state {
+			State::Closed => true,
+			State::Open { until } if until > Instant::now() => false,
+			State::Open { .. } => {
+				tracing::info!("origin circuit breaker half open");
+				inner.set_state(State::HalfOpen { probes: 1 });
+				true
+			}
+			State::HalfOpen { probes } if probes < self.config.half_open_probes => {
+				inner.state = State::HalfOpen { probes: probes + 1 };
+				true
+			}
+			State::HalfOpen { .. } => false,
+		}
+	}
+
+	/// Records the outcome of an origin request.
+	pub fn record(&self, failed: bool) {
+		if !self.config.enabled {
+			return;
+		}
+
+		let mut inner = self.inner.lock().unwrap();
+		let now = Instant::now();
+
+		match inner.state {
+			State::HalfOpen { .. } if failed => {
+				tracing::warn!("origin circuit breaker reopened");
+				inner.open(now + Duration::from_secs(self.config.cool_down));
+			}
+			State::HalfOpen { .. } => {
+				tracing::info!("origin circuit breaker closed");
+				inner.window.clear();
+				inner.set_state(State::Closed);
+			}
+			State::Open { .. } => {}
+			State::Closed => {
+				let window = Duration::from_secs(self.config.window);
+				while inner.window.front().is_some_and(|(at, _)| now.duration_since(*at) > window) {
+					inner.window.pop_front();
+				}
+
+				inner.window.push_back((now, failed));
+
+				let total = inner.window.len() as u64;
+				let failures = inner.window.iter().filter(|(_, failed)| *failed).count() as u64;
+
+				if total >= self.config.min_requests && failures as f64 / total as f64 >= self.config.failure_threshold {
+					tracing::warn!(failures, total, "origin circuit breaker opened");
+					inner.open(now + Duration::from_secs(self.config.cool_down));
+				}
+			}
+		}
+	}
+}
+
+impl Inner {
+	fn open(&mut self, until: Instant) {
+		self.window.clear();
+		self.set_state(State::Open { until });
+	}
+
+	fn set_state(&mut self, state: State) {
+		self.state = state;
+		record_state(state);
+	}
+}
+
+fn record_state(state: State) {
+	let current = match state {
+		State::Closed => cache::BreakerState::Closed,
+		State::Open { .. } => cache::BreakerState::Open,
+		State::HalfOpen { .. } => cache::BreakerState::HalfOpen,
+	};
+
+	for state in [
+		cache::BreakerState::Closed,
+		cache::BreakerState::Open,
+		cache::BreakerState::HalfOpen,
+	] {
+		cache::breaker_state(state).record((state == current) as u64);
+	}
+}
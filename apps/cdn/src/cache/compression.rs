@@ -0,0 +1,110 @@
+use std::io::Write;
+
+use bytes::Bytes;
+use http::{header, HeaderMap};
+
+use crate::config;
+
+/// Content types which are already compressed and would not benefit from
+/// being compressed again.
+const PRECOMPRESSED_CONTENT_TYPES: &[&str] = &["image/webp", "image/avif", "image/gif", "image/png", "image/jpeg"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContentEncoding {
+	Brotli,
+	Gzip,
+}
+
+impl ContentEncoding {
+	pub fn as_str(&self) -> &'static str {
+		match self {
+			Self::Brotli => "br",
+			Self::Gzip => "gzip",
+		}
+	}
+
+	/// Returns the encodings accepted by the `Accept-Encoding` header of the
+	/// request, in order of preference.
+	pub fn accepted(headers: &HeaderMap) -> Vec<Self> {
+		let mut brotli = false;
+		let mut gzip = false;
+
+		for value in headers
+			.get_all(header::ACCEPT_ENCODING)
+			.iter()
+			.filter_map(|v| v.to_str().ok())
+			.flat_map(|v| v.split(','))
+		{
+			let mut parts = value.split(';').map(|p| p.trim());
+			let coding = parts.next().unwrap_or_default();
+
+			let rejected = parts
+				.filter_map(|p| p.strip_prefix("q="))
+				.any(|q| q.parse::<f32>().is_ok_and(|q| q <= 0.0));
+			if rejected {
+				continue;
+			}
+
+			match coding.to_ascii_lowercase().as_str() {
+				"br" => brotli = true,
+				"gzip" | "x-gzip" => gzip = true,
+				"*" => {
+					brotli = true;
+					gzip = true;
+				}
+				_ => {}
+			}
+		}
+
+		let mut accepted = Vec::new();
+		if brotli {
+			accepted.push(Self::Brotli);
+		}
+		if gzip {
+			accepted.push(Self::Gzip);
+		}
+
+		accepted
+	}
+}
+
+/// Checks if a body with this content type should be compressed.
+pub fn should_compress(config: &config::Compression, content_type: Option<&str>) -> bool {
+	if !config.enabled {
+		return false;
+	}
+
+	let Some(content_type) = content_type else {
+		return false;
+	};
+
+	let mime = content_type.split(';').next().unwrap_or_default().trim().to_ascii_lowercase();
+
+	if PRECOMPRESSED_CONTENT_TYPES.contains(&mime.as_str()) {
+		return false;
+	}
+
+	config.content_types.iter().any(|allowed| match allowed.strip_suffix('*') {
+		Some(prefix) => mime.starts_with(prefix),
+		None => mime == *allowed,
+	})
+}
+
+/// Compresses the body, returning `None` if the compressed body is not
+/// smaller than the original.
+pub fn compress(encoding: ContentEncoding, data: &[u8]) -> Option<Bytes> {
+	let compressed = match encoding {
+		ContentEncoding::Brotli => {
+			let mut writer = brotli::CompressorWriter::new(Vec::with_capacity(data.len()), 4096, 11, 22);
+			writer.write_all(data).ok()?;
+			writer.into_inner()
+		}
+		ContentEncoding::Gzip => {
+			let mut encoder = flate2::write::GzEncoder::new(Vec::with_capacity(data.len()), flate2::Compression::best());
+			encoder.write_all(data).ok()?;
+			encoder.finish().ok()?
+		}
+	};
+
+	(compressed.len() < data.len()).then(|| Bytes::from(compressed))
+}
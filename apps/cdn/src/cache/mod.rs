@@ -1,16 +1,22 @@
-use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
 
 use axum::body::Body;
 use axum::response::IntoResponse;
 use bytes::{Bytes, BytesMut};
 use http::{header, HeaderMap, HeaderValue, StatusCode};
+use sha2::Digest;
 use shared::cdn::key::CacheKey;
 use tokio::sync::OnceCell;
 
 use crate::config;
 use crate::global::Global;
 
+mod breaker;
+mod compression;
+
+pub use compression::ContentEncoding;
+
 const ONE_WEEK: std::time::Duration = std::time::Duration::from_secs(60 * 60 * 24 * 7);
 
 pub struct Cache {
@@ -19,15 +25,49 @@ pub struct Cache {
 	s3_client: aws_sdk_s3::client::Client,
 	request_limiter: Arc<tokio::sync::Semaphore>,
 	capacity: size::Size,
+	upstream: UpstreamStats,
+	breaker: breaker::CircuitBreaker,
+}
+
+/// Counts of origin responses by status since startup.
+#[derive(Debug, Default)]
+struct UpstreamStats {
+	success: AtomicU64,
+	not_modified: AtomicU64,
+	not_found: AtomicU64,
+	timeout: AtomicU64,
+	internal_server_error: AtomicU64,
+}
+
+#[derive(Debug, Clone, Copy, serde::Serialize)]
+pub struct UpstreamSnapshot {
+	pub success: u64,
+	pub not_modified: u64,
+	pub not_found: u64,
+	pub timeout: u64,
+	pub internal_server_error: u64,
+}
+
+/// The outcome of warming a single key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WarmStatus {
+	/// The key was already cached and fresh, nothing was requested
+	Cached,
+	Success,
+	NotFound,
+	Error,
 }
 
 #[scuffle_metrics::metrics]
 mod cache {
-	use scuffle_metrics::{CounterU64, HistogramF64, MetricEnum, UpDownCounterI64};
+	use scuffle_metrics::{CounterU64, GaugeU64, HistogramF64, MetricEnum, UpDownCounterI64};
+	use shared::cdn::key::CacheKey;
 
 	#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, MetricEnum)]
 	pub enum State {
 		Hit,
+		Stale,
 		ReboundHit,
 		Coalesced,
 		Miss,
@@ -36,14 +76,47 @@ mod cache {
 	#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, MetricEnum)]
 	pub enum ResponseStatus {
 		Success,
+		NotModified,
 		NotFound,
 		Timeout,
 		InternalServerError,
 	}
 
-	pub fn action(state: State) -> CounterU64;
+	/// The first path segment of a cache key, kept to a fixed set so the
+	/// label cardinality stays bounded.
+	#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, MetricEnum)]
+	pub enum Prefix {
+		Badge,
+		Emote,
+		User,
+		Paint,
+	}
 
-	pub fn upstream_response(status: ResponseStatus) -> CounterU64;
+	impl From<&CacheKey> for Prefix {
+		fn from(key: &CacheKey) -> Self {
+			match key {
+				CacheKey::Badge { .. } => Self::Badge,
+				CacheKey::Emote { .. } => Self::Emote,
+				CacheKey::UserProfilePicture { .. } => Self::User,
+				CacheKey::Paint { .. } => Self::Paint,
+			}
+		}
+	}
+
+	pub fn action(prefix: Prefix, state: State) -> CounterU64;
+
+	pub fn upstream_response(prefix: Prefix, status: ResponseStatus) -> CounterU64;
+
+	#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, MetricEnum)]
+	pub enum BreakerState {
+		Closed,
+		Open,
+		HalfOpen,
+	}
+
+	pub fn breaker_state(state: BreakerState) -> GaugeU64;
+
+	pub fn breaker_rejected() -> CounterU64;
 
 	pub fn inflight() -> UpDownCounterI64;
 
@@ -98,7 +171,9 @@ impl Cache {
 
 		Self {
 			inner: moka::future::Cache::builder()
-				.expire_after(CacheExpiry)
+				.expire_after(CacheExpiry {
+					stale_grace_period: std::time::Duration::from_secs(config.stale_grace_period),
+				})
 				.weigher(|k, v: &CachedResponse| {
 					u32::try_from(v.data.len() + std::mem::size_of_val(v) + std::mem::size_of_val(k)).unwrap_or(u32::MAX)
 				})
@@ -108,6 +183,8 @@ impl Cache {
 			s3_client,
 			request_limiter,
 			capacity,
+			upstream: UpstreamStats::default(),
+			breaker: breaker::CircuitBreaker::new(&config.circuit_breaker),
 		}
 	}
 
@@ -123,19 +200,120 @@ impl Cache {
 		self.inner.weighted_size()
 	}
 
+	/// Checks if the key is cached, stale entries count as cached since they
+	/// are served without waiting on the origin.
+	pub fn contains(&self, key: &CacheKey) -> bool {
+		self.inner.contains_key(key)
+	}
+
 	pub fn inflight(&self) -> u64 {
 		self.inflight.len() as u64
 	}
 
+	/// Waits for inflight origin requests to finish, logging progress while
+	/// draining. Requests still inflight after the grace period are abandoned
+	/// and everyone waiting on them is notified.
+	pub async fn drain(&self, grace_period: std::time::Duration) {
+		let deadline = tokio::time::Instant::now() + grace_period;
+		let mut interval = tokio::time::interval(std::time::Duration::from_millis(250));
+		let mut last_logged = None;
+
+		loop {
+			let inflight = self.inflight();
+			if inflight == 0 {
+				tracing::info!("inflight requests drained");
+				return;
+			}
+
+			if tokio::time::Instant::now() >= deadline {
+				break;
+			}
+
+			if last_logged != Some(inflight) {
+				tracing::info!(inflight, "waiting for inflight requests to drain");
+				last_logged = Some(inflight);
+			}
+
+			interval.tick().await;
+		}
+
+		tracing::warn!(
+			inflight = self.inflight(),
+			"grace period elapsed, abandoning inflight requests"
+		);
+
+		self.inflight
+			.scan_async(|_, entry| {
+				entry.token.cancel();
+			})
+			.await;
+	}
+
+	pub fn upstream(&self) -> UpstreamSnapshot {
+		UpstreamSnapshot {
+			success: self.upstream.success.load(Ordering::Relaxed),
+			not_modified: self.upstream.not_modified.load(Ordering::Relaxed),
+			not_found: self.upstream.not_found.load(Ordering::Relaxed),
+			timeout: self.upstream.timeout.load(Ordering::Relaxed),
+			internal_server_error: self.upstream.internal_server_error.load(Ordering::Relaxed),
+		}
+	}
+
+	fn record_upstream(&self, key: &CacheKey, status: cache::ResponseStatus) {
+		cache::upstream_response(key.into(), status).incr();
+
+		let counter = match status {
+			cache::ResponseStatus::Success => &self.upstream.success,
+			cache::ResponseStatus::NotModified => &self.upstream.not_modified,
+			cache::ResponseStatus::NotFound => &self.upstream.not_found,
+			cache::ResponseStatus::Timeout => &self.upstream.timeout,
+			cache::ResponseStatus::InternalServerError => &self.upstream.internal_server_error,
+		};
+
+		counter.fetch_add(1, Ordering::Relaxed);
+	}
+
 	#[tracing::instrument(skip_all, name = "cache::purge", fields(key = %key))]
 	pub async fn purge(&self, key: CacheKey) {
 		tracing::info!("purging key");
 		self.inner.invalidate(&key).await;
+
+		if let Some(entry) = self.inflight.get_async(&key).await {
+			entry.get().purged.store(true, Ordering::Relaxed);
+		}
+	}
+
+	/// Purges every file in the directory `prefix`, see
+	/// [`CacheKey::files_in`]. Matching inflight requests are marked so their
+	/// responses are not cached.
+	#[tracing::instrument(skip_all, name = "cache::purge_prefix", fields(prefix = %prefix))]
+	pub async fn purge_prefix(&self, prefix: &str) {
+		let Some(keys) = CacheKey::files_in(prefix) else {
+			tracing::warn!("invalid purge prefix");
+			return;
+		};
+
+		tracing::info!("purging prefix");
+
+		for key in keys {
+			self.inner.invalidate(&key).await;
+
+			if let Some(entry) = self.inflight.get_async(&key).await {
+				entry.get().purged.store(true, Ordering::Relaxed);
+			}
+		}
 	}
 
 	pub async fn handle_request(&self, global: &Arc<Global>, key: CacheKey) -> CachedResponse {
+		let prefix = cache::Prefix::from(&key);
+
 		if let Some(hit) = self.inner.get(&key).await {
-			cache::action(cache::State::Hit).incr();
+			if hit.is_stale() {
+				cache::action(prefix, cache::State::Stale).incr();
+				self.revalidate(global, key, hit.clone());
+			} else {
+				cache::action(prefix, cache::State::Hit).incr();
+			}
 
 			// return cached response
 			return hit;
@@ -149,138 +327,237 @@ impl Cache {
 			Arc::new(Inflight {
 				token: tokio_util::sync::CancellationToken::new(),
 				response: OnceCell::new(),
+				purged: AtomicBool::new(false),
 			})
 		}));
 
 		if !insert {
 			tracing::debug!(key = %key, "pending");
-			cache::action(cache::State::Coalesced).incr();
+			cache::action(prefix, cache::State::Coalesced).incr();
 			// pending
 			entry.token.cancelled().await;
-			return entry.response.get().cloned().unwrap_or_else(CachedResponse::general_error);
+			return entry
+				.response
+				.get()
+				.cloned()
+				.unwrap_or_else(|| CachedResponse::general_error(&global.config.cdn));
 		}
 
-		struct PanicDropGuard(Option<(CacheKey, Arc<Inflight>, Arc<Global>)>);
-
-		impl PanicDropGuard {
-			fn new(key: CacheKey, entry: Arc<Inflight>, global: Arc<Global>) -> Self {
-				Self(Some((key, entry, global)))
-			}
-
-			async fn disarm(mut self) {
-				let Some((key, entry, global)) = self.0.take() else {
-					return;
-				};
+		let guard = PanicDropGuard::new(key, entry, Arc::clone(global));
 
-				entry.token.cancel();
-				global.cache.inflight.remove_async(&key).await;
-			}
+		if let Some(cached) = self.inner.get(guard.key()).await.filter(|c| !c.is_stale()) {
+			tracing::debug!(key = %guard.key(), "rebounded hit");
+			cache::action(prefix, cache::State::ReboundHit).incr();
+			guard.entry().response.set(cached.clone()).expect("unreachable");
+			guard.disarm().await;
+			return cached.clone();
+		}
 
-			fn entry(&self) -> &Arc<Inflight> {
-				&self.0.as_ref().unwrap().1
-			}
+		cache::action(prefix, cache::State::Miss).incr();
 
-			fn global(&self) -> &Arc<Global> {
-				&self.0.as_ref().unwrap().2
-			}
+		tokio::spawn(guard.fetch(None)).await.unwrap_or_else(|e| {
+			tracing::error!(error = %e, "task failed");
+			CachedResponse::general_error(&global.config.cdn)
+		})
+	}
 
-			fn key(&self) -> &CacheKey {
-				&self.0.as_ref().unwrap().0
-			}
+	/// Pre-populates the cache with the given key by requesting it from the
+	/// origin as if a client requested it. Keys which are already cached and
+	/// fresh are skipped.
+	#[tracing::instrument(skip_all, name = "cache::warm", fields(key = %key))]
+	pub async fn warm(&self, global: &Arc<Global>, key: CacheKey) -> WarmStatus {
+		if self.inner.get(&key).await.is_some_and(|c| !c.is_stale()) {
+			return WarmStatus::Cached;
 		}
 
-		impl Drop for PanicDropGuard {
-			fn drop(&mut self) {
-				let Some((key, entry, global)) = self.0.take() else {
-					return;
-				};
-
-				entry.token.cancel();
-				global.cache.inflight.remove(&key);
+		match self.handle_request(global, key).await.data {
+			CachedData::Bytes { .. } => WarmStatus::Success,
+			CachedData::NotFound => WarmStatus::NotFound,
+			CachedData::Redirect(_) | CachedData::InternalServerError | CachedData::ServiceUnavailable { .. } => {
+				WarmStatus::Error
 			}
 		}
+	}
 
-		let guard = PanicDropGuard::new(key, entry, Arc::clone(global));
-
-		if let Some(cached) = self.inner.get(guard.key()).await {
-			tracing::debug!(key = %guard.key(), "rebounded hit");
-			cache::action(cache::State::ReboundHit).incr();
-			guard.entry().response.set(cached.clone()).expect("unreachable");
-			guard.disarm().await;
-			return cached.clone();
-		}
+	/// Refreshes a stale entry in the background. The stale entry stays in the
+	/// cache until the refresh replaces it, if a refresh for this key is
+	/// already inflight this does nothing.
+	fn revalidate(&self, global: &Arc<Global>, key: CacheKey, stale: CachedResponse) {
+		let global = Arc::clone(global);
 
-		cache::action(cache::State::Miss).incr();
+		tokio::spawn(async move {
+			let mut insert = false;
 
-		let cached = tokio::spawn(async move {
-			// request file
-			let cached = guard.global().cache.request_key(guard.global(), guard.key()).await;
+			let entry = Arc::clone(&global.cache.inflight.entry_async(key.clone()).await.or_insert_with(|| {
+				insert = true;
 
-			guard.entry().response.set(cached.clone()).expect("unreachable");
+				Arc::new(Inflight {
+					token: tokio_util::sync::CancellationToken::new(),
+					response: OnceCell::new(),
+					purged: AtomicBool::new(false),
+				})
+			}));
 
-			if !cached.max_age.is_zero() {
-				guard.global().cache.inner.insert(guard.key().clone(), cached.clone()).await;
-				tracing::debug!(key = %guard.key(), "cached");
+			if !insert {
+				return;
 			}
 
-			guard.disarm().await;
+			tracing::debug!(key = %key, "revalidating");
 
-			cached
+			PanicDropGuard::new(key, entry, global).fetch(Some(stale)).await;
 		});
-
-		cached.await.unwrap_or_else(|e| {
-			tracing::error!(error = %e, "task failed");
-			CachedResponse::general_error()
-		})
 	}
 
-	async fn do_req(&self, global: &Arc<Global>, key: &CacheKey) -> Result<CachedResponse, S3ErrorWrapper> {
+	/// Requests the key from the origin. If a previous response with an etag
+	/// is given the request is conditional and `None` is returned if the
+	/// object has not changed since.
+	async fn do_req(
+		&self,
+		global: &Arc<Global>,
+		key: &CacheKey,
+		previous: Option<&CachedResponse>,
+	) -> Result<Option<CachedResponse>, S3ErrorWrapper> {
 		let _inflight = cache::InflightDropGuard::new();
 		let _permit = self.request_limiter.acquire().await.expect("semaphore closed");
 
-		tracing::debug!(key = %key, "requesting origin");
+		tracing::debug!(key = %key, conditional = previous.is_some(), "requesting origin");
 
-		tokio::time::timeout(
+		let mut req = self
+			.s3_client
+			.get_object()
+			.bucket(&global.config.cdn.bucket.name)
+			.key(key.to_string());
+
+		if let Some(previous) = previous {
+			if let Some(etag) = &previous.etag {
+				req = req.if_none_match(etag);
+			}
+
+			req = req.if_modified_since(aws_sdk_s3::primitives::DateTime::from_secs(previous.date.timestamp()));
+		}
+
+		let response = tokio::time::timeout(
 			std::time::Duration::from_secs(global.config.cdn.origin_request_timeout),
 			async {
-				Ok(CachedResponse::from_s3_response(
-					self.s3_client
-						.get_object()
-						.bucket(&global.config.cdn.bucket.name)
-						.key(key.to_string())
-						.send()
-						.await?,
-				)
-				.await?)
+				match req.send().await {
+					Ok(output) => Ok::<_, S3ErrorWrapper>(Some(CachedResponse::from_s3_response(output).await?)),
+					Err(aws_sdk_s3::error::SdkError::ServiceError(e)) if e.raw().status().as_u16() == 304 => Ok(None),
+					Err(e) => Err(e.into()),
+				}
 			},
 		)
-		.await?
+		.await??;
+
+		let Some(response) = response else {
+			return Ok(None);
+		};
+
+		Ok(Some(response.compress(&global.config.cdn.compression).await))
 	}
 
-	async fn request_key(&self, global: &Arc<Global>, key: &CacheKey) -> CachedResponse {
-		match self.do_req(global, key).await {
-			Ok(response) => {
-				cache::upstream_response(cache::ResponseStatus::Success).incr();
+	/// Requests the key from the origin, if a stale response with an etag is
+	/// given it is kept with a fresh date when the origin reports it as
+	/// unchanged.
+	async fn request_key(&self, global: &Arc<Global>, key: &CacheKey, stale: Option<CachedResponse>) -> CachedResponse {
+		if !self.breaker.try_acquire() {
+			tracing::debug!(key = %key, "origin circuit breaker open");
+			cache::breaker_rejected().incr();
+			return CachedResponse::timeout(&global.config.cdn);
+		}
+
+		let previous = stale.as_ref().filter(|s| s.etag.is_some());
+
+		match self.do_req(global, key, previous).await {
+			Ok(Some(response)) => {
+				self.breaker.record(false);
+				self.record_upstream(key, cache::ResponseStatus::Success);
 				response
 			}
+			Ok(None) => {
+				self.breaker.record(false);
+				self.record_upstream(key, cache::ResponseStatus::NotModified);
+				previous.expect("not modified without a previous response").refreshed()
+			}
 			Err(S3ErrorWrapper::Sdk(aws_sdk_s3::error::SdkError::ServiceError(e))) if e.err().is_no_such_key() => {
-				cache::upstream_response(cache::ResponseStatus::NotFound).incr();
-				CachedResponse::not_found()
+				self.breaker.record(false);
+				self.record_upstream(key, cache::ResponseStatus::NotFound);
+				CachedResponse::not_found(&global.config.cdn)
 			}
 			Err(S3ErrorWrapper::Timeout(_)) => {
 				tracing::error!(key = %key, "timeout while requesting cdn file");
-				cache::upstream_response(cache::ResponseStatus::Timeout).incr();
-				CachedResponse::timeout()
+				self.breaker.record(true);
+				self.record_upstream(key, cache::ResponseStatus::Timeout);
+				CachedResponse::timeout(&global.config.cdn)
 			}
 			Err(e) => {
 				tracing::error!(key = %key, error = %e, "failed to request cdn file");
-				cache::upstream_response(cache::ResponseStatus::InternalServerError).incr();
-				CachedResponse::general_error()
+				self.breaker.record(true);
+				self.record_upstream(key, cache::ResponseStatus::InternalServerError);
+				CachedResponse::general_error(&global.config.cdn)
 			}
 		}
 	}
 }
 
+struct PanicDropGuard(Option<(CacheKey, Arc<Inflight>, Arc<Global>)>);
+
+impl PanicDropGuard {
+	fn new(key: CacheKey, entry: Arc<Inflight>, global: Arc<Global>) -> Self {
+		Self(Some((key, entry, global)))
+	}
+
+	async fn disarm(mut self) {
+		let Some((key, entry, global)) = self.0.take() else {
+			return;
+		};
+
+		entry.token.cancel();
+		global.cache.inflight.remove_async(&key).await;
+	}
+
+	fn entry(&self) -> &Arc<Inflight> {
+		&self.0.as_ref().unwrap().1
+	}
+
+	fn global(&self) -> &Arc<Global> {
+		&self.0.as_ref().unwrap().2
+	}
+
+	fn key(&self) -> &CacheKey {
+		&self.0.as_ref().unwrap().0
+	}
+
+	/// Requests the key from the origin, stores the response in the cache and
+	/// notifies everyone waiting on the inflight entry. The stale response is
+	/// used to make the origin request conditional.
+	async fn fetch(self, stale: Option<CachedResponse>) -> CachedResponse {
+		// request file
+		let cached = self.global().cache.request_key(self.global(), self.key(), stale).await;
+
+		self.entry().response.set(cached.clone()).expect("unreachable");
+
+		if !cached.max_age.is_zero() && !self.entry().purged.load(Ordering::Relaxed) {
+			self.global().cache.inner.insert(self.key().clone(), cached.clone()).await;
+			tracing::debug!(key = %self.key(), "cached");
+		}
+
+		self.disarm().await;
+
+		cached
+	}
+}
+
+impl Drop for PanicDropGuard {
+	fn drop(&mut self) {
+		let Some((key, entry, global)) = self.0.take() else {
+			return;
+		};
+
+		entry.token.cancel();
+		global.cache.inflight.remove(&key);
+	}
+}
+
 #[derive(Debug, thiserror::Error)]
 enum S3ErrorWrapper {
 	#[error("sdk error: {0}")]
@@ -291,8 +568,7 @@ enum S3ErrorWrapper {
 	Bytes(#[from] aws_sdk_s3::primitives::ByteStreamError),
 }
 
-/// Safe to clone
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub struct Inflight {
 	/// This token is pending as long as the request to the origin is pending.
 	/// "Cancellation" is an unfortunate name for this because it is not used to
@@ -301,6 +577,9 @@ pub struct Inflight {
 	token: tokio_util::sync::CancellationToken,
 	/// The response once it is ready
 	response: OnceCell<CachedResponse>,
+	/// Set if the key was purged while the request was inflight, in which case
+	/// the response must not be cached.
+	purged: AtomicBool,
 }
 
 #[derive(Debug, Clone)]
@@ -309,33 +588,56 @@ pub struct CachedResponse {
 	pub date: chrono::DateTime<chrono::Utc>,
 	pub max_age: std::time::Duration,
 	pub hits: Arc<AtomicUsize>,
+	/// The entity tag of the response, only set for successful responses
+	pub etag: Option<String>,
 }
 
 impl CachedResponse {
-	pub fn not_found() -> Self {
+	/// Checks if the response is past its max age.
+	pub fn is_stale(&self) -> bool {
+		(chrono::Utc::now() - self.date).to_std().unwrap_or_default() >= self.max_age
+	}
+
+	pub fn not_found(config: &config::Cdn) -> Self {
 		Self {
 			data: CachedData::NotFound,
 			date: chrono::Utc::now(),
-			max_age: std::time::Duration::from_secs(10),
+			max_age: std::time::Duration::from_secs(config.not_found_ttl),
 			hits: Arc::new(AtomicUsize::new(0)),
+			etag: None,
 		}
 	}
 
-	pub fn timeout() -> Self {
+	/// A timeout is transient, so it is reported as `503 Service Unavailable`
+	/// with a `Retry-After` instead of a hard error.
+	pub fn timeout(config: &config::Cdn) -> Self {
 		Self {
-			data: CachedData::InternalServerError,
+			data: CachedData::ServiceUnavailable {
+				retry_after: config.timeout_retry_after,
+			},
 			date: chrono::Utc::now(),
-			max_age: std::time::Duration::ZERO,
+			max_age: std::time::Duration::from_secs(config.timeout_ttl),
 			hits: Arc::new(AtomicUsize::new(0)),
+			etag: None,
 		}
 	}
 
-	pub fn general_error() -> Self {
+	pub fn general_error(config: &config::Cdn) -> Self {
 		Self {
 			data: CachedData::InternalServerError,
 			date: chrono::Utc::now(),
-			max_age: std::time::Duration::ZERO,
+			max_age: std::time::Duration::from_secs(config.error_ttl),
 			hits: Arc::new(AtomicUsize::new(0)),
+			etag: None,
+		}
+	}
+
+	/// Keeps the body of the response but restarts its max age, used when the
+	/// origin reports the object as unchanged.
+	pub fn refreshed(&self) -> Self {
+		Self {
+			date: chrono::Utc::now(),
+			..self.clone()
 		}
 	}
 
@@ -345,39 +647,89 @@ impl CachedResponse {
 			date: chrono::Utc::now(),
 			max_age: std::time::Duration::ZERO,
 			hits: Arc::new(AtomicUsize::new(0)),
+			etag: None,
 		}
 	}
 }
 
 #[derive(Debug, Clone)]
 pub enum CachedData {
-	Bytes { content_type: Option<String>, data: Bytes },
+	Bytes {
+		content_type: Option<String>,
+		data: Bytes,
+		/// The brotli encoded body, if the content type is compressible
+		brotli: Option<Bytes>,
+		/// The gzip encoded body, if the content type is compressible
+		gzip: Option<Bytes>,
+	},
 	Redirect(String),
 	NotFound,
 	InternalServerError,
+	ServiceUnavailable {
+		/// Seconds the client should wait before retrying
+		retry_after: u64,
+	},
 }
 
 impl CachedData {
 	pub fn len(&self) -> usize {
 		match self {
-			Self::Bytes { data, .. } => data.len(),
+			Self::Bytes { data, brotli, gzip, .. } => {
+				data.len() + brotli.as_ref().map_or(0, |b| b.len()) + gzip.as_ref().map_or(0, |g| g.len())
+			}
 			Self::Redirect(_) => 0,
 			Self::NotFound => 0,
 			Self::InternalServerError => 0,
+			Self::ServiceUnavailable { .. } => 0,
 		}
 	}
 }
 
-impl IntoResponse for CachedData {
-	fn into_response(self) -> axum::response::Response {
+impl CachedData {
+	/// Picks the preferred encoding accepted by the request which we have an
+	/// encoded body for.
+	pub fn negotiate_encoding(&self, headers: &HeaderMap) -> Option<ContentEncoding> {
+		let Self::Bytes { brotli, gzip, .. } = self else {
+			return None;
+		};
+
+		ContentEncoding::accepted(headers)
+			.into_iter()
+			.find(|encoding| match encoding {
+				ContentEncoding::Brotli => brotli.is_some(),
+				ContentEncoding::Gzip => gzip.is_some(),
+			})
+	}
+
+	fn into_response_encoded(self, encoding: Option<ContentEncoding>) -> axum::response::Response {
 		match self {
-			Self::Bytes { data, content_type } => {
+			Self::Bytes {
+				data,
+				content_type,
+				brotli,
+				gzip,
+			} => {
 				let mut headers = HeaderMap::new();
 
 				if let Some(content_type) = content_type.as_deref().and_then(|c| c.try_into().ok()) {
 					headers.insert(header::CONTENT_TYPE, content_type);
 				}
 
+				if brotli.is_some() || gzip.is_some() {
+					headers.insert(header::VARY, HeaderValue::from_static("accept-encoding"));
+				}
+
+				let data = match encoding {
+					Some(ContentEncoding::Brotli) => brotli.map(|b| (ContentEncoding::Brotli, b)),
+					Some(ContentEncoding::Gzip) => gzip.map(|g| (ContentEncoding::Gzip, g)),
+					None => None,
+				}
+				.map(|(encoding, encoded)| {
+					headers.insert(header::CONTENT_ENCODING, HeaderValue::from_static(encoding.as_str()));
+					encoded
+				})
+				.unwrap_or(data);
+
 				headers.insert(header::CONTENT_LENGTH, data.len().to_string().try_into().unwrap());
 
 				(headers, Body::from(data)).into_response()
@@ -389,19 +741,50 @@ impl IntoResponse for CachedData {
 			}
 			Self::NotFound => StatusCode::NOT_FOUND.into_response(),
 			Self::InternalServerError => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+			Self::ServiceUnavailable { retry_after } => {
+				let mut headers = HeaderMap::new();
+				headers.insert(header::RETRY_AFTER, retry_after.into());
+				(headers, StatusCode::SERVICE_UNAVAILABLE).into_response()
+			}
 		}
 	}
 }
 
+impl IntoResponse for CachedData {
+	fn into_response(self) -> axum::response::Response {
+		self.into_response_encoded(None)
+	}
+}
+
 impl IntoResponse for CachedResponse {
 	fn into_response(self) -> axum::response::Response {
-		let mut data = self.data.into_response();
+		self.into_response_encoded(None)
+	}
+}
+
+impl CachedResponse {
+	fn into_response_encoded(self, encoding: Option<ContentEncoding>) -> axum::response::Response {
+		let etag = self.etag(encoding);
+		let is_error = matches!(
+			self.data,
+			CachedData::InternalServerError | CachedData::ServiceUnavailable { .. }
+		);
+		let mut data = self.data.into_response_encoded(encoding);
+
+		if let Some(etag) = etag.and_then(|e| HeaderValue::try_from(e).ok()) {
+			data.headers_mut().insert(header::ETAG, etag);
+		}
 
-		if self.max_age.as_secs() == 0 {
+		// Errors may still be cached by us for a short time but must never be stored
+		// by downstream caches, otherwise they outlive the outage.
+		if is_error {
+			data.headers_mut()
+				.insert(header::CACHE_CONTROL, HeaderValue::from_static("no-store"));
+		} else if self.max_age.as_secs() == 0 {
 			data.headers_mut()
 				.insert(header::CACHE_CONTROL, HeaderValue::from_static("no-cache"));
 		} else {
-			let hits = self.hits.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+			let hits = self.hits.fetch_add(1, Ordering::Relaxed);
 
 			let age = chrono::Utc::now() - self.date;
 			data.headers_mut()
@@ -436,6 +819,60 @@ impl IntoResponse for CachedResponse {
 }
 
 impl CachedResponse {
+	/// The etag of the response in the given encoding, each encoding is a
+	/// different representation so it needs its own etag.
+	fn etag(&self, encoding: Option<ContentEncoding>) -> Option<String> {
+		let etag = self.etag.as_deref()?;
+
+		let Some(encoding) = encoding else {
+			return Some(etag.to_owned());
+		};
+
+		Some(match etag.strip_suffix('"') {
+			Some(etag) => format!("{etag}-{}\"", encoding.as_str()),
+			None => format!("{etag}-{}", encoding.as_str()),
+		})
+	}
+
+	/// Checks if the `If-None-Match` header of the request matches the etag of
+	/// this response in the given encoding.
+	pub fn not_modified(&self, headers: &HeaderMap, encoding: Option<ContentEncoding>) -> bool {
+		let Some(etag) = self.etag(encoding) else {
+			return false;
+		};
+
+		let etag = etag.trim_start_matches("W/");
+
+		headers
+			.get_all(header::IF_NONE_MATCH)
+			.iter()
+			.filter_map(|v| v.to_str().ok())
+			.flat_map(|v| v.split(','))
+			.map(|v| v.trim())
+			.any(|v| v == "*" || v.trim_start_matches("W/") == etag)
+	}
+
+	/// Converts the response into an http response in the best encoding the
+	/// request accepts, returning `304 Not Modified` without a body if the
+	/// request already has the current version.
+	pub fn into_response_for(self, headers: &HeaderMap) -> axum::response::Response {
+		let encoding = self.data.negotiate_encoding(headers);
+		let not_modified = self.not_modified(headers, encoding);
+
+		let mut response = self.into_response_encoded(encoding);
+		if !not_modified {
+			return response;
+		}
+
+		*response.status_mut() = StatusCode::NOT_MODIFIED;
+		*response.body_mut() = Body::empty();
+		response.headers_mut().remove(header::CONTENT_LENGTH);
+		response.headers_mut().remove(header::CONTENT_TYPE);
+		response.headers_mut().remove(header::CONTENT_ENCODING);
+
+		response
+	}
+
 	pub async fn from_s3_response(
 		mut value: aws_sdk_s3::operation::get_object::GetObjectOutput,
 	) -> Result<Self, aws_sdk_s3::primitives::ByteStreamError> {
@@ -467,19 +904,73 @@ impl CachedResponse {
 			data.extend_from_slice(&chunk);
 		}
 
+		let data = data.freeze();
+
+		// S3 etags are already quoted, if the origin did not provide one we hash the
+		// body so that the etag is stable across nodes.
+		let etag = value
+			.e_tag
+			.filter(|e| !e.is_empty())
+			.unwrap_or_else(|| format!("\"{}\"", hex::encode(sha2::Sha256::digest(&data))));
+
 		Ok(Self {
 			data: CachedData::Bytes {
-				data: data.freeze(),
+				data,
 				content_type: value.content_type,
+				brotli: None,
+				gzip: None,
 			},
 			date,
 			max_age,
 			hits: Arc::new(AtomicUsize::new(0)),
+			etag: Some(etag),
+		})
+	}
+
+	/// Compresses the body if the content type is compressible so the encoded
+	/// variants are cached alongside the identity body.
+	pub async fn compress(mut self, config: &config::Compression) -> Self {
+		let CachedData::Bytes {
+			content_type,
+			data,
+			brotli,
+			gzip,
+		} = &mut self.data
+		else {
+			return self;
+		};
+
+		if !compression::should_compress(config, content_type.as_deref()) {
+			return self;
+		}
+
+		let body = data.clone();
+		match tokio::task::spawn_blocking(move || {
+			(
+				compression::compress(ContentEncoding::Brotli, &body),
+				compression::compress(ContentEncoding::Gzip, &body),
+			)
 		})
+		.await
+		{
+			Ok((b, g)) => {
+				*brotli = b;
+				*gzip = g;
+			}
+			Err(e) => {
+				tracing::error!(error = %e, "compression task failed");
+			}
+		}
+
+		self
 	}
 }
 
-struct CacheExpiry;
+struct CacheExpiry {
+	/// Entries are kept for this long after their max age so they can be
+	/// served stale while being revalidated.
+	stale_grace_period: std::time::Duration,
+}
 
 impl moka::Expiry<CacheKey, CachedResponse> for CacheExpiry {
 	fn expire_after_create(
@@ -488,6 +979,17 @@ impl moka::Expiry<CacheKey, CachedResponse> for CacheExpiry {
 		value: &CachedResponse,
 		_created_at: std::time::Instant,
 	) -> Option<std::time::Duration> {
-		Some(value.max_age)
+		Some(value.max_age + self.stale_grace_period)
+	}
+
+	fn expire_after_update(
+		&self,
+		_key: &CacheKey,
+		value: &CachedResponse,
+		_updated_at: std::time::Instant,
+		_duration_until_expiry: Option<std::time::Duration>,
+	) -> Option<std::time::Duration> {
+		// A revalidated entry replaces the stale one and starts a fresh lifetime.
+		Some(value.max_age + self.stale_grace_period)
 	}
 }
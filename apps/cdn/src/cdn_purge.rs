@@ -54,12 +54,16 @@ pub async fn run(global: Arc<Global>, ctx: scuffle_context::Context) -> anyhow::
 						}
 					};
 
-					tracing::info!(files = %payload.files.len(), "purging keys");
+					tracing::info!(files = %payload.files.len(), prefixes = %payload.prefixes.len(), "purging keys");
 
 					for file in payload.files {
 						global.cache.purge(file).await;
 					}
 
+					for prefix in &payload.prefixes {
+						global.cache.purge_prefix(prefix).await;
+					}
+
 					global
 						.jetstream
 						.publish(
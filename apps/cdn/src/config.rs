@@ -54,11 +54,46 @@ pub struct Cdn {
 	/// Origin request timeout in seconds
 	#[default(5)]
 	pub origin_request_timeout: u64,
+	/// How long in seconds to cache not found responses, 0 disables caching
+	#[default(10)]
+	pub not_found_ttl: u64,
+	/// How long in seconds to cache origin error responses, 0 disables caching
+	#[default(0)]
+	pub error_ttl: u64,
+	/// How long in seconds to cache origin timeout responses, 0 disables
+	/// caching
+	#[default(0)]
+	pub timeout_ttl: u64,
+	/// The `Retry-After` in seconds sent with `503 Service Unavailable`
+	/// responses when the origin times out or the circuit breaker is open
+	#[default(5)]
+	pub timeout_retry_after: u64,
+	/// How long in seconds an expired response may still be served while it
+	/// is refreshed in the background, 0 disables stale-while-revalidate
+	#[default(0)]
+	pub stale_grace_period: u64,
+	/// How long in seconds to wait for inflight origin requests to finish on
+	/// shutdown before abandoning them
+	#[default(10)]
+	pub shutdown_grace_period: u64,
+	/// Response compression configuration
+	pub compression: Compression,
+	/// Origin circuit breaker configuration
+	pub circuit_breaker: CircuitBreaker,
 	/// Rate limit configuration
 	#[default(RateLimit::default())]
 	pub rate_limit: RateLimit,
+	/// Per client ip request rate limit
+	pub client_rate_limit: ClientRateLimit,
 	/// IP Header config
 	pub incoming_request: IncomingRequestConfig,
+	/// Bearer token required by the admin routes, the admin routes are
+	/// disabled if unset
+	#[default(None)]
+	pub admin_token: Option<String>,
+	/// The maximum number of keys which can be warmed in a single request
+	#[default(1000)]
+	pub max_warm_keys: usize,
 	/// NATS Purge Stream
 	#[default("cdn.purge".to_string())]
 	pub purge_stream_subject: String,
@@ -67,4 +102,63 @@ pub struct Cdn {
 	pub purge_stream_name: String,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize, smart_default::SmartDefault)]
+#[serde(default)]
+pub struct Compression {
+	/// Enable brotli and gzip compression of compressible responses
+	#[default(true)]
+	pub enabled: bool,
+	/// Content types which are compressed, a trailing `*` matches any suffix.
+	/// Already compressed images are never compressed.
+	#[default(vec!["text/*".into(), "application/json".into()])]
+	pub content_types: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, smart_default::SmartDefault)]
+#[serde(default)]
+pub struct CircuitBreaker {
+	/// Enable the circuit breaker
+	#[default(true)]
+	pub enabled: bool,
+	/// The sliding window in seconds over which origin failures are counted
+	#[default(30)]
+	pub window: u64,
+	/// The minimum number of origin requests in the window before the breaker
+	/// can open
+	#[default(20)]
+	pub min_requests: u64,
+	/// The ratio of failed origin requests in the window at which the breaker
+	/// opens
+	#[default(0.5)]
+	pub failure_threshold: f64,
+	/// How long in seconds the breaker stays open before probing the origin
+	#[default(10)]
+	pub cool_down: u64,
+	/// The number of concurrent probe requests allowed while half open
+	#[default(5)]
+	pub half_open_probes: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, smart_default::SmartDefault)]
+#[serde(default)]
+pub struct ClientRateLimit {
+	/// Enable the per client ip rate limit
+	#[default(false)]
+	pub enabled: bool,
+	/// The number of requests per second a client is allowed to make
+	#[default(50.0)]
+	pub rate: f64,
+	/// The number of requests a client can make in a burst
+	#[default(200)]
+	pub burst: u32,
+	/// Serve cached responses without counting them against the limit, only
+	/// requests which have to go to the origin are limited
+	#[default(true)]
+	pub bypass_cached: bool,
+	/// The maximum number of clients whose buckets are kept in memory, the
+	/// least recently used buckets are dropped first
+	#[default(100_000)]
+	pub max_clients: u64,
+}
+
 scuffle_settings::bootstrap!(Config);
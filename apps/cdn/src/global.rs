@@ -12,10 +12,12 @@ use tracing_subscriber::{EnvFilter, Layer};
 
 use crate::cache;
 use crate::config::Config;
+use crate::ratelimit::ClientRateLimiter;
 
 pub struct Global {
 	pub config: Config,
 	pub cache: cache::Cache,
+	pub client_rate_limiter: ClientRateLimiter,
 	pub jetstream: async_nats::jetstream::Context,
 	pub metrics: scuffle_bootstrap_telemetry::prometheus_client::registry::Registry,
 }
@@ -63,6 +65,7 @@ impl scuffle_bootstrap::global::Global for Global {
 
 		Ok(Arc::new(Self {
 			cache: cache::Cache::new(&config.cdn),
+			client_rate_limiter: ClientRateLimiter::new(&config.cdn.client_rate_limit),
 			config,
 			jetstream,
 			metrics,
@@ -87,7 +90,7 @@ impl scuffle_bootstrap_telemetry::TelemetryConfig for Global {
 
 impl scuffle_signal::SignalConfig for Global {
 	async fn on_shutdown(self: &Arc<Self>) -> anyhow::Result<()> {
-		tracing::info!("shutting down cdn");
+		tracing::info!(inflight = self.cache.inflight(), "shutting down cdn");
 		Ok(())
 	}
 }
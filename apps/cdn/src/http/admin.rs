@@ -0,0 +1,75 @@
+use std::sync::Arc;
+
+use axum::extract::State;
+use axum::response::{IntoResponse, Response};
+use axum::routing::post;
+use axum::{Json, Router};
+use futures::StreamExt;
+use http::{header, HeaderMap, StatusCode};
+use shared::cdn::key::CacheKey;
+use subtle::ConstantTimeEq;
+
+use crate::cache::WarmStatus;
+use crate::global::Global;
+
+pub fn routes(_: &Arc<Global>) -> Router<Arc<Global>> {
+	Router::new().route("/warm", post(warm))
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct WarmRequest {
+	keys: Vec<CacheKey>,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct WarmResponse {
+	results: Vec<WarmResult>,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct WarmResult {
+	key: CacheKey,
+	status: WarmStatus,
+}
+
+/// Checks the bearer token of the request against the configured admin token,
+/// in constant time so the token cannot be guessed from response times.
+fn authorized(global: &Global, headers: &HeaderMap) -> bool {
+	let Some(token) = global.config.cdn.admin_token.as_deref() else {
+		return false;
+	};
+
+	headers
+		.get(header::AUTHORIZATION)
+		.and_then(|v| v.to_str().ok())
+		.and_then(|v| v.strip_prefix("Bearer "))
+		.is_some_and(|v| v.as_bytes().ct_eq(token.as_bytes()).into())
+}
+
+async fn warm(State(global): State<Arc<Global>>, headers: HeaderMap, Json(req): Json<WarmRequest>) -> Response {
+	if !authorized(&global, &headers) {
+		return StatusCode::UNAUTHORIZED.into_response();
+	}
+
+	if req.keys.len() > global.config.cdn.max_warm_keys {
+		return (StatusCode::BAD_REQUEST, "too many keys").into_response();
+	}
+
+	tracing::info!(keys = req.keys.len(), "warming keys");
+
+	// Origin requests are still bound by the cache request limiter, this only
+	// limits how many keys are pending at once.
+	let results = futures::stream::iter(req.keys)
+		.map(|key| {
+			let global = &global;
+			async move {
+				let status = global.cache.warm(global, key.clone()).await;
+				WarmResult { key, status }
+			}
+		})
+		.buffered(global.config.cdn.max_concurrent_requests as usize)
+		.collect()
+		.await;
+
+	Json(WarmResponse { results }).into_response()
+}
@@ -1,9 +1,11 @@
+use std::net::IpAddr;
 use std::sync::Arc;
 
 use axum::extract::{Path, State};
+use axum::response::{IntoResponse, Response};
 use axum::routing::get;
-use axum::{Json, Router};
-use http::Uri;
+use axum::{Extension, Json, Router};
+use http::{header, HeaderMap, HeaderValue, StatusCode, Uri};
 use metrics::FileKind;
 use shared::cdn::key::{CacheKey, ImageFile};
 use shared::database::badge::BadgeId;
@@ -12,12 +14,13 @@ use shared::database::paint::{PaintId, PaintLayerId};
 use shared::database::user::profile_picture::UserProfilePictureId;
 use shared::database::user::UserId;
 
-use crate::cache::CachedResponse;
+use crate::cache::{CachedResponse, UpstreamSnapshot};
 use crate::global::Global;
 
 pub fn routes(_: &Arc<Global>) -> Router<Arc<Global>> {
 	Router::new()
 		.route("/", get(root))
+		.route("/stats", get(stats))
 		.route("/badge/:id/:file", get(badge))
 		.route("/emote/:id/:file", get(emote))
 		.route("/user/:user/profile-picture/:avatar_id/:file", get(user_profile_picture))
@@ -52,6 +55,33 @@ struct Welcome {
 	inflight: u64,
 }
 
+#[derive(Debug, serde::Serialize)]
+struct Stats {
+	pod_name: String,
+	node_name: String,
+	entries: u64,
+	size: u64,
+	capacity: u64,
+	inflight: u64,
+	upstream: UpstreamSnapshot,
+}
+
+/// Serves the key from the cache, requests which would go to the origin are
+/// subject to the client rate limit.
+async fn serve(global: &Arc<Global>, key: CacheKey, ip: IpAddr, headers: &HeaderMap) -> Response {
+	if !global.client_rate_limiter.bypass_cached() || !global.cache.contains(&key) {
+		if let Err(retry_after) = global.client_rate_limiter.check(ip).await {
+			return (
+				StatusCode::TOO_MANY_REQUESTS,
+				[(header::RETRY_AFTER, HeaderValue::from(retry_after))],
+			)
+				.into_response();
+		}
+	}
+
+	global.cache.handle_request(global, key).await.into_response_for(headers)
+}
+
 fn redirect_to_new_url(key: CacheKey) -> CachedResponse {
 	CachedResponse::redirect(format!("/{key}"))
 }
@@ -69,70 +99,90 @@ async fn root(State(global): State<Arc<Global>>) -> Json<Welcome> {
 	})
 }
 
+async fn stats(State(global): State<Arc<Global>>) -> Json<Stats> {
+	Json(Stats {
+		pod_name: global.config.pod.name.clone(),
+		node_name: global.config.pod.node_name.clone(),
+		entries: global.cache.entries(),
+		size: global.cache.size(),
+		capacity: global.cache.capacity(),
+		inflight: global.cache.inflight(),
+		upstream: global.cache.upstream(),
+	})
+}
+
 async fn badge(
 	Path((badge_id, file)): Path<(BadgeId, ImageFile)>,
 	State(global): State<Arc<Global>>,
+	Extension(ip): Extension<IpAddr>,
 	uri: Uri,
-) -> CachedResponse {
+	headers: HeaderMap,
+) -> Response {
 	let key = CacheKey::Badge { badge_id, file };
 	if uri.path().trim_start_matches('/') != key.to_string() {
-		return redirect_to_new_url(key);
+		return redirect_to_new_url(key).into_response();
 	}
 
 	metrics::request(FileKind::Badge, key.extension()).incr();
 
-	global.cache.handle_request(&global, key).await
+	serve(&global, key, ip, &headers).await
 }
 
 async fn emote(
 	Path((emote_id, file)): Path<(EmoteId, ImageFile)>,
 	State(global): State<Arc<Global>>,
+	Extension(ip): Extension<IpAddr>,
 	uri: Uri,
-) -> CachedResponse {
+	headers: HeaderMap,
+) -> Response {
 	let key = CacheKey::Emote { emote_id, file };
 	if uri.path().trim_start_matches('/') != key.to_string() {
-		return redirect_to_new_url(key);
+		return redirect_to_new_url(key).into_response();
 	}
 
 	metrics::request(FileKind::Emote, key.extension()).incr();
 
-	global.cache.handle_request(&global, key).await
+	serve(&global, key, ip, &headers).await
 }
 
 async fn user_profile_picture(
 	Path((user_id, avatar_id, file)): Path<(UserId, UserProfilePictureId, ImageFile)>,
 	State(global): State<Arc<Global>>,
+	Extension(ip): Extension<IpAddr>,
 	uri: Uri,
-) -> CachedResponse {
+	headers: HeaderMap,
+) -> Response {
 	let key = CacheKey::UserProfilePicture {
 		user_id,
 		avatar_id,
 		file,
 	};
 	if uri.path().trim_start_matches('/') != key.to_string() {
-		return redirect_to_new_url(key);
+		return redirect_to_new_url(key).into_response();
 	}
 
 	metrics::request(FileKind::UserProfilePicture, key.extension()).incr();
 
-	global.cache.handle_request(&global, key).await
+	serve(&global, key, ip, &headers).await
 }
 
 async fn paint_layer(
 	Path((paint_id, layer_id, file)): Path<(PaintId, PaintLayerId, ImageFile)>,
 	State(global): State<Arc<Global>>,
+	Extension(ip): Extension<IpAddr>,
 	uri: Uri,
-) -> CachedResponse {
+	headers: HeaderMap,
+) -> Response {
 	let key = CacheKey::Paint {
 		paint_id,
 		layer_id,
 		file,
 	};
 	if uri.path().trim_start_matches('/') != key.to_string() {
-		return redirect_to_new_url(key);
+		return redirect_to_new_url(key).into_response();
 	}
 
 	metrics::request(FileKind::Paint, key.extension()).incr();
 
-	global.cache.handle_request(&global, key).await
+	serve(&global, key, ip, &headers).await
 }
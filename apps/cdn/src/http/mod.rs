@@ -22,11 +22,13 @@ use tracing::Span;
 
 use crate::global::Global;
 
+mod admin;
 mod cdn;
 
 fn routes(global: &Arc<Global>, server_name: &Arc<str>) -> Router {
 	Router::new()
 		.nest("/", cdn::routes(global))
+		.nest("/admin", admin::routes(global))
 		.with_state(Arc::clone(global))
 		.layer(
 			ServiceBuilder::new()
@@ -296,5 +298,10 @@ pub async fn run(global: Arc<Global>, ctx: scuffle_context::Context) -> anyhow::
 	)
 	.context("shutdown")?;
 
+	global
+		.cache
+		.drain(std::time::Duration::from_secs(global.config.cdn.shutdown_grace_period))
+		.await;
+
 	Ok(())
 }
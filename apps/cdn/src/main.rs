@@ -8,6 +8,7 @@ mod config;
 mod global;
 mod http;
 mod metrics;
+mod ratelimit;
 
 scuffle_bootstrap::main! {
 	Global {
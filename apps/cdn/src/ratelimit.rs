@@ -0,0 +1,184 @@
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::config;
+
+#[scuffle_metrics::metrics]
+mod client_rate_limit {
+	use scuffle_metrics::CounterU64;
+
+	pub fn rejected() -> CounterU64;
+}
+
+struct Bucket {
+	tokens: f64,
+	updated_at: Instant,
+}
+
+impl Bucket {
+	fn new(burst: u32, now: Instant) -> Self {
+		Self {
+			tokens: burst as f64,
+			updated_at: now,
+		}
+	}
+
+	/// Refills the bucket for the time since the last request and takes a
+	/// token. Returns the number of seconds until a token is available if the
+	/// bucket is empty.
+	fn take(&mut self, config: &config::ClientRateLimit, now: Instant) -> Result<(), u64> {
+		let elapsed = now.duration_since(self.updated_at).as_secs_f64();
+		self.tokens = (self.tokens + elapsed * config.rate).min(config.burst as f64);
+		self.updated_at = now;
+
+		if self.tokens >= 1.0 {
+			self.tokens -= 1.0;
+			return Ok(());
+		}
+
+		let retry_after = if config.rate > 0.0 {
+			((1.0 - self.tokens) / config.rate).ceil() as u64
+		} else {
+			60
+		};
+
+		Err(retry_after.max(1))
+	}
+}
+
+/// A token bucket per client ip which limits how many requests a single
+/// client can send to the origin.
+pub struct ClientRateLimiter {
+	config: config::ClientRateLimit,
+	buckets: moka::future::Cache<IpAddr, Arc<Mutex<Bucket>>>,
+}
+
+impl ClientRateLimiter {
+	pub fn new(config: &config::ClientRateLimit) -> Self {
+		// Once a bucket has been idle long enough to refill completely it is no
+		// different from a new bucket, so it can be dropped.
+		let refill = if config.rate > 0.0 {
+			Duration::from_secs_f64((config.burst as f64 / config.rate).max(1.0))
+		} else {
+			Duration::from_secs(60)
+		};
+
+		Self {
+			config: config.clone(),
+			buckets: moka::future::Cache::builder()
+				.max_capacity(config.max_clients)
+				.time_to_idle(refill)
+				.build(),
+		}
+	}
+
+	/// Whether cached responses can be served without taking a token.
+	pub fn bypass_cached(&self) -> bool {
+		self.config.bypass_cached
+	}
+
+	/// Takes a token from the bucket of the given ip. Returns the number of
+	/// seconds until a token is available if the bucket is empty.
+	pub async fn check(&self, ip: IpAddr) -> Result<(), u64> {
+		if !self.config.enabled {
+			return Ok(());
+		}
+
+		let bucket = self
+			.buckets
+			.get_with(ip, async {
+				Arc::new(Mutex::new(Bucket::new(self.config.burst, Instant::now())))
+			})
+			.await;
+
+		let result = bucket.lock().unwrap().take(&self.config, Instant::now());
+
+		if result.is_err() {
+			client_rate_limit::rejected().incr();
+		}
+
+		result
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn config() -> config::ClientRateLimit {
+		config::ClientRateLimit {
+			enabled: true,
+			rate: 2.0,
+			burst: 3,
+			..Default::default()
+		}
+	}
+
+	#[test]
+	fn test_bucket_take() {
+		let config = config();
+		let now = Instant::now();
+		let mut bucket = Bucket::new(config.burst, now);
+
+		for _ in 0..3 {
+			assert_eq!(bucket.take(&config, now), Ok(()));
+		}
+
+		assert_eq!(bucket.take(&config, now), Err(1));
+
+		// half a second refills a single token at 2 tokens per second
+		let now = now + Duration::from_millis(500);
+		assert_eq!(bucket.take(&config, now), Ok(()));
+		assert_eq!(bucket.take(&config, now), Err(1));
+
+		// the bucket never refills past the burst
+		let now = now + Duration::from_secs(60);
+		for _ in 0..3 {
+			assert_eq!(bucket.take(&config, now), Ok(()));
+		}
+
+		assert_eq!(bucket.take(&config, now), Err(1));
+	}
+
+	#[test]
+	fn test_bucket_retry_after() {
+		let config = config::ClientRateLimit {
+			rate: 0.1,
+			burst: 1,
+			..config()
+		};
+		let now = Instant::now();
+		let mut bucket = Bucket::new(config.burst, now);
+
+		assert_eq!(bucket.take(&config, now), Ok(()));
+		assert_eq!(bucket.take(&config, now), Err(10));
+		assert_eq!(bucket.take(&config, now + Duration::from_secs(5)), Err(5));
+		assert_eq!(bucket.take(&config, now + Duration::from_secs(10)), Ok(()));
+	}
+
+	#[tokio::test]
+	async fn test_check() {
+		let limiter = ClientRateLimiter::new(&config::ClientRateLimit { rate: 0.0, ..config() });
+		let a = IpAddr::from([127, 0, 0, 1]);
+		let b = IpAddr::from([127, 0, 0, 2]);
+
+		for _ in 0..3 {
+			assert_eq!(limiter.check(a).await, Ok(()));
+		}
+
+		assert_eq!(limiter.check(a).await, Err(60));
+
+		// every client has its own bucket
+		assert_eq!(limiter.check(b).await, Ok(()));
+
+		let disabled = ClientRateLimiter::new(&config::ClientRateLimit {
+			enabled: false,
+			..config()
+		});
+
+		for _ in 0..10 {
+			assert_eq!(disabled.check(a).await, Ok(()));
+		}
+	}
+}
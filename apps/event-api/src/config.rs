@@ -37,6 +37,11 @@ pub struct EventApi {
 	/// API heartbeat interval
 	#[default(Duration::from_secs(45))]
 	pub heartbeat_interval: Duration,
+	/// Number of heartbeat intervals a websocket client can go without sending
+	/// anything before it is told to reconnect, the pong answering the ping of
+	/// every heartbeat counts. 0 disables the check
+	#[default(3)]
+	pub heartbeat_allowed_misses: u32,
 	/// API subscription limit
 	#[default(Some(500))]
 	pub subscription_limit: Option<usize>,
@@ -56,6 +61,20 @@ pub struct EventApi {
 	pub rate_limit: RateLimit,
 	/// IP Header config
 	pub incoming_request: IncomingRequestConfig,
+	/// Number of dispatches kept per connection for resuming. When more are
+	/// sent the oldest ones are dropped, a client which missed a dropped
+	/// dispatch can not resume and is told to reconnect. This is also the
+	/// number of events buffered while a connection is closed, a connection
+	/// which receives more can no longer be resumed. 0 disables resuming
+	#[default(64)]
+	pub resume_buffer_size: usize,
+	/// How long a closed connection can be resumed, its subscriptions are kept
+	/// alive until then
+	#[default(Duration::from_secs(60))]
+	pub resume_window: Duration,
+	/// Maximum number of closed connections kept for resuming
+	#[default(10_000)]
+	pub resume_session_limit: usize,
 }
 
 scuffle_settings::bootstrap!(Config);
@@ -11,12 +11,14 @@ use tracing_subscriber::util::SubscriberInitExt;
 use tracing_subscriber::{EnvFilter, Layer};
 
 use crate::config::Config;
+use crate::http::v3::replay::DetachedSessions;
 use crate::subscription;
 
 pub struct Global {
 	pub nats: async_nats::Client,
 	pub config: Config,
 	pub subscription_manager: subscription::SubscriptionManager,
+	pub detached_sessions: DetachedSessions,
 	active_connections: Arc<std::sync::atomic::AtomicUsize>,
 	prometheus_registry: scuffle_bootstrap_telemetry::prometheus_client::registry::Registry,
 }
@@ -84,10 +86,13 @@ impl scuffle_bootstrap::global::Global for Global {
 			.await
 			.context("nats connect")?;
 
+		let detached_sessions = DetachedSessions::new(config.event_api.resume_window, config.event_api.resume_session_limit);
+
 		Ok(Arc::new(Self {
 			nats,
 			config,
 			subscription_manager: Default::default(),
+			detached_sessions,
 			active_connections: Default::default(),
 			prometheus_registry,
 		}))
@@ -21,7 +21,7 @@ use tracing::Span;
 use crate::global::Global;
 
 mod socket;
-mod v3;
+pub mod v3;
 
 fn routes(global: &Arc<Global>, server_name: &Arc<str>) -> Router {
 	Router::new()
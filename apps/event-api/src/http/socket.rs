@@ -8,14 +8,48 @@ use shared::event_api::{Message, MessagePayload};
 
 /// A Socket is a wrapper around a websocket or SSE connection.
 pub enum Socket {
-	WebSocket(Box<WebSocket>),
+	WebSocket(Box<WebSocket>, Encoding),
 	Sse(tokio::sync::mpsc::Sender<Result<Event, Infallible>>),
 }
 
+/// The wire format of websocket frames, SSE is always JSON.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Encoding {
+	#[default]
+	Json,
+	MsgPack,
+}
+
+impl Encoding {
+	pub fn parse(value: &str) -> Option<Self> {
+		match value.to_ascii_lowercase().as_str() {
+			"json" => Some(Self::Json),
+			"msgpack" | "messagepack" => Some(Self::MsgPack),
+			_ => None,
+		}
+	}
+
+	/// Decode a message received from the client.
+	pub fn decode<T: serde::de::DeserializeOwned>(self, data: &[u8]) -> Result<T, DecodeError> {
+		match self {
+			Self::Json => Ok(serde_json::from_slice(data)?),
+			Self::MsgPack => Ok(rmp_serde::from_slice(data)?),
+		}
+	}
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum DecodeError {
+	#[error("json: {0}")]
+	Json(#[from] serde_json::Error),
+	#[error("msgpack: {0}")]
+	MsgPack(#[from] rmp_serde::decode::Error),
+}
+
 /// A trait for converting a message into a websocket or SSE message.
 pub trait SocketMessage: Sized {
 	fn into_sse(self) -> Event;
-	fn into_ws(self) -> WsMessage;
+	fn into_ws(self, encoding: Encoding) -> WsMessage;
 }
 
 impl SocketMessage for WsMessage {
@@ -23,7 +57,7 @@ impl SocketMessage for WsMessage {
 		panic!("cannot convert WsMessage into SSE")
 	}
 
-	fn into_ws(self) -> WsMessage {
+	fn into_ws(self, _: Encoding) -> WsMessage {
 		self
 	}
 }
@@ -39,9 +73,12 @@ impl<T: MessagePayload + serde::Serialize> SocketMessage for Message<T> {
 			.id(self.sequence.to_string())
 	}
 
-	fn into_ws(self) -> WsMessage {
+	fn into_ws(self, encoding: Encoding) -> WsMessage {
 		// Create a new frame with the data.
-		WsMessage::Text(serde_json::to_string(&self).expect("failed to serialize message"))
+		match encoding {
+			Encoding::Json => WsMessage::Text(serde_json::to_string(&self).expect("failed to serialize message")),
+			Encoding::MsgPack => WsMessage::Binary(rmp_serde::to_vec_named(&self).expect("failed to serialize message")),
+		}
 	}
 }
 
@@ -57,8 +94,8 @@ pub enum SocketError {
 
 impl Socket {
 	/// Create a new socket from a websocket.
-	pub fn websocket(ws: WebSocket) -> Self {
-		Self::WebSocket(Box::new(ws))
+	pub fn websocket(ws: WebSocket, encoding: Encoding) -> Self {
+		Self::WebSocket(Box::new(ws), encoding)
 	}
 
 	/// Create a new socket from a SSE sender.
@@ -69,7 +106,7 @@ impl Socket {
 	/// Receive a message from the socket.
 	pub async fn recv(&mut self) -> Result<WsMessage, SocketError> {
 		match self {
-			Self::WebSocket(ws) => match ws.recv().await.ok_or(SocketError::WebsocketClosed)? {
+			Self::WebSocket(ws, _) => match ws.recv().await.ok_or(SocketError::WebsocketClosed)? {
 				Ok(WsMessage::Close(frame)) => {
 					// The tungstenite library will not send the echo back to the client
 					// if we don't flush the socket. This is a bug in the library.
@@ -89,8 +126,8 @@ impl Socket {
 	/// Send a message over the socket.
 	pub async fn send(&mut self, data: impl SocketMessage) -> Result<(), SocketError> {
 		match self {
-			Self::WebSocket(ws) => {
-				ws.send(data.into_ws()).await?;
+			Self::WebSocket(ws, encoding) => {
+				ws.send(data.into_ws(*encoding)).await?;
 			}
 			Self::Sse(sender) => {
 				sender.send(Ok(data.into_sse())).await.map_err(|_| SocketError::SseClosed)?;
@@ -100,10 +137,18 @@ impl Socket {
 		Ok(())
 	}
 
+	/// The encoding of messages received from the client.
+	pub fn encoding(&self) -> Encoding {
+		match self {
+			Self::WebSocket(_, encoding) => *encoding,
+			Self::Sse(_) => Encoding::Json,
+		}
+	}
+
 	/// Close the socket.
 	pub async fn close(&mut self, code: CloseCode, reason: &str) -> Result<(), SocketError> {
 		match self {
-			Self::WebSocket(ws) => {
+			Self::WebSocket(ws, _) => {
 				ws.send(ws::Message::Close(Some(CloseFrame {
 					code: WsCloseCode::from(code.into_websocket()),
 					reason: reason.to_owned().into(),
@@ -1,6 +1,6 @@
 use shared::event_api::types::CloseCode;
 
-use crate::http::socket::SocketError;
+use crate::http::socket::{DecodeError, SocketError};
 
 type WsCloseCode = axum::extract::ws::CloseCode;
 
@@ -15,7 +15,7 @@ pub enum ConnectionError {
 	#[error("client closed")]
 	ClientClosed(Option<WsCloseCode>),
 	#[error("invalid payload: {0}")]
-	InvalidPayload(#[from] serde_json::Error),
+	InvalidPayload(#[from] DecodeError),
 	#[error("subscription error: {0}")]
 	Subscription(#[from] crate::subscription::SubscriptionError),
 	#[error("closed by server: {0}")]
@@ -24,6 +24,12 @@ pub enum ConnectionError {
 	Bridge(#[from] reqwest::Error),
 }
 
+impl From<serde_json::Error> for ConnectionError {
+	fn from(err: serde_json::Error) -> Self {
+		Self::InvalidPayload(err.into())
+	}
+}
+
 impl ConnectionError {
 	pub const fn as_code(&self) -> &'static str {
 		match self {
@@ -0,0 +1,80 @@
+use std::pin::Pin;
+use std::time::Duration;
+
+use tokio::time::{Instant, Sleep};
+
+/// Expires when a client has not sent any frame for too long. Websocket
+/// clients answer the ping sent with every heartbeat, so clients which never
+/// send heartbeats themselves still keep the connection alive.
+pub struct HeartbeatDeadline {
+	/// `None` if heartbeats are not enforced for this connection
+	timeout: Option<Duration>,
+	deadline: Pin<Box<Sleep>>,
+}
+
+impl HeartbeatDeadline {
+	pub fn new(timeout: Option<Duration>) -> Self {
+		Self {
+			timeout,
+			deadline: Box::pin(tokio::time::sleep(timeout.unwrap_or_default())),
+		}
+	}
+
+	pub fn is_enabled(&self) -> bool {
+		self.timeout.is_some()
+	}
+
+	/// Push back the deadline, any frame received from the client counts as a
+	/// sign of life.
+	pub fn reset(&mut self) {
+		if let Some(timeout) = self.timeout {
+			self.deadline.as_mut().reset(Instant::now() + timeout);
+		}
+	}
+
+	/// Completes once the deadline has passed, never if it is disabled.
+	pub async fn expired(&mut self) {
+		if self.timeout.is_some() {
+			self.deadline.as_mut().await;
+		} else {
+			std::future::pending().await
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const TIMEOUT: Duration = Duration::from_millis(100);
+
+	#[tokio::test]
+	async fn test_deadline_expires() {
+		let mut deadline = HeartbeatDeadline::new(Some(TIMEOUT));
+
+		let start = Instant::now();
+		deadline.expired().await;
+		assert!(start.elapsed() >= TIMEOUT);
+	}
+
+	#[tokio::test]
+	async fn test_deadline_reset() {
+		let mut deadline = HeartbeatDeadline::new(Some(TIMEOUT));
+
+		// a client which keeps sending frames is not disconnected
+		for _ in 0..3 {
+			assert!(tokio::time::timeout(TIMEOUT / 2, deadline.expired()).await.is_err());
+			deadline.reset();
+		}
+
+		assert!(tokio::time::timeout(TIMEOUT * 2, deadline.expired()).await.is_ok());
+	}
+
+	#[tokio::test]
+	async fn test_deadline_disabled() {
+		let mut deadline = HeartbeatDeadline::new(None);
+
+		assert!(!deadline.is_enabled());
+		assert!(tokio::time::timeout(TIMEOUT, deadline.expired()).await.is_err());
+	}
+}
@@ -16,7 +16,9 @@ use shared::database::user::UserId;
 use shared::database::Id;
 use shared::event::InternalEventUserPresenceData;
 use shared::event_api::payload::{Subscribe, SubscribeCondition};
-use shared::event_api::types::{ChangeField, ChangeFieldType, ChangeMap, CloseCode, EventType, ObjectKind, Opcode};
+use shared::event_api::types::{
+	ChangeField, ChangeFieldType, ChangeMap, CloseCode, ErrorCode, EventType, ObjectKind, Opcode,
+};
 use shared::event_api::{payload, Message, MessageData, MessagePayload};
 use shared::old_types::cosmetic::{CosmeticBadgeModel, CosmeticKind, CosmeticModel, CosmeticPaintModel};
 use shared::old_types::{
@@ -25,8 +27,10 @@ use shared::old_types::{
 use tokio_stream::wrappers::ReceiverStream;
 use tokio_stream::StreamExt;
 
+use self::heartbeat::HeartbeatDeadline;
+use self::replay::{DetachedSession, ReplayBuffer};
 use self::topic_map::TopicMap;
-use super::socket::Socket;
+use super::socket::{Encoding, Socket};
 use crate::global::{AtomicTicket, Global};
 use crate::http::v3::error::ConnectionError;
 use crate::http::v3::topic_map::Subscription;
@@ -35,14 +39,16 @@ use crate::subscription::{EventTopic, Payload};
 use crate::utils::jitter;
 
 pub mod error;
+mod heartbeat;
 mod parser;
+pub mod replay;
 mod topic_map;
 
 #[scuffle_metrics::metrics(rename = "event_api_v3")]
 mod metrics {
 	use std::sync::Arc;
 
-	use scuffle_metrics::{CounterU64, HistogramF64, MetricEnum, UpDownCounterI64};
+	use scuffle_metrics::{CounterU64, GaugeU64, HistogramF64, MetricEnum, UpDownCounterI64};
 	use tokio::time::Instant;
 
 	use super::Metadata;
@@ -112,6 +118,44 @@ mod metrics {
 				.observe(self.start.elapsed().as_secs_f64());
 		}
 	}
+
+	/// The configured maximum number of subscriptions per connection
+	pub fn subscription_limit() -> GaugeU64;
+
+	/// The number of subscribe requests rejected because the connection
+	/// reached the subscription limit
+	pub fn subscription_limit_rejections(kind: ConnectionKind, app: &Arc<str>, version: &Arc<str>) -> CounterU64;
+
+	fn current_subscriptions(kind: ConnectionKind, app: &Arc<str>, version: &Arc<str>) -> UpDownCounterI64;
+
+	pub struct CurrentSubscriptionsDropGuard {
+		kind: ConnectionKind,
+		count: usize,
+		metadata: Metadata,
+	}
+
+	impl CurrentSubscriptionsDropGuard {
+		/// The current number of subscriptions
+		pub fn new(kind: ConnectionKind, metadata: Metadata) -> Self {
+			Self {
+				kind,
+				count: 0,
+				metadata,
+			}
+		}
+
+		pub fn set(&mut self, count: usize) {
+			current_subscriptions(self.kind, &self.metadata.app, &self.metadata.version)
+				.incr_by(count as i64 - self.count as i64);
+			self.count = count;
+		}
+	}
+
+	impl Drop for CurrentSubscriptionsDropGuard {
+		fn drop(&mut self) {
+			self.set(0);
+		}
+	}
 }
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
@@ -130,11 +174,19 @@ impl Default for Metadata {
 	}
 }
 
+#[derive(Debug, Clone, Default, serde::Deserialize)]
+#[serde(default)]
+pub struct ConnectOptions {
+	/// The wire format of websocket frames, `json` or `msgpack`
+	pub encoding: Option<String>,
+}
+
 pub async fn handle(
 	State(global): State<Arc<Global>>,
 	path: Option<Path<String>>,
 	RawQuery(query): RawQuery,
 	Query(mut metadata): Query<Metadata>,
+	Query(options): Query<ConnectOptions>,
 	headers: HeaderMap,
 	upgrade: Option<WebSocketUpgrade>,
 	req: Request,
@@ -190,10 +242,20 @@ pub async fn handle(
 			.map_err(|_| (hyper::StatusCode::BAD_REQUEST, "failed to parse query"))?
 	};
 
+	// Websocket clients can opt into binary MessagePack frames.
+	let encoding = match headers
+		.get("x-7tv-encoding")
+		.and_then(|v| v.to_str().ok())
+		.or(options.encoding.as_deref())
+	{
+		Some(encoding) => Encoding::parse(encoding).ok_or((hyper::StatusCode::BAD_REQUEST, "unsupported encoding"))?,
+		None => Encoding::Json,
+	};
+
 	let ctx = scuffle_context::Context::global();
 
 	if let Some(upgrade) = upgrade {
-		Ok(handle_ws(global, initial_subs, ticket, upgrade, metadata, ctx).await)
+		Ok(handle_ws(global, initial_subs, ticket, upgrade, encoding, metadata, ctx).await)
 	} else if req.method() == hyper::Method::GET {
 		handle_sse(global, initial_subs, ticket, metadata, ctx).await
 	} else {
@@ -206,6 +268,7 @@ async fn handle_ws(
 	initial_subs: Option<Vec<Subscribe>>,
 	ticket: AtomicTicket,
 	upgrade: WebSocketUpgrade,
+	encoding: Encoding,
 	metadata: Metadata,
 	ctx: scuffle_context::Context,
 ) -> Response<axum::body::Body> {
@@ -214,7 +277,7 @@ async fn handle_ws(
 		.max_message_size(1024 * 18)
 		.write_buffer_size(1024 * 16)
 		.on_upgrade(move |ws| async move {
-			let socket = Connection::new(Socket::websocket(ws), global, initial_subs, ticket, metadata);
+			let socket = Connection::new(Socket::websocket(ws, encoding), global, initial_subs, ticket, metadata);
 
 			tokio::spawn(socket.serve(ctx));
 		})
@@ -263,17 +326,36 @@ struct Connection {
 	ttl: Pin<Box<tokio::time::Sleep>>,
 	/// The interval for sending heartbeats.
 	heartbeat_interval: tokio::time::Interval,
+	/// Expires when the client has not sent anything for too many heartbeat
+	/// intervals.
+	heartbeat_deadline: HeartbeatDeadline,
 	/// A map of subscriptions that this connection is subscribed to.
 	topics: TopicMap,
 	/// The initial subscriptions that this connection should subscribe to.
 	initial_subs: Option<Vec<payload::Subscribe>>,
+	/// The most recent dispatches, replayed when a client resumes this
+	/// connection.
+	replay: ReplayBuffer,
 	/// Drop guard for the metrics
 	_connection_duration_drop_guard: metrics::ConnectionDurationDropGuard,
 	/// Drop guard for the metrics
 	_current_connection_drop_guard: metrics::CurrentConnectionDropGuard,
+	/// Tracks the number of subscriptions of this connection for the metrics
+	current_subscriptions: metrics::CurrentSubscriptionsDropGuard,
+	/// Used to label the metrics
+	connection_kind: metrics::ConnectionKind,
 
 	metadata: Metadata,
 
+	/// The token a client has to present to resume this connection.
+	resume_token: String,
+	/// The cosmetics and entitlements sent over this connection
+	caches: PresenceCaches,
+}
+
+/// The cosmetics and entitlements which were sent to the client, carried over
+/// when a connection is resumed.
+struct PresenceCaches {
 	presence_lru: lru::LruCache<UserId, PresenceCacheValue>,
 	// no_entitlement_lru: lru::LruCache<UserId, ()>,
 	personal_emote_set_lru: lru::LruCache<EmoteSetId, chrono::DateTime<chrono::Utc>>,
@@ -281,6 +363,18 @@ struct Connection {
 	paint_lru: lru::LruCache<PaintId, chrono::DateTime<chrono::Utc>>,
 }
 
+impl Default for PresenceCaches {
+	fn default() -> Self {
+		Self {
+			presence_lru: lru::LruCache::new(NonZeroUsize::new(512).unwrap()),
+			// no_entitlement_lru: lru::LruCache::new(NonZeroUsize::new(20480).unwrap()),
+			badge_lru: lru::LruCache::new(NonZeroUsize::new(60).unwrap()),
+			paint_lru: lru::LruCache::new(NonZeroUsize::new(250).unwrap()),
+			personal_emote_set_lru: lru::LruCache::new(NonZeroUsize::new(1024).unwrap()),
+		}
+	}
+}
+
 #[derive(Default)]
 struct PresenceCacheValue {
 	personal_emote_sets: Vec<EmoteSetId>,
@@ -298,10 +392,22 @@ impl Connection {
 		metadata: Metadata,
 	) -> Self {
 		let connection_kind = match socket {
-			Socket::WebSocket(_) => metrics::ConnectionKind::Websocket,
+			Socket::WebSocket(..) => metrics::ConnectionKind::Websocket,
 			Socket::Sse(_) => metrics::ConnectionKind::EventStream,
 		};
 
+		let heartbeat_interval = jitter(global.config.event_api.heartbeat_interval);
+
+		// Event stream clients can not send messages, so only websocket clients are
+		// expected to keep sending frames.
+		let heartbeat_timeout = (matches!(socket, Socket::WebSocket(..))
+			&& global.config.event_api.heartbeat_allowed_misses > 0)
+			.then(|| heartbeat_interval * global.config.event_api.heartbeat_allowed_misses);
+
+		if let Some(subscription_limit) = global.config.event_api.subscription_limit {
+			metrics::subscription_limit().record(subscription_limit as u64);
+		}
+
 		Self {
 			socket,
 			seq: 0,
@@ -312,19 +418,20 @@ impl Connection {
 			ttl: Box::pin(tokio::time::sleep(jitter(global.config.event_api.ttl))),
 			topics: TopicMap::default(),
 			// Same as above for the heartbeat interval.
-			heartbeat_interval: tokio::time::interval(jitter(global.config.event_api.heartbeat_interval)),
+			heartbeat_interval: tokio::time::interval(heartbeat_interval),
+			heartbeat_deadline: HeartbeatDeadline::new(heartbeat_timeout),
 			// And again for the subscription cleanup interval.
 			initial_subs,
 			_ticket: ticket,
+			replay: ReplayBuffer::new(global.config.event_api.resume_buffer_size),
 			global,
 			_connection_duration_drop_guard: metrics::ConnectionDurationDropGuard::new(connection_kind, metadata.clone()),
 			_current_connection_drop_guard: metrics::CurrentConnectionDropGuard::new(connection_kind, metadata.clone()),
+			current_subscriptions: metrics::CurrentSubscriptionsDropGuard::new(connection_kind, metadata.clone()),
+			connection_kind,
 			metadata,
-			presence_lru: lru::LruCache::new(NonZeroUsize::new(512).unwrap()),
-			// no_entitlement_lru: lru::LruCache::new(NonZeroUsize::new(20480).unwrap()),
-			badge_lru: lru::LruCache::new(NonZeroUsize::new(60).unwrap()),
-			paint_lru: lru::LruCache::new(NonZeroUsize::new(250).unwrap()),
-			personal_emote_set_lru: lru::LruCache::new(NonZeroUsize::new(1024).unwrap()),
+			resume_token: hex::encode(rand::random::<[u8; 32]>()),
+			caches: PresenceCaches::default(),
 		}
 	}
 
@@ -346,6 +453,7 @@ impl Connection {
 					name: self.global.config.pod.name.clone(),
 					population: self.global.active_connections() as i32,
 				}),
+				resume_token: Some(self.resume_token.clone()),
 			})
 			.with_context(&ctx)
 			.await
@@ -408,7 +516,7 @@ impl Connection {
 						)
 						.incr();
 					}
-					Socket::WebSocket(_) => {
+					Socket::WebSocket(..) => {
 						metrics::client_closes(
 							err.as_code(),
 							metrics::ConnectionKind::Websocket,
@@ -423,6 +531,12 @@ impl Connection {
 		} {
 			tracing::debug!("socket cycle");
 		}
+
+		self.global.detached_sessions.detach(
+			self.id,
+			&self.resume_token,
+			DetachedSession::new(self.replay, self.topics, self.caches),
+		);
 	}
 
 	/// Send a close message to the client, and then close the socket.
@@ -440,11 +554,13 @@ impl Connection {
 	/// close code is provided.
 	async fn send_error(
 		&mut self,
+		code: ErrorCode,
 		message: impl ToString,
 		fields: HashMap<String, serde_json::Value>,
 		close_code: Option<CloseCode>,
 	) -> Result<(), ConnectionError> {
 		self.send_message(payload::Error {
+			code,
 			message: message.to_string(),
 			fields,
 			message_locale: None,
@@ -481,19 +597,74 @@ impl Connection {
 		Ok(())
 	}
 
-	/// Handle a subscription request.
-	async fn handle_subscription(&mut self, subscribe: &payload::Subscribe) -> Result<(), ConnectionError> {
-		if let Some(subscription_limit) = self.global.config.event_api.subscription_limit {
-			if self.topics.len() >= subscription_limit {
-				self.send_error("Too Many Active Subscriptions!", HashMap::new(), Some(CloseCode::RateLimit))
-					.await?;
-			}
+	/// Send a dispatch to the client and keep it for resuming.
+	async fn send_dispatch(&mut self, data: payload::Dispatch) -> Result<(), ConnectionError> {
+		let sequence = self.seq;
+		self.send_message(&data).await?;
+		self.replay.push(Message::new(data, sequence));
+		Ok(())
+	}
+
+	/// Handle a resume request, takes over the subscriptions of a previous
+	/// connection and sends the dispatches which the client has not received
+	/// yet.
+	async fn handle_resume(&mut self, resume: &payload::Resume) -> Result<(), ConnectionError> {
+		let session = match resume.session_id.parse() {
+			Ok(id) => self.global.detached_sessions.resume(id, &resume.resume_token).await,
+			Err(_) => None,
+		};
+
+		let Some(session) = session else {
+			self.send_ack(
+				Opcode::Resume,
+				serde_json::json!({
+					"success": false,
+					"dispatches_replayed": 0,
+					"subscriptions_restored": 0,
+				}),
+			)
+			.await?;
+			return Ok(());
+		};
+
+		let Some(dispatches) = session.replay.replay(resume.last_sequence) else {
+			// The client missed dispatches which are no longer buffered.
+			return self.send_close(CloseCode::Reconnect).await;
+		};
+
+		let restored = self.topics.append(session.topics);
+		self.current_subscriptions.set(self.topics.len());
+		self.caches = session.caches;
+
+		let start = self.seq;
+
+		for dispatch in dispatches {
+			self.send_dispatch(dispatch).await?;
+		}
+
+		// The payloads received while the previous connection was closed.
+		for payload in session.pending {
+			self.handle_payload(payload).await?;
 		}
 
+		self.send_ack(
+			Opcode::Resume,
+			serde_json::json!({
+				"success": true,
+				"dispatches_replayed": self.seq - start,
+				"subscriptions_restored": restored,
+			}),
+		)
+		.await
+	}
+
+	/// Handle a subscription request.
+	async fn handle_subscription(&mut self, subscribe: &payload::Subscribe) -> Result<(), ConnectionError> {
 		let scope: EventScope = match subscribe.condition.clone().try_into() {
 			Ok(scope) => scope,
 			Err(()) => {
 				self.send_error(
+					ErrorCode::MalformedPayload,
 					"Invalid Subscription Condition",
 					HashMap::new(),
 					Some(CloseCode::InvalidPayload),
@@ -503,39 +674,64 @@ impl Connection {
 			}
 		};
 
-		if let SubscribeCondition::Channel { .. } = subscribe.condition {
-			let topic = EventTopic::new(EventType::UserPresence, scope.clone());
-			let topic_key = topic.as_key();
+		let topic = EventTopic::new(subscribe.ty, scope.clone());
+		let topic_key = topic.as_key();
 
-			if !self.topics.contains_key(&topic_key) {
-				self.topics.insert(
-					topic_key,
-					Subscription::new(self.global.subscription_manager.subscribe(topic).await?),
-				);
+		// Subscribing to a topic twice is a no-op.
+		if self.topics.contains_key(&topic_key) {
+			return Ok(());
+		}
+
+		// Channel subscriptions also subscribe to the presences of the channel.
+		let presence_topic = matches!(subscribe.condition, SubscribeCondition::Channel { .. })
+			.then(|| EventTopic::new(EventType::UserPresence, scope))
+			.filter(|topic| !self.topics.contains_key(&topic.as_key()));
+
+		if let Some(subscription_limit) = self.global.config.event_api.subscription_limit {
+			let new_topics = 1 + presence_topic.is_some() as usize;
+
+			if self.topics.len() + new_topics > subscription_limit {
+				metrics::subscription_limit_rejections(self.connection_kind, &self.metadata.app, &self.metadata.version)
+					.incr();
+
+				return self
+					.send_error(
+						ErrorCode::SubscriptionLimitExceeded,
+						"Too Many Active Subscriptions!",
+						HashMap::from_iter([
+							("limit".to_owned(), serde_json::json!(subscription_limit)),
+							("count".to_owned(), serde_json::json!(self.topics.len())),
+						]),
+						None,
+					)
+					.await;
 			}
 		}
 
-		let topic = EventTopic::new(subscribe.ty, scope);
-		let topic_key = topic.as_key();
+		if let Some(presence_topic) = presence_topic {
+			let presence_topic_key = presence_topic.as_key();
 
-		if !self.topics.contains_key(&topic_key) {
 			self.topics.insert(
-				topic_key,
-				Subscription::new(self.global.subscription_manager.subscribe(topic).await?),
+				presence_topic_key,
+				Subscription::new(self.global.subscription_manager.subscribe(presence_topic).await?),
 			);
-
-			self.send_ack(
-				Opcode::Subscribe,
-				serde_json::json!({
-					"id": self.seq,
-					"type": subscribe.ty.as_str(),
-					"condition": subscribe.condition,
-				}),
-			)
-			.await?;
 		}
 
-		Ok(())
+		self.topics.insert(
+			topic_key,
+			Subscription::new(self.global.subscription_manager.subscribe(topic).await?),
+		);
+		self.current_subscriptions.set(self.topics.len());
+
+		self.send_ack(
+			Opcode::Subscribe,
+			serde_json::json!({
+				"id": self.seq,
+				"type": subscribe.ty.as_str(),
+				"condition": subscribe.condition,
+			}),
+		)
+		.await
 	}
 
 	/// Handle an unsubscribe request.
@@ -544,8 +740,13 @@ impl Connection {
 			let count = self.topics.len();
 			self.topics.remove_all(unsubscribe.ty);
 			if count == self.topics.len() {
-				self.send_error("Not subscribed to this event", HashMap::new(), Some(CloseCode::NotSubscribed))
-					.await?;
+				self.send_error(
+					ErrorCode::NotSubscribed,
+					"Not subscribed to this event",
+					HashMap::new(),
+					Some(CloseCode::NotSubscribed),
+				)
+				.await?;
 			}
 		} else {
 			let topic = EventTopic::new(
@@ -554,6 +755,7 @@ impl Connection {
 					Ok(scope) => scope,
 					Err(()) => {
 						self.send_error(
+							ErrorCode::MalformedPayload,
 							"Invalid Subscription Condition",
 							HashMap::new(),
 							Some(CloseCode::InvalidPayload),
@@ -566,11 +768,18 @@ impl Connection {
 			.as_key();
 
 			if self.topics.remove(&topic).is_none() {
-				self.send_error("Not subscribed to this event", HashMap::new(), Some(CloseCode::NotSubscribed))
-					.await?;
+				self.send_error(
+					ErrorCode::NotSubscribed,
+					"Not subscribed to this event",
+					HashMap::new(),
+					Some(CloseCode::NotSubscribed),
+				)
+				.await?;
 			}
 		}
 
+		self.current_subscriptions.set(self.topics.len());
+
 		self.send_ack(
 			Opcode::Unsubscribe,
 			serde_json::json!({
@@ -596,6 +805,10 @@ impl Connection {
 		// We match on the opcode so that we can deserialize the data into the correct
 		// type.
 		let msg = match msg.opcode {
+			Opcode::Heartbeat => {
+				let msg = serde_json::from_value::<payload::Heartbeat>(msg.data)?;
+				MessageData::Heartbeat(msg)
+			}
 			Opcode::Resume => {
 				let msg = serde_json::from_value::<payload::Resume>(msg.data)?;
 				MessageData::Resume(msg)
@@ -613,24 +826,22 @@ impl Connection {
 				return Ok(());
 			}
 			_ => {
-				self.send_error("Invalid Opcode", HashMap::new(), Some(CloseCode::UnknownOperation))
-					.await?;
+				self.send_error(
+					ErrorCode::InvalidOpcode,
+					"Invalid Opcode",
+					HashMap::new(),
+					Some(CloseCode::UnknownOperation),
+				)
+				.await?;
 				return Ok(());
 			}
 		};
 
 		match msg {
-			MessageData::Resume(_) => {
-				// Subscription resume is not supported.
-				self.send_ack(
-					Opcode::Resume,
-					serde_json::json!({
-						"success": false,
-						"dispatches_replayed": 0,
-						"subscriptions_restored": 0,
-					}),
-				)
-				.await?;
+			// the deadline is already pushed back when the frame is received
+			MessageData::Heartbeat(_) => {}
+			MessageData::Resume(resume) => {
+				self.handle_resume(&resume).await?;
 			}
 			MessageData::Subscribe(subscribe) => {
 				self.handle_subscription(&subscribe).await?;
@@ -640,17 +851,29 @@ impl Connection {
 			}
 			MessageData::Bridge(_) => {}
 			_ => {
-				self.send_error("Invalid Opcode", HashMap::new(), Some(CloseCode::UnknownOperation))
-					.await?;
+				self.send_error(
+					ErrorCode::InvalidOpcode,
+					"Invalid Opcode",
+					HashMap::new(),
+					Some(CloseCode::UnknownOperation),
+				)
+				.await?;
 			}
 		}
 
 		Ok(())
 	}
 
+	async fn handle_payload(&mut self, payload: Payload) -> Result<(), ConnectionError> {
+		match payload {
+			Payload::Dispatch(payload) => self.handle_dispatch(&payload.data).await,
+			Payload::Presence(payload) => self.handle_presence(payload.as_ref()).await,
+		}
+	}
+
 	async fn handle_dispatch(&mut self, payload: &payload::Dispatch) -> Result<(), ConnectionError> {
 		// If everything is good, send the dispatch to the client.
-		self.send_message(&payload).await?;
+		self.send_dispatch(payload.clone()).await?;
 
 		Ok(())
 	}
@@ -659,7 +882,13 @@ impl Connection {
 		let mut dispatches = vec![];
 
 		if let Some(badge) = payload.active_badge.as_ref() {
-			if self.badge_lru.get(&badge.id).map(|t| t != &badge.updated_at).unwrap_or(true) {
+			if self
+				.caches
+				.badge_lru
+				.get(&badge.id)
+				.map(|t| t != &badge.updated_at)
+				.unwrap_or(true)
+			{
 				let object = CosmeticModel {
 					id: badge.id,
 					data: CosmeticBadgeModel::from_db(badge.clone(), &self.global.config.event_api.cdn_origin),
@@ -681,11 +910,17 @@ impl Connection {
 				});
 			}
 
-			self.badge_lru.put(badge.id, badge.updated_at);
+			self.caches.badge_lru.put(badge.id, badge.updated_at);
 		}
 
 		if let Some(paint) = payload.active_paint.as_ref() {
-			if self.paint_lru.get(&paint.id).map(|t| t != &paint.updated_at).unwrap_or(true) {
+			if self
+				.caches
+				.paint_lru
+				.get(&paint.id)
+				.map(|t| t != &paint.updated_at)
+				.unwrap_or(true)
+			{
 				let object = CosmeticModel {
 					id: paint.id,
 					data: CosmeticPaintModel::from_db(paint.clone(), &self.global.config.event_api.cdn_origin),
@@ -707,7 +942,7 @@ impl Connection {
 				});
 			}
 
-			self.paint_lru.put(paint.id, paint.updated_at);
+			self.caches.paint_lru.put(paint.id, paint.updated_at);
 		}
 
 		let partial_user =
@@ -715,6 +950,7 @@ impl Connection {
 
 		for emote_set in &payload.personal_emote_sets {
 			if self
+				.caches
 				.personal_emote_set_lru
 				.get(&emote_set.emote_set.id)
 				.map(|t| t != &emote_set.emote_set.updated_at)
@@ -791,12 +1027,13 @@ impl Connection {
 				});
 			}
 
-			self.personal_emote_set_lru
+			self.caches
+				.personal_emote_set_lru
 				.put(emote_set.emote_set.id, emote_set.emote_set.updated_at);
 		}
 
 		if payload.active_badge.is_some() || payload.active_paint.is_some() || !payload.personal_emote_sets.is_empty() {
-			let user_state = self.presence_lru.get_or_insert_mut_ref(&payload.user.id, || {
+			let user_state = self.caches.presence_lru.get_or_insert_mut_ref(&payload.user.id, || {
 				// if self.no_entitlement_lru.pop(&payload.user.id).is_none() {
 				// 	dispatches.push(payload::Dispatch {
 				// 		ty: EventType::ResetEntitlement,
@@ -970,7 +1207,7 @@ impl Connection {
 			// 	});
 			// }
 
-			if let Some(presence) = self.presence_lru.pop(&payload.user.id) {
+			if let Some(presence) = self.caches.presence_lru.pop(&payload.user.id) {
 				// Delete old presence
 				if let Some(badge) = presence.active_badge {
 					let object = Entitlement {
@@ -1041,7 +1278,7 @@ impl Connection {
 		}
 
 		for dispatch in dispatches {
-			self.send_message(&dispatch).await?;
+			self.send_dispatch(dispatch).await?;
 		}
 
 		Ok(())
@@ -1061,11 +1298,14 @@ impl Connection {
 		// - A dispatch from the subscription manager.
 		// - A heartbeat tick.
 		// - A subscription cleanup tick.
+		// - The client not sending anything for too many heartbeat intervals.
 		// - The TTL timer expiring.
 		tokio::select! {
 			r = self.socket.recv() => {
 				let msg = r?;
 
+				self.heartbeat_deadline.reset();
+
 				let msg = match msg {
 					ws::Message::Close(frame) => {
 						tracing::debug!("received close message: {:?}", frame);
@@ -1092,14 +1332,9 @@ impl Connection {
 					_ => return Ok(()),
 				};
 
-				self.handle_message(serde_json::from_slice(&msg)?).await
+				self.handle_message(self.socket.encoding().decode(&msg)?).await
 			},
-			Some(payload) = self.topics.next() => {
-				match payload {
-					Payload::Dispatch(payload) => self.handle_dispatch(&payload.data).await,
-					Payload::Presence(payload) => self.handle_presence(payload.as_ref()).await,
-				}
-			}
+			Some(payload) = self.topics.next() => self.handle_payload(payload).await,
 			_ = self.heartbeat_interval.tick() => {
 				tracing::debug!("sending heartbeat");
 
@@ -1109,8 +1344,22 @@ impl Connection {
 
 				self.heartbeat_count += 1;
 
+				// clients answer with a pong, which pushes back the deadline
+				if self.heartbeat_deadline.is_enabled() {
+					self.socket.send(ws::Message::Ping(Vec::new())).await?;
+				}
+
 				Ok(())
 			},
+			_ = self.heartbeat_deadline.expired() => {
+				tracing::debug!("heartbeat timeout");
+
+				self.send_message(payload::Reconnect {
+					reason: "heartbeat timeout".to_owned(),
+				}).await?;
+
+				self.send_close(CloseCode::Timeout).await
+			},
 			_ = &mut self.ttl => {
 				tracing::debug!("ttl expired");
 				Err(ConnectionError::TtlExpired)
@@ -0,0 +1,345 @@
+use std::collections::VecDeque;
+use std::num::NonZeroUsize;
+use std::time::Duration;
+
+use sha2::Digest;
+use shared::database::Id;
+use shared::event_api::{payload, Message};
+use tokio::sync::oneshot;
+use tokio_stream::StreamExt;
+
+use super::topic_map::TopicMap;
+use super::PresenceCaches;
+use crate::subscription::Payload;
+
+/// A bounded buffer of the most recent dispatches sent over a connection,
+/// keyed by their sequence number.
+///
+/// When the buffer is full the oldest dispatch is dropped to make room. A
+/// client resuming from a sequence number before a dropped dispatch has missed
+/// events which can no longer be replayed, so the resume fails and the client
+/// is told to reconnect with a fresh session.
+pub struct ReplayBuffer {
+	capacity: usize,
+	messages: VecDeque<Message<payload::Dispatch>>,
+	/// The sequence number of the last dispatch which was dropped
+	dropped: Option<u64>,
+}
+
+impl ReplayBuffer {
+	pub fn new(capacity: usize) -> Self {
+		Self {
+			capacity,
+			messages: VecDeque::with_capacity(capacity),
+			dropped: None,
+		}
+	}
+
+	pub fn push(&mut self, message: Message<payload::Dispatch>) {
+		if self.capacity == 0 {
+			return;
+		}
+
+		if self.messages.len() == self.capacity {
+			self.dropped = self.messages.pop_front().map(|m| m.sequence);
+		}
+
+		self.messages.push_back(message);
+	}
+
+	/// The dispatches sent after `last_sequence`, all buffered dispatches if it
+	/// is not set. `None` if some of them have already been dropped.
+	pub fn replay(self, last_sequence: Option<u64>) -> Option<Vec<payload::Dispatch>> {
+		let last_sequence = match (last_sequence, self.dropped) {
+			(Some(last), Some(dropped)) if dropped > last => return None,
+			(None, Some(_)) => return None,
+			(last, _) => last,
+		};
+
+		Some(
+			self.messages
+				.into_iter()
+				.filter(|m| last_sequence.is_none_or(|last| m.sequence > last))
+				.map(|m| m.data)
+				.collect(),
+		)
+	}
+}
+
+/// A closed connection which can still be resumed.
+///
+/// Its subscriptions are kept alive while it is detached and the payloads they
+/// receive are buffered, so they can be sent once the client resumes.
+pub struct DetachedSession {
+	/// The dispatches sent before the connection was closed
+	pub(super) replay: ReplayBuffer,
+	/// The subscriptions of the connection
+	pub(super) topics: TopicMap,
+	/// The cosmetics and entitlements already sent to the client
+	pub(super) caches: PresenceCaches,
+	/// The payloads received while the connection was closed
+	pub(super) pending: VecDeque<Payload>,
+}
+
+impl DetachedSession {
+	pub(super) fn new(replay: ReplayBuffer, topics: TopicMap, caches: PresenceCaches) -> Self {
+		Self {
+			replay,
+			topics,
+			caches,
+			pending: VecDeque::new(),
+		}
+	}
+
+	/// Buffer a payload received while the connection is closed, `false` if
+	/// the buffer is full and the session can no longer be resumed.
+	fn push(&mut self, payload: Payload) -> bool {
+		if self.pending.len() >= self.replay.capacity {
+			return false;
+		}
+
+		self.pending.push_back(payload);
+		true
+	}
+}
+
+/// Hands a detached session over to the connection resuming it.
+struct DetachedHandle {
+	/// The hash of the token the client has to present to resume the session
+	token_hash: [u8; 32],
+	resume: oneshot::Sender<oneshot::Sender<DetachedSession>>,
+}
+
+/// The closed connections which can still be resumed.
+pub struct DetachedSessions {
+	window: Duration,
+	sessions: std::sync::Mutex<lru::LruCache<Id, DetachedHandle>>,
+}
+
+impl DetachedSessions {
+	pub fn new(window: Duration, limit: usize) -> Self {
+		Self {
+			window,
+			sessions: std::sync::Mutex::new(lru::LruCache::new(NonZeroUsize::new(limit.max(1)).unwrap())),
+		}
+	}
+
+	/// Keep a closed connection around for resuming, it keeps buffering the
+	/// payloads of its subscriptions until it is resumed or the window
+	/// expires.
+	pub fn detach(&self, id: Id, token: &str, session: DetachedSession) {
+		if session.replay.capacity == 0 || self.window.is_zero() {
+			return;
+		}
+
+		let (resume_tx, resume_rx) = oneshot::channel();
+		tokio::spawn(buffer_detached(session, self.window, resume_rx));
+
+		self.sessions.lock().unwrap().put(
+			id,
+			DetachedHandle {
+				token_hash: hash_token(token),
+				resume: resume_tx,
+			},
+		);
+	}
+
+	/// Take over a closed connection. A session can only be resumed once and
+	/// only with the token which was sent to the client that opened it.
+	pub async fn resume(&self, id: Id, token: &str) -> Option<DetachedSession> {
+		let handle = {
+			let mut sessions = self.sessions.lock().unwrap();
+
+			// A wrong token must not discard the session of someone else.
+			if sessions.peek(&id)?.token_hash != hash_token(token) {
+				return None;
+			}
+
+			sessions.pop(&id)?
+		};
+
+		let (tx, rx) = oneshot::channel();
+		handle.resume.send(tx).ok()?;
+		rx.await.ok()
+	}
+}
+
+fn hash_token(token: &str) -> [u8; 32] {
+	sha2::Sha256::digest(token.as_bytes()).into()
+}
+
+/// Buffers the payloads of a detached session until it is resumed. The session
+/// is dropped, which also drops its subscriptions, when the window expires,
+/// the buffer is full or it is evicted from the detached sessions.
+async fn buffer_detached(
+	mut session: DetachedSession,
+	window: Duration,
+	mut resume: oneshot::Receiver<oneshot::Sender<DetachedSession>>,
+) {
+	let expired = tokio::time::sleep(window);
+	tokio::pin!(expired);
+
+	loop {
+		tokio::select! {
+			Some(payload) = session.topics.next() => {
+				if !session.push(payload) {
+					return;
+				}
+			}
+			r = &mut resume => {
+				if let Ok(tx) = r {
+					tx.send(session).ok();
+				}
+
+				return;
+			}
+			_ = &mut expired => return,
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use shared::event_api::types::{ChangeMap, EventType};
+
+	use super::*;
+	use crate::http::v3::topic_map::Subscription;
+	use crate::subscription::event_topic::EventScope;
+	use crate::subscription::{EventTopic, SubscriberReceiver};
+
+	fn buffer(capacity: usize, sequences: impl IntoIterator<Item = u64>) -> (ReplayBuffer, Vec<Id>) {
+		let mut buffer = ReplayBuffer::new(capacity);
+		let mut sent = Vec::new();
+
+		for sequence in sequences {
+			let id = Id::new();
+			sent.push(id);
+			buffer.push(Message::new(
+				payload::Dispatch {
+					ty: EventType::UpdateEmoteSet,
+					body: ChangeMap {
+						id,
+						..Default::default()
+					},
+				},
+				sequence,
+			));
+		}
+
+		(buffer, sent)
+	}
+
+	fn payload(id: Id, sequence: u64) -> Payload {
+		Payload::Dispatch(std::sync::Arc::new(Message::new(
+			payload::Dispatch {
+				ty: EventType::UpdateEmoteSet,
+				body: ChangeMap {
+					id,
+					..Default::default()
+				},
+			},
+			sequence,
+		)))
+	}
+
+	fn ids(dispatches: Vec<payload::Dispatch>) -> Vec<Id> {
+		dispatches.into_iter().map(|d| d.body.id).collect()
+	}
+
+	#[test]
+	fn test_replay() {
+		let (replay, sent) = buffer(10, 1..=5);
+		assert_eq!(ids(replay.replay(Some(2)).unwrap()), sent[2..]);
+
+		let (replay, sent) = buffer(10, 1..=3);
+		assert_eq!(ids(replay.replay(None).unwrap()), sent);
+
+		let (replay, _) = buffer(10, 1..=3);
+		assert!(replay.replay(Some(3)).unwrap().is_empty());
+	}
+
+	#[test]
+	fn test_replay_dropped() {
+		// 1 and 2 are dropped to make room for 4 and 5
+		let (replay, _) = buffer(3, 1..=5);
+		assert!(replay.replay(Some(1)).is_none());
+
+		let (replay, _) = buffer(3, 1..=5);
+		assert!(replay.replay(None).is_none());
+
+		// the client already received the dropped dispatches
+		let (replay, sent) = buffer(3, 1..=5);
+		assert_eq!(ids(replay.replay(Some(2)).unwrap()), sent[2..]);
+	}
+
+	fn session(capacity: usize) -> DetachedSession {
+		DetachedSession::new(ReplayBuffer::new(capacity), TopicMap::default(), Default::default())
+	}
+
+	#[tokio::test]
+	async fn test_resume_window() {
+		let sessions = DetachedSessions::new(Duration::from_millis(50), 10);
+		let id = Id::new();
+
+		sessions.detach(id, "token", session(10));
+		assert!(sessions.resume(Id::new(), "token").await.is_none());
+		// a wrong token does not discard the session
+		assert!(sessions.resume(id, "other").await.is_none());
+		assert!(sessions.resume(id, "token").await.is_some());
+		// a session can only be resumed once
+		assert!(sessions.resume(id, "token").await.is_none());
+
+		sessions.detach(id, "token", session(10));
+		tokio::time::sleep(Duration::from_millis(100)).await;
+		assert!(sessions.resume(id, "token").await.is_none());
+	}
+
+	#[tokio::test]
+	async fn test_buffer_while_detached() {
+		let sessions = DetachedSessions::new(Duration::from_secs(10), 10);
+		let id = Id::new();
+		let topic = EventTopic::new(EventType::UpdateEmoteSet, EventScope::Id(Id::new()));
+
+		let mut detached = session(2);
+		let (tx, receiver) = SubscriberReceiver::channel(topic.clone());
+		detached.topics.insert(topic.as_key(), Subscription::new(receiver));
+		sessions.detach(id, "token", detached);
+
+		let sent = vec![Id::new(), Id::new()];
+		for (sequence, id) in sent.iter().enumerate() {
+			assert!(tx.send(payload(*id, sequence as u64)).is_ok());
+		}
+		tokio::time::sleep(Duration::from_millis(50)).await;
+
+		let resumed = sessions.resume(id, "token").await.unwrap();
+		assert_eq!(resumed.topics.len(), 1);
+		let received = resumed
+			.pending
+			.into_iter()
+			.map(|p| match p {
+				Payload::Dispatch(message) => message.data.body.id,
+				Payload::Presence(_) => unreachable!(),
+			})
+			.collect::<Vec<_>>();
+		assert_eq!(received, sent);
+	}
+
+	#[tokio::test]
+	async fn test_buffer_while_detached_full() {
+		let sessions = DetachedSessions::new(Duration::from_secs(10), 10);
+		let id = Id::new();
+		let topic = EventTopic::new(EventType::UpdateEmoteSet, EventScope::Id(Id::new()));
+
+		let mut detached = session(1);
+		let (tx, receiver) = SubscriberReceiver::channel(topic.clone());
+		detached.topics.insert(topic.as_key(), Subscription::new(receiver));
+		sessions.detach(id, "token", detached);
+
+		// the client would miss the second payload, so it can not resume
+		assert!(tx.send(payload(Id::new(), 0)).is_ok());
+		assert!(tx.send(payload(Id::new(), 1)).is_ok());
+		tokio::time::sleep(Duration::from_millis(50)).await;
+
+		assert!(sessions.resume(id, "token").await.is_none());
+	}
+}
@@ -50,6 +50,18 @@ impl TopicMap {
 		self.0.retain(|k, _| k.0 != key);
 	}
 
+	/// Move the subscriptions of another map into this one, keeping the ones
+	/// this map already has. Returns the number of subscriptions added.
+	pub fn append(&mut self, other: TopicMap) -> usize {
+		let len = self.0.len();
+
+		for (key, subscription) in other.0 {
+			self.0.entry(key).or_insert(subscription);
+		}
+
+		self.0.len() - len
+	}
+
 	/// Poll the entries in the map.
 	fn poll_entries(&mut self, cx: &mut std::task::Context<'_>) -> std::task::Poll<Option<Payload>> {
 		let mut removals = Vec::new();
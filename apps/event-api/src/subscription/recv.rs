@@ -46,6 +46,15 @@ impl SubscriberReceiver {
 			},
 		}
 	}
+
+	/// A receiver which is fed by the returned sender instead of the
+	/// subscription manager.
+	#[cfg(test)]
+	pub fn channel(topic: EventTopic) -> (broadcast::Sender<Payload>, Self) {
+		let (tx, rx) = broadcast::channel(16);
+		let (unsub, _) = mpsc::unbounded_channel();
+		(tx, Self::new(topic, rx, unsub))
+	}
 }
 
 impl Drop for SubscriberReceiverGuard {
@@ -21,7 +21,7 @@ async fn main() -> anyhow::Result<()> {
 	let response = client
 		.process_image(ProcessImageRequest {
 			input_upload: Some(InputUpload {
-				binary: image,
+				binary: image.into(),
 				drive_path: Some(DrivePath {
 					drive: "cdn".to_string(),
 					path: "test/input.avif".to_string(),
@@ -5,8 +5,13 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 	#[cfg(feature = "serde")]
 	let descriptor_path = PathBuf::from(env::var("OUT_DIR").unwrap()).join("proto_descriptor.bin");
 
+	// the input upload is cloned when a request is retried, so it is shared
+	// instead of copied
+	let bytes = [".scuffle.image_processor.InputUpload.binary"];
+
 	let config = tonic_build::configure()
 		.compile_well_known_types(true)
+		.bytes(bytes)
 		.build_server(cfg!(feature = "server"))
 		.build_client(cfg!(feature = "client"));
 
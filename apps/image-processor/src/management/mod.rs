@@ -2,7 +2,6 @@ use std::sync::Arc;
 
 use anyhow::Context;
 use bson::oid::ObjectId;
-use bytes::Bytes;
 use image_processor_proto::{
 	input, CancelTaskRequest, CancelTaskResponse, DrivePath, Error, ErrorCode, Input, ProcessImageRequest,
 	ProcessImageResponse, ProcessImageResponseUploadInfo,
@@ -90,7 +89,7 @@ impl ManagementServer {
 			drive
 				.write(
 					&path,
-					Bytes::from(input_upload.binary),
+					input_upload.binary,
 					Some(DriveWriteOptions {
 						acl: drive_path.acl.clone(),
 						cache_control: input_upload.cache_control,
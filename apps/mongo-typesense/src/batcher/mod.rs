@@ -5,9 +5,10 @@ use serde::de::DeserializeOwned;
 use shared::database::loader::LoaderById;
 use shared::database::SearchableMongoCollection;
 use shared::typesense::types::TypesenseCollection;
-use typesense_insert::TypesenseInsert;
+use typesense_insert::{TypesenseInsert, TypesenseUpdate};
 
 pub mod clickhouse;
+pub mod retry;
 pub mod typesense_insert;
 
 pub struct CollectionBatcher<M: SearchableMongoCollection>
@@ -17,6 +18,7 @@ where
 {
 	pub loader: DataLoader<LoaderById<M>>,
 	pub inserter: Batcher<TypesenseInsert<M::Typesense>>,
+	pub patcher: Batcher<TypesenseUpdate<M::Typesense>>,
 }
 
 impl<M: SearchableMongoCollection> CollectionBatcher<M>
@@ -27,7 +29,8 @@ where
 	pub fn new(mongo: mongodb::Database, typesense: Arc<typesense_rs::apis::ApiClient>) -> Self {
 		Self {
 			loader: LoaderById::new(mongo.clone()),
-			inserter: TypesenseInsert::new(typesense),
+			inserter: TypesenseInsert::new(typesense.clone()),
+			patcher: TypesenseUpdate::new(typesense),
 		}
 	}
 }
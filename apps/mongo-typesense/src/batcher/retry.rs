@@ -0,0 +1,275 @@
+use std::future::Future;
+use std::time::Duration;
+
+/// Errors which may go away if the same request is sent again.
+pub trait TransientError: std::fmt::Display {
+	fn is_transient(&self) -> bool;
+}
+
+impl<T> TransientError for typesense_rs::apis::Error<T> {
+	fn is_transient(&self) -> bool {
+		match self {
+			// Refused connections and timeouts
+			typesense_rs::apis::Error::Reqwest(err) => err.is_connect() || err.is_timeout(),
+			typesense_rs::apis::Error::ResponseError(res) => is_transient_status(res.status.as_u16()),
+			_ => false,
+		}
+	}
+}
+
+/// Rate limits and server errors, any other status is returned as is.
+fn is_transient_status(status: u16) -> bool {
+	status == 429 || (500..600).contains(&status)
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Backoff {
+	/// How often a request is retried before the error is returned
+	pub max_retries: u32,
+	/// The delay before the first retry, doubled for every following one
+	pub base_delay: Duration,
+	/// The upper bound of the delay between two retries
+	pub max_delay: Duration,
+}
+
+impl Backoff {
+	pub const TYPESENSE: Self = Self {
+		max_retries: 5,
+		base_delay: Duration::from_millis(200),
+		max_delay: Duration::from_secs(10),
+	};
+
+	fn delay(&self, attempt: u32) -> Duration {
+		self.base_delay
+			.saturating_mul(2u32.saturating_pow(attempt))
+			.min(self.max_delay)
+	}
+}
+
+/// Runs `f` until it succeeds, fails with a non transient error or the retries
+/// of `backoff` are exhausted.
+pub async fn retry<T, E, F, Fut>(backoff: &Backoff, mut f: F) -> Result<T, E>
+where
+	E: TransientError,
+	F: FnMut() -> Fut,
+	Fut: Future<Output = Result<T, E>>,
+{
+	let mut attempt = 0;
+
+	loop {
+		match f().await {
+			Err(err) if attempt < backoff.max_retries && err.is_transient() => {
+				let delay = backoff.delay(attempt);
+				tracing::warn!(attempt, ?delay, "transient error, retrying: {err}");
+				tokio::time::sleep(delay).await;
+				attempt += 1;
+			}
+			r => return r,
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::sync::atomic::{AtomicU32, Ordering};
+	use std::sync::Arc;
+
+	use tokio::io::{AsyncReadExt, AsyncWriteExt};
+	use typesense_rs::apis::documents_api::{ImportDocumentsError, ImportDocumentsParams};
+	use typesense_rs::apis::Api;
+
+	use super::*;
+
+	/// A typesense server which answers the first imports with the given
+	/// statuses and every following one with success.
+	struct FlakyTypesense {
+		client: typesense_rs::apis::ApiClient,
+		calls: Arc<AtomicU32>,
+	}
+
+	impl FlakyTypesense {
+		async fn start(statuses: &'static [u16]) -> Self {
+			let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+			let addr = listener.local_addr().unwrap();
+			let calls = Arc::new(AtomicU32::new(0));
+
+			tokio::spawn({
+				let calls = calls.clone();
+				async move {
+					while let Ok((mut socket, _)) = listener.accept().await {
+						read_request(&mut socket).await;
+
+						let call = calls.fetch_add(1, Ordering::SeqCst) as usize;
+						let (status, body) = statuses.get(call).map_or((200, r#"{"success":true}"#), |s| (*s, "{}"));
+						let response = format!(
+							"HTTP/1.1 {status} Status\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{body}",
+							body.len()
+						);
+						socket.write_all(response.as_bytes()).await.unwrap();
+					}
+				}
+			});
+
+			Self {
+				client: client(&format!("http://{addr}")),
+				calls,
+			}
+		}
+
+		async fn import_documents(&self) -> Result<String, typesense_rs::apis::Error<ImportDocumentsError>> {
+			self.client
+				.documents_api()
+				.import_documents(
+					ImportDocumentsParams::builder()
+						.collection_name("emotes".to_owned())
+						.body("{}".to_owned())
+						.build(),
+				)
+				.await
+		}
+
+		fn calls(&self) -> u32 {
+			self.calls.load(Ordering::SeqCst)
+		}
+	}
+
+	fn client(base_path: &str) -> typesense_rs::apis::ApiClient {
+		typesense_rs::apis::ApiClient::new(Arc::new(typesense_rs::apis::configuration::Configuration {
+			base_path: base_path.to_owned(),
+			..Default::default()
+		}))
+	}
+
+	/// Reads the headers and the body of a request.
+	async fn read_request(socket: &mut tokio::net::TcpStream) {
+		let mut request = Vec::new();
+		let mut buf = [0; 1024];
+
+		loop {
+			let n = socket.read(&mut buf).await.unwrap();
+			if n == 0 {
+				return;
+			}
+
+			request.extend_from_slice(&buf[..n]);
+
+			let text = String::from_utf8_lossy(&request);
+			let Some(end) = text.find("\r\n\r\n") else {
+				continue;
+			};
+
+			let content_length = text[..end]
+				.lines()
+				.find_map(|line| {
+					let (name, value) = line.split_once(':')?;
+					name.eq_ignore_ascii_case("content-length")
+						.then(|| value.trim().parse::<usize>().ok())?
+				})
+				.unwrap_or(0);
+
+			if request.len() >= end + 4 + content_length {
+				return;
+			}
+		}
+	}
+
+	const BACKOFF: Backoff = Backoff {
+		max_retries: 3,
+		base_delay: Duration::from_millis(1),
+		max_delay: Duration::from_millis(4),
+	};
+
+	#[tokio::test]
+	async fn retries_transient_errors() {
+		let typesense = FlakyTypesense::start(&[503, 429]).await;
+
+		let result = retry(&BACKOFF, || typesense.import_documents()).await;
+
+		assert_eq!(result.unwrap(), r#"{"success":true}"#);
+		assert_eq!(typesense.calls(), 3);
+	}
+
+	#[tokio::test]
+	async fn gives_up_after_max_retries() {
+		let typesense = FlakyTypesense::start(&[500; 10]).await;
+
+		let result = retry(&BACKOFF, || typesense.import_documents()).await;
+
+		assert!(matches!(result, Err(typesense_rs::apis::Error::ResponseError(res)) if res.status == 500));
+		assert_eq!(typesense.calls(), BACKOFF.max_retries + 1);
+	}
+
+	#[tokio::test]
+	async fn does_not_retry_permanent_errors() {
+		let typesense = FlakyTypesense::start(&[400]).await;
+
+		let result = retry(&BACKOFF, || typesense.import_documents()).await;
+
+		assert!(matches!(result, Err(typesense_rs::apis::Error::ResponseError(res)) if res.status == 400));
+		assert_eq!(typesense.calls(), 1);
+	}
+
+	#[tokio::test]
+	async fn stops_at_permanent_error_after_retries() {
+		let typesense = FlakyTypesense::start(&[503, 404]).await;
+
+		let result = retry(&BACKOFF, || typesense.import_documents()).await;
+
+		assert!(matches!(result, Err(typesense_rs::apis::Error::ResponseError(res)) if res.status == 404));
+		assert_eq!(typesense.calls(), 2);
+	}
+
+	#[tokio::test]
+	async fn retries_refused_connections() {
+		// nothing listens on the port once the listener is dropped
+		let addr = tokio::net::TcpListener::bind("127.0.0.1:0")
+			.await
+			.unwrap()
+			.local_addr()
+			.unwrap();
+
+		let err = client(&format!("http://{addr}"))
+			.documents_api()
+			.import_documents(
+				ImportDocumentsParams::builder()
+					.collection_name("emotes".to_owned())
+					.body("{}".to_owned())
+					.build(),
+			)
+			.await
+			.unwrap_err();
+
+		assert!(matches!(err, typesense_rs::apis::Error::Reqwest(_)));
+		assert!(err.is_transient());
+	}
+
+	#[test]
+	fn only_retries_transient_errors() {
+		let response = |status: u16| {
+			typesense_rs::apis::Error::<()>::ResponseError(typesense_rs::apis::ResponseContent {
+				status: status.try_into().unwrap(),
+				content: String::new(),
+				entity: None,
+			})
+		};
+
+		for status in [429, 500, 502, 503, 504] {
+			assert!(response(status).is_transient(), "{status} should be retried");
+		}
+
+		for status in [400, 401, 404, 409, 422] {
+			assert!(!response(status).is_transient(), "{status} should not be retried");
+		}
+
+		let err = serde_json::from_str::<()>("{").unwrap_err();
+		assert!(!typesense_rs::apis::Error::<()>::Serde(err).is_transient());
+	}
+
+	#[test]
+	fn backoff_is_capped() {
+		assert_eq!(BACKOFF.delay(0), Duration::from_millis(1));
+		assert_eq!(BACKOFF.delay(1), Duration::from_millis(2));
+		assert_eq!(BACKOFF.delay(2), Duration::from_millis(4));
+		assert_eq!(BACKOFF.delay(10), Duration::from_millis(4));
+	}
+}
@@ -2,11 +2,13 @@ use std::sync::Arc;
 
 use scuffle_batching::batch::BatchResponse;
 use scuffle_batching::{BatchExecutor, Batcher};
-use shared::typesense::types::TypesenseCollection;
+use shared::typesense::types::{TypesenseCollection, TypesensePatchDocument};
 use typesense_rs::apis::documents_api::{ImportDocumentsError, ImportDocumentsParams, IndexDocumentError};
 use typesense_rs::apis::Api;
 use typesense_rs::models;
 
+use super::retry::{retry, Backoff};
+
 pub struct TypesenseInsert<T> {
 	client: Arc<typesense_rs::apis::ApiClient>,
 	_phantom: std::marker::PhantomData<T>,
@@ -52,61 +54,110 @@ impl<T: TypesenseCollection + serde::Serialize + 'static> BatchExecutor for Type
 	type Response = Result<bool, TypesenseInsertError>;
 
 	async fn execute(&self, documents: Vec<(Self::Request, BatchResponse<Self::Response>)>) {
-		let (body, responses) = documents
-			.into_iter()
-			.filter_map(|(d, send)| {
-				let result = serde_json::to_string(&d);
-				match result {
-					Ok(result) => Some((result, send)),
-					Err(e) => {
-						send.send_err(TypesenseInsertError::Serialize(e));
-						None
-					}
+		import(&self.client, T::COLLECTION_NAME, models::IndexAction::Upsert, documents).await
+	}
+}
+
+/// Sends partial documents to typesense, only the fields present in a patch
+/// are updated. Patching a document which does not exist fails.
+pub struct TypesenseUpdate<T> {
+	client: Arc<typesense_rs::apis::ApiClient>,
+	_phantom: std::marker::PhantomData<T>,
+}
+
+impl<T: TypesenseCollection + 'static> TypesenseUpdate<T> {
+	pub fn new(client: Arc<typesense_rs::apis::ApiClient>) -> Batcher<Self> {
+		Self::new_with_config(client, 500, 10_000, std::time::Duration::from_millis(100))
+	}
+
+	pub fn new_with_config(
+		client: Arc<typesense_rs::apis::ApiClient>,
+		concurrency: usize,
+		max_batch_size: usize,
+		sleep_duration: std::time::Duration,
+	) -> Batcher<Self> {
+		Batcher::new(
+			Self {
+				client,
+				_phantom: std::marker::PhantomData,
+			},
+			max_batch_size,
+			concurrency,
+			sleep_duration,
+		)
+	}
+}
+
+impl<T: TypesenseCollection + 'static> BatchExecutor for TypesenseUpdate<T> {
+	type Request = TypesensePatchDocument;
+	type Response = Result<bool, TypesenseInsertError>;
+
+	async fn execute(&self, documents: Vec<(Self::Request, BatchResponse<Self::Response>)>) {
+		import(&self.client, T::COLLECTION_NAME, models::IndexAction::Update, documents).await
+	}
+}
+
+async fn import<D: serde::Serialize>(
+	client: &typesense_rs::apis::ApiClient,
+	collection_name: &str,
+	action: models::IndexAction,
+	documents: Vec<(D, BatchResponse<Result<bool, TypesenseInsertError>>)>,
+) {
+	let (body, responses) = documents
+		.into_iter()
+		.filter_map(|(d, send)| {
+			let result = serde_json::to_string(&d);
+			match result {
+				Ok(result) => Some((result, send)),
+				Err(e) => {
+					send.send_err(TypesenseInsertError::Serialize(e));
+					None
 				}
-			})
-			.unzip::<_, _, Vec<_>, Vec<_>>();
-
-		let r = match self
-			.client
-			.documents_api()
-			.import_documents(
-				ImportDocumentsParams::builder()
-					.collection_name(T::COLLECTION_NAME.to_owned())
-					.action(models::IndexAction::Upsert)
-					.body(body.join("\n"))
-					.build(),
-			)
-			.await
-		{
-			Ok(r) => r,
-			Err(e) => {
-				let err = Arc::new(e);
-				responses.into_iter().for_each(|r| r.send_err(err.clone().into()));
-				return;
 			}
-		};
+		})
+		.unzip::<_, _, Vec<_>, Vec<_>>();
 
-		#[derive(serde::Deserialize)]
-		#[serde(untagged)]
-		enum BatchInsertResultJson {
-			Success { success: bool },
-			Error(IndexDocumentError),
+	let body = body.join("\n");
+
+	let r = match retry(&Backoff::TYPESENSE, || {
+		client.documents_api().import_documents(
+			ImportDocumentsParams::builder()
+				.collection_name(collection_name.to_owned())
+				.action(action)
+				.body(body.clone())
+				.build(),
+		)
+	})
+	.await
+	{
+		Ok(r) => r,
+		Err(e) => {
+			let err = Arc::new(e);
+			responses.into_iter().for_each(|r| r.send_err(err.clone().into()));
+			return;
 		}
+	};
 
-		impl BatchInsertResultJson {
-			fn into_result(self) -> Result<bool, TypesenseInsertError> {
-				match self {
-					BatchInsertResultJson::Success { success } => Ok(success),
-					BatchInsertResultJson::Error(e) => Err(TypesenseInsertError::Insert(e)),
-				}
+	#[derive(serde::Deserialize)]
+	#[serde(untagged)]
+	enum BatchInsertResultJson {
+		Success { success: bool },
+		Error(IndexDocumentError),
+	}
+
+	impl BatchInsertResultJson {
+		fn into_result(self) -> Result<bool, TypesenseInsertError> {
+			match self {
+				BatchInsertResultJson::Success { success } => Ok(success),
+				BatchInsertResultJson::Error(e) => Err(TypesenseInsertError::Insert(e)),
 			}
 		}
+	}
 
-		for (send, response) in responses.into_iter().zip(r.lines()) {
-			match serde_json::from_str::<BatchInsertResultJson>(response) {
-				Ok(result) => send.send(result.into_result()),
-				Err(e) => send.send_err(TypesenseInsertError::Deserialize(e)),
-			}
+	for (send, response) in responses.into_iter().zip(r.lines()) {
+		match serde_json::from_str::<BatchInsertResultJson>(response) {
+			Ok(result) => send.send(result.into_result()),
+			Err(e) => send.send_err(TypesenseInsertError::Deserialize(e)),
 		}
 	}
 }
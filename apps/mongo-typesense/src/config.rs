@@ -41,6 +41,10 @@ pub struct TriggersConfig {
 	/// Concurrency limit
 	#[default(10000)]
 	pub typesense_concurrency: usize,
+
+	/// Maximum number of events held back while typesense is down
+	#[default(1000)]
+	pub degraded_queue_size: usize,
 }
 
 scuffle_settings::bootstrap!(Config);
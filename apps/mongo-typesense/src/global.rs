@@ -8,8 +8,10 @@ use scuffle_bootstrap_telemetry::opentelemetry_sdk::metrics::SdkMeterProvider;
 use scuffle_bootstrap_telemetry::opentelemetry_sdk::Resource;
 use scuffle_metrics::opentelemetry::KeyValue;
 use shared::clickhouse::emote_stat::EmoteStat;
-use shared::database::entitlement_edge::{EntitlementEdgeInboundLoader, EntitlementEdgeOutboundLoader};
-use shared::database::updater::MongoUpdater;
+use shared::database::entitlement_edge::{
+	EntitlementEdgeInboundLoader, EntitlementEdgeOutboundLoader, EntitlementEdgeProductGrantLoader,
+};
+use shared::database::updater::{MongoOpError, MongoUpdater};
 use tracing::level_filters::LevelFilter;
 use tracing_subscriber::layer::SubscriberExt;
 use tracing_subscriber::util::SubscriberInitExt;
@@ -21,6 +23,21 @@ use crate::batcher::CollectionBatcher;
 use crate::config::Config;
 use crate::types::*;
 
+#[scuffle_metrics::metrics]
+mod health {
+	use scuffle_metrics::{GaugeU64, MetricEnum};
+
+	#[derive(Debug, Clone, Copy, MetricEnum)]
+	pub enum Dependency {
+		Nats,
+		Mongo,
+		Typesense,
+	}
+
+	/// 1 while the dependency is healthy, 0 while it is down
+	pub fn dependency(dependency: Dependency) -> GaugeU64;
+}
+
 pub struct Global {
 	pub nats: async_nats::Client,
 	pub jetstream: async_nats::jetstream::Context,
@@ -46,6 +63,7 @@ pub struct Global {
 	pub user_editor_batcher: CollectionBatcher<mongo::UserEditor>,
 	pub entitlement_inbound_loader: DataLoader<EntitlementEdgeInboundLoader>,
 	pub entitlement_outbound_loader: DataLoader<EntitlementEdgeOutboundLoader>,
+	pub entitlement_product_grant_loader: DataLoader<EntitlementEdgeProductGrantLoader>,
 	pub emote_stats_batcher: Batcher<ClickhouseInsert<EmoteStat>>,
 	pub subscription_product_batcher: CollectionBatcher<mongo::SubscriptionProduct>,
 	pub subscription_batcher: CollectionBatcher<mongo::Subscription>,
@@ -54,17 +72,63 @@ pub struct Global {
 	request_count: AtomicUsize,
 	health_state: tokio::sync::Mutex<HealthCheckState>,
 	semaphore: Arc<tokio::sync::Semaphore>,
+	degraded_queue: Arc<tokio::sync::Semaphore>,
 	metrics: scuffle_bootstrap_telemetry::prometheus_client::registry::Registry,
 }
 
 #[derive(Debug, Default)]
 struct HealthCheckState {
-	nats_healthy: bool,
-	db_healthy: bool,
-	typesense_healthy: bool,
+	health: DependencyHealth,
 	last_check: Option<tokio::time::Instant>,
 }
 
+/// The readiness of every dependency of the service.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DependencyHealth {
+	pub nats: bool,
+	pub db: bool,
+	pub typesense: bool,
+}
+
+impl DependencyHealth {
+	const HEALTHY: Self = Self {
+		nats: true,
+		db: true,
+		typesense: true,
+	};
+
+	/// Change stream events can be consumed, writing them to typesense might
+	/// have to wait.
+	pub fn is_ready(&self) -> bool {
+		self.nats && self.db
+	}
+
+	pub fn is_healthy(&self) -> bool {
+		self.is_ready() && self.typesense
+	}
+}
+
+impl std::fmt::Display for DependencyHealth {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		let state = |healthy| if healthy { "up" } else { "down" };
+
+		write!(
+			f,
+			"nats: {}, db: {}, typesense: {}",
+			state(self.nats),
+			state(self.db),
+			state(self.typesense)
+		)
+	}
+}
+
+/// Allows processing a single change stream event.
+pub struct Ticket {
+	_permit: tokio::sync::OwnedSemaphorePermit,
+	/// Held while the event was accepted with typesense being down
+	_queue_permit: Option<tokio::sync::OwnedSemaphorePermit>,
+}
+
 impl scuffle_bootstrap::global::Global for Global {
 	type Config = Config;
 
@@ -145,6 +209,7 @@ impl scuffle_bootstrap::global::Global for Global {
 			invoice_batcher: CollectionBatcher::new(database.clone(), typesense.clone()),
 			entitlement_inbound_loader: EntitlementEdgeInboundLoader::new(database.clone()),
 			entitlement_outbound_loader: EntitlementEdgeOutboundLoader::new(database.clone()),
+			entitlement_product_grant_loader: EntitlementEdgeProductGrantLoader::new(database.clone()),
 			subscription_product_batcher: CollectionBatcher::new(database.clone(), typesense.clone()),
 			subscription_batcher: CollectionBatcher::new(database.clone(), typesense.clone()),
 			updater: MongoUpdater::new(database.clone(), 500, 5_000, std::time::Duration::from_millis(300)),
@@ -154,6 +219,7 @@ impl scuffle_bootstrap::global::Global for Global {
 			request_count: AtomicUsize::new(0),
 			health_state: tokio::sync::Mutex::new(HealthCheckState::default()),
 			semaphore: Arc::new(tokio::sync::Semaphore::new(config.triggers.typesense_concurrency.max(1))),
+			degraded_queue: Arc::new(tokio::sync::Semaphore::new(config.triggers.degraded_queue_size.max(1))),
 			emote_stats_batcher: ClickhouseInsert::new(clickhouse),
 			config,
 			metrics: prometheus_registry,
@@ -170,38 +236,38 @@ impl Global {
 		self.is_healthy.load(std::sync::atomic::Ordering::Relaxed)
 	}
 
-	pub async fn wait_healthy(&self) -> bool {
+	pub async fn wait_healthy(&self) -> DependencyHealth {
 		if self.is_healthy() {
-			return true;
+			return DependencyHealth::HEALTHY;
 		}
 
 		self.do_health_check().await
 	}
 
-	async fn do_health_check(&self) -> bool {
+	async fn do_health_check(&self) -> DependencyHealth {
 		let mut state = self.health_state.lock().await;
 		if state
 			.last_check
 			.is_some_and(|t| t.elapsed() < std::time::Duration::from_secs(5))
 		{
-			return state.nats_healthy && state.db_healthy && state.typesense_healthy;
+			return state.health;
 		}
 
 		tracing::debug!("running health check");
 
-		state.nats_healthy = matches!(self.nats.connection_state(), async_nats::connection::State::Connected);
-		if !state.nats_healthy {
+		let nats = matches!(self.nats.connection_state(), async_nats::connection::State::Connected);
+		if !nats {
 			tracing::error!("nats not healthy");
 		}
 
-		state.db_healthy = match self.database.run_command(bson::doc! { "ping": 1 }).await {
+		let db = match self.database.run_command(bson::doc! { "ping": 1 }).await {
 			Ok(_) => true,
 			Err(e) => {
 				tracing::error!("mongo not healthy: {e}");
 				false
 			}
 		};
-		state.typesense_healthy = match self.typesense.health_api().health().await {
+		let typesense = match self.typesense.health_api().health().await {
 			Ok(r) => {
 				if r.ok {
 					true
@@ -215,23 +281,54 @@ impl Global {
 				false
 			}
 		};
+
+		state.health = DependencyHealth { nats, db, typesense };
+		health::dependency(health::Dependency::Nats).record(nats as u64);
+		health::dependency(health::Dependency::Mongo).record(db as u64);
+		health::dependency(health::Dependency::Typesense).record(typesense as u64);
 		state.last_check = Some(tokio::time::Instant::now());
 
-		self.is_healthy.store(
-			state.nats_healthy && state.db_healthy && state.typesense_healthy,
-			std::sync::atomic::Ordering::Relaxed,
-		);
+		self.is_healthy
+			.store(state.health.is_healthy(), std::sync::atomic::Ordering::Relaxed);
 
-		state.nats_healthy && state.db_healthy && state.typesense_healthy
+		state.health
 	}
 
-	pub async fn aquire_ticket(&self) -> Option<tokio::sync::OwnedSemaphorePermit> {
-		while !self.wait_healthy().await {
-			tracing::warn!("waiting for mongo, typesense, and nats to be healthy");
+	/// Waits until events can be consumed. While only typesense is down,
+	/// tickets are still handed out until the degraded queue is full, the
+	/// events then wait in [`Global::wait_typesense`] and drain once typesense
+	/// recovers.
+	pub async fn aquire_ticket(&self) -> Option<Ticket> {
+		let health = loop {
+			let health = self.wait_healthy().await;
+			if health.is_ready() {
+				break health;
+			}
+
+			tracing::warn!("waiting for mongo and nats to be healthy ({health})");
 			tokio::time::sleep(std::time::Duration::from_secs(5)).await;
-		}
+		};
+
+		let permit = self.semaphore.clone().acquire_owned().await.ok()?;
 
-		self.semaphore.clone().acquire_owned().await.ok()
+		let queue_permit = if health.typesense {
+			None
+		} else {
+			Some(self.degraded_queue.clone().acquire_owned().await.ok()?)
+		};
+
+		Some(Ticket {
+			_permit: permit,
+			_queue_permit: queue_permit,
+		})
+	}
+
+	/// Waits until typesense is healthy.
+	pub async fn wait_typesense(&self) {
+		while !self.wait_healthy().await.typesense {
+			tracing::debug!("waiting for typesense to be healthy");
+			tokio::time::sleep(std::time::Duration::from_secs(5)).await;
+		}
 	}
 
 	pub fn incr_request_count(&self) {
@@ -268,33 +365,50 @@ impl Global {
 			}
 		}
 
-		for result in self
-			.updater
-			.bulk(reindex_collection! {
-				crate::types::mongo::RedeemCode,
-				crate::types::mongo::SpecialEvent,
-				crate::types::mongo::Invoice,
-				crate::types::mongo::Product,
-				crate::types::mongo::SubscriptionProduct,
-				crate::types::mongo::SubscriptionPeriod,
-				crate::types::mongo::UserBan,
-				crate::types::mongo::UserEditor,
-				crate::types::mongo::User,
-				crate::types::mongo::StoredEvent,
-				crate::types::mongo::Badge,
-				crate::types::mongo::EmoteModerationRequest,
-				crate::types::mongo::EmoteSet,
-				crate::types::mongo::Emote,
-				crate::types::mongo::Paint,
-				crate::types::mongo::Role,
-				crate::types::mongo::Ticket,
-				crate::types::mongo::TicketMessage,
-				crate::types::mongo::Subscription,
-			})
-			.await
-		{
+		let requests = reindex_collection! {
+			crate::types::mongo::RedeemCode,
+			crate::types::mongo::SpecialEvent,
+			crate::types::mongo::Invoice,
+			crate::types::mongo::Product,
+			crate::types::mongo::SubscriptionProduct,
+			crate::types::mongo::SubscriptionPeriod,
+			crate::types::mongo::UserBan,
+			crate::types::mongo::UserEditor,
+			crate::types::mongo::User,
+			crate::types::mongo::StoredEvent,
+			crate::types::mongo::Badge,
+			crate::types::mongo::EmoteModerationRequest,
+			crate::types::mongo::EmoteSet,
+			crate::types::mongo::Emote,
+			crate::types::mongo::Paint,
+			crate::types::mongo::Role,
+			crate::types::mongo::Ticket,
+			crate::types::mongo::TicketMessage,
+			crate::types::mongo::Subscription,
+		};
+
+		// Marking a collection makes the change stream handlers write its documents
+		// to typesense again, those writes take their own tickets. Marking itself
+		// also takes a ticket, so a reindex waits for mongo and nats to be healthy
+		// and never marks more collections at once than events are handled.
+		let results = futures::future::join_all(requests.into_iter().map(|request| async move {
+			let Some(_ticket) = self.aquire_ticket().await else {
+				return Err(anyhow::anyhow!("semaphore closed"));
+			};
+
+			self.updater
+				.bulk([request])
+				.await
+				.pop()
+				.unwrap_or(Err(MongoOpError::NoResponse))
+				.context("update")
+		}))
+		.await;
+
+		for result in results {
 			if let Err(e) = result {
-				tracing::error!("failed to reindex: {e}");
+				tracing::error!("failed to reindex: {e:#}");
+				self.report_error();
 			}
 		}
 	}
@@ -310,8 +424,15 @@ impl scuffle_bootstrap_telemetry::TelemetryConfig for Global {
 	}
 
 	async fn health_check(&self) -> Result<(), anyhow::Error> {
-		if !self.do_health_check().await {
-			anyhow::bail!("health check failed");
+		let health = self.do_health_check().await;
+		if !health.is_ready() {
+			anyhow::bail!("health check failed ({health})");
+		}
+
+		// Events are still consumed while only typesense is down, so the service is
+		// degraded but should not be restarted
+		if !health.typesense {
+			tracing::warn!("health check degraded ({health})");
 		}
 
 		Ok(())
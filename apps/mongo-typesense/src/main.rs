@@ -33,7 +33,7 @@ async fn refresh(global: Arc<Global>, ctx: scuffle_context::Context) -> anyhow::
 	async {
 		loop {
 			tokio::time::sleep(std::time::Duration::from_secs(60) + jitter()).await;
-			if !global.wait_healthy().await {
+			if !global.wait_healthy().await.is_healthy() {
 				continue;
 			}
 
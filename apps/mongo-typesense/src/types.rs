@@ -5,6 +5,7 @@ pub mod mongo {
 	pub use shared::database::emote_moderation_request::*;
 	pub use shared::database::emote_set::*;
 	pub use shared::database::entitlement::*;
+	pub use shared::database::idempotency_key::*;
 	pub use shared::database::paint::*;
 	pub use shared::database::product::codes::*;
 	pub use shared::database::product::invoice::*;
@@ -5,10 +5,11 @@ use anyhow::Context;
 use bson::Document;
 use chrono::Datelike;
 use mongodb::change_stream::event::{ChangeStreamEvent, OperationType};
+use serde::de::DeserializeOwned;
 use shared::clickhouse::emote_stat::EmoteStat;
 use shared::database::emote_set::EmoteSetId;
 use shared::database::entitlement::{EntitlementEdgeId, EntitlementEdgeKind};
-use shared::database::entitlement_edge::EntitlementEdgeGraphTraverse;
+use shared::database::entitlement_edge::{EntitlementEdgeGraphTraverse, ProductGrantKey};
 use shared::database::graph::{Direction, GraphTraverse};
 use shared::database::product::special_event::SpecialEventId;
 use shared::database::product::subscription::SubscriptionId;
@@ -19,10 +20,11 @@ use shared::database::updater::MongoReq;
 use shared::database::user::editor::UserEditorId;
 use shared::database::user::UserId;
 use shared::database::{MongoCollection, SearchableMongoCollection};
-use shared::typesense::types::TypesenseCollection;
+use shared::typesense::types::{TypesenseCollection, TypesensePatch, TypesensePatchDocument};
 use typesense_rs::apis::documents_api::DeleteDocumentParams;
 use typesense_rs::apis::Api;
 
+use crate::batcher::CollectionBatcher;
 use crate::global::Global;
 use crate::types::{mongo, typesense};
 
@@ -105,8 +107,93 @@ fn parse_document_key<M: SupportedMongoCollection>(msg: &ChangeStreamEvent<Docum
 	bson::from_bson(id.clone()).context("failed to deserialize document key")
 }
 
+/// The top level database fields changed by an update event, `None` if the
+/// event does not describe them.
+fn changed_fields(message: &ChangeStreamEvent<Document>) -> Option<HashSet<String>> {
+	let description = message.update_description.as_ref()?;
+
+	Some(
+		description
+			.updated_fields
+			.keys()
+			.chain(description.removed_fields.iter())
+			.chain(description.truncated_arrays.iter().flatten().map(|array| &array.field))
+			.map(|field| field.split('.').next().unwrap_or(field).to_owned())
+			.collect(),
+	)
+}
+
+/// The fields changed by an update event which can be sent as a patch, `None`
+/// if the whole document has to be upserted. Events for later changes are
+/// skipped once the document is marked as indexed, so a patch is only sent if
+/// the loaded document is the one written by this event, otherwise the fields
+/// changed by the skipped events would never be sent.
+fn patch_fields(
+	message: &ChangeStreamEvent<Document>,
+	updated_at: chrono::DateTime<chrono::Utc>,
+) -> Option<HashSet<String>> {
+	let event_updated_at = message
+		.update_description
+		.as_ref()?
+		.updated_fields
+		.get_datetime("updated_at")
+		.ok()?;
+
+	if event_updated_at.timestamp_millis() != updated_at.timestamp_millis() {
+		return None;
+	}
+
+	changed_fields(message)
+}
+
+/// Sends only the changed fields of a document if possible, a failed patch (for
+/// example because the document was never indexed) falls back to a full upsert.
+async fn index<M>(
+	batcher: &CollectionBatcher<M>,
+	data: M::Typesense,
+	patch: Option<TypesensePatchDocument>,
+) -> anyhow::Result<()>
+where
+	M: SearchableMongoCollection + DeserializeOwned + Clone + 'static,
+	M::Typesense: TypesenseCollection + serde::Serialize + 'static,
+{
+	if let Some(patch) = patch {
+		match batcher.patcher.execute(patch).await.context("patch missing")? {
+			Ok(_) => return Ok(()),
+			Err(err) => tracing::debug!(error = %err, "failed to patch document, falling back to upsert"),
+		}
+	}
+
+	batcher
+		.inserter
+		.execute(data)
+		.await
+		.context("insert missing")?
+		.context("insert")?;
+
+	Ok(())
+}
+
 macro_rules! default_impl {
 	($batcher:ident, $mongo_collection:ty) => {
+		default_impl!(@impl $batcher, $mongo_collection, {});
+	};
+	($batcher:ident, $mongo_collection:ty, patch) => {
+		default_impl!(@impl $batcher, $mongo_collection, {
+			#[tracing::instrument(skip_all, fields(id))]
+			async fn handle_update(
+				global: &Arc<Global>,
+				id: Self::Id,
+				message: ChangeStreamEvent<Document>,
+			) -> anyhow::Result<()> {
+				default_impl!(@index global, id, $batcher, $mongo_collection, |data: &<$mongo_collection as SearchableMongoCollection>::Typesense, updated_at| {
+					patch_fields(&message, updated_at)
+						.and_then(|fields| TypesensePatch::patch(data, fields.iter().map(String::as_str)))
+				})
+			}
+		});
+	};
+	(@impl $batcher:ident, $mongo_collection:ty, { $($extra:item)* }) => {
 		impl SupportedMongoCollection for $mongo_collection {
 			#[tracing::instrument(skip_all, fields(id))]
 			async fn handle_delete(
@@ -132,60 +219,60 @@ macro_rules! default_impl {
 
 			#[tracing::instrument(skip_all, fields(id))]
 			async fn handle_any(global: &Arc<Global>, id: Self::Id, _: ChangeStreamEvent<Document>) -> anyhow::Result<()> {
-				let Ok(Some(data)) = global.$batcher.loader.load(id.clone()).await else {
-					anyhow::bail!("failed to load data");
-				};
+				default_impl!(@index global, id, $batcher, $mongo_collection, |_, _| None)
+			}
 
-				if data.search_updated_at.is_some_and(|u| u > data.updated_at) {
-					return Ok(());
-				}
+			$($extra)*
+		}
+	};
+	(@index $global:ident, $id:ident, $batcher:ident, $mongo_collection:ty, $patch:expr) => {{
+		let Ok(Some(data)) = $global.$batcher.loader.load($id.clone()).await else {
+			anyhow::bail!("failed to load data");
+		};
 
-				let updated_at = data.updated_at;
+		if data.search_updated_at.is_some_and(|u| u > data.updated_at) {
+			return Ok(());
+		}
 
-				#[allow(irrefutable_let_patterns)]
-				let Ok(data) = data.try_into() else {
-					return Ok(());
-				};
+		let updated_at = data.updated_at;
 
-				global
-					.$batcher
-					.inserter
-					.execute(data)
-					.await
-					.context("insert missing")?
-					.context("insert")?;
+		#[allow(irrefutable_let_patterns)]
+		let Ok(data): Result<<$mongo_collection as SearchableMongoCollection>::Typesense, _> = data.try_into() else {
+			return Ok(());
+		};
 
-				// Perhaps this could be a batcher?
-				global
-					.updater
-					.update::<$mongo_collection>(
-						filter::filter! {
-							$mongo_collection {
-								#[query(rename = "_id")]
-								id: id,
-								updated_at,
-							}
-						},
-						update::update! {
-							#[query(set)]
-							$mongo_collection {
-								search_updated_at: chrono::Utc::now(),
-							}
-						},
-						false,
-					)
-					.await
-					.with_context(|| {
-						format!(
-							"failed to update {}",
-							<$mongo_collection as MongoCollection>::COLLECTION_NAME
-						)
-					})?;
+		let patch: Option<TypesensePatchDocument> = ($patch)(&data, updated_at);
+		index(&$global.$batcher, data, patch).await?;
 
-				Ok(())
-			}
-		}
-	};
+		// Perhaps this could be a batcher?
+		$global
+			.updater
+			.update::<$mongo_collection>(
+				filter::filter! {
+					$mongo_collection {
+						#[query(rename = "_id")]
+						id: $id,
+						updated_at,
+					}
+				},
+				update::update! {
+					#[query(set)]
+					$mongo_collection {
+						search_updated_at: chrono::Utc::now(),
+					}
+				},
+				false,
+			)
+			.await
+			.with_context(|| {
+				format!(
+					"failed to update {}",
+					<$mongo_collection as MongoCollection>::COLLECTION_NAME
+				)
+			})?;
+
+		Ok(())
+	}};
 }
 
 default_impl!(redeem_code_batcher, mongo::RedeemCode);
@@ -195,7 +282,7 @@ default_impl!(user_ban_batcher, mongo::UserBan);
 default_impl!(event_batcher, mongo::StoredEvent);
 default_impl!(badge_batcher, mongo::Badge);
 default_impl!(emote_moderation_request_batcher, mongo::EmoteModerationRequest);
-default_impl!(emote_batcher, mongo::Emote);
+default_impl!(emote_batcher, mongo::Emote, patch);
 default_impl!(paint_batcher, mongo::Paint);
 default_impl!(ticket_batcher, mongo::Ticket);
 default_impl!(ticket_message_batcher, mongo::TicketMessage);
@@ -423,21 +510,33 @@ impl SupportedMongoCollection for mongo::User {
 
 		let updated_at = data.updated_at;
 
-		global
-			.user_batcher
-			.inserter
-			.execute(typesense::User::from_db(
-				data,
-				// This field is specifically used to filter out the user's own entitlements (things which are directly
-				// granted to them, not via some other entity)
-				granted_entitlements
-					.into_iter()
-					.filter(|edge| edge.id.from == EntitlementEdgeKind::User { user_id: id })
-					.map(|edge| edge.id.to),
-			))
-			.await
-			.context("insert missing")?
-			.context("insert")?;
+		let cached_changed = old_entitlements != data.cached.entitlements
+			|| old_emotes != data.cached.active_emotes
+			|| old_emote_set_id != data.cached.emote_set_id
+			|| old_role_rank != data.cached.role_rank
+			|| old_role_hoist_rank != data.cached.role_hoist_rank;
+
+		let document = typesense::User::from_db(
+			data,
+			// This field is specifically used to filter out the user's own entitlements (things which are directly
+			// granted to them, not via some other entity)
+			granted_entitlements
+				.into_iter()
+				.filter(|edge| edge.id.from == EntitlementEdgeKind::User { user_id: id })
+				.map(|edge| edge.id.to),
+		);
+
+		// The recomputed cache is not part of the change, but it has to be sent if it
+		// differs from the stored one
+		let patch = patch_fields(&change, updated_at).and_then(|mut fields| {
+			if cached_changed {
+				fields.insert("cached".to_owned());
+			}
+
+			document.patch(fields.iter().map(String::as_str))
+		});
+
+		index(&global.user_batcher, document, patch).await?;
 
 		global
 			.updater
@@ -678,8 +777,8 @@ impl SupportedMongoCollection for mongo::Product {
 		let updated_at = data.updated_at;
 
 		let granted_entitlements = global
-			.entitlement_outbound_loader
-			.load(EntitlementEdgeKind::Product { product_id: id.clone() })
+			.entitlement_product_grant_loader
+			.load(ProductGrantKey::Product(id.clone()))
 			.await
 			.map_err(|()| anyhow::anyhow!("failed to load entitlements"))?
 			.unwrap_or_default();
@@ -687,10 +786,7 @@ impl SupportedMongoCollection for mongo::Product {
 		global
 			.product_batcher
 			.inserter
-			.execute(typesense::Product::from_db(
-				data,
-				granted_entitlements.into_iter().map(|edge| edge.id.to),
-			))
+			.execute(typesense::Product::from_db(data, granted_entitlements))
 			.await
 			.context("insert missing")?
 			.context("insert")?;
@@ -758,16 +854,14 @@ impl SupportedMongoCollection for mongo::SubscriptionProduct {
 		let updated_at = data.updated_at;
 
 		let granted_entitlements = global
-			.entitlement_outbound_loader
-			.load_many(data.benefits.iter().map(|b| EntitlementEdgeKind::SubscriptionBenefit {
-				subscription_benefit_id: b.id,
-			}))
+			.entitlement_product_grant_loader
+			.load(ProductGrantKey::SubscriptionProduct {
+				id,
+				benefits: data.benefits.iter().map(|benefit| benefit.id).collect(),
+			})
 			.await
 			.map_err(|()| anyhow::anyhow!("failed to load entitlements"))?
-			.into_values()
-			.flatten()
-			.map(|e| e.id.to)
-			.collect::<HashSet<_>>();
+			.unwrap_or_default();
 
 		global
 			.subscription_product_batcher
@@ -219,7 +219,8 @@ async fn setup(
 					crate::types::mongo::UserProfilePicture::COLLECTION_NAME
 						| crate::types::mongo::UserSession::COLLECTION_NAME
 						| crate::types::mongo::WebhookEvent::COLLECTION_NAME
-						| crate::types::mongo::CronJob::COLLECTION_NAME => {
+						| crate::types::mongo::CronJob::COLLECTION_NAME
+						| crate::types::mongo::IdempotencyKey::COLLECTION_NAME => {
 						message.ack_with(AckKind::Term).await.ok();
 					}
 					_ => {
@@ -272,6 +273,8 @@ async fn handle<M: SupportedMongoCollection>(
 	tracing::Span::current().record("coll", &coll);
 	tracing::Span::current().record("operation", operation.as_str());
 
+	global.wait_typesense().await;
+
 	let result = handlers::process::<M>(global, message).await;
 
 	let status = match &result {
@@ -175,7 +175,7 @@ export async function fetchMe(): Promise<User | null> {
 									}
 								}
 							}
-							editorFor {
+							editorFor(states: [PENDING, ACCEPTED, REJECTED]) {
 								user {
 									id
 									mainConnection {
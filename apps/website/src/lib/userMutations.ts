@@ -787,14 +787,16 @@ export async function removeConnection(userId: string, platform: Platform, platf
 				users {
 					user(id: $userId) {
 						removeConnection(platform: $platform, platformId: $platformId) {
-							mainConnection {
-								platform
-								platformId
-							}
-							connections {
-								platform
-								platformId
-								platformDisplayName
+							user {
+								mainConnection {
+									platform
+									platformId
+								}
+								connections {
+									platform
+									platformId
+									platformDisplayName
+								}
 							}
 						}
 					}
@@ -808,7 +810,7 @@ export async function removeConnection(userId: string, platform: Platform, platf
 		return undefined;
 	}
 
-	return res.data.users.user.removeConnection as User;
+	return res.data.users.user.removeConnection.user as User;
 }
 
 export async function deleteAllSessions(userId: string) {
@@ -2,6 +2,7 @@ use proc_macro::TokenStream;
 
 mod mongo_collection;
 mod mongo_filter_query;
+mod mongo_projection;
 mod mongo_update_query;
 mod typesense_collection;
 
@@ -25,6 +26,16 @@ pub fn derive_mongo_collection(input: TokenStream) -> TokenStream {
 	tokens.into()
 }
 
+#[proc_macro_derive(MongoProjection, attributes(mongo))]
+pub fn derive_mongo_projection(input: TokenStream) -> TokenStream {
+	let tokens = match mongo_projection::derive(input.into()) {
+		Ok(output) => output,
+		Err(err) => err.to_compile_error(),
+	};
+
+	tokens.into()
+}
+
 #[proc_macro]
 pub fn mongo_update_query(input: TokenStream) -> TokenStream {
 	let tokens = match mongo_update_query::proc_macro(input.into()) {
@@ -0,0 +1,281 @@
+use darling::FromMeta;
+use proc_macro2::{Span, TokenStream};
+use proc_macro_crate::{crate_name, FoundCrate};
+use quote::{format_ident, quote, quote_spanned};
+use syn::meta::ParseNestedMeta;
+use syn::spanned::Spanned;
+use syn::{Data, Fields, Meta, Token};
+
+// #[derive(MongoProjection, serde::Deserialize)]
+// #[mongo(collection = "Emote")]
+// pub struct EmotePreview {
+//    	#[serde(rename = "_id")]
+//    	pub id: EmoteId,
+//   	pub default_name: String,
+//   	#[mongo(nested)]
+//  	pub image_set: ImageSetPreview,
+// }
+
+/// Skips the value of a serde attribute which does not change the projection.
+fn skip_meta(meta: &ParseNestedMeta) -> syn::Result<()> {
+	if meta.input.peek(Token![=]) {
+		meta.value()?.parse::<syn::Expr>()?;
+	} else if meta.input.peek(syn::token::Paren) {
+		let content;
+		syn::parenthesized!(content in meta.input);
+		content.parse::<TokenStream>()?;
+	}
+
+	Ok(())
+}
+
+#[derive(Debug)]
+struct StructAttributes {
+	ident: syn::Ident,
+	generics: syn::Generics,
+	fields: Vec<FieldAttributes>,
+
+	collection: syn::Type,
+}
+
+impl StructAttributes {
+	fn from_derive_input(input: &syn::DeriveInput) -> syn::Result<Self> {
+		let Data::Struct(data) = &input.data else {
+			return Err(syn::Error::new(Span::call_site(), "expected named struct"));
+		};
+
+		let Fields::Named(fields) = &data.fields else {
+			return Err(syn::Error::new(Span::call_site(), "expected named struct"));
+		};
+
+		let mut collection = None;
+
+		for attr in &input.attrs {
+			if attr.path().is_ident("mongo") {
+				let Meta::NameValue(meta) = attr.parse_args::<Meta>()? else {
+					return Err(syn::Error::new(attr.span(), "invalid mongo attribute"));
+				};
+
+				if !meta.path.is_ident("collection") {
+					return Err(syn::Error::new(meta.span(), "invalid mongo attribute"));
+				}
+
+				collection = Some(syn::Type::from_expr(&meta.value)?);
+			} else if attr.path().is_ident("serde") {
+				// the field names are taken as written, so they cannot be renamed as a whole
+				attr.parse_nested_meta(|meta| {
+					if meta.path.is_ident("rename_all") {
+						return Err(meta.error("rename_all is not supported on projections"));
+					}
+
+					skip_meta(&meta)
+				})?;
+			}
+		}
+
+		Ok(Self {
+			ident: input.ident.clone(),
+			generics: input.generics.clone(),
+			fields: fields
+				.named
+				.iter()
+				.map(FieldAttributes::from_field)
+				.collect::<syn::Result<_>>()?,
+			collection: collection.ok_or_else(|| syn::Error::new(Span::call_site(), "missing mongo collection"))?,
+		})
+	}
+}
+
+#[derive(Debug)]
+struct FieldAttributes {
+	ident: syn::Ident,
+	ty: syn::Type,
+	rename: Option<syn::LitStr>,
+	/// Only the fields of the nested projection are loaded
+	nested: bool,
+	/// The fields of the flattened projection are loaded at the same level
+	flatten: bool,
+}
+
+impl FieldAttributes {
+	fn from_field(field: &syn::Field) -> syn::Result<Self> {
+		let ident = field
+			.ident
+			.as_ref()
+			.ok_or_else(|| syn::Error::new(field.span(), "expected field to have an identifier"))?;
+
+		let mut rename = None;
+		let mut nested = false;
+		let mut flatten = false;
+
+		for attr in &field.attrs {
+			if attr.path().is_ident("mongo") {
+				match attr.parse_args::<Meta>()? {
+					Meta::Path(path) if path.is_ident("nested") => {
+						if nested {
+							return Err(syn::Error::new(path.span(), "duplicate nested attribute"));
+						}
+
+						nested = true;
+					}
+					meta => return Err(syn::Error::new(meta.span(), "invalid mongo attribute")),
+				}
+			} else if attr.path().is_ident("serde") {
+				attr.parse_nested_meta(|meta| {
+					if meta.path.is_ident("rename") {
+						rename = Some(meta.value()?.parse()?);
+					} else if meta.path.is_ident("flatten") {
+						flatten = true;
+					} else {
+						skip_meta(&meta)?;
+					}
+
+					Ok(())
+				})?;
+			}
+		}
+
+		if nested && flatten {
+			return Err(syn::Error::new(
+				field.span(),
+				"nested cannot be used with flatten, flattened fields are always projected",
+			));
+		}
+
+		Ok(Self {
+			ident: ident.clone(),
+			ty: field.ty.clone(),
+			rename,
+			nested,
+			flatten,
+		})
+	}
+
+	fn bson_key(&self) -> syn::LitStr {
+		self.rename
+			.clone()
+			.unwrap_or_else(|| syn::LitStr::new(&self.ident.to_string(), self.ident.span()))
+	}
+}
+
+pub fn derive(input: TokenStream) -> syn::Result<TokenStream> {
+	let input = syn::parse2(input)?;
+	let input = StructAttributes::from_derive_input(&input)?;
+
+	let found_crate = crate_name("shared").expect("shared is present in `Cargo.toml`");
+	let shared_crate = match found_crate {
+		FoundCrate::Itself => quote!(crate),
+		FoundCrate::Name(name) => {
+			let name = format_ident!("{name}");
+			quote!(::#name)
+		}
+	};
+
+	let path = quote! { #shared_crate::database::queries::projection };
+
+	let ident = &input.ident;
+	let collection = &input.collection;
+
+	let fields = input.fields.iter().map(|field| {
+		let key = field.bson_key();
+		let ty = &field.ty;
+
+		if field.flatten {
+			quote_spanned! { field.ident.span() =>
+				<#ty as #path::ProjectionFields>::fields(prefix, projection);
+			}
+		} else if field.nested {
+			quote_spanned! { field.ident.span() =>
+				<#ty as #path::ProjectionFields>::fields(&format!("{prefix}{}.", #key), projection);
+			}
+		} else {
+			quote_spanned! { field.ident.span() =>
+				projection.insert(format!("{prefix}{}", #key), 1);
+			}
+		}
+	});
+
+	// every field must exist on the collection, nested and flattened fields must
+	// be projections of the type of the field they replace
+	let asserts = input.fields.iter().map(|field| {
+		let name = &field.ident;
+		let ty = &field.ty;
+
+		if field.nested || field.flatten {
+			quote_spanned! { name.span() => ____assert_projection::<_, #ty>(&____value.#name); }
+		} else {
+			quote_spanned! { name.span() => let _ = &____value.#name; }
+		}
+	});
+
+	// the rust fields are checked above, the serialized keys can only be compared
+	// with the field names of the collection's `Deserialize` impl at runtime
+	let unknown_fields = input.fields.iter().map(|field| {
+		let name = &field.ident;
+		let key = field.bson_key();
+		let ty = &field.ty;
+
+		let check_key = quote_spanned! { name.span() =>
+			if ____fields.is_some_and(|f| !f.contains(&#key)) {
+				unknown.push(format!("{prefix}{}", #key));
+			}
+		};
+
+		if field.flatten {
+			quote_spanned! { name.span() =>
+				____unknown_fields::<_, #ty>(|____value: &#collection| &____value.#name, prefix, unknown);
+			}
+		} else if field.nested {
+			quote_spanned! { name.span() =>
+				#check_key
+				____unknown_fields::<_, #ty>(
+					|____value: &#collection| &____value.#name,
+					&format!("{prefix}{}.", #key),
+					unknown,
+				);
+			}
+		} else {
+			check_key
+		}
+	});
+
+	let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+	Ok(quote! {
+		#[allow(clippy::all)]
+		#[doc(hidden)]
+		const _: () = {
+			impl #impl_generics #path::ProjectionFields for #ident #ty_generics #where_clause {
+				fn fields(prefix: &str, projection: &mut bson::Document) {
+					#(#fields)*
+				}
+			}
+
+			impl #impl_generics #path::Projection<#collection> for #ident #ty_generics #where_clause {
+				#[allow(unused_variables)]
+				fn unknown_fields(prefix: &str, unknown: &mut Vec<String>) {
+					#[allow(dead_code)]
+					fn ____unknown_fields<T, P: #path::Projection<T>>(
+						_: impl Fn(&#collection) -> &T,
+						prefix: &str,
+						unknown: &mut Vec<String>,
+					) {
+						P::unknown_fields(prefix, unknown);
+					}
+
+					let ____fields = #path::serde_fields::<#collection>();
+
+					#(#unknown_fields)*
+				}
+			}
+
+			#[allow(unused_variables, dead_code)]
+			fn ____assert_fields(____value: &#collection) {
+				#[inline(always)]
+				fn ____assert_projection<T, P: #path::Projection<T>>(_: &T) {}
+
+				#(#asserts)*
+			}
+		};
+	})
+}
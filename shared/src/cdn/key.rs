@@ -32,6 +32,12 @@ pub enum CacheKey {
 }
 
 impl CacheKey {
+	/// The keys of every file which can be stored in the directory `prefix`,
+	/// such as `emote/{id}/`. `None` if the prefix is not such a directory.
+	pub fn files_in(prefix: &str) -> Option<Vec<Self>> {
+		ImageFile::all().map(|file| format!("{prefix}{file}").parse().ok()).collect()
+	}
+
 	pub fn extension(&self) -> ImageFileExtension {
 		match self {
 			Self::Badge { file, .. } => file.extension,
@@ -230,6 +236,34 @@ impl FromStr for ImageFileExtension {
 	}
 }
 
+impl ImageFile {
+	/// Every file name, extension and static variant of an image.
+	pub fn all() -> impl Iterator<Item = Self> {
+		let names = [
+			ImageFileName::One,
+			ImageFileName::Two,
+			ImageFileName::Three,
+			ImageFileName::Four,
+		];
+		let extensions = [
+			ImageFileExtension::Avif,
+			ImageFileExtension::Gif,
+			ImageFileExtension::Png,
+			ImageFileExtension::Webp,
+		];
+
+		names.into_iter().flat_map(move |name| {
+			extensions.into_iter().flat_map(move |extension| {
+				[false, true].map(|is_static| Self {
+					name: name.clone(),
+					extension,
+					is_static,
+				})
+			})
+		})
+	}
+}
+
 impl FromStr for ImageFile {
 	type Err = &'static str;
 
@@ -287,3 +321,23 @@ impl<'de> serde::Deserialize<'de> for ImageFile {
 		s.parse().map_err(serde::de::Error::custom)
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_files_in() {
+		let emote_id = EmoteId::new();
+
+		let keys = CacheKey::files_in(&format!("emote/{emote_id}/")).unwrap();
+		assert_eq!(keys.len(), 32);
+		assert!(keys.contains(&format!("emote/{emote_id}/4x_static.avif").parse().unwrap()));
+		assert!(keys
+			.iter()
+			.all(|key| matches!(key, CacheKey::Emote { emote_id: id, .. } if *id == emote_id)));
+
+		assert!(CacheKey::files_in(&format!("emote/{emote_id}")).is_none());
+		assert!(CacheKey::files_in("emote/").is_none());
+	}
+}
@@ -3,4 +3,8 @@ pub mod key;
 #[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
 pub struct PurgeRequest {
 	pub files: Vec<key::CacheKey>,
+	/// Directories in which every file should be purged, e.g. `emote/{id}/`
+	/// to purge all variants of an emote.
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub prefixes: Vec<String>,
 }
@@ -1,3 +1,4 @@
+use image_processor_proto::{OutputFormat, OutputQuality};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Serialize, Deserialize, smart_default::SmartDefault)]
@@ -105,6 +106,107 @@ pub struct ImageProcessorConfig {
 	/// Output Drive Name
 	#[default("s3".to_string())]
 	pub output_drive_name: String,
+	/// Output formats to generate, at least one is required
+	#[default(vec![
+		ImageProcessorOutputFormat { format: OutputFormat::WebpAnim, quality: OutputQuality::Auto },
+		ImageProcessorOutputFormat { format: OutputFormat::WebpStatic, quality: OutputQuality::Lossless },
+		ImageProcessorOutputFormat { format: OutputFormat::AvifAnim, quality: OutputQuality::Auto },
+		ImageProcessorOutputFormat { format: OutputFormat::AvifStatic, quality: OutputQuality::Auto },
+		ImageProcessorOutputFormat { format: OutputFormat::GifAnim, quality: OutputQuality::Auto },
+		ImageProcessorOutputFormat { format: OutputFormat::PngStatic, quality: OutputQuality::Auto },
+	])]
+	pub output_formats: Vec<ImageProcessorOutputFormat>,
+	/// Output scales relative to the base height
+	#[default(vec![1, 2, 3, 4])]
+	pub output_scales: Vec<u32>,
+	/// Output base height in pixels
+	#[default(32)]
+	pub output_base_height: u32,
+	/// Maximum aspect ratio (width / height) of uploaded images
+	#[default(Some(3.0))]
+	pub max_aspect_ratio: Option<f64>,
+	/// Number of times to retry a request when the image processor is
+	/// unavailable. Requests which exceed their deadline are not retried since
+	/// they might have already created a task.
+	#[default(3)]
+	pub max_retries: u32,
+	/// Backoff before the first retry, doubled on every attempt
+	#[default(std::time::Duration::from_millis(100))]
+	#[serde(with = "humantime_serde")]
+	pub retry_backoff: std::time::Duration,
+	/// Input limits per subject
+	pub limits: ImageProcessorSubjectLimits,
+	/// Output scaling per subject, subjects without one use the output scales
+	/// and base height
+	pub scaling: ImageProcessorSubjectScaling,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct ImageProcessorSubjectScaling {
+	/// Emote output scaling
+	pub emote: Option<ImageProcessorScaling>,
+	/// Profile picture output scaling
+	pub profile_picture: Option<ImageProcessorScaling>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, smart_default::SmartDefault)]
+#[serde(default)]
+pub struct ImageProcessorScaling {
+	/// The size the scales are relative to
+	#[default(ImageProcessorScalingBase::Height(32))]
+	pub base: ImageProcessorScalingBase,
+	/// Output scales relative to the base, must be positive
+	#[default(vec![1, 2, 3, 4])]
+	pub scales: Vec<u32>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImageProcessorScalingBase {
+	/// Base height in pixels
+	Height(u32),
+	/// Base width in pixels, useful for square images
+	Width(u32),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, smart_default::SmartDefault)]
+#[serde(default)]
+pub struct ImageProcessorSubjectLimits {
+	/// Emote input limits
+	pub emote: ImageProcessorLimits,
+	/// Profile picture input limits
+	pub profile_picture: ImageProcessorLimits,
+	/// Paint layer input limits
+	#[default(ImageProcessorLimits { max_input_frame_count: 1000, max_input_width: 1500, max_input_height: 1500 })]
+	pub paint_layer: ImageProcessorLimits,
+	/// Badge input limits
+	pub badge: ImageProcessorLimits,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, smart_default::SmartDefault)]
+#[serde(default)]
+pub struct ImageProcessorLimits {
+	/// Maximum number of input frames
+	#[default(1000)]
+	pub max_input_frame_count: u32,
+	/// Maximum input width in pixels
+	#[default(1000)]
+	pub max_input_width: u32,
+	/// Maximum input height in pixels
+	#[default(1000)]
+	pub max_input_height: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, smart_default::SmartDefault)]
+#[serde(default)]
+pub struct ImageProcessorOutputFormat {
+	/// Output format
+	#[default(OutputFormat::WebpAnim)]
+	pub format: OutputFormat,
+	/// Output quality
+	#[default(OutputQuality::Auto)]
+	pub quality: OutputQuality,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, smart_default::SmartDefault)]
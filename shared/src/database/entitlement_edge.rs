@@ -11,6 +11,7 @@ use super::loader::dataloader::BatchLoad;
 use super::queries::filter;
 use crate::database::entitlement::{EntitlementEdge, EntitlementEdgeKind};
 use crate::database::graph::GraphTraverse;
+use crate::database::product::{ProductId, SubscriptionBenefitId, SubscriptionProductId};
 use crate::database::MongoCollection;
 
 pub struct EntitlementEdgeInboundLoader {
@@ -125,11 +126,135 @@ impl DataLoaderFetcher for EntitlementEdgeOutboundLoader {
 	}
 }
 
+/// A product whose granted entitlements are loaded by the
+/// [`EntitlementEdgeProductGrantLoader`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ProductGrantKey {
+	Product(ProductId),
+	/// Subscription products grant entitlements through their benefits, which
+	/// are passed in by the caller that loaded the product
+	SubscriptionProduct {
+		id: SubscriptionProductId,
+		benefits: Vec<SubscriptionBenefitId>,
+	},
+}
+
+impl ProductGrantKey {
+	/// The nodes whose outbound edges are the entitlements granted by the
+	/// product.
+	fn sources(&self) -> Vec<EntitlementEdgeKind> {
+		match self {
+			Self::Product(product_id) => vec![EntitlementEdgeKind::Product {
+				product_id: product_id.clone(),
+			}],
+			Self::SubscriptionProduct { benefits, .. } => benefits
+				.iter()
+				.map(|&subscription_benefit_id| EntitlementEdgeKind::SubscriptionBenefit { subscription_benefit_id })
+				.collect(),
+		}
+	}
+}
+
+/// Maps the outbound edges of the sources of the keys to the entitlements
+/// granted by each key. Keys without any edges are left out.
+fn product_grants(
+	keys: &HashSet<ProductGrantKey>,
+	edges: Vec<EntitlementEdge>,
+) -> HashMap<ProductGrantKey, Vec<EntitlementEdgeKind>> {
+	let mut grants_by_source: HashMap<EntitlementEdgeKind, Vec<EntitlementEdgeKind>> = HashMap::new();
+
+	for edge in edges {
+		grants_by_source.entry(edge.id.from).or_default().push(edge.id.to);
+	}
+
+	keys.iter()
+		.filter_map(|key| {
+			let grants: Vec<_> = key
+				.sources()
+				.iter()
+				.filter_map(|source| grants_by_source.get(source))
+				.flatten()
+				.cloned()
+				.unique()
+				.collect();
+
+			(!grants.is_empty()).then(|| (key.clone(), grants))
+		})
+		.collect()
+}
+
+/// Loads the `to` side of the outbound edges of products. Products grant
+/// entitlements directly while subscription products grant them through their
+/// benefits, so the edges of all benefits are merged.
+pub struct EntitlementEdgeProductGrantLoader {
+	db: mongodb::Database,
+	name: String,
+}
+
+impl EntitlementEdgeProductGrantLoader {
+	pub fn new(db: mongodb::Database) -> DataLoader<Self> {
+		Self::new_with_config(
+			db,
+			"EntitlementEdgeProductGrantLoader".to_string(),
+			1000,
+			500,
+			std::time::Duration::from_millis(5),
+		)
+	}
+
+	pub fn new_with_config(
+		db: mongodb::Database,
+		name: String,
+		batch_size: usize,
+		concurrency: usize,
+		delay: std::time::Duration,
+	) -> DataLoader<Self> {
+		DataLoader::new(Self { db, name }, batch_size, concurrency, delay)
+	}
+}
+
+impl DataLoaderFetcher for EntitlementEdgeProductGrantLoader {
+	type Key = ProductGrantKey;
+	type Value = Vec<EntitlementEdgeKind>;
+
+	async fn load(&self, keys: HashSet<Self::Key>) -> Option<HashMap<Self::Key, Self::Value>> {
+		let _batch = BatchLoad::new(&self.name, keys.len());
+
+		let sources: HashSet<EntitlementEdgeKind> = keys.iter().flat_map(ProductGrantKey::sources).collect();
+
+		if sources.is_empty() {
+			return Some(HashMap::new());
+		}
+
+		let edges: Vec<EntitlementEdge> = EntitlementEdge::collection(&self.db)
+			.find(filter::filter! {
+				EntitlementEdge {
+					#[query(rename = "_id", flatten)]
+					id: EntitlementEdgeId {
+						#[query(serde, selector = "in")]
+						from: sources.iter().collect::<Vec<_>>(),
+					},
+				}
+			})
+			.into_future()
+			.and_then(|f| f.try_collect())
+			.await
+			.map_err(|err| {
+				tracing::error!("failed to load: {err}");
+			})
+			.ok()?;
+
+		Some(product_grants(&keys, edges))
+	}
+}
+
 pub struct EntitlementEdgeGraphTraverse<'a> {
 	pub inbound_loader: &'a DataLoader<EntitlementEdgeInboundLoader>,
 	pub outbound_loader: &'a DataLoader<EntitlementEdgeOutboundLoader>,
 }
 
+/// Traverses the entitlement graph, skipping edges that have expired but have
+/// not yet been removed by the expiry cron job.
 impl GraphTraverse for EntitlementEdgeGraphTraverse<'_> {
 	type Edge = EntitlementEdge;
 	type Error = ();
@@ -146,6 +271,7 @@ impl GraphTraverse for EntitlementEdgeGraphTraverse<'_> {
 				.await?
 				.into_values()
 				.flatten()
+				.filter(|edge| !edge.is_expired())
 				.collect()),
 			crate::database::graph::Direction::Outbound => Ok(self
 				.outbound_loader
@@ -153,7 +279,90 @@ impl GraphTraverse for EntitlementEdgeGraphTraverse<'_> {
 				.await?
 				.into_values()
 				.flatten()
+				.filter(|edge| !edge.is_expired())
 				.collect()),
 		}
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::database::badge::BadgeId;
+	use crate::database::paint::PaintId;
+
+	fn edge(from: EntitlementEdgeKind, to: EntitlementEdgeKind) -> EntitlementEdge {
+		EntitlementEdge {
+			id: EntitlementEdgeId {
+				from,
+				to,
+				managed_by: None,
+			},
+			expires_at: None,
+		}
+	}
+
+	#[test]
+	fn test_product_grants() {
+		let product_id: ProductId = "price_test".parse().unwrap();
+		let benefits = [SubscriptionBenefitId::new(), SubscriptionBenefitId::new()];
+		let badge = EntitlementEdgeKind::Badge {
+			badge_id: BadgeId::new(),
+		};
+		let paint = EntitlementEdgeKind::Paint {
+			paint_id: PaintId::new(),
+		};
+
+		let product = ProductGrantKey::Product(product_id.clone());
+		let subscription_product = ProductGrantKey::SubscriptionProduct {
+			id: SubscriptionProductId::new(),
+			benefits: benefits.to_vec(),
+		};
+		let empty_subscription_product = ProductGrantKey::SubscriptionProduct {
+			id: SubscriptionProductId::new(),
+			benefits: vec![],
+		};
+
+		let keys = HashSet::from([
+			product.clone(),
+			subscription_product.clone(),
+			empty_subscription_product.clone(),
+		]);
+
+		let edges = vec![
+			edge(EntitlementEdgeKind::Product { product_id }, badge.clone()),
+			// both benefits grant the badge, it is only granted once
+			edge(
+				EntitlementEdgeKind::SubscriptionBenefit {
+					subscription_benefit_id: benefits[0],
+				},
+				badge.clone(),
+			),
+			edge(
+				EntitlementEdgeKind::SubscriptionBenefit {
+					subscription_benefit_id: benefits[1],
+				},
+				badge.clone(),
+			),
+			edge(
+				EntitlementEdgeKind::SubscriptionBenefit {
+					subscription_benefit_id: benefits[1],
+				},
+				paint.clone(),
+			),
+		];
+
+		let grants = product_grants(&keys, edges);
+
+		assert_eq!(grants.len(), 2);
+		assert_eq!(grants[&product], vec![badge.clone()]);
+
+		let mut subscription_grants = grants[&subscription_product].clone();
+		subscription_grants.sort();
+		let mut expected = vec![badge, paint];
+		expected.sort();
+		assert_eq!(subscription_grants, expected);
+
+		assert!(!grants.contains_key(&empty_subscription_product));
+	}
+}
@@ -4,6 +4,15 @@ pub enum Direction {
 	Outbound,
 }
 
+impl Direction {
+	pub fn reverse(self) -> Self {
+		match self {
+			Self::Inbound => Self::Outbound,
+			Self::Outbound => Self::Inbound,
+		}
+	}
+}
+
 pub trait GraphKey: Send + Sync {
 	fn has_next(&self, direction: Direction) -> bool {
 		match direction {
@@ -77,9 +86,138 @@ pub trait GraphTraverse: Send + Sync {
 		}
 	}
 
+	/// Finds the shortest path from `start` to `target`, following at most
+	/// `max_depth` edges. The returned path starts with `start` and ends with
+	/// `target`, `None` is returned if there is no such path within the depth.
+	#[allow(clippy::type_complexity)]
+	fn find_path(
+		&self,
+		direction: Direction,
+		start: <Self::Edge as GraphEdge>::Key,
+		target: <Self::Edge as GraphEdge>::Key,
+		max_depth: usize,
+	) -> impl std::future::Future<Output = Result<Option<Vec<<Self::Edge as GraphEdge>::Key>>, Self::Error>> + Send
+	where
+		<Self::Edge as GraphEdge>::Key: std::hash::Hash + std::cmp::Eq + Clone,
+	{
+		async move {
+			if start == target {
+				return Ok(Some(vec![start]));
+			}
+
+			// the node each visited node was first reached from
+			let mut parents = fnv::FnvHashMap::default();
+
+			let mut next_nodes = if start.has_next(direction) {
+				vec![start.clone()]
+			} else {
+				vec![]
+			};
+
+			for _ in 0..max_depth {
+				if next_nodes.is_empty() {
+					break;
+				}
+
+				let edges = self.fetch_edges(direction, &next_nodes).await?;
+				next_nodes.clear();
+
+				for edge in &edges {
+					let Some(parent) = edge.edge_next(direction.reverse()).into_iter().next() else {
+						continue;
+					};
+
+					for node in edge.edge_next(direction) {
+						if node == start || parents.contains_key(&node) {
+							continue;
+						}
+
+						parents.insert(node.clone(), parent.clone());
+
+						if node == target {
+							let mut path = vec![node];
+							while let Some(parent) = parents.get(path.last().unwrap()) {
+								path.push(parent.clone());
+							}
+							path.reverse();
+							return Ok(Some(path));
+						}
+
+						if node.has_next(direction) {
+							next_nodes.push(node);
+						}
+					}
+				}
+			}
+
+			Ok(None)
+		}
+	}
+
 	fn fetch_edges(
 		&self,
 		direction: Direction,
 		nodes: &[<Self::Edge as GraphEdge>::Key],
 	) -> impl std::future::Future<Output = Result<Vec<Self::Edge>, Self::Error>> + Send;
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	impl GraphKey for u32 {
+		fn has_inbound(&self) -> bool {
+			true
+		}
+
+		fn has_outbound(&self) -> bool {
+			true
+		}
+	}
+
+	impl GraphEdge for (u32, u32) {
+		type Key = u32;
+
+		fn edge_next(&self, direction: Direction) -> impl IntoIterator<Item = Self::Key> + Send {
+			match direction {
+				Direction::Inbound => std::iter::once(self.0),
+				Direction::Outbound => std::iter::once(self.1),
+			}
+		}
+	}
+
+	struct Graph(Vec<(u32, u32)>);
+
+	impl GraphTraverse for Graph {
+		type Edge = (u32, u32);
+		type Error = ();
+
+		async fn fetch_edges(&self, direction: Direction, nodes: &[u32]) -> Result<Vec<Self::Edge>, Self::Error> {
+			Ok(self
+				.0
+				.iter()
+				.filter(|edge| edge.edge_next(direction.reverse()).into_iter().any(|n| nodes.contains(&n)))
+				.copied()
+				.collect())
+		}
+	}
+
+	#[test]
+	fn test_find_path() {
+		let graph = Graph(vec![(1, 2), (2, 3), (3, 4), (2, 4), (4, 2)]);
+		let find = |start, target, max_depth| {
+			futures::executor::block_on(graph.find_path(Direction::Outbound, start, target, max_depth)).unwrap()
+		};
+
+		assert_eq!(find(1, 4, 10), Some(vec![1, 2, 4]));
+		assert_eq!(find(4, 3, 10), Some(vec![4, 2, 3]));
+		assert_eq!(find(4, 1, 10), None);
+		assert_eq!(find(1, 3, 1), None);
+		assert_eq!(find(1, 1, 0), Some(vec![1]));
+
+		assert_eq!(
+			futures::executor::block_on(graph.find_path(Direction::Inbound, 3, 1, 10)).unwrap(),
+			Some(vec![3, 2, 1])
+		);
+	}
+}
@@ -3,6 +3,7 @@ use std::borrow::Borrow;
 use mongodb::action::Multiple;
 
 pub mod filter;
+pub mod projection;
 pub mod traits;
 pub mod update;
 
@@ -23,6 +24,10 @@ impl<T: Send + Sync> TypedCollection<T> {
 		self.0
 	}
 
+	pub fn namespace(&self) -> mongodb::Namespace {
+		self.0.namespace()
+	}
+
 	pub fn drop(&self) -> mongodb::action::DropCollection<'_> {
 		self.0.drop()
 	}
@@ -81,6 +86,21 @@ impl<T: Send + Sync> TypedCollection<T> {
 		self.0.find_one_and_update(filter.to_document(), update.to_document())
 	}
 
+	/// Like [`Self::find_one_and_update`] but with an aggregation pipeline as
+	/// the update, for updates which depend on the current document.
+	pub fn find_one_and_update_pipeline(
+		&self,
+		filter: impl Into<filter::Filter<T>>,
+		pipeline: impl IntoIterator<Item = bson::Document>,
+	) -> mongodb::action::FindOneAndUpdate<'_, T>
+	where
+		T: serde::de::DeserializeOwned,
+	{
+		let filter = filter.into();
+		self.0
+			.find_one_and_update(filter.to_document(), pipeline.into_iter().collect::<Vec<_>>())
+	}
+
 	pub fn find_one_and_delete(&self, filter: impl Into<filter::Filter<T>>) -> mongodb::action::FindOneAndDelete<'_, T>
 	where
 		T: serde::de::DeserializeOwned,
@@ -119,6 +139,10 @@ impl<T: Send + Sync> TypedCollection<T> {
 		self.0.delete_one(filter.to_document())
 	}
 
+	pub fn aggregate(&self, pipeline: impl IntoIterator<Item = bson::Document>) -> mongodb::action::Aggregate<'_> {
+		self.0.aggregate(pipeline)
+	}
+
 	pub fn count_documents(&self, filter: impl Into<filter::Filter<T>>) -> mongodb::action::CountDocuments<'_> {
 		let filter = filter.into();
 		self.0.count_documents(filter.to_document())
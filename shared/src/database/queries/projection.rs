@@ -0,0 +1,207 @@
+pub use macros::MongoProjection;
+use serde::de::{DeserializeOwned, Visitor};
+
+/// The fields loaded by a projection, implemented with [`MongoProjection`].
+pub trait ProjectionFields {
+	/// Adds the path of every loaded field, prefixed with the path of the
+	/// document the projection is nested in.
+	fn fields(prefix: &str, projection: &mut bson::Document);
+}
+
+/// A type which can be loaded from a subset of the fields of a `T` document.
+///
+/// The projection is built from the serialized field names of the type, so
+/// renames such as `#[serde(rename = "_id")]` are respected. Fields marked
+/// with `#[mongo(nested)]` only load the fields of their own projection, and
+/// `#[serde(flatten)]` fields load the fields of their projection at the same
+/// level.
+pub trait Projection<T>: ProjectionFields + DeserializeOwned {
+	/// Adds the path of every loaded field which is not a serialized field of
+	/// `T`, see [`serde_fields`].
+	fn unknown_fields(prefix: &str, unknown: &mut Vec<String>);
+
+	fn projection() -> bson::Document {
+		#[cfg(debug_assertions)]
+		{
+			let mut unknown = Vec::new();
+			Self::unknown_fields("", &mut unknown);
+			debug_assert!(unknown.is_empty(), "projected fields missing on the collection: {unknown:?}");
+		}
+
+		let mut projection = bson::Document::new();
+		Self::fields("", &mut projection);
+		projection
+	}
+}
+
+impl<P: ProjectionFields> ProjectionFields for Option<P> {
+	fn fields(prefix: &str, projection: &mut bson::Document) {
+		P::fields(prefix, projection);
+	}
+}
+
+impl<T, P: Projection<T>> Projection<Option<T>> for Option<P> {
+	fn unknown_fields(prefix: &str, unknown: &mut Vec<String>) {
+		P::unknown_fields(prefix, unknown);
+	}
+}
+
+impl<P: ProjectionFields> ProjectionFields for Vec<P> {
+	fn fields(prefix: &str, projection: &mut bson::Document) {
+		P::fields(prefix, projection);
+	}
+}
+
+impl<T, P: Projection<T>> Projection<Vec<T>> for Vec<P> {
+	fn unknown_fields(prefix: &str, unknown: &mut Vec<String>) {
+		P::unknown_fields(prefix, unknown);
+	}
+}
+
+/// The serialized field names of `T`, which are the fields its `Deserialize`
+/// impl asks for, so renames are applied. `None` if `T` is not deserialized as
+/// a struct, such as structs with flattened fields.
+pub fn serde_fields<T: DeserializeOwned>() -> Option<&'static [&'static str]> {
+	let mut fields = None;
+	let _ = T::deserialize(FieldNames(&mut fields));
+	fields
+}
+
+/// A deserializer which only records the field names of the struct that is
+/// deserialized and then fails.
+struct FieldNames<'a>(&'a mut Option<&'static [&'static str]>);
+
+impl<'de> serde::Deserializer<'de> for FieldNames<'_> {
+	type Error = serde::de::value::Error;
+
+	serde::forward_to_deserialize_any! {
+		bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf option unit unit_struct
+		newtype_struct seq tuple tuple_struct map enum identifier ignored_any
+	}
+
+	fn deserialize_any<V: Visitor<'de>>(self, _: V) -> Result<V::Value, Self::Error> {
+		Err(serde::de::Error::custom("expected a struct"))
+	}
+
+	fn deserialize_struct<V: Visitor<'de>>(
+		self,
+		_: &'static str,
+		fields: &'static [&'static str],
+		_: V,
+	) -> Result<V::Value, Self::Error> {
+		*self.0 = Some(fields);
+		Err(serde::de::Error::custom("only the field names are read"))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[allow(dead_code)]
+	#[derive(serde::Deserialize)]
+	struct Collection {
+		#[serde(rename = "_id")]
+		id: u32,
+		name: String,
+		nested: Option<Nested>,
+		items: Vec<Nested>,
+	}
+
+	#[allow(dead_code)]
+	#[derive(serde::Deserialize)]
+	struct FlattenedCollection {
+		#[serde(rename = "_id")]
+		id: u32,
+		#[serde(flatten)]
+		flattened: Nested,
+	}
+
+	#[allow(dead_code)]
+	#[derive(serde::Deserialize)]
+	struct Nested {
+		a: u32,
+		b: u32,
+	}
+
+	#[allow(dead_code)]
+	#[derive(serde::Deserialize, MongoProjection)]
+	#[mongo(collection = "Nested")]
+	struct NestedA {
+		a: u32,
+	}
+
+	#[allow(dead_code)]
+	#[derive(serde::Deserialize, MongoProjection)]
+	#[mongo(collection = "Collection")]
+	struct CollectionProjection {
+		#[serde(rename = "_id")]
+		id: u32,
+		#[mongo(nested)]
+		nested: Option<NestedA>,
+		#[mongo(nested)]
+		items: Vec<NestedA>,
+	}
+
+	#[allow(dead_code)]
+	#[derive(serde::Deserialize, MongoProjection)]
+	#[mongo(collection = "FlattenedCollection")]
+	struct FlattenedProjection {
+		#[serde(rename = "_id")]
+		id: u32,
+		#[serde(flatten)]
+		flattened: NestedA,
+	}
+
+	/// Renamed to a key which does not exist on the collection.
+	#[allow(dead_code)]
+	#[derive(serde::Deserialize, MongoProjection)]
+	#[mongo(collection = "Collection")]
+	struct RenamedProjection {
+		id: u32,
+		#[mongo(nested)]
+		#[serde(rename = "child")]
+		nested: Option<NestedB>,
+	}
+
+	#[allow(dead_code)]
+	#[derive(serde::Deserialize, MongoProjection)]
+	#[mongo(collection = "Nested")]
+	struct NestedB {
+		#[serde(rename = "c")]
+		b: u32,
+	}
+
+	#[test]
+	fn test_projection() {
+		assert_eq!(
+			<CollectionProjection as Projection<Collection>>::projection(),
+			bson::doc! {
+				"_id": 1,
+				"nested.a": 1,
+				"items.a": 1,
+			}
+		);
+
+		assert_eq!(
+			<FlattenedProjection as Projection<FlattenedCollection>>::projection(),
+			bson::doc! {
+				"_id": 1,
+				"a": 1,
+			}
+		);
+	}
+
+	#[test]
+	fn test_serde_fields() {
+		assert_eq!(serde_fields::<Collection>(), Some(&["_id", "name", "nested", "items"][..]));
+		assert_eq!(serde_fields::<FlattenedCollection>(), None);
+	}
+
+	#[test]
+	fn test_unknown_fields() {
+		let mut unknown = Vec::new();
+		<RenamedProjection as Projection<Collection>>::unknown_fields("", &mut unknown);
+		assert_eq!(unknown, ["id", "child", "child.c"]);
+	}
+}
@@ -6,6 +6,8 @@ use crate::database::{Id, MongoCollection};
 pub enum CronJobId {
 	EmoteScoresUpdate = 0,
 	SubscriptionRefresh = 1,
+	EntitlementExpiry = 2,
+	StaleImageReprocess = 3,
 }
 
 impl From<CronJobId> for bson::Bson {
@@ -89,5 +91,38 @@ pub fn default_cron_jobs() -> Vec<CronJob> {
 			updated_at: chrono::Utc::now(),
 			search_updated_at: None,
 		},
+		CronJob {
+			id: CronJobId::EntitlementExpiry,
+			name: "Entitlement Expiry".to_string(),
+			description: Some(
+				"Automatically removes expired entitlements so that time limited grants are taken away from users."
+					.to_string(),
+			),
+			tags: vec!["entitlement".to_string()],
+			last_run: None,
+			next_run: chrono::Utc::now(),
+			interval: CronJobInterval::Hours(1),
+			enabled: true,
+			currently_running_by: None,
+			held_until: chrono::Utc::now(),
+			updated_at: chrono::Utc::now(),
+			search_updated_at: None,
+		},
+		CronJob {
+			id: CronJobId::StaleImageReprocess,
+			name: "Stale Image Reprocess".to_string(),
+			description: Some(
+				"Automatically submits images which are stuck pending to the image processor again.".to_string(),
+			),
+			tags: vec!["image".to_string()],
+			last_run: None,
+			next_run: chrono::Utc::now(),
+			interval: CronJobInterval::Hours(1),
+			enabled: true,
+			currently_running_by: None,
+			held_until: chrono::Utc::now(),
+			updated_at: chrono::Utc::now(),
+			search_updated_at: None,
+		},
 	]
 }
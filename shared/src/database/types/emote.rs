@@ -13,6 +13,7 @@ pub type EmoteId = Id<Emote>;
 #[mongo(index(fields("merged.target_id" = 1)))]
 #[mongo(index(fields("merged.at" = 1)))]
 #[mongo(index(fields(search_updated_at = 1)))]
+#[mongo(index(fields("image_set.input.type" = 1)))]
 #[mongo(search = "crate::typesense::types::emote::Emote")]
 #[serde(deny_unknown_fields)]
 pub struct Emote {
@@ -29,6 +30,8 @@ pub struct Emote {
 	pub merged: Option<EmoteMerged>,
 	pub scores: EmoteScores,
 	pub deleted: bool,
+	/// Set when the emote was deleted because its image failed to process
+	pub processing_error: Option<EmoteProcessingError>,
 	#[serde(with = "crate::database::serde")]
 	pub updated_at: chrono::DateTime<chrono::Utc>,
 	#[serde(with = "crate::database::serde")]
@@ -55,6 +58,30 @@ pub struct EmoteMerged {
 	pub at: chrono::DateTime<chrono::Utc>,
 }
 
+#[derive(Debug, Clone, Default, serde::Deserialize, serde::Serialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct EmoteProcessingError {
+	/// The image processor error code
+	pub code: Option<i32>,
+	/// The image processor error message, which is not meant for users
+	pub message: Option<String>,
+}
+
+impl EmoteProcessingError {
+	/// A message explaining to the uploader why their emote failed to process.
+	pub fn reason(&self) -> &'static str {
+		use image_processor_proto::ErrorCode;
+
+		match self.code.and_then(|code| ErrorCode::try_from(code).ok()) {
+			Some(ErrorCode::Decode) => "The file could not be read, its format is not supported or it is corrupted",
+			Some(ErrorCode::InvalidInput) => "The image is too large, has too many frames or an unsupported aspect ratio",
+			Some(ErrorCode::Resize) => "The image could not be resized",
+			Some(ErrorCode::Encode) => "The image could not be converted to the output formats",
+			_ => "The image could not be processed, please try uploading it again",
+		}
+	}
+}
+
 #[bitmask(i32)]
 pub enum EmoteFlags {
 	PublicListed = 1 << 0,
@@ -108,3 +135,25 @@ pub struct EmoteAttribution {
 pub(super) fn mongo_collections() -> impl IntoIterator<Item = MongoGenericCollection> {
 	[MongoGenericCollection::new::<Emote>()]
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_processing_error_reason() {
+		let error = |code: image_processor_proto::ErrorCode| EmoteProcessingError {
+			code: Some(code as i32),
+			message: None,
+		};
+
+		assert_ne!(
+			error(image_processor_proto::ErrorCode::Decode).reason(),
+			error(image_processor_proto::ErrorCode::InvalidInput).reason()
+		);
+		assert_eq!(
+			error(image_processor_proto::ErrorCode::Internal).reason(),
+			EmoteProcessingError::default().reason()
+		);
+	}
+}
@@ -176,11 +176,17 @@ pub struct EntitlementEdgeId {
 #[mongo(index(fields("_id.from" = 1, "_id.to" = 1)))]
 #[mongo(index(fields("_id.to" = 1, "_id.from" = 1)))]
 #[mongo(index(fields("_id.managed_by" = 1)))]
+#[mongo(index(fields(expires_at = 1), sparse))]
 #[serde(deny_unknown_fields)]
 pub struct EntitlementEdge {
 	#[mongo(id)]
 	#[serde(rename = "_id")]
 	pub id: EntitlementEdgeId,
+	/// The time after which this edge no longer grants anything, edges past
+	/// this time are removed by the entitlement expiry cron job
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	#[serde(with = "crate::database::serde")]
+	pub expires_at: Option<chrono::DateTime<chrono::Utc>>,
 }
 
 impl GraphEdge for EntitlementEdge {
@@ -227,8 +233,18 @@ impl EntitlementEdge {
 	pub fn new(from: EntitlementEdgeKind, to: EntitlementEdgeKind, managed_by: Option<EntitlementEdgeManagedBy>) -> Self {
 		Self {
 			id: EntitlementEdgeId { from, to, managed_by },
+			expires_at: None,
 		}
 	}
+
+	pub fn with_expiry(mut self, expires_at: chrono::DateTime<chrono::Utc>) -> Self {
+		self.expires_at = Some(expires_at);
+		self
+	}
+
+	pub fn is_expired(&self) -> bool {
+		self.expires_at.is_some_and(|expires_at| expires_at <= chrono::Utc::now())
+	}
 }
 
 pub(super) fn mongo_collections() -> impl IntoIterator<Item = MongoGenericCollection> {
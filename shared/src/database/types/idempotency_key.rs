@@ -0,0 +1,45 @@
+use super::MongoGenericCollection;
+use crate::database::emote::EmoteId;
+use crate::database::user::profile_picture::UserProfilePictureId;
+use crate::database::user::UserId;
+use crate::database::MongoCollection;
+
+/// Idempotency keys are scoped to the user which sent them so that keys of
+/// different users can never collide.
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize, Hash, PartialEq, Eq)]
+pub struct IdempotencyKeyId {
+	pub user_id: UserId,
+	pub key: String,
+}
+
+impl std::fmt::Display for IdempotencyKeyId {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "{}:{}", self.user_id, self.key)
+	}
+}
+
+/// Remembers the result of an upload so that a retried request with the same
+/// key returns it instead of processing the upload again.
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize, MongoCollection)]
+#[mongo(collection_name = "idempotency_keys")]
+#[mongo(index(fields(expires_at = 1), expire_after = 0))]
+#[serde(deny_unknown_fields)]
+pub struct IdempotencyKey {
+	#[mongo(id)]
+	#[serde(rename = "_id")]
+	pub id: IdempotencyKeyId,
+	pub result: IdempotentResult,
+	#[serde(with = "crate::database::serde")]
+	pub expires_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Debug, Clone, Copy, serde::Deserialize, serde::Serialize, PartialEq, Eq)]
+#[serde(tag = "kind", content = "id", rename_all = "snake_case")]
+pub enum IdempotentResult {
+	Emote(EmoteId),
+	ProfilePicture(UserProfilePictureId),
+}
+
+pub(super) fn mongo_collections() -> impl IntoIterator<Item = MongoGenericCollection> {
+	[MongoGenericCollection::new::<IdempotencyKey>()]
+}
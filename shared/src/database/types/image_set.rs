@@ -14,6 +14,10 @@ pub enum ImageSetInput {
 		path: String,
 		mime: String,
 		size: i64,
+		/// How often the image was submitted to the image processor again
+		/// because it stayed pending for too long
+		#[serde(default)]
+		resubmits: u32,
 	},
 	Image(Image),
 }
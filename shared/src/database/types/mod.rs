@@ -6,6 +6,7 @@ pub mod emote_moderation_request;
 pub mod emote_set;
 pub mod entitlement;
 pub mod global;
+pub mod idempotency_key;
 pub mod image_set;
 pub mod paint;
 pub mod product;
@@ -93,6 +94,7 @@ fn mongo_collections() -> impl IntoIterator<Item = MongoGenericCollection> {
 		.chain(emote_moderation_request::mongo_collections())
 		.chain(webhook_event::mongo_collections())
 		.chain(cron_job::mongo_collections())
+		.chain(idempotency_key::mongo_collections())
 }
 
 pub(super) async fn init_mongo(db: &mongodb::Database) -> anyhow::Result<()> {
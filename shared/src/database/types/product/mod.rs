@@ -200,6 +200,15 @@ pub struct Product {
 	pub search_updated_at: Option<chrono::DateTime<chrono::Utc>>,
 }
 
+impl Product {
+	/// The price in the given currency, falls back to the default currency if
+	/// there is no price in the given currency. Returns `None` if there is no
+	/// price in either currency.
+	pub fn price_in(&self, currency: stripe::Currency) -> Option<(stripe::Currency, i64)> {
+		price_in(&self.currency_prices, currency, self.default_currency)
+	}
+}
+
 pub type SubscriptionProductId = Id<SubscriptionProduct>;
 
 /// There are only two kinds of subscriptions: monthly and yearly.
@@ -220,12 +229,25 @@ pub struct SubscriptionProduct {
 	pub description: Option<String>,
 	pub default_currency: stripe::Currency,
 	pub benefits: Vec<SubscriptionBenefit>,
+	/// The capacity of the personal emote set given to subscribers, if not
+	/// set the default capacity is used
+	#[serde(default)]
+	pub personal_emote_set_capacity: Option<i32>,
 	#[serde(with = "crate::database::serde")]
 	pub updated_at: chrono::DateTime<chrono::Utc>,
 	#[serde(with = "crate::database::serde")]
 	pub search_updated_at: Option<chrono::DateTime<chrono::Utc>>,
 }
 
+impl SubscriptionProduct {
+	pub const DEFAULT_PERSONAL_EMOTE_SET_CAPACITY: i32 = 5;
+
+	pub fn personal_emote_set_capacity(&self) -> i32 {
+		self.personal_emote_set_capacity
+			.unwrap_or(Self::DEFAULT_PERSONAL_EMOTE_SET_CAPACITY)
+	}
+}
+
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct SubscriptionProductVariant {
 	pub id: ProductId,
@@ -235,6 +257,29 @@ pub struct SubscriptionProductVariant {
 	pub currency_prices: HashMap<stripe::Currency, i64>,
 }
 
+impl SubscriptionProductVariant {
+	/// The price in the given currency, falls back to the default currency of
+	/// the subscription product if there is no price in the given currency.
+	/// Returns `None` if there is no price in either currency.
+	pub fn price_in(
+		&self,
+		currency: stripe::Currency,
+		default_currency: stripe::Currency,
+	) -> Option<(stripe::Currency, i64)> {
+		price_in(&self.currency_prices, currency, default_currency)
+	}
+}
+
+fn price_in(
+	currency_prices: &HashMap<stripe::Currency, i64>,
+	currency: stripe::Currency,
+	default_currency: stripe::Currency,
+) -> Option<(stripe::Currency, i64)> {
+	[currency, default_currency]
+		.into_iter()
+		.find_map(|c| currency_prices.get(&c).map(|price| (c, *price)))
+}
+
 pub type SubscriptionBenefitId = Id<SubscriptionBenefit>;
 
 // The `SubscriptionBenefitId` can have entitlements attached via the
@@ -285,3 +330,29 @@ pub(super) fn mongo_collections() -> impl IntoIterator<Item = MongoGenericCollec
 	.chain(subscription::collections())
 	.chain(special_event::mongo_collections())
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_variant_price_in() {
+		let variant = SubscriptionProductVariant {
+			id: "price_test".parse().unwrap(),
+			paypal_id: None,
+			active: true,
+			kind: SubscriptionProductKind::Monthly,
+			currency_prices: [(stripe::Currency::USD, 499), (stripe::Currency::EUR, 449)].into(),
+		};
+
+		assert_eq!(
+			variant.price_in(stripe::Currency::EUR, stripe::Currency::USD),
+			Some((stripe::Currency::EUR, 449))
+		);
+		assert_eq!(
+			variant.price_in(stripe::Currency::GBP, stripe::Currency::USD),
+			Some((stripe::Currency::USD, 499))
+		);
+		assert_eq!(variant.price_in(stripe::Currency::GBP, stripe::Currency::CAD), None);
+	}
+}
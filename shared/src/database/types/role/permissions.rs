@@ -4,6 +4,8 @@ use std::ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign, Not};
 use bitmask_enum::bitmask;
 use quick_impl::QuickImpl;
 
+use crate::database::emote_set::EmoteSetKind;
+
 pub trait BitMask:
 	BitAnd<Output = Self>
 	+ BitOr<Output = Self>
@@ -78,6 +80,14 @@ impl<T: BitMask> AllowDeny<T> {
 	}
 }
 
+impl<T: BitMask<Bits = i32>> AllowDeny<T> {
+	/// Every single bit which is either allowed or denied.
+	pub fn bits(&self) -> impl Iterator<Item = T> {
+		let bits = (self.allow | self.deny).bits();
+		(0..i32::BITS).map(|i| 1 << i).filter(move |bit| bits & bit != 0).map(T::from)
+	}
+}
+
 impl<T: BitMask + PartialOrd> PartialOrd for AllowDeny<T> {
 	fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
 		self.permission().partial_cmp(&other.permission())
@@ -466,6 +476,8 @@ pub enum RateLimitResource {
 	EgVaultRedeem,
 	EgVaultPaymentMethod,
 	UserPresenceWrite,
+	UserRecomputeEntitlements,
+	UserAssignRole,
 	Global,
 }
 
@@ -486,6 +498,8 @@ impl RateLimitResource {
 			Self::EgVaultRedeem => "egvault_redeem",
 			Self::EgVaultPaymentMethod => "egvault_payment_method",
 			Self::UserPresenceWrite => "user_presence_write",
+			Self::UserRecomputeEntitlements => "user_recompute_entitlements",
+			Self::UserAssignRole => "user_assign_role",
 			Self::Global => "global",
 		}
 	}
@@ -678,6 +692,59 @@ impl Permissions {
 		self.ratelimits.get(resource.as_str())?.as_ref()
 	}
 
+	/// Explains why a permission is or isn't granted by these permissions.
+	pub fn explain(&self, permission: impl Into<Permission>) -> PermissionExplanation {
+		let permission = permission.into();
+
+		let decision = match permission {
+			Permission::Emote(perm) => explain_bits(self.is_admin(), &self.emote, perm, Some(EmotePermission::Admin)),
+			Permission::Role(perm) => explain_bits(self.is_admin(), &self.role, perm, Some(RolePermission::Admin)),
+			Permission::EmoteSet(perm) => {
+				explain_bits(self.is_admin(), &self.emote_set, perm, Some(EmoteSetPermission::Admin))
+			}
+			Permission::Badge(perm) => explain_bits(self.is_admin(), &self.badge, perm, Some(BadgePermission::Admin)),
+			Permission::Paint(perm) => explain_bits(self.is_admin(), &self.paint, perm, Some(PaintPermission::Admin)),
+			Permission::User(perm) => explain_bits(self.is_admin(), &self.user, perm, Some(UserPermission::Admin)),
+			Permission::Ticket(perm) => explain_bits(self.is_admin(), &self.ticket, perm, Some(TicketPermission::Admin)),
+			Permission::EmoteModerationRequest(perm) => explain_bits(
+				self.is_admin(),
+				&self.emote_moderation_request,
+				perm,
+				Some(EmoteModerationRequestPermission::Admin),
+			),
+			Permission::Admin(perm) if perm == AdminPermission::SuperAdmin => explain_bits(false, &self.admin, perm, None),
+			Permission::Admin(perm) => explain_bits(
+				false,
+				&self.admin,
+				perm,
+				Some(AdminPermission::Admin | AdminPermission::SuperAdmin),
+			),
+			Permission::Flags(perm) => explain_bits(false, &self.flags, perm, None),
+		};
+
+		PermissionExplanation {
+			permission,
+			allowed: decision.is_allowed(),
+			decision,
+		}
+	}
+
+	/// Every single permission bit which is either allowed or denied.
+	pub fn bits(&self) -> Vec<Permission> {
+		std::iter::empty()
+			.chain(self.emote.bits().map(Permission::Emote))
+			.chain(self.role.bits().map(Permission::Role))
+			.chain(self.emote_set.bits().map(Permission::EmoteSet))
+			.chain(self.badge.bits().map(Permission::Badge))
+			.chain(self.paint.bits().map(Permission::Paint))
+			.chain(self.user.bits().map(Permission::User))
+			.chain(self.ticket.bits().map(Permission::Ticket))
+			.chain(self.emote_moderation_request.bits().map(Permission::EmoteModerationRequest))
+			.chain(self.admin.bits().map(Permission::Admin))
+			.chain(self.flags.bits().map(Permission::Flags))
+			.collect()
+	}
+
 	pub fn is_superset_of(&self, other: &Self) -> bool {
 		self.is_super_admin() || {
 			self.has(other.badge.allow)
@@ -694,6 +761,55 @@ impl Permissions {
 	}
 }
 
+fn explain_bits<T: BitMask>(
+	global_admin: bool,
+	permissions: &AllowDeny<T>,
+	permission: T,
+	category_admin: Option<T>,
+) -> PermissionDecision {
+	let allowed = permissions.permission();
+
+	if global_admin {
+		PermissionDecision::GlobalAdmin
+	} else if category_admin.is_some_and(|admin| allowed & admin != T::default()) {
+		PermissionDecision::CategoryAdmin
+	} else if allowed & permission == permission {
+		PermissionDecision::Allowed
+	} else if permissions.deny & permission != T::default() {
+		PermissionDecision::Denied
+	} else {
+		PermissionDecision::Unset
+	}
+}
+
+/// What decided whether a permission is granted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PermissionDecision {
+	/// Granted by the global admin permission
+	GlobalAdmin,
+	/// Granted by the admin permission of its category
+	CategoryAdmin,
+	/// Explicitly allowed
+	Allowed,
+	/// Explicitly denied
+	Denied,
+	/// Neither allowed nor denied
+	Unset,
+}
+
+impl PermissionDecision {
+	pub fn is_allowed(self) -> bool {
+		matches!(self, Self::GlobalAdmin | Self::CategoryAdmin | Self::Allowed)
+	}
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct PermissionExplanation {
+	pub permission: Permission,
+	pub allowed: bool,
+	pub decision: PermissionDecision,
+}
+
 impl FromIterator<Permissions> for Permissions {
 	fn from_iter<I: IntoIterator<Item = Permissions>>(iter: I) -> Self {
 		let mut permissions = Self::default();
@@ -754,6 +870,38 @@ pub enum Permission {
 	Flags(FlagPermission),
 }
 
+impl Permission {
+	pub fn category(&self) -> &'static str {
+		match self {
+			Self::Emote(_) => "emote",
+			Self::Role(_) => "role",
+			Self::EmoteSet(_) => "emote_set",
+			Self::Badge(_) => "badge",
+			Self::Paint(_) => "paint",
+			Self::User(_) => "user",
+			Self::Ticket(_) => "ticket",
+			Self::EmoteModerationRequest(_) => "emote_moderation_request",
+			Self::Admin(_) => "admin",
+			Self::Flags(_) => "flags",
+		}
+	}
+
+	pub fn bits(&self) -> i32 {
+		match self {
+			Self::Emote(perm) => perm.bits(),
+			Self::Role(perm) => perm.bits(),
+			Self::EmoteSet(perm) => perm.bits(),
+			Self::Badge(perm) => perm.bits(),
+			Self::Paint(perm) => perm.bits(),
+			Self::User(perm) => perm.bits(),
+			Self::Ticket(perm) => perm.bits(),
+			Self::EmoteModerationRequest(perm) => perm.bits(),
+			Self::Admin(perm) => perm.bits(),
+			Self::Flags(perm) => perm.bits(),
+		}
+	}
+}
+
 pub trait PermissionsExt {
 	fn has(&self, permission: impl Into<Permission>) -> bool;
 
@@ -807,3 +955,385 @@ impl PermissionsExt for Permissions {
 		}
 	}
 }
+
+/// A bitmask whose flags have stable names, used by the named permission
+/// format.
+pub trait NamedBitMask: BitMask + 'static {
+	const CATEGORY: &'static str;
+	const NAMES: &'static [(&'static str, Self)];
+
+	fn to_names(&self) -> Vec<String> {
+		Self::NAMES
+			.iter()
+			.filter(|(_, flag)| *self & *flag == *flag)
+			.map(|(name, _)| name.to_string())
+			.collect()
+	}
+
+	fn from_names(names: &[String]) -> Result<Self, UnknownPermissionName> {
+		names.iter().try_fold(Self::default(), |acc, name| {
+			let (_, flag) = Self::NAMES
+				.iter()
+				.find(|(n, _)| n == name)
+				.ok_or_else(|| UnknownPermissionName {
+					category: Self::CATEGORY,
+					name: name.clone(),
+				})?;
+
+			Ok(acc | *flag)
+		})
+	}
+}
+
+#[derive(thiserror::Error, Debug, Clone)]
+#[error("unknown {category} permission: {name}")]
+pub struct UnknownPermissionName {
+	pub category: &'static str,
+	pub name: String,
+}
+
+macro_rules! named_bitmask {
+	($ty:ident, $category:literal, [$($name:ident),* $(,)?]) => {
+		impl NamedBitMask for $ty {
+			const CATEGORY: &'static str = $category;
+			const NAMES: &'static [(&'static str, Self)] = &[$((stringify!($name), Self::$name)),*];
+		}
+	};
+}
+
+named_bitmask!(
+	EmotePermission,
+	"emote",
+	[Admin, Upload, Delete, Edit, ManageAny, Merge, ViewUnlisted]
+);
+named_bitmask!(RolePermission, "role", [Admin, Manage, Assign]);
+named_bitmask!(
+	EmoteSetPermission,
+	"emote_set",
+	[Admin, Manage, ManageAny, Resize, ManageGlobal, ManageSpecial, Assign]
+);
+named_bitmask!(BadgePermission, "badge", [Admin, Manage, Assign]);
+named_bitmask!(PaintPermission, "paint", [Admin, Manage, Assign]);
+named_bitmask!(FlagPermission, "flags", [Hidden, InstantInvite]);
+named_bitmask!(
+	UserPermission,
+	"user",
+	[
+		Admin,
+		Login,
+		InviteEditors,
+		UseCustomProfilePicture,
+		UsePersonalEmoteSet,
+		UseBadge,
+		UsePaint,
+		ManageAny,
+		Billing,
+		ManageBilling,
+		Moderate,
+		ViewHidden,
+		ManageSessions,
+	]
+);
+named_bitmask!(
+	TicketPermission,
+	"ticket",
+	[Admin, Create, ManageAbuse, ManageBilling, ManageGeneric, Message]
+);
+named_bitmask!(EmoteModerationRequestPermission, "emote_moderation_request", [Admin, Manage]);
+named_bitmask!(
+	AdminPermission,
+	"admin",
+	[Admin, SuperAdmin, BypassRateLimit, ManageRedeemCodes, ManageEntitlements]
+);
+
+/// [`AllowDeny`] with every flag serialized by name instead of as a bitmask.
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone, Default, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct NamedAllowDeny {
+	#[serde(skip_serializing_if = "Vec::is_empty")]
+	#[serde(default)]
+	pub allow: Vec<String>,
+	#[serde(skip_serializing_if = "Vec::is_empty")]
+	#[serde(default)]
+	pub deny: Vec<String>,
+}
+
+impl NamedAllowDeny {
+	pub fn is_empty(&self) -> bool {
+		self.allow.is_empty() && self.deny.is_empty()
+	}
+}
+
+impl<T: NamedBitMask> AllowDeny<T> {
+	pub fn to_named(&self) -> NamedAllowDeny {
+		NamedAllowDeny {
+			allow: self.allow.to_names(),
+			deny: self.deny.to_names(),
+		}
+	}
+
+	pub fn from_named(named: &NamedAllowDeny) -> Result<Self, UnknownPermissionName> {
+		Ok(Self {
+			allow: T::from_names(&named.allow)?,
+			deny: T::from_names(&named.deny)?,
+		})
+	}
+}
+
+/// [`Permissions`] with every flag serialized by name, used to export and
+/// import roles. The numeric format remains the one stored in the database.
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone, Default, PartialEq, Eq)]
+pub struct NamedPermissions {
+	#[serde(skip_serializing_if = "NamedAllowDeny::is_empty")]
+	#[serde(default)]
+	pub emote: NamedAllowDeny,
+	#[serde(skip_serializing_if = "NamedAllowDeny::is_empty")]
+	#[serde(default)]
+	pub role: NamedAllowDeny,
+	#[serde(skip_serializing_if = "NamedAllowDeny::is_empty")]
+	#[serde(default)]
+	pub emote_set: NamedAllowDeny,
+	#[serde(skip_serializing_if = "NamedAllowDeny::is_empty")]
+	#[serde(default)]
+	pub badge: NamedAllowDeny,
+	#[serde(skip_serializing_if = "NamedAllowDeny::is_empty")]
+	#[serde(default)]
+	pub paint: NamedAllowDeny,
+	#[serde(skip_serializing_if = "NamedAllowDeny::is_empty")]
+	#[serde(default)]
+	pub user: NamedAllowDeny,
+	#[serde(skip_serializing_if = "NamedAllowDeny::is_empty")]
+	#[serde(default)]
+	pub ticket: NamedAllowDeny,
+	#[serde(skip_serializing_if = "NamedAllowDeny::is_empty")]
+	#[serde(default)]
+	pub emote_moderation_request: NamedAllowDeny,
+	#[serde(skip_serializing_if = "NamedAllowDeny::is_empty")]
+	#[serde(default)]
+	pub admin: NamedAllowDeny,
+	#[serde(skip_serializing_if = "NamedAllowDeny::is_empty")]
+	#[serde(default)]
+	pub flags: NamedAllowDeny,
+
+	#[serde(skip_serializing_if = "Option::is_none")]
+	#[serde(default)]
+	pub emote_moderation_request_priority: Option<i32>,
+
+	#[serde(skip_serializing_if = "Option::is_none")]
+	#[serde(default)]
+	pub emote_moderation_request_limit: Option<i32>,
+
+	#[serde(skip_serializing_if = "Option::is_none")]
+	#[serde(default)]
+	pub emote_set_limit: Option<i32>,
+
+	#[serde(skip_serializing_if = "Option::is_none")]
+	#[serde(default)]
+	pub emote_set_capacity: Option<i32>,
+
+	#[serde(skip_serializing_if = "Option::is_none")]
+	#[serde(default)]
+	pub personal_emote_set_capacity: Option<i32>,
+
+	#[serde(skip_serializing_if = "HashMap::is_empty")]
+	#[serde(default)]
+	pub ratelimits: HashMap<String, Option<RateLimits>>,
+
+	#[serde(flatten)]
+	pub unknown: HashMap<String, serde_json::Value>,
+}
+
+impl Permissions {
+	pub fn to_named(&self) -> NamedPermissions {
+		NamedPermissions {
+			emote: self.emote.to_named(),
+			role: self.role.to_named(),
+			emote_set: self.emote_set.to_named(),
+			badge: self.badge.to_named(),
+			paint: self.paint.to_named(),
+			user: self.user.to_named(),
+			ticket: self.ticket.to_named(),
+			emote_moderation_request: self.emote_moderation_request.to_named(),
+			admin: self.admin.to_named(),
+			flags: self.flags.to_named(),
+			emote_moderation_request_priority: self.emote_moderation_request_priority,
+			emote_moderation_request_limit: self.emote_moderation_request_limit,
+			emote_set_limit: self.emote_set_limit,
+			emote_set_capacity: self.emote_set_capacity,
+			personal_emote_set_capacity: self.personal_emote_set_capacity,
+			ratelimits: self.ratelimits.clone(),
+			unknown: self.unknown.clone(),
+		}
+	}
+
+	pub fn from_named(named: &NamedPermissions) -> Result<Self, UnknownPermissionName> {
+		Ok(Self {
+			emote: AllowDeny::from_named(&named.emote)?,
+			role: AllowDeny::from_named(&named.role)?,
+			emote_set: AllowDeny::from_named(&named.emote_set)?,
+			badge: AllowDeny::from_named(&named.badge)?,
+			paint: AllowDeny::from_named(&named.paint)?,
+			user: AllowDeny::from_named(&named.user)?,
+			ticket: AllowDeny::from_named(&named.ticket)?,
+			emote_moderation_request: AllowDeny::from_named(&named.emote_moderation_request)?,
+			admin: AllowDeny::from_named(&named.admin)?,
+			flags: AllowDeny::from_named(&named.flags)?,
+			emote_moderation_request_priority: named.emote_moderation_request_priority,
+			emote_moderation_request_limit: named.emote_moderation_request_limit,
+			emote_set_limit: named.emote_set_limit,
+			emote_set_capacity: named.emote_set_capacity,
+			personal_emote_set_capacity: named.personal_emote_set_capacity,
+			ratelimits: named.ratelimits.clone(),
+			unknown: named.unknown.clone(),
+		})
+	}
+}
+
+/// The maximum capacity of an emote set of the given kind owned by a user with
+/// these permissions. Personal emote sets use the personal emote set capacity,
+/// all other kinds the normal one. A missing or negative capacity is treated
+/// as 0.
+pub fn effective_emote_set_capacity(permissions: &Permissions, kind: &EmoteSetKind) -> i32 {
+	let capacity = match kind {
+		EmoteSetKind::Personal => permissions.personal_emote_set_capacity,
+		_ => permissions.emote_set_capacity,
+	};
+
+	capacity.unwrap_or_default().max(0)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_explain_decisions() {
+		let mut permissions = Permissions::default();
+		permissions.allow(EmotePermission::Upload);
+		permissions.deny(EmotePermission::Delete);
+		permissions.allow(BadgePermission::Admin);
+
+		let explain = |perm: Permission| permissions.explain(perm).decision;
+
+		assert_eq!(explain(EmotePermission::Upload.into()), PermissionDecision::Allowed);
+		assert_eq!(explain(EmotePermission::Delete.into()), PermissionDecision::Denied);
+		assert_eq!(explain(EmotePermission::Edit.into()), PermissionDecision::Unset);
+		assert_eq!(explain(BadgePermission::Assign.into()), PermissionDecision::CategoryAdmin);
+	}
+
+	#[test]
+	fn test_explain_global_admin() {
+		let mut permissions = Permissions::default();
+		permissions.allow(AdminPermission::Admin);
+		permissions.deny(EmotePermission::Upload);
+
+		assert_eq!(
+			permissions.explain(EmotePermission::Upload).decision,
+			PermissionDecision::GlobalAdmin
+		);
+		assert_eq!(
+			permissions.explain(AdminPermission::ManageEntitlements).decision,
+			PermissionDecision::CategoryAdmin
+		);
+		// Admin does not imply super admin
+		assert_eq!(
+			permissions.explain(AdminPermission::SuperAdmin).decision,
+			PermissionDecision::Unset
+		);
+		// Flags are never granted by admin
+		assert_eq!(
+			permissions.explain(FlagPermission::Hidden).decision,
+			PermissionDecision::Unset
+		);
+	}
+
+	#[test]
+	fn test_explain_matches_has() {
+		let mut permissions = Permissions::default();
+		permissions.allow(EmotePermission::Upload | EmotePermission::Edit);
+		permissions.deny(EmotePermission::Edit);
+		permissions.allow(EmoteSetPermission::Admin);
+		permissions.allow(AdminPermission::BypassRateLimit);
+		permissions.allow(FlagPermission::Hidden);
+
+		let cases: [Permission; 8] = [
+			EmotePermission::Upload.into(),
+			EmotePermission::Edit.into(),
+			EmotePermission::Delete.into(),
+			EmoteSetPermission::Manage.into(),
+			AdminPermission::BypassRateLimit.into(),
+			AdminPermission::Admin.into(),
+			FlagPermission::Hidden.into(),
+			UserPermission::Login.into(),
+		];
+
+		for permission in cases {
+			assert_eq!(
+				permissions.explain(permission).allowed,
+				permissions.has(permission),
+				"{permission:?}"
+			);
+		}
+	}
+
+	#[test]
+	fn test_bits() {
+		let mut permissions = Permissions::default();
+		permissions.allow(EmotePermission::Upload | EmotePermission::Edit);
+		permissions.deny(UserPermission::Login);
+
+		let bits: Vec<_> = permissions.bits().into_iter().map(|p| (p.category(), p.bits())).collect();
+
+		assert_eq!(
+			bits,
+			vec![
+				("emote", EmotePermission::Upload.bits()),
+				("emote", EmotePermission::Edit.bits()),
+				("user", UserPermission::Login.bits()),
+			]
+		);
+	}
+
+	fn assert_all_named<T: NamedBitMask<Bits = i32> + std::fmt::Debug>(all_flags: T) {
+		let all_flags = all_flags.bits();
+
+		for bit in (0..i32::BITS).map(|i| 1i32 << i).filter(|bit| all_flags & bit != 0) {
+			let flag = T::from(bit);
+			assert!(
+				T::NAMES.iter().any(|(_, named)| *named == flag),
+				"{} flag {flag:?} has no name",
+				T::CATEGORY
+			);
+		}
+	}
+
+	#[test]
+	fn every_flag_has_a_name() {
+		assert_all_named(EmotePermission::all_flags());
+		assert_all_named(RolePermission::all_flags());
+		assert_all_named(EmoteSetPermission::all_flags());
+		assert_all_named(BadgePermission::all_flags());
+		assert_all_named(PaintPermission::all_flags());
+		assert_all_named(FlagPermission::all_flags());
+		assert_all_named(UserPermission::all_flags());
+		assert_all_named(TicketPermission::all_flags());
+		assert_all_named(EmoteModerationRequestPermission::all_flags());
+		assert_all_named(AdminPermission::all_flags());
+	}
+
+	#[test]
+	fn test_effective_emote_set_capacity() {
+		let mut permissions = Permissions::default();
+		assert_eq!(effective_emote_set_capacity(&permissions, &EmoteSetKind::Normal), 0);
+		assert_eq!(effective_emote_set_capacity(&permissions, &EmoteSetKind::Personal), 0);
+
+		permissions.emote_set_capacity = Some(1000);
+		permissions.personal_emote_set_capacity = Some(5);
+		assert_eq!(effective_emote_set_capacity(&permissions, &EmoteSetKind::Normal), 1000);
+		assert_eq!(effective_emote_set_capacity(&permissions, &EmoteSetKind::Personal), 5);
+		assert_eq!(effective_emote_set_capacity(&permissions, &EmoteSetKind::Global), 1000);
+
+		permissions.emote_set_capacity = Some(-1);
+		assert_eq!(effective_emote_set_capacity(&permissions, &EmoteSetKind::Normal), 0);
+	}
+}
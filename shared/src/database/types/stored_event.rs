@@ -109,9 +109,16 @@ pub enum StoredEventData {
 #[serde(tag = "kind", content = "data", rename_all = "snake_case", deny_unknown_fields)]
 pub enum ImageProcessorEvent {
 	Success,
-	Fail { code: Option<i32>, message: Option<String> },
+	Fail {
+		code: Option<i32>,
+		message: Option<String>,
+	},
 	Cancel,
 	Start,
+	/// Submitted again after staying pending for too long
+	Resubmit {
+		task_id: String,
+	},
 }
 
 impl From<event_callback::Fail> for ImageProcessorEvent {
@@ -183,12 +190,23 @@ pub enum StoredEventUserData {
 		old: Option<EmoteSetId>,
 		new: Option<EmoteSetId>,
 	},
+	ChangeActiveProfilePicture {
+		old: Option<UserProfilePictureId>,
+		new: Option<UserProfilePictureId>,
+	},
+	ChangeBiography {
+		old: String,
+		new: String,
+	},
 	AddConnection {
 		platform: Platform,
 	},
 	RemoveConnection {
 		platform: Platform,
 	},
+	UpdateConnection {
+		platform: Platform,
+	},
 	AddEntitlement {
 		target: EntitlementEdgeKind,
 	},
@@ -207,6 +225,7 @@ pub enum StoredEventUserData {
 pub enum StoredEventUserProfilePictureData {
 	Create,
 	Process { event: ImageProcessorEvent },
+	Delete,
 }
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize, PartialEq)]
@@ -46,6 +46,9 @@ pub struct User {
 	/// these users
 	pub merged_into_id: Option<UserId>,
 	pub settings: UserSettings,
+	/// The biography shown on the user's profile
+	#[serde(default)]
+	pub biography: String,
 	pub two_fa: Option<UserTwoFa>,
 	pub style: UserStyle,
 	pub connections: Vec<UserConnection>,
@@ -18,6 +18,12 @@ pub struct UserSession {
 	pub expires_at: chrono::DateTime<chrono::Utc>,
 	#[serde(with = "crate::database::serde")]
 	pub last_used_at: chrono::DateTime<chrono::Utc>,
+	/// The user agent of the client which created the session
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub user_agent: Option<String>,
+	/// A keyed hash of the ip address which created the session
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub ip_hash: Option<String>,
 	#[serde(default, skip_serializing_if = "bson::Document::is_empty")]
 	pub extensions: bson::Document,
 }
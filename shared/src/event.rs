@@ -5,7 +5,7 @@ use anyhow::Context;
 use crate::database::badge::Badge;
 use crate::database::emote::{Emote, EmoteFlags};
 use crate::database::emote_moderation_request::EmoteModerationRequest;
-use crate::database::emote_set::{EmoteSet, EmoteSetEmote};
+use crate::database::emote_set::{EmoteSet, EmoteSetEmote, EmoteSetId};
 use crate::database::entitlement::EntitlementEdgeKind;
 use crate::database::paint::Paint;
 use crate::database::role::Role;
@@ -19,7 +19,7 @@ use crate::database::ticket::{Ticket, TicketMessage, TicketPriority};
 use crate::database::user::ban::UserBan;
 use crate::database::user::connection::UserConnection;
 use crate::database::user::editor::{UserEditor, UserEditorPermissions};
-use crate::database::user::profile_picture::UserProfilePicture;
+use crate::database::user::profile_picture::{UserProfilePicture, UserProfilePictureId};
 use crate::database::user::session::{UserSession, UserSessionId};
 use crate::database::user::{FullUser, User, UserId};
 use crate::database::Id;
@@ -37,6 +37,12 @@ pub struct InternalEventPayload {
 	pub timestamp: chrono::DateTime<chrono::Utc>,
 }
 
+/// The subject on which the events changing a single emote set are published,
+/// so subscribers of one set do not have to receive every event.
+pub fn emote_set_subject(emote_set_id: EmoteSetId) -> String {
+	format!("api.v4.events.emote_set.{emote_set_id}")
+}
+
 impl InternalEventPayload {
 	pub fn new(events: impl IntoIterator<Item = InternalEvent>) -> Self {
 		Self {
@@ -44,6 +50,27 @@ impl InternalEventPayload {
 			timestamp: chrono::Utc::now(),
 		}
 	}
+
+	/// Splits out the events of this payload which changed an emote set,
+	/// grouped by the set they changed.
+	pub fn by_emote_set(&self) -> HashMap<EmoteSetId, Self> {
+		let mut payloads = HashMap::<EmoteSetId, Self>::new();
+
+		for event in &self.events {
+			if let InternalEventData::EmoteSet { after, .. } = &event.data {
+				payloads
+					.entry(after.id)
+					.or_insert_with(|| Self {
+						events: Vec::new(),
+						timestamp: self.timestamp,
+					})
+					.events
+					.push(event.clone());
+			}
+		}
+
+		payloads
+	}
 }
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
@@ -83,8 +110,11 @@ impl InternalEvent {
 				InternalEventUserData::ChangeActivePaint { .. } => "user.change_active_paint",
 				InternalEventUserData::ChangeActiveBadge { .. } => "user.change_active_badge",
 				InternalEventUserData::ChangeActiveEmoteSet { .. } => "user.change_active_emote_set",
+				InternalEventUserData::ChangeActiveProfilePicture { .. } => "user.change_active_profile_picture",
+				InternalEventUserData::ChangeBiography { .. } => "user.change_biography",
 				InternalEventUserData::AddConnection { .. } => "user.add_connection",
 				InternalEventUserData::RemoveConnection { .. } => "user.remove_connection",
+				InternalEventUserData::UpdateConnection { .. } => "user.update_connection",
 				InternalEventUserData::Merge { .. } => "user.merge",
 				InternalEventUserData::Delete => "user.delete",
 				InternalEventUserData::AddEntitlement { .. } => "user.add_entitlement",
@@ -93,6 +123,7 @@ impl InternalEvent {
 			InternalEventData::UserProfilePicture { data, .. } => match data {
 				StoredEventUserProfilePictureData::Create => "user_profile_picture.create",
 				StoredEventUserProfilePictureData::Process { .. } => "user_profile_picture.process",
+				StoredEventUserProfilePictureData::Delete => "user_profile_picture.delete",
 			},
 			InternalEventData::UserEditor { data, .. } => match data {
 				InternalEventUserEditorData::AddEditor { .. } => "user_editor.add_editor",
@@ -175,8 +206,11 @@ pub enum InternalEventUserKind {
 	ChangeActivePaint,
 	ChangeActiveBadge,
 	ChangeActiveEmoteSet,
+	ChangeActiveProfilePicture,
+	ChangeBiography,
 	AddConnection,
 	RemoveConnection,
+	UpdateConnection,
 	Merge,
 	Delete,
 	AddEntitlement,
@@ -560,12 +594,24 @@ pub enum InternalEventUserData {
 		old: Option<Box<EmoteSet>>,
 		new: Option<Box<EmoteSet>>,
 	},
+	ChangeActiveProfilePicture {
+		old: Option<UserProfilePictureId>,
+		new: Option<UserProfilePictureId>,
+	},
+	ChangeBiography {
+		old: String,
+		new: String,
+	},
 	AddConnection {
 		connection: UserConnection,
 	},
 	RemoveConnection {
 		connection: UserConnection,
 	},
+	UpdateConnection {
+		old: UserConnection,
+		new: UserConnection,
+	},
 	Merge {
 		source_id: UserId,
 		connections: Vec<UserConnection>,
@@ -595,12 +641,19 @@ impl From<InternalEventUserData> for StoredEventUserData {
 				old: old.map(|e| e.id),
 				new: new.map(|e| e.id),
 			},
+			InternalEventUserData::ChangeActiveProfilePicture { old, new } => {
+				StoredEventUserData::ChangeActiveProfilePicture { old, new }
+			}
+			InternalEventUserData::ChangeBiography { old, new } => StoredEventUserData::ChangeBiography { old, new },
 			InternalEventUserData::AddConnection { connection } => StoredEventUserData::AddConnection {
 				platform: connection.platform,
 			},
 			InternalEventUserData::RemoveConnection { connection } => StoredEventUserData::RemoveConnection {
 				platform: connection.platform,
 			},
+			InternalEventUserData::UpdateConnection { new, .. } => {
+				StoredEventUserData::UpdateConnection { platform: new.platform }
+			}
 			InternalEventUserData::Merge { connections, source_id } => StoredEventUserData::Merge { connections, source_id },
 			InternalEventUserData::Delete => StoredEventUserData::Delete,
 			InternalEventUserData::AddEntitlement { target } => StoredEventUserData::AddEntitlement { target },
@@ -922,6 +975,34 @@ impl InternalEventPayload {
 							..Default::default()
 						});
 					}
+					InternalEventData::User {
+						after,
+						data: InternalEventUserData::UpdateConnection { old, new },
+					} => {
+						let index = after
+							.connections
+							.iter()
+							.position(|c| c.platform_id == new.platform_id)
+							.context("failed to find connection")?;
+
+						let old_value = serde_json::to_value(UserConnectionModel::from(
+							UserConnectionPartialModel::from_db(old, after.style.active_emote_set_id, 0),
+						))?;
+						let value = serde_json::to_value(UserConnectionModel::from(UserConnectionPartialModel::from_db(
+							new,
+							after.style.active_emote_set_id,
+							0,
+						)))?;
+
+						updated.push(ChangeField {
+							key: "connections".to_string(),
+							ty: ChangeFieldType::Object,
+							index: Some(index),
+							old_value,
+							value,
+							..Default::default()
+						});
+					}
 					InternalEventData::User {
 						after,
 						data: InternalEventUserData::ChangeActiveEmoteSet { old, new },
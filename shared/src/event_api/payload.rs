@@ -9,7 +9,7 @@ use std::collections::HashMap;
 // The reason this is desirable is because if we made a mistake in one of the payloads here we
 // would like to know about it rather than silently ignoring it, and potentially causing
 // issues.
-use super::types::{self, ChangeMap, CloseCode, EventType};
+use super::types::{self, ChangeMap, CloseCode, ErrorCode, EventType};
 use super::MessagePayload;
 use crate::database::user::UserId;
 use crate::database::Id;
@@ -25,6 +25,10 @@ pub struct Hello {
 	pub actor: Option<UserId>,
 	#[serde(skip_serializing_if = "Option::is_none")]
 	pub instance: Option<HelloInstanceInfo>,
+	/// The token which has to be sent along with the session id to resume
+	/// this session.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub resume_token: Option<String>,
 }
 
 impl MessagePayload for Hello {
@@ -86,6 +90,11 @@ impl MessagePayload for Reconnect {
 #[serde(deny_unknown_fields)]
 pub struct Resume {
 	pub session_id: String,
+	/// The token from the hello of the session which is resumed.
+	pub resume_token: String,
+	/// The sequence number of the last message the client received, all
+	/// buffered dispatches are replayed if not set.
+	pub last_sequence: Option<u64>,
 }
 
 impl MessagePayload for Resume {
@@ -205,6 +214,7 @@ pub struct SignalUser {
 #[serde(default)]
 #[serde(deny_unknown_fields)]
 pub struct Error {
+	pub code: ErrorCode,
 	pub message: String,
 	#[serde(skip_serializing_if = "Option::is_none")]
 	pub message_locale: Option<String>,
@@ -132,6 +132,58 @@ impl std::fmt::Display for CloseCode {
 	}
 }
 
+/// Machine readable reason sent with an `Error` payload, so clients do not
+/// have to match on the message. Payloads without a code are `Unknown`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[repr(u16)]
+pub enum ErrorCode {
+	#[default]
+	Unknown = 0,
+	InvalidOpcode = 1,
+	MalformedPayload = 2,
+	AuthRequired = 3,
+	RateLimited = 4,
+	SubscriptionLimitExceeded = 5,
+	NotSubscribed = 6,
+}
+
+impl ErrorCode {
+	pub const fn from_u16(value: u16) -> Option<Self> {
+		match value {
+			0 => Some(Self::Unknown),
+			1 => Some(Self::InvalidOpcode),
+			2 => Some(Self::MalformedPayload),
+			3 => Some(Self::AuthRequired),
+			4 => Some(Self::RateLimited),
+			5 => Some(Self::SubscriptionLimitExceeded),
+			6 => Some(Self::NotSubscribed),
+			_ => None,
+		}
+	}
+
+	pub const fn as_u16(self) -> u16 {
+		self as u16
+	}
+
+	pub const fn as_str(self) -> &'static str {
+		match self {
+			Self::Unknown => "UNKNOWN",
+			Self::InvalidOpcode => "INVALID_OPCODE",
+			Self::MalformedPayload => "MALFORMED_PAYLOAD",
+			Self::AuthRequired => "AUTH_REQUIRED",
+			Self::RateLimited => "RATE_LIMITED",
+			Self::SubscriptionLimitExceeded => "SUBSCRIPTION_LIMIT_EXCEEDED",
+			Self::NotSubscribed => "NOT_SUBSCRIBED",
+		}
+	}
+}
+
+impl std::fmt::Display for ErrorCode {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.write_str(self.as_str())
+	}
+}
+
 impl Opcode {
 	pub const fn from_u16(value: u16) -> Option<Self> {
 		match value {
@@ -193,6 +245,12 @@ impl serde::Serialize for CloseCode {
 	}
 }
 
+impl serde::Serialize for ErrorCode {
+	fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		serializer.serialize_u16(self.as_u16())
+	}
+}
+
 impl<'a> serde::Deserialize<'a> for Opcode {
 	fn deserialize<D: serde::Deserializer<'a>>(deserializer: D) -> Result<Self, D::Error> {
 		let s = u16::deserialize(deserializer)?;
@@ -207,6 +265,13 @@ impl<'a> serde::Deserialize<'a> for CloseCode {
 	}
 }
 
+impl<'a> serde::Deserialize<'a> for ErrorCode {
+	fn deserialize<D: serde::Deserializer<'a>>(deserializer: D) -> Result<Self, D::Error> {
+		let s = u16::deserialize(deserializer)?;
+		Self::from_u16(s).ok_or_else(|| serde::de::Error::custom("invalid error code"))
+	}
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Ord, PartialOrd)]
 #[repr(u8)]
 pub enum EventType {
@@ -529,3 +594,28 @@ pub struct SessionEffect {
 	#[serde(skip_serializing_if = "Vec::is_empty")]
 	pub remove_hashes: Vec<u32>,
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_error_code_values() {
+		let codes = [
+			(ErrorCode::Unknown, 0),
+			(ErrorCode::InvalidOpcode, 1),
+			(ErrorCode::MalformedPayload, 2),
+			(ErrorCode::AuthRequired, 3),
+			(ErrorCode::RateLimited, 4),
+			(ErrorCode::SubscriptionLimitExceeded, 5),
+			(ErrorCode::NotSubscribed, 6),
+		];
+
+		for (code, value) in codes {
+			assert_eq!(code.as_u16(), value);
+			assert_eq!(ErrorCode::from_u16(value), Some(code));
+		}
+
+		assert_eq!(ErrorCode::from_u16(7), None);
+	}
+}
@@ -2,11 +2,14 @@ use std::collections::HashMap;
 
 use anyhow::Context;
 use bytes::Bytes;
-use image_processor::{OutputFormat, OutputFormatOptions, OutputQuality};
+use image_processor::OutputFormatOptions;
 use image_processor_proto::image_processor_client::ImageProcessorClient;
 use image_processor_proto::{self as image_processor};
 
-use crate::config::ImageProcessorConfig;
+use crate::config::{
+	ImageProcessorConfig, ImageProcessorOutputFormat, ImageProcessorScaling, ImageProcessorScalingBase,
+	ImageProcessorSubjectLimits,
+};
 use crate::database::badge::BadgeId;
 use crate::database::emote::EmoteId;
 use crate::database::paint::{PaintId, PaintLayerId};
@@ -71,16 +74,134 @@ impl Subject {
 	}
 }
 
+/// The priority of an image processing task, higher priority tasks are
+/// processed first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ProcessPriority {
+	/// Bulk or background reprocessing
+	Low,
+	/// Uploads done by staff or through the API
+	#[default]
+	Normal,
+	/// Uploads a user is actively waiting on
+	High,
+}
+
+impl ProcessPriority {
+	pub fn as_u32(self) -> u32 {
+		match self {
+			Self::Low => 1,
+			Self::Normal => 5,
+			Self::High => 10,
+		}
+	}
+}
+
+#[derive(Debug, Default)]
+struct RequestOptions {
+	/// Priority of the task
+	priority: ProcessPriority,
+	/// Metadata passed back to the event callback
+	metadata: HashMap<String, String>,
+	/// Overrides the configured output scaling
+	scaling: Option<image_processor::Scaling>,
+	/// Disables the maximum aspect ratio check
+	any_aspect_ratio: bool,
+}
+
+/// The input limits of a subject are not configured, so its uploads are
+/// rejected instead of being processed without limits.
+#[derive(Debug, Clone, thiserror::Error)]
+#[error("image processor input limits are not configured for {0} uploads")]
+pub struct LimitsNotConfigured(&'static str);
+
+impl From<LimitsNotConfigured> for tonic::Status {
+	fn from(value: LimitsNotConfigured) -> Self {
+		tonic::Status::failed_precondition(value.to_string())
+	}
+}
+
+/// The smallest aspect ratio (width / height) of uploaded images, to allow for
+/// 1x32 images
+const MIN_ASPECT_RATIO: f64 = 1.0 / 32.0;
+
+/// The aspect ratio of an uploaded image is outside of what the image
+/// processor accepts.
+#[derive(Debug, Clone, thiserror::Error)]
+#[error("image aspect ratio {width}x{height} is not allowed, {}", match max {
+	Some(max) => format!("it must be between 1:32 and {max:.2}:1"),
+	None => "it must be at least 1:32".to_string(),
+})]
+pub struct AspectRatioOutOfBounds {
+	pub width: usize,
+	pub height: usize,
+	pub max: Option<f64>,
+}
+
+/// Checks the aspect ratio of an image with the given dimensions, empty
+/// dimensions can not be checked and are accepted.
+fn check_aspect_ratio(width: usize, height: usize, max: Option<f64>) -> Result<(), AspectRatioOutOfBounds> {
+	if width == 0 || height == 0 {
+		return Ok(());
+	}
+
+	let aspect_ratio = width as f64 / height as f64;
+
+	if aspect_ratio < MIN_ASPECT_RATIO || max.is_some_and(|max| aspect_ratio > max) {
+		return Err(AspectRatioOutOfBounds { width, height, max });
+	}
+
+	Ok(())
+}
+
+/// Validates the configured scaling, scales are sorted and duplicates are
+/// removed.
+fn make_scaling(config: &ImageProcessorScaling) -> anyhow::Result<image_processor::Scaling> {
+	let base = match config.base {
+		ImageProcessorScalingBase::Height(height) => {
+			anyhow::ensure!(height > 0, "base height must be positive");
+			image_processor::scaling::Base::BaseHeight(height)
+		}
+		ImageProcessorScalingBase::Width(width) => {
+			anyhow::ensure!(width > 0, "base width must be positive");
+			image_processor::scaling::Base::BaseWidth(width)
+		}
+	};
+
+	anyhow::ensure!(!config.scales.is_empty(), "at least one scale is required");
+	anyhow::ensure!(config.scales.iter().all(|s| *s > 0), "scales must be positive");
+
+	let mut scales = config.scales.clone();
+	scales.sort_unstable();
+	scales.dedup();
+
+	Ok(image_processor::Scaling {
+		base: Some(base),
+		scales,
+	})
+}
+
 pub struct ImageProcessor {
 	client: ImageProcessorClient<tonic::transport::Channel>,
 	input_drive_name: String,
 	output_drive_name: String,
 	event_queue_name: String,
 	event_queue_topic_prefix: String,
+	output_formats: Vec<ImageProcessorOutputFormat>,
+	output_scales: Vec<u32>,
+	output_base_height: u32,
+	emote_scaling: image_processor::Scaling,
+	profile_picture_scaling: image_processor::Scaling,
+	max_aspect_ratio: Option<f64>,
+	max_retries: u32,
+	retry_backoff: std::time::Duration,
+	limits: ImageProcessorSubjectLimits,
 }
 
 impl ImageProcessor {
 	pub async fn new(config: &ImageProcessorConfig) -> anyhow::Result<Self> {
+		anyhow::ensure!(!config.output_formats.is_empty(), "at least one output format is required");
+
 		let channel =
 			crate::grpc::make_channel(config.address.clone(), config.resolve_interval, None).context("make channel")?;
 
@@ -88,47 +209,113 @@ impl ImageProcessor {
 			.max_decoding_message_size(128 * 1024 * 1024) // 128MB
 			.max_encoding_message_size(128 * 1024 * 1024);
 
+		let default_scaling = ImageProcessorScaling {
+			base: ImageProcessorScalingBase::Height(config.output_base_height),
+			scales: config.output_scales.clone(),
+		};
+
+		let output_scales = make_scaling(&default_scaling).context("output scales")?.scales;
+		let emote_scaling =
+			make_scaling(config.scaling.emote.as_ref().unwrap_or(&default_scaling)).context("emote scaling")?;
+		let profile_picture_scaling = make_scaling(config.scaling.profile_picture.as_ref().unwrap_or(&default_scaling))
+			.context("profile picture scaling")?;
+
 		Ok(Self {
 			client,
 			input_drive_name: config.input_drive_name.clone(),
 			output_drive_name: config.output_drive_name.clone(),
 			event_queue_name: config.event_queue_name.clone(),
 			event_queue_topic_prefix: config.event_queue_topic_prefix.clone(),
+			output_formats: config.output_formats.clone(),
+			output_scales,
+			output_base_height: config.output_base_height,
+			emote_scaling,
+			profile_picture_scaling,
+			max_aspect_ratio: config.max_aspect_ratio,
+			max_retries: config.max_retries,
+			retry_backoff: config.retry_backoff,
+			limits: config.limits.clone(),
 		})
 	}
 
 	pub async fn send_req(
 		&self,
-		req: image_processor::ProcessImageRequest,
+		mut req: image_processor::ProcessImageRequest,
 	) -> tonic::Result<image_processor::ProcessImageResponse> {
-		Ok(self.client.clone().process_image(req).await?.into_inner())
+		let mut retry = 0;
+
+		loop {
+			// Only keep a copy of the request around if we might need to send it again,
+			// the input binary is shared between the copies
+			let request = if retry < self.max_retries {
+				req.clone()
+			} else {
+				std::mem::take(&mut req)
+			};
+
+			match self.client.clone().process_image(request).await {
+				Ok(resp) => return Ok(resp.into_inner()),
+				// The image processor assigns the task id when it receives the request, so
+				// a request cannot be sent twice without creating a second task. A deadline
+				// might be exceeded after the task was created, so only requests which never
+				// reached the image processor are retried.
+				Err(status) if retry < self.max_retries && status.code() == tonic::Code::Unavailable => {
+					let backoff = self.retry_backoff.saturating_mul(1 << retry.min(16));
+					tracing::warn!(error = %status, retry, "image processor request failed, retrying in {backoff:?}");
+					tokio::time::sleep(backoff).await;
+					retry += 1;
+				}
+				Err(status) => return Err(status),
+			}
+		}
 	}
 
 	pub fn make_request(
 		&self,
 		input_upload: Option<image_processor::InputUpload>,
 		task: image_processor::Task,
+		priority: ProcessPriority,
 	) -> image_processor::ProcessImageRequest {
 		image_processor::ProcessImageRequest {
 			input_upload,
 			task: Some(task),
-			priority: 5,
+			priority: priority.as_u32(),
 			..Default::default()
 		}
 	}
 
-	pub fn make_task(&self, output: image_processor::Output, events: image_processor::Events) -> image_processor::Task {
-		image_processor::Task {
+	pub fn make_limits(&self, subject: Subject) -> Result<image_processor::Limits, LimitsNotConfigured> {
+		let (name, limits) = match subject {
+			Subject::Emote(_) => ("emote", &self.limits.emote),
+			Subject::ProfilePicture(_) => ("profile picture", &self.limits.profile_picture),
+			Subject::PaintLayer(..) => ("paint layer", &self.limits.paint_layer),
+			Subject::Badge(_) => ("badge", &self.limits.badge),
+		};
+
+		if limits.max_input_frame_count == 0 || limits.max_input_width == 0 || limits.max_input_height == 0 {
+			return Err(LimitsNotConfigured(name));
+		}
+
+		Ok(image_processor::Limits {
+			max_input_frame_count: Some(limits.max_input_frame_count),
+			max_input_width: Some(limits.max_input_width),
+			max_input_height: Some(limits.max_input_height),
+			..Default::default()
+		})
+	}
+
+	pub fn make_task(
+		&self,
+		subject: Subject,
+		output: image_processor::Output,
+		metadata: HashMap<String, String>,
+	) -> Result<image_processor::Task, LimitsNotConfigured> {
+		Ok(image_processor::Task {
 			output: Some(output),
-			events: Some(events),
-			limits: Some(image_processor::Limits {
-				max_input_frame_count: Some(1000),
-				max_input_width: Some(1000),
-				max_input_height: Some(1000),
-				..Default::default()
-			}),
+			events: Some(self.make_events(subject, metadata)),
+			limits: Some(self.make_limits(subject)?),
 			..Default::default()
-		}
+		})
 	}
 
 	pub fn make_input_upload(&self, input_path: String, data: Bytes) -> image_processor::InputUpload {
@@ -138,11 +325,22 @@ impl ImageProcessor {
 				path: input_path,
 				acl: Some("private".to_string()),
 			}),
-			binary: data.to_vec(),
+			binary: data,
 			..Default::default()
 		}
 	}
 
+	pub fn make_drive_input(&self, path: String) -> image_processor::Input {
+		image_processor::Input {
+			path: Some(image_processor::input::Path::DrivePath(image_processor::DrivePath {
+				path,
+				drive: self.input_drive_name.clone(),
+				acl: None,
+			})),
+			metadata: None,
+		}
+	}
+
 	pub fn make_output(&self, output_path: String) -> image_processor::Output {
 		image_processor::Output {
 			drive_path: Some(image_processor::DrivePath {
@@ -151,48 +349,24 @@ impl ImageProcessor {
 				acl: Some("public-read".to_string()),
 			}),
 			input_reupload_path: None,
-			formats: vec![
-				OutputFormatOptions {
-					format: OutputFormat::WebpAnim as i32,
-					quality: OutputQuality::Auto as i32,
-					name: None,
-				},
-				OutputFormatOptions {
-					format: OutputFormat::WebpStatic as i32,
-					quality: OutputQuality::Lossless as i32,
+			formats: self
+				.output_formats
+				.iter()
+				.map(|output| OutputFormatOptions {
+					format: output.format as i32,
+					quality: output.quality as i32,
 					name: None,
-				},
-				OutputFormatOptions {
-					format: OutputFormat::AvifAnim as i32,
-					quality: OutputQuality::Auto as i32,
-					name: None,
-				},
-				OutputFormatOptions {
-					format: OutputFormat::AvifStatic as i32,
-					quality: OutputQuality::Auto as i32,
-					name: None,
-				},
-				OutputFormatOptions {
-					format: OutputFormat::GifAnim as i32,
-					quality: OutputQuality::Auto as i32,
-					name: None,
-				},
-				OutputFormatOptions {
-					format: OutputFormat::PngStatic as i32,
-					quality: OutputQuality::Auto as i32,
-					name: None,
-				},
-			],
+				})
+				.collect(),
 			upscale: true,
 			skip_impossible_formats: true,
-			// To allow for 1x32 images
-			min_aspect_ratio: Some(1.0 / 32.0),
-			max_aspect_ratio: Some(3.0),
+			min_aspect_ratio: Some(MIN_ASPECT_RATIO),
+			max_aspect_ratio: self.max_aspect_ratio,
 			resize_method: image_processor::ResizeMethod::Fit as i32,
 			resize_algorithm: image_processor::ResizeAlgorithm::Lanczos3 as i32,
 			resize: Some(image_processor::output::Resize::Scaling(image_processor::Scaling {
-				base: Some(image_processor::scaling::Base::BaseHeight(32)),
-				scales: vec![1, 2, 3, 4],
+				base: Some(image_processor::scaling::Base::BaseHeight(self.output_base_height)),
+				scales: self.output_scales.clone(),
 			})),
 			..Default::default()
 		}
@@ -222,28 +396,70 @@ impl ImageProcessor {
 		}
 	}
 
+	/// Builds a request which uploads `binary` to `{drive_path}/input.{ext}`
+	/// and writes the outputs next to it.
+	fn build_request(
+		&self,
+		subject: Subject,
+		drive_path: &str,
+		binary: Bytes,
+		opts: RequestOptions,
+	) -> Result<image_processor::ProcessImageRequest, LimitsNotConfigured> {
+		let mut output = self.make_output(format!("{drive_path}/{{scale}}x{{static}}.{{ext}}"));
+		if let Some(scaling) = opts.scaling {
+			output.resize = Some(image_processor::output::Resize::Scaling(scaling));
+		}
+
+		if opts.any_aspect_ratio {
+			output.max_aspect_ratio = None;
+		}
+
+		let task = self.make_task(subject, output, opts.metadata)?;
+
+		Ok(self.make_request(
+			Some(self.make_input_upload(format!("{drive_path}/input.{{ext}}"), binary)),
+			task,
+			opts.priority,
+		))
+	}
+
+	/// Checks the aspect ratio of an image using the dimensions in its header,
+	/// so uploads which would fail to process are rejected before taking up a
+	/// processor slot. Images which can not be sniffed are left to the image
+	/// processor to validate.
+	pub fn check_aspect_ratio(&self, data: &[u8]) -> Result<(), AspectRatioOutOfBounds> {
+		let Ok(imagesize::ImageSize { width, height }) = imagesize::blob_size(data) else {
+			return Ok(());
+		};
+
+		check_aspect_ratio(width, height, self.max_aspect_ratio)
+	}
+
 	#[tracing::instrument(skip_all, name = "ImageProcessor::upload_emote", fields(emote_id = %id))]
 	pub async fn upload_emote(
 		&self,
 		id: EmoteId,
 		data: Bytes,
 		upload_ip: Option<std::net::IpAddr>,
+		priority: ProcessPriority,
 	) -> tonic::Result<image_processor::ProcessImageResponse> {
-		let req = self.make_request(
-			Some(self.make_input_upload(format!("emote/{id}/input.{{ext}}"), data)),
-			self.make_task(
-				self.make_output(format!("emote/{id}/{{scale}}x{{static}}.{{ext}}")),
-				self.make_events(Subject::Emote(id), {
-					let mut map = std::collections::HashMap::new();
-					map.insert("emote_id".to_string(), id.to_string());
-					if let Some(ip) = upload_ip {
-						map.insert("upload_ip".to_string(), ip.to_string());
-					}
-
-					map
-				}),
-			),
-		);
+		let mut metadata = HashMap::new();
+		metadata.insert("emote_id".to_string(), id.to_string());
+		if let Some(ip) = upload_ip {
+			metadata.insert("upload_ip".to_string(), ip.to_string());
+		}
+
+		let req = self.build_request(
+			Subject::Emote(id),
+			&format!("emote/{id}"),
+			data,
+			RequestOptions {
+				priority,
+				metadata,
+				scaling: Some(self.emote_scaling.clone()),
+				..Default::default()
+			},
+		)?;
 
 		self.send_req(req).await
 	}
@@ -255,22 +471,25 @@ impl ImageProcessor {
 		user_id: UserId,
 		data: Bytes,
 		upload_ip: Option<std::net::IpAddr>,
+		priority: ProcessPriority,
 	) -> tonic::Result<image_processor::ProcessImageResponse> {
-		let req = self.make_request(
-			Some(self.make_input_upload(format!("user/{user_id}/profile-picture/{id}/input.{{ext}}"), data)),
-			self.make_task(
-				self.make_output(format!("user/{user_id}/profile-picture/{id}/{{scale}}x{{static}}.{{ext}}")),
-				self.make_events(Subject::ProfilePicture(id), {
-					let mut map = std::collections::HashMap::new();
-					map.insert("user_id".to_string(), user_id.to_string());
-					if let Some(ip) = upload_ip {
-						map.insert("upload_ip".to_string(), ip.to_string());
-					}
-
-					map
-				}),
-			),
-		);
+		let mut metadata = HashMap::new();
+		metadata.insert("user_id".to_string(), user_id.to_string());
+		if let Some(ip) = upload_ip {
+			metadata.insert("upload_ip".to_string(), ip.to_string());
+		}
+
+		let req = self.build_request(
+			Subject::ProfilePicture(id),
+			&format!("user/{user_id}/profile-picture/{id}"),
+			data,
+			RequestOptions {
+				priority,
+				metadata,
+				scaling: Some(self.profile_picture_scaling.clone()),
+				..Default::default()
+			},
+		)?;
 
 		self.send_req(req).await
 	}
@@ -281,55 +500,106 @@ impl ImageProcessor {
 		id: PaintId,
 		layer_id: PaintLayerId,
 		data: Bytes,
+		priority: ProcessPriority,
 	) -> tonic::Result<image_processor::ProcessImageResponse> {
-		let req = self.make_request(
-			Some(self.make_input_upload(format!("paint/{id}/layer/{layer_id}/input.{{ext}}"), data)),
-			image_processor::Task {
-				limits: Some(image_processor::Limits {
-					max_input_frame_count: Some(1000),
-					max_input_width: Some(1500),
-					max_input_height: Some(1500),
-					..Default::default()
-				}),
-				..self.make_task(
-					image_processor_proto::Output {
-						max_aspect_ratio: None,
-						..self.make_output(format!("paint/{id}/layer/{layer_id}/{{scale}}x{{static}}.{{ext}}"))
-					},
-					self.make_events(
-						Subject::PaintLayer(id, layer_id),
-						[
-							("paint_id".to_string(), id.to_string()),
-							("layer_id".to_string(), layer_id.to_string()),
-						]
-						.into_iter()
-						.collect(),
-					),
-				)
+		let req = self.build_request(
+			Subject::PaintLayer(id, layer_id),
+			&format!("paint/{id}/layer/{layer_id}"),
+			data,
+			RequestOptions {
+				priority,
+				metadata: [
+					("paint_id".to_string(), id.to_string()),
+					("layer_id".to_string(), layer_id.to_string()),
+				]
+				.into_iter()
+				.collect(),
+				any_aspect_ratio: true,
+				..Default::default()
 			},
-		);
+		)?;
 
 		self.send_req(req).await
 	}
 
 	#[tracing::instrument(skip_all, name = "ImageProcessor::upload_badge", fields(badge_id = %id))]
-	pub async fn upload_badge(&self, id: BadgeId, data: Bytes) -> tonic::Result<image_processor::ProcessImageResponse> {
-		let req = self.make_request(
-			Some(self.make_input_upload(format!("badge/{id}/input.{{ext}}"), data)),
-			self.make_task(
-				image_processor::Output {
-					resize: Some(image_processor::output::Resize::Scaling(image_processor::Scaling {
-						base: Some(image_processor::scaling::Base::BaseHeight(18)),
-						scales: vec![1, 2, 3, 4],
-					})),
-					..self.make_output(format!("badge/{id}/{{scale}}x{{static}}.{{ext}}"))
-				},
-				self.make_events(
-					Subject::Badge(id),
-					[("badge_id".to_string(), id.to_string())].into_iter().collect(),
-				),
-			),
-		);
+	pub async fn upload_badge(
+		&self,
+		id: BadgeId,
+		data: Bytes,
+		priority: ProcessPriority,
+	) -> tonic::Result<image_processor::ProcessImageResponse> {
+		let req = self.build_request(
+			Subject::Badge(id),
+			&format!("badge/{id}"),
+			data,
+			RequestOptions {
+				priority,
+				metadata: [("badge_id".to_string(), id.to_string())].into_iter().collect(),
+				scaling: Some(image_processor::Scaling {
+					base: Some(image_processor::scaling::Base::BaseHeight(18)),
+					scales: self.output_scales.clone(),
+				}),
+				..Default::default()
+			},
+		)?;
+
+		self.send_req(req).await
+	}
+
+	#[tracing::instrument(skip_all, name = "ImageProcessor::reprocess_emote", fields(emote_id = %id))]
+	pub async fn reprocess_emote(
+		&self,
+		source_file: String,
+		id: EmoteId,
+		priority: ProcessPriority,
+	) -> tonic::Result<image_processor::ProcessImageResponse> {
+		let mut task = self.make_task(
+			Subject::Emote(id),
+			image_processor::Output {
+				resize: Some(image_processor::output::Resize::Scaling(self.emote_scaling.clone())),
+				..self.make_output(format!("emote/{id}/{{scale}}x{{static}}.{{ext}}"))
+			},
+			[
+				("emote_id".to_string(), id.to_string()),
+				("reprocess".to_string(), "true".to_string()),
+			]
+			.into_iter()
+			.collect(),
+		)?;
+
+		task.input = Some(self.make_drive_input(source_file));
+
+		let req = self.make_request(None, task, priority);
+
+		self.send_req(req).await
+	}
+
+	#[tracing::instrument(skip_all, name = "ImageProcessor::reprocess_profile_picture", fields(user_id = %user_id, profile_picture_id = %id))]
+	pub async fn reprocess_profile_picture(
+		&self,
+		source_file: String,
+		id: UserProfilePictureId,
+		user_id: UserId,
+		priority: ProcessPriority,
+	) -> tonic::Result<image_processor::ProcessImageResponse> {
+		let mut task = self.make_task(
+			Subject::ProfilePicture(id),
+			image_processor::Output {
+				resize: Some(image_processor::output::Resize::Scaling(self.profile_picture_scaling.clone())),
+				..self.make_output(format!("user/{user_id}/profile-picture/{id}/{{scale}}x{{static}}.{{ext}}"))
+			},
+			[
+				("user_id".to_string(), user_id.to_string()),
+				("reprocess".to_string(), "true".to_string()),
+			]
+			.into_iter()
+			.collect(),
+		)?;
+
+		task.input = Some(self.make_drive_input(source_file));
+
+		let req = self.make_request(None, task, priority);
 
 		self.send_req(req).await
 	}
@@ -339,36 +609,28 @@ impl ImageProcessor {
 		&self,
 		source_file: String,
 		id: BadgeId,
+		priority: ProcessPriority,
 	) -> tonic::Result<image_processor::ProcessImageResponse> {
 		let mut task = self.make_task(
+			Subject::Badge(id),
 			image_processor::Output {
 				resize: Some(image_processor::output::Resize::Scaling(image_processor::Scaling {
 					base: Some(image_processor::scaling::Base::BaseHeight(18)),
-					scales: vec![1, 2, 3, 4],
+					scales: self.output_scales.clone(),
 				})),
 				..self.make_output(format!("badge/{id}/{{scale}}x{{static}}.{{ext}}"))
 			},
-			self.make_events(
-				Subject::Badge(id),
-				[
-					("badge_id".to_string(), id.to_string()),
-					("reprocess".to_string(), "true".to_string()),
-				]
-				.into_iter()
-				.collect(),
-			),
-		);
+			[
+				("badge_id".to_string(), id.to_string()),
+				("reprocess".to_string(), "true".to_string()),
+			]
+			.into_iter()
+			.collect(),
+		)?;
 
-		task.input = Some(image_processor::Input {
-			path: Some(image_processor::input::Path::DrivePath(image_processor::DrivePath {
-				path: source_file,
-				drive: self.input_drive_name.clone(),
-				acl: None,
-			})),
-			metadata: None,
-		});
+		task.input = Some(self.make_drive_input(source_file));
 
-		let req = self.make_request(None, task);
+		let req = self.make_request(None, task, priority);
 
 		self.send_req(req).await
 	}
@@ -379,43 +641,89 @@ impl ImageProcessor {
 		source_file: String,
 		id: PaintId,
 		layer_id: PaintLayerId,
+		priority: ProcessPriority,
 	) -> tonic::Result<image_processor::ProcessImageResponse> {
-		let req = self.make_request(
-			None,
-			image_processor::Task {
-				limits: Some(image_processor::Limits {
-					max_input_frame_count: Some(1000),
-					max_input_width: Some(1500),
-					max_input_height: Some(1500),
-					..Default::default()
-				}),
-				input: Some(image_processor::Input {
-					path: Some(image_processor::input::Path::DrivePath(image_processor::DrivePath {
-						path: source_file,
-						drive: self.input_drive_name.clone(),
-						acl: None,
-					})),
-					metadata: None,
-				}),
-				..self.make_task(
-					image_processor_proto::Output {
-						max_aspect_ratio: None,
-						..self.make_output(format!("paint/{id}/layer/{layer_id}/{{scale}}x{{static}}.{{ext}}"))
-					},
-					self.make_events(
-						Subject::PaintLayer(id, layer_id),
-						[
-							("paint_id".to_string(), id.to_string()),
-							("layer_id".to_string(), layer_id.to_string()),
-							("reprocess".to_string(), "true".to_string()),
-						]
-						.into_iter()
-						.collect(),
-					),
-				)
+		let mut task = self.make_task(
+			Subject::PaintLayer(id, layer_id),
+			image_processor::Output {
+				max_aspect_ratio: None,
+				..self.make_output(format!("paint/{id}/layer/{layer_id}/{{scale}}x{{static}}.{{ext}}"))
 			},
-		);
+			[
+				("paint_id".to_string(), id.to_string()),
+				("layer_id".to_string(), layer_id.to_string()),
+				("reprocess".to_string(), "true".to_string()),
+			]
+			.into_iter()
+			.collect(),
+		)?;
+
+		task.input = Some(self.make_drive_input(source_file));
+
+		let req = self.make_request(None, task, priority);
 
 		self.send_req(req).await
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_make_scaling() {
+		let scaling = make_scaling(&ImageProcessorScaling {
+			base: ImageProcessorScalingBase::Width(64),
+			scales: vec![2, 1, 2, 4],
+		})
+		.unwrap();
+
+		assert_eq!(scaling.base, Some(image_processor::scaling::Base::BaseWidth(64)));
+		assert_eq!(scaling.scales, [1, 2, 4]);
+
+		assert!(make_scaling(&ImageProcessorScaling {
+			base: ImageProcessorScalingBase::Height(32),
+			scales: vec![0, 1],
+		})
+		.is_err());
+		assert!(make_scaling(&ImageProcessorScaling {
+			base: ImageProcessorScalingBase::Height(32),
+			scales: vec![],
+		})
+		.is_err());
+		assert!(make_scaling(&ImageProcessorScaling {
+			base: ImageProcessorScalingBase::Height(0),
+			scales: vec![1],
+		})
+		.is_err());
+	}
+
+	#[test]
+	fn test_check_aspect_ratio() {
+		assert!(check_aspect_ratio(128, 128, Some(3.0)).is_ok());
+		assert!(check_aspect_ratio(384, 128, Some(3.0)).is_ok());
+		assert!(check_aspect_ratio(385, 128, Some(3.0)).is_err());
+		assert!(check_aspect_ratio(4, 128, None).is_ok());
+		assert!(check_aspect_ratio(3, 128, None).is_err());
+		assert!(check_aspect_ratio(10_000, 1, None).is_ok());
+
+		// the dimensions could not be read
+		assert!(check_aspect_ratio(128, 0, Some(3.0)).is_ok());
+		assert!(check_aspect_ratio(0, 128, Some(3.0)).is_ok());
+	}
+
+	#[test]
+	fn test_aspect_ratio_error() {
+		let err = check_aspect_ratio(3, 128, None).unwrap_err();
+		assert_eq!(
+			err.to_string(),
+			"image aspect ratio 3x128 is not allowed, it must be at least 1:32"
+		);
+
+		let err = check_aspect_ratio(385, 128, Some(3.0)).unwrap_err();
+		assert_eq!(
+			err.to_string(),
+			"image aspect ratio 385x128 is not allowed, it must be between 1:32 and 3.00:1"
+		);
+	}
+}
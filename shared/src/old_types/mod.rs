@@ -18,7 +18,7 @@ use crate::database::badge::BadgeId;
 use crate::database::emote::{Emote, EmoteFlags, EmoteId};
 use crate::database::emote_set::{EmoteSet, EmoteSetEmote, EmoteSetEmoteFlag, EmoteSetId, EmoteSetKind};
 use crate::database::paint::PaintId;
-use crate::database::role::permissions::{PermissionsExt, UserPermission};
+use crate::database::role::permissions::{effective_emote_set_capacity, PermissionsExt, UserPermission};
 use crate::database::role::RoleId;
 use crate::database::user::connection::{Platform, UserConnection};
 use crate::database::user::editor::{
@@ -175,7 +175,7 @@ impl UserPartialModel {
 					UserConnectionPartialModel::from_db(
 						connection,
 						user.style.active_emote_set_id,
-						user.computed.permissions.emote_set_capacity.unwrap_or_default(),
+						effective_emote_set_capacity(&user.computed.permissions, &EmoteSetKind::Normal),
 					)
 				})
 				.collect(),
@@ -493,6 +493,7 @@ impl UserModel {
 		cdn_base_url: &url::Url,
 	) -> Self {
 		let created_at = user.id.timestamp_ms();
+		let biography = user.biography.clone();
 		let active_emote_set_id = user.style.active_emote_set_id;
 		let partial = UserPartialModel::from_db(user, paint, badge, cdn_base_url);
 
@@ -503,7 +504,7 @@ impl UserModel {
 			display_name: partial.display_name,
 			created_at,
 			avatar_url: partial.avatar_url,
-			biography: String::new(),
+			biography,
 			style: partial.style,
 			emote_sets,
 			editors,
@@ -838,7 +839,9 @@ impl EmotePartialModel {
 			owner,
 			state: EmoteVersionState::from_db(&value.flags),
 			flags: value.flags.into(),
-			lifecycle: if value.merged.is_some() || value.deleted {
+			lifecycle: if value.processing_error.is_some() {
+				EmoteLifecycleModel::Failed
+			} else if value.merged.is_some() || value.deleted {
 				EmoteLifecycleModel::Deleted
 			} else if value.image_set.input.is_pending() {
 				EmoteLifecycleModel::Pending
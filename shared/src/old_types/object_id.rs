@@ -2,9 +2,13 @@ use std::fmt;
 use std::str::FromStr;
 
 use async_graphql::{Scalar, ScalarType};
+use mongodb::bson::oid::ObjectId;
 
 use crate::database::Id;
 
+/// The untyped id used by the old apis. Conversions into typed ids keep the
+/// underlying ulid and therefore the timestamp as is, the caller decides
+/// which kind of id it is.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct GqlObjectId(pub Id<()>);
 
@@ -12,6 +16,16 @@ impl GqlObjectId {
 	pub fn id<T>(self) -> Id<T> {
 		self.0.cast()
 	}
+
+	/// The old apis send a nil id to unset a value, this returns `None` for
+	/// those.
+	pub fn non_nil<T>(self) -> Option<Id<T>> {
+		(!self.0.is_nil()).then(|| self.id())
+	}
+
+	pub fn timestamp(self) -> chrono::DateTime<chrono::Utc> {
+		self.0.timestamp()
+	}
 }
 
 impl<T> From<Id<T>> for GqlObjectId {
@@ -20,6 +34,42 @@ impl<T> From<Id<T>> for GqlObjectId {
 	}
 }
 
+impl<T> From<GqlObjectId> for Id<T> {
+	fn from(id: GqlObjectId) -> Self {
+		id.id()
+	}
+}
+
+impl From<ulid::Ulid> for GqlObjectId {
+	fn from(ulid: ulid::Ulid) -> Self {
+		Self(Id::from_ulid(ulid))
+	}
+}
+
+impl From<GqlObjectId> for ulid::Ulid {
+	fn from(id: GqlObjectId) -> Self {
+		id.0.as_ulid()
+	}
+}
+
+impl From<ObjectId> for GqlObjectId {
+	fn from(object_id: ObjectId) -> Self {
+		Self(Id::from_object_id(object_id))
+	}
+}
+
+#[derive(thiserror::Error, Debug, Clone, Copy)]
+#[error("id {0} can not be represented as an object id")]
+pub struct NotObjectIdCompatible(pub GqlObjectId);
+
+impl TryFrom<GqlObjectId> for ObjectId {
+	type Error = NotObjectIdCompatible;
+
+	fn try_from(id: GqlObjectId) -> Result<Self, Self::Error> {
+		id.0.as_object_id().ok_or(NotObjectIdCompatible(id))
+	}
+}
+
 impl serde::Serialize for GqlObjectId {
 	fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
 	where
@@ -62,3 +112,52 @@ impl ScalarType for GqlObjectId {
 		self.0.to_value()
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::database::user::UserId;
+
+	#[test]
+	fn test_typed_id_roundtrip() {
+		let user_id = UserId::with_timestamp_ms(1_700_000_000_123);
+
+		let id = GqlObjectId::from(user_id);
+		assert_eq!(id.timestamp(), user_id.timestamp());
+
+		let back: UserId = id.into();
+		assert_eq!(back, user_id);
+		assert_eq!(back.timestamp(), user_id.timestamp());
+	}
+
+	#[test]
+	fn test_ulid_roundtrip() {
+		let ulid = ulid::Ulid::new();
+
+		let id = GqlObjectId::from(ulid);
+		assert_eq!(id.timestamp().timestamp_millis() as u64, ulid.timestamp_ms());
+		assert_eq!(ulid::Ulid::from(id), ulid);
+	}
+
+	#[test]
+	fn test_object_id_roundtrip() {
+		let object_id = ObjectId::new();
+
+		let id = GqlObjectId::from(object_id);
+		assert_eq!(id.timestamp().timestamp(), object_id.timestamp().timestamp_millis() / 1000);
+		assert_eq!(ObjectId::try_from(id).unwrap(), object_id);
+
+		// ulids with millisecond precision do not fit into an object id
+		let id = GqlObjectId::from(UserId::with_timestamp_ms(1_700_000_000_123));
+		assert!(ObjectId::try_from(id).is_err());
+	}
+
+	#[test]
+	fn test_non_nil() {
+		let nil: Option<UserId> = GqlObjectId(Id::nil()).non_nil();
+		assert_eq!(nil, None);
+
+		let user_id = UserId::new();
+		assert_eq!(GqlObjectId::from(user_id).non_nil(), Some(user_id));
+	}
+}
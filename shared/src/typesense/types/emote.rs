@@ -1,6 +1,6 @@
 use chrono::Utc;
 
-use super::{TypesenseCollection, TypesenseGenericCollection};
+use super::{TypesenseCollection, TypesenseGenericCollection, TypesensePatch};
 use crate::database;
 use crate::database::emote::EmoteId;
 use crate::database::user::UserId;
@@ -12,6 +12,7 @@ pub struct Emote {
 	pub id: EmoteId,
 	pub owner_id: UserId,
 	pub default_name: String,
+	#[typesense(facet = true)]
 	pub tags: Vec<String>,
 	pub flag_public_listed: bool,
 	pub flag_private: bool,
@@ -72,6 +73,42 @@ impl From<database::emote::Emote> for Emote {
 	}
 }
 
+impl TypesensePatch for Emote {
+	fn derived_fields(db_field: &str) -> Option<&'static [&'static str]> {
+		Some(match db_field {
+			"owner_id" => &["owner_id"],
+			"default_name" => &["default_name"],
+			"tags" => &["tags"],
+			"image_set" => &[],
+			"flags" => &[
+				"flag_public_listed",
+				"flag_private",
+				"flag_nsfw",
+				"flag_default_zero_width",
+				"flag_approved_personal",
+				"flag_denied_personal",
+				"flag_animated",
+			],
+			"aspect_ratio" => &["aspect_ratio"],
+			"attribution" => &["attribution"],
+			"merged" => &["merged_into", "merged_at", "deleted"],
+			"scores" => &[
+				"score_trending_day",
+				"score_trending_week",
+				"score_trending_month",
+				"score_top_daily",
+				"score_top_weekly",
+				"score_top_monthly",
+				"score_top_all_time",
+			],
+			"deleted" => &["deleted"],
+			"updated_at" => &["updated_at"],
+			"search_updated_at" => &["search_updated_at"],
+			_ => return None,
+		})
+	}
+}
+
 pub(super) fn typesense_collections() -> impl IntoIterator<Item = TypesenseGenericCollection> {
 	[TypesenseGenericCollection::new::<Emote>()]
 }
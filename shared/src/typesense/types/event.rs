@@ -36,6 +36,7 @@ fn split_kinds(data: &StoredEventData) -> (EventId, ActionKind, Vec<EventId>) {
 					ImageProcessorEvent::Fail { .. } => ActionKind::EmoteProcessFailure,
 					ImageProcessorEvent::Cancel => ActionKind::EmoteProcessCancel,
 					ImageProcessorEvent::Start => ActionKind::EmoteProcessStart,
+					ImageProcessorEvent::Resubmit { .. } => ActionKind::EmoteProcessResubmit,
 				},
 				StoredEventEmoteData::ChangeName { .. } => ActionKind::EmoteChangeName,
 				StoredEventEmoteData::ChangeFlags { .. } => ActionKind::EmoteChangeFlags,
@@ -113,8 +114,19 @@ fn split_kinds(data: &StoredEventData) -> (EventId, ActionKind, Vec<EventId>) {
 					}
 					ActionKind::UserChangeActiveEmoteSet
 				}
+				StoredEventUserData::ChangeActiveProfilePicture { old, new } => {
+					if let Some(id) = *old {
+						secondary.push(EventId::UserProfilePicture(id))
+					}
+					if let Some(id) = *new {
+						secondary.push(EventId::UserProfilePicture(id))
+					}
+					ActionKind::UserChangeActiveProfilePicture
+				}
+				StoredEventUserData::ChangeBiography { .. } => ActionKind::UserChangeBiography,
 				StoredEventUserData::AddConnection { .. } => ActionKind::UserAddConnection,
 				StoredEventUserData::RemoveConnection { .. } => ActionKind::UserRemoveConnection,
+				StoredEventUserData::UpdateConnection { .. } => ActionKind::UserUpdateConnection,
 				StoredEventUserData::Merge { .. } => ActionKind::UserMerge,
 				StoredEventUserData::Delete => ActionKind::UserDelete,
 				StoredEventUserData::AddEntitlement { target } => {
@@ -255,7 +267,9 @@ fn split_kinds(data: &StoredEventData) -> (EventId, ActionKind, Vec<EventId>) {
 					ImageProcessorEvent::Fail { .. } => ActionKind::UserProfilePictureProcessFailure,
 					ImageProcessorEvent::Cancel => ActionKind::UserProfilePictureProcessCancel,
 					ImageProcessorEvent::Start => ActionKind::UserProfilePictureProcessStart,
+					ImageProcessorEvent::Resubmit { .. } => ActionKind::UserProfilePictureProcessResubmit,
 				},
+				StoredEventUserProfilePictureData::Delete => ActionKind::UserProfilePictureDelete,
 			};
 
 			(target, action, secondary)
@@ -288,6 +302,7 @@ fn split_kinds(data: &StoredEventData) -> (EventId, ActionKind, Vec<EventId>) {
 					ImageProcessorEvent::Fail { .. } => ActionKind::PaintProcessFailure,
 					ImageProcessorEvent::Cancel => ActionKind::PaintProcessCancel,
 					ImageProcessorEvent::Start => ActionKind::PaintProcessStart,
+					ImageProcessorEvent::Resubmit { .. } => ActionKind::PaintProcessResubmit,
 				},
 			};
 
@@ -304,6 +319,7 @@ fn split_kinds(data: &StoredEventData) -> (EventId, ActionKind, Vec<EventId>) {
 					ImageProcessorEvent::Fail { .. } => ActionKind::BadgeProcessFailure,
 					ImageProcessorEvent::Cancel => ActionKind::BadgeProcessCancel,
 					ImageProcessorEvent::Start => ActionKind::BadgeProcessStart,
+					ImageProcessorEvent::Resubmit { .. } => ActionKind::BadgeProcessResubmit,
 				},
 			};
 
@@ -516,6 +532,7 @@ pub enum ActionKind {
 	EmoteProcessFailure = 8,
 	EmoteProcessCancel = 9,
 	EmoteProcessStart = 10,
+	EmoteProcessResubmit = 11,
 
 	EmoteSetCreate = 100,
 	EmoteSetChangeName = 101,
@@ -536,12 +553,17 @@ pub enum ActionKind {
 	UserDelete = 207,
 	UserAddEntitlement = 208,
 	UserRemoveEntitlement = 209,
+	UserChangeActiveProfilePicture = 210,
+	UserChangeBiography = 211,
+	UserUpdateConnection = 212,
 
 	UserProfilePictureCreate = 300,
 	UserProfilePictureProcessSuccess = 301,
 	UserProfilePictureProcessFailure = 302,
 	UserProfilePictureProcessCancel = 303,
 	UserProfilePictureProcessStart = 304,
+	UserProfilePictureDelete = 305,
+	UserProfilePictureProcessResubmit = 306,
 
 	UserEditorAdd = 400,
 	UserEditorRemove = 401,
@@ -574,12 +596,14 @@ pub enum ActionKind {
 	PaintProcessFailure = 1004,
 	PaintProcessCancel = 1005,
 	PaintProcessStart = 1006,
+	PaintProcessResubmit = 1007,
 
 	BadgeCreate = 1100,
 	BadgeProcessSuccess = 1101,
 	BadgeProcessFailure = 1102,
 	BadgeProcessCancel = 1103,
 	BadgeProcessStart = 1104,
+	BadgeProcessResubmit = 1105,
 
 	RoleCreate = 1200,
 	RoleChangeName = 1201,
@@ -23,6 +23,53 @@ pub trait TypesenseCollection: Send + Sync {
 	fn fields() -> Vec<typesense_rs::models::Field>;
 }
 
+/// A partial update of a typesense document.
+pub type TypesensePatchDocument = serde_json::Map<String, serde_json::Value>;
+
+/// Allows a typesense document to be updated partially when only some fields
+/// of the database document it was built from changed.
+pub trait TypesensePatch: TypesenseCollection + serde::Serialize {
+	/// Fields which are sent with every patch.
+	const ALWAYS_PATCHED: &'static [&'static str] = &["id", "updated_at", "search_updated_at"];
+
+	/// Fields which are computed from other documents when indexing, they are
+	/// sent with every patch since their changes are not visible in the
+	/// database document.
+	const COMPUTED: &'static [&'static str] = &[];
+
+	/// The typesense fields derived from the given top level database field or
+	/// `None` if a change to it requires the full document to be sent.
+	fn derived_fields(db_field: &str) -> Option<&'static [&'static str]>;
+
+	/// Builds a sparse document containing only the fields derived from the
+	/// given database fields.
+	///
+	/// Returns `None` if the full document has to be sent instead, which is
+	/// also the case when nothing besides [`Self::ALWAYS_PATCHED`] changed
+	/// since such updates are used to request a reindex.
+	fn patch<'a>(&self, db_fields: impl IntoIterator<Item = &'a str>) -> Option<TypesensePatchDocument> {
+		let mut fields = std::collections::HashSet::new();
+		for db_field in db_fields {
+			fields.extend(Self::derived_fields(db_field)?.iter().copied());
+		}
+
+		if fields.iter().all(|field| Self::ALWAYS_PATCHED.contains(field)) {
+			return None;
+		}
+
+		fields.extend(Self::ALWAYS_PATCHED.iter().copied());
+		fields.extend(Self::COMPUTED.iter().copied());
+
+		let serde_json::Value::Object(mut document) = serde_json::to_value(self).ok()? else {
+			return None;
+		};
+
+		document.retain(|field, _| fields.contains(field.as_str()));
+
+		Some(document)
+	}
+}
+
 struct TypesenseGenericCollection {
 	name: &'static str,
 	schema: typesense_rs::models::CollectionSchema,
@@ -36,9 +83,42 @@ impl TypesenseGenericCollection {
 		}
 	}
 
-	pub fn determine_migration(&self, _resp: CollectionResponse) -> anyhow::Result<Option<CollectionUpdateSchema>> {
-		tracing::warn!("collection migration not implemented");
-		Ok(None)
+	/// Compares the fields of the existing collection with the schema. New
+	/// fields are added and fields whose options changed are dropped and added
+	/// again, typesense then indexes them from the stored documents.
+	pub fn determine_migration(&self, resp: CollectionResponse) -> anyhow::Result<Option<CollectionUpdateSchema>> {
+		let mut fields = Vec::new();
+
+		for field in &self.schema.fields {
+			let existing = resp.fields.iter().find(|f| f.name == field.name);
+
+			if field.drop == Some(true) {
+				if existing.is_some() {
+					tracing::debug!(field = %field.name, "dropping field");
+					fields.push(field.clone());
+				}
+
+				continue;
+			}
+
+			match existing {
+				Some(existing) if field_matches(field, existing) => {}
+				Some(_) => {
+					tracing::debug!(field = %field.name, "field changed, reindexing it");
+					fields.push(Field {
+						drop: Some(true),
+						..Field::new(field.name.clone(), field.r#type.clone())
+					});
+					fields.push(field.clone());
+				}
+				None => {
+					tracing::debug!(field = %field.name, "adding field");
+					fields.push(field.clone());
+				}
+			}
+		}
+
+		Ok((!fields.is_empty()).then(|| CollectionUpdateSchema::new(fields)))
 	}
 
 	#[tracing::instrument(skip_all, fields(collection = self.name))]
@@ -82,6 +162,23 @@ impl TypesenseGenericCollection {
 	}
 }
 
+/// Whether an existing field has the type and every option set by the schema,
+/// options the schema leaves to typesense are not compared.
+fn field_matches(field: &Field, existing: &Field) -> bool {
+	fn option_matches<T: PartialEq>(field: &Option<T>, existing: &Option<T>) -> bool {
+		field.is_none() || field == existing
+	}
+
+	field.r#type == existing.r#type
+		&& option_matches(&field.optional, &existing.optional)
+		&& option_matches(&field.facet, &existing.facet)
+		&& option_matches(&field.index, &existing.index)
+		&& option_matches(&field.locale, &existing.locale)
+		&& option_matches(&field.sort, &existing.sort)
+		&& option_matches(&field.infix, &existing.infix)
+		&& option_matches(&field.num_dim, &existing.num_dim)
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Ord, PartialOrd)]
 pub enum FieldType {
 	String,
@@ -183,7 +280,7 @@ macro_rules! impl_typesense_type {
 pub(crate) use impl_typesense_type;
 use typesense_rs::apis::collections_api::{CreateCollectionParams, GetCollectionParams, UpdateCollectionParams};
 use typesense_rs::apis::Api;
-use typesense_rs::models::{CollectionResponse, CollectionUpdateSchema};
+use typesense_rs::models::{CollectionResponse, CollectionUpdateSchema, Field};
 
 impl_typesense_type!(String, String);
 impl_typesense_type!(i32, Int32);
@@ -272,3 +369,103 @@ pub async fn init_typesense(client: &typesense_rs::apis::ApiClient) -> anyhow::R
 
 	Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn keys(patch: &TypesensePatchDocument) -> Vec<&str> {
+		let mut keys: Vec<_> = patch.keys().map(String::as_str).collect();
+		keys.sort_unstable();
+		keys
+	}
+
+	fn collection_response<C: TypesenseCollection>() -> CollectionResponse {
+		let schema = C::schema();
+		CollectionResponse::new(schema.fields, schema.name, 0, 0)
+	}
+
+	#[test]
+	fn test_migration_up_to_date() {
+		let collection = TypesenseGenericCollection::new::<emote::Emote>();
+
+		assert!(collection
+			.determine_migration(collection_response::<emote::Emote>())
+			.unwrap()
+			.is_none());
+	}
+
+	#[test]
+	fn test_migration_changed_field() {
+		let collection = TypesenseGenericCollection::new::<emote::Emote>();
+
+		// a collection created before the tags were facetable
+		let mut resp = collection_response::<emote::Emote>();
+		resp.fields.iter_mut().find(|f| f.name == "tags").unwrap().facet = Some(false);
+		resp.fields.retain(|f| f.name != "default_name");
+
+		let migration = collection.determine_migration(resp).unwrap().unwrap();
+		let changes: Vec<_> = migration.fields.iter().map(|f| (f.name.as_str(), f.drop, f.facet)).collect();
+
+		assert_eq!(
+			changes,
+			[
+				("default_name", None, None),
+				("tags", Some(true), None),
+				("tags", None, Some(true)),
+			]
+		);
+	}
+
+	#[test]
+	fn test_emote_patch() {
+		let emote = emote::Emote {
+			default_name: "peepoHappy".to_string(),
+			..Default::default()
+		};
+
+		let patch = emote.patch(["default_name", "updated_at"]).unwrap();
+
+		assert_eq!(keys(&patch), ["default_name", "id", "search_updated_at", "updated_at"]);
+		assert_eq!(patch["default_name"], "peepoHappy");
+
+		let patch = emote.patch(["flags"]).unwrap();
+		assert!(patch.contains_key("flag_nsfw"));
+		assert!(!patch.contains_key("default_name"));
+	}
+
+	#[test]
+	fn test_patch_falls_back_to_full_document() {
+		let emote = emote::Emote::default();
+
+		// unknown fields require the full document
+		assert!(emote.patch(["default_name", "versions"]).is_none());
+		// a reindex request sends the full document
+		assert!(emote.patch(["search_updated_at"]).is_none());
+		assert!(emote.patch([]).is_none());
+	}
+
+	#[test]
+	fn test_user_patch_sends_computed_fields() {
+		let user = user::User::default();
+
+		let patch = user.patch(["connections"]).unwrap();
+
+		assert_eq!(
+			keys(&patch),
+			[
+				"discord_names",
+				"entitlement_grants",
+				"google_names",
+				"id",
+				"kick_names",
+				"search_updated_at",
+				"twitch_names",
+				"updated_at",
+			]
+		);
+
+		// fields which are not indexed do not cause a patch on their own
+		assert!(user.patch(["settings", "updated_at"]).is_none());
+	}
+}
@@ -5,7 +5,7 @@ use std::collections::HashSet;
 
 use chrono::Utc;
 
-use super::TypesenseGenericCollection;
+use super::{TypesenseGenericCollection, TypesensePatch};
 use crate::database;
 use crate::database::badge::BadgeId;
 use crate::database::emote_set::EmoteSetId;
@@ -114,6 +114,33 @@ impl User {
 	}
 }
 
+impl TypesensePatch for User {
+	const COMPUTED: &'static [&'static str] = &["entitlement_grants"];
+
+	fn derived_fields(db_field: &str) -> Option<&'static [&'static str]> {
+		Some(match db_field {
+			"email" => &["email"],
+			"email_verified" => &["email_verified"],
+			"has_bans" => &["has_bans"],
+			"two_fa" => &["has_2fa"],
+			"style" => &["active_badge_id", "active_paint_id", "active_emote_set_id"],
+			"connections" => &["discord_names", "kick_names", "google_names", "twitch_names"],
+			"cached" => &[
+				"entitlements",
+				"emotes",
+				"role_rank",
+				"role_hoist_rank",
+				"active_badge_id",
+				"active_paint_id",
+			],
+			"all_cosmetics" | "merged_into_id" | "settings" | "stripe_customer_id" => &[],
+			"updated_at" => &["updated_at"],
+			"search_updated_at" => &["search_updated_at"],
+			_ => return None,
+		})
+	}
+}
+
 pub(super) fn typesense_collections() -> impl IntoIterator<Item = TypesenseGenericCollection> {
 	std::iter::once(TypesenseGenericCollection::new::<User>())
 		.chain(ban::typesense_collections())
@@ -38,6 +38,7 @@ async fn main() {
 				to: EntitlementEdgeKind::Paint { paint_id },
 				managed_by: None,
 			},
+			expires_at: None,
 		}))
 		.await
 		.unwrap();
@@ -77,6 +77,7 @@ async fn main() {
 					special_event_id: minion_special_event,
 				}),
 			},
+			expires_at: None,
 		}))
 		.await
 		.unwrap();